@@ -0,0 +1,13 @@
+# OpenChamber shell integration: --rcfile entry point for bash.
+# Replaces the login startup the terminal would otherwise get from `bash -l`.
+[ -r /etc/profile ] && . /etc/profile
+if [ -r "$HOME/.bash_profile" ]; then
+  . "$HOME/.bash_profile"
+elif [ -r "$HOME/.bash_login" ]; then
+  . "$HOME/.bash_login"
+elif [ -r "$HOME/.profile" ]; then
+  . "$HOME/.profile"
+elif [ -r "$HOME/.bashrc" ]; then
+  . "$HOME/.bashrc"
+fi
+[ -r "$OPENCHAMBER_SHELL_INTEGRATION_SCRIPT" ] && . "$OPENCHAMBER_SHELL_INTEGRATION_SCRIPT"
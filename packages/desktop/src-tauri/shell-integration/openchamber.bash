@@ -0,0 +1,53 @@
+# OpenChamber shell integration for bash: emits OSC 133 prompt/command marks
+[[ $- == *i* ]] || return 0
+[ -n "$OPENCHAMBER_SHELL_INTEGRATION_LOADED" ] && return 0
+OPENCHAMBER_SHELL_INTEGRATION_LOADED=1
+__openchamber_in_command=0
+__openchamber_at_prompt=0
+
+__openchamber_escape() {
+  local value="${1//\\/\\\\}"
+  value="${value//;/\\x3b}"
+  value="${value//$'\n'/\\x0a}"
+  builtin printf '%s' "$value"
+}
+
+__openchamber_prompt() {
+  local exit_code=$?
+  if [ "$__openchamber_in_command" = 1 ]; then
+    builtin printf '\e]133;D;%s\a' "$exit_code"
+  fi
+  __openchamber_in_command=0
+  if [[ "$PS1" != *'133;B'* ]]; then
+    PS1="${PS1}\[\e]133;B\a\]"
+  fi
+  builtin printf '\e]133;A\a'
+  return $exit_code
+}
+
+__openchamber_preexec() {
+  [ "$__openchamber_at_prompt" = 1 ] || return
+  [ -n "$COMP_LINE" ] && return
+  [ "$BASH_COMMAND" = __openchamber_prompt ] && return
+  __openchamber_at_prompt=0
+
+  local command_line
+  command_line=$(HISTTIMEFORMAT= builtin history 1)
+  if [[ $command_line =~ ^[[:space:]]*[0-9]+[*[:space:]]+(.*)$ ]]; then
+    command_line="${BASH_REMATCH[1]}"
+  else
+    command_line="$BASH_COMMAND"
+  fi
+  builtin printf '\e]633;E;%s\a' "$(__openchamber_escape "$command_line")"
+  builtin printf '\e]133;C\a'
+  __openchamber_in_command=1
+}
+
+__openchamber_user_prompt_command="${PROMPT_COMMAND%%;}"
+PROMPT_COMMAND="__openchamber_prompt${__openchamber_user_prompt_command:+; $__openchamber_user_prompt_command}; __openchamber_at_prompt=1"
+builtin unset __openchamber_user_prompt_command
+
+# Leave an existing DEBUG trap alone; prompt marks still work without command tracking
+if [ -z "$(builtin trap -p DEBUG)" ]; then
+  builtin trap '__openchamber_preexec' DEBUG
+fi
@@ -0,0 +1,18 @@
+# OpenChamber shell integration for fish: emits OSC 133 prompt/command marks
+status is-interactive; or return 0
+set -q OPENCHAMBER_SHELL_INTEGRATION_LOADED; and return 0
+set -g OPENCHAMBER_SHELL_INTEGRATION_LOADED 1
+
+function __openchamber_prompt --on-event fish_prompt
+    printf '\e]133;A\a'
+end
+
+function __openchamber_preexec --on-event fish_preexec
+    set -l command_line (string replace -a '\\' '\\\\' -- $argv[1] | string replace -a ';' '\\x3b' | string join '\\x0a')
+    printf '\e]633;E;%s\a' "$command_line"
+    printf '\e]133;C\a'
+end
+
+function __openchamber_postexec --on-event fish_postexec
+    printf '\e]133;D;%s\a' $status
+end
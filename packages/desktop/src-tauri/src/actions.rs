@@ -0,0 +1,337 @@
+use std::collections::HashSet;
+
+use serde::Serialize;
+use serde_json::{json, Value};
+
+use crate::{
+    commands::{logs::log_download, projects::switch_directory},
+    DesktopRuntime,
+};
+
+/// Backend capabilities offered to the command palette. New actions are added
+/// here: the descriptor table and the dispatcher both match on this enum, so the
+/// palette cannot list an action the backend does not route.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ActionId {
+    RestartOpenCode,
+    SwitchProject,
+    OpenRecentDirectory,
+    DownloadLogs,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ActionArgument {
+    None,
+    /// A directory path; suggestions come from settings
+    Directory,
+}
+
+struct ActionSpec {
+    id: &'static str,
+    title: &'static str,
+    category: &'static str,
+    argument: ActionArgument,
+}
+
+impl ActionId {
+    pub const ALL: [ActionId; 4] = [
+        ActionId::RestartOpenCode,
+        ActionId::SwitchProject,
+        ActionId::OpenRecentDirectory,
+        ActionId::DownloadLogs,
+    ];
+
+    fn spec(&self) -> ActionSpec {
+        match self {
+            ActionId::RestartOpenCode => ActionSpec {
+                id: "opencode.restart",
+                title: "Restart OpenCode",
+                category: "OpenCode",
+                argument: ActionArgument::None,
+            },
+            ActionId::SwitchProject => ActionSpec {
+                id: "project.switch",
+                title: "Switch Project",
+                category: "Project",
+                argument: ActionArgument::Directory,
+            },
+            ActionId::OpenRecentDirectory => ActionSpec {
+                id: "project.openRecent",
+                title: "Open Recent Directory",
+                category: "Project",
+                argument: ActionArgument::Directory,
+            },
+            ActionId::DownloadLogs => ActionSpec {
+                id: "diagnostics.downloadLogs",
+                title: "Download Desktop Logs",
+                category: "Diagnostics",
+                argument: ActionArgument::None,
+            },
+        }
+    }
+
+    pub fn id(&self) -> &'static str {
+        self.spec().id
+    }
+
+    pub fn argument(&self) -> ActionArgument {
+        self.spec().argument
+    }
+
+    pub fn parse(id: &str) -> Option<ActionId> {
+        Self::ALL.into_iter().find(|action| action.id() == id)
+    }
+}
+
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ActionSuggestion {
+    pub value: String,
+    pub label: String,
+}
+
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ActionDescriptor {
+    pub id: &'static str,
+    pub title: &'static str,
+    pub category: &'static str,
+    pub needs_argument: bool,
+    pub suggestions: Vec<ActionSuggestion>,
+}
+
+fn string_list(settings: &Value, key: &str) -> Vec<String> {
+    settings
+        .get(key)
+        .and_then(Value::as_array)
+        .map(|values| {
+            values
+                .iter()
+                .filter_map(Value::as_str)
+                .filter(|value| !value.is_empty())
+                .map(str::to_string)
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+fn directory_suggestions(directories: impl IntoIterator<Item = String>) -> Vec<ActionSuggestion> {
+    let mut seen = HashSet::new();
+    directories
+        .into_iter()
+        .filter(|dir| seen.insert(dir.clone()))
+        .map(|dir| ActionSuggestion {
+            label: std::path::Path::new(&dir)
+                .file_name()
+                .and_then(|name| name.to_str())
+                .unwrap_or(&dir)
+                .to_string(),
+            value: dir,
+        })
+        .collect()
+}
+
+/// Build palette descriptors, filling argument suggestions from `settings`
+pub fn describe(settings: &Value) -> Vec<ActionDescriptor> {
+    ActionId::ALL
+        .iter()
+        .map(|action| {
+            let spec = action.spec();
+            let suggestions = match action {
+                ActionId::SwitchProject => {
+                    let mut dirs = string_list(settings, "pinnedDirectories");
+                    dirs.extend(string_list(settings, "approvedDirectories"));
+                    directory_suggestions(dirs)
+                }
+                ActionId::OpenRecentDirectory => {
+                    let last = settings
+                        .get("lastDirectory")
+                        .and_then(Value::as_str)
+                        .map(str::to_string);
+                    directory_suggestions(
+                        last.into_iter()
+                            .chain(string_list(settings, "approvedDirectories")),
+                    )
+                }
+                ActionId::RestartOpenCode | ActionId::DownloadLogs => Vec::new(),
+            };
+            ActionDescriptor {
+                id: spec.id,
+                title: spec.title,
+                category: spec.category,
+                needs_argument: spec.argument != ActionArgument::None,
+                suggestions,
+            }
+        })
+        .collect()
+}
+
+/// Run an action by id. Errors are prefixed with the action id so the palette
+/// can show them without knowing which backend function failed.
+pub async fn invoke(
+    runtime: &DesktopRuntime,
+    id: &str,
+    argument: Option<String>,
+) -> Result<Value, String> {
+    let (action, argument) = resolve(id, argument)?;
+    dispatch(runtime, action, argument)
+        .await
+        .map_err(|err| format!("{}: {}", id, err))
+}
+
+/// Look up the action and check it got the argument it needs
+fn resolve(id: &str, argument: Option<String>) -> Result<(ActionId, Option<String>), String> {
+    let action = ActionId::parse(id).ok_or_else(|| format!("Unknown action: {}", id))?;
+    let argument = argument
+        .map(|value| value.trim().to_string())
+        .filter(|value| !value.is_empty());
+    if action.argument() != ActionArgument::None && argument.is_none() {
+        return Err(format!("{}: an argument is required", id));
+    }
+    Ok((action, argument))
+}
+
+async fn dispatch(
+    runtime: &DesktopRuntime,
+    action: ActionId,
+    argument: Option<String>,
+) -> Result<Value, String> {
+    match action {
+        ActionId::RestartOpenCode => {
+            runtime.ensure_restart_allowed(false, false).await?;
+            runtime
+                .opencode_manager()
+                .restart_for("restart action")
+                .await
+                .map_err(|e| format!("Failed to restart OpenCode: {}", e))?;
+            Ok(json!({ "restarted": true }))
+        }
+        ActionId::SwitchProject | ActionId::OpenRecentDirectory => {
+            let path = argument.unwrap_or_default();
+            switch_directory(runtime.server_port(), runtime.api_token(), &path, false).await?;
+            Ok(json!({ "path": path }))
+        }
+        ActionId::DownloadLogs => {
+            let download = log_download(runtime).await?;
+            serde_json::to_value(download).map_err(|e| e.to_string())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn every_action_id_routes_back_to_its_action() {
+        let mut ids = HashSet::new();
+        for action in ActionId::ALL {
+            assert!(ids.insert(action.id()), "duplicate id {}", action.id());
+            assert_eq!(ActionId::parse(action.id()), Some(action));
+        }
+        assert_eq!(ActionId::parse("opencode.stop"), None);
+    }
+
+    #[test]
+    fn invocations_are_resolved_before_dispatch() {
+        let routed = [
+            ("opencode.restart", None, ActionId::RestartOpenCode, None),
+            (
+                "project.switch",
+                Some(" /work/next "),
+                ActionId::SwitchProject,
+                Some("/work/next"),
+            ),
+            (
+                "project.openRecent",
+                Some("/work/last"),
+                ActionId::OpenRecentDirectory,
+                Some("/work/last"),
+            ),
+            (
+                "diagnostics.downloadLogs",
+                Some("ignored"),
+                ActionId::DownloadLogs,
+                Some("ignored"),
+            ),
+        ];
+        for (id, argument, action, resolved) in routed {
+            assert_eq!(
+                resolve(id, argument.map(str::to_string)),
+                Ok((action, resolved.map(str::to_string))),
+                "{id}"
+            );
+        }
+
+        let refused = [
+            (
+                "project.switch",
+                Some("  "),
+                "project.switch: an argument is required",
+            ),
+            (
+                "project.openRecent",
+                None,
+                "project.openRecent: an argument is required",
+            ),
+            ("opencode.stop", None, "Unknown action: opencode.stop"),
+        ];
+        for (id, argument, error) in refused {
+            assert_eq!(
+                resolve(id, argument.map(str::to_string)),
+                Err(error.to_string()),
+                "{id}"
+            );
+        }
+    }
+
+    #[test]
+    fn descriptors_list_every_action_with_suggestions_from_settings() {
+        let settings = json!({
+            "lastDirectory": "/work/current",
+            "pinnedDirectories": ["/work/pinned", ""],
+            "approvedDirectories": ["/work/current", "/work/pinned", "/work/other"],
+        });
+        let descriptors = describe(&settings);
+        let ids: Vec<_> = descriptors.iter().map(|d| d.id).collect();
+        assert_eq!(ids, ActionId::ALL.map(|action| action.id()).to_vec());
+
+        let cases = [
+            ("opencode.restart", false, vec![]),
+            (
+                "project.switch",
+                true,
+                vec!["/work/pinned", "/work/current", "/work/other"],
+            ),
+            (
+                "project.openRecent",
+                true,
+                vec!["/work/current", "/work/pinned", "/work/other"],
+            ),
+            ("diagnostics.downloadLogs", false, vec![]),
+        ];
+        for (id, needs_argument, suggestions) in cases {
+            let descriptor = descriptors.iter().find(|d| d.id == id).unwrap();
+            assert_eq!(descriptor.needs_argument, needs_argument, "{id}");
+            let values: Vec<_> = descriptor
+                .suggestions
+                .iter()
+                .map(|s| s.value.as_str())
+                .collect();
+            assert_eq!(values, suggestions, "{id}");
+        }
+
+        let switch = descriptors
+            .iter()
+            .find(|d| d.id == "project.switch")
+            .unwrap();
+        assert_eq!(switch.suggestions[0].label, "pinned");
+    }
+
+    #[test]
+    fn descriptors_need_no_settings() {
+        for descriptor in describe(&Value::Null) {
+            assert!(descriptor.suggestions.is_empty(), "{}", descriptor.id);
+        }
+    }
+}
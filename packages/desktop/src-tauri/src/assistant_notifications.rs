@@ -1,40 +1,44 @@
-use std::{collections::HashSet, time::Duration};
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Arc,
+    time::{Duration, Instant},
+};
 
 use anyhow::Result;
-use futures_util::TryStreamExt;
 use log::{debug, info, warn};
-use reqwest::Client;
-use serde::Deserialize;
 use serde_json::Value;
 use tauri::{AppHandle, Manager};
 use tauri_plugin_notification::NotificationExt;
-use tokio::{io::AsyncBufReadExt, sync::Mutex};
-use tokio_util::io::StreamReader;
-
-use crate::DesktopRuntime;
-
-#[derive(Deserialize)]
-struct EventEnvelope {
-    #[serde(rename = "type")]
-    event_type: String,
-    #[serde(default)]
-    properties: Value,
-}
+use tokio::sync::Mutex;
+
+use crate::{
+    notification_digest::{Completion, CompletionBatcher, PushOutcome, DEFAULT_DIGEST_WINDOW},
+    notification_history, notification_policy,
+    opencode_client::{OpenCodeClient, OpenCodeError, OpenCodeEvent},
+    opencode_lifecycle, platform,
+    system_dnd::{self, DndState},
+    DesktopRuntime,
+};
+
+/// Upper bound on session lookups so notification latency stays low
+const SESSION_LOOKUP_TIMEOUT: Duration = Duration::from_secs(2);
+/// Longest wait before reconnecting to the event stream when OpenCode does not report ready
+const RECONNECT_DELAY: Duration = Duration::from_secs(2);
+const SESSION_TITLE_CACHE_LIMIT: usize = 256;
+const EXCERPT_MAX_CHARS: usize = 80;
 
 pub fn spawn_assistant_notifications(
     app: AppHandle,
     runtime: DesktopRuntime,
 ) -> tauri::async_runtime::JoinHandle<()> {
     tauri::async_runtime::spawn(async move {
-        let client = Client::builder()
-            // Give SSE a very long overall timeout so idle periods don't abort the stream.
-            .timeout(Duration::from_secs(24 * 60 * 60))
-            .tcp_keepalive(Some(Duration::from_secs(30)))
-            .build()
-            .expect("failed to build reqwest client");
-
         let mut shutdown_rx = runtime.subscribe_shutdown();
+        let mut lifecycle_rx = runtime.opencode_manager().subscribe_lifecycle();
         let notified_messages = Mutex::new(HashSet::<String>::new());
+        let session_titles = Mutex::new(HashMap::<String, String>::new());
+        let batcher = Arc::new(parking_lot::Mutex::new(CompletionBatcher::new(
+            DEFAULT_DIGEST_WINDOW,
+        )));
 
         loop {
             tokio::select! {
@@ -43,10 +47,11 @@ pub fn spawn_assistant_notifications(
                     break;
                 }
                 _ = async {
-                    if let Err(err) = run_once(&app, &runtime, &client, &notified_messages).await {
+                    opencode_lifecycle::discard_pending(&mut lifecycle_rx);
+                    if let Err(err) = run_once(&app, &runtime, &notified_messages, &session_titles, &batcher).await {
                         warn!("[desktop:notify] SSE loop error: {err:?}");
                     }
-                    tokio::time::sleep(Duration::from_secs(2)).await;
+                    opencode_lifecycle::wait_for_ready(&mut lifecycle_rx, RECONNECT_DELAY).await;
                 } => {}
             }
         }
@@ -56,102 +61,35 @@ pub fn spawn_assistant_notifications(
 async fn run_once(
     app: &AppHandle,
     runtime: &DesktopRuntime,
-    client: &Client,
     notified_messages: &Mutex<HashSet<String>>,
+    session_titles: &Mutex<HashMap<String, String>>,
+    batcher: &Arc<parking_lot::Mutex<CompletionBatcher>>,
 ) -> Result<()> {
-    let opencode = runtime.opencode_manager();
-
-    let port = match opencode.current_port() {
-        Some(port) => port,
-        None => {
+    let mut events = match runtime.opencode_client().event_stream().await {
+        Ok(events) => events,
+        Err(OpenCodeError::NotReady) => {
             warn!("[desktop:notify] OpenCode port unavailable; will retry");
-            tokio::time::sleep(Duration::from_secs(2)).await;
             return Ok(());
         }
-    };
-
-    let prefix = opencode.api_prefix();
-    let mut url = format!("http://127.0.0.1:{port}{}/event", prefix);
-
-    if let Some(dir) = opencode.get_working_directory().to_str().map(|s| s.to_string()) {
-        let mut parsed = reqwest::Url::parse(&url)?;
-        parsed
-            .query_pairs_mut()
-            .append_pair("directory", &dir);
-        url = parsed.to_string();
-    }
-
-    debug!("[desktop:notify] Connecting SSE for notifications: {url}");
-
-    let response = client
-        .get(&url)
-        .header("accept", "text/event-stream")
-        .header("accept-encoding", "identity")
-        .send()
-        .await?;
-
-    debug!(
-        "[desktop:notify] SSE response status={} headers={:?}",
-        response.status(),
-        response.headers()
-    );
-
-    if !response.status().is_success() {
-        warn!(
-            "[desktop:notify] SSE connect failed with status {}",
-            response.status()
-        );
-        tokio::time::sleep(Duration::from_secs(2)).await;
-        return Ok(());
-    }
-
-    let stream = response
-        .bytes_stream()
-        .map_err(|err| std::io::Error::new(std::io::ErrorKind::Other, err));
-    let mut reader = StreamReader::new(stream);
-    let mut buf = Vec::new();
-    let mut data_lines: Vec<String> = Vec::new();
-
-    loop {
-        buf.clear();
-        let bytes_read = match reader.read_until(b'\n', &mut buf).await {
-            Ok(n) => n,
-            Err(err) => {
-                warn!("[desktop:notify] Read error in SSE stream: {err:?}");
-                return Err(err.into());
-            }
-        };
-        if bytes_read == 0 {
-            break;
-        }
-
-        let line = match std::str::from_utf8(&buf) {
-            Ok(s) => s.trim_end_matches(&['\r', '\n'][..]).to_string(),
-            Err(err) => {
-                warn!("[desktop:notify] Non-UTF8 SSE chunk: {err}");
-                continue;
-            }
-        };
-
-        if line.is_empty() {
-            if data_lines.is_empty() {
-                continue;
-            }
-            let raw = data_lines.join("\n");
-            data_lines.clear();
-
-            match serde_json::from_str::<EventEnvelope>(&raw) {
-                Ok(event) => handle_event(app, event, notified_messages).await,
-                Err(err) => {
-                    warn!("[desktop:notify] Failed to parse SSE data: {err}; raw={raw}");
-                }
-            }
-            continue;
+        Err(err) => {
+            warn!("[desktop:notify] SSE connect failed: {err}");
+            return Ok(());
         }
+    };
 
-        if let Some(rest) = line.strip_prefix("data:") {
-            data_lines.push(rest.trim_start().to_string());
-        }
+    while let Some(event) = events.next_event().await.map_err(|err| {
+        warn!("[desktop:notify] Read error in SSE stream: {err:?}");
+        err
+    })? {
+        handle_event(
+            app,
+            runtime,
+            event,
+            notified_messages,
+            session_titles,
+            batcher,
+        )
+        .await;
     }
 
     Ok(())
@@ -159,8 +97,11 @@ async fn run_once(
 
 async fn handle_event(
     app: &AppHandle,
-    event: EventEnvelope,
+    runtime: &DesktopRuntime,
+    event: OpenCodeEvent,
     notified_messages: &Mutex<HashSet<String>>,
+    session_titles: &Mutex<HashMap<String, String>>,
+    batcher: &Arc<parking_lot::Mutex<CompletionBatcher>>,
 ) {
     if event.event_type.as_str() != "message.updated" {
         return;
@@ -205,29 +146,190 @@ async fn handle_event(
         .unwrap_or("assistant");
 
     let title = format!("{} agent is ready", format_mode(raw_mode));
-    let body = format!("{} completed the task", format_model_id(raw_model));
 
-    let should_notify = app
+    let session_id = info.get("sessionID").and_then(Value::as_str);
+    let (session_title, excerpt) = match session_id {
+        Some(session_id) => {
+            let lookup = SessionLookup::new(runtime);
+            tokio::join!(
+                lookup.session_title(session_id, session_titles),
+                lookup.message_excerpt(session_id, &message_id),
+            )
+        }
+        None => (None, None),
+    };
+
+    let label = session_title
+        .clone()
+        .unwrap_or_else(|| raw_mode.to_string());
+    let body = match (session_title, excerpt) {
+        (Some(session_title), Some(excerpt)) => format!("{session_title}: {excerpt}"),
+        (Some(session_title), None) => session_title,
+        (None, Some(excerpt)) => excerpt,
+        (None, None) => format!("{} completed the task", format_model_id(raw_model)),
+    };
+
+    let focused = app
         .get_webview_window("main")
         .map(|window| {
             let focused = window.is_focused().unwrap_or(false);
             let minimized = window.is_minimized().unwrap_or(false);
-            // Only notify when the app is not in the foreground or is minimized
-            !focused || minimized
+            focused && !minimized
         })
-        .unwrap_or(true);
-
-    if should_notify {
-        let _ = app
-            .notification()
-            .builder()
-            .title(title)
-            .body(body)
-            .sound("Glass")
-            .show();
+        .unwrap_or(false);
+
+    let settings = runtime.settings().load().await.unwrap_or(Value::Null);
+    let agent_name = info
+        .get("agent")
+        .and_then(Value::as_str)
+        .filter(|s| !s.is_empty())
+        .unwrap_or(raw_mode);
+    let agent_prefs = runtime.agent_preferences().get(agent_name).await;
+    let policy = notification_policy::resolve(
+        agent_prefs.as_ref(),
+        notification_policy::session_muted(&settings, session_id),
+        platform::notification_sound(&settings),
+        focused,
+    );
+    if !policy.show {
+        return;
+    }
+
+    let respect_dnd = system_dnd::respect_system_dnd(&settings);
+    let dnd = tokio::task::spawn_blocking(system_dnd::get_system_dnd_state)
+        .await
+        .unwrap_or(DndState::Unknown);
+    let decision = system_dnd::decide(dnd, respect_dnd);
+    notification_history::record(
+        app,
+        runtime.notification_history(),
+        &title,
+        &body,
+        !decision.show,
+    );
+
+    if !decision.show {
+        debug!("[desktop:notify] System DND active; suppressing notification for {message_id}");
+        return;
+    }
+
+    let completion = Completion {
+        label,
+        title,
+        body,
+        sound: decision.sound,
+    };
+
+    let sound = policy.sound;
+    let window = digest_window(&settings);
+    let watching = session_id.is_some() && runtime.active_session().as_deref() == session_id;
+    if watching || window.is_zero() {
+        show_completion(app, completion, sound.as_deref());
+        return;
+    }
+
+    let outcome = {
+        let mut batcher = batcher.lock();
+        batcher.set_window(window);
+        batcher.push(completion, Instant::now())
+    };
+
+    if let PushOutcome::Scheduled(deadline) = outcome {
+        let app = app.clone();
+        let batcher = batcher.clone();
+        tauri::async_runtime::spawn(async move {
+            tokio::time::sleep_until(deadline.into()).await;
+            let ready = batcher.lock().flush(Instant::now());
+            if let Some(completion) = ready {
+                show_completion(&app, completion, sound.as_deref());
+            }
+        });
+    }
+}
+
+/// `notifications.digestWindowSeconds`; 0 disables digests
+fn digest_window(settings: &Value) -> Duration {
+    settings
+        .get("notifications")
+        .and_then(|value| value.get("digestWindowSeconds"))
+        .and_then(Value::as_f64)
+        .filter(|secs| secs.is_finite() && *secs >= 0.0)
+        .map(Duration::from_secs_f64)
+        .unwrap_or(DEFAULT_DIGEST_WINDOW)
+}
+
+fn show_completion(app: &AppHandle, completion: Completion, sound: Option<&str>) {
+    let mut builder = app
+        .notification()
+        .builder()
+        .title(completion.title)
+        .body(completion.body);
+    if let Some(sound) = sound.filter(|_| completion.sound) {
+        builder = builder.sound(sound);
+    }
+    let _ = builder.show();
+}
+
+/// Session details for the notification body, bounded so notifications stay prompt
+struct SessionLookup {
+    client: OpenCodeClient,
+}
+
+impl SessionLookup {
+    fn new(runtime: &DesktopRuntime) -> Self {
+        Self {
+            client: runtime
+                .opencode_client()
+                .with_timeout(SESSION_LOOKUP_TIMEOUT),
+        }
+    }
+
+    async fn session_title(
+        &self,
+        session_id: &str,
+        cache: &Mutex<HashMap<String, String>>,
+    ) -> Option<String> {
+        if let Some(title) = cache.lock().await.get(session_id) {
+            return Some(title.clone());
+        }
+
+        let session = self
+            .client
+            .get_session(session_id)
+            .await
+            .map_err(|err| debug!("[desktop:notify] Session {session_id} lookup failed: {err}"))
+            .ok()?;
+        let title = session.display_title()?.to_string();
+
+        let mut cache = cache.lock().await;
+        if cache.len() >= SESSION_TITLE_CACHE_LIMIT {
+            cache.clear();
+        }
+        cache.insert(session_id.to_string(), title.clone());
+        Some(title)
+    }
+
+    async fn message_excerpt(&self, session_id: &str, message_id: &str) -> Option<String> {
+        let message = self
+            .client
+            .get_message(session_id, message_id)
+            .await
+            .map_err(|err| debug!("[desktop:notify] Message {message_id} lookup failed: {err}"))
+            .ok()?;
+        Some(excerpt(message.last_text()?))
     }
 }
 
+/// Collapse whitespace and cut to the first EXCERPT_MAX_CHARS characters
+fn excerpt(text: &str) -> String {
+    let collapsed = text.split_whitespace().collect::<Vec<_>>().join(" ");
+    if collapsed.chars().count() <= EXCERPT_MAX_CHARS {
+        return collapsed;
+    }
+    let cut: String = collapsed.chars().take(EXCERPT_MAX_CHARS).collect();
+    format!("{}…", cut.trim_end())
+}
+
 fn format_mode(raw: &str) -> String {
     if raw.is_empty() {
         return "Agent".to_string();
@@ -278,3 +380,116 @@ fn capitalize(s: &str) -> String {
         None => String::new(),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{opencode_manager::OpenCodeManager, upstream_pool::UpstreamPool};
+    use axum::{
+        extract::Path, http::StatusCode, response::IntoResponse, routing::get, Json, Router,
+    };
+    use serde_json::json;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    /// OpenCode serving one titled session with a two-part reply; counts session lookups
+    async fn lookup() -> (SessionLookup, Arc<AtomicUsize>) {
+        let lookups = Arc::new(AtomicUsize::new(0));
+        let counter = lookups.clone();
+        let app = Router::new()
+            .route(
+                "/session/{id}",
+                get(move |Path(id): Path<String>| async move {
+                    counter.fetch_add(1, Ordering::SeqCst);
+                    match id.as_str() {
+                        "ses_1" => Json(json!({ "id": id, "title": "  Fix the login bug " }))
+                            .into_response(),
+                        "untitled" => Json(json!({ "id": id, "title": "" })).into_response(),
+                        _ => StatusCode::NOT_FOUND.into_response(),
+                    }
+                }),
+            )
+            .route(
+                "/session/{id}/message/{message}",
+                get(|| async {
+                    Json(json!({
+                        "info": { "id": "msg_1", "role": "assistant" },
+                        "parts": [
+                            { "type": "text", "text": "Looking at the handler" },
+                            { "type": "tool" },
+                            { "type": "text", "text": "Fixed the\n\nredirect loop." },
+                            { "type": "text", "text": "   " },
+                        ],
+                    }))
+                }),
+            );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        tokio::spawn(async move {
+            axum::serve(listener, app).await.ok();
+        });
+
+        let opencode = Arc::new(OpenCodeManager::ready_on(port, std::env::temp_dir()));
+        let pool = Arc::new(UpstreamPool::new(opencode.clone()).unwrap());
+        let client = OpenCodeClient::new(pool, opencode).with_timeout(SESSION_LOOKUP_TIMEOUT);
+        (SessionLookup { client }, lookups)
+    }
+
+    #[tokio::test]
+    async fn session_titles_are_trimmed_and_cached() {
+        let (lookup, lookups) = lookup().await;
+        let cache = Mutex::new(HashMap::new());
+
+        for _ in 0..2 {
+            assert_eq!(
+                lookup.session_title("ses_1", &cache).await.as_deref(),
+                Some("Fix the login bug")
+            );
+        }
+        assert_eq!(lookups.load(Ordering::SeqCst), 1);
+
+        assert_eq!(lookup.session_title("untitled", &cache).await, None);
+        assert_eq!(lookup.session_title("missing", &cache).await, None);
+    }
+
+    #[tokio::test]
+    async fn the_excerpt_comes_from_the_last_text_part() {
+        let (lookup, _) = lookup().await;
+        assert_eq!(
+            lookup.message_excerpt("ses_1", "msg_1").await.as_deref(),
+            Some("Fixed the redirect loop.")
+        );
+    }
+
+    #[test]
+    fn excerpts_collapse_whitespace_and_stop_at_the_limit() {
+        assert_eq!(excerpt("  short\n reply "), "short reply");
+
+        let long = "word ".repeat(40);
+        let cut = excerpt(&long);
+        assert!(cut.ends_with('…'));
+        assert_eq!(cut.chars().count(), EXCERPT_MAX_CHARS);
+    }
+
+    #[test]
+    fn modes_and_models_read_as_names() {
+        assert_eq!(format_mode("code-review"), "Code Review");
+        assert_eq!(format_mode(""), "Agent");
+        assert_eq!(format_model_id("claude-sonnet-4-5"), "Claude Sonnet 4.5");
+        assert_eq!(format_model_id("gpt_4o"), "Gpt 4o");
+    }
+
+    #[test]
+    fn digest_window_reads_seconds_and_zero_disables_it() {
+        let window = |value: Value| digest_window(&json!({ "notifications": value }));
+        assert_eq!(window(json!({})), DEFAULT_DIGEST_WINDOW);
+        assert_eq!(
+            window(json!({ "digestWindowSeconds": 2.5 })),
+            Duration::from_millis(2500)
+        );
+        assert!(window(json!({ "digestWindowSeconds": 0 })).is_zero());
+        assert_eq!(
+            window(json!({ "digestWindowSeconds": -1 })),
+            DEFAULT_DIGEST_WINDOW
+        );
+    }
+}
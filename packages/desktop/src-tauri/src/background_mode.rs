@@ -0,0 +1,67 @@
+use log::{info, warn};
+use serde_json::Value;
+use tauri::{
+    menu::{Menu, MenuItem},
+    tray::TrayIconBuilder,
+    AppHandle, Manager, Window,
+};
+
+/// Settings flag: closing the window hides it and leaves OpenCode running
+pub const KEEP_RUNNING_SETTING: &str = "keepRunningInBackground";
+
+const TRAY_ID: &str = "openchamber-background";
+const MENU_SHOW: &str = "show";
+const MENU_QUIT: &str = "quit";
+
+pub fn keep_running(settings: &Value) -> bool {
+    settings
+        .get(KEEP_RUNNING_SETTING)
+        .and_then(Value::as_bool)
+        .unwrap_or(false)
+}
+
+/// Hide the window instead of exiting. The runtime, HTTP server and event
+/// listeners stay up; the tray icon brings the window back or quits for real.
+pub fn hide_window(window: &Window) {
+    if let Err(err) = ensure_tray(window.app_handle()) {
+        // Without a tray there would be no way back to a hidden window
+        warn!("[desktop:background] Failed to create tray icon: {}", err);
+        crate::shutdown_and_exit(window.app_handle());
+        return;
+    }
+    let _ = window.hide();
+    info!("[desktop:background] Window hidden; OpenCode keeps running");
+}
+
+pub fn show_main_window(app: &AppHandle) {
+    if let Some(window) = app.get_webview_window("main") {
+        let _ = window.unminimize();
+        let _ = window.show();
+        let _ = window.set_focus();
+    }
+}
+
+/// Created on first hide, so users who never enable background mode get no tray icon
+fn ensure_tray(app: &AppHandle) -> tauri::Result<()> {
+    if app.tray_by_id(TRAY_ID).is_some() {
+        return Ok(());
+    }
+    let show = MenuItem::with_id(app, MENU_SHOW, "Show OpenChamber", true, None::<&str>)?;
+    let quit = MenuItem::with_id(app, MENU_QUIT, "Quit OpenChamber", true, None::<&str>)?;
+    let menu = Menu::with_items(app, &[&show, &quit])?;
+
+    let mut builder = TrayIconBuilder::with_id(TRAY_ID)
+        .tooltip("OpenChamber")
+        .menu(&menu)
+        .show_menu_on_left_click(true)
+        .on_menu_event(|app, event| match event.id().as_ref() {
+            MENU_SHOW => show_main_window(app),
+            MENU_QUIT => crate::shutdown_and_exit(app),
+            _ => {}
+        });
+    if let Some(icon) = app.default_window_icon() {
+        builder = builder.icon(icon.clone());
+    }
+    builder.build(app)?;
+    Ok(())
+}
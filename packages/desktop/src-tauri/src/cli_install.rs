@@ -0,0 +1,301 @@
+use std::{
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicBool, Ordering},
+    time::{Duration, Instant},
+};
+
+use anyhow::{anyhow, Context, Result};
+use futures_util::StreamExt;
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tauri::{AppHandle, Emitter};
+use tokio::{io::AsyncWriteExt, process::Command};
+
+use crate::paths;
+
+pub const CLI_INSTALL_PROGRESS_EVENT: &str = "openchamber:cli-install-progress";
+
+/// Official release feed; each asset carries a `sha256:` digest
+const LATEST_RELEASE_URL: &str = "https://api.github.com/repos/sst/opencode/releases/latest";
+const USER_AGENT: &str = concat!("openchamber-desktop/", env!("CARGO_PKG_VERSION"));
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+/// Keeps a fast download from flooding the webview with events
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(200);
+
+#[cfg(windows)]
+const BINARY_NAME: &str = "opencode.exe";
+#[cfg(not(windows))]
+const BINARY_NAME: &str = "opencode";
+
+static INSTALLING: AtomicBool = AtomicBool::new(false);
+
+#[derive(Clone, Copy, Debug, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum InstallStage {
+    Resolving,
+    Downloading,
+    Verifying,
+    Extracting,
+    Done,
+    Failed,
+}
+
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct InstallProgress {
+    pub stage: InstallStage,
+    pub downloaded: u64,
+    /// None when the server did not send a length
+    pub total: Option<u64>,
+    pub message: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct Release {
+    tag_name: String,
+    assets: Vec<ReleaseAsset>,
+}
+
+#[derive(Deserialize)]
+struct ReleaseAsset {
+    name: String,
+    browser_download_url: String,
+    /// `sha256:<hex>`
+    digest: Option<String>,
+}
+
+/// Directory the CLI is installed into, also searched when resolving the binary
+pub fn install_dir() -> Option<PathBuf> {
+    paths::config_dir().map(|dir| dir.join("bin"))
+}
+
+/// Release asset for this platform, e.g. `opencode-darwin-arm64.zip`
+fn asset_name() -> Result<String> {
+    let os = if cfg!(target_os = "macos") {
+        "darwin"
+    } else if cfg!(target_os = "linux") {
+        "linux"
+    } else if cfg!(windows) {
+        "windows"
+    } else {
+        return Err(anyhow!("No OpenCode build for this operating system"));
+    };
+    let arch = if cfg!(target_arch = "x86_64") {
+        "x64"
+    } else if cfg!(target_arch = "aarch64") {
+        "arm64"
+    } else {
+        return Err(anyhow!("No OpenCode build for this CPU architecture"));
+    };
+    let ext = if cfg!(target_os = "linux") {
+        "tar.gz"
+    } else {
+        "zip"
+    };
+    Ok(format!("opencode-{}-{}.{}", os, arch, ext))
+}
+
+fn emit(
+    app: &AppHandle,
+    stage: InstallStage,
+    downloaded: u64,
+    total: Option<u64>,
+    message: Option<String>,
+) {
+    let progress = InstallProgress {
+        stage,
+        downloaded,
+        total,
+        message,
+    };
+    let _ = app.emit(CLI_INSTALL_PROGRESS_EVENT, progress);
+}
+
+/// Download the latest OpenCode release into `install_dir()` and return the
+/// binary's path. Nothing is left in the install directory unless the
+/// checksum matched and the binary was moved into place.
+pub async fn install(app: &AppHandle) -> Result<PathBuf> {
+    if INSTALLING.swap(true, Ordering::SeqCst) {
+        return Err(anyhow!("OpenCode is already being installed"));
+    }
+    let result = run_install(app).await;
+    INSTALLING.store(false, Ordering::SeqCst);
+    match &result {
+        Ok(path) => {
+            info!("[desktop:cli-install] Installed OpenCode at {:?}", path);
+            emit(
+                app,
+                InstallStage::Done,
+                0,
+                None,
+                Some(path.to_string_lossy().to_string()),
+            );
+        }
+        Err(err) => {
+            warn!("[desktop:cli-install] Install failed: {:#}", err);
+            emit(
+                app,
+                InstallStage::Failed,
+                0,
+                None,
+                Some(format!("{:#}", err)),
+            );
+        }
+    }
+    result
+}
+
+async fn run_install(app: &AppHandle) -> Result<PathBuf> {
+    let dir = install_dir().ok_or_else(|| anyhow!("No config directory to install into"))?;
+    tokio::fs::create_dir_all(&dir)
+        .await
+        .with_context(|| format!("Failed to create {:?}", dir))?;
+    // Staged next to the target so the final rename stays on one filesystem
+    let staging = dir.join(format!(".install-{}", uuid::Uuid::new_v4().simple()));
+    tokio::fs::create_dir_all(&staging)
+        .await
+        .with_context(|| format!("Failed to create {:?}", staging))?;
+
+    let result = download_into(app, &dir, &staging).await;
+    if let Err(err) = tokio::fs::remove_dir_all(&staging).await {
+        warn!(
+            "[desktop:cli-install] Failed to remove {:?}: {}",
+            staging, err
+        );
+    }
+    result
+}
+
+async fn download_into(app: &AppHandle, dir: &Path, staging: &Path) -> Result<PathBuf> {
+    let client = reqwest::Client::builder()
+        .user_agent(USER_AGENT)
+        .connect_timeout(REQUEST_TIMEOUT)
+        .build()?;
+
+    emit(app, InstallStage::Resolving, 0, None, None);
+    let asset_name = asset_name()?;
+    let release: Release = client
+        .get(LATEST_RELEASE_URL)
+        .timeout(REQUEST_TIMEOUT)
+        .send()
+        .await
+        .context("Failed to reach the OpenCode release feed")?
+        .error_for_status()?
+        .json()
+        .await
+        .context("Unexpected OpenCode release feed")?;
+    let asset = release
+        .assets
+        .into_iter()
+        .find(|asset| asset.name == asset_name)
+        .ok_or_else(|| anyhow!("Release {} has no {}", release.tag_name, asset_name))?;
+    // Refuse rather than install something we cannot check
+    let expected = asset
+        .digest
+        .as_deref()
+        .and_then(|digest| digest.strip_prefix("sha256:"))
+        .map(str::to_ascii_lowercase)
+        .ok_or_else(|| {
+            anyhow!(
+                "Release {} publishes no checksum for {}",
+                release.tag_name,
+                asset_name
+            )
+        })?;
+    info!(
+        "[desktop:cli-install] Downloading {} from {}",
+        asset_name, release.tag_name
+    );
+
+    let archive = staging.join(&asset_name);
+    let response = client
+        .get(&asset.browser_download_url)
+        .send()
+        .await
+        .context("Failed to start the download")?
+        .error_for_status()?;
+    let total = response.content_length();
+    let mut file = tokio::fs::File::create(&archive).await?;
+    let mut hasher = Sha256::new();
+    let mut downloaded = 0u64;
+    let mut last_emit = Instant::now();
+    let mut stream = response.bytes_stream();
+    emit(app, InstallStage::Downloading, 0, total, None);
+    while let Some(chunk) = stream.next().await {
+        let chunk = chunk.context("Download interrupted")?;
+        hasher.update(&chunk);
+        file.write_all(&chunk).await?;
+        downloaded += chunk.len() as u64;
+        if last_emit.elapsed() >= PROGRESS_INTERVAL {
+            emit(app, InstallStage::Downloading, downloaded, total, None);
+            last_emit = Instant::now();
+        }
+    }
+    file.flush().await?;
+    drop(file);
+    emit(app, InstallStage::Downloading, downloaded, total, None);
+
+    emit(app, InstallStage::Verifying, downloaded, total, None);
+    let actual = hasher
+        .finalize()
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect::<String>();
+    if actual != expected {
+        return Err(anyhow!(
+            "Checksum mismatch for {}: expected {}, got {}",
+            asset_name,
+            expected,
+            actual
+        ));
+    }
+
+    emit(app, InstallStage::Extracting, downloaded, total, None);
+    let extracted = staging.join("extracted");
+    tokio::fs::create_dir_all(&extracted).await?;
+    // bsdtar, shipped with macOS and Windows 10+, also reads zip archives
+    let output = Command::new("tar")
+        .arg("-xf")
+        .arg(&archive)
+        .arg("-C")
+        .arg(&extracted)
+        .output()
+        .await
+        .context("Failed to run tar")?;
+    if !output.status.success() {
+        return Err(anyhow!(
+            "Failed to extract {}: {}",
+            asset_name,
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    let binary = find_binary(&extracted)
+        .ok_or_else(|| anyhow!("{} does not contain {}", asset_name, BINARY_NAME))?;
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        tokio::fs::set_permissions(&binary, std::fs::Permissions::from_mode(0o755)).await?;
+    }
+
+    let target = dir.join(BINARY_NAME);
+    tokio::fs::rename(&binary, &target)
+        .await
+        .with_context(|| format!("Failed to move the binary to {:?}", target))?;
+    Ok(target)
+}
+
+fn find_binary(dir: &Path) -> Option<PathBuf> {
+    for entry in std::fs::read_dir(dir).ok()?.flatten() {
+        let path = entry.path();
+        if path.is_dir() {
+            if let Some(found) = find_binary(&path) {
+                return Some(found);
+            }
+        } else if path.file_name().is_some_and(|name| name == BINARY_NAME) {
+            return Some(path);
+        }
+    }
+    None
+}
@@ -0,0 +1,24 @@
+use serde_json::Value;
+use tauri::State;
+
+use crate::{
+    actions::{self, ActionDescriptor},
+    DesktopRuntime,
+};
+
+#[tauri::command]
+pub async fn list_actions(
+    state: State<'_, DesktopRuntime>,
+) -> Result<Vec<ActionDescriptor>, String> {
+    let settings = state.settings().load().await.unwrap_or(Value::Null);
+    Ok(actions::describe(&settings))
+}
+
+#[tauri::command]
+pub async fn invoke_action(
+    id: String,
+    arg: Option<String>,
+    state: State<'_, DesktopRuntime>,
+) -> Result<Value, String> {
+    actions::invoke(&state, &id, arg).await
+}
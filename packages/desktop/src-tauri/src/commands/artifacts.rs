@@ -0,0 +1,329 @@
+use std::{
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+
+use log::info;
+use serde::Serialize;
+use serde_json::json;
+use tauri::{AppHandle, Emitter, State};
+use tauri_plugin_dialog::DialogExt;
+use tokio::{
+    fs,
+    io::{AsyncReadExt, AsyncWriteExt},
+};
+
+use crate::DesktopRuntime;
+
+/// Event emitted while large artifacts are being copied
+pub const ARTIFACT_PROGRESS_EVENT: &str = "openchamber:artifact-progress";
+
+const DEFAULT_ARTIFACT_TTL_MINUTES: u64 = 30;
+const MAX_ARTIFACT_TTL_MINUTES: u64 = 24 * 60;
+const MAX_ARTIFACT_BYTES: u64 = 2 * 1024 * 1024 * 1024; // 2GB
+const COPY_CHUNK_BYTES: usize = 1024 * 1024;
+/// Files below this size copy without progress events
+const PROGRESS_THRESHOLD_BYTES: u64 = 8 * 1024 * 1024;
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(100);
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RegisteredArtifact {
+    token: String,
+    file_name: String,
+    size: u64,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SavedArtifact {
+    /// None when the save dialog was cancelled; the token stays valid
+    path: Option<String>,
+    bytes: u64,
+}
+
+/// Register a file an agent produced so the user can save a copy. The file may
+/// live outside the workspace but must be a readable regular file under the size cap.
+#[tauri::command]
+pub async fn register_artifact(
+    path: String,
+    suggested_name: Option<String>,
+    ttl_minutes: Option<u64>,
+    state: State<'_, DesktopRuntime>,
+) -> Result<RegisteredArtifact, String> {
+    let path = PathBuf::from(path.trim());
+    if !path.is_absolute() {
+        return Err("Artifact path must be absolute".to_string());
+    }
+
+    let metadata = fs::metadata(&path)
+        .await
+        .map_err(|e| format!("Artifact not found: {}", e))?;
+    if !metadata.is_file() {
+        return Err("Artifact must be a regular file".to_string());
+    }
+    if metadata.len() > MAX_ARTIFACT_BYTES {
+        return Err(format!(
+            "Artifact exceeds the {} byte limit",
+            MAX_ARTIFACT_BYTES
+        ));
+    }
+    fs::File::open(&path)
+        .await
+        .map_err(|e| format!("Artifact is not readable: {}", e))?;
+
+    let file_name = suggested_name
+        .map(|name| sanitize_file_name(&name))
+        .filter(|name| !name.is_empty())
+        .or_else(|| {
+            path.file_name()
+                .and_then(|name| name.to_str())
+                .map(str::to_string)
+        })
+        .unwrap_or_else(|| "artifact".to_string());
+
+    let ttl_minutes = ttl_minutes
+        .unwrap_or(DEFAULT_ARTIFACT_TTL_MINUTES)
+        .clamp(1, MAX_ARTIFACT_TTL_MINUTES);
+    let token = state.downloads().register_for(
+        path,
+        file_name.clone(),
+        Duration::from_secs(ttl_minutes * 60),
+    );
+
+    Ok(RegisteredArtifact {
+        token,
+        file_name,
+        size: metadata.len(),
+    })
+}
+
+/// Copy a registered artifact to `destination`, asking with a save dialog when omitted.
+/// The token is consumed once the copy succeeds.
+#[tauri::command]
+pub async fn save_artifact(
+    token: String,
+    destination: Option<String>,
+    app: AppHandle,
+    state: State<'_, DesktopRuntime>,
+) -> Result<SavedArtifact, String> {
+    let (source, file_name) = state
+        .downloads()
+        .lookup(&token)
+        .ok_or_else(|| "Artifact link expired or was already used".to_string())?;
+
+    let destination = match destination.map(|value| value.trim().to_string()) {
+        Some(value) if !value.is_empty() => PathBuf::from(value),
+        _ => match ask_destination(&app, &file_name).await {
+            Some(path) => path,
+            None => {
+                return Ok(SavedArtifact {
+                    path: None,
+                    bytes: 0,
+                })
+            }
+        },
+    };
+
+    let bytes = copy_with_progress(&source, &destination, |copied, total| {
+        let _ = app.emit(
+            ARTIFACT_PROGRESS_EVENT,
+            json!({ "token": token, "copied": copied, "total": total }),
+        );
+    })
+    .await
+    .map_err(|e| format!("Failed to save artifact: {}", e))?;
+
+    state.downloads().consume(&token);
+    info!(
+        "[desktop:artifacts] Saved {} to {}",
+        file_name,
+        destination.display()
+    );
+    Ok(SavedArtifact {
+        path: Some(destination.to_string_lossy().to_string()),
+        bytes,
+    })
+}
+
+fn sanitize_file_name(name: &str) -> String {
+    name.trim()
+        .chars()
+        .filter(|ch| !ch.is_control() && !matches!(ch, '/' | '\\' | ':'))
+        .collect::<String>()
+        .trim_start_matches('.')
+        .to_string()
+}
+
+async fn ask_destination(app: &AppHandle, file_name: &str) -> Option<PathBuf> {
+    let (tx, rx) = tokio::sync::oneshot::channel();
+    app.dialog()
+        .file()
+        .set_file_name(file_name)
+        .save_file(move |path| {
+            let _ = tx.send(path);
+        });
+    rx.await.ok().flatten().and_then(|path| path.into_path().ok())
+}
+
+/// Copy through a `.part` file renamed into place, reporting `(copied, total)`
+/// at most every PROGRESS_INTERVAL for files above PROGRESS_THRESHOLD_BYTES.
+async fn copy_with_progress(
+    source: &Path,
+    destination: &Path,
+    mut on_progress: impl FnMut(u64, u64),
+) -> std::io::Result<u64> {
+    let mut input = fs::File::open(source).await?;
+    let total = input.metadata().await?.len();
+    let report = total >= PROGRESS_THRESHOLD_BYTES;
+
+    let mut partial = destination.as_os_str().to_owned();
+    partial.push(".part");
+    let partial = PathBuf::from(partial);
+
+    let result = async {
+        let mut output = fs::File::create(&partial).await?;
+        let mut buffer = vec![0u8; COPY_CHUNK_BYTES];
+        let mut copied = 0u64;
+        let mut last_report = Instant::now();
+        loop {
+            let read = input.read(&mut buffer).await?;
+            if read == 0 {
+                break;
+            }
+            output.write_all(&buffer[..read]).await?;
+            copied += read as u64;
+            if report && last_report.elapsed() >= PROGRESS_INTERVAL {
+                on_progress(copied, total);
+                last_report = Instant::now();
+            }
+        }
+        output.flush().await?;
+        output.sync_all().await?;
+        Ok::<_, std::io::Error>(copied)
+    }
+    .await;
+
+    match result {
+        Ok(copied) => {
+            fs::rename(&partial, destination).await?;
+            if report {
+                on_progress(copied, total);
+            }
+            Ok(copied)
+        }
+        Err(err) => {
+            let _ = fs::remove_file(&partial).await;
+            Err(err)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A scratch directory under the system temp dir, removed on drop
+    struct TempDir(PathBuf);
+
+    impl TempDir {
+        fn new() -> Self {
+            let dir = std::env::temp_dir()
+                .join(format!("openchamber-artifacts-{}", uuid::Uuid::new_v4()));
+            std::fs::create_dir_all(&dir).unwrap();
+            Self(dir)
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            std::fs::remove_dir_all(&self.0).ok();
+        }
+    }
+
+    fn entries(dir: &Path) -> Vec<String> {
+        let mut names: Vec<_> = std::fs::read_dir(dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().to_string())
+            .collect();
+        names.sort();
+        names
+    }
+
+    #[test]
+    fn suggested_names_cannot_pick_a_directory() {
+        assert_eq!(sanitize_file_name(" fix.patch "), "fix.patch");
+        assert_eq!(sanitize_file_name("../../etc/passwd"), "etcpasswd");
+        assert_eq!(sanitize_file_name("C:\\out\nput.zip"), "Coutput.zip");
+        assert_eq!(sanitize_file_name("..."), "");
+    }
+
+    #[tokio::test]
+    async fn small_files_copy_without_progress_events() {
+        let dir = TempDir::new();
+        let source = dir.0.join("fix.patch");
+        std::fs::write(&source, "diff --git a b").unwrap();
+        let destination = dir.0.join("saved.patch");
+
+        let mut events = Vec::new();
+        let copied = copy_with_progress(&source, &destination, |copied, total| {
+            events.push((copied, total))
+        })
+        .await
+        .unwrap();
+
+        assert_eq!(copied, 14);
+        assert!(events.is_empty());
+        assert_eq!(
+            std::fs::read_to_string(destination).unwrap(),
+            "diff --git a b"
+        );
+        assert_eq!(entries(&dir.0), ["fix.patch", "saved.patch"]);
+    }
+
+    #[tokio::test]
+    async fn large_files_report_progress_up_to_the_total() {
+        let dir = TempDir::new();
+        let source = dir.0.join("build.zip");
+        let contents: Vec<u8> = (0..PROGRESS_THRESHOLD_BYTES + 12345)
+            .map(|i| (i % 251) as u8)
+            .collect();
+        std::fs::write(&source, &contents).unwrap();
+        let destination = dir.0.join("saved.zip");
+
+        let mut events = Vec::new();
+        let copied = copy_with_progress(&source, &destination, |copied, total| {
+            events.push((copied, total))
+        })
+        .await
+        .unwrap();
+
+        let total = contents.len() as u64;
+        assert_eq!(copied, total);
+        assert_eq!(events.last(), Some(&(total, total)));
+        assert!(events.windows(2).all(|pair| pair[0].0 <= pair[1].0));
+        assert!(std::fs::read(destination).unwrap() == contents);
+    }
+
+    #[tokio::test]
+    async fn failed_copies_leave_nothing_behind() {
+        let dir = TempDir::new();
+        let source = dir.0.join("fix.patch");
+        std::fs::write(&source, "diff").unwrap();
+
+        let missing_dir = dir.0.join("missing").join("saved.patch");
+        assert!(copy_with_progress(&source, &missing_dir, |_, _| {})
+            .await
+            .is_err());
+        // A directory opens but fails on the first read, after the .part file exists
+        let unreadable = dir.0.join("unreadable");
+        std::fs::create_dir(&unreadable).unwrap();
+        assert!(
+            copy_with_progress(&unreadable, &dir.0.join("out"), |_, _| {})
+                .await
+                .is_err()
+        );
+        std::fs::remove_dir(&unreadable).unwrap();
+        assert_eq!(entries(&dir.0), ["fix.patch"]);
+    }
+}
@@ -0,0 +1,71 @@
+use log::info;
+use portable_pty::{CommandBuilder, PtySize};
+use serde::Deserialize;
+use tauri::{State, Window};
+
+use super::terminal::{
+    apply_terminal_environment, resolve_shell, spawn_pty_session, CreateTerminalResponse,
+    TerminalState,
+};
+use crate::{
+    opencode_auth::{self, AuthStatus},
+    DesktopRuntime,
+};
+
+#[derive(Deserialize)]
+pub struct StartAuthPayload {
+    pub provider: String,
+    pub cols: u16,
+    pub rows: u16,
+}
+
+/// Providers logged in through `opencode auth login`, with secrets redacted
+#[tauri::command]
+pub async fn get_opencode_auth_status() -> Result<AuthStatus, String> {
+    let path = opencode_auth::auth_file_path()
+        .ok_or_else(|| "Cannot determine the OpenCode data directory".to_string())?;
+    Ok(opencode_auth::auth_status(&path).await)
+}
+
+/// Run `opencode auth login <provider>` in a terminal session so interactive
+/// OAuth prompts can be answered from the UI. Output streams like any other
+/// terminal; refresh OpenCode via /api/config/reload once it exits.
+#[tauri::command]
+pub async fn start_opencode_auth(
+    payload: StartAuthPayload,
+    state: State<'_, TerminalState>,
+    runtime: State<'_, DesktopRuntime>,
+    window: Window,
+) -> Result<CreateTerminalResponse, String> {
+    let provider = opencode_auth::validate_provider_id(&payload.provider)
+        .map_err(|e| format!("Invalid provider id: {}", e))?;
+    let manager = runtime.opencode_manager();
+    let Some((binary, env)) = manager.cli_invocation() else {
+        return Err("OpenCode CLI not found".to_string());
+    };
+
+    let mut cmd = CommandBuilder::new(binary);
+    cmd.args(["auth", "login", provider.as_str()]);
+    for (key, value) in env {
+        cmd.env(key, value);
+    }
+    apply_terminal_environment(&mut cmd, &resolve_shell());
+    let workspace = manager.get_working_directory();
+    if let Some(home) = dirs::home_dir() {
+        cmd.cwd(home);
+    }
+
+    let size = PtySize {
+        rows: payload.rows,
+        cols: payload.cols,
+        pixel_width: 0,
+        pixel_height: 0,
+    };
+    let session_id = spawn_pty_session(cmd, size, &state, window, workspace, false)?;
+    info!(
+        "[desktop:config] Started opencode auth login for {}",
+        provider
+    );
+
+    Ok(CreateTerminalResponse { session_id })
+}
@@ -0,0 +1,34 @@
+use tauri::State;
+
+use crate::{
+    config_journal::{default_journal_dir, ConfigJournal, PendingRecovery, RecoveryAction},
+    DesktopRuntime,
+};
+
+/// Interrupted config mutations, for UIs that missed the startup event
+#[tauri::command]
+pub async fn list_config_recoveries() -> Result<Vec<PendingRecovery>, String> {
+    Ok(ConfigJournal::new(default_journal_dir()).pending().await)
+}
+
+/// Resolve an interrupted mutation: "rollback" restores the pre-operation files,
+/// "ignore" keeps the files as they are and forgets the record.
+#[tauri::command]
+pub async fn resolve_config_recovery(
+    id: String,
+    action: String,
+    state: State<'_, DesktopRuntime>,
+) -> Result<(), String> {
+    let action = RecoveryAction::parse(action.trim())
+        .ok_or_else(|| format!("Unknown recovery action: {}", action))?;
+    ConfigJournal::new(default_journal_dir())
+        .resolve(id.trim(), action)
+        .await
+        .map_err(|e| format!("Failed to resolve config recovery: {}", e))?;
+
+    if action == RecoveryAction::Rollback {
+        state.model_capabilities().invalidate();
+        state.agent_preferences().invalidate().await;
+    }
+    Ok(())
+}
@@ -0,0 +1,154 @@
+use std::path::{Path, PathBuf};
+
+use chrono::Local;
+use log::info;
+use serde::Serialize;
+use tauri::State;
+use tokio::fs;
+
+use crate::{
+    commands::git::diff_text,
+    document_export::{self, ExportKind},
+    paths, DesktopRuntime,
+};
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ExportResult {
+    path: String,
+    bytes: u64,
+}
+
+/// HTML page for an export
+async fn render(kind: ExportKind, target: &str, state: &DesktopRuntime) -> Result<String, String> {
+    let generated = format!("Exported {}", Local::now().format("%Y-%m-%d %H:%M"));
+    match kind {
+        ExportKind::Session => {
+            if target.is_empty() {
+                return Err("Session id is required".to_string());
+            }
+            let client = state.opencode_client();
+            let session = client
+                .get_session(target)
+                .await
+                .map_err(|e| format!("Failed to load session: {}", e))?;
+            let messages = client
+                .list_messages(target)
+                .await
+                .map_err(|e| format!("Failed to load messages: {}", e))?;
+            let title = session.display_title().unwrap_or(target).to_string();
+            let body = document_export::render_session(&messages);
+            Ok(document_export::render_document(
+                &title,
+                &format!("Session {} · {}", session.id, generated),
+                &body,
+            ))
+        }
+        ExportKind::Diff => {
+            let workspace = state.opencode_manager().get_working_directory();
+            let path = if target.is_empty() { "." } else { target };
+            let diff = diff_text(&workspace, path, false, 3)
+                .await
+                .map_err(|e| format!("Failed to read diff: {}", e))?;
+            let title = if target.is_empty() {
+                workspace
+                    .file_name()
+                    .map(|name| format!("Changes in {}", name.to_string_lossy()))
+                    .unwrap_or_else(|| "Changes".to_string())
+            } else {
+                format!("Changes to {}", target)
+            };
+            let body = document_export::render_diff(&diff);
+            Ok(document_export::render_document(
+                &title,
+                &format!("{} · {}", workspace.display(), generated),
+                &body,
+            ))
+        }
+    }
+}
+
+/// `path` when given, else a timestamped file in Downloads
+fn output_path(path: Option<String>, kind: ExportKind, extension: &str) -> Result<PathBuf, String> {
+    if let Some(path) = path
+        .map(|path| path.trim().to_string())
+        .filter(|path| !path.is_empty())
+    {
+        let path = PathBuf::from(path);
+        if !path.is_absolute() {
+            return Err("Export path must be absolute".to_string());
+        }
+        return Ok(path);
+    }
+    let dir = dirs::download_dir()
+        .or_else(|| paths::cache_dir().map(|dir| dir.join("exports")))
+        .ok_or_else(|| "No directory to export to".to_string())?;
+    Ok(dir.join(format!(
+        "openchamber-{}-{}.{}",
+        kind.label(),
+        Local::now().format("%Y%m%d-%H%M%S"),
+        extension
+    )))
+}
+
+async fn write_file(path: &Path, contents: &[u8]) -> Result<u64, String> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)
+            .await
+            .map_err(|e| format!("Failed to create {}: {}", parent.display(), e))?;
+    }
+    fs::write(path, contents)
+        .await
+        .map_err(|e| format!("Failed to write {}: {}", path.display(), e))?;
+    Ok(contents.len() as u64)
+}
+
+/// Render a session transcript or the workspace diff as a standalone HTML page
+#[tauri::command]
+pub async fn export_to_html(
+    kind: ExportKind,
+    target: String,
+    path: Option<String>,
+    state: State<'_, DesktopRuntime>,
+) -> Result<ExportResult, String> {
+    let html = render(kind, target.trim(), &state).await?;
+    let output = output_path(path, kind, "html")?;
+    let bytes = write_file(&output, html.as_bytes()).await?;
+    info!("[desktop:export] Wrote {}", output.display());
+    Ok(ExportResult {
+        path: output.to_string_lossy().to_string(),
+        bytes,
+    })
+}
+
+/// Like `export_to_html`, printed to PDF. Without a browser able to print,
+/// fails with PDF_UNSUPPORTED so the UI can offer the HTML export instead.
+#[tauri::command]
+pub async fn export_to_pdf(
+    kind: ExportKind,
+    target: String,
+    path: Option<String>,
+    state: State<'_, DesktopRuntime>,
+) -> Result<ExportResult, String> {
+    let html = render(kind, target.trim(), &state).await?;
+    let output = output_path(path, kind, "pdf")?;
+    if let Some(parent) = output.parent() {
+        fs::create_dir_all(parent)
+            .await
+            .map_err(|e| format!("Failed to create {}: {}", parent.display(), e))?;
+    }
+
+    let page =
+        std::env::temp_dir().join(format!("openchamber-export-{}.html", uuid::Uuid::new_v4()));
+    write_file(&page, html.as_bytes()).await?;
+    let printed = document_export::print_to_pdf(&page, &output).await;
+    let _ = fs::remove_file(&page).await;
+    printed.map_err(|e| e.to_string())?;
+
+    let bytes = fs::metadata(&output).await.map(|m| m.len()).unwrap_or(0);
+    info!("[desktop:export] Wrote {}", output.display());
+    Ok(ExportResult {
+        path: output.to_string_lossy().to_string(),
+        bytes,
+    })
+}
@@ -1,4 +1,7 @@
-use crate::{DesktopRuntime, SettingsStore};
+use crate::{
+    file_index::{self, IndexStatus},
+    DesktopRuntime, SettingsStore,
+};
 use serde::Serialize;
 use std::{
     collections::{HashSet, VecDeque},
@@ -224,14 +227,47 @@ pub async fn search_files(
 
     let limit = clamp_search_limit(max_results);
     let normalized_query = query.unwrap_or_default().trim().to_lowercase();
-    let match_all = normalized_query.is_empty();
 
+    let settings = state.settings().load().await.unwrap_or_default();
+    if file_index::indexing_enabled(&settings) {
+        let indexed = state
+            .file_index()
+            .search(&resolved_root, &normalized_query, limit);
+        if let Some(relative_paths) = indexed {
+            let files: Vec<FileSearchHit> = relative_paths
+                .into_iter()
+                .map(|relative_path| index_hit(&resolved_root, relative_path))
+                .collect();
+            return Ok(SearchFilesResponse {
+                root: normalize_path(&resolved_root),
+                count: files.len(),
+                files,
+            });
+        }
+        // Cold or indexing another root: warm it for the next keystroke and walk now
+        if let Some(root) = workspace_root.clone() {
+            state.file_index().ensure(root);
+        }
+    }
+
+    let files = walk_search(&resolved_root, &normalized_query, limit).await;
+    Ok(SearchFilesResponse {
+        root: normalize_path(&resolved_root),
+        count: files.len(),
+        files,
+    })
+}
+
+/// Breadth-first walk of `root` for files whose name or path contains `query`
+/// (already lowercased; empty matches everything)
+async fn walk_search(root: &Path, query: &str, limit: usize) -> Vec<FileSearchHit> {
+    let match_all = query.is_empty();
     let mut files = Vec::new();
     let mut queue = VecDeque::new();
     let mut visited = HashSet::new();
 
-    queue.push_back(resolved_root.clone());
-    visited.insert(resolved_root.clone());
+    queue.push_back(root.to_path_buf());
+    visited.insert(root.to_path_buf());
 
     while !queue.is_empty() && files.len() < limit {
         for _ in 0..FILE_SEARCH_MAX_CONCURRENCY {
@@ -270,13 +306,11 @@ pub async fn search_files(
                     continue;
                 }
 
-                let relative_path = relative_path(&resolved_root, &entry_path);
+                let relative_path = relative_path(root, &entry_path);
                 if !match_all {
                     let lowercase_name = name_str.to_lowercase();
                     let lowercase_path = relative_path.to_lowercase();
-                    if !lowercase_name.contains(&normalized_query)
-                        && !lowercase_path.contains(&normalized_query)
-                    {
+                    if !lowercase_name.contains(query) && !lowercase_path.contains(query) {
                         continue;
                     }
                 }
@@ -304,11 +338,31 @@ pub async fn search_files(
         }
     }
 
-    Ok(SearchFilesResponse {
-        root: normalize_path(&resolved_root),
-        count: files.len(),
-        files,
-    })
+    files
+}
+
+/// State of the background file index used by `search_files`
+#[tauri::command]
+pub async fn get_index_status(
+    state: tauri::State<'_, DesktopRuntime>,
+) -> Result<IndexStatus, String> {
+    Ok(state.file_index().status())
+}
+
+/// Re-walk the workspace into the file index, e.g. after a large checkout
+#[tauri::command]
+pub async fn rebuild_file_index(
+    state: tauri::State<'_, DesktopRuntime>,
+) -> Result<IndexStatus, String> {
+    let settings = state.settings().load().await.unwrap_or_default();
+    if !file_index::indexing_enabled(&settings) {
+        return Err("File indexing is disabled (fileSearch.indexing)".to_string());
+    }
+    let root = resolve_workspace_root(state.settings())
+        .await
+        .ok_or_else(|| "No workspace is open".to_string())?;
+    state.file_index().rebuild(root);
+    Ok(state.file_index().status())
 }
 
 #[tauri::command]
@@ -404,6 +458,47 @@ async fn resolve_creatable_path(
     Ok(absolute)
 }
 
+/// Resolve a directory to create as a new project root. Unlike `create_directory`,
+/// the parent may be any approved directory rather than only the current workspace.
+pub(crate) async fn resolve_new_project_root(
+    path: &str,
+    settings: &SettingsStore,
+) -> Result<PathBuf, String> {
+    let candidate = PathBuf::from(path.trim());
+    if !candidate.is_absolute() {
+        return Err("Project path must be absolute".to_string());
+    }
+
+    let mut roots = Vec::new();
+    if let Some(root) = resolve_workspace_root(settings).await {
+        roots.push(root);
+    }
+    let approved = settings
+        .load()
+        .await
+        .ok()
+        .and_then(|value| value.get("approvedDirectories").cloned())
+        .and_then(|value| value.as_array().cloned())
+        .unwrap_or_default();
+    for dir in approved.iter().filter_map(|value| value.as_str()) {
+        if let Ok(canonical) = fs::canonicalize(dir).await {
+            roots.push(canonical);
+        }
+    }
+    if roots.is_empty() {
+        roots.push(default_home_directory());
+    }
+
+    let mut last_error = FsCommandError::OutsideWorkspace;
+    for root in &roots {
+        match resolve_creatable_path(path.trim(), Some(root)).await {
+            Ok(resolved) => return Ok(resolved),
+            Err(err) => last_error = err,
+        }
+    }
+    Err(last_error.to_create_message())
+}
+
 async fn resolve_workspace_root(settings: &SettingsStore) -> Option<PathBuf> {
     if let Ok(Some(last_dir)) = settings.last_directory().await {
         if let Ok(canonicalized) = fs::canonicalize(&last_dir).await {
@@ -422,7 +517,7 @@ fn clamp_search_limit(value: Option<usize>) -> usize {
     limit.clamp(1, MAX_FILE_SEARCH_LIMIT)
 }
 
-fn should_skip_directory(name: &str) -> bool {
+pub(crate) fn should_skip_directory(name: &str) -> bool {
     if name.starts_with('.') {
         return true;
     }
@@ -431,6 +526,25 @@ fn should_skip_directory(name: &str) -> bool {
         .any(|dir| dir.eq_ignore_ascii_case(name))
 }
 
+fn index_hit(root: &Path, relative_path: String) -> FileSearchHit {
+    let full_path = root.join(&relative_path);
+    let name = relative_path
+        .rsplit('/')
+        .next()
+        .unwrap_or(&relative_path)
+        .to_string();
+    let extension = full_path
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .map(|ext| ext.to_lowercase());
+    FileSearchHit {
+        name,
+        path: normalize_path(&full_path),
+        relative_path,
+        extension,
+    }
+}
+
 fn normalize_path(path: &Path) -> String {
     path.to_string_lossy().replace('\\', "/")
 }
@@ -441,3 +555,158 @@ fn relative_path(root: &Path, target: &Path) -> String {
         .map(|relative| normalize_path(relative))
         .unwrap_or_else(|_| normalize_path(target))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::file_index::FileIndex;
+
+    /// A workspace tree under the system temp dir, removed on drop
+    struct Workspace {
+        root: PathBuf,
+    }
+
+    impl Workspace {
+        fn new() -> Self {
+            let root =
+                std::env::temp_dir().join(format!("openchamber-files-{}", uuid::Uuid::new_v4()));
+            std::fs::create_dir_all(&root).unwrap();
+            Self { root }
+        }
+
+        fn create(&self, relative: &str) {
+            let path = self.root.join(relative);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, relative).unwrap();
+        }
+
+        fn delete(&self, relative: &str) {
+            std::fs::remove_file(self.root.join(relative)).unwrap();
+        }
+    }
+
+    impl Drop for Workspace {
+        fn drop(&mut self) {
+            std::fs::remove_dir_all(&self.root).ok();
+        }
+    }
+
+    /// name, path, relative path, extension
+    type HitFields = (String, String, String, Option<String>);
+
+    fn hit_fields(hit: &FileSearchHit) -> HitFields {
+        (
+            hit.name.clone(),
+            hit.path.clone(),
+            hit.relative_path.clone(),
+            hit.extension.clone(),
+        )
+    }
+
+    /// Rebuild the index and wait until it reflects the tree on disk
+    async fn reindex(index: &FileIndex, root: &Path) {
+        index.rebuild(root.to_path_buf());
+        for _ in 0..500 {
+            if serde_json::to_value(index.status()).unwrap()["state"] == "ready" {
+                return;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        }
+        panic!("index never became ready");
+    }
+
+    /// Walker hits, and the indexed hits that are plain substring matches (the
+    /// index also returns fuzzy ones), both sorted by relative path
+    async fn both_searches(
+        index: &FileIndex,
+        scope: &Path,
+        query: &str,
+    ) -> (Vec<HitFields>, Vec<HitFields>) {
+        let mut walked: Vec<_> = walk_search(scope, query, MAX_FILE_SEARCH_LIMIT)
+            .await
+            .iter()
+            .map(hit_fields)
+            .collect();
+        let mut indexed: Vec<_> = index
+            .search(scope, query, MAX_FILE_SEARCH_LIMIT)
+            .expect("index answers")
+            .into_iter()
+            .map(|relative| hit_fields(&index_hit(scope, relative)))
+            .filter(|(name, _, relative, _)| {
+                name.to_lowercase().contains(query) || relative.to_lowercase().contains(query)
+            })
+            .collect();
+        walked.sort_by(|a, b| a.2.cmp(&b.2));
+        indexed.sort_by(|a, b| a.2.cmp(&b.2));
+        (walked, indexed)
+    }
+
+    async fn assert_index_matches_walker(index: &FileIndex, workspace: &Workspace) {
+        let scopes = [workspace.root.clone(), workspace.root.join("src")];
+        for scope in &scopes {
+            for query in ["", "main", "mod.rs", "readme", "lib/", "zzz"] {
+                let (walked, indexed) = both_searches(index, scope, query).await;
+                assert_eq!(indexed, walked, "{} / {query:?}", scope.display());
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn indexed_results_match_the_walker_after_changes() {
+        let workspace = Workspace::new();
+        for file in [
+            "README.md",
+            "src/main.rs",
+            "src/lib/mod.rs",
+            "src/lib/parser.RS",
+            "docs/main.md",
+            ".hidden/main.rs",
+            "node_modules/pkg/main.js",
+            "target/debug/main",
+        ] {
+            workspace.create(file);
+        }
+        let index = FileIndex::default();
+        reindex(&index, &workspace.root).await;
+        assert_index_matches_walker(&index, &workspace).await;
+
+        workspace.create("src/bin/main_tool.rs");
+        workspace.create("src/lib/deep/mod.rs");
+        workspace.delete("src/main.rs");
+        workspace.delete("README.md");
+        reindex(&index, &workspace.root).await;
+        assert_index_matches_walker(&index, &workspace).await;
+
+        workspace.create("README.md");
+        workspace.delete("src/lib/mod.rs");
+        reindex(&index, &workspace.root).await;
+        assert_index_matches_walker(&index, &workspace).await;
+    }
+
+    #[tokio::test]
+    async fn walker_results_are_capped_at_the_limit() {
+        let workspace = Workspace::new();
+        for i in 0..10 {
+            workspace.create(&format!("dir{i}/file{i}.txt"));
+        }
+
+        assert_eq!(walk_search(&workspace.root, "", 4).await.len(), 4);
+        assert_eq!(walk_search(&workspace.root, "file3", 4).await.len(), 1);
+        assert_eq!(clamp_search_limit(Some(0)), 1);
+        assert_eq!(clamp_search_limit(Some(usize::MAX)), MAX_FILE_SEARCH_LIMIT);
+    }
+
+    #[test]
+    fn index_hits_describe_the_file_like_walker_hits() {
+        let hit = index_hit(Path::new("/work/repo"), "src/Lib.RS".to_string());
+        assert_eq!(
+            hit_fields(&hit),
+            (
+                "Lib.RS".to_string(),
+                "/work/repo/src/Lib.RS".to_string(),
+                "src/Lib.RS".to_string(),
+                Some("rs".to_string()),
+            )
+        );
+    }
+}
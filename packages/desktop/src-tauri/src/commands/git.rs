@@ -1,5 +1,6 @@
-use crate::{DesktopRuntime, SettingsStore};
+use crate::{paths, DesktopRuntime, SettingsStore};
 use anyhow::{anyhow, Context, Result};
+use futures_util::future::{join_all, BoxFuture, FutureExt, Shared};
 use log::{error, info, warn};
 use regex::Regex;
 use reqwest::Client;
@@ -8,11 +9,18 @@ use serde_json::Value;
 use std::collections::{HashMap, HashSet};
 use std::path::{Path, PathBuf};
 use std::sync::LazyLock;
+use std::time::Instant;
 use tauri::State;
 use tokio::fs;
 use tokio::process::Command;
+use tokio::sync::Semaphore;
 
 const GIT_IDENTITY_STORAGE_FILE: &str = "git-identities.json";
+/// Above this many changed files `get_git_status` leaves out diff stats unless
+/// `includeStats` asks for them; the UI fetches per-file diffs lazily instead
+const LAZY_STATS_THRESHOLD: usize = 1000;
+/// Status and diff git processes allowed at once across all callers
+const GIT_WORK_CONCURRENCY: usize = 4;
 
 // --- Structs mirroring TypeScript types ---
 
@@ -42,6 +50,25 @@ pub struct DiffStat {
     pub deletions: i32,
 }
 
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct GitFileDiff {
+    pub path: String,
+    pub diff: String,
+    pub truncated: bool,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct GitDiffResult {
+    /// Diff of `pathStr`; empty when only `paths` was requested
+    pub diff: String,
+    pub truncated: bool,
+    /// One entry per requested `paths` entry, in request order
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub files: Vec<GitFileDiff>,
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone)]
 #[serde(rename_all = "camelCase")]
 pub struct GitBranchDetails {
@@ -229,6 +256,15 @@ pub struct GitWorktreeInfo {
     pub branch: Option<String>,
 }
 
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct GitRepositoryInfo {
+    pub root: String,
+    /// The repository that owns the workspace itself
+    pub is_primary: bool,
+    pub branch: Option<String>,
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone)]
 #[serde(rename_all = "camelCase")]
 pub struct GeneratedCommitMessage {
@@ -268,12 +304,27 @@ static INSERTIONS_REGEX: LazyLock<Regex> =
 static DELETIONS_REGEX: LazyLock<Regex> =
     LazyLock::new(|| Regex::new(r"(\d+)\s+deletions?\(-\)").unwrap());
 
+static GIT_WORK: LazyLock<Semaphore> = LazyLock::new(|| Semaphore::new(GIT_WORK_CONCURRENCY));
+
+type StatusFuture = Shared<BoxFuture<'static, Result<GitStatus, String>>>;
+/// Keyed by repository root and `includeStats`
+type StatusRuns = HashMap<(PathBuf, Option<bool>), StatusFuture>;
+
+/// Status runs in flight per repository and `includeStats`
+static STATUS_IN_FLIGHT: LazyLock<parking_lot::Mutex<StatusRuns>> =
+    LazyLock::new(Default::default);
+
 // --- Helpers ---
 
 async fn run_git(args: &[&str], cwd: &Path) -> Result<String> {
     run_git_with_allowed_exit(args, cwd, &[]).await
 }
 
+/// Initialise a new repository in `root`
+pub(crate) async fn init_repository(root: &Path) -> Result<()> {
+    run_git(&["init"], root).await.map(|_| ())
+}
+
 async fn run_git_with_allowed_exit(
     args: &[&str],
     cwd: &Path,
@@ -369,12 +420,94 @@ async fn validate_git_path(path: &str, _settings: &SettingsStore) -> Result<Path
     Ok(path_buf)
 }
 
+/// Directories that commonly hold vendored or nested checkouts
+const NESTED_REPOSITORY_CONTAINERS: &[&str] = &[
+    "vendor",
+    "third_party",
+    "third-party",
+    "external",
+    "deps",
+    "libs",
+    "packages",
+    "submodules",
+];
+
+/// `.git` is a directory for normal checkouts and a file for worktrees and submodules
+fn has_git_marker(dir: &Path) -> bool {
+    dir.join(".git").exists()
+}
+
+/// Repository that owns the workspace, which may sit above the workspace directory
+async fn primary_repository_root(workspace: &Path) -> Option<PathBuf> {
+    let output = run_git(&["rev-parse", "--show-toplevel"], workspace)
+        .await
+        .ok()?;
+    let root = output.trim();
+    if root.is_empty() {
+        return None;
+    }
+    fs::canonicalize(root).await.ok()
+}
+
+async fn describe_repository(root: &Path, primary: Option<&Path>) -> GitRepositoryInfo {
+    let branch = get_current_branch_name(root)
+        .await
+        .ok()
+        .filter(|name| !name.is_empty());
+    GitRepositoryInfo {
+        root: root.to_string_lossy().to_string(),
+        is_primary: primary == Some(root),
+        branch,
+    }
+}
+
+/// Walk up from `start` to the nearest directory with a `.git` marker without
+/// leaving `workspace`; falls back to the repository owning the workspace.
+async fn discover_repository_root(workspace: &Path, start: &Path) -> Option<PathBuf> {
+    let mut current = if start.is_file() {
+        start.parent()?.to_path_buf()
+    } else {
+        start.to_path_buf()
+    };
+    while current.starts_with(workspace) {
+        if has_git_marker(&current) {
+            return fs::canonicalize(&current).await.ok();
+        }
+        if current == workspace || !current.pop() {
+            break;
+        }
+    }
+    primary_repository_root(workspace).await
+}
+
+/// Resolve the directory git commands run in: the workspace, or a nested
+/// repository under it when `repo_root` is given
+async fn scoped_git_root(
+    directory: &str,
+    repo_root: Option<String>,
+    settings: &SettingsStore,
+) -> Result<PathBuf> {
+    let workspace = validate_git_path(directory, settings).await?;
+    let Some(repo_root) = repo_root.filter(|value| !value.trim().is_empty()) else {
+        return Ok(workspace);
+    };
+
+    let repo_root = validate_git_path(repo_root.trim(), settings).await?;
+    let workspace = fs::canonicalize(&workspace).await?;
+    let repo_root = fs::canonicalize(&repo_root).await?;
+    if !repo_root.starts_with(&workspace) {
+        return Err(anyhow!("Repository must be inside the workspace"));
+    }
+    if !has_git_marker(&repo_root) {
+        return Err(anyhow!("Not a git repository: {}", repo_root.display()));
+    }
+    Ok(repo_root)
+}
+
 // --- Identity Storage ---
 
 async fn get_identity_storage_path() -> Result<PathBuf> {
-    let mut path = dirs::home_dir().ok_or_else(|| anyhow!("Could not find home directory"))?;
-    path.push(".config");
-    path.push("openchamber");
+    let mut path = paths::config_dir().ok_or_else(|| anyhow!("Could not find config directory"))?;
     fs::create_dir_all(&path).await?;
     path.push(GIT_IDENTITY_STORAGE_FILE);
     Ok(path)
@@ -445,27 +578,95 @@ pub async fn check_is_git_repository(
 #[tauri::command]
 pub async fn get_git_status(
     directory: String,
+    repo_root: Option<String>,
+    include_stats: Option<bool>,
     state: State<'_, DesktopRuntime>,
 ) -> Result<GitStatus, String> {
-    let path = validate_git_path(&directory, state.settings())
+    let path = scoped_git_root(&directory, repo_root, state.settings())
         .await
         .map_err(|e| e.to_string())?;
+    let started = Instant::now();
+    let result = shared_status(&path, include_stats).await;
+
+    if let Ok(status) = &result {
+        info!(
+            "[desktop:git] Status of {} ({} files, stats: {}) in {:?}",
+            path.display(),
+            status.files.len(),
+            status.diff_stats.is_some(),
+            started.elapsed()
+        );
+    }
+    result
+}
 
-    // 1. Get porcelain status
-    let status_output = run_git(&["status", "--porcelain", "-b", "-z"], &path)
-        .await
-        .map_err(|e| e.to_string())?;
+/// Panels refreshing together share one status run instead of each spawning git
+async fn shared_status(path: &Path, include_stats: Option<bool>) -> Result<GitStatus, String> {
+    let key = (path.to_path_buf(), include_stats);
+    let future = STATUS_IN_FLIGHT
+        .lock()
+        .entry(key.clone())
+        .or_insert_with(|| {
+            let path = path.to_path_buf();
+            async move {
+                collect_status(&path, include_stats)
+                    .await
+                    .map_err(|e| e.to_string())
+            }
+            .boxed()
+            .shared()
+        })
+        .clone();
+    let result = future.clone().await;
+    {
+        let mut in_flight = STATUS_IN_FLIGHT.lock();
+        if in_flight
+            .get(&key)
+            .is_some_and(|existing| existing.ptr_eq(&future))
+        {
+            in_flight.remove(&key);
+        }
+    }
+    result
+}
+
+/// Run `git` holding a GIT_WORK permit
+async fn run_git_limited(args: &[&str], cwd: &Path) -> Result<String> {
+    let _permit = GIT_WORK.acquire().await.ok();
+    run_git(args, cwd).await
+}
+
+async fn collect_status(root: &Path, include_stats: Option<bool>) -> Result<GitStatus> {
+    // Untracked cache and a configured fsmonitor keep this fast on large trees
+    let output = run_git_limited(
+        &[
+            "-c",
+            "core.untrackedCache=true",
+            "status",
+            "--porcelain",
+            "-b",
+            "-z",
+        ],
+        root,
+    )
+    .await?;
+    let mut status = tokio::task::spawn_blocking(move || parse_porcelain_status(&output)).await?;
+
+    if include_stats.unwrap_or(status.files.len() <= LAZY_STATS_THRESHOLD) {
+        status.diff_stats = Some(collect_diff_stats(root, &status.files).await);
+    }
+    Ok(status)
+}
 
-    // Parse status output
+/// Branch and file entries from `git status --porcelain -b -z`
+fn parse_porcelain_status(output: &str) -> GitStatus {
     let mut files = Vec::new();
     let mut current = String::new();
     let mut tracking = None;
     let mut ahead = 0;
     let mut behind = 0;
 
-    let entries: Vec<&str> = status_output.split('\0').collect();
-
-    for entry in entries {
+    for entry in output.split('\0') {
         if entry.is_empty() {
             continue;
         }
@@ -512,136 +713,234 @@ pub async fn get_git_status(
         }
     }
 
-    // 2. Get diff stats (staged and unstaged)
-    let mut diff_stats = HashMap::new();
-
-    let collect_stats = |output: String| {
-        let mut stats = HashMap::new();
-        for line in output.lines() {
-            let parts: Vec<&str> = line.split('\t').collect();
-            if parts.len() >= 3 {
-                let insertions = if parts[0] == "-" {
-                    0
-                } else {
-                    parts[0].parse().unwrap_or(0)
-                };
-                let deletions = if parts[1] == "-" {
-                    0
-                } else {
-                    parts[1].parse().unwrap_or(0)
-                };
-                let path = parts[2].to_string();
-                stats.insert(
-                    path,
-                    DiffStat {
-                        insertions,
-                        deletions,
-                    },
-                );
-            }
-        }
-        stats
-    };
-
-    let staged_stats_raw = run_git(&["diff", "--cached", "--numstat"], &path)
-        .await
-        .unwrap_or_default();
-    let working_stats_raw = run_git(&["diff", "--numstat"], &path)
-        .await
-        .unwrap_or_default();
-
-    let staged_stats = collect_stats(staged_stats_raw);
-    let working_stats = collect_stats(working_stats_raw);
-
-    // Merge stats
-    let mut all_paths: HashSet<String> = staged_stats.keys().cloned().collect();
-    all_paths.extend(working_stats.keys().cloned());
-
-    for p in all_paths {
-        let s = staged_stats.get(&p).unwrap_or(&DiffStat {
-            insertions: 0,
-            deletions: 0,
-        });
-        let w = working_stats.get(&p).unwrap_or(&DiffStat {
-            insertions: 0,
-            deletions: 0,
-        });
-        diff_stats.insert(
-            p,
-            DiffStat {
-                insertions: s.insertions + w.insertions,
-                deletions: s.deletions + w.deletions,
-            },
-        );
-    }
-
-    // 3. Handle new/untracked files (manual calculation if needed, or skip if complex)
-    // Node implementation does manual read. For now, let's assume files with '??' or 'A'
-    // might need stats if they aren't in numstat.
-    // NOTE: untracked files don't show up in `git diff --numstat`.
-    // We can try `wc -l` logic but Rust fs read is safer.
-
-    for file in &files {
-        if (file.working_dir == "?" || file.index == "A") && !diff_stats.contains_key(&file.path) {
-            let full_path = path.join(&file.path);
-            if let Ok(metadata) = fs::metadata(&full_path).await {
-                if metadata.is_file() {
-                    if let Ok(content) = fs::read_to_string(&full_path).await {
-                        let lines = content.lines().count() as i32;
-                        diff_stats.insert(
-                            file.path.clone(),
-                            DiffStat {
-                                insertions: lines,
-                                deletions: 0,
-                            },
-                        );
-                    }
-                }
-            }
-        }
-    }
-
-    Ok(GitStatus {
+    GitStatus {
         current,
         tracking,
         ahead,
         behind,
         is_clean: files.is_empty(),
         files,
-        diff_stats: Some(diff_stats),
+        diff_stats: None,
+    }
+}
+
+fn parse_numstat(output: &str) -> HashMap<String, DiffStat> {
+    let mut stats = HashMap::new();
+    for line in output.lines() {
+        let parts: Vec<&str> = line.split('\t').collect();
+        if parts.len() >= 3 {
+            let insertions = if parts[0] == "-" {
+                0
+            } else {
+                parts[0].parse().unwrap_or(0)
+            };
+            let deletions = if parts[1] == "-" {
+                0
+            } else {
+                parts[1].parse().unwrap_or(0)
+            };
+            let path = parts[2].to_string();
+            stats.insert(
+                path,
+                DiffStat {
+                    insertions,
+                    deletions,
+                },
+            );
+        }
+    }
+    stats
+}
+
+/// Insertions and deletions per file, staged and unstaged combined
+async fn collect_diff_stats(root: &Path, files: &[GitStatusFile]) -> HashMap<String, DiffStat> {
+    let (staged, working) = tokio::join!(
+        run_git_limited(&["diff", "--cached", "--numstat"], root),
+        run_git_limited(&["diff", "--numstat"], root),
+    );
+    // NOTE: untracked files don't show up in `git diff --numstat`, so count their lines
+    let new_files: Vec<String> = files
+        .iter()
+        .filter(|file| file.working_dir == "?" || file.index == "A")
+        .map(|file| file.path.clone())
+        .collect();
+    let root = root.to_path_buf();
+
+    tokio::task::spawn_blocking(move || {
+        let staged_stats = parse_numstat(&staged.unwrap_or_default());
+        let working_stats = parse_numstat(&working.unwrap_or_default());
+
+        let mut diff_stats = HashMap::new();
+        let mut all_paths: HashSet<String> = staged_stats.keys().cloned().collect();
+        all_paths.extend(working_stats.keys().cloned());
+        for p in all_paths {
+            let s = staged_stats.get(&p).unwrap_or(&DiffStat {
+                insertions: 0,
+                deletions: 0,
+            });
+            let w = working_stats.get(&p).unwrap_or(&DiffStat {
+                insertions: 0,
+                deletions: 0,
+            });
+            diff_stats.insert(
+                p,
+                DiffStat {
+                    insertions: s.insertions + w.insertions,
+                    deletions: s.deletions + w.deletions,
+                },
+            );
+        }
+
+        for file_path in new_files {
+            if diff_stats.contains_key(&file_path) {
+                continue;
+            }
+            let full_path = root.join(&file_path);
+            if !full_path.is_file() {
+                continue;
+            }
+            if let Ok(content) = std::fs::read_to_string(&full_path) {
+                diff_stats.insert(
+                    file_path,
+                    DiffStat {
+                        insertions: content.lines().count() as i32,
+                        deletions: 0,
+                    },
+                );
+            }
+        }
+        diff_stats
     })
+    .await
+    .unwrap_or_default()
 }
 
+/// Diff `pathStr`, and/or each of `paths` separately. With `maxBytes`, each
+/// file's diff is cut at the last full line within the cap and marked truncated.
 #[tauri::command]
 pub async fn get_git_diff(
     directory: String,
-    path_str: String,
+    path_str: Option<String>,
+    paths: Option<Vec<String>>,
     staged: Option<bool>,
     context_lines: Option<u32>,
+    max_bytes: Option<usize>,
     state: State<'_, DesktopRuntime>,
-) -> Result<String, String> {
+) -> Result<GitDiffResult, String> {
     let root = validate_git_path(&directory, state.settings())
         .await
         .map_err(|e| e.to_string())?;
+    let paths = paths.unwrap_or_default();
+    if path_str.is_none() && paths.is_empty() {
+        return Err("No path to diff".to_string());
+    }
+    let started = Instant::now();
+    let result = collect_diffs(
+        &root,
+        path_str.as_deref(),
+        &paths,
+        staged.unwrap_or(false),
+        context_lines.unwrap_or(3),
+        max_bytes,
+    )
+    .await
+    .map_err(|e| e.to_string())?;
+
+    info!(
+        "[desktop:git] Diff of {} path(s) in {:?}",
+        paths.len() + usize::from(path_str.is_some()),
+        started.elapsed()
+    );
+    Ok(result)
+}
+
+/// Diffs of `path_str` and of each of `paths`; nothing else is diffed
+async fn collect_diffs(
+    root: &Path,
+    path_str: Option<&str>,
+    paths: &[String],
+    staged: bool,
+    context_lines: u32,
+    max_bytes: Option<usize>,
+) -> Result<GitDiffResult> {
+    let single = match path_str {
+        Some(path) => Some(file_diff(root, path, staged, context_lines, max_bytes).await?),
+        None => None,
+    };
+    let files = join_all(
+        paths
+            .iter()
+            .map(|path| file_diff(root, path, staged, context_lines, max_bytes)),
+    )
+    .await
+    .into_iter()
+    .collect::<Result<Vec<_>>>()?;
+
+    let truncated = files.iter().any(|file| file.truncated)
+        || single.as_ref().is_some_and(|file| file.truncated);
+    Ok(GitDiffResult {
+        diff: single.map(|file| file.diff).unwrap_or_default(),
+        truncated,
+        files,
+    })
+}
+
+async fn file_diff(
+    root: &Path,
+    path: &str,
+    staged: bool,
+    context_lines: u32,
+    max_bytes: Option<usize>,
+) -> Result<GitFileDiff> {
+    let _permit = GIT_WORK.acquire().await.ok();
+    let mut diff = diff_text(root, path, staged, context_lines).await?;
+    let truncated = max_bytes.is_some_and(|max_bytes| truncate_diff(&mut diff, max_bytes));
+    Ok(GitFileDiff {
+        path: path.to_string(),
+        diff,
+        truncated,
+    })
+}
+
+/// Cut `diff` to at most `max_bytes`, ending on a full line. True when anything was cut.
+fn truncate_diff(diff: &mut String, max_bytes: usize) -> bool {
+    if diff.len() <= max_bytes {
+        return false;
+    }
+    let mut cut = max_bytes;
+    while !diff.is_char_boundary(cut) {
+        cut -= 1;
+    }
+    let cut = diff[..cut].rfind('\n').unwrap_or(cut);
+    diff.truncate(cut);
+    true
+}
 
+/// Unified diff of `path_str` in `root`; untracked files diff against /dev/null
+pub(crate) async fn diff_text(
+    root: &Path,
+    path_str: &str,
+    staged: bool,
+    context_lines: u32,
+) -> Result<String> {
     let mut args = vec!["diff", "--no-color"];
-    let context = format!("-U{}", context_lines.unwrap_or(3));
+    let context = format!("-U{}", context_lines);
     args.push(&context);
 
-    if staged.unwrap_or(false) {
+    if staged {
         args.push("--cached");
     }
 
     args.push("--");
-    args.push(&path_str);
+    args.push(path_str);
 
-    let output = run_git(&args, &root).await.unwrap_or_default();
+    let output = run_git(&args, root).await.unwrap_or_default();
 
-    if output.trim().is_empty() && !staged.unwrap_or(false) {
+    if output.trim().is_empty() && !staged {
         // Try --no-index for untracked files
         // git diff --no-index -- /dev/null path
-        let full_path = root.join(&path_str);
-        if full_path.exists() {
+        let full_path = root.join(path_str);
+        if full_path.is_file() {
             let args_no_index = vec![
                 "diff",
                 "--no-color",
@@ -649,26 +948,26 @@ pub async fn get_git_diff(
                 "--no-index",
                 "--",
                 "/dev/null",
-                &path_str,
+                path_str,
             ];
-            return run_git_with_allowed_exit(&args_no_index, &root, &[1])
-                .await
-                .map_err(|e| e.to_string());
+            return run_git_with_allowed_exit(&args_no_index, root, &[1]).await;
         }
     }
 
     Ok(output)
 }
 
+/// `path_str` is relative to `repo_root` when one is given, otherwise to `directory`
 #[tauri::command]
 pub async fn get_git_file_diff(
     directory: String,
     path_str: String,
+    repo_root: Option<String>,
     state: State<'_, DesktopRuntime>,
 ) -> Result<(String, String), String> {
     use tokio::fs;
 
-    let root = validate_git_path(&directory, state.settings())
+    let root = scoped_git_root(&directory, repo_root, state.settings())
         .await
         .map_err(|e| e.to_string())?;
 
@@ -731,6 +1030,92 @@ pub async fn revert_git_file(
     Ok(())
 }
 
+/// Find the repository owning `path` (absolute or relative to `directory`),
+/// searching no higher than the workspace root
+#[tauri::command]
+pub async fn discover_git_repository(
+    directory: String,
+    path: String,
+    state: State<'_, DesktopRuntime>,
+) -> Result<Option<GitRepositoryInfo>, String> {
+    let workspace = validate_git_path(&directory, state.settings())
+        .await
+        .map_err(|e| e.to_string())?;
+    discover_in_workspace(&workspace, &path).await
+}
+
+async fn discover_in_workspace(
+    workspace: &Path,
+    path: &str,
+) -> Result<Option<GitRepositoryInfo>, String> {
+    let target = workspace.join(path.trim());
+    let escapes = target
+        .components()
+        .any(|component| matches!(component, std::path::Component::ParentDir));
+    if escapes || !target.starts_with(workspace) {
+        return Err("Path must be inside the workspace".to_string());
+    }
+
+    let primary = primary_repository_root(workspace).await;
+    let Some(root) = discover_repository_root(workspace, &target).await else {
+        return Ok(None);
+    };
+    Ok(Some(describe_repository(&root, primary.as_deref()).await))
+}
+
+/// Repositories nested one level below the workspace or its common vendor directories
+#[tauri::command]
+pub async fn list_nested_repositories(
+    directory: String,
+    state: State<'_, DesktopRuntime>,
+) -> Result<Vec<GitRepositoryInfo>, String> {
+    let workspace = validate_git_path(&directory, state.settings())
+        .await
+        .map_err(|e| e.to_string())?;
+    Ok(nested_repositories(&workspace).await)
+}
+
+async fn nested_repositories(workspace: &Path) -> Vec<GitRepositoryInfo> {
+    let primary = primary_repository_root(workspace).await;
+
+    let mut scan_dirs = vec![workspace.to_path_buf()];
+    scan_dirs.extend(
+        NESTED_REPOSITORY_CONTAINERS
+            .iter()
+            .map(|name| workspace.join(name))
+            .filter(|dir| dir.is_dir()),
+    );
+
+    let mut seen = HashSet::new();
+    let mut repositories = Vec::new();
+    for dir in scan_dirs {
+        let Ok(mut entries) = fs::read_dir(&dir).await else {
+            continue;
+        };
+        while let Ok(Some(entry)) = entries.next_entry().await {
+            let name = entry.file_name();
+            let name = name.to_string_lossy();
+            if name.starts_with('.') || name == "node_modules" {
+                continue;
+            }
+            let candidate = entry.path();
+            if !candidate.is_dir() || !has_git_marker(&candidate) {
+                continue;
+            }
+            let Ok(root) = fs::canonicalize(&candidate).await else {
+                continue;
+            };
+            if primary.as_deref() == Some(root.as_path()) || !seen.insert(root.clone()) {
+                continue;
+            }
+            repositories.push(describe_repository(&root, primary.as_deref()).await);
+        }
+    }
+
+    repositories.sort_by(|a, b| a.root.cmp(&b.root));
+    repositories
+}
+
 #[tauri::command]
 pub async fn is_linked_worktree(
     directory: String,
@@ -1019,6 +1404,11 @@ pub async fn ensure_openchamber_ignored(
     let root = validate_git_path(&directory, state.settings())
         .await
         .map_err(|e| e.to_string())?;
+    add_openchamber_exclude(&root).await
+}
+
+/// Add /.openchamber/ to the repository's local exclude file
+pub(crate) async fn add_openchamber_exclude(root: &Path) -> Result<(), String> {
     let exclude_path = root.join(".git/info/exclude");
 
     if let Some(parent) = exclude_path.parent() {
@@ -1595,17 +1985,30 @@ pub async fn generate_commit_message(
     let _root = validate_git_path(&directory, state.settings())
         .await
         .map_err(|e| e.to_string())?;
+    state
+        .model_capabilities()
+        .require_provider("commit message generation")
+        .await?;
 
     // 1. Collect diffs
     let mut diff_summaries = String::new();
     for file in files {
-        if let Ok(diff) =
-            get_git_diff(directory.clone(), file.clone(), None, None, state.clone()).await
+        if let Ok(result) = get_git_diff(
+            directory.clone(),
+            Some(file.clone()),
+            None,
+            None,
+            None,
+            Some(4000),
+            state.clone(),
+        )
+        .await
         {
-            let trimmed = if diff.len() > 4000 {
-                format!("{}\n...", &diff[..4000])
+            // get_git_diff cuts at a line boundary, never inside a character
+            let trimmed = if result.truncated {
+                format!("{}\n...", result.diff)
             } else {
-                diff
+                result.diff
             };
             diff_summaries.push_str(&format!("FILE: {}\n{}\n\n", file, trimmed));
         }
@@ -1671,3 +2074,443 @@ Diff summary:
 
     Ok(CommitMessageResponse { message })
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A workspace repository on `main` with nested repositories on their own
+    /// branches, under the system temp dir and removed on drop
+    struct Fixture {
+        root: PathBuf,
+    }
+
+    impl Fixture {
+        fn new() -> Self {
+            let fixture = Self::single();
+            fixture.repository("docs", "docs-branch");
+            fixture.repository("vendor/lib", "nested");
+            fixture.write("src/main.rs", "fn main() {}");
+            fixture
+        }
+
+        /// Just the workspace repository on `main`
+        fn single() -> Self {
+            let root =
+                std::env::temp_dir().join(format!("openchamber-git-{}", uuid::Uuid::new_v4()));
+            std::fs::create_dir_all(&root).unwrap();
+            let root = std::fs::canonicalize(root).unwrap();
+            let fixture = Self { root };
+            fixture.repository("", "main");
+            fixture
+        }
+
+        fn path(&self, relative: &str) -> PathBuf {
+            self.root.join(relative)
+        }
+
+        fn git(&self, args: &[&str]) {
+            let status = std::process::Command::new("git")
+                .args(["-c", "user.name=Test", "-c", "user.email=test@example.com"])
+                .args(args)
+                .current_dir(&self.root)
+                .output()
+                .unwrap()
+                .status;
+            assert!(status.success(), "git {:?} failed", args);
+        }
+
+        fn write(&self, relative: &str, content: &str) {
+            let path = self.path(relative);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, content).unwrap();
+        }
+
+        fn repository(&self, relative: &str, branch: &str) {
+            let dir = self.path(relative);
+            std::fs::create_dir_all(&dir).unwrap();
+            let git = |args: &[&str]| {
+                let status = std::process::Command::new("git")
+                    .args(["-c", "user.name=Test", "-c", "user.email=test@example.com"])
+                    .args(args)
+                    .current_dir(&dir)
+                    .output()
+                    .unwrap()
+                    .status;
+                assert!(status.success(), "git {:?} failed", args);
+            };
+            git(&["init", "-q"]);
+            git(&["checkout", "-q", "-b", branch]);
+            std::fs::write(dir.join("README.md"), branch).unwrap();
+            git(&["add", "README.md"]);
+            git(&["commit", "-q", "-m", "init"]);
+        }
+
+        /// Path as the commands report it, without a trailing separator
+        fn root_of(&self, relative: &str) -> String {
+            let path: PathBuf = self.path(relative).components().collect();
+            path.to_string_lossy().to_string()
+        }
+    }
+
+    impl Drop for Fixture {
+        fn drop(&mut self) {
+            std::fs::remove_dir_all(&self.root).ok();
+        }
+    }
+
+    fn settings(fixture: &Fixture) -> SettingsStore {
+        SettingsStore::at(fixture.path("settings.json"))
+    }
+
+    #[tokio::test]
+    async fn paths_belong_to_the_nearest_repository() {
+        let fixture = Fixture::new();
+        fixture.write("vendor/lib/src/lib.rs", "");
+
+        let nested = discover_in_workspace(&fixture.root, "vendor/lib/src/lib.rs")
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(nested.root, fixture.root_of("vendor/lib"));
+        assert!(!nested.is_primary);
+        assert_eq!(nested.branch.as_deref(), Some("nested"));
+
+        let docs = discover_in_workspace(&fixture.root, "docs")
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(docs.root, fixture.root_of("docs"));
+        assert_eq!(docs.branch.as_deref(), Some("docs-branch"));
+
+        for path in ["src/main.rs", "vendor", "", "not/created/yet.rs"] {
+            let primary = discover_in_workspace(&fixture.root, path)
+                .await
+                .unwrap()
+                .unwrap();
+            assert_eq!(primary.root, fixture.root_of(""), "{path}");
+            assert!(primary.is_primary);
+            assert_eq!(primary.branch.as_deref(), Some("main"));
+        }
+    }
+
+    #[tokio::test]
+    async fn a_workspace_inside_a_repository_falls_back_to_it() {
+        let fixture = Fixture::new();
+        let workspace = fixture.path("src");
+
+        let info = discover_in_workspace(&workspace, "main.rs")
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(info.root, fixture.root_of(""));
+        assert!(info.is_primary);
+    }
+
+    #[tokio::test]
+    async fn discovery_stays_inside_the_workspace() {
+        let fixture = Fixture::new();
+        let workspace = fixture.path("vendor");
+
+        for path in ["../docs", "lib/../../docs", "/etc"] {
+            assert_eq!(
+                discover_in_workspace(&workspace, path).await.unwrap_err(),
+                "Path must be inside the workspace",
+                "{path}"
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn plain_directories_have_no_repository() {
+        let plain =
+            std::env::temp_dir().join(format!("openchamber-plain-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(plain.join("src")).unwrap();
+
+        let info = discover_in_workspace(&plain, "src").await;
+        std::fs::remove_dir_all(&plain).ok();
+        assert!(info.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn nested_repositories_are_listed_without_the_primary_one() {
+        let fixture = Fixture::new();
+        fixture.repository("node_modules/dep", "main");
+        fixture.repository(".cache/tool", "main");
+        fixture.repository("src/deep/repo", "main");
+
+        let repositories = nested_repositories(&fixture.root).await;
+        let found: Vec<_> = repositories
+            .iter()
+            .map(|repo| (repo.root.clone(), repo.is_primary, repo.branch.clone()))
+            .collect();
+        assert_eq!(
+            found,
+            [
+                (
+                    fixture.root_of("docs"),
+                    false,
+                    Some("docs-branch".to_string())
+                ),
+                (
+                    fixture.root_of("vendor/lib"),
+                    false,
+                    Some("nested".to_string())
+                ),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn operations_are_scoped_to_a_nested_repository() {
+        let fixture = Fixture::new();
+        let settings = settings(&fixture);
+        let workspace = fixture.root_of("");
+        fixture.write("vendor/lib/README.md", "changed");
+
+        let root = scoped_git_root(&workspace, None, &settings).await.unwrap();
+        assert_eq!(root, fixture.root);
+        let root = scoped_git_root(&workspace, Some("  ".to_string()), &settings)
+            .await
+            .unwrap();
+        assert_eq!(root, fixture.root);
+
+        let nested = scoped_git_root(&workspace, Some(fixture.root_of("vendor/lib")), &settings)
+            .await
+            .unwrap();
+        assert_eq!(nested, fixture.path("vendor/lib"));
+
+        let status = collect_status(&nested, None).await.unwrap();
+        let files: Vec<_> = status.files.iter().map(|file| file.path.as_str()).collect();
+        assert_eq!(files, ["README.md"]);
+        assert_eq!(status.current, "nested");
+
+        // From the workspace repository the nested checkout is just untracked content
+        let status = collect_status(&fixture.root, None).await.unwrap();
+        assert_eq!(status.current, "main");
+        assert!(status.files.iter().all(|file| file.path != "README.md"));
+    }
+
+    #[tokio::test]
+    async fn scoped_roots_must_be_repositories_inside_the_workspace() {
+        let fixture = Fixture::new();
+        let settings = settings(&fixture);
+        let workspace = fixture.root_of("vendor");
+
+        let outside = scoped_git_root(&workspace, Some(fixture.root_of("docs")), &settings)
+            .await
+            .unwrap_err();
+        assert_eq!(
+            outside.to_string(),
+            "Repository must be inside the workspace"
+        );
+
+        let plain = scoped_git_root(
+            &fixture.root_of(""),
+            Some(fixture.root_of("src")),
+            &settings,
+        )
+        .await
+        .unwrap_err();
+        assert!(plain.to_string().starts_with("Not a git repository"));
+
+        let missing =
+            scoped_git_root(&workspace, Some(fixture.root_of("vendor/gone")), &settings).await;
+        assert!(missing.is_err());
+    }
+
+    /// `count` committed files under `src/`, all modified since, plus a few
+    /// untracked ones next to them
+    fn large_change_set(count: usize) -> Fixture {
+        let fixture = Fixture::single();
+        for index in 0..count {
+            fixture.write(&format!("src/{index:05}.txt"), "one\ntwo\nthree\n");
+        }
+        fixture.git(&["add", "-A"]);
+        fixture.git(&["commit", "-q", "-m", "generated"]);
+        for index in 0..count {
+            fixture.write(&format!("src/{index:05}.txt"), "one\n2\nthree\nfour\n");
+        }
+        for index in 0..5 {
+            fixture.write(&format!("new-{index}.txt"), "a\nb\n");
+        }
+        fixture
+    }
+
+    #[test]
+    fn porcelain_status_is_parsed() {
+        let status = parse_porcelain_status(
+            "## main...origin/main [ahead 2, behind 1]\0M  staged.rs\0 M src/edited file.rs\0?? new.txt\0A  added.rs\0",
+        );
+        assert_eq!(status.current, "main");
+        assert_eq!(status.tracking.as_deref(), Some("origin/main"));
+        assert_eq!((status.ahead, status.behind), (2, 1));
+        assert!(!status.is_clean);
+        let files: Vec<(&str, &str, &str)> = status
+            .files
+            .iter()
+            .map(|file| {
+                (
+                    file.path.as_str(),
+                    file.index.as_str(),
+                    file.working_dir.as_str(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            files,
+            vec![
+                ("staged.rs", "M", ""),
+                ("src/edited file.rs", "", "M"),
+                ("new.txt", "?", "?"),
+                ("added.rs", "A", ""),
+            ]
+        );
+        assert!(status.diff_stats.is_none());
+
+        let fresh = parse_porcelain_status("## No commits yet on main\0");
+        assert_eq!(fresh.current, "No commits yet on main");
+        assert_eq!(fresh.tracking, None);
+        assert!(fresh.is_clean);
+
+        let untracked = parse_porcelain_status("## feature...origin/feature\0");
+        assert_eq!(untracked.tracking.as_deref(), Some("origin/feature"));
+        assert_eq!((untracked.ahead, untracked.behind), (0, 0));
+    }
+
+    #[test]
+    fn numstat_counts_binary_files_as_zero() {
+        let stats = parse_numstat("3\t1\tsrc/a.rs\n-\t-\tlogo.png\nnot a stat line\n");
+        assert_eq!(stats.len(), 2);
+        assert_eq!(
+            (stats["src/a.rs"].insertions, stats["src/a.rs"].deletions),
+            (3, 1)
+        );
+        assert_eq!(
+            (stats["logo.png"].insertions, stats["logo.png"].deletions),
+            (0, 0)
+        );
+    }
+
+    #[test]
+    fn diffs_are_cut_on_line_boundaries() {
+        let mut diff = "line one\nline two\nline three\n".to_string();
+        assert!(!truncate_diff(&mut diff, 100));
+        assert_eq!(diff, "line one\nline two\nline three\n");
+
+        assert!(truncate_diff(&mut diff, 22));
+        assert_eq!(diff, "line one\nline two");
+
+        // Never splits a character, even when no line fits
+        let mut wide = "ééééé".to_string();
+        assert!(truncate_diff(&mut wide, 5));
+        assert_eq!(wide, "éé");
+    }
+
+    #[tokio::test]
+    async fn status_of_a_large_change_set_is_fast_and_leaves_stats_lazy() {
+        let count = LAZY_STATS_THRESHOLD + 500;
+        let fixture = large_change_set(count);
+
+        let started = Instant::now();
+        let status = collect_status(&fixture.root, None).await.unwrap();
+        let elapsed = started.elapsed();
+        assert_eq!(status.current, "main");
+        assert_eq!(status.files.len(), count + 5);
+        assert!(status.diff_stats.is_none());
+        assert!(
+            elapsed < std::time::Duration::from_secs(5),
+            "status of {} files took {:?}",
+            count,
+            elapsed
+        );
+
+        let status = collect_status(&fixture.root, Some(true)).await.unwrap();
+        let stats = status.diff_stats.unwrap();
+        assert_eq!(stats.len(), count + 5);
+        let edited = &stats["src/00042.txt"];
+        assert_eq!((edited.insertions, edited.deletions), (2, 1));
+        let untracked = &stats["new-3.txt"];
+        assert_eq!((untracked.insertions, untracked.deletions), (2, 0));
+    }
+
+    #[tokio::test]
+    async fn small_change_sets_include_stats_unless_declined() {
+        let fixture = Fixture::single();
+        fixture.write("README.md", "changed");
+        let status = collect_status(&fixture.root, None).await.unwrap();
+        assert!(status.diff_stats.unwrap().contains_key("README.md"));
+        let status = collect_status(&fixture.root, Some(false)).await.unwrap();
+        assert!(status.diff_stats.is_none());
+    }
+
+    #[tokio::test]
+    async fn concurrent_status_requests_share_a_run() {
+        let fixture = large_change_set(50);
+        let root = fixture.root.clone();
+        let results = join_all((0..6).map(|_| shared_status(&root, None))).await;
+
+        for result in &results {
+            let status = result.as_ref().unwrap();
+            assert_eq!(status.files.len(), 55);
+        }
+        assert!(!STATUS_IN_FLIGHT.lock().contains_key(&(root, None)));
+    }
+
+    #[tokio::test]
+    async fn lazy_diffs_only_cover_the_requested_paths() {
+        let fixture = large_change_set(LAZY_STATS_THRESHOLD + 500);
+        let requested = vec!["src/00007.txt".to_string(), "new-3.txt".to_string()];
+
+        let started = Instant::now();
+        let result = collect_diffs(&fixture.root, None, &requested, false, 3, None)
+            .await
+            .unwrap();
+        assert!(
+            started.elapsed() < std::time::Duration::from_secs(5),
+            "diffing {} paths took {:?}",
+            requested.len(),
+            started.elapsed()
+        );
+
+        assert!(result.diff.is_empty());
+        assert!(!result.truncated);
+        let paths: Vec<&str> = result.files.iter().map(|file| file.path.as_str()).collect();
+        assert_eq!(paths, ["src/00007.txt", "new-3.txt"]);
+        for file in &result.files {
+            assert_eq!(file.diff.matches("diff --git").count(), 1, "{}", file.diff);
+            assert!(file.diff.contains(&format!("b/{}", file.path)));
+        }
+        assert!(result.files[0].diff.contains("-two\n+2\n"));
+        assert!(result.files[1].diff.ends_with("+a\n+b"));
+
+        let single = collect_diffs(&fixture.root, Some("src/00001.txt"), &[], false, 3, None)
+            .await
+            .unwrap();
+        assert!(single.files.is_empty());
+        assert!(single.diff.contains("b/src/00001.txt"));
+    }
+
+    #[tokio::test]
+    async fn oversized_file_diffs_are_truncated_and_flagged() {
+        let fixture = Fixture::single();
+        let long: String = (0..500).map(|line| format!("line {line}\n")).collect();
+        fixture.write("long.txt", &long);
+        fixture.write("short.txt", "short\n");
+        let paths = vec!["long.txt".to_string(), "short.txt".to_string()];
+
+        let full = collect_diffs(&fixture.root, None, &paths, false, 3, None)
+            .await
+            .unwrap();
+        let result = collect_diffs(&fixture.root, None, &paths, false, 3, Some(400))
+            .await
+            .unwrap();
+        assert!(result.truncated);
+        let (long, short) = (&result.files[0], &result.files[1]);
+        assert!(long.truncated);
+        assert!(long.diff.len() <= 400);
+        assert!(full.files[0].diff.starts_with(&format!("{}\n", long.diff)));
+        assert!(!short.truncated);
+        assert_eq!(short.diff, full.files[1].diff);
+    }
+}
@@ -1,5 +1,10 @@
-use crate::logging::log_file_path;
+use crate::{
+    heartbeat::{self, CrashReport, UncleanShutdown},
+    logging::log_file_path,
+    DesktopRuntime,
+};
 use serde::Serialize;
+use tauri::State;
 use tokio::fs;
 
 #[derive(Serialize)]
@@ -23,3 +28,52 @@ pub async fn fetch_desktop_logs() -> Result<DesktopLogFile, String> {
 
     Ok(DesktopLogFile { file_name, content })
 }
+
+/// How the previous run ended, for UIs that missed the startup event
+#[tauri::command]
+pub fn get_unclean_shutdown() -> Option<UncleanShutdown> {
+    heartbeat::unclean_shutdown()
+}
+
+/// Log snapshots taken after unclean shutdowns, newest first
+#[tauri::command]
+pub fn list_crash_reports() -> Vec<CrashReport> {
+    heartbeat::list_crash_reports()
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DesktopDownload {
+    pub url: String,
+    pub file_name: String,
+}
+
+/// Offer the log file over HTTP so large logs don't travel through IPC
+#[tauri::command]
+pub async fn create_desktop_log_download(
+    state: State<'_, DesktopRuntime>,
+) -> Result<DesktopDownload, String> {
+    log_download(&state).await
+}
+
+pub(crate) async fn log_download(runtime: &DesktopRuntime) -> Result<DesktopDownload, String> {
+    let path = log_file_path().ok_or_else(|| "Log location unavailable".to_string())?;
+    if !fs::try_exists(&path).await.unwrap_or(false) {
+        return Err("Log file not found".to_string());
+    }
+    let file_name = path
+        .file_name()
+        .and_then(|value| value.to_str())
+        .unwrap_or("desktop.log")
+        .to_string();
+
+    let token = runtime.downloads().register(path, file_name.clone());
+    Ok(DesktopDownload {
+        url: format!(
+            "http://127.0.0.1:{}/api/openchamber/download/{}",
+            runtime.server_port(),
+            token
+        ),
+        file_name,
+    })
+}
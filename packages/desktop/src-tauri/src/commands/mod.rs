@@ -1,7 +1,17 @@
+pub mod actions;
+pub mod artifacts;
+pub mod auth;
+pub mod config_recovery;
+pub mod exports;
 pub mod files;
 pub mod git;
 pub mod logs;
 pub mod permissions;
+pub mod project_state;
+pub mod projects;
+pub mod sessions;
 pub mod settings;
+pub mod snapshots;
 pub mod terminal;
 pub mod notifications;
+pub mod usage;
@@ -1,7 +1,19 @@
+use std::time::Instant;
+
+use log::warn;
 use serde::Deserialize;
-use tauri::{AppHandle, Runtime};
+use tauri::{AppHandle, Runtime, State};
 use tauri_plugin_notification::NotificationExt;
 
+use crate::{
+    notification_history::{self, NotificationHistorySnapshot},
+    notification_limiter::{self, Admission, NotifyOutcome},
+    platform::{self, PlatformCapabilities},
+    system_appearance::{self, SystemAppearance},
+    system_dnd::{self, DndState},
+    DesktopRuntime,
+};
+
 #[derive(Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct NotificationPayload {
@@ -9,11 +21,17 @@ pub struct NotificationPayload {
     pub body: Option<String>,
 }
 
+/// Show a notification for the webview. Requests are deduplicated and rate limited
+/// so a frontend loop can't flood the OS notification center; the outcome tells
+/// the caller whether it was shown, deduped, or suppressed. With
+/// `notifications.respectSystemDnd` set, system DND suppresses it too; it is still
+/// recorded in the notification history and badge.
 #[tauri::command]
 pub async fn desktop_notify<R: Runtime>(
     app: AppHandle<R>,
+    state: State<'_, DesktopRuntime>,
     payload: Option<NotificationPayload>,
-) -> Result<bool, String> {
+) -> Result<NotifyOutcome, String> {
     let title = payload
         .as_ref()
         .and_then(|p| p.title.as_deref())
@@ -23,15 +41,100 @@ pub async fn desktop_notify<R: Runtime>(
         .and_then(|p| p.body.as_deref())
         .unwrap_or("Task completed");
 
-    match app
-        .notification()
-        .builder()
-        .title(title)
-        .body(body)
-        .sound("Glass")
-        .show()
-    {
-        Ok(_) => Ok(true),
+    let settings = state.settings().load().await.unwrap_or_default();
+
+    let limiter = state.notification_limiter();
+    let admission = {
+        let mut limiter = limiter.lock();
+        limiter.set_rate(notification_limiter::rate_limit(&settings));
+        limiter.check(title, body, Instant::now())
+    };
+    match admission {
+        Admission::Show => {}
+        Admission::Deduped => return Ok(NotifyOutcome::Deduped),
+        Admission::Suppressed(flush_at) => {
+            if let Some(deadline) = flush_at {
+                warn!("[desktop:notify] Notification rate limit hit; suppressing until the window closes");
+                let app = app.clone();
+                tauri::async_runtime::spawn(async move {
+                    tokio::time::sleep_until(deadline.into()).await;
+                    let suppressed = limiter.lock().flush(Instant::now());
+                    if let Some(count) = suppressed {
+                        let _ = app
+                            .notification()
+                            .builder()
+                            .title("OpenChamber")
+                            .body(notification_limiter::summary_body(count))
+                            .show();
+                    }
+                });
+            }
+            return Ok(admission.outcome());
+        }
+    }
+
+    let dnd = tokio::task::spawn_blocking(system_dnd::get_system_dnd_state)
+        .await
+        .unwrap_or(DndState::Unknown);
+
+    let decision = system_dnd::decide(dnd, system_dnd::respect_system_dnd(&settings));
+    notification_history::record(
+        &app,
+        state.notification_history(),
+        title,
+        body,
+        !decision.show,
+    );
+    if !decision.show {
+        return Ok(NotifyOutcome::Suppressed);
+    }
+
+    let mut builder = app.notification().builder().title(title).body(body);
+    if let Some(sound) = platform::notification_sound(&settings).filter(|_| decision.sound) {
+        builder = builder.sound(sound);
+    }
+
+    match builder.show() {
+        Ok(_) => Ok(NotifyOutcome::Shown),
         Err(e) => Err(e.to_string()),
     }
 }
+
+/// Recent notifications, including ones system DND kept off screen, and the
+/// unseen count shown on the dock badge
+#[tauri::command]
+pub fn desktop_get_notification_history(
+    state: State<'_, DesktopRuntime>,
+) -> NotificationHistorySnapshot {
+    state.notification_history().snapshot()
+}
+
+#[tauri::command]
+pub async fn desktop_get_system_dnd_state() -> Result<DndState, String> {
+    tokio::task::spawn_blocking(system_dnd::get_system_dnd_state)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// Dark/light mode, accent color and accessibility flags of the OS; changes
+/// arrive as `openchamber:appearance-changed`
+#[tauri::command]
+pub async fn desktop_get_system_appearance() -> Result<SystemAppearance, String> {
+    tokio::task::spawn_blocking(system_appearance::read_system_appearance)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// Frontend reports the focused session so its completions skip digest batching
+#[tauri::command]
+pub fn set_active_session(state: State<'_, DesktopRuntime>, session_id: Option<String>) {
+    let session_id = session_id
+        .map(|id| id.trim().to_string())
+        .filter(|id| !id.is_empty());
+    state.set_active_session(session_id);
+}
+
+#[tauri::command]
+pub fn get_platform_capabilities() -> PlatformCapabilities {
+    PlatformCapabilities::current()
+}
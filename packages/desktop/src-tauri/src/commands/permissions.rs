@@ -1,9 +1,18 @@
+use std::path::{Path, PathBuf};
+
 use log::{info, warn};
 use serde::{Deserialize, Serialize};
 use tauri::AppHandle;
 use tauri::State;
+use tauri_plugin_dialog::DialogExt;
 
-use crate::DesktopRuntime;
+use crate::{
+    project_state::ProjectStateStore,
+    workspace_fs::{self, WorkspaceFsInfo},
+    workspace_relink,
+    workspace_trust::{self, WorkspaceTrust},
+    DesktopRuntime, SettingsStore,
+};
 
 #[derive(Debug, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
@@ -17,6 +26,12 @@ pub struct DirectoryPermissionResult {
     success: bool,
     path: Option<String>,
     error: Option<String>,
+    /// Filesystem classification of the activated directory
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    fs_info: Option<WorkspaceFsInfo>,
+    /// Trust of the activated directory; terminals and quick runs need it trusted
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    trust: Option<WorkspaceTrust>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -26,37 +41,67 @@ pub struct StartAccessingResult {
     error: Option<String>,
 }
 
-/// Process directory selection from frontend
-/// Updates settings with lastDirectory
-/// OpenCode restart is triggered separately via /api/opencode/directory endpoint
-#[tauri::command]
-pub async fn process_directory_selection(
-    path: String,
-    state: State<'_, DesktopRuntime>,
-) -> Result<DirectoryPermissionResult, String> {
-    use std::path::PathBuf;
+/// Options for the native project folder picker
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PickDirectoryOptions {
+    /// Folder the dialog opens in; defaults to the current workspace, then home
+    default_path: Option<String>,
+    title: Option<String>,
+    /// Offer a "create folder" button where the platform dialog supports it
+    #[serde(default)]
+    allow_create: bool,
+}
 
-    // Validate directory exists
-    let path_buf = PathBuf::from(&path);
-    if !path_buf.exists() {
-        return Ok(DirectoryPermissionResult {
+impl DirectoryPermissionResult {
+    fn failed(error: impl Into<String>) -> Self {
+        Self {
             success: false,
             path: None,
-            error: Some("Directory does not exist".to_string()),
-        });
+            error: Some(error.into()),
+            fs_info: None,
+            trust: None,
+        }
     }
 
-    if !path_buf.is_dir() {
-        return Ok(DirectoryPermissionResult {
+    /// The user dismissed the picker; not an error
+    fn cancelled() -> Self {
+        Self {
             success: false,
             path: None,
-            error: Some("Path is not a directory".to_string()),
-        });
+            error: None,
+            fs_info: None,
+            trust: None,
+        }
+    }
+}
+
+/// Check that `path` is an existing directory and return its canonical form
+fn validate_directory(path: &Path) -> Result<PathBuf, String> {
+    if !path.exists() {
+        return Err("Directory does not exist".to_string());
     }
+    if !path.is_dir() {
+        return Err("Path is not a directory".to_string());
+    }
+    std::fs::canonicalize(path).map_err(|e| format!("Cannot access directory: {}", e))
+}
+
+/// Validate a chosen directory, record it as lastDirectory and remember it for
+/// relinking. Everything activation does apart from the UI side effects.
+async fn record_selection(
+    path: &Path,
+    store: &SettingsStore,
+    project_state: &ProjectStateStore,
+) -> Result<DirectoryPermissionResult, String> {
+    let canonical = match validate_directory(path) {
+        Ok(canonical) => canonical,
+        Err(err) => return Ok(DirectoryPermissionResult::failed(err)),
+    };
+    let path = canonical.to_string_lossy().to_string();
 
     // Update settings with lastDirectory
-    let mut settings = state
-        .settings()
+    let mut settings = store
         .load()
         .await
         .map_err(|e| format!("Failed to load settings: {}", e))?;
@@ -67,9 +112,10 @@ pub async fn process_directory_selection(
             serde_json::Value::String(path.clone()),
         );
     }
+    workspace_trust::mark_untrusted_if_new(&mut settings, &canonical);
+    let trust = workspace_trust::trust_for(&settings, &canonical);
 
-    state
-        .settings()
+    store
         .save(settings)
         .await
         .map_err(|e| format!("Failed to save updated settings: {}", e))?;
@@ -79,26 +125,123 @@ pub async fn process_directory_selection(
         path
     );
 
+    workspace_relink::remember_workspace(store, project_state, &canonical).await;
+
     Ok(DirectoryPermissionResult {
         success: true,
         path: Some(path),
         error: None,
+        fs_info: None,
+        trust: Some(trust),
     })
 }
 
-/// Legacy directory picker command (frontend handles actual dialog)
+/// Validate a chosen directory and record it as lastDirectory
+async fn activate_directory(
+    path: &Path,
+    app_handle: &AppHandle,
+    state: &DesktopRuntime,
+) -> Result<DirectoryPermissionResult, String> {
+    let mut result = record_selection(path, state.settings(), state.project_state()).await?;
+    let Some(canonical) = result.path.clone().map(PathBuf::from) else {
+        if !path.exists() {
+            workspace_relink::suggest_relink(app_handle, state.settings(), path);
+        }
+        return Ok(result);
+    };
+
+    result.fs_info = workspace_fs::inspect(canonical).await;
+    if let Some(info) = &result.fs_info {
+        workspace_fs::warn_once(app_handle, info);
+    }
+    Ok(result)
+}
+
+/// Folder the picker opens in: the requested one if it exists, then the
+/// current workspace, then home
+fn start_directory(requested: Option<String>, workspace: PathBuf) -> Option<PathBuf> {
+    requested
+        .map(PathBuf::from)
+        .filter(|path| path.is_dir())
+        .or_else(|| Some(workspace).filter(|path| path.is_dir()))
+        .or_else(dirs::home_dir)
+}
+
+/// Process directory selection from frontend
+/// Updates settings with lastDirectory
+/// OpenCode restart is triggered separately via /api/opencode/directory endpoint
+#[tauri::command]
+pub async fn process_directory_selection(
+    path: String,
+    app_handle: AppHandle,
+    state: State<'_, DesktopRuntime>,
+) -> Result<DirectoryPermissionResult, String> {
+    activate_directory(Path::new(&path), &app_handle, &state).await
+}
+
+/// Show the native folder picker, then validate and activate the selection
+/// like `process_directory_selection`. Cancelling returns success=false with
+/// no error.
 #[tauri::command]
 pub async fn pick_directory(
-    _app_handle: AppHandle,
-    _state: State<'_, DesktopRuntime>,
+    options: Option<PickDirectoryOptions>,
+    app_handle: AppHandle,
+    state: State<'_, DesktopRuntime>,
 ) -> Result<DirectoryPermissionResult, String> {
-    Ok(DirectoryPermissionResult {
-        success: false,
-        path: None,
-        error: Some(
-            "Use requestDirectoryAccess instead - it handles native dialog properly".to_string(),
-        ),
-    })
+    let options = options.unwrap_or_default();
+    let start = start_directory(
+        options.default_path,
+        state.opencode_manager().get_working_directory(),
+    );
+
+    let mut dialog = app_handle
+        .dialog()
+        .file()
+        .set_title(
+            options
+                .title
+                .unwrap_or_else(|| "Select Working Directory".to_string()),
+        )
+        .set_can_create_directories(options.allow_create);
+    if let Some(start) = start {
+        dialog = dialog.set_directory(start);
+    }
+
+    let (tx, rx) = tokio::sync::oneshot::channel();
+    dialog.pick_folder(move |path| {
+        let _ = tx.send(path);
+    });
+    let selected = match rx.await.ok().flatten() {
+        Some(selected) => selected,
+        None => return Ok(DirectoryPermissionResult::cancelled()),
+    };
+    let path = match selected.into_path() {
+        Ok(path) => path,
+        Err(e) => {
+            warn!("[permissions] Picker returned an unusable path: {}", e);
+            return Ok(DirectoryPermissionResult::failed(
+                "Selected location is not a local directory",
+            ));
+        }
+    };
+
+    activate_directory(&path, &app_handle, &state).await
+}
+
+/// Filesystem classification of `path`, or of the current workspace
+#[tauri::command]
+pub async fn get_workspace_fs_info(
+    path: Option<String>,
+    state: State<'_, DesktopRuntime>,
+) -> Result<WorkspaceFsInfo, String> {
+    let path = match path.filter(|path| !path.trim().is_empty()) {
+        Some(path) => PathBuf::from(path.trim()),
+        None => state.opencode_manager().get_working_directory(),
+    };
+    let canonical = validate_directory(&path)?;
+    workspace_fs::inspect(canonical)
+        .await
+        .ok_or_else(|| "Failed to inspect directory".to_string())
 }
 
 /// Request directory access (desktop implementation)
@@ -116,6 +259,8 @@ pub async fn request_directory_access(
             success: false,
             path: None,
             error: Some("Directory does not exist".to_string()),
+            fs_info: None,
+            trust: None,
         });
     }
 
@@ -124,6 +269,8 @@ pub async fn request_directory_access(
             success: false,
             path: None,
             error: Some("Path is not a directory".to_string()),
+            fs_info: None,
+            trust: None,
         });
     }
 
@@ -133,11 +280,15 @@ pub async fn request_directory_access(
             success: true,
             path: Some(path),
             error: None,
+            fs_info: None,
+            trust: None,
         }),
         Err(e) => Ok(DirectoryPermissionResult {
             success: false,
             path: None,
             error: Some(format!("Cannot access directory: {}", e)),
+            fs_info: None,
+            trust: None,
         }),
     }
 }
@@ -207,3 +358,192 @@ pub async fn restore_bookmarks_on_startup(_state: State<'_, DesktopRuntime>) ->
     info!("[permissions] Bookmark restore not needed for unsandboxed app");
     Ok(())
 }
+
+/// Trust state of `path`, or of the current workspace
+#[tauri::command]
+pub async fn get_workspace_trust(
+    path: Option<String>,
+    state: State<'_, DesktopRuntime>,
+) -> Result<WorkspaceTrust, String> {
+    let path = match path.filter(|path| !path.trim().is_empty()) {
+        Some(path) => PathBuf::from(path.trim()),
+        None => state.opencode_manager().get_working_directory(),
+    };
+    let settings = state
+        .settings()
+        .load()
+        .await
+        .map_err(|e| format!("Failed to load settings: {}", e))?;
+    Ok(workspace_trust::trust_for(&settings, &path))
+}
+
+/// Trust or distrust `path`; with `parent` the decision applies to its parent
+/// folder and everything inside it
+#[tauri::command]
+pub async fn set_workspace_trust(
+    path: String,
+    trusted: bool,
+    parent: Option<bool>,
+    state: State<'_, DesktopRuntime>,
+) -> Result<WorkspaceTrust, String> {
+    let path = validate_directory(Path::new(path.trim()))?;
+    let target = if parent.unwrap_or(false) {
+        path.parent()
+            .map(Path::to_path_buf)
+            .ok_or_else(|| "Directory has no parent folder".to_string())?
+    } else {
+        path.clone()
+    };
+
+    let mut settings = state
+        .settings()
+        .load()
+        .await
+        .map_err(|e| format!("Failed to load settings: {}", e))?;
+    workspace_trust::set_trust(&mut settings, &target, trusted);
+    state
+        .settings()
+        .save(settings.clone())
+        .await
+        .map_err(|e| format!("Failed to save settings: {}", e))?;
+    info!(
+        "[permissions] Marked {} as {}",
+        target.display(),
+        if trusted { "trusted" } else { "untrusted" }
+    );
+
+    Ok(workspace_trust::trust_for(&settings, &path))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::{json, Value};
+
+    /// Workspace folders and a settings file under the system temp dir, removed on drop
+    struct Fixture {
+        root: PathBuf,
+        settings: SettingsStore,
+        project_state: ProjectStateStore,
+    }
+
+    impl Fixture {
+        fn new() -> Self {
+            let root = std::env::temp_dir()
+                .join(format!("openchamber-permissions-{}", uuid::Uuid::new_v4()));
+            std::fs::create_dir_all(root.join("project")).unwrap();
+            let root = std::fs::canonicalize(root).unwrap();
+            Self {
+                settings: SettingsStore::at(root.join("settings.json")),
+                project_state: ProjectStateStore::default(),
+                root,
+            }
+        }
+
+        async fn select(&self, path: &Path) -> DirectoryPermissionResult {
+            record_selection(path, &self.settings, &self.project_state)
+                .await
+                .unwrap()
+        }
+
+        async fn settings(&self) -> Value {
+            self.settings.load().await.unwrap()
+        }
+    }
+
+    impl Drop for Fixture {
+        fn drop(&mut self) {
+            std::fs::remove_dir_all(&self.root).ok();
+        }
+    }
+
+    #[test]
+    fn the_picker_starts_in_the_requested_folder_then_the_workspace_then_home() {
+        let fixture = Fixture::new();
+        let project = fixture.root.join("project");
+        let missing = fixture.root.join("missing");
+        let requested = |path: &Path| Some(path.to_string_lossy().to_string());
+
+        assert_eq!(
+            start_directory(requested(&fixture.root), project.clone()),
+            Some(fixture.root.clone())
+        );
+        assert_eq!(
+            start_directory(requested(&missing), project.clone()),
+            Some(project.clone())
+        );
+        assert_eq!(start_directory(None, project.clone()), Some(project));
+        assert_eq!(start_directory(None, missing), dirs::home_dir());
+    }
+
+    #[test]
+    fn cancelling_is_not_an_error() {
+        assert_eq!(
+            serde_json::to_value(DirectoryPermissionResult::cancelled()).unwrap(),
+            json!({"success": false, "path": null, "error": null})
+        );
+    }
+
+    #[tokio::test]
+    async fn invalid_selections_leave_settings_alone() {
+        let fixture = Fixture::new();
+        let file = fixture.root.join("notes.txt");
+        std::fs::write(&file, "notes").unwrap();
+
+        let missing = fixture.select(&fixture.root.join("missing")).await;
+        assert!(!missing.success);
+        assert_eq!(missing.error.as_deref(), Some("Directory does not exist"));
+
+        let not_a_directory = fixture.select(&file).await;
+        assert!(!not_a_directory.success);
+        assert_eq!(
+            not_a_directory.error.as_deref(),
+            Some("Path is not a directory")
+        );
+
+        assert_eq!(fixture.settings().await, json!({}));
+    }
+
+    #[tokio::test]
+    async fn selections_are_canonicalized_and_recorded() {
+        let fixture = Fixture::new();
+        let project = fixture.root.join("project");
+        let indirect = fixture.root.join("project").join("..").join("project");
+
+        let result = fixture.select(&indirect).await;
+        assert!(result.success);
+        assert_eq!(result.error, None);
+        let path = project.to_string_lossy().to_string();
+        assert_eq!(result.path.as_deref(), Some(path.as_str()));
+
+        // A first visit is recorded as an undecided, untrusted workspace
+        let trust = result.trust.unwrap();
+        assert!(!trust.trusted);
+        assert_eq!(trust.path, path);
+
+        let settings = fixture.settings().await;
+        assert_eq!(settings["lastDirectory"], json!(path));
+        let fingerprint = ProjectStateStore::read_fingerprint(&project).await.unwrap();
+        assert_eq!(settings["workspaceIds"][&path], json!(fingerprint));
+    }
+
+    #[tokio::test]
+    async fn earlier_trust_decisions_are_kept() {
+        let fixture = Fixture::new();
+        let project = fixture.root.join("project");
+        let mut settings = json!({"lastDirectory": "/elsewhere", "theme": "dark"});
+        workspace_trust::set_trust(&mut settings, &fixture.root, true);
+        fixture.settings.save(settings).await.unwrap();
+
+        let result = fixture.select(&project).await;
+        let trust = result.trust.unwrap();
+        assert!(trust.trusted);
+        let root = fixture.root.to_string_lossy().to_string();
+        assert_eq!(trust.decided_at.as_deref(), Some(root.as_str()));
+
+        let settings = fixture.settings().await;
+        assert_eq!(settings["theme"], json!("dark"));
+        // The inherited decision was not shadowed by an untrusted entry for the project
+        assert_eq!(workspace_trust::trust_for(&settings, &project), trust);
+    }
+}
@@ -0,0 +1,43 @@
+use serde_json::Value;
+use tauri::State;
+
+use crate::DesktopRuntime;
+
+#[tauri::command]
+pub async fn get_project_state(
+    key: String,
+    state: State<'_, DesktopRuntime>,
+) -> Result<Option<Value>, String> {
+    let workspace = state.opencode_manager().get_working_directory();
+    state
+        .project_state()
+        .get(Some(&workspace), &key)
+        .await
+        .map_err(|e| format!("Failed to read project state: {}", e))
+}
+
+#[tauri::command]
+pub async fn set_project_state(
+    key: String,
+    value: Value,
+    state: State<'_, DesktopRuntime>,
+) -> Result<(), String> {
+    let workspace = state.opencode_manager().get_working_directory();
+    state
+        .project_state()
+        .set(Some(&workspace), &key, &value)
+        .await
+        .map_err(|e| format!("Failed to save project state: {}", e))
+}
+
+#[tauri::command]
+pub async fn list_project_state_keys(
+    state: State<'_, DesktopRuntime>,
+) -> Result<Vec<String>, String> {
+    let workspace = state.opencode_manager().get_working_directory();
+    state
+        .project_state()
+        .keys(Some(&workspace))
+        .await
+        .map_err(|e| format!("Failed to list project state: {}", e))
+}
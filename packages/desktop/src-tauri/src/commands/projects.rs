@@ -0,0 +1,571 @@
+use std::path::{Path, PathBuf};
+
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use tauri::State;
+use tokio::fs;
+
+use crate::{
+    commands::{
+        files::resolve_new_project_root,
+        git::{add_openchamber_exclude, init_repository},
+        settings::remember_directory,
+    },
+    loopback_auth, paths,
+    workspace_relink::{self, RelinkResult},
+    DesktopRuntime,
+};
+
+const PROJECT_TEMPLATES_DIR: &str = "templates/project";
+const OPENCODE_DIR: &str = ".opencode";
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ProjectTemplate {
+    name: String,
+    path: String,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(rename_all = "camelCase", default)]
+pub struct CreateProjectOptions {
+    /// Template under templates/project/<name> copied into .opencode/
+    template: Option<String>,
+    /// Defaults to true
+    git_init: Option<bool>,
+    /// Switch OpenCode into the new project when done; defaults to true
+    switch_to: Option<bool>,
+    /// Switch even if sessions in the current directory are busy
+    force: bool,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CreateProjectResult {
+    path: String,
+    completed_steps: Vec<String>,
+    switched: bool,
+}
+
+fn templates_root() -> Option<PathBuf> {
+    paths::config_dir().map(|dir| dir.join(PROJECT_TEMPLATES_DIR))
+}
+
+#[tauri::command]
+pub async fn list_project_templates() -> Result<Vec<ProjectTemplate>, String> {
+    let Some(root) = templates_root() else {
+        return Ok(Vec::new());
+    };
+
+    let mut entries = match fs::read_dir(&root).await {
+        Ok(entries) => entries,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(err) => return Err(format!("Failed to read templates: {}", err)),
+    };
+
+    let mut templates = Vec::new();
+    while let Ok(Some(entry)) = entries.next_entry().await {
+        let is_dir = entry.file_type().await.map(|ft| ft.is_dir()).unwrap_or(false);
+        if !is_dir {
+            continue;
+        }
+        templates.push(ProjectTemplate {
+            name: entry.file_name().to_string_lossy().to_string(),
+            path: entry.path().to_string_lossy().to_string(),
+        });
+    }
+    templates.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(templates)
+}
+
+/// Tracks what a scaffold created so a failure can undo it
+struct Scaffold {
+    root: PathBuf,
+    created_root: bool,
+    created: Vec<PathBuf>,
+    completed_steps: Vec<String>,
+}
+
+impl Scaffold {
+    fn step(&mut self, step: &str) {
+        self.completed_steps.push(step.to_string());
+    }
+
+    async fn rollback(&self) -> Result<(), String> {
+        if self.created_root {
+            return fs::remove_dir_all(&self.root)
+                .await
+                .map_err(|e| e.to_string());
+        }
+        for path in self.created.iter().rev() {
+            let result = if path.is_dir() {
+                fs::remove_dir_all(path).await
+            } else {
+                fs::remove_file(path).await
+            };
+            if let Err(err) = result {
+                return Err(format!("{}: {}", path.display(), err));
+            }
+        }
+        Ok(())
+    }
+
+    fn failure(&self, step: &str, err: impl std::fmt::Display) -> String {
+        let completed = if self.completed_steps.is_empty() {
+            "nothing".to_string()
+        } else {
+            self.completed_steps.join(", ")
+        };
+        format!("Failed to {}: {} (completed: {})", step, err, completed)
+    }
+}
+
+#[tauri::command]
+pub async fn create_project_from_template(
+    path: String,
+    options: Option<CreateProjectOptions>,
+    state: State<'_, DesktopRuntime>,
+) -> Result<CreateProjectResult, String> {
+    let options = options.unwrap_or_default();
+    let root = resolve_new_project_root(&path, state.settings()).await?;
+
+    let template_dir = match options.template.as_deref().map(str::trim) {
+        Some(name) if !name.is_empty() => Some(resolve_template(name).await?),
+        _ => None,
+    };
+
+    let mut scaffold = match prepare_root(&root).await {
+        Ok(scaffold) => scaffold,
+        Err(err) => return Err(format!("Failed to create directory: {}", err)),
+    };
+
+    if let Err(message) = populate(&mut scaffold, template_dir.as_deref(), &options).await {
+        warn!("[desktop:projects] {}", message);
+        return match scaffold.rollback().await {
+            Ok(()) => Err(format!("{}; changes were rolled back", message)),
+            Err(err) => Err(format!("{}; rollback failed: {}", message, err)),
+        };
+    }
+
+    let root_str = root.to_string_lossy().to_string();
+    let switch_to = options.switch_to.unwrap_or(true);
+
+    // The project is usable from here on; later failures are reported, not rolled back
+    if let Err(err) = remember_directory(state.settings(), &root_str, switch_to).await {
+        return Err(scaffold.failure("record the directory", err));
+    }
+    scaffold.step("recorded directory");
+
+    let mut switched = false;
+    if switch_to {
+        switch_directory(
+            state.server_port(),
+            state.api_token(),
+            &root_str,
+            options.force,
+        )
+        .await
+        .map_err(|err| scaffold.failure("switch to the project", err))?;
+        scaffold.step("switched directory");
+        switched = true;
+    }
+
+    info!("[desktop:projects] Created project at {}", root.display());
+    Ok(CreateProjectResult {
+        path: root_str,
+        completed_steps: scaffold.completed_steps,
+        switched,
+    })
+}
+
+async fn resolve_template(name: &str) -> Result<PathBuf, String> {
+    if name.contains(['/', '\\']) || name == "." || name == ".." {
+        return Err(format!("Invalid template name: {}", name));
+    }
+    let dir = templates_root()
+        .ok_or_else(|| "No config directory".to_string())?
+        .join(name);
+    if !fs::metadata(&dir).await.map(|m| m.is_dir()).unwrap_or(false) {
+        return Err(format!("Template not found: {}", name));
+    }
+    Ok(dir)
+}
+
+/// Create the root, which must be new or an empty directory
+async fn prepare_root(root: &Path) -> std::io::Result<Scaffold> {
+    let created_root = match fs::metadata(root).await {
+        Ok(metadata) if metadata.is_dir() => {
+            let mut entries = fs::read_dir(root).await?;
+            if entries.next_entry().await?.is_some() {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::AlreadyExists,
+                    "directory is not empty",
+                ));
+            }
+            false
+        }
+        Ok(_) => {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::AlreadyExists,
+                "path exists and is not a directory",
+            ))
+        }
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
+            fs::create_dir_all(root).await?;
+            true
+        }
+        Err(err) => return Err(err),
+    };
+
+    let mut scaffold = Scaffold {
+        root: root.to_path_buf(),
+        created_root,
+        created: Vec::new(),
+        completed_steps: Vec::new(),
+    };
+    if created_root {
+        scaffold.step("created directory");
+    }
+    Ok(scaffold)
+}
+
+async fn populate(
+    scaffold: &mut Scaffold,
+    template_dir: Option<&Path>,
+    options: &CreateProjectOptions,
+) -> Result<(), String> {
+    let root = scaffold.root.clone();
+
+    if options.git_init.unwrap_or(true) {
+        scaffold.created.push(root.join(".git"));
+        init_repository(&root)
+            .await
+            .map_err(|err| scaffold.failure("initialise git", err))?;
+        scaffold.step("initialised git");
+
+        add_openchamber_exclude(&root)
+            .await
+            .map_err(|err| scaffold.failure("ignore .openchamber", err))?;
+        scaffold.step("ignored .openchamber");
+    }
+
+    if let Some(template_dir) = template_dir {
+        let target = root.join(OPENCODE_DIR);
+        scaffold.created.push(target.clone());
+        copy_dir_recursive(template_dir, &target)
+            .await
+            .map_err(|err| scaffold.failure("copy template", err))?;
+        scaffold.step("copied template");
+    }
+
+    Ok(())
+}
+
+async fn copy_dir_recursive(source: &Path, target: &Path) -> std::io::Result<()> {
+    let mut pending = vec![(source.to_path_buf(), target.to_path_buf())];
+    while let Some((from, to)) = pending.pop() {
+        fs::create_dir_all(&to).await?;
+        let mut entries = fs::read_dir(&from).await?;
+        while let Some(entry) = entries.next_entry().await? {
+            let file_type = entry.file_type().await?;
+            let dest = to.join(entry.file_name());
+            if file_type.is_dir() {
+                pending.push((entry.path(), dest));
+            } else if file_type.is_file() {
+                fs::copy(entry.path(), &dest).await?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Point everything that referenced `old_path` at `new_path` after the folder
+/// was moved or renamed outside the app: settings paths, per-project state
+/// and, when it was the active workspace, the running OpenCode directory.
+#[tauri::command]
+pub async fn relink_workspace(
+    old_path: String,
+    new_path: String,
+    state: State<'_, DesktopRuntime>,
+) -> Result<RelinkResult, String> {
+    let old = PathBuf::from(old_path.trim());
+    let new = PathBuf::from(new_path.trim());
+    if old.as_os_str().is_empty() {
+        return Err("Old path is required".to_string());
+    }
+    if !new.is_dir() {
+        return Err(format!("{} is not a directory", new.display()));
+    }
+    let new = fs::canonicalize(&new)
+        .await
+        .map_err(|e| format!("Cannot access directory: {}", e))?;
+    if new == old {
+        return Err("Old and new path are the same".to_string());
+    }
+
+    let mut settings = state
+        .settings()
+        .load()
+        .await
+        .map_err(|e| format!("Failed to load settings: {}", e))?;
+    let was_active = workspace_relink::is_last_directory(&settings, &old);
+    let changed_keys = workspace_relink::rewrite_settings(&mut settings, &old, &new);
+    if !changed_keys.is_empty() {
+        state
+            .settings()
+            .save(settings)
+            .await
+            .map_err(|e| format!("Failed to save settings: {}", e))?;
+    }
+
+    let state_copied = match state.project_state().copy_workspace_state(&old, &new).await {
+        Ok(copied) => copied,
+        Err(err) => {
+            warn!("[desktop:projects] Failed to copy workspace state: {}", err);
+            false
+        }
+    };
+
+    let new_str = new.to_string_lossy().to_string();
+    if was_active {
+        switch_directory(state.server_port(), state.api_token(), &new_str, false).await?;
+    }
+    workspace_relink::remember_workspace(state.settings(), state.project_state(), &new).await;
+
+    info!(
+        "[desktop:projects] Relinked {} to {} ({})",
+        old.display(),
+        new.display(),
+        changed_keys.join(", ")
+    );
+    Ok(RelinkResult {
+        old_path: old.to_string_lossy().to_string(),
+        new_path: new_str,
+        changed_keys,
+        state_copied,
+        switched: was_active,
+    })
+}
+
+/// Run the regular directory-change flow (busy-session guard, restart, events).
+/// The route is behind the loopback auth, so the request carries `api_token`.
+pub(crate) async fn switch_directory(
+    server_port: u16,
+    api_token: &str,
+    path: &str,
+    force: bool,
+) -> Result<(), String> {
+    let url = format!("http://127.0.0.1:{}/api/opencode/directory", server_port);
+    let response = reqwest::Client::new()
+        .post(&url)
+        .header(loopback_auth::TOKEN_HEADER, api_token)
+        .json(&json!({ "path": path, "force": force }))
+        .send()
+        .await
+        .map_err(|e| e.to_string())?;
+
+    if response.status().is_success() {
+        return Ok(());
+    }
+    let status = response.status();
+    let body = response.text().await.unwrap_or_default();
+    Err(format!("directory change returned {}: {}", status, body))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        loopback_auth::{require_api_auth, ApiAuth, LoopbackAuth},
+        web_ui::WebUiAccess,
+    };
+    use axum::{middleware, routing::post, Json, Router};
+    use serde_json::Value;
+    use std::sync::Arc;
+
+    /// The directory route behind the same auth layer as the desktop server,
+    /// recording the bodies that got through
+    async fn authenticated_server(auth: ApiAuth) -> (u16, Arc<parking_lot::Mutex<Vec<Value>>>) {
+        let received = Arc::new(parking_lot::Mutex::new(Vec::new()));
+        let recorder = received.clone();
+        let app = Router::new()
+            .route(
+                "/api/opencode/directory",
+                post(move |Json(body): Json<Value>| async move {
+                    recorder.lock().push(body);
+                    Json(json!({ "success": true }))
+                }),
+            )
+            .route_layer(middleware::from_fn_with_state(auth, require_api_auth));
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        tokio::spawn(async move {
+            axum::serve(listener, app).await.ok();
+        });
+        (port, received)
+    }
+
+    #[tokio::test]
+    async fn switch_directory_authenticates_with_the_api_token() {
+        let auth = ApiAuth {
+            loopback: LoopbackAuth::new(None),
+            web_ui: WebUiAccess::new(true),
+        };
+        let (port, received) = authenticated_server(auth.clone()).await;
+
+        switch_directory(port, auth.loopback.token(), "/work/next", true)
+            .await
+            .unwrap();
+        assert_eq!(
+            *received.lock(),
+            vec![json!({ "path": "/work/next", "force": true })]
+        );
+
+        let err = switch_directory(port, "stale-token", "/work/other", false)
+            .await
+            .unwrap_err();
+        assert!(err.contains("401"), "{err}");
+        assert_eq!(received.lock().len(), 1);
+    }
+
+    /// A scratch directory under the system temp dir, removed on drop
+    struct TempDir(PathBuf);
+
+    impl TempDir {
+        fn new() -> Self {
+            let dir =
+                std::env::temp_dir().join(format!("openchamber-projects-{}", uuid::Uuid::new_v4()));
+            std::fs::create_dir_all(&dir).unwrap();
+            Self(dir)
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            std::fs::remove_dir_all(&self.0).ok();
+        }
+    }
+
+    fn template(dir: &Path) -> PathBuf {
+        let template = dir.join("template");
+        std::fs::create_dir_all(template.join("agent")).unwrap();
+        std::fs::write(template.join("opencode.json"), "{}").unwrap();
+        std::fs::write(template.join("agent/review.md"), "Review carefully").unwrap();
+        template
+    }
+
+    fn without_git() -> CreateProjectOptions {
+        CreateProjectOptions {
+            git_init: Some(false),
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn templates_are_copied_into_the_opencode_dir() {
+        let dir = TempDir::new();
+        let template = template(&dir.0);
+        let root = dir.0.join("new-project");
+
+        let mut scaffold = prepare_root(&root).await.unwrap();
+        populate(&mut scaffold, Some(&template), &without_git())
+            .await
+            .unwrap();
+
+        let opencode = root.join(OPENCODE_DIR);
+        assert_eq!(
+            std::fs::read_to_string(opencode.join("opencode.json")).unwrap(),
+            "{}"
+        );
+        assert_eq!(
+            std::fs::read_to_string(opencode.join("agent/review.md")).unwrap(),
+            "Review carefully"
+        );
+        assert_eq!(
+            scaffold.completed_steps,
+            ["created directory", "copied template"]
+        );
+    }
+
+    #[tokio::test]
+    async fn only_new_or_empty_directories_become_projects() {
+        let dir = TempDir::new();
+        let empty = dir.0.join("empty");
+        std::fs::create_dir(&empty).unwrap();
+        let scaffold = prepare_root(&empty).await.unwrap();
+        assert!(!scaffold.created_root);
+        assert!(scaffold.completed_steps.is_empty());
+
+        std::fs::write(empty.join("notes.txt"), "").unwrap();
+        assert!(prepare_root(&empty).await.is_err());
+        assert!(prepare_root(&empty.join("notes.txt")).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn a_created_root_is_removed_on_rollback() {
+        let dir = TempDir::new();
+        let root = dir.0.join("new-project");
+
+        let mut scaffold = prepare_root(&root).await.unwrap();
+        let err = populate(&mut scaffold, Some(&dir.0.join("missing")), &without_git())
+            .await
+            .unwrap_err();
+        assert!(err.starts_with("Failed to copy template:"), "{err}");
+        assert!(err.ends_with("(completed: created directory)"), "{err}");
+
+        scaffold.rollback().await.unwrap();
+        assert!(!root.exists());
+    }
+
+    #[tokio::test]
+    async fn rollback_keeps_an_existing_root_and_removes_what_was_added() {
+        let dir = TempDir::new();
+        let template = template(&dir.0);
+        let root = dir.0.join("existing");
+        std::fs::create_dir(&root).unwrap();
+
+        let mut scaffold = prepare_root(&root).await.unwrap();
+        populate(&mut scaffold, Some(&template), &without_git())
+            .await
+            .unwrap();
+        assert_eq!(
+            scaffold.failure("record the directory", "disk full"),
+            "Failed to record the directory: disk full (completed: copied template)"
+        );
+
+        scaffold.rollback().await.unwrap();
+        assert!(root.is_dir());
+        assert!(!root.join(OPENCODE_DIR).exists());
+    }
+
+    #[tokio::test]
+    async fn git_init_ignores_openchamber_and_is_rolled_back() {
+        let dir = TempDir::new();
+        let root = dir.0.join("existing");
+        std::fs::create_dir(&root).unwrap();
+
+        let mut scaffold = prepare_root(&root).await.unwrap();
+        populate(&mut scaffold, None, &CreateProjectOptions::default())
+            .await
+            .unwrap();
+        assert_eq!(
+            scaffold.completed_steps,
+            ["initialised git", "ignored .openchamber"]
+        );
+        let exclude = std::fs::read_to_string(root.join(".git/info/exclude")).unwrap();
+        assert!(exclude.contains(".openchamber"), "{exclude}");
+
+        scaffold.rollback().await.unwrap();
+        assert!(root.is_dir());
+        assert!(!root.join(".git").exists());
+    }
+
+    #[tokio::test]
+    async fn template_names_cannot_leave_the_templates_dir() {
+        for name in ["..", ".", "../elsewhere", "a/b", "a\\b"] {
+            let err = resolve_template(name).await.unwrap_err();
+            assert!(err.starts_with("Invalid template name"), "{name}: {err}");
+        }
+    }
+}
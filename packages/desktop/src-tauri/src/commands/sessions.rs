@@ -0,0 +1,228 @@
+use serde_json::json;
+use tauri::{AppHandle, Emitter, State};
+
+use crate::{
+    session_activity::{SessionPins, PINNED_SESSIONS_KEY},
+    session_search::{self, RebuildResult, SearchOptions, SearchResponse},
+    DesktopRuntime, SettingsStore,
+};
+
+/// Emitted when a session is pinned or unpinned
+pub const SESSION_PIN_EVENT: &str = "openchamber:session-pinned";
+
+/// Pin a session so restarts treat it as a hard block while it is busy
+#[tauri::command]
+pub async fn pin_session(
+    session_id: String,
+    app: AppHandle,
+    state: State<'_, DesktopRuntime>,
+) -> Result<Vec<String>, String> {
+    set_pinned(&app, &state, session_id, true).await
+}
+
+#[tauri::command]
+pub async fn unpin_session(
+    session_id: String,
+    app: AppHandle,
+    state: State<'_, DesktopRuntime>,
+) -> Result<Vec<String>, String> {
+    set_pinned(&app, &state, session_id, false).await
+}
+
+#[tauri::command]
+pub async fn get_pinned_sessions(state: State<'_, DesktopRuntime>) -> Result<Vec<String>, String> {
+    Ok(sorted_pins(&state))
+}
+
+fn sorted_pins(state: &DesktopRuntime) -> Vec<String> {
+    sorted(&state.session_pins())
+}
+
+fn sorted(pins: &SessionPins) -> Vec<String> {
+    let mut pins: Vec<String> = pins.read().iter().cloned().collect();
+    pins.sort();
+    pins
+}
+
+async fn set_pinned(
+    app: &AppHandle,
+    state: &DesktopRuntime,
+    session_id: String,
+    pinned: bool,
+) -> Result<Vec<String>, String> {
+    let session_id = session_id.trim().to_string();
+    let (changed, current) =
+        update_pins(&state.session_pins(), state.settings(), &session_id, pinned).await?;
+    if changed {
+        let _ = app.emit(
+            SESSION_PIN_EVENT,
+            json!({ "sessionId": session_id, "pinned": pinned }),
+        );
+    }
+    Ok(current)
+}
+
+/// Pin or unpin `session_id` in memory and in settings. Returns whether that
+/// changed anything and the pins afterwards.
+async fn update_pins(
+    pins: &SessionPins,
+    store: &SettingsStore,
+    session_id: &str,
+    pinned: bool,
+) -> Result<(bool, Vec<String>), String> {
+    if session_id.is_empty() {
+        return Err("Session id is required".to_string());
+    }
+
+    let changed = {
+        let mut pins = pins.write();
+        if pinned {
+            pins.insert(session_id.to_string())
+        } else {
+            pins.remove(session_id)
+        }
+    };
+    let current = sorted(pins);
+    if !changed {
+        return Ok((false, current));
+    }
+
+    let mut settings = store
+        .load()
+        .await
+        .map_err(|e| format!("Failed to load settings: {}", e))?;
+    if let Some(object) = settings.as_object_mut() {
+        object.insert(PINNED_SESSIONS_KEY.to_string(), json!(current));
+    } else {
+        settings = json!({ PINNED_SESSIONS_KEY: current });
+    }
+    store
+        .save(settings)
+        .await
+        .map_err(|e| format!("Failed to save settings: {}", e))?;
+    Ok((true, current))
+}
+
+/// Search prompt and response text across the current project's sessions
+#[tauri::command]
+pub async fn search_sessions(
+    query: String,
+    options: Option<SearchOptions>,
+    state: State<'_, DesktopRuntime>,
+) -> Result<SearchResponse, String> {
+    let settings = state.settings().load().await.unwrap_or_default();
+    state
+        .session_search()
+        .search(
+            &query,
+            options.unwrap_or_default(),
+            session_search::indexing_enabled(&settings),
+        )
+        .await
+}
+
+/// Re-fetch every session of the current project into the search index
+#[tauri::command]
+pub async fn rebuild_session_index(
+    state: State<'_, DesktopRuntime>,
+) -> Result<RebuildResult, String> {
+    let settings = state.settings().load().await.unwrap_or_default();
+    if !session_search::indexing_enabled(&settings) {
+        return Err("Session indexing is disabled (sessionSearch.indexing)".to_string());
+    }
+    state.session_search().rebuild().await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::session_activity::load_pins;
+
+    /// A settings file under the system temp dir, removed on drop
+    struct TempSettings {
+        root: std::path::PathBuf,
+        store: SettingsStore,
+    }
+
+    impl TempSettings {
+        fn new() -> Self {
+            let root =
+                std::env::temp_dir().join(format!("openchamber-pins-{}", uuid::Uuid::new_v4()));
+            let store = SettingsStore::at(root.join("settings.json"));
+            Self { root, store }
+        }
+    }
+
+    impl Drop for TempSettings {
+        fn drop(&mut self) {
+            std::fs::remove_dir_all(&self.root).ok();
+        }
+    }
+
+    #[tokio::test]
+    async fn pins_persist_across_restarts() {
+        let settings = TempSettings::new();
+        settings
+            .store
+            .save(json!({ "theme": "dark" }))
+            .await
+            .unwrap();
+        let pins = SessionPins::default();
+
+        assert_eq!(
+            update_pins(&pins, &settings.store, "ses_b", true)
+                .await
+                .unwrap(),
+            (true, vec!["ses_b".to_string()])
+        );
+        update_pins(&pins, &settings.store, "ses_a", true)
+            .await
+            .unwrap();
+
+        let saved = settings.store.load().await.unwrap();
+        assert_eq!(saved[PINNED_SESSIONS_KEY], json!(["ses_a", "ses_b"]));
+        assert_eq!(saved["theme"], "dark");
+        // What the next launch starts with
+        assert_eq!(sorted(&load_pins(&saved)), ["ses_a", "ses_b"]);
+
+        update_pins(&pins, &settings.store, "ses_b", false)
+            .await
+            .unwrap();
+        let saved = settings.store.load().await.unwrap();
+        assert_eq!(sorted(&load_pins(&saved)), ["ses_a"]);
+    }
+
+    #[tokio::test]
+    async fn repeated_pins_leave_settings_alone() {
+        let settings = TempSettings::new();
+        let pins = SessionPins::default();
+        update_pins(&pins, &settings.store, "ses_a", true)
+            .await
+            .unwrap();
+        settings.store.save(json!({})).await.unwrap();
+
+        let (changed, current) = update_pins(&pins, &settings.store, "ses_a", true)
+            .await
+            .unwrap();
+        assert!(!changed);
+        assert_eq!(current, ["ses_a"]);
+        let (changed, _) = update_pins(&pins, &settings.store, "ses_z", false)
+            .await
+            .unwrap();
+        assert!(!changed);
+        assert_eq!(settings.store.load().await.unwrap(), json!({}));
+    }
+
+    #[tokio::test]
+    async fn a_session_id_is_required() {
+        let settings = TempSettings::new();
+        let pins = SessionPins::default();
+
+        let error = update_pins(&pins, &settings.store, "", true)
+            .await
+            .unwrap_err();
+        assert_eq!(error, "Session id is required");
+        assert!(pins.read().is_empty());
+        assert!(!settings.root.exists());
+    }
+}
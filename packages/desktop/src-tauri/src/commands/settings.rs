@@ -1,9 +1,12 @@
 use serde::{Deserialize, Serialize};
 use serde_json::{json, Value};
-use std::collections::HashSet;
+use std::{collections::HashSet, path::PathBuf};
 use tauri::State;
 
-use crate::DesktopRuntime;
+use crate::{
+    login_shell, model_capabilities::ModelCapabilities, models_metadata, opencode_manager,
+    proxy_routes, server_binding, DesktopRuntime, SettingsStore,
+};
 
 #[derive(Debug, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
@@ -46,6 +49,23 @@ pub async fn save_settings(
         .await
         .map_err(|e| format!("Failed to load current settings: {}", e))?;
 
+    // Launch and readiness settings apply on the next restart, so reject bad ones now
+    if let Some(section) = changes.get("opencode") {
+        if !section.is_object() {
+            return Err("opencode must be an object".to_string());
+        }
+        opencode_manager::parse_launch_overrides(section)?;
+        opencode_manager::parse_readiness_config(section)?;
+    }
+    if let Some(section) = changes.get("server") {
+        if !section.is_object() {
+            return Err("server must be an object".to_string());
+        }
+        server_binding::parse_bind_address(section)?;
+        proxy_routes::parse_max_body_mb(section)?;
+    }
+    models_metadata::parse_source_url(changes.get("modelsMetadataUrl"))?;
+
     // Sanitize incoming changes
     let sanitized_changes = sanitize_settings_update(&changes);
 
@@ -58,6 +78,9 @@ pub async fn save_settings(
         .save(merged.clone())
         .await
         .map_err(|e| format!("Failed to save settings: {}", e))?;
+    login_shell::configure(&merged);
+    state.proxy_log().configure(&merged);
+    state.configure_server(&merged);
 
     // Format response
     Ok(format_settings_response(&merged))
@@ -65,16 +88,111 @@ pub async fn save_settings(
 
 /// Restart OpenCode CLI (matches Express /api/config/reload)
 #[tauri::command]
-pub async fn restart_opencode(state: State<'_, DesktopRuntime>) -> Result<RestartResult, String> {
+pub async fn restart_opencode(
+    state: State<'_, DesktopRuntime>,
+    force: Option<bool>,
+    override_pinned: Option<bool>,
+) -> Result<RestartResult, String> {
+    state
+        .ensure_restart_allowed(force.unwrap_or(false), override_pinned.unwrap_or(false))
+        .await?;
     state
         .opencode
-        .restart()
+        .restart_for("manual restart")
         .await
         .map_err(|e| format!("Failed to restart OpenCode: {}", e))?;
 
     Ok(RestartResult { restarted: true })
 }
 
+/// Providers OpenCode can use and its default model (cached briefly)
+#[tauri::command]
+pub async fn get_model_capabilities(
+    state: State<'_, DesktopRuntime>,
+) -> Result<ModelCapabilities, String> {
+    Ok(state.model_capabilities().get().await)
+}
+
+/// Generation of the config OpenCode is running with; see `openchamber:config-generation`
+#[tauri::command]
+pub async fn get_config_generation(state: State<'_, DesktopRuntime>) -> Result<u64, String> {
+    Ok(state.config_generation().current())
+}
+
+/// Use `config` as the OpenCode config for `directory` instead of its
+/// `.openchamber/opencode.json` (None clears it). Applies from the next start;
+/// returns the config the current directory now resolves to.
+#[tauri::command]
+pub async fn set_project_opencode_config(
+    directory: String,
+    config: Option<String>,
+    state: State<'_, DesktopRuntime>,
+) -> Result<Option<String>, String> {
+    let directory = PathBuf::from(directory.trim());
+    if !directory.is_dir() {
+        return Err(format!("{} is not a directory", directory.display()));
+    }
+    let config = config
+        .map(|config| PathBuf::from(config.trim()))
+        .filter(|config| !config.as_os_str().is_empty());
+    if let Some(config) = &config {
+        if !config.is_absolute() || !config.is_file() {
+            return Err(format!("{} is not a config file", config.display()));
+        }
+    }
+
+    let mut settings = state
+        .settings()
+        .load()
+        .await
+        .map_err(|e| format!("Failed to load current settings: {}", e))?;
+    opencode_manager::record_project_config(&mut settings, &directory, config.as_deref());
+    state
+        .settings()
+        .save(settings)
+        .await
+        .map_err(|e| format!("Failed to save settings: {}", e))?;
+
+    // Refresh what the current directory resolves to without restarting
+    let opencode = state.opencode_manager();
+    opencode
+        .set_working_directory(opencode.get_working_directory())
+        .await
+        .map_err(|e| e.to_string())?;
+    Ok(opencode
+        .config_path()
+        .map(|path| path.to_string_lossy().to_string()))
+}
+
+/// Add a directory to approvedDirectories, optionally making it the last directory too
+pub(crate) async fn remember_directory(
+    settings: &SettingsStore,
+    path: &str,
+    make_current: bool,
+) -> Result<(), String> {
+    let current = settings
+        .load()
+        .await
+        .map_err(|e| format!("Failed to load current settings: {}", e))?;
+
+    let changes = if make_current {
+        json!({ "lastDirectory": path })
+    } else {
+        let mut approved = current
+            .get("approvedDirectories")
+            .map(extract_string_vec)
+            .unwrap_or_default();
+        approved.push(path.to_string());
+        json!({ "approvedDirectories": approved })
+    };
+
+    let merged = merge_persisted_settings(&current, &changes);
+    settings
+        .save(merged)
+        .await
+        .map_err(|e| format!("Failed to save settings: {}", e))
+}
+
 /// Sanitize settings update payload (port of Express sanitizeSettingsUpdate)
 fn sanitize_settings_update(payload: &Value) -> Value {
     let mut result = json!({});
@@ -128,6 +246,12 @@ fn sanitize_settings_update(payload: &Value) -> Value {
                 result_obj.insert("markdownDisplayMode".to_string(), json!(s));
             }
         }
+        // Validated by save_settings; empty or null goes back to models.dev
+        if let Some(value) = obj.get("modelsMetadataUrl") {
+            if let Ok(url) = models_metadata::parse_source_url(Some(value)) {
+                result_obj.insert("modelsMetadataUrl".to_string(), json!(url));
+            }
+        }
 
         // Boolean fields
         if let Some(Value::Bool(b)) = obj.get("useSystemTheme") {
@@ -136,6 +260,15 @@ fn sanitize_settings_update(payload: &Value) -> Value {
         if let Some(Value::Bool(b)) = obj.get("showReasoningTraces") {
             result_obj.insert("showReasoningTraces".to_string(), json!(b));
         }
+        if let Some(Value::Bool(b)) = obj.get("disableLoginShellPath") {
+            result_obj.insert("disableLoginShellPath".to_string(), json!(b));
+        }
+        if let Some(Value::Bool(b)) = obj.get("keepRunningInBackground") {
+            result_obj.insert("keepRunningInBackground".to_string(), json!(b));
+        }
+        if let Some(Value::Bool(b)) = obj.get("proxyDebug") {
+            result_obj.insert("proxyDebug".to_string(), json!(b));
+        }
 
         // Array fields
         if let Some(arr) = obj.get("approvedDirectories") {
@@ -160,6 +293,20 @@ fn sanitize_settings_update(payload: &Value) -> Value {
                 result_obj.insert("typographySizes".to_string(), sanitized);
             }
         }
+
+        // OpenCode launch and readiness settings (partial, validated by save_settings)
+        if let Some(section) = obj.get("opencode") {
+            if let Some(sanitized) = sanitize_opencode_launch_partial(section) {
+                result_obj.insert("opencode".to_string(), sanitized);
+            }
+        }
+
+        // Desktop server settings (partial, validated by save_settings)
+        if let Some(section) = obj.get("server") {
+            if let Some(sanitized) = sanitize_server_partial(section) {
+                result_obj.insert("server".to_string(), sanitized);
+            }
+        }
     }
 
     result
@@ -234,6 +381,40 @@ fn merge_persisted_settings(current: &Value, changes: &Value) -> Value {
             }
             result_obj.insert("typographySizes".to_string(), json!(merged_typo));
         }
+
+        // Merge OpenCode launch settings into the rest of that section; null clears a key
+        if let Some(changes_opencode) = changes_obj.get("opencode").and_then(|v| v.as_object()) {
+            let mut merged_opencode = current
+                .get("opencode")
+                .and_then(|v| v.as_object())
+                .cloned()
+                .unwrap_or_default();
+            for (key, value) in changes_opencode {
+                if value.is_null() {
+                    merged_opencode.remove(key);
+                } else {
+                    merged_opencode.insert(key.clone(), value.clone());
+                }
+            }
+            result_obj.insert("opencode".to_string(), json!(merged_opencode));
+        }
+
+        // Same for the desktop server section
+        if let Some(changes_server) = changes_obj.get("server").and_then(|v| v.as_object()) {
+            let mut merged_server = current
+                .get("server")
+                .and_then(|v| v.as_object())
+                .cloned()
+                .unwrap_or_default();
+            for (key, value) in changes_server {
+                if value.is_null() {
+                    merged_server.remove(key);
+                } else {
+                    merged_server.insert(key.clone(), value.clone());
+                }
+            }
+            result_obj.insert("server".to_string(), json!(merged_server));
+        }
     }
 
     result
@@ -329,6 +510,45 @@ fn sanitize_typography_sizes_partial(input: &Value) -> Option<Value> {
     }
 }
 
+/// Keep the launch and readiness keys of an `opencode` section
+fn sanitize_server_partial(input: &Value) -> Option<Value> {
+    let obj = input.as_object()?;
+    let mut result = serde_json::Map::new();
+    if let Some(value) = obj.get("bindAddress") {
+        if value.is_string() || value.is_null() {
+            result.insert("bindAddress".to_string(), value.clone());
+        }
+    }
+    if let Some(value) = obj.get("maxBodyMb") {
+        if value.is_number() || value.is_null() {
+            result.insert("maxBodyMb".to_string(), value.clone());
+        }
+    }
+    if let Some(Value::Bool(b)) = obj.get("serveWebUi") {
+        result.insert("serveWebUi".to_string(), json!(b));
+    }
+    if result.is_empty() {
+        None
+    } else {
+        Some(json!(result))
+    }
+}
+
+fn sanitize_opencode_launch_partial(input: &Value) -> Option<Value> {
+    let obj = input.as_object()?;
+    let mut result = serde_json::Map::new();
+    for key in &["extraArgs", "env", "readinessEndpoints", "lenientReadiness"] {
+        if let Some(value) = obj.get(*key) {
+            result.insert(key.to_string(), value.clone());
+        }
+    }
+    if result.is_empty() {
+        None
+    } else {
+        Some(json!(result))
+    }
+}
+
 /// Extract string vector from JSON value
 fn extract_string_vec(value: &Value) -> Vec<String> {
     if let Some(arr) = value.as_array() {
@@ -0,0 +1,72 @@
+use std::path::PathBuf;
+
+use tauri::State;
+
+use crate::{
+    workspace_snapshot::{self, RestoreResult, SnapshotInfo},
+    DesktopRuntime,
+};
+
+/// The current workspace; snapshots always belong to it
+fn workspace(state: &DesktopRuntime) -> Result<PathBuf, String> {
+    let workspace = state.opencode_manager().get_working_directory();
+    if !workspace.is_dir() {
+        return Err(format!("Workspace does not exist: {}", workspace.display()));
+    }
+    Ok(workspace)
+}
+
+#[tauri::command]
+pub async fn create_workspace_snapshot(
+    label: Option<String>,
+    state: State<'_, DesktopRuntime>,
+) -> Result<SnapshotInfo, String> {
+    let workspace = workspace(&state)?;
+    let settings = state.settings().load().await.unwrap_or_default();
+    let label = label.unwrap_or_default();
+    workspace_snapshot::create(
+        &workspace,
+        label.trim(),
+        workspace_snapshot::max_age(&settings),
+    )
+    .await
+    .map_err(|e| format!("Failed to create snapshot: {}", e))
+}
+
+#[tauri::command]
+pub async fn list_workspace_snapshots(
+    state: State<'_, DesktopRuntime>,
+) -> Result<Vec<SnapshotInfo>, String> {
+    let workspace = workspace(&state)?;
+    let settings = state.settings().load().await.unwrap_or_default();
+    workspace_snapshot::prune(&workspace, workspace_snapshot::max_age(&settings)).await;
+    workspace_snapshot::list(&workspace)
+        .await
+        .map_err(|e| format!("Failed to list snapshots: {}", e))
+}
+
+/// Restore all files of a snapshot, or only `paths`. Files modified after
+/// the snapshot come back as conflicts and block the restore unless `force`.
+#[tauri::command]
+pub async fn restore_workspace_snapshot(
+    id: String,
+    paths: Option<Vec<String>>,
+    force: Option<bool>,
+    state: State<'_, DesktopRuntime>,
+) -> Result<RestoreResult, String> {
+    let workspace = workspace(&state)?;
+    workspace_snapshot::restore(&workspace, id.trim(), paths, force.unwrap_or(false))
+        .await
+        .map_err(|e| format!("Failed to restore snapshot: {}", e))
+}
+
+#[tauri::command]
+pub async fn delete_workspace_snapshot(
+    id: String,
+    state: State<'_, DesktopRuntime>,
+) -> Result<(), String> {
+    let workspace = workspace(&state)?;
+    workspace_snapshot::delete(&workspace, id.trim())
+        .await
+        .map_err(|e| format!("Failed to delete snapshot: {}", e))
+}
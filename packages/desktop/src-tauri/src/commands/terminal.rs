@@ -1,4 +1,9 @@
 use log::error;
+#[cfg(unix)]
+use nix::{
+    sys::signal::{killpg, Signal},
+    unistd::Pid,
+};
 use portable_pty::{Child, CommandBuilder, MasterPty, NativePtySystem, PtySize, PtySystem};
 use serde::{Deserialize, Serialize};
 use std::{
@@ -6,10 +11,19 @@ use std::{
     env,
     io::{Read, Write},
     path::{Path, PathBuf},
-    sync::{Arc, Mutex},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
     thread,
+    time::{Duration, Instant},
+};
+use tauri::{AppHandle, Emitter, State, Window};
+
+use crate::{
+    shell_integration::{self, OscParser, ShellMark},
+    workspace_trust, DesktopRuntime,
 };
-use tauri::{Emitter, State, Window};
 
 const DEFAULT_SHELL: &str = "/bin/zsh";
 const DEFAULT_TERM: &str = "xterm-256color";
@@ -22,6 +36,106 @@ pub struct TerminalSession {
     pub master: Box<dyn MasterPty + Send>,
     pub writer: Arc<Mutex<Box<dyn Write + Send>>>,
     pub child: Arc<Mutex<Box<dyn Child + Send + Sync>>>,
+    /// Captured at spawn; the child mutex is held by the exit watcher while it waits
+    pub shell_pid: Option<u32>,
+    /// Workspace the app had open when the terminal started
+    pub workspace: PathBuf,
+    /// Follow project switches with a `cd` when idle at a prompt
+    pub follow_workspace: bool,
+    /// Updated from shell-integration (OSC 133/633) prompt markers
+    pub at_prompt: Arc<AtomicBool>,
+}
+
+/// Quote a path for POSIX shells (and fish) as a single word
+pub fn shell_quote(value: &str) -> String {
+    format!("'{}'", value.replace('\'', "'\\''"))
+}
+
+/// What currently owns a terminal's foreground process group
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TerminalForeground {
+    /// False where foreground detection isn't implemented (Windows)
+    supported: bool,
+    pid: Option<u32>,
+    name: Option<String>,
+    command_line: Option<String>,
+    /// The foreground process is something other than the shell itself
+    busy: bool,
+}
+
+#[cfg(unix)]
+fn terminal_foreground(session: &TerminalSession) -> TerminalForeground {
+    let pid = session
+        .master
+        .process_group_leader()
+        .and_then(|leader| u32::try_from(leader).ok());
+    let (name, command_line) = pid.map(read_process_info).unwrap_or_default();
+    TerminalForeground {
+        supported: true,
+        pid,
+        name,
+        command_line,
+        busy: pid.is_some() && pid != session.shell_pid,
+    }
+}
+
+#[cfg(not(unix))]
+fn terminal_foreground(_session: &TerminalSession) -> TerminalForeground {
+    TerminalForeground {
+        supported: false,
+        pid: None,
+        name: None,
+        command_line: None,
+        busy: false,
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn read_process_info(pid: u32) -> (Option<String>, Option<String>) {
+    let name = std::fs::read_to_string(format!("/proc/{pid}/comm"))
+        .ok()
+        .map(|comm| comm.trim().to_string())
+        .filter(|comm| !comm.is_empty());
+    let command_line = std::fs::read(format!("/proc/{pid}/cmdline"))
+        .ok()
+        .map(|raw| {
+            raw.split(|byte| *byte == 0)
+                .filter(|arg| !arg.is_empty())
+                .map(|arg| String::from_utf8_lossy(arg).to_string())
+                .collect::<Vec<_>>()
+                .join(" ")
+        })
+        .filter(|command_line| !command_line.is_empty());
+    (name, command_line)
+}
+
+#[cfg(all(unix, not(target_os = "linux")))]
+fn read_process_info(pid: u32) -> (Option<String>, Option<String>) {
+    let ps = |field: &str| {
+        std::process::Command::new("ps")
+            .args(["-o", field, "-p", &pid.to_string()])
+            .output()
+            .ok()
+            .filter(|output| output.status.success())
+            .map(|output| String::from_utf8_lossy(&output.stdout).trim().to_string())
+            .filter(|value| !value.is_empty())
+    };
+    let name = ps("comm=").map(|comm| {
+        Path::new(&comm)
+            .file_name()
+            .and_then(|name| name.to_str())
+            .map(str::to_string)
+            .unwrap_or(comm)
+    });
+    (name, ps("args="))
+}
+
+/// True when something other than the shell owns the terminal's foreground;
+/// an unknown foreground counts as busy so no input gets injected
+fn has_foreground_process(session: &TerminalSession) -> bool {
+    let foreground = terminal_foreground(session);
+    foreground.supported && (foreground.pid.is_none() || foreground.busy)
 }
 
 pub struct TerminalState {
@@ -34,6 +148,45 @@ impl TerminalState {
             sessions: Arc::new(Mutex::new(HashMap::new())),
         }
     }
+
+    /// Hang up every terminal so shells exit on their own (their exit watchers
+    /// still emit exit events), then kill whatever is left after `grace`
+    pub async fn close_all(&self, grace: Duration) {
+        #[cfg(unix)]
+        {
+            let shells: Vec<u32> = {
+                let sessions = self.sessions.lock().unwrap();
+                sessions
+                    .values()
+                    .filter_map(|session| session.shell_pid)
+                    .collect()
+            };
+            for pid in shells {
+                let _ = killpg(Pid::from_raw(pid as i32), Signal::SIGHUP);
+            }
+            let all_exited = || self.sessions.lock().unwrap().is_empty();
+            let deadline = Instant::now() + grace;
+            while Instant::now() < deadline && !all_exited() {
+                tokio::time::sleep(Duration::from_millis(50)).await;
+            }
+        }
+        #[cfg(not(unix))]
+        let _ = grace;
+
+        let remaining: Vec<TerminalSession> = {
+            let mut sessions = self.sessions.lock().unwrap();
+            sessions.drain().map(|(_, session)| session).collect()
+        };
+        for session in remaining {
+            // The exit watcher holds the child lock, so kill by pid; dropping
+            // the session closes the PTY, which ends ConPTY shells on Windows
+            #[cfg(unix)]
+            if let Some(pid) = session.shell_pid {
+                let _ = killpg(Pid::from_raw(pid as i32), Signal::SIGKILL);
+            }
+            drop(session);
+        }
+    }
 }
 
 #[derive(Deserialize)]
@@ -41,6 +194,31 @@ pub struct CreateTerminalPayload {
     pub cols: u16,
     pub rows: u16,
     pub cwd: Option<String>,
+    /// Overrides the `terminal.followWorkspace` setting for this session
+    #[serde(default)]
+    pub follow_workspace: Option<bool>,
+}
+
+/// `terminal.*` preferences from settings.json
+struct TerminalPreferences {
+    follow_workspace: bool,
+    shell_integration: bool,
+}
+
+impl TerminalPreferences {
+    async fn load(runtime: &DesktopRuntime) -> Self {
+        let settings = runtime.settings().load().await.unwrap_or_default();
+        let flag = |key: &str| {
+            settings
+                .get("terminal")
+                .and_then(|terminal| terminal.get(key))
+                .and_then(|value| value.as_bool())
+        };
+        Self {
+            follow_workspace: flag("followWorkspace").unwrap_or(false),
+            shell_integration: flag("shellIntegration").unwrap_or(true),
+        }
+    }
 }
 
 #[derive(Serialize)]
@@ -52,9 +230,15 @@ pub struct CreateTerminalResponse {
 pub async fn create_terminal_session(
     payload: CreateTerminalPayload,
     state: State<'_, TerminalState>,
+    runtime: State<'_, DesktopRuntime>,
     window: Window,
 ) -> Result<CreateTerminalResponse, String> {
-    let pty_system = NativePtySystem::default();
+    let preferences = TerminalPreferences::load(&runtime).await;
+    let follow_workspace = payload
+        .follow_workspace
+        .unwrap_or(preferences.follow_workspace);
+    let workspace = runtime.opencode_manager().get_working_directory();
+
     let size = PtySize {
         rows: payload.rows,
         cols: payload.cols,
@@ -63,10 +247,13 @@ pub async fn create_terminal_session(
     };
 
     let working_dir = resolve_working_directory(payload.cwd.as_deref())?;
+    workspace_trust::require_trust(runtime.settings(), &working_dir, "the terminal").await?;
     let shell_path = resolve_shell();
 
     let mut cmd = CommandBuilder::new(&shell_path);
-    if shell_accepts_login_flag(&shell_path) {
+    let integrated =
+        preferences.shell_integration && shell_integration::configure(&mut cmd, &shell_path);
+    if !integrated && shell_accepts_login_flag(&shell_path) {
         cmd.arg("-l");
     }
     if let Some(cwd) = working_dir.to_str() {
@@ -74,11 +261,52 @@ pub async fn create_terminal_session(
     }
     apply_terminal_environment(&mut cmd, &shell_path);
 
-    let pair = pty_system.openpty(size).map_err(|e| e.to_string())?;
+    let session_id = spawn_pty_session(cmd, size, &state, window, workspace, follow_workspace)?;
+
+    Ok(CreateTerminalResponse { session_id })
+}
+
+/// Run `cmd` in a new PTY registered as a terminal session. Output and exit
+/// stream on `terminal://<session id>`; input goes through `send_terminal_input`.
+pub(crate) fn spawn_pty_session(
+    cmd: CommandBuilder,
+    size: PtySize,
+    state: &TerminalState,
+    window: Window,
+    workspace: PathBuf,
+    follow_workspace: bool,
+) -> Result<String, String> {
+    let (session, reader) = open_pty_session(cmd, size, workspace, follow_workspace)?;
+    let child = session.child.clone();
+    let at_prompt = session.at_prompt.clone();
+
+    let session_id = uuid::Uuid::new_v4().to_string();
+    state
+        .sessions
+        .lock()
+        .unwrap()
+        .insert(session_id.clone(), session);
+
+    spawn_reader_thread(reader, window.clone(), session_id.clone(), at_prompt);
+    spawn_exit_watcher(child, window, state.sessions.clone(), session_id.clone());
+
+    Ok(session_id)
+}
+
+/// Spawn `cmd` in a new PTY, returning the session and the PTY's output reader
+fn open_pty_session(
+    cmd: CommandBuilder,
+    size: PtySize,
+    workspace: PathBuf,
+    follow_workspace: bool,
+) -> Result<(TerminalSession, Box<dyn Read + Send>), String> {
+    let pair = NativePtySystem::default()
+        .openpty(size)
+        .map_err(|e| e.to_string())?;
     let child = pair
         .slave
         .spawn_command(cmd)
-        .map_err(|e| format!("Failed to spawn shell: {e}"))?;
+        .map_err(|e| format!("Failed to spawn terminal process: {e}"))?;
     drop(pair.slave);
 
     let reader = pair
@@ -90,23 +318,18 @@ pub async fn create_terminal_session(
             .take_writer()
             .map_err(|e| format!("Failed to take PTY writer: {e}"))?,
     ));
-    let master = pair.master;
-    let child = Arc::new(Mutex::new(child));
+    let shell_pid = child.process_id();
 
-    let session_id = uuid::Uuid::new_v4().to_string();
-    state.sessions.lock().unwrap().insert(
-        session_id.clone(),
-        TerminalSession {
-            master,
-            writer: writer.clone(),
-            child: child.clone(),
-        },
-    );
-
-    spawn_reader_thread(reader, window.clone(), session_id.clone());
-    spawn_exit_watcher(child, window, state.sessions.clone(), session_id.clone());
-
-    Ok(CreateTerminalResponse { session_id })
+    let session = TerminalSession {
+        master: pair.master,
+        writer,
+        child: Arc::new(Mutex::new(child)),
+        shell_pid,
+        workspace,
+        follow_workspace,
+        at_prompt: Arc::new(AtomicBool::new(false)),
+    };
+    Ok((session, reader))
 }
 
 #[tauri::command]
@@ -157,13 +380,34 @@ pub async fn resize_terminal(
     Ok(())
 }
 
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CloseTerminalResult {
+    closed: bool,
+    /// Set instead of closing when `confirm_if_busy` found a running process
+    #[serde(skip_serializing_if = "Option::is_none")]
+    busy: Option<TerminalForeground>,
+}
+
 #[tauri::command]
 pub async fn close_terminal(
     session_id: String,
+    confirm_if_busy: Option<bool>,
     state: State<'_, TerminalState>,
-) -> Result<(), String> {
+) -> Result<CloseTerminalResult, String> {
     let session = {
         let mut sessions = state.sessions.lock().unwrap();
+        if confirm_if_busy.unwrap_or(false) {
+            if let Some(session) = sessions.get(&session_id) {
+                let foreground = terminal_foreground(session);
+                if foreground.busy {
+                    return Ok(CloseTerminalResult {
+                        closed: false,
+                        busy: Some(foreground),
+                    });
+                }
+            }
+        }
         sessions.remove(&session_id)
     };
 
@@ -173,7 +417,47 @@ pub async fn close_terminal(
         }
     }
 
-    Ok(())
+    Ok(CloseTerminalResult {
+        closed: true,
+        busy: None,
+    })
+}
+
+#[tauri::command]
+pub async fn get_terminal_foreground(
+    session_id: String,
+    state: State<'_, TerminalState>,
+) -> Result<TerminalForeground, String> {
+    let sessions = state.sessions.lock().unwrap();
+    let Some(session) = sessions.get(&session_id) else {
+        return Err("Terminal session not found".to_string());
+    };
+    Ok(terminal_foreground(session))
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TerminalSessionInfo {
+    session_id: String,
+    workspace: String,
+    follow_workspace: bool,
+    foreground: TerminalForeground,
+}
+
+#[tauri::command]
+pub async fn list_terminal_sessions(
+    state: State<'_, TerminalState>,
+) -> Result<Vec<TerminalSessionInfo>, String> {
+    let sessions = state.sessions.lock().unwrap();
+    Ok(sessions
+        .iter()
+        .map(|(session_id, session)| TerminalSessionInfo {
+            session_id: session_id.clone(),
+            workspace: session.workspace.to_string_lossy().to_string(),
+            follow_workspace: session.follow_workspace,
+            foreground: terminal_foreground(session),
+        })
+        .collect())
 }
 
 #[derive(Deserialize)]
@@ -182,14 +466,27 @@ pub struct RestartTerminalPayload {
     pub cols: u16,
     pub rows: u16,
     pub cwd: String,
+    #[serde(default)]
+    pub follow_workspace: Option<bool>,
 }
 
+
 #[tauri::command]
 pub async fn restart_terminal_session(
     payload: RestartTerminalPayload,
     state: State<'_, TerminalState>,
+    runtime: State<'_, DesktopRuntime>,
     window: Window,
 ) -> Result<CreateTerminalResponse, String> {
+    let preferences = TerminalPreferences::load(&runtime).await;
+    let follow_workspace = payload
+        .follow_workspace
+        .unwrap_or(preferences.follow_workspace);
+    let workspace = runtime.opencode_manager().get_working_directory();
+    // Checked before the old session is killed so a refusal leaves it running
+    let working_dir = resolve_working_directory(Some(&payload.cwd))?;
+    workspace_trust::require_trust(runtime.settings(), &working_dir, "the terminal").await?;
+
     {
         let mut sessions = state.sessions.lock().unwrap();
         if let Some(session) = sessions.remove(&payload.session_id) {
@@ -199,7 +496,6 @@ pub async fn restart_terminal_session(
         }
     }
 
-    let pty_system = NativePtySystem::default();
     let size = PtySize {
         rows: payload.rows,
         cols: payload.cols,
@@ -207,11 +503,12 @@ pub async fn restart_terminal_session(
         pixel_height: 0,
     };
 
-    let working_dir = resolve_working_directory(Some(&payload.cwd))?;
     let shell_path = resolve_shell();
 
     let mut cmd = CommandBuilder::new(&shell_path);
-    if shell_accepts_login_flag(&shell_path) {
+    let integrated =
+        preferences.shell_integration && shell_integration::configure(&mut cmd, &shell_path);
+    if !integrated && shell_accepts_login_flag(&shell_path) {
         cmd.arg("-l");
     }
     if let Some(cwd) = working_dir.to_str() {
@@ -219,37 +516,7 @@ pub async fn restart_terminal_session(
     }
     apply_terminal_environment(&mut cmd, &shell_path);
 
-    let pair = pty_system.openpty(size).map_err(|e| e.to_string())?;
-    let child = pair
-        .slave
-        .spawn_command(cmd)
-        .map_err(|e| format!("Failed to spawn shell: {e}"))?;
-    drop(pair.slave);
-
-    let reader = pair
-        .master
-        .try_clone_reader()
-        .map_err(|e| format!("Failed to clone PTY reader: {e}"))?;
-    let writer = Arc::new(Mutex::new(
-        pair.master
-            .take_writer()
-            .map_err(|e| format!("Failed to take PTY writer: {e}"))?,
-    ));
-    let master = pair.master;
-    let child = Arc::new(Mutex::new(child));
-
-    let session_id = uuid::Uuid::new_v4().to_string();
-    state.sessions.lock().unwrap().insert(
-        session_id.clone(),
-        TerminalSession {
-            master,
-            writer: writer.clone(),
-            child: child.clone(),
-        },
-    );
-
-    spawn_reader_thread(reader, window.clone(), session_id.clone());
-    spawn_exit_watcher(child, window, state.sessions.clone(), session_id.clone());
+    let session_id = spawn_pty_session(cmd, size, &state, window, workspace, follow_workspace)?;
 
     Ok(CreateTerminalResponse { session_id })
 }
@@ -298,9 +565,58 @@ pub async fn force_kill_terminal(
     Ok(())
 }
 
-fn spawn_reader_thread(mut reader: Box<dyn Read + Send>, window: Window, session_id: String) {
+/// Turns shell-integration marks into `command-started` / `command-finished` events
+#[derive(Default)]
+struct CommandTracker {
+    at_prompt: bool,
+    command_line: Option<String>,
+    started_at: Option<Instant>,
+}
+
+impl CommandTracker {
+    fn apply(&mut self, mark: ShellMark) -> Option<serde_json::Value> {
+        match mark {
+            ShellMark::PromptStart | ShellMark::PromptEnd => {
+                self.at_prompt = true;
+                None
+            }
+            ShellMark::CommandLine(text) => {
+                self.command_line = Some(text);
+                None
+            }
+            ShellMark::CommandStart => {
+                self.at_prompt = false;
+                self.started_at = Some(Instant::now());
+                Some(serde_json::json!({
+                    "type": "command-started",
+                    "text": self.command_line.take().unwrap_or_default(),
+                }))
+            }
+            ShellMark::CommandFinished(exit_code) => {
+                let duration_ms = self
+                    .started_at
+                    .take()
+                    .map(|started| started.elapsed().as_millis() as u64);
+                Some(serde_json::json!({
+                    "type": "command-finished",
+                    "exitCode": exit_code,
+                    "durationMs": duration_ms,
+                }))
+            }
+        }
+    }
+}
+
+fn spawn_reader_thread(
+    mut reader: Box<dyn Read + Send>,
+    window: Window,
+    session_id: String,
+    at_prompt: Arc<AtomicBool>,
+) {
     thread::spawn(move || {
         let mut buffer = [0u8; 16384];
+        let mut parser = OscParser::default();
+        let mut tracker = CommandTracker::default();
         let event_name = format!("terminal://{}", session_id);
         loop {
             match reader.read(&mut buffer) {
@@ -310,6 +626,7 @@ fn spawn_reader_thread(mut reader: Box<dyn Read + Send>, window: Window, session
                     if data.is_empty() {
                         continue;
                     }
+                    let marks = parser.feed(&data);
 
                     if let Err(error) =
                         window.emit(&event_name, serde_json::json!({ "type": "data", "data": data }))
@@ -317,6 +634,13 @@ fn spawn_reader_thread(mut reader: Box<dyn Read + Send>, window: Window, session
                         error!("Failed to emit terminal data: {error}");
                         break;
                     }
+
+                    for mark in marks {
+                        if let Some(event) = tracker.apply(mark) {
+                            let _ = window.emit(&event_name, event);
+                        }
+                    }
+                    at_prompt.store(tracker.at_prompt, Ordering::SeqCst);
                 }
                 Err(error) => {
                     error!("Terminal read error: {error}");
@@ -363,7 +687,7 @@ fn spawn_exit_watcher(
     });
 }
 
-fn resolve_shell() -> String {
+pub(crate) fn resolve_shell() -> String {
     env::var("SHELL")
         .ok()
         .filter(|value| !value.trim().is_empty())
@@ -406,7 +730,7 @@ fn resolve_working_directory(input: Option<&str>) -> Result<PathBuf, String> {
     Ok(path)
 }
 
-fn apply_terminal_environment(cmd: &mut CommandBuilder, shell_path: &str) {
+pub(crate) fn apply_terminal_environment(cmd: &mut CommandBuilder, shell_path: &str) {
     cmd.env(
         "TERM",
         env::var("TERM").unwrap_or_else(|_| DEFAULT_TERM.to_string()),
@@ -428,3 +752,320 @@ fn apply_terminal_environment(cmd: &mut CommandBuilder, shell_path: &str) {
     cmd.env("OPENCHAMBER_DESKTOP", "1");
     cmd.env("SHELL", shell_path);
 }
+
+/// After a project switch, `cd` following terminals that are idle at a prompt and
+/// tell the rest via a `workspace-changed` event so the UI can badge them.
+pub fn handle_workspace_change(app: &AppHandle, state: &TerminalState, new_dir: &Path) {
+    let Some(dir) = new_dir.to_str() else {
+        return;
+    };
+    for (session_id, following) in follow_workspace_change(state, dir) {
+        let _ = app.emit(
+            &format!("terminal://{}", session_id),
+            serde_json::json!({
+                "type": "workspace-changed",
+                "workspace": dir,
+                "following": following,
+            }),
+        );
+    }
+}
+
+/// Send `cd` to following sessions idle at a prompt. Returns the sessions left
+/// in another workspace, with whether each one follows project switches.
+fn follow_workspace_change(state: &TerminalState, dir: &str) -> Vec<(String, bool)> {
+    let new_dir = Path::new(dir);
+    let mut sessions = match state.sessions.lock() {
+        Ok(sessions) => sessions,
+        Err(_) => return Vec::new(),
+    };
+
+    let mut left_behind = Vec::new();
+    for (session_id, session) in sessions.iter_mut() {
+        if session.workspace == new_dir {
+            continue;
+        }
+
+        let idle = session.at_prompt.load(Ordering::SeqCst) && !has_foreground_process(session);
+        if session.follow_workspace && idle {
+            let command = format!("cd {}\n", shell_quote(dir));
+            let written = session
+                .writer
+                .lock()
+                .map(|mut writer| {
+                    writer
+                        .write_all(command.as_bytes())
+                        .and_then(|_| writer.flush())
+                        .is_ok()
+                })
+                .unwrap_or(false);
+            if written {
+                session.workspace = new_dir.to_path_buf();
+                continue;
+            }
+        }
+        left_behind.push((session_id.clone(), session.follow_workspace));
+    }
+    left_behind
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A scratch directory under the system temp dir, removed on drop
+    struct TempDir(PathBuf);
+
+    impl TempDir {
+        fn new(name: &str) -> Self {
+            let dir = std::env::temp_dir()
+                .join(format!("openchamber-terminal-{}", uuid::Uuid::new_v4()))
+                .join(name);
+            std::fs::create_dir_all(&dir).unwrap();
+            Self(dir)
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            std::fs::remove_dir_all(self.0.parent().unwrap()).ok();
+        }
+    }
+
+    const PROMPT_MARK: &str = r"printf '\033]133;A\007'";
+
+    /// Run `script` under bash in a PTY, tracking prompt marks the way the reader
+    /// thread does. Returns the session id and the collected output.
+    fn scripted_shell(
+        state: &TerminalState,
+        script: &str,
+        workspace: &Path,
+        follow_workspace: bool,
+    ) -> (String, Arc<Mutex<String>>) {
+        let mut cmd = CommandBuilder::new("bash");
+        cmd.args(["--norc", "--noprofile", "-c", script]);
+        let size = PtySize {
+            rows: 24,
+            cols: 80,
+            pixel_width: 0,
+            pixel_height: 0,
+        };
+        let (session, mut reader) =
+            open_pty_session(cmd, size, workspace.to_path_buf(), follow_workspace).unwrap();
+
+        let output = Arc::new(Mutex::new(String::new()));
+        let collected = output.clone();
+        let at_prompt = session.at_prompt.clone();
+        thread::spawn(move || {
+            let mut buffer = [0u8; 4096];
+            let mut parser = OscParser::default();
+            let mut tracker = CommandTracker::default();
+            while let Ok(n) = reader.read(&mut buffer) {
+                if n == 0 {
+                    break;
+                }
+                let data = String::from_utf8_lossy(&buffer[..n]).to_string();
+                for mark in parser.feed(&data) {
+                    tracker.apply(mark);
+                }
+                at_prompt.store(tracker.at_prompt, Ordering::SeqCst);
+                collected.lock().unwrap().push_str(&data);
+            }
+        });
+
+        let session_id = uuid::Uuid::new_v4().to_string();
+        state
+            .sessions
+            .lock()
+            .unwrap()
+            .insert(session_id.clone(), session);
+        (session_id, output)
+    }
+
+    fn wait_until(what: &str, mut condition: impl FnMut() -> bool) {
+        let deadline = Instant::now() + Duration::from_secs(10);
+        while !condition() {
+            assert!(Instant::now() < deadline, "timed out waiting for {what}");
+            thread::sleep(Duration::from_millis(20));
+        }
+    }
+
+    fn at_prompt(state: &TerminalState, session_id: &str) -> bool {
+        state.sessions.lock().unwrap()[session_id]
+            .at_prompt
+            .load(Ordering::SeqCst)
+    }
+
+    fn workspace_of(state: &TerminalState, session_id: &str) -> PathBuf {
+        state.sessions.lock().unwrap()[session_id].workspace.clone()
+    }
+
+    fn kill_all(state: &TerminalState) {
+        for session in state.sessions.lock().unwrap().values() {
+            let _ = session.child.lock().unwrap().kill();
+        }
+    }
+
+    #[test]
+    fn paths_are_quoted_as_one_word() {
+        assert_eq!(shell_quote("/work/project"), "'/work/project'");
+        assert_eq!(shell_quote("/work/my project"), "'/work/my project'");
+        assert_eq!(shell_quote("/work/it's"), r"'/work/it'\''s'");
+    }
+
+    #[test]
+    fn marks_become_command_events() {
+        let mut tracker = CommandTracker::default();
+        assert_eq!(tracker.apply(ShellMark::PromptStart), None);
+        assert!(tracker.at_prompt);
+        assert_eq!(
+            tracker.apply(ShellMark::CommandLine("cargo test".to_string())),
+            None
+        );
+
+        assert_eq!(
+            tracker.apply(ShellMark::CommandStart),
+            Some(serde_json::json!({ "type": "command-started", "text": "cargo test" }))
+        );
+        assert!(!tracker.at_prompt);
+
+        let finished = tracker
+            .apply(ShellMark::CommandFinished(Some(101)))
+            .unwrap();
+        assert_eq!(finished["type"], "command-finished");
+        assert_eq!(finished["exitCode"], 101);
+        assert!(finished["durationMs"].is_u64());
+
+        // A finish without a start, e.g. for the prompt shown at startup
+        let finished = tracker.apply(ShellMark::CommandFinished(None)).unwrap();
+        assert!(finished["exitCode"].is_null());
+        assert!(finished["durationMs"].is_null());
+        assert_eq!(tracker.apply(ShellMark::CommandStart).unwrap()["text"], "");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn idle_following_terminals_cd_into_the_new_workspace() {
+        let old = TempDir::new("old");
+        let new = TempDir::new("it's a $project");
+        let state = TerminalState::new();
+        let script = format!(
+            r#"{PROMPT_MARK}; read -r line; eval "$line"; printf 'pwd=%s\n' "$PWD"; sleep 30"#
+        );
+        let (session_id, output) = scripted_shell(&state, &script, &old.0, true);
+        wait_until("the prompt", || at_prompt(&state, &session_id));
+
+        let dir = new.0.to_str().unwrap();
+        assert!(follow_workspace_change(&state, dir).is_empty());
+        assert_eq!(workspace_of(&state, &session_id), new.0);
+
+        let expected = format!("pwd={dir}");
+        wait_until("the cd", || output.lock().unwrap().contains(&expected));
+        kill_all(&state);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn terminals_not_at_a_prompt_are_left_alone() {
+        let old = TempDir::new("old");
+        let new = TempDir::new("new");
+        let state = TerminalState::new();
+        let (session_id, _) = scripted_shell(&state, "sleep 30", &old.0, true);
+
+        let left_behind = follow_workspace_change(&state, new.0.to_str().unwrap());
+        assert_eq!(left_behind, [(session_id.clone(), true)]);
+        assert_eq!(workspace_of(&state, &session_id), old.0);
+        kill_all(&state);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn terminals_running_a_command_are_left_alone() {
+        let old = TempDir::new("old");
+        let new = TempDir::new("new");
+        let state = TerminalState::new();
+        // Job control puts `sleep` in its own foreground process group; the
+        // trailing `exit` stops bash from exec'ing it in place of the shell
+        let script = format!("set -m; {PROMPT_MARK}; sleep 30; exit");
+        let (session_id, _) = scripted_shell(&state, &script, &old.0, true);
+        wait_until("the prompt", || at_prompt(&state, &session_id));
+        wait_until("sleep to start", || {
+            has_foreground_process(&state.sessions.lock().unwrap()[&session_id])
+        });
+
+        let left_behind = follow_workspace_change(&state, new.0.to_str().unwrap());
+        assert_eq!(left_behind, [(session_id.clone(), true)]);
+        assert_eq!(workspace_of(&state, &session_id), old.0);
+        kill_all(&state);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn only_terminals_in_another_workspace_that_opted_out_are_reported() {
+        let old = TempDir::new("old");
+        let new = TempDir::new("new");
+        let state = TerminalState::new();
+        let script = format!("{PROMPT_MARK}; sleep 30");
+        let (pinned, _) = scripted_shell(&state, &script, &old.0, false);
+        let (already_there, _) = scripted_shell(&state, &script, &new.0, true);
+        wait_until("the prompts", || {
+            at_prompt(&state, &pinned) && at_prompt(&state, &already_there)
+        });
+
+        let left_behind = follow_workspace_change(&state, new.0.to_str().unwrap());
+        assert_eq!(left_behind, [(pinned.clone(), false)]);
+        assert_eq!(workspace_of(&state, &pinned), old.0);
+        kill_all(&state);
+    }
+
+    fn foreground(state: &TerminalState, session_id: &str) -> TerminalForeground {
+        terminal_foreground(&state.sessions.lock().unwrap()[session_id])
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn a_command_in_the_foreground_is_reported_as_busy() {
+        let dir = TempDir::new("project");
+        let state = TerminalState::new();
+        let (session_id, _) = scripted_shell(&state, "set -m; sleep 30; exit", &dir.0, false);
+        // The forked job takes the terminal before it execs sleep
+        wait_until("sleep to start", || {
+            foreground(&state, &session_id).name.as_deref() == Some("sleep")
+        });
+
+        let foreground = foreground(&state, &session_id);
+        assert!(foreground.supported);
+        assert!(foreground.busy);
+        assert_ne!(
+            foreground.pid,
+            state.sessions.lock().unwrap()[&session_id].shell_pid
+        );
+        assert_eq!(foreground.name.as_deref(), Some("sleep"));
+        assert_eq!(foreground.command_line.as_deref(), Some("sleep 30"));
+        kill_all(&state);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn an_idle_shell_is_not_busy() {
+        let dir = TempDir::new("project");
+        let state = TerminalState::new();
+        let (session_id, _) = scripted_shell(&state, "read -r line; exit", &dir.0, false);
+        // The forked child owns the terminal briefly before it execs bash
+        wait_until("the shell to own the terminal", || {
+            foreground(&state, &session_id).name.as_deref() == Some("bash")
+        });
+
+        let foreground = foreground(&state, &session_id);
+        assert!(!foreground.busy);
+        assert_eq!(
+            foreground.pid,
+            state.sessions.lock().unwrap()[&session_id].shell_pid
+        );
+        assert_eq!(foreground.name.as_deref(), Some("bash"));
+        assert!(!has_foreground_process(
+            &state.sessions.lock().unwrap()[&session_id]
+        ));
+        kill_all(&state);
+    }
+}
@@ -0,0 +1,34 @@
+use tauri::State;
+
+use serde_json::Value;
+
+use crate::{
+    usage_budget::{BudgetSettings, BudgetStatus},
+    usage_tracker::{UsageSummary, UsageTotals},
+    DesktopRuntime,
+};
+
+#[tauri::command]
+pub fn get_usage_summary(
+    state: State<'_, DesktopRuntime>,
+    range: Option<String>,
+) -> Result<UsageSummary, String> {
+    state.usage().summary(range.as_deref().unwrap_or("all"))
+}
+
+#[tauri::command]
+pub fn get_session_usage(
+    state: State<'_, DesktopRuntime>,
+    session_id: String,
+) -> Result<Option<UsageTotals>, String> {
+    Ok(state.usage().session_usage(&session_id))
+}
+
+#[tauri::command]
+pub async fn get_budget_status(
+    state: State<'_, DesktopRuntime>,
+) -> Result<Vec<BudgetStatus>, String> {
+    let settings = state.settings().load().await.unwrap_or(Value::Null);
+    let budgets = BudgetSettings::from_settings(&settings);
+    Ok(state.usage().budget_status(&budgets))
+}
@@ -0,0 +1,603 @@
+use std::path::PathBuf;
+
+use anyhow::{anyhow, Result};
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use tokio::fs;
+
+use crate::paths;
+
+/// Event emitted at startup when a config mutation was interrupted
+pub const CONFIG_RECOVERY_EVENT: &str = "openchamber:config-recovery-needed";
+
+const JOURNAL_DIR_NAME: &str = "journal";
+const RECORD_EXTENSION: &str = "json";
+
+/// Default journal location inside the OpenChamber state directory
+pub fn default_journal_dir() -> PathBuf {
+    paths::state_dir()
+        .unwrap_or_else(|| std::env::temp_dir().join("openchamber"))
+        .join(JOURNAL_DIR_NAME)
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct JournaledFile {
+    pub path: PathBuf,
+    /// Pre-operation copy; None when the file did not exist yet
+    pub backup: Option<PathBuf>,
+}
+
+/// Intent record written before a multi-file config mutation starts
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct JournalRecord {
+    pub id: String,
+    pub operation: String,
+    pub entity: String,
+    pub started_at: String,
+    pub files: Vec<JournaledFile>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum FileState {
+    Unchanged,
+    Modified,
+    Created,
+    Deleted,
+}
+
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FileStatus {
+    pub path: String,
+    pub state: FileState,
+}
+
+/// A leftover record with the consistency of each touched file
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PendingRecovery {
+    pub id: String,
+    pub operation: String,
+    pub entity: String,
+    pub started_at: String,
+    pub files: Vec<FileStatus>,
+    /// Some files were written and others were not
+    pub partial: bool,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RecoveryAction {
+    Rollback,
+    Ignore,
+}
+
+impl RecoveryAction {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "rollback" => Some(Self::Rollback),
+            "ignore" => Some(Self::Ignore),
+            _ => None,
+        }
+    }
+}
+
+/// Journal of in-flight config mutations. A record (plus pre-operation backups)
+/// exists only while a mutation runs, so anything found at startup was interrupted.
+#[derive(Clone, Debug)]
+pub struct ConfigJournal {
+    dir: PathBuf,
+}
+
+impl ConfigJournal {
+    pub fn new(dir: PathBuf) -> Self {
+        Self { dir }
+    }
+
+    fn record_path(&self, id: &str) -> PathBuf {
+        self.dir.join(format!("{id}.{RECORD_EXTENSION}"))
+    }
+
+    fn backup_dir(&self, id: &str) -> PathBuf {
+        self.dir.join(id)
+    }
+
+    /// Back up `files` and write the intent record. The returned entry must be
+    /// finished once the mutation returns.
+    pub async fn begin(
+        &self,
+        operation: &str,
+        entity: &str,
+        files: &[PathBuf],
+    ) -> Result<JournalEntry> {
+        let id = uuid::Uuid::new_v4().to_string();
+        let backup_dir = self.backup_dir(&id);
+        fs::create_dir_all(&backup_dir).await?;
+
+        let mut journaled = Vec::with_capacity(files.len());
+        for (index, path) in files.iter().enumerate() {
+            let backup = if path.is_file() {
+                let backup = backup_dir.join(format!("{index}.bak"));
+                fs::copy(path, &backup).await?;
+                Some(backup)
+            } else {
+                None
+            };
+            journaled.push(JournaledFile {
+                path: path.clone(),
+                backup,
+            });
+        }
+
+        let record = JournalRecord {
+            id,
+            operation: operation.to_string(),
+            entity: entity.to_string(),
+            started_at: chrono::Utc::now().to_rfc3339(),
+            files: journaled,
+        };
+
+        // Written last and renamed into place so a record never references missing backups
+        let record_path = self.record_path(&record.id);
+        let temp_path = record_path.with_extension("tmp");
+        fs::write(&temp_path, serde_json::to_vec_pretty(&record)?).await?;
+        fs::rename(&temp_path, &record_path).await?;
+
+        Ok(JournalEntry {
+            journal: self.clone(),
+            record,
+        })
+    }
+
+    async fn remove(&self, id: &str) {
+        let _ = fs::remove_file(self.record_path(id)).await;
+        let _ = fs::remove_dir_all(self.backup_dir(id)).await;
+    }
+
+    async fn load(&self, id: &str) -> Result<JournalRecord> {
+        // Ids become file names, so only accept the ones begin() generates
+        uuid::Uuid::parse_str(id).map_err(|_| anyhow!("Invalid journal id"))?;
+        let content = fs::read(self.record_path(id))
+            .await
+            .map_err(|_| anyhow!("No pending config operation with id {}", id))?;
+        Ok(serde_json::from_slice(&content)?)
+    }
+
+    /// Leftover records with per-file consistency; unreadable records are dropped
+    pub async fn pending(&self) -> Vec<PendingRecovery> {
+        let mut entries = match fs::read_dir(&self.dir).await {
+            Ok(entries) => entries,
+            Err(_) => return Vec::new(),
+        };
+
+        let mut pending = Vec::new();
+        while let Ok(Some(entry)) = entries.next_entry().await {
+            let path = entry.path();
+            if path.extension().and_then(|ext| ext.to_str()) != Some(RECORD_EXTENSION) {
+                continue;
+            }
+            let record = match fs::read(&path)
+                .await
+                .ok()
+                .and_then(|content| serde_json::from_slice::<JournalRecord>(&content).ok())
+            {
+                Some(record) => record,
+                None => {
+                    warn!(
+                        "[desktop:config] Dropping unreadable journal record {}",
+                        path.display()
+                    );
+                    let _ = fs::remove_file(&path).await;
+                    continue;
+                }
+            };
+
+            let files = inspect(&record).await;
+            let changed = files
+                .iter()
+                .filter(|file| file.state != FileState::Unchanged)
+                .count();
+            if changed == 0 {
+                // Interrupted before anything was written; nothing to recover
+                self.remove(&record.id).await;
+                continue;
+            }
+
+            pending.push(PendingRecovery {
+                partial: changed < files.len(),
+                id: record.id,
+                operation: record.operation,
+                entity: record.entity,
+                started_at: record.started_at,
+                files,
+            });
+        }
+
+        pending.sort_by(|a, b| a.started_at.cmp(&b.started_at));
+        pending
+    }
+
+    /// Apply the user's choice for an interrupted operation
+    pub async fn resolve(&self, id: &str, action: RecoveryAction) -> Result<()> {
+        let record = self.load(id).await?;
+        if action == RecoveryAction::Rollback {
+            for file in &record.files {
+                restore(file).await?;
+            }
+            info!(
+                "[desktop:config] Rolled back interrupted {} of {}",
+                record.operation, record.entity
+            );
+        }
+        self.remove(id).await;
+        Ok(())
+    }
+}
+
+/// Handle for a running mutation
+pub struct JournalEntry {
+    journal: ConfigJournal,
+    record: JournalRecord,
+}
+
+impl JournalEntry {
+    /// Drop the record when the mutation succeeded or failed before writing anything.
+    /// A failure after partial writes keeps it for the startup recovery scan.
+    pub async fn finish<T>(self, result: &Result<T>) {
+        if result.is_ok() {
+            self.journal.remove(&self.record.id).await;
+            return;
+        }
+
+        let untouched = inspect(&self.record)
+            .await
+            .iter()
+            .all(|file| file.state == FileState::Unchanged);
+        if untouched {
+            self.journal.remove(&self.record.id).await;
+        } else {
+            warn!(
+                "[desktop:config] {} of {} failed part-way; journal {} kept for recovery",
+                self.record.operation, self.record.entity, self.record.id
+            );
+        }
+    }
+}
+
+async fn file_state(file: &JournaledFile) -> FileState {
+    let current = fs::read(&file.path).await.ok();
+    match (&file.backup, current) {
+        (None, None) => FileState::Unchanged,
+        (None, Some(_)) => FileState::Created,
+        (Some(_), None) => FileState::Deleted,
+        (Some(backup), Some(current)) => match fs::read(backup).await {
+            Ok(original) if original == current => FileState::Unchanged,
+            _ => FileState::Modified,
+        },
+    }
+}
+
+async fn inspect(record: &JournalRecord) -> Vec<FileStatus> {
+    let mut files = Vec::with_capacity(record.files.len());
+    for file in &record.files {
+        files.push(FileStatus {
+            path: file.path.to_string_lossy().to_string(),
+            state: file_state(file).await,
+        });
+    }
+    files
+}
+
+async fn restore(file: &JournaledFile) -> Result<()> {
+    match &file.backup {
+        Some(backup) => {
+            if let Some(parent) = file.path.parent() {
+                fs::create_dir_all(parent).await?;
+            }
+            fs::copy(backup, &file.path).await?;
+        }
+        None => {
+            if file.path.exists() {
+                fs::remove_file(&file.path).await?;
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Config files and a journal under the system temp dir, removed on drop
+    struct TempJournal {
+        root: PathBuf,
+        journal: ConfigJournal,
+    }
+
+    impl TempJournal {
+        fn new() -> Self {
+            let root =
+                std::env::temp_dir().join(format!("openchamber-journal-{}", uuid::Uuid::new_v4()));
+            std::fs::create_dir_all(root.join("config")).unwrap();
+            let journal = ConfigJournal::new(root.join("journal"));
+            Self { root, journal }
+        }
+
+        fn file(&self, name: &str) -> PathBuf {
+            self.root.join("config").join(name)
+        }
+    }
+
+    impl Drop for TempJournal {
+        fn drop(&mut self) {
+            std::fs::remove_dir_all(&self.root).ok();
+        }
+    }
+
+    fn read(path: &std::path::Path) -> Option<String> {
+        std::fs::read_to_string(path).ok()
+    }
+
+    /// Journal an update of `existing` and the creation of `created`, then
+    /// crash after the first write: the entry is never finished.
+    async fn crash_after_first_write(temp: &TempJournal) -> (PathBuf, PathBuf) {
+        let existing = temp.file("opencode.json");
+        let created = temp.file("agent.md");
+        std::fs::write(&existing, "original").unwrap();
+
+        let entry = temp
+            .journal
+            .begin(
+                "update",
+                "agent reviewer",
+                &[existing.clone(), created.clone()],
+            )
+            .await
+            .unwrap();
+        fs::write(&existing, "updated").await.unwrap();
+        drop(entry);
+
+        (existing, created)
+    }
+
+    #[test]
+    fn recovery_actions_are_parsed() {
+        assert_eq!(
+            RecoveryAction::parse("rollback"),
+            Some(RecoveryAction::Rollback)
+        );
+        assert_eq!(
+            RecoveryAction::parse("ignore"),
+            Some(RecoveryAction::Ignore)
+        );
+        assert_eq!(RecoveryAction::parse("Rollback"), None);
+        assert_eq!(RecoveryAction::parse(""), None);
+    }
+
+    #[tokio::test]
+    async fn finished_operations_leave_nothing_behind() {
+        let temp = TempJournal::new();
+        let path = temp.file("opencode.json");
+        std::fs::write(&path, "original").unwrap();
+
+        let entry = temp
+            .journal
+            .begin("update", "agent", std::slice::from_ref(&path))
+            .await
+            .unwrap();
+        fs::write(&path, "updated").await.unwrap();
+        entry.finish(&Ok(())).await;
+
+        assert!(temp.journal.pending().await.is_empty());
+        let leftovers = std::fs::read_dir(temp.root.join("journal")).unwrap();
+        assert_eq!(leftovers.count(), 0);
+    }
+
+    #[tokio::test]
+    async fn failures_before_any_write_are_forgotten() {
+        let temp = TempJournal::new();
+        let path = temp.file("opencode.json");
+        std::fs::write(&path, "original").unwrap();
+
+        let entry = temp
+            .journal
+            .begin("update", "agent", std::slice::from_ref(&path))
+            .await
+            .unwrap();
+        entry.finish::<()>(&Err(anyhow!("validation failed"))).await;
+
+        assert!(temp.journal.pending().await.is_empty());
+        assert_eq!(read(&path).as_deref(), Some("original"));
+    }
+
+    #[tokio::test]
+    async fn failures_after_a_partial_write_are_kept() {
+        let temp = TempJournal::new();
+        let first = temp.file("opencode.json");
+        let second = temp.file("agent.md");
+
+        let entry = temp
+            .journal
+            .begin("create", "agent", &[first.clone(), second.clone()])
+            .await
+            .unwrap();
+        fs::write(&first, "{}").await.unwrap();
+        entry.finish::<()>(&Err(anyhow!("disk full"))).await;
+
+        let pending = temp.journal.pending().await;
+        assert_eq!(pending.len(), 1);
+        assert!(pending[0].partial);
+    }
+
+    #[tokio::test]
+    async fn crashes_before_any_write_need_no_recovery() {
+        let temp = TempJournal::new();
+        let path = temp.file("opencode.json");
+        std::fs::write(&path, "original").unwrap();
+
+        drop(
+            temp.journal
+                .begin("delete", "agent", std::slice::from_ref(&path))
+                .await
+                .unwrap(),
+        );
+
+        assert!(temp.journal.pending().await.is_empty());
+        // The scan cleaned up after itself
+        let leftovers = std::fs::read_dir(temp.root.join("journal")).unwrap();
+        assert_eq!(leftovers.count(), 0);
+    }
+
+    #[tokio::test]
+    async fn crashes_mid_operation_are_detected() {
+        let temp = TempJournal::new();
+        let (existing, created) = crash_after_first_write(&temp).await;
+
+        let pending = temp.journal.pending().await;
+        assert_eq!(pending.len(), 1);
+        let recovery = &pending[0];
+        assert_eq!(recovery.operation, "update");
+        assert_eq!(recovery.entity, "agent reviewer");
+        assert!(recovery.partial);
+        let states: Vec<_> = recovery
+            .files
+            .iter()
+            .map(|file| (file.path.clone(), file.state))
+            .collect();
+        assert_eq!(
+            states,
+            [
+                (existing.to_string_lossy().to_string(), FileState::Modified),
+                (created.to_string_lossy().to_string(), FileState::Unchanged),
+            ]
+        );
+
+        // Still pending on the next startup until resolved
+        assert_eq!(temp.journal.pending().await.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn every_kind_of_change_is_recognised() {
+        let temp = TempJournal::new();
+        let modified = temp.file("modified");
+        let deleted = temp.file("deleted");
+        let created = temp.file("created");
+        std::fs::write(&modified, "a").unwrap();
+        std::fs::write(&deleted, "b").unwrap();
+
+        let entry = temp
+            .journal
+            .begin(
+                "update",
+                "command",
+                &[modified.clone(), deleted.clone(), created.clone()],
+            )
+            .await
+            .unwrap();
+        fs::write(&modified, "changed").await.unwrap();
+        fs::remove_file(&deleted).await.unwrap();
+        fs::write(&created, "new").await.unwrap();
+        drop(entry);
+
+        let pending = temp.journal.pending().await;
+        let states: Vec<_> = pending[0].files.iter().map(|file| file.state).collect();
+        assert_eq!(
+            states,
+            [FileState::Modified, FileState::Deleted, FileState::Created]
+        );
+        assert!(!pending[0].partial);
+    }
+
+    #[tokio::test]
+    async fn rollback_restores_every_file() {
+        let temp = TempJournal::new();
+        let existing = temp.file("opencode.json");
+        let created = temp.file("agent.md");
+        std::fs::write(&existing, "original").unwrap();
+
+        let entry = temp
+            .journal
+            .begin("update", "agent", &[existing.clone(), created.clone()])
+            .await
+            .unwrap();
+        fs::remove_file(&existing).await.unwrap();
+        fs::write(&created, "new").await.unwrap();
+        drop(entry);
+
+        let id = temp.journal.pending().await[0].id.clone();
+        temp.journal
+            .resolve(&id, RecoveryAction::Rollback)
+            .await
+            .unwrap();
+
+        assert_eq!(read(&existing).as_deref(), Some("original"));
+        assert!(!created.exists());
+        assert!(temp.journal.pending().await.is_empty());
+        assert!(temp
+            .journal
+            .resolve(&id, RecoveryAction::Rollback)
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn ignoring_keeps_the_files_and_clears_the_record() {
+        let temp = TempJournal::new();
+        let (existing, _) = crash_after_first_write(&temp).await;
+
+        let id = temp.journal.pending().await[0].id.clone();
+        temp.journal
+            .resolve(&id, RecoveryAction::Ignore)
+            .await
+            .unwrap();
+
+        assert_eq!(read(&existing).as_deref(), Some("updated"));
+        assert!(temp.journal.pending().await.is_empty());
+        assert!(!temp.root.join("journal").join(&id).exists());
+    }
+
+    #[tokio::test]
+    async fn only_journal_ids_can_be_resolved() {
+        let temp = TempJournal::new();
+        crash_after_first_write(&temp).await;
+
+        for id in ["../config/opencode", "", "not-a-uuid"] {
+            let error = temp
+                .journal
+                .resolve(id, RecoveryAction::Rollback)
+                .await
+                .unwrap_err();
+            assert_eq!(error.to_string(), "Invalid journal id");
+        }
+        let unknown = uuid::Uuid::new_v4().to_string();
+        assert!(temp
+            .journal
+            .resolve(&unknown, RecoveryAction::Ignore)
+            .await
+            .is_err());
+        assert_eq!(temp.journal.pending().await.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn unreadable_records_are_dropped() {
+        let temp = TempJournal::new();
+        crash_after_first_write(&temp).await;
+        let garbage = temp
+            .root
+            .join("journal")
+            .join(format!("{}.json", uuid::Uuid::new_v4()));
+        std::fs::write(&garbage, "{ truncated").unwrap();
+
+        assert_eq!(temp.journal.pending().await.len(), 1);
+        assert!(!garbage.exists());
+    }
+
+    #[tokio::test]
+    async fn a_missing_journal_has_nothing_pending() {
+        let temp = TempJournal::new();
+        assert!(temp.journal.pending().await.is_empty());
+    }
+}
@@ -0,0 +1,758 @@
+use std::{
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use futures_util::future::{BoxFuture, FutureExt, Shared};
+use log::{info, warn};
+use serde_json::json;
+use tauri::{AppHandle, Emitter};
+use tokio::{sync::Mutex, time::Instant};
+
+use crate::{
+    opencode_manager::OpenCodeManager,
+    reload_decision::ChangedEntity,
+    session_activity::{self, BusySession, SessionPhases, SessionPins},
+};
+
+/// How long a scheduled restart waits for further config changes to join it
+const RESTART_COALESCE_WINDOW: Duration = Duration::from_millis(300);
+
+/// Quiet time after the last agent or command mutation before OpenCode picks
+/// the changes up; each mutation inside it starts the wait again
+pub const REFRESH_DEBOUNCE_WINDOW: Duration = Duration::from_secs(2);
+
+/// Emitted with the new generation each time a config change is applied
+pub const CONFIG_GENERATION_EVENT: &str = "openchamber:config-generation";
+
+/// Emitted once a scheduled refresh ran, with how it was applied and whether
+/// the webview needs reloading for it
+pub const CONFIG_REFRESH_EVENT: &str = "openchamber:config-refresh";
+
+/// Counts config changes applied to the running OpenCode server. Changes that
+/// share one restart share one bump, so a client that compares its loaded
+/// generation with the current one reloads once per burst, not once per response.
+pub struct ConfigGeneration {
+    value: AtomicU64,
+    /// Where bumps are announced; absent in tests
+    app: Option<AppHandle>,
+}
+
+impl ConfigGeneration {
+    pub fn new(app: AppHandle) -> Self {
+        Self {
+            value: AtomicU64::new(0),
+            app: Some(app),
+        }
+    }
+
+    #[cfg(test)]
+    fn detached() -> Self {
+        Self {
+            value: AtomicU64::new(0),
+            app: None,
+        }
+    }
+
+    pub fn current(&self) -> u64 {
+        self.value.load(Ordering::SeqCst)
+    }
+
+    pub fn bump(&self, reason: &str) -> u64 {
+        let generation = self.value.fetch_add(1, Ordering::SeqCst) + 1;
+        info!(
+            "[desktop:config] Config generation {} after {}",
+            generation, reason
+        );
+        if let Some(app) = &self.app {
+            let _ = app.emit(
+                CONFIG_GENERATION_EVENT,
+                json!({ "generation": generation, "reason": reason }),
+            );
+        }
+        generation
+    }
+}
+
+/// Resolves to the config generation the restart produced
+type RestartFuture = Shared<BoxFuture<'static, Result<u64, String>>>;
+
+/// Restarts OpenCode for a reason, resolving to the config generation afterwards
+type RestartFn = Arc<dyn Fn(String) -> BoxFuture<'static, Result<u64, String>> + Send + Sync>;
+
+struct PendingRestart {
+    started: Arc<AtomicBool>,
+    future: RestartFuture,
+}
+
+/// Result of requesting a restart after a config change
+#[derive(Clone, Copy, Debug)]
+pub struct RestartOutcome {
+    /// True when this request joined a restart another request had already scheduled
+    pub coalesced: bool,
+    /// Config generation once the restart completed, the same for every joined request
+    pub generation: u64,
+}
+
+/// Coalesces OpenCode restarts triggered by config mutations. Requests that arrive
+/// while a restart is scheduled but not yet started await that same restart;
+/// requests arriving after it started schedule a fresh one so their change is picked up.
+pub struct ConfigRestartCoalescer {
+    restart: RestartFn,
+    pending: Mutex<Option<PendingRestart>>,
+}
+
+impl ConfigRestartCoalescer {
+    pub fn new(opencode: Arc<OpenCodeManager>, generation: Arc<ConfigGeneration>) -> Self {
+        Self::bumping(generation, move |reason| {
+            let opencode = opencode.clone();
+            async move {
+                opencode
+                    .restart_for(&reason)
+                    .await
+                    .map_err(|err| err.to_string())
+            }
+            .boxed()
+        })
+    }
+
+    /// Bumps `generation` once per completed `restart`, after it finished, so
+    /// every request sharing that restart sees the same new generation
+    fn bumping<F>(generation: Arc<ConfigGeneration>, restart: F) -> Self
+    where
+        F: Fn(String) -> BoxFuture<'static, Result<(), String>> + Send + Sync + 'static,
+    {
+        let restart = Arc::new(restart);
+        Self::with_restart(Arc::new(move |reason: String| {
+            let restart = restart.clone();
+            let generation = generation.clone();
+            async move {
+                restart(reason.clone()).await?;
+                Ok(generation.bump(&reason))
+            }
+            .boxed()
+        }))
+    }
+
+    fn with_restart(restart: RestartFn) -> Self {
+        Self {
+            restart,
+            pending: Mutex::new(None),
+        }
+    }
+
+    pub async fn request_restart(&self, reason: &str) -> Result<RestartOutcome, String> {
+        let (future, coalesced) = {
+            let mut pending = self.pending.lock().await;
+            match pending.as_ref() {
+                Some(existing) if !existing.started.load(Ordering::SeqCst) => {
+                    info!(
+                        "[desktop:config] Joining scheduled OpenCode restart for {}",
+                        reason
+                    );
+                    (existing.future.clone(), true)
+                }
+                _ => {
+                    let started = Arc::new(AtomicBool::new(false));
+                    let future = self.schedule(started.clone(), reason);
+                    *pending = Some(PendingRestart {
+                        started,
+                        future: future.clone(),
+                    });
+                    (future, false)
+                }
+            }
+        };
+
+        let result = future.clone().await;
+
+        {
+            let mut pending = self.pending.lock().await;
+            if pending
+                .as_ref()
+                .map(|existing| existing.future.ptr_eq(&future))
+                .unwrap_or(false)
+            {
+                *pending = None;
+            }
+        }
+
+        result.map(|generation| RestartOutcome {
+            coalesced,
+            generation,
+        })
+    }
+
+    fn schedule(&self, started: Arc<AtomicBool>, reason: &str) -> RestartFuture {
+        let restart = self.restart.clone();
+        let reason = reason.to_string();
+        // Run detached so the restart still happens if the requesting client disconnects
+        let handle = tauri::async_runtime::spawn(async move {
+            tokio::time::sleep(RESTART_COALESCE_WINDOW).await;
+            started.store(true, Ordering::SeqCst);
+            restart(reason).await
+        });
+
+        async move {
+            match handle.await {
+                Ok(result) => result,
+                Err(err) => Err(err.to_string()),
+            }
+        }
+        .boxed()
+        .shared()
+    }
+}
+
+struct PendingRefresh {
+    due: Instant,
+    /// Mutations waiting for the refresh, for the log and the refresh event
+    reasons: Vec<String>,
+    mutations: usize,
+    /// Entities those mutations touched
+    changed: Vec<ChangedEntity>,
+    /// Interrupt busy sessions only if every joined mutation asked to
+    overrides: BusyOverride,
+    applied: RefreshFuture,
+}
+
+impl PendingRefresh {
+    /// Nothing joined yet; the first mutation sets the overrides
+    fn new(due: Instant, applied: RefreshFuture) -> Self {
+        Self {
+            due,
+            reasons: Vec::new(),
+            mutations: 0,
+            changed: Vec::new(),
+            overrides: BusyOverride {
+                force: true,
+                override_pinned: true,
+            },
+            applied,
+        }
+    }
+
+    fn join(&mut self, reason: &str, changed: &[ChangedEntity], overrides: BusyOverride) {
+        self.mutations += 1;
+        self.overrides.force &= overrides.force;
+        self.overrides.override_pinned &= overrides.override_pinned;
+        if !self.reasons.iter().any(|queued| queued == reason) {
+            self.reasons.push(reason.to_string());
+        }
+        for entity in changed {
+            if !self.changed.contains(entity) {
+                self.changed.push(entity.clone());
+            }
+        }
+    }
+}
+
+/// `force` and `overridePinned` of a mutation: whether its refresh may
+/// interrupt busy sessions, and pinned ones among them
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct BusyOverride {
+    pub force: bool,
+    pub override_pinned: bool,
+}
+
+#[derive(Clone, Debug)]
+pub enum RefreshOutcome {
+    HotReloaded,
+    Restarted,
+    /// Sessions became busy while the refresh waited, so OpenCode was left
+    /// running with the old config
+    Skipped {
+        busy: Vec<BusySession>,
+    },
+}
+
+/// How a scheduled refresh was applied, once it ran
+#[derive(Clone, Debug)]
+pub struct AppliedRefresh {
+    pub reason: String,
+    /// Config generation the refresh produced; unchanged when it was skipped
+    pub generation: u64,
+    pub outcome: RefreshOutcome,
+    /// True when the refresh covered several mutations, or its restart joined
+    /// one that was already scheduled
+    pub coalesced: bool,
+    pub changed: Vec<ChangedEntity>,
+}
+
+/// Resolves once a scheduled refresh ran
+pub type RefreshFuture = Shared<BoxFuture<'static, Result<AppliedRefresh, String>>>;
+
+/// When a scheduled refresh will run
+#[derive(Clone)]
+pub struct ScheduledRefresh {
+    pub delay: Duration,
+    /// True when this mutation pushed back a refresh that was already scheduled
+    pub rearmed: bool,
+    /// The generation to wait for; the refresh lands at this one or a later one
+    pub generation: u64,
+    pub applied: RefreshFuture,
+}
+
+/// Debounces the OpenCode refresh after config mutations, so a burst of them,
+/// even one from a client stuck in a loop, costs a single reload or restart.
+/// Mutations return at once; the refresh runs in a detached task once they
+/// have been quiet for REFRESH_DEBOUNCE_WINDOW.
+pub struct ConfigRefreshScheduler {
+    opencode: Arc<OpenCodeManager>,
+    generation: Arc<ConfigGeneration>,
+    restarts: Arc<ConfigRestartCoalescer>,
+    /// Checked again right before restarting; sessions may have become busy
+    /// since the mutations were accepted
+    session_phases: SessionPhases,
+    session_pins: SessionPins,
+    pending: Arc<parking_lot::Mutex<Option<PendingRefresh>>>,
+}
+
+impl ConfigRefreshScheduler {
+    pub fn new(
+        opencode: Arc<OpenCodeManager>,
+        generation: Arc<ConfigGeneration>,
+        restarts: Arc<ConfigRestartCoalescer>,
+        session_phases: SessionPhases,
+        session_pins: SessionPins,
+    ) -> Self {
+        Self {
+            opencode,
+            generation,
+            restarts,
+            session_phases,
+            session_pins,
+            pending: Arc::new(parking_lot::Mutex::new(None)),
+        }
+    }
+
+    /// Schedule a refresh for `reason`, or push the scheduled one back
+    pub fn schedule(
+        &self,
+        reason: &str,
+        changed: &[ChangedEntity],
+        overrides: BusyOverride,
+    ) -> ScheduledRefresh {
+        let due = Instant::now() + REFRESH_DEBOUNCE_WINDOW;
+        let generation = self.generation.current() + 1;
+        let mut pending = self.pending.lock();
+        if let Some(existing) = pending.as_mut() {
+            existing.due = due;
+            existing.join(reason, changed, overrides);
+            info!(
+                "[desktop:config] Pushing back scheduled OpenCode refresh for {}",
+                reason
+            );
+            return ScheduledRefresh {
+                delay: REFRESH_DEBOUNCE_WINDOW,
+                rearmed: true,
+                generation,
+                applied: existing.applied.clone(),
+            };
+        }
+        info!(
+            "[desktop:config] Refreshing OpenCode in {}ms after {}",
+            REFRESH_DEBOUNCE_WINDOW.as_millis(),
+            reason
+        );
+
+        let opencode = self.opencode.clone();
+        let config_generation = self.generation.clone();
+        let restarts = self.restarts.clone();
+        let phases = self.session_phases.clone();
+        let pins = self.session_pins.clone();
+        let waiting = self.pending.clone();
+        // `pending` stays locked until the refresh is queued below, so the task
+        // cannot look for it before it is there
+        let handle = tauri::async_runtime::spawn(async move {
+            let queued = wait_until_quiet(&waiting)
+                .await
+                .ok_or_else(|| "scheduled refresh was dropped".to_string())?;
+            refresh(
+                &opencode,
+                &config_generation,
+                &restarts,
+                (&phases, &pins),
+                queued,
+            )
+            .await
+        });
+        let applied = async move {
+            match handle.await {
+                Ok(result) => result,
+                Err(err) => Err(err.to_string()),
+            }
+        }
+        .boxed()
+        .shared();
+
+        let mut queued = PendingRefresh::new(due, applied.clone());
+        queued.join(reason, changed, overrides);
+        *pending = Some(queued);
+        ScheduledRefresh {
+            delay: REFRESH_DEBOUNCE_WINDOW,
+            rearmed: false,
+            generation,
+            applied,
+        }
+    }
+}
+
+/// Sleep until the pending refresh is due, following it as later mutations
+/// push it back, then take it. Mutations after this schedule a new refresh.
+async fn wait_until_quiet(
+    pending: &parking_lot::Mutex<Option<PendingRefresh>>,
+) -> Option<PendingRefresh> {
+    loop {
+        let due = pending.lock().as_ref()?.due;
+        tokio::time::sleep_until(due).await;
+        let mut guard = pending.lock();
+        if guard.as_ref()?.due <= Instant::now() {
+            return guard.take();
+        }
+    }
+}
+
+/// Busy sessions a restart for `queued` would interrupt against its overrides
+async fn blocking_sessions(
+    queued: &PendingRefresh,
+    phases: &SessionPhases,
+    pins: &SessionPins,
+) -> Vec<BusySession> {
+    let busy = session_activity::busy_sessions(phases, pins).await;
+    let overrides = queued.overrides;
+    if session_activity::restart_blocked(&busy, overrides.force, overrides.override_pinned) {
+        busy
+    } else {
+        Vec::new()
+    }
+}
+
+/// Hot-reload OpenCode's config where it supports that, otherwise restart it
+/// unless that would interrupt sessions that are busy by then
+async fn refresh(
+    opencode: &OpenCodeManager,
+    generation: &ConfigGeneration,
+    restarts: &ConfigRestartCoalescer,
+    (phases, pins): (&SessionPhases, &SessionPins),
+    queued: PendingRefresh,
+) -> Result<AppliedRefresh, String> {
+    let reason = queued.reasons.join(", ");
+    let merged = queued.mutations > 1;
+    if opencode.supports_config_reload() {
+        match opencode.reload_config().await {
+            Ok(()) => {
+                return Ok(AppliedRefresh {
+                    generation: generation.bump(&reason),
+                    reason,
+                    outcome: RefreshOutcome::HotReloaded,
+                    coalesced: merged,
+                    changed: queued.changed,
+                })
+            }
+            Err(err) => warn!(
+                "[desktop:config] Hot reload failed after {}, falling back to restart: {}",
+                reason, err
+            ),
+        }
+    }
+    let busy = blocking_sessions(&queued, phases, pins).await;
+    if !busy.is_empty() {
+        warn!(
+            "[desktop:config] Not restarting OpenCode after {}: {} session(s) became busy",
+            reason,
+            busy.len()
+        );
+        return Ok(AppliedRefresh {
+            generation: generation.current(),
+            reason,
+            outcome: RefreshOutcome::Skipped { busy },
+            coalesced: merged,
+            changed: queued.changed,
+        });
+    }
+    match restarts.request_restart(&reason).await {
+        Ok(outcome) => Ok(AppliedRefresh {
+            generation: outcome.generation,
+            reason,
+            outcome: RefreshOutcome::Restarted,
+            coalesced: merged || outcome.coalesced,
+            changed: queued.changed,
+        }),
+        Err(err) => {
+            warn!(
+                "[desktop:config] Scheduled OpenCode restart after {} failed: {}",
+                reason, err
+            );
+            Err(err)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn queued(due: Instant) -> PendingRefresh {
+        PendingRefresh::new(due, futures_util::future::pending().boxed().shared())
+    }
+
+    const FORCED: BusyOverride = BusyOverride {
+        force: true,
+        override_pinned: false,
+    };
+
+    /// A coalescer whose restarts only count themselves
+    fn counting_coalescer() -> (ConfigRestartCoalescer, Arc<AtomicU64>) {
+        let restarts = Arc::new(AtomicU64::new(0));
+        let counter = restarts.clone();
+        let coalescer = ConfigRestartCoalescer::with_restart(Arc::new(move |_reason: String| {
+            let counter = counter.clone();
+            async move {
+                tokio::time::sleep(Duration::from_millis(50)).await;
+                Ok(counter.fetch_add(1, Ordering::SeqCst) + 1)
+            }
+            .boxed()
+        }));
+        (coalescer, restarts)
+    }
+
+    /// A coalescer bumping `generation` after restarts that take 50ms and
+    /// succeed unless `fail` is set, with the number of restarts run
+    fn bumping_coalescer(
+        generation: Arc<ConfigGeneration>,
+        fail: Arc<AtomicBool>,
+    ) -> (ConfigRestartCoalescer, Arc<AtomicU64>) {
+        let restarts = Arc::new(AtomicU64::new(0));
+        let counter = restarts.clone();
+        let coalescer = ConfigRestartCoalescer::bumping(generation, move |_reason| {
+            let counter = counter.clone();
+            let fail = fail.clone();
+            async move {
+                tokio::time::sleep(Duration::from_millis(50)).await;
+                counter.fetch_add(1, Ordering::SeqCst);
+                if fail.load(Ordering::SeqCst) {
+                    return Err("opencode did not come back".to_string());
+                }
+                Ok(())
+            }
+            .boxed()
+        });
+        (coalescer, restarts)
+    }
+
+    #[tokio::test]
+    async fn concurrent_requests_share_one_restart() {
+        let (coalescer, restarts) = counting_coalescer();
+        let (first, second) = tokio::join!(
+            coalescer.request_restart("agent update"),
+            coalescer.request_restart("command update"),
+        );
+        let (first, second) = (first.unwrap(), second.unwrap());
+
+        assert_eq!(restarts.load(Ordering::SeqCst), 1);
+        assert_eq!(first.generation, second.generation);
+        assert!(!first.coalesced);
+        assert!(second.coalesced);
+    }
+
+    #[tokio::test]
+    async fn requests_after_a_restart_started_get_their_own() {
+        let (coalescer, restarts) = counting_coalescer();
+        let coalescer = Arc::new(coalescer);
+        let first = {
+            let coalescer = coalescer.clone();
+            tokio::spawn(async move { coalescer.request_restart("agent update").await })
+        };
+        tokio::time::sleep(RESTART_COALESCE_WINDOW + Duration::from_millis(20)).await;
+        let second = coalescer.request_restart("agent update").await.unwrap();
+        let first = first.await.unwrap().unwrap();
+
+        assert_eq!(restarts.load(Ordering::SeqCst), 2);
+        assert!(!second.coalesced);
+        assert!(second.generation > first.generation);
+    }
+
+    #[test]
+    fn joined_mutations_keep_every_entity_once() {
+        let mut pending = queued(Instant::now());
+        pending.join("agent update", &[ChangedEntity::agent("build")], FORCED);
+        pending.join("agent update", &[ChangedEntity::agent("build")], FORCED);
+        pending.join(
+            "command creation",
+            &[ChangedEntity::command("test")],
+            FORCED,
+        );
+
+        assert_eq!(pending.mutations, 3);
+        assert_eq!(pending.reasons, ["agent update", "command creation"]);
+        assert_eq!(
+            pending.changed,
+            [
+                ChangedEntity::agent("build"),
+                ChangedEntity::command("test")
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn wait_until_quiet_follows_a_pushed_back_refresh() {
+        let start = Instant::now();
+        let pending = Arc::new(parking_lot::Mutex::new(Some(queued(
+            start + Duration::from_millis(50),
+        ))));
+        let waiter = {
+            let pending = pending.clone();
+            tokio::spawn(async move { wait_until_quiet(&pending).await.map(|_| Instant::now()) })
+        };
+
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        if let Some(existing) = pending.lock().as_mut() {
+            existing.due = start + Duration::from_millis(150);
+        }
+
+        let taken_at = waiter.await.unwrap().expect("refresh was taken");
+        assert!(taken_at >= start + Duration::from_millis(150));
+        assert!(pending.lock().is_none());
+    }
+
+    #[tokio::test]
+    async fn wait_until_quiet_gives_up_when_nothing_is_pending() {
+        let pending = parking_lot::Mutex::new(None);
+        assert!(wait_until_quiet(&pending).await.is_none());
+    }
+
+    #[test]
+    fn joined_mutations_force_only_when_all_of_them_did() {
+        let mut pending = queued(Instant::now());
+        pending.join("agent update", &[], FORCED);
+        assert_eq!(pending.overrides, FORCED);
+
+        pending.join("command update", &[], BusyOverride::default());
+        pending.join("agent update", &[], FORCED);
+        assert_eq!(pending.overrides, BusyOverride::default());
+    }
+
+    #[tokio::test]
+    async fn sessions_busy_by_refresh_time_block_it_unless_overridden() {
+        let phases: SessionPhases = Default::default();
+        let pins: SessionPins = Default::default();
+        let mut pending = queued(Instant::now());
+        pending.join("agent update", &[], BusyOverride::default());
+        assert!(blocking_sessions(&pending, &phases, &pins).await.is_empty());
+
+        // A session starts working during the debounce window
+        phases
+            .lock()
+            .await
+            .insert("ses_a".to_string(), session_activity::ActivityPhase::Busy);
+        let busy = blocking_sessions(&pending, &phases, &pins).await;
+        assert_eq!(busy.len(), 1);
+        assert_eq!(busy[0].session_id, "ses_a");
+
+        let mut forced = queued(Instant::now());
+        forced.join("agent update", &[], FORCED);
+        assert!(blocking_sessions(&forced, &phases, &pins).await.is_empty());
+
+        pins.write().insert("ses_a".to_string());
+        assert_eq!(blocking_sessions(&forced, &phases, &pins).await.len(), 1);
+        forced.overrides.override_pinned = true;
+        assert!(blocking_sessions(&forced, &phases, &pins).await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn a_burst_of_mutations_bumps_the_generation_once() {
+        let generation = Arc::new(ConfigGeneration::detached());
+        let (coalescer, restarts) =
+            bumping_coalescer(generation.clone(), Arc::new(AtomicBool::new(false)));
+        let coalescer = Arc::new(coalescer);
+
+        let requests: Vec<_> = (0..8)
+            .map(|index| {
+                let coalescer = coalescer.clone();
+                tokio::spawn(async move {
+                    // Spread the burst over part of the coalescing window
+                    tokio::time::sleep(Duration::from_millis(index * 10)).await;
+                    coalescer.request_restart(&format!("agent {index}")).await
+                })
+            })
+            .collect();
+        let mut outcomes = Vec::new();
+        for request in requests {
+            outcomes.push(request.await.unwrap().unwrap());
+        }
+
+        assert_eq!(restarts.load(Ordering::SeqCst), 1);
+        assert_eq!(generation.current(), 1);
+        assert!(outcomes.iter().all(|outcome| outcome.generation == 1));
+        assert_eq!(
+            outcomes.iter().filter(|outcome| !outcome.coalesced).count(),
+            1
+        );
+    }
+
+    #[tokio::test]
+    async fn each_burst_gets_the_next_generation() {
+        let generation = Arc::new(ConfigGeneration::detached());
+        let (coalescer, restarts) =
+            bumping_coalescer(generation.clone(), Arc::new(AtomicBool::new(false)));
+
+        for expected in 1..=3 {
+            let (first, second) = tokio::join!(
+                coalescer.request_restart("agent update"),
+                coalescer.request_restart("command update"),
+            );
+            assert_eq!(first.unwrap().generation, expected);
+            assert_eq!(second.unwrap().generation, expected);
+        }
+        assert_eq!(restarts.load(Ordering::SeqCst), 3);
+        assert_eq!(generation.current(), 3);
+    }
+
+    #[tokio::test]
+    async fn a_mutation_during_the_restart_waits_for_the_next_generation() {
+        let generation = Arc::new(ConfigGeneration::detached());
+        let (coalescer, _) =
+            bumping_coalescer(generation.clone(), Arc::new(AtomicBool::new(false)));
+        let coalescer = Arc::new(coalescer);
+
+        let first = {
+            let coalescer = coalescer.clone();
+            tokio::spawn(async move { coalescer.request_restart("agent update").await })
+        };
+        // The restart has started but not finished; the generation is still old
+        tokio::time::sleep(RESTART_COALESCE_WINDOW + Duration::from_millis(20)).await;
+        assert_eq!(generation.current(), 0);
+
+        let second = coalescer.request_restart("command update").await.unwrap();
+        let first = first.await.unwrap().unwrap();
+        assert_eq!(first.generation, 1);
+        assert_eq!(second.generation, 2);
+        assert!(!second.coalesced);
+    }
+
+    #[tokio::test]
+    async fn failed_restarts_leave_the_generation_alone() {
+        let generation = Arc::new(ConfigGeneration::detached());
+        let fail = Arc::new(AtomicBool::new(true));
+        let (coalescer, restarts) = bumping_coalescer(generation.clone(), fail.clone());
+
+        let (first, second) = tokio::join!(
+            coalescer.request_restart("agent update"),
+            coalescer.request_restart("command update"),
+        );
+        assert_eq!(first.unwrap_err(), "opencode did not come back");
+        assert_eq!(second.unwrap_err(), "opencode did not come back");
+        assert_eq!(restarts.load(Ordering::SeqCst), 1);
+        assert_eq!(generation.current(), 0);
+
+        // The failed restart is not reused by the next request
+        fail.store(false, Ordering::SeqCst);
+        let retried = coalescer.request_restart("agent update").await.unwrap();
+        assert_eq!(retried.generation, 1);
+        assert!(!retried.coalesced);
+    }
+}
@@ -0,0 +1,615 @@
+use std::{
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use anyhow::{anyhow, Result};
+use log::{info, warn};
+use serde::Deserialize;
+use tokio::process::Command;
+
+use crate::{environment_report::find_on_path, opencode_client::MessageWithParts};
+
+/// Error code the UI maps to "export as HTML instead"
+pub const PDF_UNSUPPORTED: &str = "PDF_UNSUPPORTED";
+
+const PDF_RENDER_TIMEOUT: Duration = Duration::from_secs(90);
+
+/// Print stylesheet; the browser paginates, these rules keep headers with
+/// their content and let long code blocks and hunks break across pages
+const DOCUMENT_CSS: &str = r#"
+@page { size: A4; margin: 16mm 14mm; }
+body { font: 11pt/1.5 -apple-system, "Segoe UI", Helvetica, Arial, sans-serif; color: #1f2328; margin: 0; }
+header { border-bottom: 1px solid #d0d7de; margin-bottom: 16px; padding-bottom: 8px; }
+header h1 { font-size: 18pt; margin: 0 0 4px; }
+header p { color: #59636e; margin: 0; font-size: 9pt; }
+h2, h3, h4 { break-after: avoid-page; }
+pre, code { font: 9pt/1.45 ui-monospace, SFMono-Regular, Menlo, Consolas, monospace; }
+pre { background: #f6f8fa; border-radius: 6px; padding: 8px 10px; white-space: pre-wrap; word-break: break-word; }
+code { background: #eff1f3; border-radius: 4px; padding: 0 3px; }
+pre code { background: none; padding: 0; }
+.message { margin-bottom: 18px; }
+.message h2 { font-size: 11pt; text-transform: uppercase; letter-spacing: 0.04em; color: #59636e; margin: 0 0 6px; }
+.message.user { border-left: 3px solid #0969da; padding-left: 10px; }
+.file { font: 600 10pt ui-monospace, SFMono-Regular, Menlo, Consolas, monospace; background: #f6f8fa; border: 1px solid #d0d7de; border-radius: 6px 6px 0 0; padding: 6px 10px; margin: 18px 0 0; }
+.hunk { border: 1px solid #d0d7de; border-top: 0; margin: 0; padding: 0; border-radius: 0; background: #fff; }
+.hunk span { display: block; padding: 0 10px; }
+.hunk .meta { color: #59636e; }
+.hunk .range { background: #ddf4ff; color: #0550ae; }
+.hunk .add { background: #dafbe1; color: #116329; }
+.hunk .del { background: #ffebe9; color: #82071e; }
+.hunk .note { color: #59636e; font-style: italic; }
+.empty { color: #59636e; font-style: italic; }
+"#;
+
+/// What an export renders
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ExportKind {
+    /// A session transcript; the target is the session id
+    Session,
+    /// Uncommitted changes; the target is a workspace-relative path, empty for all
+    Diff,
+}
+
+impl ExportKind {
+    pub fn label(self) -> &'static str {
+        match self {
+            Self::Session => "session",
+            Self::Diff => "diff",
+        }
+    }
+}
+
+pub fn escape_html(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for ch in text.chars() {
+        match ch {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            _ => escaped.push(ch),
+        }
+    }
+    escaped
+}
+
+/// Standalone HTML page around an already rendered `body`
+pub fn render_document(title: &str, subtitle: &str, body: &str) -> String {
+    format!(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{title}</title>\n<style>{css}</style>\n</head>\n<body>\n<header><h1>{title}</h1><p>{subtitle}</p></header>\n{body}\n</body>\n</html>\n",
+        title = escape_html(title),
+        subtitle = escape_html(subtitle),
+        css = DOCUMENT_CSS,
+        body = body,
+    )
+}
+
+/// `code`, **bold** and plain text of one line of Markdown
+fn render_inline(text: &str) -> String {
+    let mut html = String::new();
+    for (index, segment) in text.split('`').enumerate() {
+        if index % 2 == 1 {
+            html.push_str(&format!("<code>{}</code>", escape_html(segment)));
+            continue;
+        }
+        for (index, part) in escape_html(segment).split("**").enumerate() {
+            if index % 2 == 1 {
+                html.push_str(&format!("<strong>{part}</strong>"));
+            } else {
+                html.push_str(part);
+            }
+        }
+    }
+    html
+}
+
+/// Small Markdown subset for transcripts: fenced code, headings, bullet
+/// lists and paragraphs. Anything else is shown as text.
+pub fn render_markdown(markdown: &str) -> String {
+    let mut html = String::new();
+    let mut paragraph: Vec<&str> = Vec::new();
+    let mut in_list = false;
+    let mut code: Option<Vec<&str>> = None;
+
+    fn flush_paragraph(html: &mut String, paragraph: &mut Vec<&str>) {
+        if !paragraph.is_empty() {
+            let lines: Vec<String> = paragraph.iter().map(|line| render_inline(line)).collect();
+            html.push_str(&format!("<p>{}</p>\n", lines.join("<br>")));
+            paragraph.clear();
+        }
+    }
+    fn close_list(html: &mut String, in_list: &mut bool) {
+        if *in_list {
+            html.push_str("</ul>\n");
+            *in_list = false;
+        }
+    }
+
+    for line in markdown.lines() {
+        if let Some(lines) = code.as_mut() {
+            if line.trim_start().starts_with("```") {
+                html.push_str(&format!(
+                    "<pre><code>{}</code></pre>\n",
+                    escape_html(&lines.join("\n"))
+                ));
+                code = None;
+            } else {
+                lines.push(line);
+            }
+            continue;
+        }
+
+        let trimmed = line.trim();
+        if trimmed.starts_with("```") {
+            flush_paragraph(&mut html, &mut paragraph);
+            close_list(&mut html, &mut in_list);
+            code = Some(Vec::new());
+        } else if trimmed.is_empty() {
+            flush_paragraph(&mut html, &mut paragraph);
+            close_list(&mut html, &mut in_list);
+        } else if let Some(item) = trimmed
+            .strip_prefix("- ")
+            .or_else(|| trimmed.strip_prefix("* "))
+        {
+            flush_paragraph(&mut html, &mut paragraph);
+            if !in_list {
+                html.push_str("<ul>\n");
+                in_list = true;
+            }
+            html.push_str(&format!("<li>{}</li>\n", render_inline(item)));
+        } else if trimmed.starts_with('#') {
+            flush_paragraph(&mut html, &mut paragraph);
+            close_list(&mut html, &mut in_list);
+            let level = trimmed.chars().take_while(|ch| *ch == '#').count();
+            // Message headers are h2, so transcript headings start below them
+            let tag = (level + 2).min(6);
+            html.push_str(&format!(
+                "<h{tag}>{}</h{tag}>\n",
+                render_inline(trimmed[level..].trim())
+            ));
+        } else {
+            close_list(&mut html, &mut in_list);
+            paragraph.push(trimmed);
+        }
+    }
+    // An unterminated fence still shows its code
+    if let Some(lines) = code {
+        html.push_str(&format!(
+            "<pre><code>{}</code></pre>\n",
+            escape_html(&lines.join("\n"))
+        ));
+    }
+    flush_paragraph(&mut html, &mut paragraph);
+    close_list(&mut html, &mut in_list);
+    html
+}
+
+/// Transcript body: one section per message with text parts as Markdown
+pub fn render_session(messages: &[MessageWithParts]) -> String {
+    let mut html = String::new();
+    for message in messages {
+        let text = message.text();
+        if text.trim().is_empty() {
+            continue;
+        }
+        let role = message.info.role.as_deref().unwrap_or("message");
+        let heading = match (role, message.info.mode.as_deref()) {
+            ("assistant", Some(agent)) => format!("assistant · {agent}"),
+            _ => role.to_string(),
+        };
+        html.push_str(&format!(
+            "<section class=\"message {}\">\n<h2>{}</h2>\n{}</section>\n",
+            escape_html(role),
+            escape_html(&heading),
+            render_markdown(&text)
+        ));
+    }
+    if html.is_empty() {
+        html.push_str("<p class=\"empty\">This session has no messages.</p>\n");
+    }
+    html
+}
+
+/// Unified diff body: a header per file and coloured hunks
+pub fn render_diff(diff: &str) -> String {
+    let mut html = String::new();
+    let mut in_hunk = false;
+
+    let close_hunk = |html: &mut String, in_hunk: &mut bool| {
+        if *in_hunk {
+            html.push_str("</pre>\n");
+            *in_hunk = false;
+        }
+    };
+
+    for line in diff.lines() {
+        if let Some(files) = line.strip_prefix("diff --git ") {
+            close_hunk(&mut html, &mut in_hunk);
+            let name = files
+                .rsplit_once(" b/")
+                .map(|(_, name)| name)
+                .unwrap_or(files);
+            html.push_str(&format!("<h2 class=\"file\">{}</h2>\n", escape_html(name)));
+            continue;
+        }
+        if !in_hunk {
+            html.push_str("<pre class=\"hunk\">");
+            in_hunk = true;
+        }
+        let class = if line.starts_with("@@") {
+            "range"
+        } else if line.starts_with("+++")
+            || line.starts_with("---")
+            || line.starts_with("index ")
+            || line.starts_with("new file")
+            || line.starts_with("deleted file")
+            || line.starts_with("similarity")
+            || line.starts_with("rename ")
+            || line.starts_with("Binary files")
+        {
+            "meta"
+        } else if line.starts_with('+') {
+            "add"
+        } else if line.starts_with('-') {
+            "del"
+        } else if line.starts_with('\\') {
+            "note"
+        } else {
+            "ctx"
+        };
+        let text = if line.is_empty() { " " } else { line };
+        html.push_str(&format!(
+            "<span class=\"{class}\">{}</span>",
+            escape_html(text)
+        ));
+    }
+    close_hunk(&mut html, &mut in_hunk);
+    if html.is_empty() {
+        html.push_str("<p class=\"empty\">No changes.</p>\n");
+    }
+    html
+}
+
+/// Chromium-family browser used to print HTML to PDF: OPENCHAMBER_PDF_BROWSER,
+/// then the usual install locations, then PATH
+fn find_pdf_browser() -> Option<PathBuf> {
+    if let Some(path) = std::env::var_os("OPENCHAMBER_PDF_BROWSER").map(PathBuf::from) {
+        if path.is_file() {
+            return Some(path);
+        }
+        warn!(
+            "[desktop:export] OPENCHAMBER_PDF_BROWSER does not exist: {}",
+            path.display()
+        );
+    }
+
+    let installed: &[&str] = if cfg!(target_os = "macos") {
+        &[
+            "/Applications/Google Chrome.app/Contents/MacOS/Google Chrome",
+            "/Applications/Microsoft Edge.app/Contents/MacOS/Microsoft Edge",
+            "/Applications/Chromium.app/Contents/MacOS/Chromium",
+            "/Applications/Brave Browser.app/Contents/MacOS/Brave Browser",
+        ]
+    } else if cfg!(windows) {
+        &[
+            r"C:\Program Files (x86)\Microsoft\Edge\Application\msedge.exe",
+            r"C:\Program Files\Microsoft\Edge\Application\msedge.exe",
+            r"C:\Program Files\Google\Chrome\Application\chrome.exe",
+            r"C:\Program Files (x86)\Google\Chrome\Application\chrome.exe",
+        ]
+    } else {
+        &[]
+    };
+    if let Some(path) = installed
+        .iter()
+        .map(PathBuf::from)
+        .find(|path| path.is_file())
+    {
+        return Some(path);
+    }
+
+    [
+        "google-chrome",
+        "google-chrome-stable",
+        "chromium",
+        "chromium-browser",
+        "microsoft-edge",
+        "msedge",
+        "chrome",
+    ]
+    .iter()
+    .find_map(|name| find_on_path(name))
+}
+
+/// Print the HTML file at `html` to `output` with a headless browser, which
+/// paginates long documents. Fails with PDF_UNSUPPORTED when none is available.
+pub async fn print_to_pdf(html: &Path, output: &Path) -> Result<()> {
+    let Some(browser) = find_pdf_browser() else {
+        return Err(anyhow!(
+            "{}: no Chromium-based browser found to render PDFs; export as HTML instead",
+            PDF_UNSUPPORTED
+        ));
+    };
+    print_with(&browser, html, output).await
+}
+
+async fn print_with(browser: &Path, html: &Path, output: &Path) -> Result<()> {
+    let url = reqwest::Url::from_file_path(html)
+        .map_err(|_| anyhow!("Cannot build a file URL for {}", html.display()))?;
+    let profile = std::env::temp_dir().join(format!("openchamber-pdf-{}", uuid::Uuid::new_v4()));
+
+    info!(
+        "[desktop:export] Printing {} with {}",
+        html.display(),
+        browser.display()
+    );
+    let mut command = Command::new(browser);
+    command
+        .arg("--headless=new")
+        .arg("--disable-gpu")
+        .arg("--no-first-run")
+        .arg("--no-pdf-header-footer")
+        .arg("--print-to-pdf-no-header")
+        .arg(format!("--user-data-dir={}", profile.display()))
+        .arg(format!("--print-to-pdf={}", output.display()))
+        .arg(url.as_str())
+        .kill_on_drop(true);
+    let result = tokio::time::timeout(PDF_RENDER_TIMEOUT, command.output()).await;
+    let _ = tokio::fs::remove_dir_all(&profile).await;
+
+    let output_status = match result {
+        Ok(Ok(status)) => status,
+        Ok(Err(err)) => {
+            return Err(anyhow!(
+                "{}: failed to run {}: {}",
+                PDF_UNSUPPORTED,
+                browser.display(),
+                err
+            ))
+        }
+        Err(_) => return Err(anyhow!("PDF rendering timed out")),
+    };
+    if !output_status.status.success() || !output.is_file() {
+        let stderr = String::from_utf8_lossy(&output_status.stderr);
+        return Err(anyhow!(
+            "PDF rendering failed: {}",
+            stderr.lines().last().unwrap_or("no output")
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn message(
+        role: Option<&str>,
+        mode: Option<&str>,
+        parts: serde_json::Value,
+    ) -> MessageWithParts {
+        serde_json::from_value(json!({
+            "info": { "id": "msg", "role": role, "mode": mode },
+            "parts": parts,
+        }))
+        .unwrap()
+    }
+
+    /// A directory holding stand-in browsers, removed on drop
+    struct TempDir(PathBuf);
+
+    impl TempDir {
+        fn new() -> Self {
+            let dir =
+                std::env::temp_dir().join(format!("openchamber-export-{}", uuid::Uuid::new_v4()));
+            std::fs::create_dir_all(&dir).unwrap();
+            Self(dir)
+        }
+
+        #[cfg(unix)]
+        fn browser(&self, script: &str) -> PathBuf {
+            use std::os::unix::fs::PermissionsExt;
+
+            let path = self.0.join("browser");
+            std::fs::write(&path, format!("#!/bin/sh\n{script}")).unwrap();
+            std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755)).unwrap();
+            path
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.0);
+        }
+    }
+
+    #[test]
+    fn html_is_escaped() {
+        assert_eq!(
+            escape_html(r#"<a href="x">Tom & 'Jerry'</a>"#),
+            "&lt;a href=&quot;x&quot;&gt;Tom &amp; &#39;Jerry&#39;&lt;/a&gt;"
+        );
+        assert_eq!(escape_html("plain ünïcode"), "plain ünïcode");
+    }
+
+    #[test]
+    fn documents_wrap_the_body_with_escaped_titles() {
+        let html = render_document("<Review> & notes", "main · today", "<p>body</p>");
+        assert!(html.starts_with("<!DOCTYPE html>\n"));
+        assert!(html.contains("<title>&lt;Review&gt; &amp; notes</title>"));
+        assert!(html.contains(
+            "<header><h1>&lt;Review&gt; &amp; notes</h1><p>main · today</p></header>\n<p>body</p>\n</body>"
+        ));
+        assert!(html.contains("<meta charset=\"utf-8\">"));
+        assert!(html.contains("@page { size: A4;"));
+    }
+
+    #[test]
+    fn markdown_blocks_render() {
+        let markdown = "# Plan\n\nRun `cargo <test>` **now**\nsecond line\n- one\n* two\n\n```rust\nlet a = 1 < 2;\n\n```\nafter";
+        assert_eq!(
+            render_markdown(markdown),
+            "<h3>Plan</h3>\n\
+             <p>Run <code>cargo &lt;test&gt;</code> <strong>now</strong><br>second line</p>\n\
+             <ul>\n<li>one</li>\n<li>two</li>\n</ul>\n\
+             <pre><code>let a = 1 &lt; 2;\n</code></pre>\n\
+             <p>after</p>\n"
+        );
+    }
+
+    #[test]
+    fn markdown_edge_cases_render() {
+        assert_eq!(render_markdown("###### deep"), "<h6>deep</h6>\n");
+        assert_eq!(
+            render_markdown("- item\ntext"),
+            "<ul>\n<li>item</li>\n</ul>\n<p>text</p>\n"
+        );
+        assert_eq!(
+            render_markdown("`a**b` and **<b>**"),
+            "<p><code>a**b</code> and <strong>&lt;b&gt;</strong></p>\n"
+        );
+        // An unterminated fence keeps its code instead of dropping it
+        assert_eq!(
+            render_markdown("text\n```\nfn main() {}"),
+            "<p>text</p>\n<pre><code>fn main() {}</code></pre>\n"
+        );
+        assert_eq!(
+            render_markdown("<script>alert(1)</script>"),
+            "<p>&lt;script&gt;alert(1)&lt;/script&gt;</p>\n"
+        );
+        assert_eq!(render_markdown(""), "");
+    }
+
+    #[test]
+    fn sessions_render_one_section_per_message() {
+        let messages = vec![
+            message(
+                Some("user"),
+                None,
+                json!([{ "type": "text", "text": "Fix the **bug**" }]),
+            ),
+            message(
+                Some("assistant"),
+                Some("build"),
+                json!([
+                    { "type": "text", "text": "Done." },
+                    { "type": "tool", "tool": "edit" },
+                    { "type": "text", "text": "- a.rs" },
+                ]),
+            ),
+            message(Some("assistant"), None, json!([{ "type": "tool" }])),
+            message(None, None, json!([{ "type": "text", "text": "untyped" }])),
+        ];
+        assert_eq!(
+            render_session(&messages),
+            "<section class=\"message user\">\n<h2>user</h2>\n<p>Fix the <strong>bug</strong></p>\n</section>\n\
+             <section class=\"message assistant\">\n<h2>assistant · build</h2>\n<p>Done.</p>\n<ul>\n<li>a.rs</li>\n</ul>\n</section>\n\
+             <section class=\"message message\">\n<h2>message</h2>\n<p>untyped</p>\n</section>\n"
+        );
+        assert_eq!(
+            render_session(&messages[2..3]),
+            "<p class=\"empty\">This session has no messages.</p>\n"
+        );
+    }
+
+    #[test]
+    fn diffs_render_files_and_classified_lines() {
+        let diff = "diff --git a/src/a.rs b/src/a.rs\n\
+                    index 1111111..2222222 100644\n\
+                    --- a/src/a.rs\n\
+                    +++ b/src/a.rs\n\
+                    @@ -1,3 +1,3 @@\n \
+                    keep\n\
+                    -old <x>\n\
+                    +new & y\n\
+                    \n\
+                    \\ No newline at end of file\n\
+                    diff --git a/my notes.md b/my notes.md\n\
+                    new file mode 100644\n\
+                    Binary files /dev/null and b/my notes.md differ\n";
+        assert_eq!(
+            render_diff(diff),
+            "<h2 class=\"file\">src/a.rs</h2>\n<pre class=\"hunk\">\
+             <span class=\"meta\">index 1111111..2222222 100644</span>\
+             <span class=\"meta\">--- a/src/a.rs</span>\
+             <span class=\"meta\">+++ b/src/a.rs</span>\
+             <span class=\"range\">@@ -1,3 +1,3 @@</span>\
+             <span class=\"ctx\"> keep</span>\
+             <span class=\"del\">-old &lt;x&gt;</span>\
+             <span class=\"add\">+new &amp; y</span>\
+             <span class=\"ctx\"> </span>\
+             <span class=\"note\">\\ No newline at end of file</span></pre>\n\
+             <h2 class=\"file\">my notes.md</h2>\n<pre class=\"hunk\">\
+             <span class=\"meta\">new file mode 100644</span>\
+             <span class=\"meta\">Binary files /dev/null and b/my notes.md differ</span></pre>\n"
+        );
+        assert_eq!(render_diff(""), "<p class=\"empty\">No changes.</p>\n");
+    }
+
+    #[test]
+    fn export_kinds_deserialize_from_camel_case() {
+        let kind: ExportKind = serde_json::from_value(json!("session")).unwrap();
+        assert_eq!(kind, ExportKind::Session);
+        let kind: ExportKind = serde_json::from_value(json!("diff")).unwrap();
+        assert_eq!(kind.label(), "diff");
+        assert!(serde_json::from_value::<ExportKind>(json!("pdf")).is_err());
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn pdfs_are_printed_by_the_browser() {
+        let dir = TempDir::new();
+        let arguments = dir.0.join("arguments");
+        let browser = dir.browser(&format!(
+            "printf '%s\\n' \"$@\" > '{}'\n\
+             for arg; do case \"$arg\" in --print-to-pdf=*) printf '%%PDF-1.7' > \"${{arg#--print-to-pdf=}}\";; esac; done\n",
+            arguments.display()
+        ));
+        let html = dir.0.join("page.html");
+        std::fs::write(&html, "<p>hi</p>").unwrap();
+        let output = dir.0.join("out.pdf");
+
+        print_with(&browser, &html, &output).await.unwrap();
+        assert_eq!(std::fs::read_to_string(&output).unwrap(), "%PDF-1.7");
+        let arguments = std::fs::read_to_string(&arguments).unwrap();
+        let arguments: Vec<&str> = arguments.lines().collect();
+        assert!(arguments.contains(&"--headless=new"));
+        assert!(arguments.contains(&format!("--print-to-pdf={}", output.display()).as_str()));
+        assert_eq!(
+            arguments.last().copied(),
+            Some(format!("file://{}", html.display()).as_str())
+        );
+        // The throwaway browser profile is removed afterwards
+        let profile = arguments
+            .iter()
+            .find_map(|arg| arg.strip_prefix("--user-data-dir="))
+            .unwrap();
+        assert!(!Path::new(profile).exists());
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn failed_prints_report_why() {
+        let dir = TempDir::new();
+        let html = dir.0.join("page.html");
+        std::fs::write(&html, "<p>hi</p>").unwrap();
+        let output = dir.0.join("out.pdf");
+
+        let crashing = dir.browser("echo starting >&2\necho 'GPU process crashed' >&2\nexit 1\n");
+        let err = print_with(&crashing, &html, &output).await.unwrap_err();
+        assert_eq!(err.to_string(), "PDF rendering failed: GPU process crashed");
+
+        // Exiting cleanly without writing the PDF is still a failure
+        let silent = dir.browser("exit 0\n");
+        let err = print_with(&silent, &html, &output).await.unwrap_err();
+        assert_eq!(err.to_string(), "PDF rendering failed: no output");
+
+        let missing = dir.0.join("no-such-browser");
+        let err = print_with(&missing, &html, &output).await.unwrap_err();
+        assert!(err.to_string().starts_with(PDF_UNSUPPORTED), "{}", err);
+    }
+}
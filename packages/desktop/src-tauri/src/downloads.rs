@@ -0,0 +1,343 @@
+use std::{
+    collections::HashMap,
+    io::SeekFrom,
+    path::PathBuf,
+    sync::Arc,
+    time::{Duration, Instant, UNIX_EPOCH},
+};
+
+use axum::{
+    body::Body,
+    extract::{Path, State},
+    http::{header, HeaderMap, HeaderValue, Method, Response, StatusCode},
+    response::IntoResponse,
+};
+use log::{debug, info};
+use parking_lot::Mutex;
+use tokio::io::{AsyncReadExt, AsyncSeekExt};
+use tokio_util::io::ReaderStream;
+
+use crate::ServerState;
+
+/// How long a download link stays valid if nobody fetches it
+pub const DOWNLOAD_TOKEN_TTL: Duration = Duration::from_secs(5 * 60);
+
+const CLEANUP_INTERVAL: Duration = Duration::from_secs(60);
+
+const DOWNLOAD_CACHE_CONTROL: &str = "private, no-cache";
+
+struct DownloadEntry {
+    path: PathBuf,
+    file_name: String,
+    expires_at: Instant,
+}
+
+/// Short-lived, single-use tokens mapping to files on disk so the UI can offer
+/// large downloads over HTTP instead of Tauri IPC. A token is consumed once a
+/// response has delivered the file's last byte; partial range requests keep it
+/// alive so interrupted downloads can resume until it expires.
+#[derive(Clone, Default)]
+pub struct DownloadRegistry {
+    entries: Arc<Mutex<HashMap<String, DownloadEntry>>>,
+}
+
+impl DownloadRegistry {
+    pub fn register(&self, path: PathBuf, file_name: String) -> String {
+        self.register_for(path, file_name, DOWNLOAD_TOKEN_TTL)
+    }
+
+    pub fn register_for(&self, path: PathBuf, file_name: String, ttl: Duration) -> String {
+        let token = uuid::Uuid::new_v4().simple().to_string();
+        let mut entries = self.entries.lock();
+        let now = Instant::now();
+        entries.retain(|_, entry| entry.expires_at > now);
+        entries.insert(
+            token.clone(),
+            DownloadEntry {
+                path,
+                file_name,
+                expires_at: now + ttl,
+            },
+        );
+        token
+    }
+
+    /// Drop expired tokens so long-lived registrations don't pile up unseen
+    pub fn purge_expired(&self) {
+        let now = Instant::now();
+        self.entries.lock().retain(|_, entry| entry.expires_at > now);
+    }
+
+    pub fn spawn_cleanup(&self) {
+        let registry = self.clone();
+        tauri::async_runtime::spawn(async move {
+            let mut interval = tokio::time::interval(CLEANUP_INTERVAL);
+            loop {
+                interval.tick().await;
+                registry.purge_expired();
+            }
+        });
+    }
+
+    pub(crate) fn lookup(&self, token: &str) -> Option<(PathBuf, String)> {
+        let mut entries = self.entries.lock();
+        match entries.get(token) {
+            Some(entry) if entry.expires_at > Instant::now() => {
+                Some((entry.path.clone(), entry.file_name.clone()))
+            }
+            Some(_) => {
+                entries.remove(token);
+                None
+            }
+            None => None,
+        }
+    }
+
+    pub(crate) fn consume(&self, token: &str) {
+        self.entries.lock().remove(token);
+    }
+}
+
+/// Inclusive byte range resolved against the file length
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ByteRange {
+    pub start: u64,
+    pub end: u64,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RangeRequest {
+    /// No usable Range header; send the whole file
+    Full,
+    Partial(ByteRange),
+    Unsatisfiable,
+}
+
+/// Parse a single `bytes=` range. Multi-range and malformed headers fall back to the full body.
+pub fn parse_range(value: &str, len: u64) -> RangeRequest {
+    let Some((start, end)) = value
+        .trim()
+        .strip_prefix("bytes=")
+        .filter(|spec| !spec.contains(','))
+        .and_then(|spec| spec.split_once('-'))
+    else {
+        return RangeRequest::Full;
+    };
+    let (start, end) = (start.trim(), end.trim());
+
+    if start.is_empty() {
+        return match end.parse::<u64>() {
+            Ok(suffix) if suffix > 0 && len > 0 => RangeRequest::Partial(ByteRange {
+                start: len.saturating_sub(suffix),
+                end: len - 1,
+            }),
+            Ok(_) => RangeRequest::Unsatisfiable,
+            Err(_) => RangeRequest::Full,
+        };
+    }
+
+    let Ok(start) = start.parse::<u64>() else {
+        return RangeRequest::Full;
+    };
+    let end = if end.is_empty() {
+        len.saturating_sub(1)
+    } else {
+        match end.parse::<u64>() {
+            Ok(end) => end.min(len.saturating_sub(1)),
+            Err(_) => return RangeRequest::Full,
+        }
+    };
+    if start >= len || start > end {
+        return RangeRequest::Unsatisfiable;
+    }
+    RangeRequest::Partial(ByteRange { start, end })
+}
+
+/// Strong validator derived from size and modification time
+fn file_etag(metadata: &std::fs::Metadata) -> String {
+    let modified = metadata
+        .modified()
+        .ok()
+        .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+        .map(|duration| duration.as_nanos())
+        .unwrap_or_default();
+    format!("\"{:x}-{:x}\"", metadata.len(), modified)
+}
+
+fn etag_matches(headers: &HeaderMap, etag: &str) -> bool {
+    headers
+        .get(header::IF_NONE_MATCH)
+        .and_then(|value| value.to_str().ok())
+        .map(|value| {
+            value
+                .split(',')
+                .map(str::trim)
+                .any(|candidate| candidate == "*" || candidate == etag)
+        })
+        .unwrap_or(false)
+}
+
+fn status_response(status: StatusCode) -> Response<Body> {
+    status.into_response()
+}
+
+/// `GET|HEAD /api/openchamber/download/{token}`
+pub async fn download_handler(
+    State(state): State<ServerState>,
+    Path(token): Path<String>,
+    method: Method,
+    headers: HeaderMap,
+) -> Response<Body> {
+    let Some((path, file_name)) = state.downloads.lookup(&token) else {
+        return status_response(StatusCode::NOT_FOUND);
+    };
+
+    let metadata = match tokio::fs::metadata(&path).await {
+        Ok(metadata) if metadata.is_file() => metadata,
+        _ => {
+            state.downloads.consume(&token);
+            return status_response(StatusCode::NOT_FOUND);
+        }
+    };
+    let len = metadata.len();
+    let etag = file_etag(&metadata);
+
+    let mut builder = Response::builder()
+        .header(header::ETAG, &etag)
+        .header(header::CACHE_CONTROL, DOWNLOAD_CACHE_CONTROL)
+        .header(header::ACCEPT_RANGES, "bytes");
+
+    if etag_matches(&headers, &etag) {
+        return builder
+            .status(StatusCode::NOT_MODIFIED)
+            .body(Body::empty())
+            .unwrap_or_else(|_| status_response(StatusCode::INTERNAL_SERVER_ERROR));
+    }
+
+    // If-Range: only honour the range when the client's copy is still current
+    let range_allowed = headers
+        .get(header::IF_RANGE)
+        .and_then(|value| value.to_str().ok())
+        .map(|value| value.trim() == etag)
+        .unwrap_or(true);
+    let range = headers
+        .get(header::RANGE)
+        .and_then(|value| value.to_str().ok())
+        .filter(|_| range_allowed)
+        .map(|value| parse_range(value, len))
+        .unwrap_or(RangeRequest::Full);
+
+    let (status, range) = match range {
+        RangeRequest::Full => (
+            StatusCode::OK,
+            ByteRange {
+                start: 0,
+                end: len.saturating_sub(1),
+            },
+        ),
+        RangeRequest::Partial(range) => {
+            builder = builder.header(
+                header::CONTENT_RANGE,
+                format!("bytes {}-{}/{}", range.start, range.end, len),
+            );
+            (StatusCode::PARTIAL_CONTENT, range)
+        }
+        RangeRequest::Unsatisfiable => {
+            return builder
+                .status(StatusCode::RANGE_NOT_SATISFIABLE)
+                .header(header::CONTENT_RANGE, format!("bytes */{}", len))
+                .body(Body::empty())
+                .unwrap_or_else(|_| status_response(StatusCode::INTERNAL_SERVER_ERROR));
+        }
+    };
+    let body_len = if len == 0 { 0 } else { range.end - range.start + 1 };
+
+    let disposition = format!("attachment; filename=\"{}\"", file_name.replace('"', ""));
+    if let Ok(disposition) = HeaderValue::from_str(&disposition) {
+        builder = builder.header(header::CONTENT_DISPOSITION, disposition);
+    }
+    builder = builder
+        .status(status)
+        .header(header::CONTENT_TYPE, "application/octet-stream")
+        .header(header::CONTENT_LENGTH, body_len);
+
+    if method == Method::HEAD {
+        return builder
+            .body(Body::empty())
+            .unwrap_or_else(|_| status_response(StatusCode::INTERNAL_SERVER_ERROR));
+    }
+
+    let mut file = match tokio::fs::File::open(&path).await {
+        Ok(file) => file,
+        Err(_) => return status_response(StatusCode::NOT_FOUND),
+    };
+    if range.start > 0 && file.seek(SeekFrom::Start(range.start)).await.is_err() {
+        return status_response(StatusCode::INTERNAL_SERVER_ERROR);
+    }
+
+    if len == 0 || range.end + 1 >= len {
+        state.downloads.consume(&token);
+        info!("[desktop:http] Download {} delivered", file_name);
+    } else {
+        debug!(
+            "[desktop:http] Partial download {} bytes {}-{}",
+            file_name, range.start, range.end
+        );
+    }
+
+    let stream = ReaderStream::new(file.take(body_len));
+    builder
+        .body(Body::from_stream(stream))
+        .unwrap_or_else(|_| status_response(StatusCode::INTERNAL_SERVER_ERROR))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tokens_resolve_until_consumed() {
+        let registry = DownloadRegistry::default();
+        let token = registry.register(PathBuf::from("/tmp/fix.patch"), "fix.patch".to_string());
+
+        assert_eq!(
+            registry.lookup(&token),
+            Some((PathBuf::from("/tmp/fix.patch"), "fix.patch".to_string()))
+        );
+        // Looking a token up doesn't use it
+        assert!(registry.lookup(&token).is_some());
+        registry.consume(&token);
+        assert_eq!(registry.lookup(&token), None);
+        assert_eq!(registry.lookup("unknown"), None);
+    }
+
+    #[test]
+    fn expired_tokens_are_rejected_and_cleaned_up() {
+        let registry = DownloadRegistry::default();
+        let expired =
+            registry.register_for(PathBuf::from("/tmp/a"), "a".to_string(), Duration::ZERO);
+        let live = registry.register_for(
+            PathBuf::from("/tmp/b"),
+            "b".to_string(),
+            Duration::from_secs(60),
+        );
+
+        assert_eq!(registry.lookup(&expired), None);
+        assert!(registry.lookup(&live).is_some());
+
+        registry.register_for(PathBuf::from("/tmp/c"), "c".to_string(), Duration::ZERO);
+        registry.purge_expired();
+        let remaining: Vec<_> = registry.entries.lock().keys().cloned().collect();
+        assert_eq!(remaining, [live]);
+    }
+
+    #[test]
+    fn registering_drops_expired_entries() {
+        let registry = DownloadRegistry::default();
+        for name in ["a", "b", "c"] {
+            registry.register_for(PathBuf::from(name), name.to_string(), Duration::ZERO);
+        }
+        registry.register(PathBuf::from("d"), "d".to_string());
+        assert_eq!(registry.entries.lock().len(), 1);
+    }
+}
@@ -0,0 +1,491 @@
+use std::{
+    ffi::OsStr,
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+
+use axum::{
+    extract::{Query, State},
+    Json,
+};
+use futures_util::future::join_all;
+use once_cell::sync::Lazy;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tauri::Manager;
+use tokio::{process::Command, sync::Mutex};
+
+use crate::{DesktopRuntime, ServerState};
+
+const REPORT_TTL: Duration = Duration::from_secs(10 * 60);
+const PROBE_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Runtimes looked up on PATH, with the arguments that print their version
+const RUNTIME_PROBES: &[(&str, &[&str])] = &[
+    ("node", &["--version"]),
+    ("python3", &["--version"]),
+    ("python", &["--version"]),
+    ("go", &["version"]),
+    ("rustc", &["--version"]),
+    ("docker", &["--version"]),
+];
+
+/// Facts that `environment.expose` in settings can list; all are exposed when unset
+const FACTS: &[&str] = &["os", "arch", "runtimes", "memory", "cpus", "workspace", "gitBranch"];
+
+static VERSION_PATTERN: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"\d+\.\d+(?:\.\d+)?").expect("valid version regex"));
+
+/// Host facts are slow to gather, so they are cached; workspace facts are read per request
+static HOST_CACHE: Lazy<Mutex<Option<(Instant, HostFacts)>>> = Lazy::new(|| Mutex::new(None));
+
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RuntimeInfo {
+    pub name: String,
+    pub version: Option<String>,
+    pub path: String,
+}
+
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MemoryInfo {
+    pub total_bytes: u64,
+    pub free_bytes: Option<u64>,
+}
+
+#[derive(Clone, Debug)]
+struct HostFacts {
+    os: String,
+    os_version: Option<String>,
+    arch: String,
+    cpus: usize,
+    memory: Option<MemoryInfo>,
+    runtimes: Vec<RuntimeInfo>,
+}
+
+#[derive(Debug, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EnvironmentReport {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    os: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    os_version: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    arch: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    cpus: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    memory: Option<MemoryInfo>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    runtimes: Option<Vec<RuntimeInfo>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    workspace: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    git_branch: Option<String>,
+    /// Seconds since the host facts were gathered
+    age_seconds: u64,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default)]
+pub struct EnvironmentQuery {
+    refresh: bool,
+}
+
+/// `GET /api/openchamber/environment[?refresh=true]`
+pub async fn environment_handler(
+    State(state): State<ServerState>,
+    Query(query): Query<EnvironmentQuery>,
+) -> Json<EnvironmentReport> {
+    let settings = match state.app.try_state::<DesktopRuntime>() {
+        Some(runtime) => runtime.settings().load().await.unwrap_or(Value::Null),
+        None => Value::Null,
+    };
+    let exposed = exposed_facts(&settings);
+    let show = |fact: &str| exposed.iter().any(|name| name == fact);
+
+    let (gathered_at, host) = host_facts(query.refresh).await;
+    let workspace = state.opencode.get_working_directory();
+
+    Json(EnvironmentReport {
+        os: show("os").then(|| host.os.clone()),
+        os_version: show("os").then(|| host.os_version.clone()).flatten(),
+        arch: show("arch").then(|| host.arch.clone()),
+        cpus: show("cpus").then_some(host.cpus),
+        memory: show("memory").then(|| host.memory.clone()).flatten(),
+        runtimes: show("runtimes").then(|| host.runtimes.clone()),
+        workspace: show("workspace").then(|| workspace.to_string_lossy().to_string()),
+        git_branch: show("gitBranch")
+            .then(|| git_branch(&workspace))
+            .flatten(),
+        age_seconds: gathered_at.elapsed().as_secs(),
+    })
+}
+
+fn exposed_facts(settings: &Value) -> Vec<String> {
+    match settings
+        .get("environment")
+        .and_then(|environment| environment.get("expose"))
+        .and_then(Value::as_array)
+    {
+        Some(list) => list
+            .iter()
+            .filter_map(Value::as_str)
+            .filter(|fact| FACTS.contains(fact))
+            .map(str::to_string)
+            .collect(),
+        None => FACTS.iter().map(|fact| fact.to_string()).collect(),
+    }
+}
+
+/// Return cached host facts, gathering them when stale or when `refresh` is set.
+/// The lock is held while gathering so concurrent requests share one probe run.
+async fn host_facts(refresh: bool) -> (Instant, HostFacts) {
+    let mut cache = HOST_CACHE.lock().await;
+    if let Some((gathered_at, facts)) = cache.as_ref() {
+        if !refresh && gathered_at.elapsed() < REPORT_TTL {
+            return (*gathered_at, facts.clone());
+        }
+    }
+
+    let search_path = std::env::var_os("PATH").unwrap_or_default();
+    let (os_version, memory, runtimes) = tokio::join!(
+        os_version(),
+        memory_info(),
+        probe_runtimes(RUNTIME_PROBES, &search_path)
+    );
+    let facts = HostFacts {
+        os: std::env::consts::OS.to_string(),
+        os_version,
+        arch: std::env::consts::ARCH.to_string(),
+        cpus: std::thread::available_parallelism()
+            .map(|count| count.get())
+            .unwrap_or(1),
+        memory,
+        runtimes,
+    };
+    let gathered_at = Instant::now();
+    *cache = Some((gathered_at, facts.clone()));
+    (gathered_at, facts)
+}
+
+/// Locate `name` on PATH the way a shell would
+pub(crate) fn find_on_path(name: &str) -> Option<PathBuf> {
+    find_in(&std::env::var_os("PATH")?, name)
+}
+
+fn find_in(search_path: &OsStr, name: &str) -> Option<PathBuf> {
+    std::env::split_paths(search_path).find_map(|dir| {
+        let candidate = dir.join(name);
+        if candidate.is_file() {
+            return Some(candidate);
+        }
+        if cfg!(windows) {
+            let exe = dir.join(format!("{name}.exe"));
+            if exe.is_file() {
+                return Some(exe);
+            }
+        }
+        None
+    })
+}
+
+/// Run `program args` with a deadline, returning the first non-empty output line
+async fn run_probe(program: &Path, args: &[&str]) -> Option<String> {
+    let mut command = Command::new(program);
+    command.args(args).kill_on_drop(true);
+    let output = tokio::time::timeout(PROBE_TIMEOUT, command.output())
+        .await
+        .ok()?
+        .ok()?;
+    // Older Pythons print their version on stderr
+    [output.stdout, output.stderr]
+        .iter()
+        .map(|bytes| String::from_utf8_lossy(bytes).to_string())
+        .find_map(|text| {
+            text.lines()
+                .map(str::trim)
+                .find(|line| !line.is_empty())
+                .map(str::to_string)
+        })
+}
+
+/// Probe every runtime concurrently; a hung binary only costs its own timeout
+async fn probe_runtimes(probes: &[(&str, &[&str])], search_path: &OsStr) -> Vec<RuntimeInfo> {
+    let probes = probes.iter().filter_map(|(name, args)| {
+        find_in(search_path, name).map(|path| async move {
+            let version = run_probe(&path, args).await.and_then(|line| {
+                VERSION_PATTERN
+                    .find(&line)
+                    .map(|version| version.as_str().to_string())
+            });
+            RuntimeInfo {
+                name: name.to_string(),
+                version,
+                path: path.to_string_lossy().to_string(),
+            }
+        })
+    });
+    join_all(probes).await
+}
+
+#[cfg(target_os = "linux")]
+async fn os_version() -> Option<String> {
+    if let Ok(release) = tokio::fs::read_to_string("/etc/os-release").await {
+        let pretty_name = release
+            .lines()
+            .find_map(|line| line.strip_prefix("PRETTY_NAME="))
+            .map(|name| name.trim_matches('"').to_string());
+        if pretty_name.is_some() {
+            return pretty_name;
+        }
+    }
+    tokio::fs::read_to_string("/proc/sys/kernel/osrelease")
+        .await
+        .ok()
+        .map(|kernel| kernel.trim().to_string())
+}
+
+#[cfg(target_os = "macos")]
+async fn os_version() -> Option<String> {
+    run_probe(Path::new("/usr/bin/sw_vers"), &["-productVersion"]).await
+}
+
+#[cfg(windows)]
+async fn os_version() -> Option<String> {
+    run_probe(Path::new("cmd"), &["/C", "ver"]).await
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos", windows)))]
+async fn os_version() -> Option<String> {
+    None
+}
+
+#[cfg(target_os = "linux")]
+async fn memory_info() -> Option<MemoryInfo> {
+    let meminfo = tokio::fs::read_to_string("/proc/meminfo").await.ok()?;
+    let field = |key: &str| {
+        meminfo.lines().find_map(|line| {
+            line.strip_prefix(key)?
+                .trim()
+                .trim_end_matches("kB")
+                .trim()
+                .parse::<u64>()
+                .ok()
+                .map(|kib| kib * 1024)
+        })
+    };
+    Some(MemoryInfo {
+        total_bytes: field("MemTotal:")?,
+        free_bytes: field("MemAvailable:"),
+    })
+}
+
+#[cfg(target_os = "macos")]
+async fn memory_info() -> Option<MemoryInfo> {
+    let total_bytes = run_probe(Path::new("/usr/sbin/sysctl"), &["-n", "hw.memsize"])
+        .await?
+        .parse()
+        .ok()?;
+
+    // vm_stat reports page counts; free + inactive approximates available memory
+    let free_bytes = Command::new("/usr/bin/vm_stat")
+        .kill_on_drop(true)
+        .output();
+    let free_bytes = tokio::time::timeout(PROBE_TIMEOUT, free_bytes)
+        .await
+        .ok()
+        .and_then(Result::ok)
+        .and_then(|output| {
+            let text = String::from_utf8_lossy(&output.stdout).to_string();
+            let page_size: u64 = text
+                .lines()
+                .next()?
+                .split("page size of ")
+                .nth(1)?
+                .split_whitespace()
+                .next()?
+                .parse()
+                .ok()?;
+            let pages = |key: &str| {
+                text.lines()
+                    .find_map(|line| line.strip_prefix(key))
+                    .and_then(|value| value.trim().trim_end_matches('.').parse::<u64>().ok())
+                    .unwrap_or(0)
+            };
+            Some((pages("Pages free:") + pages("Pages inactive:")) * page_size)
+        });
+
+    Some(MemoryInfo {
+        total_bytes,
+        free_bytes,
+    })
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos")))]
+async fn memory_info() -> Option<MemoryInfo> {
+    None
+}
+
+/// Current branch from `.git/HEAD`, following worktree `gitdir:` files; detached
+/// heads report the short commit id
+fn git_branch(workspace: &Path) -> Option<String> {
+    let dot_git = workspace.join(".git");
+    let git_dir = if dot_git.is_file() {
+        let pointer = std::fs::read_to_string(&dot_git).ok()?;
+        let target = PathBuf::from(pointer.trim().strip_prefix("gitdir:")?.trim());
+        if target.is_absolute() {
+            target
+        } else {
+            workspace.join(target)
+        }
+    } else {
+        dot_git
+    };
+
+    let head = std::fs::read_to_string(git_dir.join("HEAD")).ok()?;
+    let head = head.trim();
+    match head.strip_prefix("ref: ") {
+        Some(reference) => Some(
+            reference
+                .strip_prefix("refs/heads/")
+                .unwrap_or(reference)
+                .to_string(),
+        ),
+        None => Some(head.chars().take(7).collect()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    /// A scratch directory under the system temp dir, removed on drop
+    struct TempDir(PathBuf);
+
+    impl TempDir {
+        fn new() -> Self {
+            let dir =
+                std::env::temp_dir().join(format!("openchamber-env-{}", uuid::Uuid::new_v4()));
+            std::fs::create_dir_all(&dir).unwrap();
+            Self(dir)
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            std::fs::remove_dir_all(&self.0).ok();
+        }
+    }
+
+    /// Write an executable shell script named `name` into `dir`
+    #[cfg(unix)]
+    fn fake_executable(dir: &Path, name: &str, script: &str) {
+        use std::os::unix::fs::PermissionsExt;
+        let path = dir.join(name);
+        std::fs::write(&path, format!("#!/bin/sh\n{script}\n")).unwrap();
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755)).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn runtimes_are_found_and_their_versions_parsed() {
+        let bin = TempDir::new();
+        fake_executable(&bin.0, "node", "echo v20.11.1");
+        // Older Pythons print their version on stderr
+        fake_executable(&bin.0, "python", "echo 'Python 2.7.18' >&2");
+        fake_executable(&bin.0, "go", "echo 'go version go1.22 linux/amd64'");
+        fake_executable(&bin.0, "docker", "exit 1");
+        let probes: &[(&str, &[&str])] = &[
+            ("node", &["--version"]),
+            ("python", &["--version"]),
+            ("go", &["version"]),
+            ("docker", &["--version"]),
+            ("rustc", &["--version"]),
+        ];
+
+        let runtimes = probe_runtimes(probes, bin.0.as_os_str()).await;
+        let found: Vec<_> = runtimes
+            .iter()
+            .map(|runtime| (runtime.name.as_str(), runtime.version.as_deref()))
+            .collect();
+        assert_eq!(
+            found,
+            [
+                ("node", Some("20.11.1")),
+                ("python", Some("2.7.18")),
+                ("go", Some("1.22")),
+                ("docker", None),
+            ]
+        );
+        assert_eq!(runtimes[0].path, bin.0.join("node").to_string_lossy());
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn probes_run_concurrently_and_a_hung_one_only_costs_its_timeout() {
+        let bin = TempDir::new();
+        for name in ["node", "python3", "rustc"] {
+            fake_executable(&bin.0, name, "sleep 1; echo 1.0.0");
+        }
+        fake_executable(&bin.0, "docker", "sleep 30");
+        let probes: &[(&str, &[&str])] = &[
+            ("node", &[]),
+            ("python3", &[]),
+            ("rustc", &[]),
+            ("docker", &[]),
+        ];
+
+        let started = Instant::now();
+        let runtimes = probe_runtimes(probes, bin.0.as_os_str()).await;
+        let elapsed = started.elapsed();
+
+        assert!(
+            elapsed < PROBE_TIMEOUT + Duration::from_secs(1),
+            "{elapsed:?}"
+        );
+        let versions: Vec<_> = runtimes.iter().map(|r| r.version.as_deref()).collect();
+        assert_eq!(
+            versions,
+            [Some("1.0.0"), Some("1.0.0"), Some("1.0.0"), None]
+        );
+    }
+
+    #[test]
+    fn the_expose_setting_limits_the_facts() {
+        assert_eq!(exposed_facts(&Value::Null), FACTS);
+        assert_eq!(
+            exposed_facts(&json!({ "environment": { "expose": ["os", "secrets", "cpus"] } })),
+            ["os", "cpus"]
+        );
+        assert!(exposed_facts(&json!({ "environment": { "expose": [] } })).is_empty());
+    }
+
+    #[test]
+    fn git_branch_reads_head_directly_and_through_worktrees() {
+        let repo = TempDir::new();
+        std::fs::create_dir(repo.0.join(".git")).unwrap();
+        std::fs::write(repo.0.join(".git/HEAD"), "ref: refs/heads/feature/env\n").unwrap();
+        assert_eq!(git_branch(&repo.0).as_deref(), Some("feature/env"));
+
+        std::fs::write(repo.0.join(".git/HEAD"), "0123456789abcdef\n").unwrap();
+        assert_eq!(git_branch(&repo.0).as_deref(), Some("0123456"));
+
+        let worktree = TempDir::new();
+        let git_dir = repo.0.join(".git/worktrees/wt");
+        std::fs::create_dir_all(&git_dir).unwrap();
+        std::fs::write(git_dir.join("HEAD"), "ref: refs/heads/wt-branch\n").unwrap();
+        std::fs::write(
+            worktree.0.join(".git"),
+            format!("gitdir: {}\n", git_dir.display()),
+        )
+        .unwrap();
+        assert_eq!(git_branch(&worktree.0).as_deref(), Some("wt-branch"));
+
+        let plain = TempDir::new();
+        assert_eq!(git_branch(&plain.0), None);
+    }
+}
@@ -0,0 +1,678 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    convert::Infallible,
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use axum::{
+    extract::{Query, State},
+    http::HeaderMap,
+    response::sse::{Event, KeepAlive, Sse},
+    Json,
+};
+use futures_util::{stream, Stream, StreamExt};
+use log::{debug, info, warn};
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use tokio::sync::broadcast::{self, error::RecvError};
+
+use crate::{opencode_client::OpenCodeClient, ServerState};
+
+/// Comment sent to idle downstream clients so proxies and webviews keep the stream open
+pub const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(15);
+/// Recent events kept per directory for Last-Event-ID replay
+const REPLAY_CAPACITY: usize = 256;
+/// Events a slow downstream client may fall behind before it is disconnected
+const CHANNEL_CAPACITY: usize = 1024;
+/// How long an upstream connection outlives its last downstream client, so
+/// reloads and reconnects don't reopen it
+const UPSTREAM_IDLE_GRACE: Duration = Duration::from_secs(30);
+const RECONNECT_DELAY: Duration = Duration::from_secs(2);
+
+/// One upstream event, numbered for Last-Event-ID
+#[derive(Clone, Debug)]
+pub struct HubEvent {
+    pub id: u64,
+    pub data: Arc<str>,
+}
+
+struct Recent {
+    events: VecDeque<HubEvent>,
+    last_id: u64,
+}
+
+/// The shared upstream `/event` connection for one directory
+struct Channel {
+    directory: String,
+    sender: broadcast::Sender<HubEvent>,
+    /// Also serializes publishing against subscribing, so a new subscriber
+    /// sees each event either in its replay or on its receiver, never both
+    recent: Mutex<Recent>,
+    connected: AtomicBool,
+    subscribers: AtomicUsize,
+    upstream: Mutex<Option<tauri::async_runtime::JoinHandle<()>>>,
+}
+
+impl Channel {
+    fn new(directory: String) -> Self {
+        let (sender, _) = broadcast::channel(CHANNEL_CAPACITY);
+        Self {
+            directory,
+            sender,
+            recent: Mutex::new(Recent {
+                events: VecDeque::with_capacity(REPLAY_CAPACITY),
+                last_id: 0,
+            }),
+            connected: AtomicBool::new(false),
+            subscribers: AtomicUsize::new(0),
+            upstream: Mutex::new(None),
+        }
+    }
+
+    fn publish(&self, data: String) {
+        let mut recent = self.recent.lock();
+        recent.last_id += 1;
+        let event = HubEvent {
+            id: recent.last_id,
+            data: Arc::from(data),
+        };
+        if recent.events.len() == REPLAY_CAPACITY {
+            recent.events.pop_front();
+        }
+        recent.events.push_back(event.clone());
+        // No receivers is fine: the event stays in the replay ring
+        let _ = self.sender.send(event);
+    }
+
+    fn stats(&self) -> ChannelStats {
+        let recent = self.recent.lock();
+        ChannelStats {
+            directory: self.directory.clone(),
+            upstream_connected: self.connected.load(Ordering::SeqCst),
+            subscribers: self.subscribers.load(Ordering::SeqCst),
+            last_event_id: recent.last_id,
+            buffered: recent.events.len(),
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ChannelStats {
+    pub directory: String,
+    pub upstream_connected: bool,
+    /// Downstream clients of /api/openchamber/events
+    pub subscribers: usize,
+    pub last_event_id: u64,
+    /// Events available for Last-Event-ID replay
+    pub buffered: usize,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EventHubStats {
+    pub channels: Vec<ChannelStats>,
+    /// Upstream connections held open by clients streaming `/event` through
+    /// the plain proxy instead of the hub
+    pub proxied_streams: usize,
+}
+
+/// Fans one upstream `/event` connection per directory out to any number of
+/// downstream clients. Clients come and go without touching the upstream.
+pub struct EventHub {
+    client: OpenCodeClient,
+    channels: Mutex<HashMap<String, Arc<Channel>>>,
+    proxied_streams: Arc<AtomicUsize>,
+}
+
+/// A downstream client's view of a channel: missed events, then live ones
+pub struct Subscription {
+    pub replay: Vec<HubEvent>,
+    live: LiveEvents,
+}
+
+struct LiveEvents {
+    receiver: broadcast::Receiver<HubEvent>,
+    _guard: SubscriberGuard,
+}
+
+struct SubscriberGuard {
+    hub: Arc<EventHub>,
+    channel: Arc<Channel>,
+}
+
+impl Drop for SubscriberGuard {
+    fn drop(&mut self) {
+        if self.channel.subscribers.fetch_sub(1, Ordering::SeqCst) == 1 {
+            self.hub.schedule_retire(self.channel.clone());
+        }
+    }
+}
+
+/// Counts a proxied `/event` stream for as long as it is held
+pub struct ProxiedStreamGuard(Arc<AtomicUsize>);
+
+impl Drop for ProxiedStreamGuard {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+impl EventHub {
+    pub fn new(client: OpenCodeClient) -> Self {
+        Self {
+            client,
+            channels: Mutex::new(HashMap::new()),
+            proxied_streams: Arc::new(AtomicUsize::new(0)),
+        }
+    }
+
+    /// Join the channel for `directory`, opening its upstream connection if
+    /// needed. With `last_event_id`, buffered events after it are replayed.
+    pub fn subscribe(
+        self: &Arc<Self>,
+        directory: &str,
+        last_event_id: Option<u64>,
+    ) -> Subscription {
+        let channel = {
+            let mut channels = self.channels.lock();
+            let channel = channels
+                .entry(directory.to_string())
+                .or_insert_with(|| {
+                    let channel = Arc::new(Channel::new(directory.to_string()));
+                    self.spawn_upstream(channel.clone());
+                    channel
+                })
+                .clone();
+            // Counted under the map lock so a pending retire can't remove it meanwhile
+            channel.subscribers.fetch_add(1, Ordering::SeqCst);
+            channel
+        };
+
+        let (receiver, replay) = {
+            let recent = channel.recent.lock();
+            let replay = match last_event_id {
+                Some(last) => recent
+                    .events
+                    .iter()
+                    .filter(|event| event.id > last)
+                    .cloned()
+                    .collect(),
+                None => Vec::new(),
+            };
+            (channel.sender.subscribe(), replay)
+        };
+
+        Subscription {
+            replay,
+            live: LiveEvents {
+                receiver,
+                _guard: SubscriberGuard {
+                    hub: self.clone(),
+                    channel,
+                },
+            },
+        }
+    }
+
+    pub fn track_proxied_stream(&self) -> ProxiedStreamGuard {
+        self.proxied_streams.fetch_add(1, Ordering::SeqCst);
+        ProxiedStreamGuard(self.proxied_streams.clone())
+    }
+
+    /// Event streams open right now, through the hub or the plain proxy
+    pub fn active_streams(&self) -> usize {
+        let subscribers: usize = self
+            .channels
+            .lock()
+            .values()
+            .map(|channel| channel.subscribers.load(Ordering::SeqCst))
+            .sum();
+        subscribers + self.proxied_streams.load(Ordering::SeqCst)
+    }
+
+    pub fn stats(&self) -> EventHubStats {
+        let mut channels: Vec<ChannelStats> = self
+            .channels
+            .lock()
+            .values()
+            .map(|channel| channel.stats())
+            .collect();
+        channels.sort_by(|a, b| a.directory.cmp(&b.directory));
+        EventHubStats {
+            channels,
+            proxied_streams: self.proxied_streams.load(Ordering::SeqCst),
+        }
+    }
+
+    fn spawn_upstream(&self, channel: Arc<Channel>) {
+        let client = self
+            .client
+            .clone()
+            .with_directory(channel.directory.clone());
+        let task_channel = channel.clone();
+        let handle = tauri::async_runtime::spawn(async move {
+            run_upstream(client, task_channel).await;
+        });
+        *channel.upstream.lock() = Some(handle);
+    }
+
+    /// Close the channel's upstream once it has had no clients for the grace period
+    fn schedule_retire(self: &Arc<Self>, channel: Arc<Channel>) {
+        let hub = self.clone();
+        tauri::async_runtime::spawn(async move {
+            tokio::time::sleep(UPSTREAM_IDLE_GRACE).await;
+            let mut channels = hub.channels.lock();
+            if channel.subscribers.load(Ordering::SeqCst) > 0 {
+                return;
+            }
+            let current = channels.get(&channel.directory);
+            if !current.is_some_and(|current| Arc::ptr_eq(current, &channel)) {
+                return;
+            }
+            channels.remove(&channel.directory);
+            if let Some(handle) = channel.upstream.lock().take() {
+                handle.abort();
+            }
+            channel.connected.store(false, Ordering::SeqCst);
+            debug!(
+                "[desktop:events] Closed idle upstream for {}",
+                channel.directory
+            );
+        });
+    }
+}
+
+async fn run_upstream(client: OpenCodeClient, channel: Arc<Channel>) {
+    loop {
+        let mut events = match client.event_stream().await {
+            Ok(events) => events,
+            Err(err) => {
+                debug!(
+                    "[desktop:events] Upstream for {} unavailable: {err}",
+                    channel.directory
+                );
+                tokio::time::sleep(RECONNECT_DELAY).await;
+                continue;
+            }
+        };
+        channel.connected.store(true, Ordering::SeqCst);
+        info!(
+            "[desktop:events] Upstream connected for {}",
+            channel.directory
+        );
+
+        loop {
+            match events.next_data().await {
+                Ok(Some(data)) => channel.publish(data),
+                Ok(None) => break,
+                Err(err) => {
+                    warn!("[desktop:events] Read error in upstream SSE stream: {err}");
+                    break;
+                }
+            }
+        }
+
+        channel.connected.store(false, Ordering::SeqCst);
+        tokio::time::sleep(RECONNECT_DELAY).await;
+    }
+}
+
+/// Replayed events followed by live ones. Ends when the client falls too far
+/// behind, so it reconnects and catches up through Last-Event-ID.
+fn event_stream(subscription: Subscription) -> impl Stream<Item = HubEvent> {
+    let live = stream::unfold(subscription.live, |mut live| async move {
+        match live.receiver.recv().await {
+            Ok(event) => Some((event, live)),
+            Err(RecvError::Lagged(missed)) => {
+                warn!("[desktop:events] Downstream client missed {missed} events; disconnecting");
+                None
+            }
+            Err(RecvError::Closed) => None,
+        }
+    });
+    stream::iter(subscription.replay).chain(live)
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EventsQuery {
+    /// Defaults to the current workspace
+    directory: Option<String>,
+    /// For clients that cannot set the Last-Event-ID header on first connect
+    last_event_id: Option<u64>,
+}
+
+/// `GET /api/openchamber/events`: OpenCode's event stream, shared between clients
+pub async fn events_handler(
+    State(state): State<ServerState>,
+    headers: HeaderMap,
+    Query(query): Query<EventsQuery>,
+) -> Sse<impl Stream<Item = Result<Event, Infallible>>> {
+    let directory = query
+        .directory
+        .filter(|directory| !directory.trim().is_empty())
+        .unwrap_or_else(|| {
+            state
+                .opencode
+                .get_working_directory()
+                .to_string_lossy()
+                .to_string()
+        });
+    let last_event_id = resume_from(&headers, query.last_event_id);
+    sse(state.event_hub.subscribe(&directory, last_event_id))
+}
+
+/// Last-Event-ID from the header an EventSource sends on reconnect, else the query
+fn resume_from(headers: &HeaderMap, query: Option<u64>) -> Option<u64> {
+    headers
+        .get("last-event-id")
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.trim().parse().ok())
+        .or(query)
+}
+
+/// Frame a subscription as SSE, with heartbeat comments while it is idle
+fn sse(subscription: Subscription) -> Sse<impl Stream<Item = Result<Event, Infallible>>> {
+    let events = event_stream(subscription).map(|event| {
+        Ok(Event::default()
+            .id(event.id.to_string())
+            .data(event.data.as_ref()))
+    });
+    Sse::new(events).keep_alive(
+        KeepAlive::new()
+            .interval(HEARTBEAT_INTERVAL)
+            .text("heartbeat"),
+    )
+}
+
+/// `GET /api/openchamber/events/stats`: upstream and downstream connection counts
+pub async fn stats_handler(State(state): State<ServerState>) -> Json<EventHubStats> {
+    Json(state.event_hub.stats())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    use axum::{routing::get, Router};
+
+    use crate::{opencode_manager::OpenCodeManager, upstream_pool::UpstreamPool};
+
+    const DIRECTORY: &str = "/work/project";
+
+    /// A fake OpenCode `/event` endpoint fed by the test
+    struct Upstream {
+        events: broadcast::Sender<String>,
+        connections: Arc<AtomicUsize>,
+        hub: Arc<EventHub>,
+    }
+
+    impl Upstream {
+        async fn start() -> Self {
+            let (events, _) = broadcast::channel::<String>(4096);
+            let connections = Arc::new(AtomicUsize::new(0));
+            let app = Router::new().route(
+                "/event",
+                get({
+                    let events = events.clone();
+                    let connections = connections.clone();
+                    move || {
+                        connections.fetch_add(1, Ordering::SeqCst);
+                        let receiver = events.subscribe();
+                        async move {
+                            let stream = stream::unfold(receiver, |mut receiver| async move {
+                                let data = receiver.recv().await.ok()?;
+                                Some((Ok::<_, Infallible>(Event::default().data(data)), receiver))
+                            });
+                            Sse::new(stream)
+                        }
+                    }
+                }),
+            );
+            let port = serve(app).await;
+            Self {
+                events,
+                connections,
+                hub: hub_on(port),
+            }
+        }
+
+        fn send(&self, range: std::ops::RangeInclusive<u64>) {
+            for n in range {
+                self.events.send(payload(n)).unwrap();
+            }
+        }
+
+        async fn wait_connected(&self) {
+            wait_for(|| {
+                self.hub
+                    .stats()
+                    .channels
+                    .iter()
+                    .any(|channel| channel.upstream_connected)
+            })
+            .await;
+        }
+    }
+
+    async fn serve(app: Router) -> u16 {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        tokio::spawn(async move {
+            axum::serve(listener, app).await.ok();
+        });
+        port
+    }
+
+    fn hub_on(port: u16) -> Arc<EventHub> {
+        let opencode = Arc::new(OpenCodeManager::ready_on(port, PathBuf::from(DIRECTORY)));
+        let pool = Arc::new(UpstreamPool::new(opencode.clone()).unwrap());
+        Arc::new(EventHub::new(OpenCodeClient::new(pool, opencode)))
+    }
+
+    /// A hub whose upstream never connects, for feeding channels directly
+    fn offline_hub() -> Arc<EventHub> {
+        let port = std::net::TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        hub_on(port)
+    }
+
+    fn channel(hub: &EventHub) -> Arc<Channel> {
+        hub.channels.lock().get(DIRECTORY).unwrap().clone()
+    }
+
+    fn payload(n: u64) -> String {
+        format!(r#"{{"type":"test","properties":{{"n":{n}}}}}"#)
+    }
+
+    async fn wait_for(mut condition: impl FnMut() -> bool) {
+        tokio::time::timeout(Duration::from_secs(10), async {
+            while !condition() {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .expect("condition not reached in time");
+    }
+
+    /// The next `count` events of a subscription
+    async fn read(subscription: Subscription, count: usize) -> Vec<HubEvent> {
+        tokio::time::timeout(
+            Duration::from_secs(10),
+            event_stream(subscription).take(count).collect::<Vec<_>>(),
+        )
+        .await
+        .expect("events not received in time")
+    }
+
+    fn assert_sequence(events: &[HubEvent], first: u64, last: u64) {
+        let ids: Vec<u64> = events.iter().map(|event| event.id).collect();
+        assert_eq!(ids, (first..=last).collect::<Vec<_>>());
+        for event in events {
+            assert_eq!(event.data.as_ref(), payload(event.id));
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn concurrent_readers_share_one_upstream_and_see_every_event_once() {
+        let upstream = Upstream::start().await;
+        let readers: Vec<_> = (0..8)
+            .map(|_| {
+                let subscription = upstream.hub.subscribe(DIRECTORY, None);
+                tokio::spawn(read(subscription, 500))
+            })
+            .collect();
+        upstream.wait_connected().await;
+
+        upstream.send(1..=500);
+        for reader in readers {
+            assert_sequence(&reader.await.unwrap(), 1, 500);
+        }
+
+        assert_eq!(upstream.connections.load(Ordering::SeqCst), 1);
+        let stats = upstream.hub.stats();
+        assert_eq!(stats.channels.len(), 1);
+        assert_eq!(stats.channels[0].last_event_id, 500);
+        assert_eq!(stats.channels[0].buffered, REPLAY_CAPACITY);
+        assert_eq!(stats.channels[0].subscribers, 0);
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn reconnecting_readers_resume_without_gaps_or_duplicates() {
+        let upstream = Upstream::start().await;
+        let steady = upstream.hub.subscribe(DIRECTORY, None);
+        let steady = tokio::spawn(read(steady, 300));
+        let first = upstream.hub.subscribe(DIRECTORY, None);
+        upstream.wait_connected().await;
+
+        upstream.send(1..=200);
+        let seen = read(first, 50).await;
+        assert_sequence(&seen, 1, 50);
+
+        // The client drops off while events keep flowing, then reconnects
+        upstream.send(201..=300);
+        let resumed = upstream.hub.subscribe(DIRECTORY, Some(50));
+        assert_sequence(&read(resumed, 250).await, 51, 300);
+
+        // Other clients and the upstream connection were not disturbed
+        assert_sequence(&steady.await.unwrap(), 1, 300);
+        assert_eq!(upstream.connections.load(Ordering::SeqCst), 1);
+        assert!(upstream.hub.stats().channels[0].upstream_connected);
+    }
+
+    #[tokio::test]
+    async fn replay_is_limited_to_the_recent_ring() {
+        let hub = offline_hub();
+        let _holder = hub.subscribe(DIRECTORY, None);
+        let channel = channel(&hub);
+        for n in 1..=300 {
+            channel.publish(payload(n));
+        }
+
+        let replay = hub.subscribe(DIRECTORY, Some(100)).replay;
+        assert_sequence(&replay, 101, 300);
+        // Older events than the ring holds are gone; the client gets what is left
+        let replay = hub.subscribe(DIRECTORY, Some(10)).replay;
+        assert_sequence(&replay, 300 - REPLAY_CAPACITY as u64 + 1, 300);
+        assert!(hub.subscribe(DIRECTORY, Some(300)).replay.is_empty());
+        assert!(hub.subscribe(DIRECTORY, None).replay.is_empty());
+    }
+
+    #[tokio::test]
+    async fn clients_that_fall_too_far_behind_are_disconnected() {
+        let hub = offline_hub();
+        let slow = hub.subscribe(DIRECTORY, None);
+        let channel = channel(&hub);
+        for n in 1..=(CHANNEL_CAPACITY as u64 + 10) {
+            channel.publish(payload(n));
+        }
+
+        assert!(read(slow, usize::MAX).await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn downstream_connections_are_counted() {
+        let hub = offline_hub();
+        let first = hub.subscribe(DIRECTORY, None);
+        let second = hub.subscribe(DIRECTORY, None);
+        let proxied = hub.track_proxied_stream();
+
+        assert_eq!(hub.active_streams(), 3);
+        assert_eq!(hub.stats().channels[0].subscribers, 2);
+        assert_eq!(hub.stats().proxied_streams, 1);
+
+        drop(first);
+        drop(proxied);
+        assert_eq!(hub.active_streams(), 1);
+        drop(second);
+        assert_eq!(hub.active_streams(), 0);
+        // The channel outlives its last client for the grace period
+        assert_eq!(hub.stats().channels.len(), 1);
+    }
+
+    #[test]
+    fn the_header_wins_over_the_query_when_resuming() {
+        let mut headers = HeaderMap::new();
+        assert_eq!(resume_from(&headers, None), None);
+        assert_eq!(resume_from(&headers, Some(7)), Some(7));
+
+        headers.insert("last-event-id", " 42 ".parse().unwrap());
+        assert_eq!(resume_from(&headers, Some(7)), Some(42));
+
+        headers.insert("last-event-id", "abc".parse().unwrap());
+        assert_eq!(resume_from(&headers, Some(7)), Some(7));
+    }
+
+    #[tokio::test]
+    async fn events_are_framed_with_their_ids() {
+        let hub = offline_hub();
+        let _holder = hub.subscribe(DIRECTORY, None);
+        let channel = channel(&hub);
+        channel.publish(payload(1));
+        channel.publish(payload(2));
+        let app = Router::new().route(
+            "/events",
+            get({
+                let hub = hub.clone();
+                move |headers: HeaderMap| async move {
+                    sse(hub.subscribe(DIRECTORY, resume_from(&headers, None)))
+                }
+            }),
+        );
+        let port = serve(app).await;
+
+        let mut response = reqwest::Client::new()
+            .get(format!("http://127.0.0.1:{port}/events"))
+            .header("last-event-id", "0")
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.headers()["content-type"], "text/event-stream");
+        let mut body = String::new();
+        while !body.ends_with(&format!("{}\n\n", payload(2))) {
+            let chunk = tokio::time::timeout(Duration::from_secs(5), response.chunk())
+                .await
+                .unwrap()
+                .unwrap()
+                .unwrap();
+            body.push_str(std::str::from_utf8(&chunk).unwrap());
+        }
+        assert_eq!(
+            body,
+            format!(
+                "id: 1\ndata: {}\n\nid: 2\ndata: {}\n\n",
+                payload(1),
+                payload(2)
+            )
+        );
+    }
+}
@@ -0,0 +1,617 @@
+use std::{convert::Infallible, path::PathBuf, time::Duration};
+
+use axum::{
+    body::Body,
+    extract::State,
+    http::{header, HeaderMap, Response, StatusCode},
+    response::{
+        sse::{Event, KeepAlive, Sse},
+        IntoResponse,
+    },
+    Json,
+};
+use futures_util::{stream, TryStreamExt};
+use log::{info, warn};
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use tauri::{Emitter, Manager};
+use tokio::{io::AsyncBufReadExt, sync::mpsc};
+use tokio_util::io::StreamReader;
+
+use crate::{opencode_client::OpenCodeClient, paths, workspace_trust, DesktopRuntime, ServerState};
+
+/// Event emitted to the webview when a script started a session through /api/openchamber/run
+pub const EXTERNAL_RUN_EVENT: &str = "openchamber:external-run";
+
+const RUN_TOKEN_FILE: &str = "run-token";
+const MAX_PROMPT_BYTES: usize = 100 * 1024;
+const DEFAULT_WAIT_TIMEOUT: Duration = Duration::from_secs(10 * 60);
+const MAX_WAIT_TIMEOUT: Duration = Duration::from_secs(60 * 60);
+const OPENCODE_REQUEST_TIMEOUT: Duration = Duration::from_secs(15);
+
+/// Token scripts must present to start runs; generated once and kept in the state directory
+static RUN_TOKEN: Lazy<Option<String>> = Lazy::new(load_or_create_run_token);
+
+fn load_or_create_run_token() -> Option<String> {
+    let path = paths::state_dir()?.join(RUN_TOKEN_FILE);
+    if let Ok(existing) = std::fs::read_to_string(&path) {
+        let existing = existing.trim();
+        if !existing.is_empty() {
+            return Some(existing.to_string());
+        }
+    }
+
+    let token = uuid::Uuid::new_v4().simple().to_string();
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent).ok()?;
+    }
+    if let Err(err) = std::fs::write(&path, &token) {
+        warn!("[desktop:run] Failed to persist run token: {}", err);
+        return None;
+    }
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        let _ = std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o600));
+    }
+    info!("[desktop:run] Created run token at {}", path.display());
+    Some(token)
+}
+
+pub fn run_token() -> Option<String> {
+    RUN_TOKEN.clone()
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RunRequest {
+    prompt: String,
+    agent: Option<String>,
+    directory: Option<String>,
+    #[serde(default)]
+    wait: bool,
+    /// Upper bound for `wait` mode, capped at one hour
+    timeout_seconds: Option<u64>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct RunResponse {
+    session_id: String,
+    directory: String,
+}
+
+fn error_response(status: StatusCode, message: impl Into<String>) -> Response<Body> {
+    (status, Json(json!({ "error": message.into() }))).into_response()
+}
+
+pub(crate) fn presented_token(headers: &HeaderMap) -> Option<&str> {
+    headers
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .or_else(|| {
+            headers
+                .get("x-openchamber-token")
+                .and_then(|value| value.to_str().ok())
+        })
+        .map(str::trim)
+}
+
+pub(crate) fn tokens_match(expected: &str, presented: &str) -> bool {
+    expected.len() == presented.len()
+        && expected
+            .bytes()
+            .zip(presented.bytes())
+            .fold(0u8, |acc, (a, b)| acc | (a ^ b))
+            == 0
+}
+
+fn validate(request: &RunRequest, default_dir: PathBuf) -> Result<PathBuf, String> {
+    let prompt = request.prompt.trim();
+    if prompt.is_empty() {
+        return Err("prompt is required".to_string());
+    }
+    if prompt.len() > MAX_PROMPT_BYTES {
+        return Err(format!("prompt exceeds {} bytes", MAX_PROMPT_BYTES));
+    }
+    if let Some(agent) = &request.agent {
+        if agent.trim().is_empty() || agent.len() > 128 {
+            return Err("agent must be a non-empty name".to_string());
+        }
+    }
+
+    let directory = match request.directory.as_deref().map(str::trim) {
+        Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => default_dir,
+    };
+    if !directory.is_absolute() || !directory.is_dir() {
+        return Err(format!("directory not found: {}", directory.display()));
+    }
+    Ok(directory)
+}
+
+/// `POST /api/openchamber/run`: create a session, send a prompt, and either return
+/// the session id immediately or stream its events as SSE with `"wait": true`.
+pub async fn run_handler(
+    State(state): State<ServerState>,
+    headers: HeaderMap,
+    Json(request): Json<RunRequest>,
+) -> Response<Body> {
+    let Some(expected) = run_token() else {
+        return error_response(
+            StatusCode::SERVICE_UNAVAILABLE,
+            "Run token unavailable; check the desktop logs",
+        );
+    };
+    match presented_token(&headers) {
+        Some(presented) if tokens_match(&expected, presented) => {}
+        _ => return error_response(StatusCode::UNAUTHORIZED, "Invalid or missing run token"),
+    }
+
+    let directory = match validate(&request, state.opencode.get_working_directory()) {
+        Ok(directory) => directory,
+        Err(message) => return error_response(StatusCode::BAD_REQUEST, message),
+    };
+    if let Some(runtime) = state.app.try_state::<DesktopRuntime>() {
+        if let Err(message) =
+            workspace_trust::require_trust(runtime.settings(), &directory, "quick runs").await
+        {
+            return error_response(StatusCode::FORBIDDEN, message);
+        }
+    }
+    let Some(port) = state.opencode.current_port() else {
+        return error_response(StatusCode::SERVICE_UNAVAILABLE, "OpenCode is not running");
+    };
+
+    let base = format!("http://127.0.0.1:{port}{}", state.opencode.api_prefix());
+    let directory_str = directory.to_string_lossy().to_string();
+    let opencode = OpenCodeClient::new(state.upstream.clone(), state.opencode.clone())
+        .with_directory(directory_str.clone());
+
+    let (session_id, events) =
+        match start_run(opencode, &state.client, &base, &directory_str, &request).await {
+            Ok(started) => started,
+            Err(message) => return error_response(StatusCode::BAD_GATEWAY, message),
+        };
+
+    info!(
+        "[desktop:run] Started external run {} in {}",
+        session_id, directory_str
+    );
+    let _ = state.app.emit(
+        EXTERNAL_RUN_EVENT,
+        json!({
+            "sessionId": session_id,
+            "directory": directory_str,
+            "agent": request.agent,
+        }),
+    );
+
+    match events {
+        None => Json(RunResponse {
+            session_id,
+            directory: directory_str,
+        })
+        .into_response(),
+        Some(rx) => {
+            let stream = stream::unfold(rx, |mut rx| async move {
+                rx.recv()
+                    .await
+                    .map(|data| (Ok::<_, Infallible>(Event::default().data(data)), rx))
+            });
+            Sse::new(stream)
+                .keep_alive(KeepAlive::default())
+                .into_response()
+        }
+    }
+}
+
+/// Create the session and send the prompt. In wait mode the session's events are
+/// subscribed to before prompting, so none are missed.
+async fn start_run(
+    opencode: OpenCodeClient,
+    client: &reqwest::Client,
+    base: &str,
+    directory: &str,
+    request: &RunRequest,
+) -> Result<(String, Option<mpsc::Receiver<String>>), String> {
+    let session_id = match opencode
+        .clone()
+        .with_timeout(OPENCODE_REQUEST_TIMEOUT)
+        .create_session()
+        .await
+    {
+        Ok(session) => session.id,
+        Err(err) => {
+            warn!("[desktop:run] Failed to create session: {}", err);
+            return Err(err.to_string());
+        }
+    };
+
+    let events = if request.wait {
+        let timeout = request
+            .timeout_seconds
+            .map(Duration::from_secs)
+            .unwrap_or(DEFAULT_WAIT_TIMEOUT)
+            .min(MAX_WAIT_TIMEOUT);
+        Some(subscribe_session_events(client, base, directory, &session_id, timeout).await?)
+    } else {
+        None
+    };
+
+    spawn_prompt(opencode, &session_id, request);
+    Ok((session_id, events))
+}
+
+/// The message endpoint only answers once the assistant finishes, so run it detached
+fn spawn_prompt(opencode: OpenCodeClient, session_id: &str, request: &RunRequest) {
+    let mut body = json!({
+        "parts": [{ "type": "text", "text": request.prompt.trim() }],
+    });
+    if let Some(agent) = &request.agent {
+        body["agent"] = json!(agent.trim());
+    }
+
+    let session_id = session_id.to_string();
+    tauri::async_runtime::spawn(async move {
+        match opencode.post_message(&session_id, &body).await {
+            Ok(message) => info!(
+                "[desktop:run] Prompt for {} finished with {} ({})",
+                session_id,
+                message.info.id,
+                message.info.finish.as_deref().unwrap_or("unknown")
+            ),
+            Err(err) => warn!("[desktop:run] Prompt for {} failed: {}", session_id, err),
+        }
+    });
+}
+
+fn event_session_id(event: &Value) -> Option<&str> {
+    let properties = event.get("properties")?;
+    properties
+        .get("sessionID")
+        .or_else(|| properties.get("info").and_then(|info| info.get("sessionID")))
+        .or_else(|| properties.get("part").and_then(|part| part.get("sessionID")))
+        .and_then(Value::as_str)
+}
+
+fn is_completion(event: &Value) -> bool {
+    match event.get("type").and_then(Value::as_str) {
+        Some("session.idle") | Some("session.error") => true,
+        Some("session.status") => {
+            event
+                .pointer("/properties/status/type")
+                .and_then(Value::as_str)
+                == Some("idle")
+        }
+        _ => false,
+    }
+}
+
+/// Forward this session's OpenCode events until it goes idle or the timeout elapses
+async fn subscribe_session_events(
+    client: &reqwest::Client,
+    base: &str,
+    directory: &str,
+    session_id: &str,
+    timeout: Duration,
+) -> Result<mpsc::Receiver<String>, String> {
+    let response = client
+        .get(format!("{base}/event"))
+        .query(&[("directory", directory)])
+        .header("accept", "text/event-stream")
+        .header("accept-encoding", "identity")
+        .send()
+        .await
+        .map_err(|err| err.to_string())?;
+    if !response.status().is_success() {
+        return Err(format!("event stream returned {}", response.status()));
+    }
+
+    let (tx, rx) = mpsc::channel(64);
+    let session_id = session_id.to_string();
+    tauri::async_runtime::spawn(async move {
+        let stream = response.bytes_stream().map_err(std::io::Error::other);
+        let mut reader = StreamReader::new(stream);
+
+        let forward = async {
+            let mut buf = Vec::new();
+            let mut data_lines: Vec<String> = Vec::new();
+            loop {
+                buf.clear();
+                match reader.read_until(b'\n', &mut buf).await {
+                    Ok(0) | Err(_) => return "stream closed",
+                    Ok(_) => {}
+                }
+                let line = String::from_utf8_lossy(&buf)
+                    .trim_end_matches(&['\r', '\n'][..])
+                    .to_string();
+
+                if let Some(rest) = line.strip_prefix("data:") {
+                    data_lines.push(rest.trim_start().to_string());
+                    continue;
+                }
+                if !line.is_empty() || data_lines.is_empty() {
+                    continue;
+                }
+
+                let raw = data_lines.join("\n");
+                data_lines.clear();
+                let Ok(event) = serde_json::from_str::<Value>(&raw) else {
+                    continue;
+                };
+                if event_session_id(&event) != Some(session_id.as_str()) {
+                    continue;
+                }
+                if tx.send(raw).await.is_err() {
+                    return "client disconnected";
+                }
+                if is_completion(&event) {
+                    return "completed";
+                }
+            }
+        };
+
+        let outcome = match tokio::time::timeout(timeout, forward).await {
+            Ok(reason) => reason,
+            Err(_) => {
+                let _ = tx
+                    .send(json!({ "type": "openchamber.run.timeout", "sessionId": session_id }).to_string())
+                    .await;
+                "timed out"
+            }
+        };
+        info!("[desktop:run] Stopped streaming {}: {}", session_id, outcome);
+    });
+
+    Ok(rx)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{opencode_manager::OpenCodeManager, upstream_pool::UpstreamPool};
+    use axum::{
+        extract::{Path, Query},
+        routing::{get, post},
+        Router,
+    };
+    use futures_util::StreamExt;
+    use std::{collections::HashMap, sync::Arc};
+
+    const RUN_SESSION: &str = "ses_run";
+
+    fn sse(event: Value) -> Result<axum::body::Bytes, Infallible> {
+        Ok(axum::body::Bytes::from(format!("data: {event}\n\n")))
+    }
+
+    fn idle(session_id: &str) -> Value {
+        json!({ "type": "session.idle", "properties": { "sessionID": session_id } })
+    }
+
+    /// OpenCode's session, message and event routes, reporting each request it
+    /// sees. With `finishes` unset the event stream stays open without going idle.
+    async fn mock_opencode(finishes: bool) -> (u16, mpsc::UnboundedReceiver<(String, Value)>) {
+        let (tx, requests) = mpsc::unbounded_channel();
+        let sessions = tx.clone();
+        let app = Router::new()
+            .route(
+                "/session",
+                post(
+                    move |Query(query): Query<HashMap<String, String>>| async move {
+                        let _ = sessions.send(("session".to_string(), json!(query)));
+                        Json(json!({ "id": RUN_SESSION }))
+                    },
+                ),
+            )
+            .route(
+                "/session/{id}/message",
+                post(
+                    move |Path(id): Path<String>, Json(body): Json<Value>| async move {
+                        let _ = tx.send((format!("message {id}"), body));
+                        Json(json!({ "info": { "id": "msg_1", "finish": "stop" } }))
+                    },
+                ),
+            )
+            .route(
+                "/event",
+                get(move || async move {
+                    let mut events = vec![
+                        sse(idle("ses_other")),
+                        sse(json!({
+                            "type": "message.part.updated",
+                            "properties": {
+                                "part": { "sessionID": RUN_SESSION, "text": "working" },
+                            },
+                        })),
+                    ];
+                    if finishes {
+                        events.push(sse(idle(RUN_SESSION)));
+                    }
+                    Body::from_stream(stream::iter(events).chain(stream::pending()))
+                }),
+            );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        tokio::spawn(async move {
+            axum::serve(listener, app).await.ok();
+        });
+        (port, requests)
+    }
+
+    async fn start(
+        finishes: bool,
+        request: Value,
+    ) -> (
+        (String, Option<mpsc::Receiver<String>>),
+        mpsc::UnboundedReceiver<(String, Value)>,
+    ) {
+        let (port, requests) = mock_opencode(finishes).await;
+        let opencode = Arc::new(OpenCodeManager::ready_on(
+            port,
+            PathBuf::from("/work/project"),
+        ));
+        let pool = Arc::new(UpstreamPool::new(opencode.clone()).unwrap());
+        let client = OpenCodeClient::new(pool, opencode).with_directory("/work/run");
+        let request: RunRequest = serde_json::from_value(request).unwrap();
+        let base = format!("http://127.0.0.1:{port}");
+        let started = start_run(
+            client,
+            &reqwest::Client::new(),
+            &base,
+            "/work/run",
+            &request,
+        )
+        .await
+        .unwrap();
+        (started, requests)
+    }
+
+    async fn next_request(
+        requests: &mut mpsc::UnboundedReceiver<(String, Value)>,
+    ) -> (String, Value) {
+        tokio::time::timeout(Duration::from_secs(5), requests.recv())
+            .await
+            .expect("no request reached OpenCode")
+            .unwrap()
+    }
+
+    async fn collect(mut events: mpsc::Receiver<String>) -> Vec<Value> {
+        let mut collected = Vec::new();
+        while let Some(raw) = tokio::time::timeout(Duration::from_secs(5), events.recv())
+            .await
+            .expect("the event stream never ended")
+        {
+            collected.push(serde_json::from_str(&raw).unwrap());
+        }
+        collected
+    }
+
+    #[test]
+    fn requests_are_validated() {
+        let workspace = std::env::temp_dir();
+        let check = |request: Value| {
+            let request: RunRequest = serde_json::from_value(request).unwrap();
+            validate(&request, workspace.clone())
+        };
+
+        assert_eq!(
+            check(json!({ "prompt": " fix it " })),
+            Ok(workspace.clone())
+        );
+        assert_eq!(
+            check(json!({ "prompt": "fix it", "directory": workspace })),
+            Ok(workspace.clone())
+        );
+        assert_eq!(
+            check(json!({ "prompt": "  " })),
+            Err("prompt is required".to_string())
+        );
+        assert!(check(json!({ "prompt": "x".repeat(MAX_PROMPT_BYTES + 1) })).is_err());
+        assert!(check(json!({ "prompt": "fix it", "agent": " " })).is_err());
+        assert!(check(json!({ "prompt": "fix it", "agent": "a".repeat(129) })).is_err());
+        assert!(check(json!({ "prompt": "fix it", "directory": "relative/dir" })).is_err());
+        let missing = workspace.join(uuid::Uuid::new_v4().to_string());
+        assert!(check(json!({ "prompt": "fix it", "directory": missing })).is_err());
+    }
+
+    #[test]
+    fn tokens_are_read_from_either_header() {
+        let mut headers = HeaderMap::new();
+        assert_eq!(presented_token(&headers), None);
+        headers.insert("x-openchamber-token", "from-header".parse().unwrap());
+        assert_eq!(presented_token(&headers), Some("from-header"));
+        headers.insert(
+            header::AUTHORIZATION,
+            "Bearer from-bearer ".parse().unwrap(),
+        );
+        assert_eq!(presented_token(&headers), Some("from-bearer"));
+
+        assert!(tokens_match("secret", "secret"));
+        assert!(!tokens_match("secret", "secreT"));
+        assert!(!tokens_match("secret", "secret2"));
+    }
+
+    #[test]
+    fn session_events_are_recognised() {
+        let part = json!({
+            "type": "message.part.updated",
+            "properties": { "part": { "sessionID": "a" } },
+        });
+        let info =
+            json!({ "type": "message.updated", "properties": { "info": { "sessionID": "b" } } });
+        assert_eq!(event_session_id(&part), Some("a"));
+        assert_eq!(event_session_id(&info), Some("b"));
+        assert_eq!(
+            event_session_id(&json!({ "type": "server.connected" })),
+            None
+        );
+
+        assert!(is_completion(&json!({ "type": "session.idle" })));
+        assert!(is_completion(&json!({ "type": "session.error" })));
+        assert!(is_completion(
+            &json!({ "type": "session.status", "properties": { "status": { "type": "idle" } } })
+        ));
+        assert!(!is_completion(
+            &json!({ "type": "session.status", "properties": { "status": { "type": "busy" } } })
+        ));
+        assert!(!is_completion(&part));
+    }
+
+    #[tokio::test]
+    async fn a_run_creates_a_session_and_sends_the_prompt() {
+        let ((session_id, events), mut requests) = start(
+            true,
+            json!({ "prompt": "  fix the build  ", "agent": " build-fixer " }),
+        )
+        .await;
+        assert_eq!(session_id, RUN_SESSION);
+        assert!(events.is_none());
+
+        assert_eq!(
+            next_request(&mut requests).await,
+            ("session".to_string(), json!({ "directory": "/work/run" }))
+        );
+        assert_eq!(
+            next_request(&mut requests).await,
+            (
+                format!("message {RUN_SESSION}"),
+                json!({
+                    "parts": [{ "type": "text", "text": "fix the build" }],
+                    "agent": "build-fixer",
+                })
+            )
+        );
+    }
+
+    #[tokio::test]
+    async fn waiting_streams_the_sessions_events_until_it_is_idle() {
+        let ((_, events), _requests) =
+            start(true, json!({ "prompt": "fix it", "wait": true })).await;
+
+        let types: Vec<_> = collect(events.unwrap())
+            .await
+            .iter()
+            .map(|event| event["type"].as_str().unwrap().to_string())
+            .collect();
+        assert_eq!(types, ["message.part.updated", "session.idle"]);
+    }
+
+    #[tokio::test]
+    async fn waiting_ends_with_a_timeout_event() {
+        let ((_, events), _requests) = start(
+            false,
+            json!({ "prompt": "fix it", "wait": true, "timeoutSeconds": 1 }),
+        )
+        .await;
+
+        let events = collect(events.unwrap()).await;
+        assert_eq!(events.len(), 2);
+        assert_eq!(
+            events[1],
+            json!({ "type": "openchamber.run.timeout", "sessionId": RUN_SESSION })
+        );
+    }
+}
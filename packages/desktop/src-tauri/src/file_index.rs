@@ -0,0 +1,508 @@
+use std::{
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use chrono::{DateTime, Utc};
+use log::{debug, info};
+use parking_lot::Mutex;
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::commands::files::should_skip_directory;
+
+/// Upper bound on indexed paths; larger trees are truncated and searched by the walker
+const MAX_INDEXED_FILES: usize = 300_000;
+/// Ready indexes older than this are rebuilt in the background while still answering
+const INDEX_STALE_AFTER: Duration = Duration::from_secs(60);
+
+/// `fileSearch.indexing` in settings; off by default
+pub fn indexing_enabled(settings: &Value) -> bool {
+    settings
+        .get("fileSearch")
+        .and_then(|search| search.get("indexing"))
+        .and_then(Value::as_bool)
+        .unwrap_or(false)
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum IndexPhase {
+    Cold,
+    Building,
+    Ready,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct IndexStatus {
+    root: Option<String>,
+    state: IndexPhase,
+    file_count: usize,
+    last_refresh: Option<DateTime<Utc>>,
+    /// The tree had more than MAX_INDEXED_FILES files
+    truncated: bool,
+}
+
+struct IndexState {
+    root: Option<PathBuf>,
+    /// Paths relative to `root`, `/`-separated and sorted
+    files: Arc<Vec<Box<str>>>,
+    phase: IndexPhase,
+    truncated: bool,
+    last_refresh: Option<DateTime<Utc>>,
+    refreshed_at: Option<Instant>,
+    /// Bumped per rebuild so a slow walk can't overwrite a newer one
+    generation: u64,
+}
+
+impl Default for IndexState {
+    fn default() -> Self {
+        Self {
+            root: None,
+            files: Arc::new(Vec::new()),
+            phase: IndexPhase::Cold,
+            truncated: false,
+            last_refresh: None,
+            refreshed_at: None,
+            generation: 0,
+        }
+    }
+}
+
+/// In-memory list of workspace file paths so file search can skip the disk walk.
+/// Only one root is indexed at a time; searches elsewhere fall back to the walker.
+#[derive(Clone, Default)]
+pub struct FileIndex {
+    state: Arc<Mutex<IndexState>>,
+}
+
+impl FileIndex {
+    pub fn status(&self) -> IndexStatus {
+        let state = self.state.lock();
+        IndexStatus {
+            root: state
+                .root
+                .as_ref()
+                .map(|root| root.to_string_lossy().to_string()),
+            state: state.phase,
+            file_count: state.files.len(),
+            last_refresh: state.last_refresh,
+            truncated: state.truncated,
+        }
+    }
+
+    /// Drop the index, e.g. when indexing is turned off
+    pub fn clear(&self) {
+        let mut state = self.state.lock();
+        let generation = state.generation + 1;
+        *state = IndexState {
+            generation,
+            ..IndexState::default()
+        };
+    }
+
+    /// Start indexing `root` unless it is already indexed or being indexed
+    pub fn ensure(&self, root: PathBuf) {
+        if self.state.lock().root.as_ref() != Some(&root) {
+            self.rebuild(root);
+        }
+    }
+
+    /// Walk `root` in the background and swap the result in when done.
+    /// The previous index keeps answering for the same root until then.
+    pub fn rebuild(&self, root: PathBuf) {
+        let generation = {
+            let mut state = self.state.lock();
+            state.generation += 1;
+            if state.root.as_ref() != Some(&root) {
+                state.files = Arc::new(Vec::new());
+                state.truncated = false;
+                state.last_refresh = None;
+                state.refreshed_at = None;
+            }
+            state.root = Some(root.clone());
+            state.phase = IndexPhase::Building;
+            state.generation
+        };
+
+        let shared = self.state.clone();
+        tauri::async_runtime::spawn(async move {
+            let started = Instant::now();
+            let walk_root = root.clone();
+            let Ok((files, truncated)) =
+                tokio::task::spawn_blocking(move || walk(&walk_root)).await
+            else {
+                return;
+            };
+
+            let mut state = shared.lock();
+            if state.generation != generation {
+                debug!(
+                    "[desktop:index] Discarding superseded index of {}",
+                    root.display()
+                );
+                return;
+            }
+            info!(
+                "[desktop:index] Indexed {} files under {} in {:?}{}",
+                files.len(),
+                root.display(),
+                started.elapsed(),
+                if truncated { " (truncated)" } else { "" }
+            );
+            state.files = Arc::new(files);
+            state.truncated = truncated;
+            state.phase = IndexPhase::Ready;
+            state.last_refresh = Some(Utc::now());
+            state.refreshed_at = Some(Instant::now());
+        });
+    }
+
+    /// Answer a search under `scope` from the index, returning paths relative to
+    /// `scope`. None means the index can't answer (cold, other root, truncated)
+    /// and the caller should walk the disk.
+    pub fn search(&self, scope: &Path, query: &str, limit: usize) -> Option<Vec<String>> {
+        let (root, files, stale) = {
+            let state = self.state.lock();
+            let root = state.root.clone()?;
+            if state.files.is_empty() && state.phase != IndexPhase::Ready {
+                return None;
+            }
+            if state.truncated || !scope.starts_with(&root) {
+                return None;
+            }
+            let stale = state.phase == IndexPhase::Ready
+                && state
+                    .refreshed_at
+                    .is_some_and(|at| at.elapsed() > INDEX_STALE_AFTER);
+            (root, state.files.clone(), stale)
+        };
+        if stale {
+            self.rebuild(root.clone());
+        }
+
+        let prefix = scope
+            .strip_prefix(&root)
+            .ok()?
+            .to_string_lossy()
+            .replace('\\', "/");
+        let prefix = if prefix.is_empty() {
+            prefix
+        } else {
+            format!("{}/", prefix.trim_end_matches('/'))
+        };
+        let query = query.trim().to_lowercase();
+
+        let mut scored: Vec<(i64, &str)> = files
+            .iter()
+            .filter_map(|path| {
+                let relative = path.strip_prefix(prefix.as_str())?;
+                let name = relative.rsplit('/').next().unwrap_or(relative);
+                fuzzy_score(&query, name, relative).map(|score| (score, relative))
+            })
+            .collect();
+        scored.sort_by(|a, b| {
+            b.0.cmp(&a.0)
+                .then(a.1.len().cmp(&b.1.len()))
+                .then(a.1.cmp(b.1))
+        });
+
+        Some(
+            scored
+                .into_iter()
+                .take(limit)
+                .map(|(_, path)| path.to_string())
+                .collect(),
+        )
+    }
+}
+
+/// Walk `root` with the same exclusions as the live search
+fn walk(root: &Path) -> (Vec<Box<str>>, bool) {
+    let mut files = Vec::new();
+    let mut pending = vec![root.to_path_buf()];
+    while let Some(dir) = pending.pop() {
+        let Ok(entries) = std::fs::read_dir(&dir) else {
+            continue;
+        };
+        for entry in entries.flatten() {
+            let Ok(file_type) = entry.file_type() else {
+                continue;
+            };
+            let name = entry.file_name();
+            let name = name.to_string_lossy();
+            if name.is_empty() || name.starts_with('.') {
+                continue;
+            }
+
+            let path = entry.path();
+            if file_type.is_dir() {
+                if !should_skip_directory(&name) {
+                    pending.push(path);
+                }
+                continue;
+            }
+            if !file_type.is_file() {
+                continue;
+            }
+            if files.len() >= MAX_INDEXED_FILES {
+                return (files, true);
+            }
+            if let Ok(relative) = path.strip_prefix(root) {
+                files.push(
+                    relative
+                        .to_string_lossy()
+                        .replace('\\', "/")
+                        .into_boxed_str(),
+                );
+            }
+        }
+    }
+    files.sort_unstable();
+    (files, false)
+}
+
+fn contains_ignore_case(haystack: &str, needle: &str) -> bool {
+    if needle.len() > haystack.len() {
+        return false;
+    }
+    haystack
+        .as_bytes()
+        .windows(needle.len())
+        .any(|window| window.eq_ignore_ascii_case(needle.as_bytes()))
+}
+
+/// Score `query` (already lowercased) against a file: exact and prefix name matches
+/// first, then substrings of the name or path, then in-order character matches
+/// where contiguous runs score higher. None when the query doesn't match.
+fn fuzzy_score(query: &str, name: &str, path: &str) -> Option<i64> {
+    if query.is_empty() {
+        return Some(0);
+    }
+    if name.eq_ignore_ascii_case(query) {
+        return Some(1000);
+    }
+    if name.len() >= query.len()
+        && name.as_bytes()[..query.len()].eq_ignore_ascii_case(query.as_bytes())
+    {
+        return Some(800);
+    }
+    if contains_ignore_case(name, query) {
+        return Some(600);
+    }
+    if contains_ignore_case(path, query) {
+        return Some(400);
+    }
+
+    let mut needle = query.bytes().peekable();
+    let mut score = 0i64;
+    let mut run = 0i64;
+    for byte in path.bytes() {
+        let Some(&wanted) = needle.peek() else {
+            break;
+        };
+        if byte.to_ascii_lowercase() == wanted {
+            needle.next();
+            run += 1;
+            score += run;
+        } else {
+            run = 0;
+        }
+    }
+    if needle.peek().is_some() {
+        return None;
+    }
+    Some(score.min(300))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    /// A file tree under the system temp dir, removed on drop
+    struct TempTree {
+        root: PathBuf,
+    }
+
+    impl TempTree {
+        fn new(files: &[&str]) -> Self {
+            let root =
+                std::env::temp_dir().join(format!("openchamber-index-{}", uuid::Uuid::new_v4()));
+            std::fs::create_dir_all(&root).unwrap();
+            let tree = Self { root };
+            for file in files {
+                tree.create(file);
+            }
+            tree
+        }
+
+        fn create(&self, relative: &str) {
+            let path = self.root.join(relative);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, relative).unwrap();
+        }
+    }
+
+    impl Drop for TempTree {
+        fn drop(&mut self) {
+            std::fs::remove_dir_all(&self.root).ok();
+        }
+    }
+
+    /// Wait for the background walk of `root` to land
+    async fn ready(index: &FileIndex, root: &Path) {
+        let root = root.to_string_lossy().to_string();
+        for _ in 0..500 {
+            let status = index.status();
+            if status.state == IndexPhase::Ready && status.root.as_ref() == Some(&root) {
+                return;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        panic!("index of {root} never became ready");
+    }
+
+    fn search(index: &FileIndex, scope: &Path, query: &str) -> Vec<String> {
+        index.search(scope, query, 100).expect("index answers")
+    }
+
+    #[test]
+    fn indexing_is_opt_in() {
+        assert!(!indexing_enabled(&json!({})));
+        assert!(!indexing_enabled(&json!({ "fileSearch": {} })));
+        assert!(!indexing_enabled(
+            &json!({ "fileSearch": { "indexing": "yes" } })
+        ));
+        assert!(indexing_enabled(
+            &json!({ "fileSearch": { "indexing": true } })
+        ));
+    }
+
+    #[test]
+    fn names_outrank_paths_and_contiguous_runs_outrank_scattered_ones() {
+        let score = |query: &str, path: &str| {
+            let name = path.rsplit('/').next().unwrap();
+            fuzzy_score(query, name, path)
+        };
+
+        assert_eq!(score("", "src/main.rs"), Some(0));
+        assert_eq!(score("main.rs", "src/Main.RS"), Some(1000));
+        assert_eq!(score("main", "src/main.rs"), Some(800));
+        assert_eq!(score("ain", "src/main.rs"), Some(600));
+        assert_eq!(score("src/m", "src/main.rs"), Some(400));
+        assert!(score("smr", "src/main.rs").unwrap() < 400);
+        assert!(score("main", "src/m_a_i_n.rs") < score("mai", "src/xmaix/n.rs"));
+        assert_eq!(score("zz", "src/main.rs"), None);
+        assert_eq!(score("nm", "src/main.rs"), None);
+    }
+
+    #[tokio::test]
+    async fn walks_skip_hidden_and_excluded_directories() {
+        let tree = TempTree::new(&[
+            "src/lib.rs",
+            "README.md",
+            ".env",
+            ".git/config",
+            "node_modules/dep/index.js",
+            "docs/guide/intro.md",
+        ]);
+
+        let (files, truncated) = walk(&tree.root);
+        let files: Vec<&str> = files.iter().map(|file| &**file).collect();
+        assert_eq!(files, ["README.md", "docs/guide/intro.md", "src/lib.rs"]);
+        assert!(!truncated);
+    }
+
+    #[tokio::test]
+    async fn searches_are_answered_relative_to_their_scope() {
+        let tree = TempTree::new(&["src/main.rs", "src/util/mod.rs", "main.rs", "docs/main.md"]);
+        let index = FileIndex::default();
+        index.ensure(tree.root.clone());
+        ready(&index, &tree.root).await;
+
+        assert_eq!(index.status().file_count, 4);
+        assert!(index.status().last_refresh.is_some());
+        assert_eq!(
+            search(&index, &tree.root, "main"),
+            ["main.rs", "src/main.rs", "docs/main.md"]
+        );
+        assert_eq!(
+            search(&index, &tree.root.join("src"), ""),
+            ["main.rs", "util/mod.rs"]
+        );
+        assert_eq!(
+            search(&index, &tree.root.join("src/"), "mod"),
+            ["util/mod.rs"]
+        );
+        assert_eq!(index.search(&tree.root, "", 2).unwrap().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn the_walker_answers_when_the_index_cannot() {
+        let tree = TempTree::new(&["a.rs"]);
+        let index = FileIndex::default();
+        assert_eq!(index.status().state, IndexPhase::Cold);
+        assert!(index.search(&tree.root, "", 10).is_none());
+
+        index.ensure(tree.root.clone());
+        ready(&index, &tree.root).await;
+        assert!(index.search(&tree.root, "", 10).is_some());
+        assert!(index.search(&std::env::temp_dir(), "", 10).is_none());
+
+        index.state.lock().truncated = true;
+        assert!(index.search(&tree.root, "", 10).is_none());
+
+        index.clear();
+        let status = index.status();
+        assert_eq!(status.state, IndexPhase::Cold);
+        assert_eq!(status.root, None);
+        assert!(index.search(&tree.root, "", 10).is_none());
+    }
+
+    #[tokio::test]
+    async fn a_newer_rebuild_wins_over_a_slower_one() {
+        let first = TempTree::new(&["first.rs"]);
+        let second = TempTree::new(&["second.rs"]);
+        let index = FileIndex::default();
+
+        index.rebuild(first.root.clone());
+        index.rebuild(second.root.clone());
+        ready(&index, &second.root).await;
+        tokio::time::sleep(Duration::from_millis(50)).await;
+
+        assert_eq!(search(&index, &second.root, ""), ["second.rs"]);
+        assert_eq!(
+            index.status().root,
+            Some(second.root.to_string_lossy().to_string())
+        );
+    }
+
+    #[tokio::test]
+    async fn stale_indexes_keep_answering_while_they_refresh() {
+        let tree = TempTree::new(&["old.rs"]);
+        let index = FileIndex::default();
+        index.ensure(tree.root.clone());
+        ready(&index, &tree.root).await;
+
+        tree.create("new.rs");
+        // Ensuring the same root is a no-op; only a refresh picks up changes
+        index.ensure(tree.root.clone());
+        assert_eq!(search(&index, &tree.root, ""), ["old.rs"]);
+
+        index.state.lock().refreshed_at =
+            Instant::now().checked_sub(INDEX_STALE_AFTER + Duration::from_secs(1));
+        assert_eq!(search(&index, &tree.root, ""), ["old.rs"]);
+
+        // The stale search started a background walk that swaps in the new tree
+        for _ in 0..500 {
+            if search(&index, &tree.root, "").len() == 2 {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        assert_eq!(search(&index, &tree.root, ""), ["new.rs", "old.rs"]);
+    }
+}
@@ -0,0 +1,504 @@
+use std::{
+    fs,
+    io::{Read, Seek, SeekFrom},
+    path::PathBuf,
+};
+
+use log::{info, warn};
+use once_cell::sync::OnceCell;
+use serde::{Deserialize, Serialize};
+
+use crate::{logging, paths};
+
+/// Event emitted at startup when the previous run did not shut down cleanly
+pub const UNCLEAN_SHUTDOWN_EVENT: &str = "openchamber:unclean-shutdown";
+
+const HEARTBEAT_FILE: &str = "heartbeat.json";
+const CRASH_REPORTS_DIR: &str = "crash-reports";
+/// Amount of the previous log kept in a crash report
+const LOG_TAIL_BYTES: u64 = 256 * 1024;
+const MAX_CRASH_REPORTS: usize = 10;
+
+/// Written at startup and removed on clean exit, so a leftover file means the
+/// process that wrote it died without shutting down
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Heartbeat {
+    pid: u32,
+    started_at: String,
+    version: String,
+    /// OS-specific process identity (start time where available) so a reused
+    /// pid isn't mistaken for the original process
+    #[serde(default)]
+    identity: Option<String>,
+}
+
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UncleanShutdown {
+    pub previous_started_at: String,
+    pub previous_version: String,
+    /// Log snapshot taken for the crash, if the log could be read
+    pub crash_report: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CrashReport {
+    pub file_name: String,
+    pub path: String,
+    pub size_bytes: u64,
+}
+
+static PREVIOUS_SESSION: OnceCell<Option<UncleanShutdown>> = OnceCell::new();
+
+/// Where the heartbeat, the log it snapshots and the crash reports live
+struct HeartbeatFiles {
+    heartbeat: PathBuf,
+    log: Option<PathBuf>,
+    crash_reports: PathBuf,
+}
+
+impl HeartbeatFiles {
+    fn current() -> Option<Self> {
+        let state = paths::state_dir()?;
+        Some(Self {
+            heartbeat: state.join(HEARTBEAT_FILE),
+            log: logging::log_file_path(),
+            crash_reports: state.join(CRASH_REPORTS_DIR),
+        })
+    }
+
+    fn read(&self) -> Option<Heartbeat> {
+        fs::read(&self.heartbeat)
+            .ok()
+            .and_then(|content| serde_json::from_slice(&content).ok())
+    }
+
+    /// Check for a leftover heartbeat from a dead process, then write ours
+    fn start(&self, version: &str) -> Option<UncleanShutdown> {
+        let unclean = self
+            .read()
+            .filter(|heartbeat| !still_running(heartbeat))
+            .map(|heartbeat| UncleanShutdown {
+                crash_report: self
+                    .snapshot_log_tail(&heartbeat.started_at)
+                    .map(|report| report.to_string_lossy().to_string()),
+                previous_started_at: heartbeat.started_at,
+                previous_version: heartbeat.version,
+            });
+
+        let pid = std::process::id();
+        let heartbeat = Heartbeat {
+            pid,
+            started_at: chrono::Utc::now().to_rfc3339(),
+            version: version.to_string(),
+            identity: process_identity(pid),
+        };
+        if let Some(parent) = self.heartbeat.parent() {
+            let _ = fs::create_dir_all(parent);
+        }
+        if let Ok(content) = serde_json::to_vec_pretty(&heartbeat) {
+            let _ = fs::write(&self.heartbeat, content);
+        }
+        unclean
+    }
+
+    fn clear(&self) {
+        // Only our own file; a second instance may have written its own since
+        let ours = self
+            .read()
+            .is_some_and(|heartbeat| heartbeat.pid == std::process::id());
+        if ours {
+            let _ = fs::remove_file(&self.heartbeat);
+        }
+    }
+
+    /// Crash report snapshots, newest first
+    fn crash_reports(&self) -> Vec<CrashReport> {
+        let Ok(entries) = fs::read_dir(&self.crash_reports) else {
+            return Vec::new();
+        };
+        let mut reports: Vec<CrashReport> = entries
+            .flatten()
+            .filter(|entry| entry.path().is_file())
+            .map(|entry| CrashReport {
+                file_name: entry.file_name().to_string_lossy().to_string(),
+                path: entry.path().to_string_lossy().to_string(),
+                size_bytes: entry.metadata().map(|meta| meta.len()).unwrap_or(0),
+            })
+            .collect();
+        // Names start with a sortable timestamp
+        reports.sort_by(|a, b| b.file_name.cmp(&a.file_name));
+        reports
+    }
+
+    /// Copy the end of the desktop log into the crash-report folder
+    fn snapshot_log_tail(&self, previous_started_at: &str) -> Option<PathBuf> {
+        let mut file = fs::File::open(self.log.as_ref()?).ok()?;
+        let len = file.metadata().ok()?.len();
+        file.seek(SeekFrom::Start(len.saturating_sub(LOG_TAIL_BYTES)))
+            .ok()?;
+        let mut tail = Vec::new();
+        file.read_to_end(&mut tail).ok()?;
+        // Drop the partial first line when the log was cut
+        if len > LOG_TAIL_BYTES {
+            if let Some(newline) = tail.iter().position(|byte| *byte == b'\n') {
+                tail.drain(..=newline);
+            }
+        }
+
+        fs::create_dir_all(&self.crash_reports).ok()?;
+        let stamp = chrono::Utc::now().format("%Y%m%dT%H%M%SZ");
+        let report = self.crash_reports.join(format!("{stamp}-desktop.log"));
+        let mut content = format!(
+            "# Previous session started {} ended unexpectedly\n",
+            previous_started_at
+        )
+        .into_bytes();
+        content.extend_from_slice(&tail);
+        fs::write(&report, content).ok()?;
+
+        self.prune_crash_reports();
+        Some(report)
+    }
+
+    fn prune_crash_reports(&self) {
+        for report in self.crash_reports().into_iter().skip(MAX_CRASH_REPORTS) {
+            let _ = fs::remove_file(report.path);
+        }
+    }
+}
+
+/// Check for a leftover heartbeat from a dead process, then write ours. Runs
+/// before logging is set up so the previous log tail is snapshotted untouched.
+pub fn start(version: &str) {
+    let unclean = HeartbeatFiles::current().and_then(|files| files.start(version));
+    let _ = PREVIOUS_SESSION.set(unclean);
+}
+
+/// Remove the heartbeat on clean shutdown
+pub fn clear() {
+    if let Some(files) = HeartbeatFiles::current() {
+        files.clear();
+    }
+}
+
+/// Details of the previous run when it ended unexpectedly
+pub fn unclean_shutdown() -> Option<UncleanShutdown> {
+    PREVIOUS_SESSION.get().cloned().flatten()
+}
+
+/// Log startup findings once logging is available
+pub fn log_startup() {
+    if let Some(previous) = unclean_shutdown() {
+        warn!(
+            "[desktop:heartbeat] Previous session (started {}, v{}) ended unexpectedly",
+            previous.previous_started_at, previous.previous_version
+        );
+        if let Some(report) = &previous.crash_report {
+            info!("[desktop:heartbeat] Saved previous log tail to {}", report);
+        }
+    }
+}
+
+/// Crash report snapshots, newest first
+pub fn list_crash_reports() -> Vec<CrashReport> {
+    HeartbeatFiles::current()
+        .map(|files| files.crash_reports())
+        .unwrap_or_default()
+}
+
+/// The recorded process is alive and is the same process, not a reused pid
+fn still_running(heartbeat: &Heartbeat) -> bool {
+    if heartbeat.pid == std::process::id() {
+        return false;
+    }
+    match (process_identity(heartbeat.pid), &heartbeat.identity) {
+        (None, _) => false,
+        (Some(current), Some(recorded)) => &current == recorded,
+        // Alive but unverifiable; assume it is a running instance
+        (Some(_), None) => true,
+    }
+}
+
+/// Identity of a live process: its start time in clock ticks since boot
+#[cfg(target_os = "linux")]
+fn process_identity(pid: u32) -> Option<String> {
+    let stat = fs::read_to_string(format!("/proc/{pid}/stat")).ok()?;
+    // The command name may contain spaces, so fields are counted after its closing paren
+    let (_, fields) = stat.rsplit_once(')')?;
+    fields.split_whitespace().nth(19).map(str::to_string)
+}
+
+/// Identity of a live process: its start time as printed by ps
+#[cfg(target_os = "macos")]
+fn process_identity(pid: u32) -> Option<String> {
+    let output = std::process::Command::new("ps")
+        .args(["-o", "lstart=", "-p", &pid.to_string()])
+        .output()
+        .ok()?;
+    let started = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    (output.status.success() && !started.is_empty()).then_some(started)
+}
+
+/// Identity of a live process: its image name (Windows exposes no start time
+/// without extra APIs, so this only rules out reuse by other programs)
+#[cfg(windows)]
+fn process_identity(pid: u32) -> Option<String> {
+    use std::os::windows::process::CommandExt;
+    const CREATE_NO_WINDOW: u32 = 0x0800_0000;
+
+    let output = std::process::Command::new("tasklist")
+        .args(["/FI", &format!("PID eq {pid}"), "/FO", "CSV", "/NH"])
+        .creation_flags(CREATE_NO_WINDOW)
+        .output()
+        .ok()?;
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let line = stdout.lines().find(|line| line.starts_with('"'))?;
+    line.split("\",\"")
+        .next()
+        .map(|image| image.trim_matches('"').to_string())
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos", windows)))]
+fn process_identity(_pid: u32) -> Option<String> {
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::Path;
+
+    /// State and log directories under the system temp dir, removed on drop
+    struct TempState {
+        root: PathBuf,
+        files: HeartbeatFiles,
+    }
+
+    impl TempState {
+        fn new() -> Self {
+            let root = std::env::temp_dir()
+                .join(format!("openchamber-heartbeat-{}", uuid::Uuid::new_v4()));
+            fs::create_dir_all(root.join("logs")).unwrap();
+            let files = HeartbeatFiles {
+                heartbeat: root.join(HEARTBEAT_FILE),
+                log: Some(root.join("logs").join("desktop.log")),
+                crash_reports: root.join(CRASH_REPORTS_DIR),
+            };
+            Self { root, files }
+        }
+
+        /// Fabricate the heartbeat a previous run left behind
+        fn leave_heartbeat(&self, pid: u32, identity: Option<String>) {
+            let heartbeat = Heartbeat {
+                pid,
+                started_at: "2026-01-02T03:04:05+00:00".to_string(),
+                version: "0.9.0".to_string(),
+                identity,
+            };
+            fs::write(
+                &self.files.heartbeat,
+                serde_json::to_vec(&heartbeat).unwrap(),
+            )
+            .unwrap();
+        }
+
+        fn write_log(&self, content: &[u8]) {
+            fs::write(self.files.log.as_ref().unwrap(), content).unwrap();
+        }
+    }
+
+    impl Drop for TempState {
+        fn drop(&mut self) {
+            fs::remove_dir_all(&self.root).ok();
+        }
+    }
+
+    /// A pid that belonged to a process which has since exited
+    fn dead_pid() -> u32 {
+        let mut child = std::process::Command::new("true").spawn().unwrap();
+        let pid = child.id();
+        child.wait().unwrap();
+        pid
+    }
+
+    /// A live process, killed on drop
+    struct Running(std::process::Child);
+
+    impl Running {
+        fn start() -> Self {
+            Self(
+                std::process::Command::new("sleep")
+                    .arg("30")
+                    .spawn()
+                    .unwrap(),
+            )
+        }
+
+        fn pid(&self) -> u32 {
+            self.0.id()
+        }
+    }
+
+    impl Drop for Running {
+        fn drop(&mut self) {
+            self.0.kill().ok();
+            self.0.wait().ok();
+        }
+    }
+
+    fn report_content(path: &str) -> String {
+        fs::read_to_string(Path::new(path)).unwrap()
+    }
+
+    #[test]
+    fn a_first_launch_is_clean_and_writes_a_heartbeat() {
+        let state = TempState::new();
+
+        assert!(state.files.start("1.0.0").is_none());
+
+        let heartbeat = state.files.read().unwrap();
+        assert_eq!(heartbeat.pid, std::process::id());
+        assert_eq!(heartbeat.version, "1.0.0");
+        assert!(chrono::DateTime::parse_from_rfc3339(&heartbeat.started_at).is_ok());
+    }
+
+    #[test]
+    fn a_heartbeat_left_by_a_dead_process_is_an_unclean_shutdown() {
+        let state = TempState::new();
+        state.leave_heartbeat(dead_pid(), Some("12345".to_string()));
+        state.write_log(b"first line\nlast words\n");
+
+        let unclean = state.files.start("1.0.0").unwrap();
+        assert_eq!(unclean.previous_started_at, "2026-01-02T03:04:05+00:00");
+        assert_eq!(unclean.previous_version, "0.9.0");
+        let report = report_content(unclean.crash_report.as_deref().unwrap());
+        assert_eq!(
+            report,
+            "# Previous session started 2026-01-02T03:04:05+00:00 ended unexpectedly\n\
+             first line\nlast words\n"
+        );
+
+        // Our own heartbeat replaced the stale one, so the next launch is clean
+        assert_eq!(state.files.read().unwrap().pid, std::process::id());
+        state.files.clear();
+        assert!(state.files.start("1.0.0").is_none());
+    }
+
+    #[test]
+    fn a_missing_log_still_reports_the_crash() {
+        let state = TempState::new();
+        state.leave_heartbeat(dead_pid(), None);
+
+        let unclean = state.files.start("1.0.0").unwrap();
+        assert!(unclean.crash_report.is_none());
+        assert!(state.files.crash_reports().is_empty());
+    }
+
+    #[test]
+    fn unreadable_heartbeats_are_ignored() {
+        let state = TempState::new();
+        fs::write(&state.files.heartbeat, "{ not json").unwrap();
+
+        assert!(state.files.start("1.0.0").is_none());
+        assert_eq!(state.files.read().unwrap().pid, std::process::id());
+    }
+
+    #[test]
+    fn a_heartbeat_with_our_own_pid_is_stale() {
+        let state = TempState::new();
+        state.leave_heartbeat(std::process::id(), process_identity(std::process::id()));
+
+        assert!(state.files.start("1.0.0").is_some());
+    }
+
+    #[cfg(any(target_os = "linux", target_os = "macos"))]
+    #[test]
+    fn a_running_instance_is_not_a_crash() {
+        let state = TempState::new();
+        let other = Running::start();
+        state.leave_heartbeat(other.pid(), process_identity(other.pid()));
+
+        assert!(state.files.start("1.0.0").is_none());
+    }
+
+    #[cfg(any(target_os = "linux", target_os = "macos"))]
+    #[test]
+    fn a_reused_pid_is_told_apart_by_its_start_time() {
+        let state = TempState::new();
+        let reused = Running::start();
+        state.leave_heartbeat(reused.pid(), Some("started long ago".to_string()));
+
+        assert!(state.files.start("1.0.0").is_some());
+    }
+
+    #[cfg(any(target_os = "linux", target_os = "macos"))]
+    #[test]
+    fn a_live_pid_without_a_recorded_identity_is_assumed_running() {
+        let state = TempState::new();
+        let other = Running::start();
+        state.leave_heartbeat(other.pid(), None);
+
+        assert!(state.files.start("1.0.0").is_none());
+    }
+
+    #[test]
+    fn clearing_only_removes_our_own_heartbeat() {
+        let state = TempState::new();
+        state.leave_heartbeat(dead_pid(), None);
+        state.files.clear();
+        assert!(state.files.heartbeat.exists());
+
+        state.files.start("1.0.0");
+        state.files.clear();
+        assert!(!state.files.heartbeat.exists());
+    }
+
+    #[test]
+    fn long_logs_are_cut_at_a_line_boundary() {
+        let state = TempState::new();
+        let line = "x".repeat(99) + "\n";
+        let mut log = "partial".repeat(10).into_bytes();
+        for _ in 0..(LOG_TAIL_BYTES as usize / line.len() + 10) {
+            log.extend_from_slice(line.as_bytes());
+        }
+        log.extend_from_slice(b"the end\n");
+        state.write_log(&log);
+
+        let report = state.files.snapshot_log_tail("earlier").unwrap();
+        let content = fs::read_to_string(report).unwrap();
+        let body = content
+            .strip_prefix("# Previous session started earlier ended unexpectedly\n")
+            .unwrap();
+        assert!(body.len() <= LOG_TAIL_BYTES as usize);
+        assert!(body.starts_with(&line));
+        assert!(body.ends_with("the end\n"));
+        assert!(!body.contains("partial"));
+    }
+
+    #[test]
+    fn only_the_newest_crash_reports_are_kept() {
+        let state = TempState::new();
+        fs::create_dir_all(&state.files.crash_reports).unwrap();
+        for i in 0..MAX_CRASH_REPORTS + 2 {
+            let name = format!("2000010{}T000000Z-desktop.log", i % 10) + &"_".repeat(i / 10);
+            fs::write(state.files.crash_reports.join(name), "old").unwrap();
+        }
+        state.write_log(b"log\n");
+
+        let newest = state.files.snapshot_log_tail("earlier").unwrap();
+        let reports = state.files.crash_reports();
+        assert_eq!(reports.len(), MAX_CRASH_REPORTS);
+        assert_eq!(reports[0].path, newest.to_string_lossy());
+        assert!(reports
+            .windows(2)
+            .all(|pair| pair[0].file_name > pair[1].file_name));
+        assert!(!state
+            .files
+            .crash_reports
+            .join("20000100T000000Z-desktop.log")
+            .exists());
+    }
+}
@@ -1,16 +1,9 @@
 use std::path::PathBuf;
 
-#[cfg(target_os = "macos")]
-const PLATFORM_LOG_SEGMENTS: &[&str] = &["Library", "Logs", "OpenChamber"];
-#[cfg(not(target_os = "macos"))]
-const PLATFORM_LOG_SEGMENTS: &[&str] = &[".config", "openchamber", "logs"];
+use crate::paths;
 
 pub fn log_directory() -> Option<PathBuf> {
-    let mut path = dirs::home_dir()?;
-    for segment in PLATFORM_LOG_SEGMENTS {
-        path.push(segment);
-    }
-    Some(path)
+    paths::logs_dir()
 }
 
 pub fn log_file_path() -> Option<PathBuf> {
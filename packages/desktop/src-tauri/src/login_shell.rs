@@ -0,0 +1,258 @@
+use std::{
+    path::PathBuf,
+    sync::atomic::{AtomicBool, Ordering},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::paths;
+
+/// Settings flag that skips running the login shell entirely
+const DISABLE_SETTING: &str = "disableLoginShellPath";
+const CACHE_FILE_NAME: &str = "shell-path-cache.json";
+/// Long enough that most launches skip the shell, short enough to notice PATH edits
+const CACHE_TTL: Duration = Duration::from_secs(24 * 60 * 60);
+/// Slow rc files (nvm, brew shellenv) take a second or two; anything longer is stuck
+#[cfg(unix)]
+const PROBE_TIMEOUT: Duration = Duration::from_secs(3);
+
+static DISABLED: AtomicBool = AtomicBool::new(false);
+
+/// PATH and OPENCODE_BINARY as the user's login shell sets them
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ShellEnv {
+    pub path: Option<String>,
+    pub opencode_binary: Option<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct CacheEntry {
+    shell: String,
+    /// Seconds since the Unix epoch
+    detected_at: u64,
+    env: ShellEnv,
+}
+
+/// Apply `disableLoginShellPath` from settings to later probes
+pub fn configure(settings: &Value) {
+    let disabled = settings
+        .get(DISABLE_SETTING)
+        .and_then(Value::as_bool)
+        .unwrap_or(false);
+    DISABLED.store(disabled, Ordering::SeqCst);
+}
+
+/// The login shell's environment, from the cache when it is fresh. Empty when
+/// the probe is disabled, fails or times out, so callers use the process PATH.
+pub fn detect() -> ShellEnv {
+    // Windows apps inherit the user's PATH, so there is no shell to ask
+    if DISABLED.load(Ordering::SeqCst) || cfg!(not(unix)) {
+        return ShellEnv::default();
+    }
+    let shell = get_user_shell().unwrap_or_else(|| "/bin/zsh".into());
+    if let Some(env) = read_cache(&shell) {
+        return env;
+    }
+    let env = probe(&shell);
+    if env.path.is_some() {
+        write_cache(&shell, &env);
+    }
+    env
+}
+
+fn cache_path() -> Option<PathBuf> {
+    paths::cache_dir().map(|dir| dir.join(CACHE_FILE_NAME))
+}
+
+fn now_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_secs())
+        .unwrap_or(0)
+}
+
+fn read_cache(shell: &str) -> Option<ShellEnv> {
+    let bytes = std::fs::read(cache_path()?).ok()?;
+    let entry: CacheEntry = serde_json::from_slice(&bytes).ok()?;
+    let age = now_secs().saturating_sub(entry.detected_at);
+    // A different shell has a different rc file, so its PATH is not ours
+    (entry.shell == shell && age < CACHE_TTL.as_secs()).then_some(entry.env)
+}
+
+fn write_cache(shell: &str, env: &ShellEnv) {
+    let Some(path) = cache_path() else {
+        return;
+    };
+    let entry = CacheEntry {
+        shell: shell.to_string(),
+        detected_at: now_secs(),
+        env: env.clone(),
+    };
+    let result = serde_json::to_vec_pretty(&entry)
+        .map_err(std::io::Error::from)
+        .and_then(|bytes| {
+            if let Some(parent) = path.parent() {
+                std::fs::create_dir_all(parent)?;
+            }
+            std::fs::write(&path, bytes)
+        });
+    if let Err(err) = result {
+        warn!("[desktop:opencode] Failed to cache shell PATH: {}", err);
+    }
+}
+
+#[cfg(target_os = "macos")]
+fn get_user_shell() -> Option<String> {
+    use std::process::Command;
+
+    let username =
+        dirs::home_dir().and_then(|p| p.file_name().map(|s| s.to_string_lossy().to_string()))?;
+
+    let output = Command::new("dscl")
+        .args([".", "-read", &format!("/Users/{}", username), "UserShell"])
+        .output()
+        .ok()?;
+    if output.status.success() {
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        stdout.split(':').nth(1).map(|s| s.trim().to_string())
+    } else {
+        None
+    }
+}
+
+#[cfg(all(unix, not(target_os = "macos")))]
+fn get_user_shell() -> Option<String> {
+    std::env::var("SHELL").ok()
+}
+
+#[cfg(not(unix))]
+fn get_user_shell() -> Option<String> {
+    None
+}
+
+#[cfg(unix)]
+fn build_shell_env_command(shell: &str) -> Vec<String> {
+    let shell_name = std::path::Path::new(shell)
+        .file_name()
+        .and_then(|s| s.to_str())
+        .unwrap_or("sh");
+
+    match shell_name {
+        "bash" => vec![
+            "-lic".to_string(),
+            "source ~/.bashrc 2>/dev/null; echo \"__PATH__=$PATH\"; echo \"__OPENCODE_BINARY__=$OPENCODE_BINARY\"".to_string(),
+        ],
+        _ => vec![
+            "-lic".to_string(),
+            "echo \"__PATH__=$PATH\"; echo \"__OPENCODE_BINARY__=$OPENCODE_BINARY\"".to_string(),
+        ],
+    }
+}
+
+#[cfg(not(unix))]
+fn probe(_shell: &str) -> ShellEnv {
+    ShellEnv::default()
+}
+
+/// Run the shell with a hard timeout; an rc file waiting on input is killed
+#[cfg(unix)]
+fn probe(shell: &str) -> ShellEnv {
+    use std::{
+        io::Read,
+        process::{Command, Stdio},
+        sync::mpsc,
+        time::Instant,
+    };
+
+    info!("[desktop:opencode] detected user shell: {}", shell);
+    let args = build_shell_env_command(shell);
+    info!("[desktop:opencode] shell args: {:?}", args);
+
+    let mut child = match Command::new(shell)
+        .args(&args)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+    {
+        Ok(child) => child,
+        Err(e) => {
+            warn!("[desktop:opencode] failed to run shell {}: {}", shell, e);
+            return ShellEnv::default();
+        }
+    };
+
+    // Read on a thread: a daemon started by the rc file can hold stdout open
+    let (tx, rx) = mpsc::channel();
+    if let Some(mut stdout) = child.stdout.take() {
+        std::thread::spawn(move || {
+            let mut buf = String::new();
+            let _ = stdout.read_to_string(&mut buf);
+            let _ = tx.send(buf);
+        });
+    }
+
+    let deadline = Instant::now() + PROBE_TIMEOUT;
+    let status = loop {
+        match child.try_wait() {
+            Ok(Some(status)) => break status,
+            Ok(None) if Instant::now() < deadline => {
+                std::thread::sleep(Duration::from_millis(25));
+            }
+            Ok(None) => {
+                let _ = child.kill();
+                let _ = child.wait();
+                warn!(
+                    "[desktop:opencode] shell {} did not print PATH within {:?}; using the process PATH",
+                    shell, PROBE_TIMEOUT
+                );
+                return ShellEnv::default();
+            }
+            Err(e) => {
+                warn!(
+                    "[desktop:opencode] failed to wait for shell {}: {}",
+                    shell, e
+                );
+                return ShellEnv::default();
+            }
+        }
+    };
+
+    if !status.success() {
+        warn!(
+            "[desktop:opencode] shell env detection failed for {}: {}",
+            shell, status
+        );
+        return ShellEnv::default();
+    }
+
+    let remaining = deadline.saturating_duration_since(Instant::now());
+    let stdout = rx
+        .recv_timeout(remaining.max(Duration::from_millis(100)))
+        .unwrap_or_default();
+    info!("[desktop:opencode] shell stdout length: {}", stdout.len());
+    let mut env = ShellEnv::default();
+
+    for line in stdout.lines() {
+        if let Some(path) = line.strip_prefix("__PATH__=") {
+            if !path.is_empty() {
+                env.path = Some(path.to_string());
+            }
+        } else if let Some(binary) = line.strip_prefix("__OPENCODE_BINARY__=") {
+            if !binary.is_empty() {
+                env.opencode_binary = Some(binary.to_string());
+            }
+        }
+    }
+
+    info!(
+        "[desktop:opencode] parsed path exists: {}",
+        env.path.is_some()
+    );
+    env
+}
@@ -0,0 +1,171 @@
+use std::sync::Arc;
+
+use axum::{
+    body::Body,
+    extract::{FromRef, Request, State},
+    http::{HeaderMap, HeaderValue, Response, StatusCode},
+    middleware::Next,
+    response::IntoResponse,
+};
+use log::warn;
+use tower_http::cors::{AllowOrigin, Any, CorsLayer};
+
+use crate::{external_run::tokens_match, web_ui::WebUiAccess, ServerState};
+
+/// Header the webview sends the token in
+pub const TOKEN_HEADER: &str = "x-openchamber-token";
+/// Query fallback for EventSource, which cannot set headers; stripped before proxying
+pub const TOKEN_QUERY: &str = "openchamberToken";
+
+/// Origins the Tauri webview uses for the bundled UI
+const WEBVIEW_ORIGINS: &[&str] = &[
+    "tauri://localhost",
+    "http://tauri.localhost",
+    "https://tauri.localhost",
+];
+
+struct LoopbackAuthInner {
+    /// Fresh each launch and only handed to the webview, so other local
+    /// processes and websites cannot drive the server
+    token: String,
+    /// Extra trusted origin, e.g. the dev server in debug builds
+    dev_origin: Option<String>,
+}
+
+/// Guards the desktop HTTP server's /api routes. The port is reachable by any
+/// local process and, through CORS, by any website.
+#[derive(Clone)]
+pub struct LoopbackAuth {
+    inner: Arc<LoopbackAuthInner>,
+}
+
+impl LoopbackAuth {
+    pub fn new(dev_origin: Option<String>) -> Self {
+        Self {
+            inner: Arc::new(LoopbackAuthInner {
+                token: uuid::Uuid::new_v4().simple().to_string(),
+                dev_origin,
+            }),
+        }
+    }
+
+    pub fn token(&self) -> &str {
+        &self.inner.token
+    }
+
+    fn presented(&self, headers: &HeaderMap, query: Option<&str>) -> bool {
+        let header = headers
+            .get(TOKEN_HEADER)
+            .and_then(|value| value.to_str().ok())
+            .map(str::trim);
+        let presented = header.or_else(|| {
+            query?
+                .split('&')
+                .filter_map(|pair| pair.split_once('='))
+                .find(|(name, _)| *name == TOKEN_QUERY)
+                .map(|(_, value)| value)
+        });
+        presented.is_some_and(|token| tokens_match(&self.inner.token, token))
+    }
+
+    /// Cross-origin requests only from the webview; the served web UI is same-origin
+    pub fn cors_layer(&self) -> CorsLayer {
+        let origins = WEBVIEW_ORIGINS
+            .iter()
+            .copied()
+            .chain(self.inner.dev_origin.as_deref())
+            .filter_map(|origin| HeaderValue::from_str(origin).ok())
+            .collect::<Vec<_>>();
+        CorsLayer::new()
+            .allow_origin(AllowOrigin::list(origins))
+            .allow_methods(Any)
+            .allow_headers(Any)
+            .expose_headers(Any)
+    }
+}
+
+/// What require_api_auth checks requests against
+#[derive(Clone)]
+pub struct ApiAuth {
+    pub loopback: LoopbackAuth,
+    pub web_ui: WebUiAccess,
+}
+
+impl ApiAuth {
+    /// The webview's token in the header or query, or a web UI browser session
+    pub fn allows(&self, headers: &HeaderMap, query: Option<&str>) -> bool {
+        self.loopback.presented(headers, query) || self.web_ui.authorized(headers)
+    }
+}
+
+impl FromRef<ServerState> for ApiAuth {
+    fn from_ref(state: &ServerState) -> Self {
+        Self {
+            loopback: state.loopback_auth.clone(),
+            web_ui: state.web_ui.clone(),
+        }
+    }
+}
+
+/// Middleware for /api routes: the webview's token, or a web UI browser session
+pub async fn require_api_auth(
+    State(auth): State<ApiAuth>,
+    request: Request,
+    next: Next,
+) -> Response<Body> {
+    if auth.allows(request.headers(), request.uri().query()) {
+        return next.run(request).await;
+    }
+    warn!(
+        "[desktop:http] Rejected unauthenticated request to {}",
+        request.uri().path()
+    );
+    (StatusCode::UNAUTHORIZED, "Missing or invalid token").into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::header;
+
+    fn auth(web_ui_enabled: bool) -> ApiAuth {
+        ApiAuth {
+            loopback: LoopbackAuth::new(None),
+            web_ui: WebUiAccess::new(web_ui_enabled),
+        }
+    }
+
+    fn headers(pairs: &[(&'static str, &str)]) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        for (name, value) in pairs {
+            headers.insert(*name, HeaderValue::from_str(value).unwrap());
+        }
+        headers
+    }
+
+    #[test]
+    fn the_webview_token_is_accepted_in_the_header_or_query() {
+        let auth = auth(false);
+        let token = auth.loopback.token().to_string();
+
+        assert!(auth.allows(&headers(&[(TOKEN_HEADER, &token)]), None));
+        let query = format!("directory=%2Fwork&{TOKEN_QUERY}={token}");
+        assert!(auth.allows(&HeaderMap::new(), Some(&query)));
+
+        assert!(!auth.allows(&HeaderMap::new(), None));
+        assert!(!auth.allows(&headers(&[(TOKEN_HEADER, "guess")]), None));
+        assert!(!auth.allows(&HeaderMap::new(), Some("openchamberToken=guess")));
+    }
+
+    #[test]
+    fn tokens_differ_per_launch() {
+        assert_ne!(auth(false).loopback.token(), auth(false).loopback.token());
+    }
+
+    #[test]
+    fn cookies_only_count_while_the_web_ui_is_served() {
+        let cookie = [(header::COOKIE.as_str(), "openchamber_web=unknown")];
+        assert!(!auth(true).allows(&headers(&cookie), None));
+        assert!(!auth(false).allows(&headers(&cookie), None));
+    }
+}
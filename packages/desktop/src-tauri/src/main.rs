@@ -1,54 +1,161 @@
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
+mod actions;
+mod background_mode;
+mod cli_install;
 mod commands;
+mod config_journal;
+mod config_restart;
+mod document_export;
+mod downloads;
+mod environment_report;
+mod event_hub;
+mod external_run;
+mod file_index;
+mod heartbeat;
 mod logging;
+mod login_shell;
+mod loopback_auth;
+mod model_capabilities;
+mod models_metadata;
+mod notification_digest;
+mod notification_history;
+mod notification_limiter;
+mod notification_policy;
+mod opencode_auth;
 mod assistant_notifications;
 mod session_activity;
+mod session_search;
+mod shell_integration;
+mod shutdown;
+mod sse_keepalive;
+mod opencode_client;
 mod opencode_config;
+mod opencode_lifecycle;
 mod opencode_manager;
+mod opencode_output;
+mod opencode_pool;
+mod opencode_version;
+mod paths;
+mod platform;
+mod prefix_watch;
+mod process_stats;
+mod project_state;
+mod proxy_log;
+mod proxy_routes;
+mod reload_decision;
+mod server_binding;
+mod server_metrics;
+mod system_appearance;
+mod system_dnd;
+mod upstream_pool;
+mod usage_budget;
+mod usage_tracker;
+mod web_ui;
 mod window_state;
+mod workspace_fs;
+mod workspace_relink;
+mod workspace_snapshot;
+mod workspace_trust;
 
-use std::{collections::HashMap, path::PathBuf, sync::Arc, time::{Duration, Instant}};
+use std::{collections::{BTreeMap, HashMap}, future::IntoFuture, net::IpAddr, path::{Path, PathBuf}, sync::Arc, time::{Duration, Instant}};
 
 use anyhow::{anyhow, Result};
 use axum::{
-    body::{to_bytes, Body},
-    extract::{OriginalUri, State},
-    http::{Method, Request, Response, StatusCode},
+    body::{to_bytes, Body, HttpBody},
+    extract::{FromRef, OriginalUri, Query, RawQuery, State},
+    http::{HeaderMap, Method, Request, Response, StatusCode},
+    middleware,
     response::IntoResponse,
     routing::{any, get, post},
+    serve::ListenerExt,
     Json, Router,
 };
 use assistant_notifications::spawn_assistant_notifications;
-use session_activity::spawn_session_activity_tracker;
-use commands::files::{create_directory, list_directory, search_files};
+use session_activity::{spawn_session_activity_tracker, BusySession, SessionPhases, SessionPins};
+use system_appearance::{spawn_appearance_watcher, AppearanceMonitor, PlatformAppearance};
+use shutdown::{ShutdownCoordinator, ShutdownStage};
+use upstream_pool::UpstreamPool;
+use session_search::SessionSearch;
+use commands::files::{
+    create_directory, get_index_status, list_directory, rebuild_file_index, search_files,
+};
 use commands::git::{
     add_git_worktree, check_is_git_repository, checkout_branch, create_branch, create_git_commit,
     create_git_identity, delete_git_branch, delete_git_identity, delete_remote_branch,
-    ensure_openchamber_ignored, generate_commit_message, get_commit_files, get_current_git_identity,
-    get_git_branches, get_git_diff, get_git_file_diff, get_git_identities, get_git_log, get_git_status,
-    git_fetch, git_pull, git_push, is_linked_worktree, list_git_worktrees, remove_git_worktree,
+    discover_git_repository, ensure_openchamber_ignored, generate_commit_message, get_commit_files,
+    get_current_git_identity, get_git_branches, get_git_diff, get_git_file_diff,
+    get_git_identities, get_git_log, get_git_status, git_fetch, git_pull, git_push,
+    is_linked_worktree, list_git_worktrees, list_nested_repositories, remove_git_worktree,
     revert_git_file, set_git_identity, update_git_identity,
 };
-use commands::logs::fetch_desktop_logs;
+use commands::logs::{
+    create_desktop_log_download, fetch_desktop_logs, get_unclean_shutdown, list_crash_reports,
+};
+use config_restart::{
+    BusyOverride, ConfigGeneration, ConfigRefreshScheduler, ConfigRestartCoalescer,
+    RefreshFuture, RefreshOutcome, CONFIG_REFRESH_EVENT,
+};
+use downloads::DownloadRegistry;
+use event_hub::EventHub;
 use commands::permissions::{
-    pick_directory, process_directory_selection, request_directory_access,
-    restore_bookmarks_on_startup, start_accessing_directory, stop_accessing_directory,
+    get_workspace_fs_info, get_workspace_trust, pick_directory, process_directory_selection,
+    request_directory_access, restore_bookmarks_on_startup, set_workspace_trust,
+    start_accessing_directory, stop_accessing_directory,
+};
+use commands::notifications::{
+    desktop_get_notification_history, desktop_get_system_appearance, desktop_get_system_dnd_state,
+    desktop_notify, get_platform_capabilities, set_active_session,
 };
-use commands::notifications::desktop_notify;
-use commands::settings::{load_settings, restart_opencode, save_settings};
+use commands::actions::{invoke_action, list_actions};
+use commands::artifacts::{register_artifact, save_artifact};
+use commands::auth::{get_opencode_auth_status, start_opencode_auth};
+use commands::exports::{export_to_html, export_to_pdf};
+use commands::config_recovery::{list_config_recoveries, resolve_config_recovery};
+use commands::project_state::{get_project_state, list_project_state_keys, set_project_state};
+use commands::projects::{create_project_from_template, list_project_templates, relink_workspace};
+use commands::sessions::{
+    get_pinned_sessions, pin_session, rebuild_session_index, search_sessions, unpin_session,
+};
+use commands::snapshots::{
+    create_workspace_snapshot, delete_workspace_snapshot, list_workspace_snapshots,
+    restore_workspace_snapshot,
+};
+use commands::settings::{
+    get_config_generation, get_model_capabilities, load_settings, restart_opencode, save_settings,
+    set_project_opencode_config,
+};
+use commands::usage::{get_budget_status, get_session_usage, get_usage_summary};
 use commands::terminal::{
-    close_terminal, create_terminal_session, force_kill_terminal, resize_terminal,
-    restart_terminal_session, send_terminal_input, TerminalState,
+    close_terminal, create_terminal_session, force_kill_terminal, get_terminal_foreground,
+    handle_workspace_change, list_terminal_sessions, resize_terminal, restart_terminal_session,
+    send_terminal_input, TerminalState,
 };
 use futures_util::StreamExt as FuturesStreamExt;
 use log::{error, info, warn};
-use opencode_manager::OpenCodeManager;
+use file_index::FileIndex;
+use model_capabilities::ModelCapabilityCache;
+use models_metadata::{ModelsDiskCache, StoredPayload};
+use notification_history::NotificationHistory;
+use notification_limiter::NotificationLimiter;
+use notification_policy::AgentPreferenceCache;
+use opencode_client::OpenCodeClient;
+use opencode_lifecycle::{LifecycleState, OpenCodeStatus, RestartRecord};
+use opencode_manager::{
+    OpenCodeManager, PrefixRedetection, Reachability, ReadinessConfig, StartupExitError,
+};
+use opencode_output::OutputLine;
+use opencode_pool::{InstanceInfo, OpenCodeInstancePool};
+use process_stats::ProcessStats;
+use prefix_watch::PrefixWatch;
+use project_state::ProjectStateStore;
+use proxy_routes::{ConfigRoute, ProxyRetry, ProxyTimeouts};
+use reload_decision::ChangedEntity;
 use portpicker::pick_unused_port;
 use reqwest::{header, Body as ReqwestBody, Client};
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
-use tauri::{Emitter, Manager};
+use tauri::{AppHandle, Emitter, Manager};
 #[cfg(feature = "devtools")]
 use tauri::WebviewWindow;
 use tauri_plugin_dialog::init as dialog_plugin;
@@ -59,13 +166,17 @@ use tauri_plugin_shell::init as shell_plugin;
 use tokio::{
     fs,
     net::TcpListener,
-    sync::{broadcast, Mutex},
+    sync::{broadcast, watch, Mutex},
 };
-use tower_http::cors::CorsLayer;
+use usage_tracker::UsageTracker;
+use loopback_auth::LoopbackAuth;
+use proxy_log::ProxyLog;
+use server_binding::ServerBinding;
+use server_metrics::ServerMetrics;
+use web_ui::WebUiAccess;
 use window_state::{load_window_state, persist_window_state, WindowStateManager};
 
-#[cfg(target_os = "macos")]
-use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
 
 #[cfg(target_os = "macos")]
 use window_vibrancy::{apply_vibrancy, NSVisualEffectMaterial};
@@ -73,10 +184,12 @@ use window_vibrancy::{apply_vibrancy, NSVisualEffectMaterial};
 #[cfg(target_os = "macos")]
 static NEEDS_TRAFFIC_LIGHT_FIX: AtomicBool = AtomicBool::new(false);
 
-const PROXY_BODY_LIMIT: usize = 32 * 1024 * 1024; // 32MB
+const CONFIG_PAYLOAD_LIMIT: u64 = 1024 * 1024; // 1MB
+const CONFIG_FIELD_LIMIT: usize = 256 * 1024; // 256KB
 const CLIENT_RELOAD_DELAY_MS: u64 = 800;
-const MODELS_DEV_API_URL: &str = "https://models.dev/api.json";
 const MODELS_METADATA_CACHE_TTL: Duration = Duration::from_secs(5 * 60);
+/// Between refresh attempts while models.dev is unreachable
+const MODELS_METADATA_RETRY_INTERVAL: Duration = Duration::from_secs(30);
 const MODELS_METADATA_REQUEST_TIMEOUT: Duration = Duration::from_secs(8);
 
 #[derive(Clone)]
@@ -85,50 +198,246 @@ pub(crate) struct DesktopRuntime {
     shutdown_tx: broadcast::Sender<()>,
     opencode: Arc<OpenCodeManager>,
     settings: Arc<SettingsStore>,
+    active_session: Arc<parking_lot::RwLock<Option<String>>>,
+    usage: Arc<UsageTracker>,
+    session_phases: SessionPhases,
+    session_pins: SessionPins,
+    downloads: DownloadRegistry,
+    project_state: Arc<ProjectStateStore>,
+    model_capabilities: Arc<ModelCapabilityCache>,
+    file_index: FileIndex,
+    opencode_client: OpenCodeClient,
+    notification_limiter: Arc<parking_lot::Mutex<NotificationLimiter>>,
+    notification_history: Arc<NotificationHistory>,
+    web_ui: WebUiAccess,
+    loopback_auth: LoopbackAuth,
+    proxy_log: Arc<ProxyLog>,
+    server_binding: Arc<ServerBinding>,
+    max_request_body: Arc<AtomicU64>,
+    agent_preferences: Arc<AgentPreferenceCache>,
+    session_search: Arc<SessionSearch>,
+    config_generation: Arc<ConfigGeneration>,
+    appearance: Arc<AppearanceMonitor>,
+    shutdown: Arc<ShutdownCoordinator>,
 }
 
 impl DesktopRuntime {
-    fn initialize_sync() -> Result<Self> {
+    fn initialize_sync(app: &AppHandle) -> Result<Self> {
         let settings = Arc::new(SettingsStore::new()?);
+        let initial_settings = tauri::async_runtime::block_on(settings.load()).unwrap_or_default();
+        // Before the manager runs the login shell to find OpenCode
+        login_shell::configure(&initial_settings);
         let initial_dir = tauri::async_runtime::block_on(settings.last_directory()).ok().flatten();
         let opencode = Arc::new(OpenCodeManager::new_with_directory(initial_dir.clone()));
+        opencode.attach_app(app.clone());
+        opencode.attach_settings(settings.clone());
+
+        // External requests (models.dev); OpenCode traffic goes through the upstream pool
+        let client = Client::builder()
+            .gzip(true)
+            .brotli(true)
+            .deflate(true)
+            .build()?;
+        let upstream = Arc::new(UpstreamPool::new(opencode.clone())?);
+        let session_phases = SessionPhases::default();
+        let session_pins = session_activity::load_pins(&initial_settings);
+        let web_ui = WebUiAccess::new(web_ui::serve_web_ui_enabled(&initial_settings));
+        let loopback_auth = LoopbackAuth::new(
+            // The dev server serves the UI in debug builds
+            cfg!(debug_assertions)
+                .then(|| app.config().build.dev_url.as_ref())
+                .flatten()
+                .map(|url| url.origin().ascii_serialization()),
+        );
+        let proxy_log = Arc::new(ProxyLog::default());
+        proxy_log.configure(&initial_settings);
+        let server_binding = Arc::new(ServerBinding::new(server_binding::bind_address(
+            &initial_settings,
+            &web_ui,
+        )));
+        let max_request_body = Arc::new(AtomicU64::new(proxy_routes::max_request_body_bytes(
+            &initial_settings,
+        )));
+        let downloads = DownloadRegistry::default();
+        downloads.spawn_cleanup();
+        let opencode_client = OpenCodeClient::new(upstream.clone(), opencode.clone());
+        let model_capabilities = Arc::new(ModelCapabilityCache::new(opencode_client.clone()));
+        let config_paths = opencode_config::ConfigPaths::from_env();
+        let agent_preferences = Arc::new(AgentPreferenceCache::new(config_paths.clone()));
+        let session_search = Arc::new(SessionSearch::new(opencode_client.clone()));
+        let config_generation = Arc::new(ConfigGeneration::new(app.clone()));
+
+        let opencode_pool = Arc::new(OpenCodeInstancePool::new(
+            opencode.clone(),
+            settings.clone(),
+            opencode_pool::max_instances(&initial_settings),
+        ));
 
-        let client = Client::builder().build()?;
+        let models_disk_cache = Arc::new(ModelsDiskCache::new());
+        let config_restart = Arc::new(ConfigRestartCoalescer::new(
+            opencode.clone(),
+            config_generation.clone(),
+        ));
 
         let (shutdown_tx, shutdown_rx) = broadcast::channel(2);
         let server_port =
             pick_unused_port().ok_or_else(|| anyhow!("No free port available"))? as u16;
         let server_state = ServerState {
+            app: app.clone(),
             client,
+            upstream,
             opencode: opencode.clone(),
             server_port,
             directory_change_lock: Arc::new(Mutex::new(())),
-            models_metadata_cache: Arc::new(Mutex::new(ModelsMetadataCache::default())),
+            models_metadata_cache: Arc::new(Mutex::new(ModelsMetadataCache::preload(
+                &models_disk_cache,
+            ))),
+            models_disk_cache,
+            config_paths,
+            config_restart: config_restart.clone(),
+            config_refresh: Arc::new(ConfigRefreshScheduler::new(
+                opencode.clone(),
+                config_generation.clone(),
+                config_restart,
+                session_phases.clone(),
+                session_pins.clone(),
+            )),
+            config_generation: config_generation.clone(),
+            session_phases: session_phases.clone(),
+            session_pins: session_pins.clone(),
+            downloads: downloads.clone(),
+            model_capabilities: model_capabilities.clone(),
+            web_ui: web_ui.clone(),
+            loopback_auth: loopback_auth.clone(),
+            proxy_log: proxy_log.clone(),
+            server_binding: server_binding.clone(),
+            agent_preferences: agent_preferences.clone(),
+            session_search: session_search.clone(),
+            event_hub: Arc::new(EventHub::new(opencode_client.clone())),
+            prefix_watch: Arc::new(PrefixWatch::default()),
+            metrics: Arc::new(ServerMetrics::default()),
+            forward_directory: proxy_routes::forward_directory_enabled(&initial_settings),
+            max_request_body: max_request_body.clone(),
+            proxy_timeouts: ProxyTimeouts::from_settings(&initial_settings),
+            proxy_retry: ProxyRetry::from_settings(&initial_settings),
+            opencode_pool: opencode_pool.clone(),
         };
 
         spawn_http_server(server_port, server_state, shutdown_rx);
 
+        let usage = UsageTracker::load();
+        let shutdown = Arc::new(ShutdownCoordinator::default());
+        {
+            let usage = usage.clone();
+            shutdown.register(
+                "usage",
+                ShutdownStage::PersistState,
+                Duration::from_secs(2),
+                move || async move {
+                    if let Err(err) = usage.flush().await {
+                        warn!("[desktop] Failed to persist usage on shutdown: {}", err);
+                    }
+                },
+            );
+            // Stops the HTTP server and the SSE listeners subscribed via subscribe_shutdown
+            let shutdown_tx = shutdown_tx.clone();
+            shutdown.register(
+                "listeners",
+                ShutdownStage::StopListeners,
+                Duration::from_secs(1),
+                move || async move {
+                    let _ = shutdown_tx.send(());
+                },
+            );
+            let opencode = opencode.clone();
+            shutdown.register(
+                "opencode",
+                ShutdownStage::StopOpenCode,
+                Duration::from_secs(5),
+                move || async move {
+                    let _ = opencode.shutdown().await;
+                },
+            );
+            shutdown.register(
+                "opencode-pool",
+                ShutdownStage::StopOpenCode,
+                Duration::from_secs(5),
+                move || async move {
+                    opencode_pool.shutdown().await;
+                },
+            );
+        }
+
         Ok(Self {
             server_port,
             shutdown_tx,
             opencode,
             settings,
+            active_session: Arc::new(parking_lot::RwLock::new(None)),
+            usage,
+            session_phases,
+            session_pins,
+            downloads,
+            project_state: Arc::new(ProjectStateStore::default()),
+            model_capabilities,
+            file_index: FileIndex::default(),
+            opencode_client,
+            notification_limiter: Arc::new(parking_lot::Mutex::new(NotificationLimiter::default())),
+            notification_history: Arc::new(NotificationHistory::default()),
+            web_ui,
+            loopback_auth,
+            proxy_log,
+            server_binding,
+            max_request_body,
+            agent_preferences,
+            session_search,
+            config_generation,
+            appearance: Arc::new(AppearanceMonitor::new(Box::new(PlatformAppearance))),
+            shutdown,
         })
     }
 
-    async fn start_opencode(&self) {
+    async fn start_opencode(&self, app: &AppHandle) {
+        let external_port = self
+            .settings
+            .load()
+            .await
+            .ok()
+            .and_then(|settings| settings.get("opencode")?.get("externalPort")?.as_u64())
+            .and_then(|port| u16::try_from(port).ok());
+        self.opencode.set_external_port(external_port);
+
         if self.opencode.is_cli_available() {
             if let Err(e) = self.opencode.ensure_running().await {
                 warn!("[desktop] Failed to start OpenCode: {}", e);
+                // The UI shows the actual reason, e.g. a config parse error, instead of "not ready"
+                let _ = app.emit(
+                    OPENCODE_START_FAILED_EVENT,
+                    serde_json::json!({
+                        "error": e.to_string(),
+                        "exit": e.downcast_ref::<StartupExitError>(),
+                    }),
+                );
             }
+            self.opencode.spawn_supervisor();
+            self.opencode.spawn_watchdog();
+            self.opencode.spawn_binary_watch();
         } else {
             info!("[desktop] OpenCode CLI not available - running in limited mode");
         }
     }
 
+    /// Run the registered teardowns; returns within shutdown::SHUTDOWN_BUDGET
     async fn shutdown(&self) {
-        let _ = self.shutdown_tx.send(());
-        let _ = self.opencode.shutdown().await;
+        let report = self.shutdown.run().await;
+        if report.timed_out.is_empty() && report.skipped.is_empty() {
+            info!("[desktop:shutdown] Shutdown complete");
+        }
+    }
+
+    /// Where subsystems register teardown for app exit
+    pub(crate) fn shutdown_coordinator(&self) -> Arc<ShutdownCoordinator> {
+        self.shutdown.clone()
     }
 
     pub(crate) fn settings(&self) -> &SettingsStore {
@@ -142,21 +451,266 @@ impl DesktopRuntime {
     pub(crate) fn opencode_manager(&self) -> Arc<OpenCodeManager> {
         self.opencode.clone()
     }
+    pub(crate) fn server_port(&self) -> u16 {
+        self.server_port
+    }
+
+    /// Token the desktop's own /api requests authenticate with
+    pub(crate) fn api_token(&self) -> &str {
+        self.loopback_auth.token()
+    }
+
+    /// Session the user is looking at, as reported by the frontend
+    pub(crate) fn active_session(&self) -> Option<String> {
+        self.active_session.read().clone()
+    }
+
+    pub(crate) fn set_active_session(&self, session_id: Option<String>) {
+        *self.active_session.write() = session_id;
+    }
+
+    pub(crate) fn usage(&self) -> Arc<UsageTracker> {
+        self.usage.clone()
+    }
+
+    pub(crate) fn session_phases(&self) -> SessionPhases {
+        self.session_phases.clone()
+    }
+
+    pub(crate) fn session_pins(&self) -> SessionPins {
+        self.session_pins.clone()
+    }
+
+    pub(crate) fn downloads(&self) -> &DownloadRegistry {
+        &self.downloads
+    }
+
+    pub(crate) fn project_state(&self) -> &ProjectStateStore {
+        &self.project_state
+    }
+
+    pub(crate) fn model_capabilities(&self) -> &ModelCapabilityCache {
+        &self.model_capabilities
+    }
+
+    pub(crate) fn file_index(&self) -> &FileIndex {
+        &self.file_index
+    }
+
+    pub(crate) fn proxy_log(&self) -> &ProxyLog {
+        &self.proxy_log
+    }
+
+    /// Apply the `server` settings: the HTTP server rebinds if `bindAddress`
+    /// changed, and `maxBodyMb` holds from the next request
+    pub(crate) fn configure_server(&self, settings: &Value) {
+        self.server_binding.configure(settings, &self.web_ui);
+        self.max_request_body.store(
+            proxy_routes::max_request_body_bytes(settings),
+            Ordering::Relaxed,
+        );
+    }
+
+    pub(crate) fn agent_preferences(&self) -> &AgentPreferenceCache {
+        &self.agent_preferences
+    }
+
+    pub(crate) fn session_search(&self) -> Arc<SessionSearch> {
+        self.session_search.clone()
+    }
+
+    pub(crate) fn config_generation(&self) -> Arc<ConfigGeneration> {
+        self.config_generation.clone()
+    }
+
+    pub(crate) fn appearance(&self) -> Arc<AppearanceMonitor> {
+        self.appearance.clone()
+    }
+
+    pub(crate) fn opencode_client(&self) -> OpenCodeClient {
+        self.opencode_client.clone()
+    }
+
+    /// Shared across webview windows so every caller draws from one budget
+    pub(crate) fn notification_limiter(&self) -> Arc<parking_lot::Mutex<NotificationLimiter>> {
+        self.notification_limiter.clone()
+    }
+
+    pub(crate) fn notification_history(&self) -> &NotificationHistory {
+        &self.notification_history
+    }
+
+    /// Refuse to restart OpenCode while sessions are busy unless `force` is set
+    pub(crate) async fn ensure_restart_allowed(
+        &self,
+        force: bool,
+        override_pinned: bool,
+    ) -> Result<(), String> {
+        let busy = session_activity::busy_sessions(&self.session_phases, &self.session_pins).await;
+        if !session_activity::restart_blocked(&busy, force, override_pinned) {
+            return Ok(());
+        }
+        let ids = busy
+            .iter()
+            .map(|session| session.session_id.as_str())
+            .collect::<Vec<_>>()
+            .join(", ");
+        if busy.iter().any(|session| session.pinned) {
+            return Err(format!(
+                "{} session(s) still running, including pinned ones: {}. Retry with force and overridePinned to restart anyway.",
+                busy.len(),
+                ids
+            ));
+        }
+        Err(format!(
+            "{} session(s) still running: {}. Retry with force to restart anyway.",
+            busy.len(),
+            ids
+        ))
+    }
 }
 
 #[derive(Clone)]
 struct ServerState {
+    app: AppHandle,
     client: Client,
+    upstream: Arc<UpstreamPool>,
     opencode: Arc<OpenCodeManager>,
     server_port: u16,
     directory_change_lock: Arc<Mutex<()>>,
     models_metadata_cache: Arc<Mutex<ModelsMetadataCache>>,
+    models_disk_cache: Arc<ModelsDiskCache>,
+    config_paths: opencode_config::ConfigPaths,
+    config_restart: Arc<ConfigRestartCoalescer>,
+    /// Debounced refresh after agent and command mutations
+    config_refresh: Arc<ConfigRefreshScheduler>,
+    config_generation: Arc<ConfigGeneration>,
+    session_phases: SessionPhases,
+    session_pins: SessionPins,
+    downloads: DownloadRegistry,
+    model_capabilities: Arc<ModelCapabilityCache>,
+    web_ui: WebUiAccess,
+    loopback_auth: LoopbackAuth,
+    proxy_log: Arc<ProxyLog>,
+    server_binding: Arc<ServerBinding>,
+    agent_preferences: Arc<AgentPreferenceCache>,
+    session_search: Arc<SessionSearch>,
+    event_hub: Arc<EventHub>,
+    prefix_watch: Arc<PrefixWatch>,
+    metrics: Arc<ServerMetrics>,
+    /// Append the active workspace as `directory` to scoped proxied requests
+    forward_directory: bool,
+    /// Request bodies larger than this are refused, see max_request_body_bytes
+    max_request_body: Arc<AtomicU64>,
+    proxy_timeouts: ProxyTimeouts,
+    proxy_retry: ProxyRetry,
+    opencode_pool: Arc<OpenCodeInstancePool>,
+}
+
+impl ServerState {
+    fn max_request_body(&self) -> u64 {
+        self.max_request_body.load(Ordering::Relaxed)
+    }
 }
 
 #[derive(Default)]
 struct ModelsMetadataCache {
-    payload: Option<Value>,
-    fetched_at: Option<Instant>,
+    /// models.dev as fetched, shared by every project
+    upstream: Option<UpstreamPayload>,
+    /// Bumped whenever the upstream payload changes
+    revision: u64,
+    /// A background refresh is in flight; later expirations leave it to finish
+    refreshing: bool,
+    /// With each project's overrides merged in, keyed by directory
+    merged: HashMap<String, MergedPayload>,
+}
+
+impl ModelsMetadataCache {
+    /// Seed the upstream entry from disk, so the first request after an
+    /// offline start has something to serve
+    fn preload(disk: &ModelsDiskCache) -> Self {
+        let Some(stored) = tauri::async_runtime::block_on(disk.load()) else {
+            return Self::default();
+        };
+        let checked_at = stored
+            .age()
+            .filter(|age| *age < MODELS_METADATA_CACHE_TTL)
+            .and_then(|age| Instant::now().checked_sub(age));
+        Self {
+            upstream: Some(UpstreamPayload {
+                source: stored.source,
+                payload: stored.payload,
+                etag: stored.etag,
+                last_modified: stored.last_modified,
+                checked_at,
+                stale: checked_at.is_none(),
+            }),
+            ..Self::default()
+        }
+    }
+
+    /// The upstream payload, if it came from `source`
+    fn upstream_for(&self, source: &str) -> Option<&UpstreamPayload> {
+        self.upstream
+            .as_ref()
+            .filter(|upstream| upstream.source == source)
+    }
+
+    fn upstream_for_mut(&mut self, source: &str) -> Option<&mut UpstreamPayload> {
+        self.upstream
+            .as_mut()
+            .filter(|upstream| upstream.source == source)
+    }
+
+    /// Whether a background refresh should start; marks one in flight if so
+    fn begin_refresh(&mut self, now: Instant) -> bool {
+        let due = self.upstream.as_ref().is_some_and(|upstream| {
+            let interval = if upstream.stale {
+                MODELS_METADATA_RETRY_INTERVAL
+            } else {
+                MODELS_METADATA_CACHE_TTL
+            };
+            upstream
+                .checked_at
+                .is_none_or(|checked_at| now.duration_since(checked_at) >= interval)
+        });
+        if !due || self.refreshing {
+            return false;
+        }
+        self.refreshing = true;
+        true
+    }
+}
+
+struct UpstreamPayload {
+    /// URL it was fetched from; a different configured source starts over
+    source: String,
+    payload: Value,
+    etag: Option<String>,
+    last_modified: Option<String>,
+    /// Last time models.dev was asked, successfully or not; `None` for a copy
+    /// loaded from disk past its TTL
+    checked_at: Option<Instant>,
+    /// models.dev could not be reached to confirm this copy
+    stale: bool,
+}
+
+struct MergedPayload {
+    payload: Value,
+    merged_at: Instant,
+    /// `ModelsMetadataCache::revision` this was merged over
+    revision: u64,
+}
+
+/// Result of asking models.dev for its payload
+enum ModelsFetch {
+    Updated {
+        payload: Value,
+        etag: Option<String>,
+        last_modified: Option<String>,
+    },
+    /// 304 to a conditional request; the cached copy is current
+    NotModified,
 }
 
 #[derive(Serialize)]
@@ -166,6 +720,22 @@ struct ConfigActionResponse {
     requires_reload: bool,
     message: String,
     reload_delay_ms: u64,
+    restart_coalesced: bool,
+    /// When a debounced refresh will pick the change up
+    #[serde(skip_serializing_if = "Option::is_none")]
+    restart_scheduled_in_ms: Option<u64>,
+    /// Bumped once per applied change (once per coalesced restart); the webview
+    /// only needs to reload when the generation it loaded is older. For a
+    /// scheduled refresh, the generation it will land at or after.
+    config_generation: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    busy_sessions: Option<Vec<BusySession>>,
+    /// Entities the mutation touched, so a soft refresh knows what to re-fetch
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    changed_entities: Vec<ChangedEntity>,
+    /// After a PUT, the definition now on disk
+    #[serde(skip_serializing_if = "Option::is_none")]
+    definition: Option<Value>,
 }
 
 #[derive(Serialize)]
@@ -186,10 +756,35 @@ struct ConfigMetadataResponse {
 struct HealthResponse {
     status: &'static str,
     server_port: u16,
+    /// Effective `server.bindAddress`
+    bind_address: String,
     opencode_port: Option<u16>,
     api_prefix: String,
     is_opencode_ready: bool,
+    /// Whether OpenCode answers /health right now, as opposed to having
+    /// finished starting up; cached for a couple of seconds
+    opencode_reachability: Reachability,
+    /// OpenCode was started outside the app and is only attached to
+    is_opencode_adopted: bool,
     cli_available: bool,
+    /// Lifecycle state behind `isOpenCodeReady` (`status` is the desktop server's own)
+    opencode_status: OpenCodeStatus,
+    opencode_version: Option<String>,
+    /// Shown by the UI when the CLI is older than supported
+    version_warning: Option<String>,
+    /// The CLI was upgraded on disk; restarting OpenCode applies it
+    update_available: bool,
+    /// Endpoints polled to decide OpenCode is ready, per settings
+    opencode_readiness: ReadinessConfig,
+    /// Most recent restart or crash recovery, see desktop_opencode_restart_history
+    last_restart: Option<RestartRecord>,
+    /// Every running OpenCode server, the primary first
+    opencode_instances: Vec<InstanceInfo>,
+    /// Only with `?stats=1`, as sampling takes a moment
+    #[serde(skip_serializing_if = "Option::is_none")]
+    opencode_stats: Option<ProcessStats>,
+    #[serde(flatten)]
+    project: ProjectInfo,
 }
 
 #[derive(Serialize)]
@@ -199,31 +794,221 @@ struct ServerInfoPayload {
     api_prefix: String,
     cli_available: bool,
     has_last_directory: bool,
+    status: OpenCodeStatus,
+    opencode_version: Option<String>,
+    /// Shown by the UI when the CLI is older than supported
+    version_warning: Option<String>,
+    /// Config file OpenCode is (or will be) started with
+    opencode_config: Option<String>,
+    /// Sent as `x-openchamber-token` with every /api request
+    api_token: String,
+    #[serde(flatten)]
+    project: ProjectInfo,
+}
+
+impl ServerInfoPayload {
+    fn new(
+        server_port: u16,
+        opencode: &OpenCodeManager,
+        has_last_directory: bool,
+        api_token: &str,
+    ) -> Self {
+        Self {
+            server_port,
+            opencode_port: opencode.current_port(),
+            api_prefix: opencode.api_prefix(),
+            cli_available: opencode.is_cli_available(),
+            has_last_directory,
+            status: opencode.status(),
+            opencode_version: opencode.cli_version().and_then(|version| version.version),
+            version_warning: opencode.version_warning(),
+            opencode_config: opencode
+                .config_path()
+                .map(|path| path.to_string_lossy().to_string()),
+            api_token: api_token.to_string(),
+            project: ProjectInfo::from_manager(opencode),
+        }
+    }
+}
+
+/// Working directory details, read from OpenCodeManager so they always match what OpenCode serves
+#[derive(Clone, Serialize)]
+struct ProjectInfo {
+    working_directory: String,
+    is_git_repository: bool,
+    project_name: Option<String>,
+}
+
+impl ProjectInfo {
+    fn from_manager(opencode: &OpenCodeManager) -> Self {
+        let dir = opencode.get_working_directory();
+        Self {
+            working_directory: dir.to_string_lossy().to_string(),
+            is_git_repository: dir.join(".git").exists(),
+            project_name: dir
+                .file_name()
+                .map(|name| name.to_string_lossy().to_string()),
+        }
+    }
 }
 
+const DIRECTORY_CHANGED_EVENT: &str = "openchamber:directory-changed";
+/// Emitted when the first start of OpenCode fails, with the error and any output it printed
+const OPENCODE_START_FAILED_EVENT: &str = "openchamber:opencode-start-failed";
+
 #[tauri::command]
 async fn desktop_server_info(
     state: tauri::State<'_, DesktopRuntime>,
 ) -> Result<ServerInfoPayload, String> {
     let has_last_directory = state.settings().last_directory().await.ok().flatten().is_some();
-    Ok(ServerInfoPayload {
-        server_port: state.server_port,
-        opencode_port: state.opencode.current_port(),
-        api_prefix: state.opencode.api_prefix(),
-        cli_available: state.opencode.is_cli_available(),
+    Ok(ServerInfoPayload::new(
+        state.server_port,
+        &state.opencode,
         has_last_directory,
-    })
+        state.loopback_auth.token(),
+    ))
+}
+
+/// Token scripts pass to /api/openchamber/run
+#[tauri::command]
+fn desktop_run_token() -> Result<String, String> {
+    external_run::run_token().ok_or_else(|| "Run token unavailable".to_string())
+}
+
+/// Browser URL for the served web UI; its token is accepted once
+#[tauri::command]
+fn desktop_get_web_ui_url(state: tauri::State<'_, DesktopRuntime>) -> Result<String, String> {
+    if !state.web_ui.enabled() {
+        return Err(
+            "Web UI serving is off; enable server.serveWebUi and restart OpenChamber".to_string(),
+        );
+    }
+    // Reachable from other machines when the server listens beyond loopback
+    let base = server_binding::remote_base_url(state.server_binding.current(), state.server_port)
+        .unwrap_or_else(|| format!("http://127.0.0.1:{}", state.server_port));
+    Ok(format!(
+        "{}{}?token={}",
+        base,
+        web_ui::EXCHANGE_PATH,
+        state.web_ui.issue_code()
+    ))
+}
+
+#[tauri::command]
+async fn desktop_restart_opencode(
+    state: tauri::State<'_, DesktopRuntime>,
+    force: Option<bool>,
+    override_pinned: Option<bool>,
+) -> Result<(), String> {
+    state
+        .ensure_restart_allowed(force.unwrap_or(false), override_pinned.unwrap_or(false))
+        .await?;
+    state
+        .opencode
+        .restart_for("manual restart")
+        .await
+        .map_err(|err| err.to_string())
+}
+
+/// Restart OpenCode only if its binary was upgraded since it was spawned, so the
+/// UI can offer "restart to apply update". Returns whether it restarted.
+#[tauri::command]
+async fn desktop_restart_opencode_if_updated(
+    state: tauri::State<'_, DesktopRuntime>,
+    force: Option<bool>,
+    override_pinned: Option<bool>,
+) -> Result<bool, String> {
+    if !state.opencode.check_binary_update() {
+        return Ok(false);
+    }
+    state
+        .ensure_restart_allowed(force.unwrap_or(false), override_pinned.unwrap_or(false))
+        .await?;
+    state
+        .opencode
+        .restart_if_updated()
+        .await
+        .map_err(|err| err.to_string())
+}
+
+/// Download the OpenCode CLI for users who don't have it, then start it without
+/// an app restart. Progress arrives as `openchamber:cli-install-progress` events.
+#[tauri::command]
+async fn desktop_install_opencode(
+    app: AppHandle,
+    state: tauri::State<'_, DesktopRuntime>,
+) -> Result<String, String> {
+    if state.opencode.is_cli_available() {
+        return Err("OpenCode CLI is already available".to_string());
+    }
+    let binary = cli_install::install(&app)
+        .await
+        .map_err(|err| format!("{:#}", err))?;
+    let binary = binary.to_string_lossy().to_string();
+    state.opencode.set_binary(binary.clone());
+    // Without a saved directory the UI prompts for one and starts OpenCode then
+    let last_directory = state.settings().last_directory().await.ok().flatten();
+    if last_directory.is_some() {
+        state.start_opencode(&app).await;
+    }
+    Ok(binary)
 }
 
+/// Re-check OpenCode's API prefix, e.g. after it was upgraded in place
 #[tauri::command]
-async fn desktop_restart_opencode(state: tauri::State<'_, DesktopRuntime>) -> Result<(), String> {
+async fn desktop_redetect_api_prefix(
+    state: tauri::State<'_, DesktopRuntime>,
+) -> Result<PrefixRedetection, String> {
     state
         .opencode
-        .restart()
+        .redetect_api_prefix()
         .await
         .map_err(|err| err.to_string())
 }
 
+/// Clear a crash-loop Failed state and try starting OpenCode again
+#[tauri::command]
+async fn desktop_reset_opencode_failure(
+    state: tauri::State<'_, DesktopRuntime>,
+) -> Result<OpenCodeStatus, String> {
+    state.opencode.reset_failure();
+    state
+        .opencode
+        .ensure_running()
+        .await
+        .map_err(|err| err.to_string())?;
+    Ok(state.opencode.status())
+}
+
+/// Environment OpenCode is launched with, secrets redacted, for checking which
+/// PATH and provider variables it receives
+#[tauri::command]
+async fn desktop_opencode_environment(
+    state: tauri::State<'_, DesktopRuntime>,
+) -> Result<BTreeMap<String, String>, String> {
+    Ok(state.opencode.debug_environment().await)
+}
+
+/// Recent OpenCode stdout/stderr, for debugging startup failures
+#[tauri::command]
+fn desktop_opencode_logs(state: tauri::State<'_, DesktopRuntime>) -> Vec<OutputLine> {
+    state.opencode.output_lines()
+}
+
+/// Why and when OpenCode restarted recently, oldest first
+#[tauri::command]
+fn desktop_opencode_restart_history(state: tauri::State<'_, DesktopRuntime>) -> Vec<RestartRecord> {
+    state.opencode.restart_history()
+}
+
+/// Memory, CPU, uptime and open files of the OpenCode process
+#[tauri::command]
+async fn desktop_opencode_stats(
+    state: tauri::State<'_, DesktopRuntime>,
+) -> Result<ProcessStats, String> {
+    Ok(state.opencode.process_stats().await)
+}
+
 #[cfg(feature = "devtools")]
 #[tauri::command]
 async fn desktop_open_devtools(window: WebviewWindow) -> Result<(), String> {
@@ -278,6 +1063,8 @@ fn prevent_app_nap() {
 }
 
 fn main() {
+    heartbeat::start(env!("CARGO_PKG_VERSION"));
+
     let mut log_builder = tauri_plugin_log::Builder::default()
         .level(log::LevelFilter::Info)
         .clear_targets()
@@ -303,6 +1090,9 @@ fn main() {
             #[cfg(target_os = "macos")]
             prevent_app_nap();
 
+            paths::migrate_legacy_files();
+            heartbeat::log_startup();
+
             app.manage(TerminalState::new());
 
             let stored_state = tauri::async_runtime::block_on(load_window_state()).unwrap_or(None);
@@ -316,7 +1106,9 @@ fn main() {
                     info!("[macos] Detected macOS version: {}", macos_version);
 
                     let corner_radius = if macos_version >= 26 { 24.0 } else { 10.0 };
-                    if let Err(error) =
+                    if system_appearance::read_system_appearance().reduce_transparency {
+                        info!("[desktop:vibrancy] Skipping vibrancy: Reduce Transparency is on");
+                    } else if let Err(error) =
                         apply_vibrancy(&window, NSVisualEffectMaterial::Sidebar, None, Some(corner_radius))
                     {
                         warn!("[desktop:vibrancy] Failed to apply macOS vibrancy: {}", error);
@@ -338,16 +1130,43 @@ fn main() {
                 let _ = window.set_focus();
             }
 
-            let runtime = DesktopRuntime::initialize_sync()?;
+            let runtime = DesktopRuntime::initialize_sync(app.app_handle())?;
             app.manage(runtime.clone());
 
+            let shutdown = runtime.shutdown_coordinator();
+            if let Some(window) = app
+                .get_webview_window("main")
+                .map(|webview| webview.as_ref().window())
+            {
+                let manager = manager.clone();
+                shutdown.register(
+                    "window-state",
+                    ShutdownStage::PersistState,
+                    Duration::from_secs(2),
+                    move || async move {
+                        if let Err(err) = persist_window_state(&window, &manager).await {
+                            warn!("Failed to persist window state: {}", err);
+                        }
+                    },
+                );
+            }
+            let app_handle = app.app_handle().clone();
+            shutdown.register(
+                "terminals",
+                ShutdownStage::CloseTerminals,
+                Duration::from_secs(3),
+                move || async move {
+                    app_handle.state::<TerminalState>().close_all(Duration::from_secs(2)).await;
+                },
+            );
+
             let app_handle = app.app_handle().clone();
             let runtime_clone = runtime.clone();
             let has_initial_dir = tauri::async_runtime::block_on(runtime.settings().last_directory()).ok().flatten().is_some();
             tauri::async_runtime::spawn(async move {
                 // Only start opencode if we have a saved directory, otherwise frontend will prompt
                 if has_initial_dir {
-                    runtime_clone.start_opencode().await;
+                    runtime_clone.start_opencode(&app_handle).await;
                 } else {
                     info!("[desktop] No saved directory - waiting for user to select one");
                 }
@@ -356,31 +1175,86 @@ fn main() {
                     warn!("Failed to restore bookmarks on startup: {}", e);
                 }
 
+                let recoveries = config_journal::ConfigJournal::new(config_journal::default_journal_dir())
+                    .pending()
+                    .await;
+                if !recoveries.is_empty() {
+                    warn!(
+                        "[desktop:config] {} interrupted config operation(s) need recovery",
+                        recoveries.len()
+                    );
+                    let _ = app_handle.emit(config_journal::CONFIG_RECOVERY_EVENT, &recoveries);
+                }
+
+                if let Some(previous) = heartbeat::unclean_shutdown() {
+                    let _ = app_handle.emit(heartbeat::UNCLEAN_SHUTDOWN_EVENT, &previous);
+                }
+
                 let _ = app_handle.emit("openchamber:runtime-ready", ());
             });
 
             spawn_assistant_notifications(app.app_handle().clone(), runtime.clone());
             spawn_session_activity_tracker(app.app_handle().clone(), runtime.clone());
+            spawn_appearance_watcher(app.app_handle().clone(), runtime.appearance());
 
             Ok(())
         })
         .invoke_handler(tauri::generate_handler![
             desktop_server_info,
             desktop_restart_opencode,
+            desktop_restart_opencode_if_updated,
+            desktop_install_opencode,
+            desktop_redetect_api_prefix,
+            desktop_opencode_logs,
+            desktop_opencode_environment,
+            desktop_opencode_stats,
+            desktop_opencode_restart_history,
+            desktop_reset_opencode_failure,
+            desktop_run_token,
+            desktop_get_web_ui_url,
             #[cfg(feature = "devtools")]
             desktop_open_devtools,
             load_settings,
             save_settings,
             restart_opencode,
+            get_model_capabilities,
+            get_config_generation,
+            set_project_opencode_config,
+            get_opencode_auth_status,
+            start_opencode_auth,
             list_directory,
             search_files,
+            get_index_status,
+            rebuild_file_index,
             create_directory,
+            list_project_templates,
+            create_project_from_template,
+            relink_workspace,
+            get_project_state,
+            set_project_state,
+            list_project_state_keys,
+            create_workspace_snapshot,
+            list_workspace_snapshots,
+            restore_workspace_snapshot,
+            delete_workspace_snapshot,
+            list_config_recoveries,
+            pin_session,
+            unpin_session,
+            get_pinned_sessions,
+            search_sessions,
+            rebuild_session_index,
+            resolve_config_recovery,
+            list_actions,
+            invoke_action,
             request_directory_access,
             start_accessing_directory,
             stop_accessing_directory,
             pick_directory,
             restore_bookmarks_on_startup,
             process_directory_selection,
+            get_workspace_fs_info,
+            get_workspace_trust,
+            set_workspace_trust,
             check_is_git_repository,
             get_git_status,
             get_git_diff,
@@ -391,6 +1265,8 @@ fn main() {
             delete_git_branch,
             delete_remote_branch,
             list_git_worktrees,
+            discover_git_repository,
+            list_nested_repositories,
             add_git_worktree,
             remove_git_worktree,
             ensure_openchamber_ignored,
@@ -413,10 +1289,27 @@ fn main() {
             send_terminal_input,
             resize_terminal,
             close_terminal,
+            get_terminal_foreground,
+            list_terminal_sessions,
             restart_terminal_session,
             force_kill_terminal,
             fetch_desktop_logs,
+            create_desktop_log_download,
+            get_unclean_shutdown,
+            list_crash_reports,
+            register_artifact,
+            save_artifact,
+            export_to_html,
+            export_to_pdf,
             desktop_notify,
+            desktop_get_notification_history,
+            desktop_get_system_dnd_state,
+            desktop_get_system_appearance,
+            get_platform_capabilities,
+            set_active_session,
+            get_usage_summary,
+            get_session_usage,
+            get_budget_status,
         ])
         .on_window_event(|window, event| {
             let window_state_manager = window.state::<WindowStateManager>().inner().clone();
@@ -426,6 +1319,11 @@ fn main() {
                     // Clear dock badge and underlying badge state when the window gains focus
                     let _ = window.set_badge_count(None);
                     let _ = window.app_handle().emit("openchamber:clear-badge-sessions", ());
+                    if let Some(runtime) = window.try_state::<DesktopRuntime>() {
+                        runtime.notification_history().mark_seen();
+                        // Catch an upgrade made while the user was in a terminal
+                        runtime.opencode.check_binary_update();
+                    }
                 }
                 tauri::WindowEvent::Moved(position) => {
                     let is_maximized = window.is_maximized().unwrap_or(false);
@@ -449,18 +1347,28 @@ fn main() {
                         }
                     }
                 }
+                tauri::WindowEvent::ThemeChanged(_) => {
+                    if let Some(runtime) = window.try_state::<DesktopRuntime>() {
+                        let monitor = runtime.appearance();
+                        let app_handle = window.app_handle().clone();
+                        tauri::async_runtime::spawn(async move {
+                            monitor.check(&app_handle).await;
+                        });
+                    }
+                }
                 tauri::WindowEvent::CloseRequested { api, .. } => {
                     api.prevent_close();
                     let runtime = window.state::<DesktopRuntime>().inner().clone();
                     let window_handle = window.clone();
-                    let manager_clone = window_state_manager.clone();
                     tauri::async_runtime::spawn(async move {
-                        if let Err(err) = persist_window_state(&window_handle, &manager_clone).await
-                        {
-                            warn!("Failed to persist window state: {}", err);
+                        let settings = runtime.settings().load().await.unwrap_or_default();
+                        // Closing a window that is already hidden (e.g. from the taskbar) quits
+                        let visible = window_handle.is_visible().unwrap_or(false);
+                        if visible && background_mode::keep_running(&settings) {
+                            background_mode::hide_window(&window_handle);
+                        } else {
+                            shutdown_and_exit(window_handle.app_handle());
                         }
-                        runtime.shutdown().await;
-                        let _ = window_handle.app_handle().exit(0);
                     });
                 }
                 _ => {}
@@ -469,108 +1377,484 @@ fn main() {
         .build(tauri::generate_context!())
         .expect("failed to build Tauri application");
 
-    app.run(|_app_handle, _event| {});
+    app.run(|_app_handle, event| match event {
+        tauri::RunEvent::Exit => heartbeat::clear(),
+        // Clicking the dock icon brings back a window hidden by background mode
+        #[cfg(target_os = "macos")]
+        tauri::RunEvent::Reopen { .. } => background_mode::show_main_window(_app_handle),
+        _ => {}
+    });
+}
+
+/// Run the registered teardowns and exit; used by window close and the tray's Quit
+pub(crate) fn shutdown_and_exit(app: &AppHandle) {
+    let app = app.clone();
+    tauri::async_runtime::spawn(async move {
+        let runtime = app
+            .try_state::<DesktopRuntime>()
+            .map(|state| state.inner().clone());
+        if let Some(runtime) = runtime {
+            runtime.shutdown().await;
+        }
+        heartbeat::clear();
+        app.exit(0);
+    });
 }
 
 
+/// Why run_http_server returned without an error
+enum ServerExit {
+    Shutdown,
+    /// `server.bindAddress` changed; bind again on the same port
+    Rebind,
+}
+
 fn spawn_http_server(port: u16, state: ServerState, shutdown_rx: broadcast::Receiver<()>) {
     tauri::async_runtime::spawn(async move {
-        if let Err(error) = run_http_server(port, state, shutdown_rx).await {
-            error!("[desktop:http] server stopped: {error:?}");
+        let mut bind_rx = state.server_binding.subscribe();
+        loop {
+            let address = *bind_rx.borrow_and_update();
+            match run_http_server(address, port, state.clone(), &shutdown_rx, &mut bind_rx).await {
+                Ok(ServerExit::Rebind) => continue,
+                Ok(ServerExit::Shutdown) => break,
+                // Keep the app reachable when a LAN address cannot be bound
+                Err(error) if address != server_binding::DEFAULT_BIND_ADDRESS => {
+                    error!("[desktop:http] Failed to serve on {address}: {error:?}");
+                    state.server_binding.fall_back();
+                }
+                Err(error) => {
+                    error!("[desktop:http] server stopped: {error:?}");
+                    break;
+                }
+            }
         }
     });
 }
 
 async fn run_http_server(
+    address: IpAddr,
     port: u16,
     state: ServerState,
-    mut shutdown_rx: broadcast::Receiver<()>,
-) -> Result<()> {
-    let router = Router::new()
-        .route("/health", get(health_handler))
+    shutdown_rx: &broadcast::Receiver<()>,
+    bind_rx: &mut watch::Receiver<IpAddr>,
+) -> Result<ServerExit> {
+    // The webview presents its token, browsers of the served web UI their session
+    let api = Router::new()
         .route("/api/openchamber/models-metadata", get(models_metadata_handler))
         .route("/api/opencode/directory", post(change_directory_handler))
+        .route("/api/openchamber/events", get(event_hub::events_handler))
+        .route(
+            "/api/openchamber/events/stats",
+            get(event_hub::stats_handler),
+        )
+        .route("/api/openchamber/opencode-logs", get(opencode_logs_handler))
+        .route(
+            "/api/openchamber/environment",
+            get(environment_report::environment_handler),
+        )
+        .route(
+            server_metrics::METRICS_PATH,
+            get(server_metrics::metrics_handler),
+        )
+        .route(
+            proxy_log::LOG_PATH,
+            get(proxy_log::log_handler).delete(proxy_log::clear_handler),
+        )
         .route("/api", any(proxy_to_opencode))
         .route("/api/{*rest}", any(proxy_to_opencode))
+        .route_layer(middleware::from_fn_with_state(
+            state.clone(),
+            loopback_auth::require_api_auth,
+        ));
+
+    // Run and download carry their own tokens; health answers probes, with
+    // details only for authenticated callers
+    let cors = state.loopback_auth.cors_layer();
+    let web_ui = state.web_ui.clone();
+    let router = Router::new()
+        .route("/health", get(health_handler))
+        .route("/api/openchamber/run", post(external_run::run_handler))
+        .route(
+            "/api/openchamber/download/{token}",
+            get(downloads::download_handler),
+        )
+        .route(web_ui::EXCHANGE_PATH, get(web_ui::exchange_handler))
+        .merge(api)
+        .fallback(web_ui::static_handler)
+        // Outside the auth check, so rejected requests are recorded too
+        .layer(middleware::from_fn_with_state(
+            state.clone(),
+            proxy_log::record,
+        ))
+        .layer(middleware::from_fn_with_state(
+            state.clone(),
+            server_metrics::count_requests,
+        ))
         .with_state(state)
-        .layer(CorsLayer::permissive());
+        .layer(cors);
 
-    let addr = format!("127.0.0.1:{port}");
-    let listener = TcpListener::bind(&addr).await?;
-    info!("[desktop:http] listening on http://{addr}");
+    let mut listeners = Vec::new();
+    for addr in server_binding::listen_addresses(address, port) {
+        // Event streams to the webview are many small writes; don't let Nagle batch them
+        let listener = TcpListener::bind(addr).await?.tap_io(|tcp| {
+            let _ = tcp.set_nodelay(true);
+        });
+        info!("[desktop:http] listening on http://{addr}");
+        listeners.push(listener);
+    }
+    server_binding::log_remote_url(address, port, &web_ui);
 
-    axum::serve(listener, router)
-        .with_graceful_shutdown(async move {
-            let _ = shutdown_rx.recv().await;
-        })
-        .await?;
+    let servers = listeners.into_iter().map(|listener| {
+        let mut shutdown_rx = shutdown_rx.resubscribe();
+        axum::serve(listener, router.clone())
+            .with_graceful_shutdown(async move {
+                let _ = shutdown_rx.recv().await;
+            })
+            .into_future()
+    });
+    tokio::select! {
+        result = futures_util::future::try_join_all(servers) => {
+            result?;
+            Ok(ServerExit::Shutdown)
+        }
+        // Dropping the servers closes the listeners; open connections finish on their own
+        changed = bind_rx.changed() => Ok(if changed.is_ok() {
+            ServerExit::Rebind
+        } else {
+            ServerExit::Shutdown
+        }),
+    }
+}
 
-    Ok(())
+#[derive(Deserialize)]
+struct HealthQuery {
+    stats: Option<String>,
 }
 
-async fn health_handler(State(state): State<ServerState>) -> Json<HealthResponse> {
+/// Without the token or a web UI session /health only says the server is up:
+/// on a LAN address anyone can reach it
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct LivenessResponse {
+    status: &'static str,
+    is_opencode_ready: bool,
+}
+
+async fn health_handler(
+    State(state): State<ServerState>,
+    headers: HeaderMap,
+    RawQuery(raw_query): RawQuery,
+    Query(query): Query<HealthQuery>,
+) -> Response<Body> {
+    if !loopback_auth::ApiAuth::from_ref(&state).allows(&headers, raw_query.as_deref()) {
+        return Json(LivenessResponse {
+            status: "ok",
+            is_opencode_ready: state.opencode.is_ready(),
+        })
+        .into_response();
+    }
+    let opencode_stats = match query.stats.as_deref() {
+        Some("1") | Some("true") => Some(state.opencode.process_stats().await),
+        _ => None,
+    };
     Json(HealthResponse {
         status: "ok",
         server_port: state.server_port,
+        bind_address: state.server_binding.current().to_string(),
         opencode_port: state.opencode.current_port(),
         api_prefix: state.opencode.api_prefix(),
         is_opencode_ready: state.opencode.is_ready(),
+        opencode_reachability: state.opencode.reachability().await,
+        is_opencode_adopted: state.opencode.is_adopted(),
         cli_available: opencode_manager::check_cli_exists(),
+        opencode_status: state.opencode.status(),
+        opencode_version: state
+            .opencode
+            .cli_version()
+            .and_then(|version| version.version),
+        version_warning: state.opencode.version_warning(),
+        update_available: state.opencode.update_available(),
+        opencode_readiness: state.opencode.readiness_config().await,
+        last_restart: state.opencode.last_restart(),
+        opencode_instances: state.opencode_pool.instances().await,
+        opencode_stats,
+        project: ProjectInfo::from_manager(&state.opencode),
     })
+    .into_response()
+}
+
+async fn opencode_logs_handler(State(state): State<ServerState>) -> Json<Vec<OutputLine>> {
+    Json(state.opencode.output_lines())
 }
 
-async fn models_metadata_handler(State(state): State<ServerState>) -> Result<Json<Value>, StatusCode> {
+/// `GET /api/openchamber/models-metadata?directory=&refresh=1`: models.dev data
+/// with the project's configured providers and models merged over it.
+/// A cached payload is served right away and refreshed in the background once
+/// its TTL has passed; `refresh` waits for models.dev instead. A copy
+/// models.dev could not confirm is served with `x-openchamber-cache: stale`.
+/// The source is models.dev unless `modelsMetadataUrl` names another.
+async fn models_metadata_handler(
+    State(state): State<ServerState>,
+    RawQuery(query): RawQuery,
+    headers: HeaderMap,
+) -> Response<Body> {
+    let settings = match state.app.try_state::<DesktopRuntime>() {
+        Some(runtime) => runtime.settings().load().await.unwrap_or(Value::Null),
+        None => Value::Null,
+    };
+    let source = models_metadata::source_url(&settings);
+    let refresh = query
+        .as_deref()
+        .is_some_and(|query| query.split('&').any(|pair| pair == "refresh=1"));
+    let directory = proxy_routes::requested_directory(query.as_deref(), &headers)
+        .map(PathBuf::from)
+        .unwrap_or_else(|| state.opencode.get_working_directory());
+    if !directory.is_dir() {
+        return models_metadata_error(
+            StatusCode::BAD_REQUEST,
+            format!("{} is not a directory", directory.display()),
+            &source,
+        );
+    }
+    let key = directory.to_string_lossy().to_string();
+
+    // Nothing to serve yet, or the caller asked to wait for the source
+    let cached = &state.models_metadata_cache;
+    if refresh || cached.lock().await.upstream_for(&source).is_none() {
+        if let Err(message) = refresh_models_metadata(&state, &source).await {
+            if cached.lock().await.upstream_for(&source).is_none() {
+                return models_metadata_error(StatusCode::BAD_GATEWAY, message, &source);
+            }
+        }
+    }
+
     let now = Instant::now();
-    let cached_payload: Option<Value> = {
-        let cache = state.models_metadata_cache.lock().await;
-        if let (Some(payload), Some(fetched_at)) = (&cache.payload, cache.fetched_at) {
-            if now.duration_since(fetched_at) < MODELS_METADATA_CACHE_TTL {
-                return Ok(Json(payload.clone()));
+    let (upstream, revision, stale) = {
+        let mut cache = state.models_metadata_cache.lock().await;
+        if cache.begin_refresh(now) {
+            spawn_models_metadata_refresh(state.clone(), source.clone());
+        }
+        let Some(upstream) = cache.upstream_for(&source) else {
+            // The source changed while this request was waiting
+            return models_metadata_error(
+                StatusCode::SERVICE_UNAVAILABLE,
+                "models metadata source changed; retry",
+                &source,
+            );
+        };
+        let stale = upstream.stale;
+        if let Some(merged) = cache.merged.get(&key) {
+            if merged.revision == cache.revision
+                && now.duration_since(merged.merged_at) < MODELS_METADATA_CACHE_TTL
+            {
+                return models_metadata_response(merged.payload.clone(), stale, &source);
             }
         }
-        cache.payload.clone()
+        (upstream.payload.clone(), cache.revision, stale)
     };
+    let merged = merge_models_metadata(&state, key, &directory, &upstream, revision).await;
+    models_metadata_response(merged, stale, &source)
+}
 
-    let response = state
-        .client
-        .get(MODELS_DEV_API_URL)
-        .header(header::ACCEPT, "application/json")
-        .timeout(MODELS_METADATA_REQUEST_TIMEOUT)
-        .send()
-        .await
-        .map_err(|error| {
-            warn!("[desktop:http] Failed to fetch models metadata: {error}");
-            StatusCode::BAD_GATEWAY
-        })?;
-
-    if !response.status().is_success() {
-        warn!(
-            "[desktop:http] models.dev responded with status {}",
-            response.status()
+fn models_metadata_response(payload: Value, stale: bool, source: &str) -> Response<Body> {
+    let mut response = Json(payload).into_response();
+    if stale {
+        response.headers_mut().insert(
+            models_metadata::CACHE_STATUS_HEADER,
+            header::HeaderValue::from_static(models_metadata::CACHE_STATUS_STALE),
         );
-        if let Some(payload) = cached_payload {
-            return Ok(Json(payload));
-        }
-        return Err(StatusCode::BAD_GATEWAY);
     }
+    with_models_source(response, source)
+}
 
-    let payload = response.json::<Value>().await.map_err(|error| {
-        warn!("[desktop:http] Failed to parse models.dev payload: {error}");
-        StatusCode::BAD_GATEWAY
-    })?;
+fn models_metadata_error(
+    status: StatusCode,
+    message: impl Into<String>,
+    source: &str,
+) -> Response<Body> {
+    let body = serde_json::json!({ "error": message.into(), "source": source });
+    with_models_source((status, Json(body)).into_response(), source)
+}
 
-    {
-        let mut cache = state.models_metadata_cache.lock().await;
-        cache.payload = Some(payload.clone());
-        cache.fetched_at = Some(Instant::now());
+fn with_models_source(mut response: Response<Body>, source: &str) -> Response<Body> {
+    if let Ok(value) = header::HeaderValue::from_str(source) {
+        response
+            .headers_mut()
+            .insert(models_metadata::SOURCE_HEADER, value);
+    }
+    response
+}
+
+/// Refresh the upstream payload off the request path, then allow the next one
+fn spawn_models_metadata_refresh(state: ServerState, source: String) {
+    tauri::async_runtime::spawn(async move {
+        let _ = refresh_models_metadata(&state, &source).await;
+        state.models_metadata_cache.lock().await.refreshing = false;
+    });
+}
+
+/// Ask `source` for a newer payload than the cached one and store it. On
+/// failure the cached copy, if any, is marked stale and kept.
+async fn refresh_models_metadata(state: &ServerState, source: &str) -> Result<(), String> {
+    let (etag, last_modified) = {
+        let cache = state.models_metadata_cache.lock().await;
+        cache
+            .upstream_for(source)
+            .map(|upstream| (upstream.etag.clone(), upstream.last_modified.clone()))
+            .unwrap_or_default()
+    };
+    let fetched =
+        fetch_models_metadata(state, source, etag.as_deref(), last_modified.as_deref()).await;
+
+    let mut cache = state.models_metadata_cache.lock().await;
+    let checked_at = Some(Instant::now());
+    let stored = match fetched {
+        Ok(ModelsFetch::Updated {
+            payload,
+            etag,
+            last_modified,
+        }) => {
+            cache.revision += 1;
+            cache.upstream = Some(UpstreamPayload {
+                source: source.to_string(),
+                payload: payload.clone(),
+                etag: etag.clone(),
+                last_modified: last_modified.clone(),
+                checked_at,
+                stale: false,
+            });
+            StoredPayload {
+                source: source.to_string(),
+                fetched_at: chrono::Utc::now(),
+                etag,
+                last_modified,
+                payload,
+            }
+        }
+        Ok(ModelsFetch::NotModified) => {
+            if let Some(upstream) = cache.upstream_for_mut(source) {
+                upstream.checked_at = checked_at;
+                upstream.stale = false;
+            }
+            return Ok(());
+        }
+        Err(message) => {
+            if let Some(upstream) = cache.upstream_for_mut(source) {
+                upstream.checked_at = checked_at;
+                upstream.stale = true;
+            }
+            return Err(message);
+        }
+    };
+    drop(cache);
+    if let Err(err) = state.models_disk_cache.save(&stored).await {
+        warn!("[desktop:http] Failed to persist models metadata: {}", err);
+    }
+    Ok(())
+}
+
+async fn fetch_models_metadata(
+    state: &ServerState,
+    source: &str,
+    etag: Option<&str>,
+    last_modified: Option<&str>,
+) -> Result<ModelsFetch, String> {
+    let mut request = state
+        .client
+        .get(source)
+        .header(header::ACCEPT, "application/json")
+        .timeout(MODELS_METADATA_REQUEST_TIMEOUT);
+    if let Some(etag) = etag {
+        request = request.header(header::IF_NONE_MATCH, etag);
+    }
+    if let Some(last_modified) = last_modified {
+        request = request.header(header::IF_MODIFIED_SINCE, last_modified);
+    }
+    let response = request.send().await.map_err(|error| {
+        warn!("[desktop:http] Failed to fetch models metadata from {source}: {error}");
+        format!("Failed to fetch models metadata from {source}: {error}")
+    })?;
+
+    if response.status() == reqwest::StatusCode::NOT_MODIFIED {
+        return Ok(ModelsFetch::NotModified);
+    }
+    if !response.status().is_success() {
+        warn!(
+            "[desktop:http] {} responded with status {}",
+            source,
+            response.status()
+        );
+        return Err(format!(
+            "{source} responded with status {}",
+            response.status()
+        ));
+    }
+
+    let validator = |name: header::HeaderName| {
+        response
+            .headers()
+            .get(name)
+            .and_then(|value| value.to_str().ok())
+            .map(str::to_string)
+    };
+    let etag = validator(header::ETAG);
+    let last_modified = validator(header::LAST_MODIFIED);
+    let payload = response.json::<Value>().await.map_err(|error| {
+        warn!("[desktop:http] Failed to parse models metadata from {source}: {error}");
+        format!("{source} did not return JSON: {error}")
+    })?;
+    // A models.dev-compatible payload maps provider ids to providers
+    if !payload.is_object() {
+        let kind = match payload {
+            Value::Array(_) => "an array",
+            Value::String(_) => "a string",
+            Value::Number(_) => "a number",
+            Value::Bool(_) => "a boolean",
+            _ => "null",
+        };
+        warn!("[desktop:http] Rejecting models metadata from {source}: {kind}");
+        return Err(format!(
+            "{source} returned {kind}, not an object of providers keyed by id"
+        ));
     }
+    Ok(ModelsFetch::Updated {
+        payload,
+        etag,
+        last_modified,
+    })
+}
 
-    Ok(Json(payload))
+/// Merge the project's providers over `upstream` and cache the result for `key`
+async fn merge_models_metadata(
+    state: &ServerState,
+    key: String,
+    directory: &Path,
+    upstream: &Value,
+    revision: u64,
+) -> Value {
+    let providers = models_metadata::project_providers(&state.opencode, directory).await;
+    let payload = models_metadata::merge_providers(upstream, &providers);
+    state.models_metadata_cache.lock().await.merged.insert(
+        key,
+        MergedPayload {
+            payload: payload.clone(),
+            merged_at: Instant::now(),
+            revision,
+        },
+    );
+    payload
 }
 
 #[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
 struct DirectoryChangeRequest {
     path: String,
+    /// Switch even if sessions in the current directory are still running
+    #[serde(default)]
+    force: bool,
+    /// With `force`, also interrupt busy pinned sessions
+    #[serde(default)]
+    override_pinned: bool,
 }
 
 #[derive(Serialize)]
@@ -578,46 +1862,457 @@ struct DirectoryChangeResponse {
     success: bool,
     restarted: bool,
     path: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    fs_info: Option<workspace_fs::WorkspaceFsInfo>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    trust: Option<workspace_trust::WorkspaceTrust>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct DirectoryChangeBlockedResponse {
+    success: bool,
+    error: String,
+    busy_sessions: Vec<BusySession>,
+}
+
+/// Machine-readable `error` of a failed proxied request or directory change
+#[derive(Clone, Copy, Debug, Serialize)]
+#[serde(rename_all = "snake_case")]
+enum ProxyErrorCode {
+    /// Stopped, failed or missing its CLI; nothing is bringing it up
+    OpencodeNotRunning,
+    /// Starting, restarting or about to retry after a crash
+    OpencodeStarting,
+    ConnectRefused,
+    /// Answered with 504 and `elapsedMs`
+    Timeout,
+    BodyTooLarge,
+    /// Any other failure talking to OpenCode
+    UpstreamError,
+    InvalidDirectory,
+    DirectoryNotFound,
+    DirectorySwitchFailed,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ProxyErrorResponse {
+    error: ProxyErrorCode,
+    message: String,
+    opencode_port: Option<u16>,
+    /// How long the request ran before it timed out
+    #[serde(skip_serializing_if = "Option::is_none")]
+    elapsed_ms: Option<u64>,
+    /// The body size limit, when the body exceeded it
+    #[serde(skip_serializing_if = "Option::is_none")]
+    limit_bytes: Option<u64>,
+    /// The declared size of a body over the limit
+    #[serde(skip_serializing_if = "Option::is_none")]
+    size_bytes: Option<u64>,
+    /// Lifecycle status, when OpenCode is unavailable
+    #[serde(skip_serializing_if = "Option::is_none")]
+    status: Option<OpenCodeStatus>,
 }
 
 fn json_response<T: Serialize>(status: StatusCode, payload: T) -> Response<Body> {
     (status, Json(payload)).into_response()
 }
 
+fn proxy_error_response(
+    status: StatusCode,
+    error: ProxyErrorCode,
+    message: impl Into<String>,
+    opencode_port: Option<u16>,
+) -> Response<Body> {
+    json_response(
+        status,
+        ProxyErrorResponse {
+            error,
+            message: message.into(),
+            opencode_port,
+            elapsed_ms: None,
+            limit_bytes: None,
+            size_bytes: None,
+            status: None,
+        },
+    )
+}
+
+/// 503 carrying the lifecycle status so the UI can explain the outage without polling
+fn opencode_unavailable_response(opencode: &OpenCodeManager) -> Response<Body> {
+    let status = opencode.status();
+    let error = match status.state {
+        LifecycleState::Starting | LifecycleState::Restarting | LifecycleState::Crashed => {
+            ProxyErrorCode::OpencodeStarting
+        }
+        _ => ProxyErrorCode::OpencodeNotRunning,
+    };
+    json_response(
+        StatusCode::SERVICE_UNAVAILABLE,
+        ProxyErrorResponse {
+            error,
+            message: status.message.clone(),
+            opencode_port: opencode.current_port(),
+            elapsed_ms: None,
+            limit_bytes: None,
+            size_bytes: None,
+            status: Some(status),
+        },
+    )
+}
+
 fn config_error_response(status: StatusCode, message: impl Into<String>) -> Response<Body> {
     json_response(status, ConfigErrorResponse {
         error: message.into(),
     })
 }
 
-async fn parse_request_payload(req: Request<Body>) -> Result<HashMap<String, Value>, Response<Body>> {
-    let (_, body) = req.into_parts();
-    let body_bytes = to_bytes(body, PROXY_BODY_LIMIT)
+/// Buffer and parse a config payload. `limit` is `server.maxBodyMb`, but config
+/// payloads are held in memory, so they never get more than CONFIG_PAYLOAD_LIMIT.
+async fn parse_request_payload(
+    req: Request<Body>,
+    limit: u64,
+) -> Result<HashMap<String, Value>, Response<Body>> {
+    let limit = limit.min(CONFIG_PAYLOAD_LIMIT);
+    let (parts, body) = req.into_parts();
+    let declared_length = parts
+        .headers
+        .get(header::CONTENT_LENGTH)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.parse::<u64>().ok());
+    let body_bytes = to_bytes(body, usize::try_from(limit).unwrap_or(usize::MAX))
         .await
-        .map_err(|_| config_error_response(StatusCode::BAD_REQUEST, "Invalid request body"))?;
+        .map_err(|_| body_too_large_response(limit, declared_length, None))?;
 
     if body_bytes.is_empty() {
         return Ok(HashMap::new());
     }
 
-    serde_json::from_slice::<HashMap<String, Value>>(&body_bytes)
-        .map_err(|_| config_error_response(StatusCode::BAD_REQUEST, "Malformed JSON payload"))
+    let payload = serde_json::from_slice::<HashMap<String, Value>>(&body_bytes)
+        .map_err(|_| config_error_response(StatusCode::BAD_REQUEST, "Malformed JSON payload"))?;
+
+    for (field, value) in payload.iter() {
+        let size = match value {
+            Value::String(text) => text.len(),
+            other => other.to_string().len(),
+        };
+        if size > CONFIG_FIELD_LIMIT {
+            return Err(config_error_response(
+                StatusCode::PAYLOAD_TOO_LARGE,
+                format!("Field {} exceeds {} bytes", field, CONFIG_FIELD_LIMIT),
+            ));
+        }
+    }
+
+    Ok(payload)
+}
+
+/// How a config mutation was applied to the running OpenCode server
+#[derive(Clone, Copy, Debug)]
+enum ConfigRefresh {
+    Restarted { coalesced: bool, generation: u64 },
+    HotReloaded { generation: u64 },
+    /// Left to ConfigRefreshScheduler, which runs once mutations go quiet and
+    /// reports how with CONFIG_REFRESH_EVENT; `generation` is the one to wait for
+    Scheduled {
+        delay_ms: u64,
+        rearmed: bool,
+        generation: u64,
+    },
+    /// The client asked to batch changes with `?restart=false`
+    Deferred,
+}
+
+/// Query flags on config mutations: `restart=false` defers the OpenCode refresh,
+/// `force=true` restarts even while sessions are busy, and `overridePinned=true`
+/// additionally lets it interrupt pinned sessions
+#[derive(Clone, Copy, Debug)]
+struct RefreshOptions {
+    restart: bool,
+    force: bool,
+    override_pinned: bool,
+}
+
+impl RefreshOptions {
+    fn from_query(query: Option<&str>) -> Self {
+        let mut options = Self {
+            restart: true,
+            force: false,
+            override_pinned: false,
+        };
+        for pair in query.unwrap_or_default().split('&') {
+            let mut parts = pair.splitn(2, '=');
+            let key = parts.next().unwrap_or_default();
+            let value = parts.next().unwrap_or_default();
+            match key {
+                "restart" => options.restart = !matches!(value, "false" | "0"),
+                "force" => options.force = matches!(value, "true" | "1"),
+                "overridePinned" => options.override_pinned = matches!(value, "true" | "1"),
+                _ => {}
+            }
+        }
+        options
+    }
+}
+
+/// Drop caches the mutation invalidated and check OpenCode may be refreshed:
+/// `Some(Deferred)` when the client deferred it, an error while sessions are busy
+async fn check_config_refresh(
+    state: &ServerState,
+    reason: &str,
+    options: RefreshOptions,
+) -> Result<Option<ConfigRefresh>, Response<Body>> {
+    state.model_capabilities.invalidate();
+    state.agent_preferences.invalidate().await;
+
+    if !options.restart {
+        info!("[desktop:config] Deferring OpenCode refresh after {}", reason);
+        return Ok(Some(ConfigRefresh::Deferred));
+    }
+
+    let busy = session_activity::busy_sessions(&state.session_phases, &state.session_pins).await;
+    if session_activity::restart_blocked(&busy, options.force, options.override_pinned) {
+        warn!(
+            "[desktop:config] Not refreshing OpenCode after {}: {} session(s) busy",
+            reason,
+            busy.len()
+        );
+        return Err(json_response(
+            StatusCode::CONFLICT,
+            ConfigActionResponse {
+                success: false,
+                requires_reload: false,
+                message: "Configuration saved, but OpenCode was not reloaded because sessions are busy. Retry with force=true (plus overridePinned=true for pinned sessions) or call /api/config/reload later.".to_string(),
+                reload_delay_ms: 0,
+                restart_coalesced: false,
+                restart_scheduled_in_ms: None,
+                config_generation: state.config_generation.current(),
+                busy_sessions: Some(busy),
+                changed_entities: Vec::new(),
+                definition: None,
+            },
+        ));
+    }
+    Ok(None)
 }
 
 async fn refresh_opencode_after_config_change(
     state: &ServerState,
     reason: &str,
-) -> Result<(), Response<Body>> {
+    options: RefreshOptions,
+) -> Result<ConfigRefresh, Response<Body>> {
+    if let Some(refresh) = check_config_refresh(state, reason, options).await? {
+        return Ok(refresh);
+    }
+
+    if state.opencode.supports_config_reload() {
+        match state.opencode.reload_config().await {
+            Ok(()) => {
+                return Ok(ConfigRefresh::HotReloaded {
+                    generation: state.config_generation.bump(reason),
+                })
+            }
+            Err(err) => warn!(
+                "[desktop:config] Hot reload failed after {}, falling back to restart: {}",
+                reason, err
+            ),
+        }
+    }
+
     info!("[desktop:config] Restarting OpenCode after {}", reason);
     state
-        .opencode
-        .restart()
+        .config_restart
+        .request_restart(reason)
         .await
+        .map(|outcome| ConfigRefresh::Restarted {
+            coalesced: outcome.coalesced,
+            generation: outcome.generation,
+        })
         .map_err(|err| config_error_response(
             StatusCode::INTERNAL_SERVER_ERROR,
             format!("Failed to restart OpenCode: {}", err),
-        ))?;
-    Ok(())
+        ))
+}
+
+/// Debounced refresh_opencode_after_config_change for agent and command
+/// mutations: the response goes out at once, and OpenCode picks up a burst of
+/// changes together once they stop
+async fn schedule_opencode_refresh_after_config_change(
+    state: &ServerState,
+    reason: &str,
+    changed: &[ChangedEntity],
+    options: RefreshOptions,
+) -> Result<ConfigRefresh, Response<Body>> {
+    if let Some(refresh) = check_config_refresh(state, reason, options).await? {
+        return Ok(refresh);
+    }
+    let scheduled = state.config_refresh.schedule(
+        reason,
+        changed,
+        BusyOverride {
+            force: options.force,
+            override_pinned: options.override_pinned,
+        },
+    );
+    if !scheduled.rearmed {
+        tauri::async_runtime::spawn(publish_scheduled_refresh(
+            state.clone(),
+            scheduled.applied.clone(),
+        ));
+    }
+    Ok(ConfigRefresh::Scheduled {
+        delay_ms: scheduled.delay.as_millis() as u64,
+        rearmed: scheduled.rearmed,
+        generation: scheduled.generation,
+    })
+}
+
+/// Once a scheduled refresh ran, tell the webview the generation it produced
+/// and, as config_action would have, whether open sessions need a reload. A
+/// refresh skipped for busy sessions also reports the restart as skipped.
+async fn publish_scheduled_refresh(state: ServerState, applied: RefreshFuture) {
+    let payload = match applied.await {
+        Ok(applied) => {
+            let (outcome, busy) = match &applied.outcome {
+                RefreshOutcome::HotReloaded => ("hotReloaded", None),
+                RefreshOutcome::Restarted => ("restarted", None),
+                RefreshOutcome::Skipped { busy } => ("skipped", Some(busy)),
+            };
+            if let Some(busy) = busy {
+                let _ = state.app.emit(
+                    opencode_manager::RESTART_SKIPPED_EVENT,
+                    serde_json::json!({
+                        "reason": "busySessions",
+                        "trigger": applied.reason,
+                        "busySessions": busy,
+                    }),
+                );
+            }
+            let requires_reload = matches!(applied.outcome, RefreshOutcome::Restarted)
+                && client_reload_needed(&state, &applied.changed).await;
+            serde_json::json!({
+                "success": true,
+                "generation": applied.generation,
+                "reason": applied.reason,
+                "outcome": outcome,
+                "coalesced": applied.coalesced,
+                "requiresReload": requires_reload,
+                "reloadDelayMs": if requires_reload { CLIENT_RELOAD_DELAY_MS } else { 0 },
+                "changedEntities": applied.changed,
+                "busySessions": busy,
+            })
+        }
+        Err(err) => serde_json::json!({ "success": false, "error": err }),
+    };
+    let _ = state.app.emit(CONFIG_REFRESH_EVENT, payload);
+}
+
+/// Build the success response for a config mutation; `summary` reads like "Agent foo created".
+/// After a restart the webview only reloads when open sessions depend on `changed`.
+async fn config_action_response(
+    state: &ServerState,
+    summary: &str,
+    changed: Vec<ChangedEntity>,
+    refresh: ConfigRefresh,
+) -> Response<Body> {
+    json_response(
+        StatusCode::OK,
+        config_action(state, summary, changed, refresh).await,
+    )
+}
+
+/// Body of config_action_response, for routes that add to it
+async fn config_action(
+    state: &ServerState,
+    summary: &str,
+    changed: Vec<ChangedEntity>,
+    refresh: ConfigRefresh,
+) -> ConfigActionResponse {
+    let (requires_reload, message) = match refresh {
+        ConfigRefresh::Restarted { .. } if client_reload_needed(state, &changed).await => (
+            true,
+            format!("{} successfully. Reloading interface...", summary),
+        ),
+        ConfigRefresh::Restarted { .. } => (
+            false,
+            format!(
+                "{} successfully. OpenCode restarted; open sessions are unaffected.",
+                summary
+            ),
+        ),
+        ConfigRefresh::HotReloaded { .. } => (
+            false,
+            format!("{} successfully. OpenCode reloaded the configuration.", summary),
+        ),
+        // Whether the refresh restarts OpenCode, and so whether the webview
+        // reloads, is only known when it runs; CONFIG_REFRESH_EVENT says
+        ConfigRefresh::Scheduled { delay_ms, .. } => (
+            false,
+            format!(
+                "{} successfully. OpenCode picks up the change in {}ms.",
+                summary, delay_ms
+            ),
+        ),
+        ConfigRefresh::Deferred => (
+            false,
+            format!(
+                "{} successfully. Changes apply after /api/config/reload.",
+                summary
+            ),
+        ),
+    };
+
+    let config_generation = match refresh {
+        ConfigRefresh::Restarted { generation, .. }
+        | ConfigRefresh::HotReloaded { generation }
+        | ConfigRefresh::Scheduled { generation, .. } => generation,
+        ConfigRefresh::Deferred => state.config_generation.current(),
+    };
+
+    ConfigActionResponse {
+        success: true,
+        requires_reload,
+        message,
+        reload_delay_ms: if requires_reload {
+            CLIENT_RELOAD_DELAY_MS
+        } else {
+            0
+        },
+        restart_coalesced: matches!(
+            refresh,
+            ConfigRefresh::Restarted {
+                coalesced: true,
+                ..
+            } | ConfigRefresh::Scheduled { rearmed: true, .. }
+        ),
+        restart_scheduled_in_ms: match refresh {
+            ConfigRefresh::Scheduled { delay_ms, .. } => Some(delay_ms),
+            _ => None,
+        },
+        config_generation,
+        busy_sessions: None,
+        changed_entities: changed,
+        definition: None,
+    }
+}
+
+async fn client_reload_needed(state: &ServerState, changed: &[ChangedEntity]) -> bool {
+    if changed.is_empty() {
+        return true;
+    }
+    let active_session = state
+        .app
+        .try_state::<DesktopRuntime>()
+        .and_then(|runtime| runtime.active_session());
+    let client = OpenCodeClient::new(state.upstream.clone(), state.opencode.clone());
+    let dependencies = reload_decision::collect_session_dependencies(
+        client,
+        &state.session_phases,
+        active_session,
+    )
+    .await;
+    reload_decision::requires_client_reload(changed, dependencies.as_ref())
 }
 
 async fn handle_agent_route(
@@ -625,10 +2320,11 @@ async fn handle_agent_route(
     method: Method,
     req: Request<Body>,
     name: String,
+    options: RefreshOptions,
 ) -> Result<Response<Body>, StatusCode> {
     match method {
         Method::GET => {
-            match opencode_config::get_agent_sources(&name).await {
+            match opencode_config::get_agent_sources(&state.config_paths, &name).await {
                 Ok(sources) => Ok(json_response(
                     StatusCode::OK,
                     ConfigMetadataResponse {
@@ -647,31 +2343,36 @@ async fn handle_agent_route(
             }
         }
         Method::POST => {
-            let payload = match parse_request_payload(req).await {
+            let payload = match parse_request_payload(req, state.max_request_body()).await {
                 Ok(data) => data,
                 Err(resp) => return Ok(resp),
             };
 
-            match opencode_config::create_agent(&name, &payload).await {
+            if let Err(err) = notification_policy::validate_agent_payload(&payload) {
+                return Ok(config_error_response(StatusCode::BAD_REQUEST, err));
+            }
+
+            match opencode_config::create_agent(&state.config_paths, &name, &payload).await {
                 Ok(()) => {
-                    if let Err(resp) =
-                        refresh_opencode_after_config_change(state, "agent creation").await
+                    let refresh = match schedule_opencode_refresh_after_config_change(
+                        state,
+                        "agent creation",
+                        &[ChangedEntity::agent(&name)],
+                        options,
+                    )
+                    .await
                     {
-                        return Ok(resp);
-                    }
+                        Ok(refresh) => refresh,
+                        Err(resp) => return Ok(resp),
+                    };
 
-                    Ok(json_response(
-                        StatusCode::OK,
-                        ConfigActionResponse {
-                            success: true,
-                            requires_reload: true,
-                            message: format!(
-                                "Agent {} created successfully. Reloading interface...",
-                                name
-                            ),
-                            reload_delay_ms: CLIENT_RELOAD_DELAY_MS,
-                        },
-                    ))
+                    Ok(config_action_response(
+                        state,
+                        &format!("Agent {} created", name),
+                        vec![ChangedEntity::agent(&name)],
+                        refresh,
+                    )
+                    .await)
                 }
                 Err(err) => {
                     error!("[desktop:config] Failed to create agent {}: {}", name, err);
@@ -683,31 +2384,36 @@ async fn handle_agent_route(
             }
         }
         Method::PATCH => {
-            let payload = match parse_request_payload(req).await {
+            let payload = match parse_request_payload(req, state.max_request_body()).await {
                 Ok(data) => data,
                 Err(resp) => return Ok(resp),
             };
 
-            match opencode_config::update_agent(&name, &payload).await {
+            if let Err(err) = notification_policy::validate_agent_payload(&payload) {
+                return Ok(config_error_response(StatusCode::BAD_REQUEST, err));
+            }
+
+            match opencode_config::update_agent(&state.config_paths, &name, &payload).await {
                 Ok(()) => {
-                    if let Err(resp) =
-                        refresh_opencode_after_config_change(state, "agent update").await
+                    let refresh = match schedule_opencode_refresh_after_config_change(
+                        state,
+                        "agent update",
+                        &[ChangedEntity::agent(&name)],
+                        options,
+                    )
+                    .await
                     {
-                        return Ok(resp);
-                    }
+                        Ok(refresh) => refresh,
+                        Err(resp) => return Ok(resp),
+                    };
 
-                    Ok(json_response(
-                        StatusCode::OK,
-                        ConfigActionResponse {
-                            success: true,
-                            requires_reload: true,
-                            message: format!(
-                                "Agent {} updated successfully. Reloading interface...",
-                                name
-                            ),
-                            reload_delay_ms: CLIENT_RELOAD_DELAY_MS,
-                        },
-                    ))
+                    Ok(config_action_response(
+                        state,
+                        &format!("Agent {} updated", name),
+                        vec![ChangedEntity::agent(&name)],
+                        refresh,
+                    )
+                    .await)
                 }
                 Err(err) => {
                     error!("[desktop:config] Failed to update agent {}: {}", name, err);
@@ -718,26 +2424,70 @@ async fn handle_agent_route(
                 }
             }
         }
-        Method::DELETE => match opencode_config::delete_agent(&name).await {
+        Method::PUT => {
+            let payload = match parse_request_payload(req, state.max_request_body()).await {
+                Ok(data) => data,
+                Err(resp) => return Ok(resp),
+            };
+
+            if let Err(err) = notification_policy::validate_agent_payload(&payload) {
+                return Ok(config_error_response(StatusCode::BAD_REQUEST, err));
+            }
+
+            match opencode_config::replace_agent(&state.config_paths, &name, &payload).await {
+                Ok(definition) => {
+                    let refresh = match schedule_opencode_refresh_after_config_change(
+                        state,
+                        "agent replacement",
+                        &[ChangedEntity::agent(&name)],
+                        options,
+                    )
+                    .await
+                    {
+                        Ok(refresh) => refresh,
+                        Err(resp) => return Ok(resp),
+                    };
+
+                    let mut response = config_action(
+                        state,
+                        &format!("Agent {} replaced", name),
+                        vec![ChangedEntity::agent(&name)],
+                        refresh,
+                    )
+                    .await;
+                    response.definition = Some(definition);
+                    Ok(json_response(StatusCode::OK, response))
+                }
+                Err(err) => {
+                    error!("[desktop:config] Failed to replace agent {}: {}", name, err);
+                    Ok(config_error_response(
+                        StatusCode::INTERNAL_SERVER_ERROR,
+                        err.to_string(),
+                    ))
+                }
+            }
+        }
+        Method::DELETE => match opencode_config::delete_agent(&state.config_paths, &name).await {
             Ok(()) => {
-                if let Err(resp) =
-                    refresh_opencode_after_config_change(state, "agent deletion").await
+                let refresh = match schedule_opencode_refresh_after_config_change(
+                    state,
+                    "agent deletion",
+                    &[ChangedEntity::agent(&name)],
+                    options,
+                )
+                .await
                 {
-                    return Ok(resp);
-                }
+                    Ok(refresh) => refresh,
+                    Err(resp) => return Ok(resp),
+                };
 
-                Ok(json_response(
-                    StatusCode::OK,
-                    ConfigActionResponse {
-                        success: true,
-                        requires_reload: true,
-                        message: format!(
-                            "Agent {} deleted successfully. Reloading interface...",
-                            name
-                        ),
-                        reload_delay_ms: CLIENT_RELOAD_DELAY_MS,
-                    },
-                ))
+                Ok(config_action_response(
+                    state,
+                    &format!("Agent {} deleted", name),
+                    vec![ChangedEntity::agent(&name)],
+                    refresh,
+                )
+                .await)
             }
             Err(err) => {
                 error!("[desktop:config] Failed to delete agent {}: {}", name, err);
@@ -747,7 +2497,10 @@ async fn handle_agent_route(
                 ))
             }
         },
-        _ => Ok(StatusCode::METHOD_NOT_ALLOWED.into_response()),
+        _ => Ok(config_method_response(
+            StatusCode::METHOD_NOT_ALLOWED,
+            ConfigRoute::Agent,
+        )),
     }
 }
 
@@ -756,10 +2509,11 @@ async fn handle_command_route(
     method: Method,
     req: Request<Body>,
     name: String,
+    options: RefreshOptions,
 ) -> Result<Response<Body>, StatusCode> {
     match method {
         Method::GET => {
-            match opencode_config::get_command_sources(&name).await {
+            match opencode_config::get_command_sources(&state.config_paths, &name).await {
                 Ok(sources) => Ok(json_response(
                     StatusCode::OK,
                     ConfigMetadataResponse {
@@ -778,31 +2532,32 @@ async fn handle_command_route(
             }
         }
         Method::POST => {
-            let payload = match parse_request_payload(req).await {
+            let payload = match parse_request_payload(req, state.max_request_body()).await {
                 Ok(data) => data,
                 Err(resp) => return Ok(resp),
             };
 
-            match opencode_config::create_command(&name, &payload).await {
+            match opencode_config::create_command(&state.config_paths, &name, &payload).await {
                 Ok(()) => {
-                    if let Err(resp) =
-                        refresh_opencode_after_config_change(state, "command creation").await
+                    let refresh = match schedule_opencode_refresh_after_config_change(
+                        state,
+                        "command creation",
+                        &[ChangedEntity::command(&name)],
+                        options,
+                    )
+                    .await
                     {
-                        return Ok(resp);
-                    }
+                        Ok(refresh) => refresh,
+                        Err(resp) => return Ok(resp),
+                    };
 
-                    Ok(json_response(
-                        StatusCode::OK,
-                        ConfigActionResponse {
-                            success: true,
-                            requires_reload: true,
-                            message: format!(
-                                "Command {} created successfully. Reloading interface...",
-                                name
-                            ),
-                            reload_delay_ms: CLIENT_RELOAD_DELAY_MS,
-                        },
-                    ))
+                    Ok(config_action_response(
+                        state,
+                        &format!("Command {} created", name),
+                        vec![ChangedEntity::command(&name)],
+                        refresh,
+                    )
+                    .await)
                 }
                 Err(err) => {
                     error!("[desktop:config] Failed to create command {}: {}", name, err);
@@ -814,31 +2569,32 @@ async fn handle_command_route(
             }
         }
         Method::PATCH => {
-            let payload = match parse_request_payload(req).await {
+            let payload = match parse_request_payload(req, state.max_request_body()).await {
                 Ok(data) => data,
                 Err(resp) => return Ok(resp),
             };
 
-            match opencode_config::update_command(&name, &payload).await {
+            match opencode_config::update_command(&state.config_paths, &name, &payload).await {
                 Ok(()) => {
-                    if let Err(resp) =
-                        refresh_opencode_after_config_change(state, "command update").await
+                    let refresh = match schedule_opencode_refresh_after_config_change(
+                        state,
+                        "command update",
+                        &[ChangedEntity::command(&name)],
+                        options,
+                    )
+                    .await
                     {
-                        return Ok(resp);
-                    }
+                        Ok(refresh) => refresh,
+                        Err(resp) => return Ok(resp),
+                    };
 
-                    Ok(json_response(
-                        StatusCode::OK,
-                        ConfigActionResponse {
-                            success: true,
-                            requires_reload: true,
-                            message: format!(
-                                "Command {} updated successfully. Reloading interface...",
-                                name
-                            ),
-                            reload_delay_ms: CLIENT_RELOAD_DELAY_MS,
-                        },
-                    ))
+                    Ok(config_action_response(
+                        state,
+                        &format!("Command {} updated", name),
+                        vec![ChangedEntity::command(&name)],
+                        refresh,
+                    )
+                    .await)
                 }
                 Err(err) => {
                     error!("[desktop:config] Failed to update command {}: {}", name, err);
@@ -849,26 +2605,69 @@ async fn handle_command_route(
                 }
             }
         }
-        Method::DELETE => match opencode_config::delete_command(&name).await {
+        Method::PUT => {
+            let payload = match parse_request_payload(req, state.max_request_body()).await {
+                Ok(data) => data,
+                Err(resp) => return Ok(resp),
+            };
+
+            match opencode_config::replace_command(&state.config_paths, &name, &payload).await {
+                Ok(definition) => {
+                    let refresh = match schedule_opencode_refresh_after_config_change(
+                        state,
+                        "command replacement",
+                        &[ChangedEntity::command(&name)],
+                        options,
+                    )
+                    .await
+                    {
+                        Ok(refresh) => refresh,
+                        Err(resp) => return Ok(resp),
+                    };
+
+                    let mut response = config_action(
+                        state,
+                        &format!("Command {} replaced", name),
+                        vec![ChangedEntity::command(&name)],
+                        refresh,
+                    )
+                    .await;
+                    response.definition = Some(definition);
+                    Ok(json_response(StatusCode::OK, response))
+                }
+                Err(err) => {
+                    error!(
+                        "[desktop:config] Failed to replace command {}: {}",
+                        name, err
+                    );
+                    Ok(config_error_response(
+                        StatusCode::INTERNAL_SERVER_ERROR,
+                        err.to_string(),
+                    ))
+                }
+            }
+        }
+        Method::DELETE => match opencode_config::delete_command(&state.config_paths, &name).await {
             Ok(()) => {
-                if let Err(resp) =
-                    refresh_opencode_after_config_change(state, "command deletion").await
+                let refresh = match schedule_opencode_refresh_after_config_change(
+                    state,
+                    "command deletion",
+                    &[ChangedEntity::command(&name)],
+                    options,
+                )
+                .await
                 {
-                    return Ok(resp);
-                }
+                    Ok(refresh) => refresh,
+                    Err(resp) => return Ok(resp),
+                };
 
-                Ok(json_response(
-                    StatusCode::OK,
-                    ConfigActionResponse {
-                        success: true,
-                        requires_reload: true,
-                        message: format!(
-                            "Command {} deleted successfully. Reloading interface...",
-                            name
-                        ),
-                        reload_delay_ms: CLIENT_RELOAD_DELAY_MS,
-                    },
-                ))
+                Ok(config_action_response(
+                    state,
+                    &format!("Command {} deleted", name),
+                    vec![ChangedEntity::command(&name)],
+                    refresh,
+                )
+                .await)
             }
             Err(err) => {
                 error!("[desktop:config] Failed to delete command {}: {}", name, err);
@@ -880,132 +2679,584 @@ async fn handle_command_route(
                 Ok(config_error_response(status, err.to_string()))
             }
         },
-        _ => Ok(StatusCode::METHOD_NOT_ALLOWED.into_response()),
+        _ => Ok(config_method_response(
+            StatusCode::METHOD_NOT_ALLOWED,
+            ConfigRoute::Command,
+        )),
     }
 }
 
-async fn handle_config_routes(
-    state: ServerState,
-    path: &str,
-    method: Method,
-    req: Request<Body>,
-) -> Result<Response<Body>, StatusCode> {
-    if let Some(name) = path.strip_prefix("/api/config/agents/") {
-        let trimmed = name.trim();
-        if trimmed.is_empty() {
-            return Ok(config_error_response(
-                StatusCode::BAD_REQUEST,
-                "Agent name is required",
-            ));
-        }
-        return handle_agent_route(&state, method, req, trimmed.to_string()).await;
-    }
-
-    if let Some(name) = path.strip_prefix("/api/config/commands/") {
-        let trimmed = name.trim();
-        if trimmed.is_empty() {
-            return Ok(config_error_response(
-                StatusCode::BAD_REQUEST,
-                "Command name is required",
-            ));
-        }
-        return handle_command_route(&state, method, req, trimmed.to_string()).await;
+/// OPTIONS and methods a route doesn't take are answered from its method table
+fn config_preflight(route: ConfigRoute, method: &Method) -> Option<Response<Body>> {
+    if *method == Method::OPTIONS {
+        return Some(config_method_response(StatusCode::NO_CONTENT, route));
     }
-
-    if path == "/api/config/reload" && method == Method::POST {
-        if let Err(resp) =
-            refresh_opencode_after_config_change(&state, "manual configuration reload").await
-        {
-            return Ok(resp);
-        }
-
-        return Ok(json_response(
-            StatusCode::OK,
-            ConfigActionResponse {
-                success: true,
-                requires_reload: true,
-                message: "Configuration reloaded successfully. Refreshing interface..."
-                    .to_string(),
-                reload_delay_ms: CLIENT_RELOAD_DELAY_MS,
-            },
+    if !route.allows(method) {
+        return Some(config_method_response(
+            StatusCode::METHOD_NOT_ALLOWED,
+            route,
         ));
     }
-
-    Ok(StatusCode::NOT_FOUND.into_response())
+    None
 }
 
-async fn change_directory_handler(
-    State(state): State<ServerState>,
-    Json(payload): Json<DirectoryChangeRequest>,
-) -> Result<Json<DirectoryChangeResponse>, StatusCode> {
-    // Acquire lock to prevent concurrent directory changes
-    let _lock = state.directory_change_lock.lock().await;
-
-    let requested_path = payload.path.trim();
-    if requested_path.is_empty() {
-        warn!("[desktop:http] ERROR: Empty path provided");
-        return Err(StatusCode::BAD_REQUEST);
-    }
-
-    let resolved_path = PathBuf::from(requested_path);
+fn config_method_response(status: StatusCode, route: ConfigRoute) -> Response<Body> {
+    Response::builder()
+        .status(status)
+        .header(header::ALLOW, route.allow_header())
+        .header("access-control-allow-methods", route.allow_header())
+        .body(Body::empty())
+        .unwrap_or_else(|_| status.into_response())
+}
 
-    // Validate directory exists and is accessible
-    match fs::metadata(&resolved_path).await {
-        Ok(metadata) => {
-            if !metadata.is_dir() {
+/// `/api/config/auth[/<provider>]`: list OpenCode's stored logins (redacted)
+/// or delete one provider's credentials
+async fn handle_auth_route(
+    state: &ServerState,
+    method: Method,
+    provider: &str,
+    options: RefreshOptions,
+) -> Result<Response<Body>, StatusCode> {
+    let Some(auth_path) = opencode_auth::auth_file_path() else {
+        return Ok(config_error_response(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "Cannot determine the OpenCode data directory",
+        ));
+    };
+    let provider = if provider.is_empty() {
+        None
+    } else {
+        match opencode_auth::validate_provider_id(provider) {
+            Ok(provider) => Some(provider),
+            Err(err) => {
                 warn!(
-                    "[desktop:http] ERROR: Path is not a directory: {:?}",
-                    resolved_path
+                    "[desktop:config] Rejected provider id {:?}: {}",
+                    provider, err
                 );
-                return Err(StatusCode::BAD_REQUEST);
+                return Ok(config_error_response(
+                    StatusCode::BAD_REQUEST,
+                    format!("Invalid provider id: {}", err),
+                ));
             }
         }
-        Err(err) => {
-            warn!(
-                "[desktop:http] ERROR: Cannot access path: {:?} - {}",
-                resolved_path, err
-            );
-            return Err(StatusCode::NOT_FOUND);
-        }
+    };
+
+    match (method, provider) {
+        (Method::GET, None) => Ok(json_response(
+            StatusCode::OK,
+            opencode_auth::auth_status(&auth_path).await,
+        )),
+        (Method::GET, Some(provider)) => {
+            let status = opencode_auth::auth_status(&auth_path).await;
+            match status
+                .providers
+                .into_iter()
+                .find(|entry| entry.provider == provider)
+            {
+                Some(entry) => Ok(json_response(StatusCode::OK, entry)),
+                None => Ok(config_error_response(
+                    StatusCode::NOT_FOUND,
+                    format!("No credentials stored for {}", provider),
+                )),
+            }
+        }
+        (Method::DELETE, Some(provider)) => {
+            match opencode_auth::remove_provider(&auth_path, &provider).await {
+                Ok(true) => {}
+                Ok(false) => {
+                    return Ok(config_error_response(
+                        StatusCode::NOT_FOUND,
+                        format!("No credentials stored for {}", provider),
+                    ))
+                }
+                Err(err) => {
+                    error!(
+                        "[desktop:config] Failed to remove credentials for {}: {}",
+                        provider, err
+                    );
+                    return Ok(config_error_response(
+                        StatusCode::INTERNAL_SERVER_ERROR,
+                        err.to_string(),
+                    ));
+                }
+            }
+
+            let refresh =
+                match refresh_opencode_after_config_change(state, "credential removal", options)
+                    .await
+                {
+                    Ok(refresh) => refresh,
+                    Err(resp) => return Ok(resp),
+                };
+            Ok(config_action_response(
+                state,
+                &format!("Credentials for {} removed", provider),
+                Vec::new(),
+                refresh,
+            )
+            .await)
+        }
+        _ => Ok(config_method_response(
+            StatusCode::METHOD_NOT_ALLOWED,
+            ConfigRoute::Auth,
+        )),
+    }
+}
+
+async fn handle_config_routes(
+    state: ServerState,
+    path: &str,
+    query: Option<&str>,
+    method: Method,
+    req: Request<Body>,
+) -> Result<Response<Body>, StatusCode> {
+    let Some((route, name)) = proxy_routes::match_config_route(path) else {
+        return Ok(StatusCode::NOT_FOUND.into_response());
+    };
+
+    if let Some(response) = config_preflight(route, &method) {
+        return Ok(response);
+    }
+
+    // HEAD runs the GET handler and drops the body
+    let is_head = method == Method::HEAD;
+    let method = if is_head { Method::GET } else { method };
+    let options = RefreshOptions::from_query(query);
+
+    let response = match route {
+        ConfigRoute::Agent => {
+            let name = match opencode_config::validate_config_name(name) {
+                Ok(name) => name,
+                Err(err) => {
+                    warn!("[desktop:config] Rejected agent name {:?}: {}", name, err);
+                    return Ok(config_error_response(
+                        StatusCode::BAD_REQUEST,
+                        format!("Invalid agent name: {}", err),
+                    ));
+                }
+            };
+            handle_agent_route(&state, method, req, name, options).await?
+        }
+        ConfigRoute::Command => {
+            let name = match opencode_config::validate_config_name(name) {
+                Ok(name) => name,
+                Err(err) => {
+                    warn!("[desktop:config] Rejected command name {:?}: {}", name, err);
+                    return Ok(config_error_response(
+                        StatusCode::BAD_REQUEST,
+                        format!("Invalid command name: {}", err),
+                    ));
+                }
+            };
+            handle_command_route(&state, method, req, name, options).await?
+        }
+        ConfigRoute::Auth => handle_auth_route(&state, method, name, options).await?,
+        ConfigRoute::Reload => {
+            let options = RefreshOptions {
+                restart: true,
+                ..options
+            };
+            match refresh_opencode_after_config_change(
+                &state,
+                "manual configuration reload",
+                options,
+            )
+            .await
+            {
+                Ok(refresh) => {
+                    config_action_response(&state, "Configuration reloaded", Vec::new(), refresh)
+                        .await
+                }
+                Err(resp) => resp,
+            }
+        }
+    };
+
+    if is_head {
+        let (parts, _) = response.into_parts();
+        return Ok(Response::from_parts(parts, Body::empty()));
+    }
+    Ok(response)
+}
+
+/// Remember `path` for relinking and record its activation for trust. Only
+/// called once the switch succeeded, so a refused or failed one leaves no trace.
+async fn record_workspace_activation(
+    state: &ServerState,
+    path: &Path,
+) -> Option<workspace_trust::WorkspaceTrust> {
+    let runtime = state.app.try_state::<DesktopRuntime>()?;
+    workspace_relink::remember_workspace(runtime.settings(), runtime.project_state(), path).await;
+    workspace_trust::record_activation(runtime.settings(), path)
+        .await
+        .map_err(|err| warn!("[desktop:http] Failed to record workspace trust: {}", err))
+        .ok()
+}
+
+async fn change_directory_handler(
+    State(state): State<ServerState>,
+    Json(payload): Json<DirectoryChangeRequest>,
+) -> Result<Response<Body>, StatusCode> {
+    // Acquire lock to prevent concurrent directory changes
+    let _lock = state.directory_change_lock.lock().await;
+
+    let requested_path = payload.path.trim();
+    if requested_path.is_empty() {
+        warn!("[desktop:http] ERROR: Empty path provided");
+        return Ok(proxy_error_response(
+            StatusCode::BAD_REQUEST,
+            ProxyErrorCode::InvalidDirectory,
+            "No directory given",
+            state.opencode.current_port(),
+        ));
+    }
+
+    let resolved_path = PathBuf::from(requested_path);
+
+    // Validate directory exists and is accessible
+    match fs::metadata(&resolved_path).await {
+        Ok(metadata) => {
+            if !metadata.is_dir() {
+                warn!(
+                    "[desktop:http] ERROR: Path is not a directory: {:?}",
+                    resolved_path
+                );
+                return Ok(proxy_error_response(
+                    StatusCode::BAD_REQUEST,
+                    ProxyErrorCode::InvalidDirectory,
+                    format!("{} is not a directory", resolved_path.display()),
+                    state.opencode.current_port(),
+                ));
+            }
+        }
+        Err(err) => {
+            warn!(
+                "[desktop:http] ERROR: Cannot access path: {:?} - {}",
+                resolved_path, err
+            );
+            if err.kind() == std::io::ErrorKind::NotFound {
+                if let Some(runtime) = state.app.try_state::<DesktopRuntime>() {
+                    workspace_relink::suggest_relink(
+                        &state.app,
+                        runtime.settings(),
+                        &resolved_path,
+                    );
+                }
+            }
+            return Ok(proxy_error_response(
+                StatusCode::NOT_FOUND,
+                ProxyErrorCode::DirectoryNotFound,
+                format!("Cannot open {}: {}", resolved_path.display(), err),
+                state.opencode.current_port(),
+            ));
+        }
     }
 
+    let fs_info = workspace_fs::inspect(resolved_path.clone()).await;
+    if let Some(info) = &fs_info {
+        workspace_fs::warn_once(&state.app, info);
+    }
     let current_dir = state.opencode.get_working_directory();
     let is_running = state.opencode.current_port().is_some();
 
     // If already on this directory and OpenCode is running, no restart needed
     if current_dir == resolved_path && is_running {
+        let trust = record_workspace_activation(&state, &resolved_path).await;
         return Ok(Json(DirectoryChangeResponse {
             success: true,
             restarted: false,
             path: resolved_path.to_string_lossy().to_string(),
-        }));
+            fs_info,
+            trust,
+        })
+        .into_response());
+    }
+
+    // A server that scopes requests by the `directory` query (appended by the
+    // proxy) can serve the new project as is
+    let retargeted =
+        state.forward_directory && state.opencode.retarget_directory(&resolved_path).await;
+
+    // With the instance pool the current server is parked instead of stopped,
+    // so its sessions keep running
+    if !retargeted && !state.opencode_pool.enabled() {
+        let busy_sessions =
+            session_activity::busy_sessions(&state.session_phases, &state.session_pins).await;
+        if session_activity::restart_blocked(&busy_sessions, payload.force, payload.override_pinned)
+        {
+            warn!(
+                "[desktop:http] Refusing directory change: {} session(s) busy",
+                busy_sessions.len()
+            );
+            return Ok(json_response(
+                StatusCode::CONFLICT,
+                DirectoryChangeBlockedResponse {
+                    success: false,
+                    error: "Sessions are still running in the current directory".to_string(),
+                    busy_sessions,
+                },
+            ));
+        }
     }
 
     info!("[desktop:http] Changing directory to {:?}", resolved_path);
 
-    // Update working directory and restart OpenCode
-    state
-        .opencode
-        .set_working_directory(resolved_path.clone())
-        .await
-        .map_err(|e| {
-        error!(
-            "[desktop:http] ERROR: Failed to set working directory: {}",
-            e
-        );
-        StatusCode::INTERNAL_SERVER_ERROR
-    })?;
+    // Point OpenCode at the new directory, restarting it unless a pooled server takes over
+    let restarted = if retargeted {
+        false
+    } else {
+        match state
+            .opencode_pool
+            .switch_primary(resolved_path.clone())
+            .await
+        {
+            Ok(reused) => !reused,
+            Err(e) => {
+                error!("[desktop:http] ERROR: Failed to switch OpenCode: {}", e);
+                return Ok(proxy_error_response(
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    ProxyErrorCode::DirectorySwitchFailed,
+                    format!(
+                        "Failed to start OpenCode in {}: {}",
+                        resolved_path.display(),
+                        e
+                    ),
+                    state.opencode.current_port(),
+                ));
+            }
+        }
+    };
 
-    state.opencode.restart().await.map_err(|e| {
-        error!("[desktop:http] ERROR: Failed to restart OpenCode: {}", e);
-        StatusCode::INTERNAL_SERVER_ERROR
-    })?;
+    let _ = state
+        .app
+        .emit(DIRECTORY_CHANGED_EVENT, ProjectInfo::from_manager(&state.opencode));
+    // Project-level config may configure different providers
+    state.model_capabilities.invalidate();
+    state.agent_preferences.invalidate().await;
+    if let Some(terminals) = state.app.try_state::<TerminalState>() {
+        handle_workspace_change(&state.app, &terminals, &resolved_path);
+    }
+    if let Some(runtime) = state.app.try_state::<DesktopRuntime>() {
+        let settings = runtime.settings().load().await.unwrap_or_default();
+        if file_index::indexing_enabled(&settings) {
+            runtime.file_index().rebuild(resolved_path.clone());
+        } else {
+            runtime.file_index().clear();
+        }
+    }
+    let trust = record_workspace_activation(&state, &resolved_path).await;
 
     Ok(Json(DirectoryChangeResponse {
         success: true,
-        restarted: true,
+        restarted,
         path: resolved_path.to_string_lossy().to_string(),
-    }))
+        fs_info,
+        trust,
+    })
+    .into_response())
+}
+
+/// 413 naming the limit and, when the client declared it, the body's size
+fn body_too_large_response(
+    limit: u64,
+    size: Option<u64>,
+    opencode_port: Option<u16>,
+) -> Response<Body> {
+    let message = match size {
+        Some(size) => format!("Request body of {} bytes exceeds {} bytes", size, limit),
+        None => format!("Request body exceeds {} bytes", limit),
+    };
+    json_response(
+        StatusCode::PAYLOAD_TOO_LARGE,
+        ProxyErrorResponse {
+            error: ProxyErrorCode::BodyTooLarge,
+            message,
+            opencode_port,
+            elapsed_ms: None,
+            limit_bytes: Some(limit),
+            size_bytes: size,
+            status: None,
+        },
+    )
+}
+
+/// Forward a request body as it arrives instead of collecting it, failing the
+/// stream once more than `limit` bytes went through; `exceeded` is set then so
+/// the caller can tell the aborted send from an upstream error
+fn limited_request_body(
+    body: Body,
+    limit: u64,
+    exceeded: Arc<AtomicBool>,
+    metrics: Arc<ServerMetrics>,
+) -> ReqwestBody {
+    let mut forwarded = 0u64;
+    let stream = body.into_data_stream().map(move |chunk| {
+        let chunk = chunk.map_err(std::io::Error::other)?;
+        forwarded += chunk.len() as u64;
+        metrics.add_bytes_up(chunk.len());
+        if forwarded > limit {
+            exceeded.store(true, Ordering::SeqCst);
+            return Err(std::io::Error::other(
+                "request body exceeds the proxy limit",
+            ));
+        }
+        Ok(chunk)
+    });
+    ReqwestBody::wrap_stream(stream)
+}
+
+/// Poll for OpenCode's port over the retry window
+async fn wait_for_port(opencode: &OpenCodeManager, retry: &ProxyRetry) -> Option<u16> {
+    for attempt in 1..=retry.attempts() {
+        tokio::time::sleep(retry.delay()).await;
+        if let Some(port) = opencode.current_port() {
+            info!(
+                "[desktop:http] OpenCode came up on port {} after {} retries",
+                port, attempt
+            );
+            return Some(port);
+        }
+    }
+    None
+}
+
+/// Send a bodiless GET or HEAD, retrying refused connections over the retry
+/// window. Each retry goes to whatever port OpenCode is on by then, which is
+/// written back to `port`.
+async fn send_with_retry(
+    client: &Client,
+    builder: reqwest::RequestBuilder,
+    opencode: &OpenCodeManager,
+    retry: &ProxyRetry,
+    port: &mut u16,
+) -> reqwest::Result<reqwest::Response> {
+    let mut request = builder.build()?;
+    let mut attempt = 0;
+    loop {
+        let Some(next) = request.try_clone() else {
+            return client.execute(request).await;
+        };
+        match client.execute(next).await {
+            Err(err) if err.is_connect() && attempt < retry.attempts() => {
+                attempt += 1;
+                tokio::time::sleep(retry.delay()).await;
+                if let Some(current) = opencode.current_port().filter(|current| *current != *port) {
+                    let _ = request.url_mut().set_port(Some(current));
+                    if let Ok(host) = format!("127.0.0.1:{current}").parse() {
+                        request.headers_mut().insert(header::HOST, host);
+                    }
+                    *port = current;
+                }
+            }
+            result => return result,
+        }
+    }
+}
+
+/// Aborts an OpenCode prompt when dropped before its reply was relayed in
+/// full, e.g. because the webview cancelled the fetch. Closing the upstream
+/// connection alone does not stop the generation.
+struct PromptAbortGuard {
+    client: Client,
+    session_id: String,
+    /// The session's abort endpoint; None once disarmed
+    target: Option<String>,
+}
+
+impl PromptAbortGuard {
+    fn arm(client: &Client, method: &Method, path: &str, target: &str) -> Option<Self> {
+        let session_id = proxy_routes::match_prompt(method, path)?;
+        Some(Self {
+            client: client.clone(),
+            session_id: session_id.to_string(),
+            target: Some(proxy_routes::prompt_abort_target(target)?),
+        })
+    }
+
+    fn disarm(&mut self) {
+        self.target = None;
+    }
+}
+
+impl Drop for PromptAbortGuard {
+    fn drop(&mut self) {
+        let Some(target) = self.target.take() else {
+            return;
+        };
+        let client = self.client.clone();
+        let session_id = std::mem::take(&mut self.session_id);
+        tauri::async_runtime::spawn(async move {
+            info!(
+                "[desktop:http] Client left before the reply to {} was done; aborting it",
+                session_id
+            );
+            let result = client
+                .post(&target)
+                .send()
+                .await
+                .and_then(|response| response.error_for_status());
+            if let Err(err) = result {
+                warn!("[desktop:http] Failed to abort {}: {}", session_id, err);
+            }
+        });
+    }
+}
+
+/// Tell clients when a request that hit a restart is worth repeating
+fn with_retry_after(mut response: Response<Body>, retry: &ProxyRetry) -> Response<Body> {
+    response.headers_mut().insert(
+        header::RETRY_AFTER,
+        header::HeaderValue::from(retry.retry_after_secs()),
+    );
+    response
+}
+
+/// What the proxy needs from ServerState. Split out so it can run against a
+/// mock OpenCode without a Tauri app.
+#[derive(Clone)]
+struct OpenCodeProxy {
+    /// None in tests; the sessions-changed event is then not emitted
+    app: Option<AppHandle>,
+    upstream: Arc<UpstreamPool>,
+    opencode_pool: Arc<OpenCodeInstancePool>,
+    session_search: Arc<SessionSearch>,
+    event_hub: Arc<EventHub>,
+    prefix_watch: Arc<PrefixWatch>,
+    metrics: Arc<ServerMetrics>,
+    forward_directory: bool,
+    max_request_body: Arc<AtomicU64>,
+    proxy_timeouts: ProxyTimeouts,
+    proxy_retry: ProxyRetry,
+}
+
+impl OpenCodeProxy {
+    fn max_request_body(&self) -> u64 {
+        self.max_request_body.load(Ordering::Relaxed)
+    }
+
+    fn emit(&self, event: &str, payload: Value) {
+        if let Some(app) = &self.app {
+            let _ = app.emit(event, payload);
+        }
+    }
+}
+
+impl FromRef<ServerState> for OpenCodeProxy {
+    fn from_ref(state: &ServerState) -> Self {
+        Self {
+            app: Some(state.app.clone()),
+            upstream: state.upstream.clone(),
+            opencode_pool: state.opencode_pool.clone(),
+            session_search: state.session_search.clone(),
+            event_hub: state.event_hub.clone(),
+            prefix_watch: state.prefix_watch.clone(),
+            metrics: state.metrics.clone(),
+            forward_directory: state.forward_directory,
+            max_request_body: state.max_request_body.clone(),
+            proxy_timeouts: state.proxy_timeouts.clone(),
+            proxy_retry: state.proxy_retry.clone(),
+        }
+    }
 }
 
 async fn proxy_to_opencode(
@@ -1014,80 +3265,335 @@ async fn proxy_to_opencode(
     req: Request<Body>,
 ) -> Result<Response<Body>, StatusCode> {
     let origin_path = original.0.path().to_string();
-    let method = req.method().clone();
+    if proxy_routes::match_config_route(&origin_path).is_some() {
+        let method = req.method().clone();
+        return handle_config_routes(state, &origin_path, original.0.query(), method, req).await;
+    }
+    forward_to_opencode(OpenCodeProxy::from_ref(&state), original, req).await
+}
 
-    let is_desktop_config_route = origin_path.starts_with("/api/config/agents/")
-        || origin_path.starts_with("/api/config/commands/")
-        || origin_path == "/api/config/reload";
+/// Relay a request to the OpenCode serving it, streaming both bodies
+async fn forward_to_opencode(
+    proxy: OpenCodeProxy,
+    original: OriginalUri,
+    req: Request<Body>,
+) -> Result<Response<Body>, StatusCode> {
+    let origin_path = original.0.path().to_string();
+    let method = req.method().clone();
 
-    if is_desktop_config_route {
-        return handle_config_routes(state, &origin_path, method, req).await;
-    }
+    // With the instance pool, requests naming another project go to its own server
+    let requested = proxy_routes::requested_directory(original.0.query(), req.headers());
+    let opencode = match proxy.opencode_pool.backend_for(requested.as_deref()).await {
+        Ok(opencode) => opencode,
+        Err(err) => {
+            error!(
+                "[desktop:http] PROXY FAILED: pooled OpenCode did not start: {}",
+                err
+            );
+            return Ok(proxy_error_response(
+                StatusCode::BAD_GATEWAY,
+                ProxyErrorCode::OpencodeNotRunning,
+                format!("OpenCode for this project did not start: {}", err),
+                None,
+            ));
+        }
+    };
 
-    let port = state.opencode.current_port().ok_or_else(|| {
+    // GET and HEAD ride out a restart instead of failing straight away
+    let retryable = proxy.proxy_retry.applies_to(&method);
+    let port = match opencode.current_port() {
+        None if retryable => wait_for_port(&opencode, &proxy.proxy_retry).await,
+        port => port,
+    };
+    let Some(mut port) = port else {
         error!("[desktop:http] PROXY FAILED: OpenCode not running (no port)");
-        StatusCode::SERVICE_UNAVAILABLE
-    })?;
+        return Ok(with_retry_after(
+            opencode_unavailable_response(&opencode),
+            &proxy.proxy_retry,
+        ));
+    };
 
-    let query = original.0.query();
-    let rewritten_path = state.opencode.rewrite_path(&origin_path);
-    let mut target = format!("http://127.0.0.1:{port}{rewritten_path}");
-    if let Some(q) = query {
+    // The EventSource token authorizes the request here and is not OpenCode's business
+    let query = original
+        .0
+        .query()
+        .map(|query| proxy_routes::without_query_param(query, loopback_auth::TOKEN_QUERY))
+        .filter(|query| !query.is_empty());
+    // Routes are matched on OpenCode's own path; the target carries the backend prefix
+    let rewritten_path = opencode_manager::opencode_path(&origin_path);
+    let mut target = format!(
+        "http://127.0.0.1:{port}{}",
+        opencode.rewrite_path(&origin_path)
+    );
+    if let Some(q) = &query {
         target.push('?');
         target.push_str(q);
     }
+    // Scope to the active workspace explicitly instead of relying on OpenCode's cwd
+    if proxy.forward_directory && proxy_routes::is_directory_scoped(&rewritten_path) {
+        if let Some(directory) = opencode.get_working_directory().to_str() {
+            target = proxy_routes::with_directory_query(&target, directory);
+        }
+    }
+
+    let session_mutation = proxy_routes::match_session_mutation(&method, &rewritten_path);
+
+    // HEAD carries no payload and expects none back
+    let is_head = method == Method::HEAD;
 
     let (parts, body) = req.into_parts();
+    // Requests without a body are sent without one rather than as an empty chunked stream
+    let has_body = !is_head && body.size_hint().exact() != Some(0);
+    // Chunked framing overrides any Content-Length the client also sent
+    let chunked = parts.headers.contains_key(header::TRANSFER_ENCODING);
+    let declared_length = parts
+        .headers
+        .get(header::CONTENT_LENGTH)
+        .filter(|_| !chunked)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.parse::<u64>().ok());
+    let max_request_body = proxy.max_request_body();
+    if let Some(len) = declared_length.filter(|len| has_body && *len > max_request_body) {
+        warn!(
+            "[desktop:http] Rejected {} byte request body to {} (limit {})",
+            len, origin_path, max_request_body
+        );
+        return Ok(body_too_large_response(
+            max_request_body,
+            Some(len),
+            Some(port),
+        ));
+    }
     let method = parts.method.clone();
-    let mut builder = state.client.request(method, &target);
+    // Inspected responses are decoded so they can be parsed; everything else passes through
+    let client = if session_mutation.is_some() {
+        proxy.upstream.decoding()
+    } else {
+        proxy.upstream.passthrough()
+    };
+    // An abandoned prompt would otherwise keep generating, and billing, upstream
+    let mut abort_guard = PromptAbortGuard::arm(&client, &method, &rewritten_path, &target);
+    let mut builder = client.request(method, &target);
 
     let mut headers = parts.headers;
     headers.insert(header::HOST, format!("127.0.0.1:{port}").parse().unwrap());
-    if headers
+    let wants_event_stream = headers
         .get(header::ACCEPT)
         .and_then(|v| v.to_str().ok())
         .map(|val| val.contains("text/event-stream"))
-        .unwrap_or(false)
-    {
+        .unwrap_or(false);
+    if wants_event_stream {
         headers.insert(header::CONNECTION, "keep-alive".parse().unwrap());
     }
 
     for (key, value) in headers.iter() {
-        if key == &header::CONTENT_LENGTH {
+        // A streamed body keeps the client's length so OpenCode is not sent chunked
+        // uploads; Content-Type passes through untouched, multipart boundary included
+        if *key == header::CONTENT_LENGTH && (!has_body || chunked) {
+            continue;
+        }
+        // Framing is per hop: hyper chunks the body itself when it has no length
+        if *key == header::TRANSFER_ENCODING {
+            continue;
+        }
+        // Let the decoding client negotiate encodings it can undo. Event streams
+        // are asked for uncompressed, so keep-alive frames can be added to them.
+        if (session_mutation.is_some() || wants_event_stream) && *key == header::ACCEPT_ENCODING {
             continue;
         }
         builder = builder.header(key, value);
     }
 
-    let body_bytes = to_bytes(body, PROXY_BODY_LIMIT)
-        .await
-        .map_err(|_| StatusCode::BAD_GATEWAY)?;
+    if let Some(timeout) = proxy.proxy_timeouts.timeout_for(&rewritten_path, &headers) {
+        builder = builder.timeout(timeout);
+    }
 
-    let response = if body_bytes.is_empty() {
-        builder.send().await.map_err(|_| StatusCode::BAD_GATEWAY)?
+    let body_exceeded = Arc::new(AtomicBool::new(false));
+    let started = Instant::now();
+    let sent = if has_body {
+        let body = limited_request_body(
+            body,
+            max_request_body,
+            body_exceeded.clone(),
+            proxy.metrics.clone(),
+        );
+        builder.body(body).send().await
+    } else if retryable {
+        send_with_retry(&client, builder, &opencode, &proxy.proxy_retry, &mut port).await
     } else {
-        builder
-            .body(ReqwestBody::from(body_bytes))
-            .send()
-            .await
-            .map_err(|_| StatusCode::BAD_GATEWAY)?
+        builder.send().await
+    };
+    if let (Some(guard), Err(err)) = (abort_guard.as_mut(), &sent) {
+        // Nothing reached OpenCode, so there is nothing to abort
+        if err.is_connect() {
+            guard.disarm();
+        }
+    }
+    let response = match sent {
+        Ok(response) => response,
+        Err(_) if body_exceeded.load(Ordering::SeqCst) => {
+            warn!(
+                "[desktop:http] Request body to {} exceeded {} bytes; aborted",
+                origin_path, max_request_body
+            );
+            return Ok(body_too_large_response(max_request_body, None, Some(port)));
+        }
+        // The server went away between the port lookup and the request, e.g. a restart began
+        Err(err) if err.is_connect() && !opencode.is_ready() => {
+            return Ok(with_retry_after(
+                opencode_unavailable_response(&opencode),
+                &proxy.proxy_retry,
+            ));
+        }
+        Err(err) if err.is_timeout() => {
+            let elapsed = started.elapsed();
+            warn!(
+                "[desktop:http] PROXY TIMEOUT: {} after {:?}",
+                origin_path, elapsed
+            );
+            return Ok(json_response(
+                StatusCode::GATEWAY_TIMEOUT,
+                ProxyErrorResponse {
+                    error: ProxyErrorCode::Timeout,
+                    message: format!(
+                        "OpenCode did not respond within {:.1}s",
+                        elapsed.as_secs_f64()
+                    ),
+                    opencode_port: Some(port),
+                    elapsed_ms: Some(elapsed.as_millis() as u64),
+                    limit_bytes: None,
+                    size_bytes: None,
+                    status: None,
+                },
+            ));
+        }
+        Err(err) => {
+            warn!("[desktop:http] PROXY FAILED: {}: {}", origin_path, err);
+            let (error, message) = if err.is_connect() {
+                (
+                    ProxyErrorCode::ConnectRefused,
+                    "OpenCode refused the connection",
+                )
+            } else {
+                (
+                    ProxyErrorCode::UpstreamError,
+                    "The request to OpenCode failed",
+                )
+            };
+            let response =
+                proxy_error_response(StatusCode::BAD_GATEWAY, error, message, Some(port));
+            return Ok(if err.is_connect() {
+                with_retry_after(response, &proxy.proxy_retry)
+            } else {
+                response
+            });
+        }
     };
 
     let status = response.status();
+    // A burst of 404s on routes that used to work points at a changed API prefix
+    if proxy
+        .prefix_watch
+        .observe(&rewritten_path, &opencode.api_prefix(), status)
+    {
+        let opencode = opencode.clone();
+        tauri::async_runtime::spawn(async move {
+            if let Err(err) = opencode.redetect_api_prefix().await {
+                warn!("[desktop:http] API prefix re-detection failed: {}", err);
+            }
+        });
+    }
     let mut resp_builder = Response::builder().status(status);
     for (key, value) in response.headers() {
         if key.as_str().eq_ignore_ascii_case("connection") {
             continue;
         }
+        // Inspected responses are decoded and re-framed below, so the original
+        // encoding and length no longer describe the body
+        if session_mutation.is_some()
+            && (*key == header::CONTENT_LENGTH || *key == header::CONTENT_ENCODING)
+        {
+            continue;
+        }
         resp_builder = resp_builder.header(key, value);
     }
 
-    let stream = response.bytes_stream().map(|chunk| {
+    if is_head {
+        return resp_builder
+            .body(Body::empty())
+            .map_err(|_| StatusCode::BAD_GATEWAY);
+    }
+
+    // Session list mutations are small, so buffer them to read the session id and notify the UI
+    if let Some(mutation) = session_mutation {
+        let body_bytes = match response.bytes().await {
+            Ok(body_bytes) => body_bytes,
+            Err(_) => {
+                return Ok(proxy_error_response(
+                    StatusCode::BAD_GATEWAY,
+                    ProxyErrorCode::UpstreamError,
+                    "OpenCode's response was cut off",
+                    Some(port),
+                ))
+            }
+        };
+        proxy.metrics.add_bytes_down(body_bytes.len());
+        if status.is_success() {
+            if let (proxy_routes::SessionMutationKind::Deleted, Some(session_id)) =
+                (mutation.kind, &mutation.session_id)
+            {
+                proxy.session_search.remove_session(session_id);
+            }
+            if let Some(payload) = proxy_routes::sessions_changed_payload(&mutation, &body_bytes) {
+                proxy.emit(proxy_routes::SESSIONS_CHANGED_EVENT, payload);
+            }
+        }
+        return resp_builder
+            .body(Body::from(body_bytes))
+            .map_err(|_| StatusCode::BAD_GATEWAY);
+    }
+
+    let is_event_stream = response
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.starts_with("text/event-stream"));
+    // Plain frames cannot be spliced into a compressed body
+    let is_encoded = response
+        .headers()
+        .get(header::CONTENT_ENCODING)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| !value.trim().eq_ignore_ascii_case("identity"));
+    // Count event streams that bypass the hub so duplicate connections are visible
+    let event_stream_guard = is_event_stream.then(|| proxy.event_hub.track_proxied_stream());
+    let metrics = proxy.metrics.clone();
+    let stream = response.bytes_stream().map(move |chunk| {
+        let _held = &event_stream_guard;
+        if let Ok(chunk) = &chunk {
+            metrics.add_bytes_down(chunk.len());
+        }
         chunk
-            .map_err(|err| std::io::Error::new(std::io::ErrorKind::Other, err))
-            .map(axum::body::Bytes::from)
+            .map_err(std::io::Error::other)
     });
-    let body = Body::from_stream(stream);
+    // Reached only once the whole reply went out
+    let stream = stream.chain(
+        futures_util::stream::once(async move {
+            if let Some(guard) = abort_guard.as_mut() {
+                guard.disarm();
+            }
+        })
+        .filter_map(|()| async { None }),
+    );
+    // OpenCode's /event can sit quiet for minutes, long enough for some
+    // proxies and VPNs to drop the connection
+    let body = if is_event_stream && !is_encoded {
+        Body::from_stream(sse_keepalive::with_keep_alive(
+            stream,
+            sse_keepalive::KEEP_ALIVE_INTERVAL,
+        ))
+    } else {
+        Body::from_stream(stream)
+    };
     resp_builder.body(body).map_err(|_| StatusCode::BAD_GATEWAY)
 }
 
@@ -1099,11 +3605,7 @@ pub(crate) struct SettingsStore {
 
 impl SettingsStore {
     pub(crate) fn new() -> Result<Self> {
-        // Use ~/.config/openchamber for consistency with Electron/web versions
-        let home = dirs::home_dir().ok_or_else(|| anyhow!("No home directory"))?;
-        let mut dir = home;
-        dir.push(".config");
-        dir.push("openchamber");
+        let mut dir = paths::config_dir().ok_or_else(|| anyhow!("No config directory"))?;
         std::fs::create_dir_all(&dir).ok();
         dir.push("settings.json");
         Ok(Self {
@@ -1112,6 +3614,15 @@ impl SettingsStore {
         })
     }
 
+    /// A store backed by `path`, for tests that must not touch real settings
+    #[cfg(test)]
+    pub(crate) fn at(path: PathBuf) -> Self {
+        Self {
+            path,
+            guard: Arc::new(Mutex::new(())),
+        }
+    }
+
     pub(crate) async fn load(&self) -> Result<Value> {
         let _lock = self.guard.lock().await;
         match fs::read(&self.path).await {
@@ -1148,3 +3659,811 @@ impl SettingsStore {
         Ok(candidate)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use std::net::SocketAddr;
+    use std::sync::atomic::AtomicUsize;
+    use sysinfo::{ProcessRefreshKind, ProcessesToUpdate, System};
+
+    static CHUNK: [u8; 64 * 1024] = [0; 64 * 1024];
+
+    /// A body of `chunks` static chunks, produced lazily so the test itself
+    /// holds none of it
+    fn generated_body(chunks: usize) -> Body {
+        let stream = futures_util::stream::iter(
+            (0..chunks).map(|_| Ok::<_, std::io::Error>(axum::body::Bytes::from_static(&CHUNK))),
+        );
+        Body::from_stream(stream)
+    }
+
+    /// Accepts uploads and answers with how many bytes arrived, reading them
+    /// as they come
+    async fn counting_upstream() -> SocketAddr {
+        let app = Router::new().route(
+            "/",
+            post(|body: Body| async move {
+                let mut stream = body.into_data_stream();
+                let mut received = 0u64;
+                while let Some(Ok(chunk)) = stream.next().await {
+                    received += chunk.len() as u64;
+                }
+                received.to_string()
+            }),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            axum::serve(listener, app).await.ok();
+        });
+        addr
+    }
+
+    fn resident_bytes(system: &mut System, pid: sysinfo::Pid) -> u64 {
+        system.refresh_processes_specifics(
+            ProcessesToUpdate::Some(&[pid]),
+            true,
+            ProcessRefreshKind::new().with_memory(),
+        );
+        system
+            .process(pid)
+            .map(|process| process.memory())
+            .unwrap_or(0)
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn limited_request_body_streams_large_uploads_in_flat_memory() {
+        const CHUNKS: usize = 6 * 1024; // 384MB
+        let total = (CHUNKS * CHUNK.len()) as u64;
+        let addr = counting_upstream().await;
+
+        let pid = sysinfo::get_current_pid().unwrap();
+        let mut system = System::new();
+        let baseline = resident_bytes(&mut system, pid);
+        let done = Arc::new(AtomicBool::new(false));
+        let sampler = {
+            let done = done.clone();
+            tokio::spawn(async move {
+                let mut peak = 0;
+                while !done.load(Ordering::SeqCst) {
+                    peak = peak.max(resident_bytes(&mut system, pid));
+                    tokio::time::sleep(Duration::from_millis(20)).await;
+                }
+                peak
+            })
+        };
+
+        let exceeded = Arc::new(AtomicBool::new(false));
+        let body = limited_request_body(
+            generated_body(CHUNKS),
+            u64::MAX,
+            exceeded.clone(),
+            Arc::new(ServerMetrics::default()),
+        );
+        let received = reqwest::Client::new()
+            .post(format!("http://{addr}/"))
+            .body(body)
+            .send()
+            .await
+            .unwrap()
+            .text()
+            .await
+            .unwrap();
+        done.store(true, Ordering::SeqCst);
+        let peak = sampler.await.unwrap();
+
+        assert_eq!(received, total.to_string());
+        assert!(!exceeded.load(Ordering::SeqCst));
+        let growth = peak.saturating_sub(baseline);
+        assert!(
+            growth < 64 * 1024 * 1024,
+            "resident memory grew by {growth} bytes while streaming {total}"
+        );
+    }
+
+    #[tokio::test]
+    async fn limited_request_body_fails_the_send_past_the_limit() {
+        let addr = counting_upstream().await;
+        let exceeded = Arc::new(AtomicBool::new(false));
+        let body = limited_request_body(
+            generated_body(64),
+            CHUNK.len() as u64 * 16,
+            exceeded.clone(),
+            Arc::new(ServerMetrics::default()),
+        );
+        let result = reqwest::Client::new()
+            .post(format!("http://{addr}/"))
+            .body(body)
+            .send()
+            .await;
+
+        assert!(exceeded.load(Ordering::SeqCst));
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn refresh_options_read_the_force_flags() {
+        let defaults = RefreshOptions::from_query(None);
+        assert!(defaults.restart && !defaults.force && !defaults.override_pinned);
+
+        let forced = RefreshOptions::from_query(Some("force=true&overridePinned=1"));
+        assert!(forced.restart && forced.force && forced.override_pinned);
+
+        let deferred = RefreshOptions::from_query(Some("restart=false&force=yes"));
+        assert!(!deferred.restart && !deferred.force);
+    }
+
+    #[tokio::test]
+    async fn oversized_config_fields_are_refused() {
+        let field = "x".repeat(CONFIG_FIELD_LIMIT + 1);
+        let req = Request::builder()
+            .body(Body::from(json!({ "prompt": field }).to_string()))
+            .unwrap();
+        let err = parse_request_payload(req, CONFIG_PAYLOAD_LIMIT)
+            .await
+            .unwrap_err();
+        assert_eq!(err.status(), StatusCode::PAYLOAD_TOO_LARGE);
+
+        let req = Request::builder().body(Body::from("{not json")).unwrap();
+        let err = parse_request_payload(req, CONFIG_PAYLOAD_LIMIT)
+            .await
+            .unwrap_err();
+        assert_eq!(err.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn config_payloads_stay_capped_under_a_large_body_limit() {
+        let field = "x".repeat(2 * CONFIG_PAYLOAD_LIMIT as usize);
+        let req = Request::builder()
+            .body(Body::from(json!({ "prompt": field }).to_string()))
+            .unwrap();
+        let err = parse_request_payload(req, 1024 * 1024 * 1024)
+            .await
+            .unwrap_err();
+        assert_eq!(err.status(), StatusCode::PAYLOAD_TOO_LARGE);
+
+        let req = Request::builder()
+            .body(Body::from(json!({ "mode": "primary" }).to_string()))
+            .unwrap();
+        let payload = parse_request_payload(req, 1024 * 1024 * 1024)
+            .await
+            .unwrap();
+        assert_eq!(payload.get("mode"), Some(&json!("primary")));
+    }
+
+    /// A proxy in front of `upstream_port`, which stands in for a running OpenCode
+    fn proxy_to(upstream_port: u16, max_request_body: u64) -> OpenCodeProxy {
+        let opencode = Arc::new(OpenCodeManager::ready_on(
+            upstream_port,
+            std::env::temp_dir(),
+        ));
+        let upstream = Arc::new(UpstreamPool::new(opencode.clone()).unwrap());
+        let client = OpenCodeClient::new(upstream.clone(), opencode.clone());
+        let settings =
+            std::env::temp_dir().join(format!("openchamber-proxy-{}.json", uuid::Uuid::new_v4()));
+        OpenCodeProxy {
+            app: None,
+            upstream,
+            opencode_pool: Arc::new(OpenCodeInstancePool::new(
+                opencode,
+                Arc::new(SettingsStore::at(settings)),
+                1,
+            )),
+            session_search: Arc::new(SessionSearch::new(client.clone())),
+            event_hub: Arc::new(EventHub::new(client)),
+            prefix_watch: Arc::new(PrefixWatch::default()),
+            metrics: Arc::new(ServerMetrics::default()),
+            forward_directory: false,
+            max_request_body: Arc::new(AtomicU64::new(max_request_body)),
+            proxy_timeouts: ProxyTimeouts::from_settings(&Value::Null),
+            proxy_retry: ProxyRetry::from_settings(&Value::Null),
+        }
+    }
+
+    async fn forward(proxy: OpenCodeProxy, req: Request<Body>) -> Response<Body> {
+        let original = OriginalUri(req.uri().clone());
+        forward_to_opencode(proxy, original, req).await.unwrap()
+    }
+
+    const BOUNDARY: &str = "openchamber-test-boundary";
+    /// 0..=255 repeated, so any shifted, dropped or duplicated byte shows up
+    static PATTERN: [u8; 64 * 1024] = {
+        let mut pattern = [0; 64 * 1024];
+        let mut i = 0;
+        while i < pattern.len() {
+            pattern[i] = i as u8;
+            i += 1;
+        }
+        pattern
+    };
+
+    fn multipart_parts() -> (String, String) {
+        let head = format!(
+            "--{BOUNDARY}\r\nContent-Disposition: form-data; name=\"file\"; filename=\"big.bin\"\r\n\
+             Content-Type: application/octet-stream\r\n\r\n"
+        );
+        (head, format!("\r\n--{BOUNDARY}--\r\n"))
+    }
+
+    /// Checks an upload against the generated multipart body as it streams in,
+    /// answering with its verdict, the byte count and the framing headers it saw
+    async fn verifying_upstream(payload_len: u64) -> SocketAddr {
+        let app = Router::new().route(
+            "/file/upload",
+            post(move |headers: HeaderMap, body: Body| async move {
+                let (head, tail) = multipart_parts();
+                let expected_at = |position: u64| -> u8 {
+                    let head_len = head.len() as u64;
+                    if position < head_len {
+                        head.as_bytes()[position as usize]
+                    } else if position < head_len + payload_len {
+                        (position - head_len) as u8
+                    } else {
+                        tail.as_bytes()[(position - head_len - payload_len) as usize]
+                    }
+                };
+                let total = head.len() as u64 + payload_len + tail.len() as u64;
+                let mut stream = body.into_data_stream();
+                let mut received = 0u64;
+                let mut mismatch = None;
+                while let Some(Ok(chunk)) = stream.next().await {
+                    for byte in chunk.iter() {
+                        if mismatch.is_none()
+                            && (received >= total || *byte != expected_at(received))
+                        {
+                            mismatch = Some(received);
+                        }
+                        received += 1;
+                    }
+                }
+                let header = |name| {
+                    headers
+                        .get(name)
+                        .and_then(|value: &header::HeaderValue| value.to_str().ok())
+                        .map(str::to_string)
+                };
+                Json(json!({
+                    "received": received,
+                    "mismatch": mismatch,
+                    "contentLength": header(header::CONTENT_LENGTH),
+                    "contentType": header(header::CONTENT_TYPE),
+                    "chunked": headers.contains_key(header::TRANSFER_ENCODING),
+                }))
+            }),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            axum::serve(listener, app).await.ok();
+        });
+        addr
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn large_multipart_uploads_stream_through_the_proxy_unchanged() {
+        const CHUNKS: usize = 1600; // 100MB
+        let payload_len = (CHUNKS * PATTERN.len()) as u64;
+        let (head, tail) = multipart_parts();
+        let total = head.len() as u64 + payload_len + tail.len() as u64;
+        let addr = verifying_upstream(payload_len).await;
+        let proxy = proxy_to(addr.port(), 2 * total);
+
+        let pid = sysinfo::get_current_pid().unwrap();
+        let mut system = System::new();
+        let baseline = resident_bytes(&mut system, pid);
+        let done = Arc::new(AtomicBool::new(false));
+        let sampler = {
+            let done = done.clone();
+            tokio::spawn(async move {
+                let mut peak = 0;
+                while !done.load(Ordering::SeqCst) {
+                    peak = peak.max(resident_bytes(&mut system, pid));
+                    tokio::time::sleep(Duration::from_millis(20)).await;
+                }
+                peak
+            })
+        };
+
+        let chunks = std::iter::once(axum::body::Bytes::from(head))
+            .chain((0..CHUNKS).map(|_| axum::body::Bytes::from_static(&PATTERN)))
+            .chain(std::iter::once(axum::body::Bytes::from(tail)))
+            .map(Ok::<_, std::io::Error>);
+        let content_type = format!("multipart/form-data; boundary={BOUNDARY}");
+        let req = Request::builder()
+            .method(Method::POST)
+            .uri("/api/file/upload")
+            .header(header::CONTENT_TYPE, &content_type)
+            .header(header::CONTENT_LENGTH, total)
+            .body(Body::from_stream(futures_util::stream::iter(chunks)))
+            .unwrap();
+        let response = forward(proxy, req).await;
+        let status = response.status();
+        let bytes = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        done.store(true, Ordering::SeqCst);
+        let peak = sampler.await.unwrap();
+
+        assert_eq!(status, StatusCode::OK);
+        let verdict: Value = serde_json::from_slice(&bytes).unwrap();
+        assert_eq!(
+            verdict,
+            json!({
+                "received": total,
+                "mismatch": null,
+                "contentLength": total.to_string(),
+                "contentType": content_type,
+                "chunked": false,
+            })
+        );
+        let growth = peak.saturating_sub(baseline);
+        assert!(
+            growth < 64 * 1024 * 1024,
+            "resident memory grew by {growth} bytes while proxying {total}"
+        );
+    }
+
+    const GZIP_JSON: &str = r#"{"id":"ses_1","title":"Compressed"}"#;
+
+    fn gzip(bytes: &[u8]) -> Vec<u8> {
+        use std::io::Write;
+        let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        encoder.write_all(bytes).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    fn gunzip(bytes: &[u8]) -> Vec<u8> {
+        use std::io::Read;
+        let mut decoded = Vec::new();
+        flate2::read::GzDecoder::new(bytes)
+            .read_to_end(&mut decoded)
+            .unwrap();
+        decoded
+    }
+
+    /// Answers every request with gzip-encoded JSON, whatever the client accepts
+    async fn gzip_upstream() -> SocketAddr {
+        let compressed = gzip(GZIP_JSON.as_bytes());
+        let app = Router::new().fallback(move || async move {
+            Response::builder()
+                .header(header::CONTENT_TYPE, "application/json")
+                .header(header::CONTENT_ENCODING, "gzip")
+                .header(header::CONTENT_LENGTH, compressed.len())
+                .body(Body::from(compressed))
+                .unwrap()
+        });
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            axum::serve(listener, app).await.ok();
+        });
+        addr
+    }
+
+    #[tokio::test]
+    async fn gzip_responses_pass_through_with_their_encoding() {
+        let addr = gzip_upstream().await;
+        let req = Request::builder()
+            .uri("/api/config")
+            .header(header::ACCEPT_ENCODING, "gzip")
+            .body(Body::empty())
+            .unwrap();
+        let response = forward(proxy_to(addr.port(), u64::MAX), req).await;
+
+        let headers = response.headers().clone();
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        assert_eq!(headers[header::CONTENT_ENCODING], "gzip");
+        assert_eq!(headers[header::CONTENT_LENGTH], body.len().to_string());
+        let decoded: Value = serde_json::from_slice(&gunzip(&body)).unwrap();
+        assert_eq!(decoded, serde_json::from_str::<Value>(GZIP_JSON).unwrap());
+    }
+
+    #[tokio::test]
+    async fn inspected_gzip_responses_are_decoded_with_consistent_headers() {
+        let addr = gzip_upstream().await;
+        // Session creation is read by the proxy, so it goes through the decoding client
+        let req = Request::builder()
+            .method(Method::POST)
+            .uri("/api/session")
+            .header(header::ACCEPT_ENCODING, "gzip")
+            .body(Body::empty())
+            .unwrap();
+        let response = forward(proxy_to(addr.port(), u64::MAX), req).await;
+
+        let headers = response.headers().clone();
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        assert!(headers.get(header::CONTENT_ENCODING).is_none());
+        assert!(headers.get(header::CONTENT_LENGTH).is_none());
+        let decoded: Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(decoded["title"], "Compressed");
+    }
+
+    /// A scratch project directory under the system temp dir, removed on drop
+    struct TempProject(PathBuf);
+
+    impl TempProject {
+        fn new(git: bool) -> Self {
+            let dir = std::env::temp_dir()
+                .join(format!("openchamber-info-{}", uuid::Uuid::new_v4()))
+                .join("my-project");
+            std::fs::create_dir_all(&dir).unwrap();
+            if git {
+                std::fs::create_dir(dir.join(".git")).unwrap();
+            }
+            Self(dir)
+        }
+    }
+
+    impl Drop for TempProject {
+        fn drop(&mut self) {
+            std::fs::remove_dir_all(self.0.parent().unwrap()).ok();
+        }
+    }
+
+    #[test]
+    fn server_info_describes_the_running_opencode_and_project() {
+        let project = TempProject::new(true);
+        let opencode = OpenCodeManager::ready_on(4096, project.0.clone());
+
+        let info =
+            serde_json::to_value(ServerInfoPayload::new(57123, &opencode, true, "secret")).unwrap();
+
+        assert_eq!(info["server_port"], 57123);
+        assert_eq!(info["opencode_port"], 4096);
+        assert_eq!(info["has_last_directory"], true);
+        assert_eq!(info["api_token"], "secret");
+        assert_eq!(
+            info["working_directory"],
+            project.0.to_string_lossy().as_ref()
+        );
+        assert_eq!(info["is_git_repository"], true);
+        assert_eq!(info["project_name"], "my-project");
+        assert!(
+            info.get("project").is_none(),
+            "project details are flattened"
+        );
+    }
+
+    #[test]
+    fn server_info_reports_plain_directories_as_not_git() {
+        let project = TempProject::new(false);
+        let opencode = OpenCodeManager::ready_on(4096, project.0.clone());
+
+        let info = serde_json::to_value(ServerInfoPayload::new(57123, &opencode, false, "secret"))
+            .unwrap();
+
+        assert_eq!(info["is_git_repository"], false);
+        assert_eq!(info["has_last_directory"], false);
+        assert_eq!(info["project_name"], "my-project");
+    }
+
+    #[test]
+    fn config_routes_answer_options_and_refuse_other_methods_with_allow() {
+        let preflight = config_preflight(ConfigRoute::Agent, &Method::OPTIONS).unwrap();
+        assert_eq!(preflight.status(), StatusCode::NO_CONTENT);
+        assert_eq!(
+            preflight.headers()[header::ALLOW],
+            "GET, HEAD, POST, PUT, PATCH, DELETE, OPTIONS"
+        );
+        assert_eq!(
+            preflight.headers()["access-control-allow-methods"],
+            "GET, HEAD, POST, PUT, PATCH, DELETE, OPTIONS"
+        );
+
+        let refused = config_preflight(ConfigRoute::Reload, &Method::GET).unwrap();
+        assert_eq!(refused.status(), StatusCode::METHOD_NOT_ALLOWED);
+        assert_eq!(refused.headers()[header::ALLOW], "POST, OPTIONS");
+        let refused = config_preflight(ConfigRoute::Command, &Method::TRACE).unwrap();
+        assert_eq!(refused.status(), StatusCode::METHOD_NOT_ALLOWED);
+
+        assert!(config_preflight(ConfigRoute::Command, &Method::HEAD).is_none());
+        assert!(config_preflight(ConfigRoute::Reload, &Method::POST).is_none());
+    }
+
+    #[tokio::test]
+    async fn head_requests_reach_opencode_as_head_and_return_no_body() {
+        let app = Router::new().fallback(|method: Method| async move {
+            Response::builder()
+                .header("x-upstream-method", method.as_str())
+                .header(header::CONTENT_TYPE, "application/json")
+                .body(Body::from(r#"[{"id":"ses_1"}]"#))
+                .unwrap()
+        });
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        tokio::spawn(async move {
+            axum::serve(listener, app).await.ok();
+        });
+
+        let req = Request::builder()
+            .method(Method::HEAD)
+            .uri("/api/session")
+            .body(Body::empty())
+            .unwrap();
+        let response = forward(proxy_to(port, u64::MAX), req).await;
+
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()["x-upstream-method"], "HEAD");
+        assert_eq!(response.headers()[header::CONTENT_TYPE], "application/json");
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        assert!(body.is_empty());
+    }
+
+    fn quick_retry(attempts: u32, window_ms: u64) -> ProxyRetry {
+        ProxyRetry::from_settings(&json!({
+            "opencode": {"proxyRetryAttempts": attempts, "proxyRetryWindowMs": window_ms}
+        }))
+    }
+
+    /// A port nothing is listening on, for now
+    async fn free_port() -> u16 {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        listener.local_addr().unwrap().port()
+    }
+
+    /// Starts answering on `port` after `after`, counting the requests it sees
+    /// and echoing the Host header they carried
+    fn late_upstream(port: u16, after: Duration) -> Arc<AtomicUsize> {
+        let hits = Arc::new(AtomicUsize::new(0));
+        let counter = hits.clone();
+        let app = Router::new().fallback(move |headers: HeaderMap| {
+            let counter = counter.clone();
+            async move {
+                counter.fetch_add(1, Ordering::SeqCst);
+                headers[header::HOST].to_str().unwrap().to_string()
+            }
+        });
+        tokio::spawn(async move {
+            tokio::time::sleep(after).await;
+            let listener = tokio::net::TcpListener::bind(("127.0.0.1", port))
+                .await
+                .unwrap();
+            axum::serve(listener, app).await.ok();
+        });
+        hits
+    }
+
+    #[tokio::test]
+    async fn gets_are_retried_until_the_port_comes_alive() {
+        let mut port = free_port().await;
+        let hits = late_upstream(port, Duration::from_millis(300));
+        let opencode = OpenCodeManager::ready_on(port, std::env::temp_dir());
+        let client = Client::new();
+        let builder = client.get(format!("http://127.0.0.1:{port}/session"));
+
+        let response = send_with_retry(
+            &client,
+            builder,
+            &opencode,
+            &quick_retry(8, 1600),
+            &mut port,
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(response.status(), reqwest::StatusCode::OK);
+        assert_eq!(hits.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn retries_follow_opencode_to_its_new_port() {
+        let old_port = free_port().await;
+        let new_port = free_port().await;
+        let hits = late_upstream(new_port, Duration::ZERO);
+        let opencode = Arc::new(OpenCodeManager::ready_on(old_port, std::env::temp_dir()));
+        let restarted = opencode.clone();
+        tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(200)).await;
+            restarted.move_to(Some(new_port));
+        });
+        let client = Client::new();
+        let builder = client.get(format!("http://127.0.0.1:{old_port}/session"));
+        let mut port = old_port;
+
+        let response = send_with_retry(
+            &client,
+            builder,
+            &opencode,
+            &quick_retry(8, 1600),
+            &mut port,
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(port, new_port);
+        assert_eq!(hits.load(Ordering::SeqCst), 1);
+        assert_eq!(
+            response.text().await.unwrap(),
+            format!("127.0.0.1:{new_port}")
+        );
+    }
+
+    #[tokio::test]
+    async fn retries_give_up_after_the_window() {
+        let mut port = free_port().await;
+        let opencode = OpenCodeManager::ready_on(port, std::env::temp_dir());
+        let client = Client::new();
+        let builder = client.get(format!("http://127.0.0.1:{port}/session"));
+        let started = Instant::now();
+
+        let err = send_with_retry(&client, builder, &opencode, &quick_retry(2, 400), &mut port)
+            .await
+            .unwrap_err();
+
+        assert!(err.is_connect());
+        assert!(started.elapsed() >= Duration::from_millis(400));
+        assert!(started.elapsed() < Duration::from_secs(5));
+    }
+
+    #[tokio::test]
+    async fn waiting_for_a_port_picks_up_one_set_mid_wait() {
+        let opencode = Arc::new(OpenCodeManager::new_with_directory(Some(
+            std::env::temp_dir(),
+        )));
+        let retry = quick_retry(3, 300);
+        assert_eq!(wait_for_port(&opencode, &retry).await, None);
+
+        let restarted = opencode.clone();
+        tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(150)).await;
+            restarted.move_to(Some(4096));
+        });
+        assert_eq!(wait_for_port(&opencode, &retry).await, Some(4096));
+    }
+
+    #[tokio::test]
+    async fn proxied_gets_ride_out_a_restart() {
+        let port = free_port().await;
+        let hits = late_upstream(port, Duration::from_millis(300));
+        let mut proxy = proxy_to(port, u64::MAX);
+        proxy.proxy_retry = quick_retry(8, 1600);
+
+        let req = Request::builder()
+            .uri("/api/session")
+            .body(Body::empty())
+            .unwrap();
+        let response = forward(proxy, req).await;
+
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(hits.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn proxied_mutations_are_never_retried() {
+        for method in [Method::POST, Method::PUT, Method::PATCH, Method::DELETE] {
+            let port = free_port().await;
+            let hits = late_upstream(port, Duration::from_millis(300));
+            let mut proxy = proxy_to(port, u64::MAX);
+            proxy.proxy_retry = quick_retry(8, 1600);
+
+            let req = Request::builder()
+                .method(method.clone())
+                .uri("/api/session/ses_1")
+                .body(Body::empty())
+                .unwrap();
+            let response = forward(proxy, req).await;
+
+            assert_eq!(response.status(), StatusCode::BAD_GATEWAY, "{method}");
+            assert_eq!(response.headers()[header::RETRY_AFTER], "1", "{method}");
+            tokio::time::sleep(Duration::from_millis(500)).await;
+            assert_eq!(hits.load(Ordering::SeqCst), 0, "{method}");
+        }
+    }
+
+    /// Reports on `events` when the handler or reply holding it goes away,
+    /// i.e. when the proxy closed its connection
+    struct OnDrop(tokio::sync::mpsc::UnboundedSender<String>);
+
+    impl Drop for OnDrop {
+        fn drop(&mut self) {
+            let _ = self.0.send("closed".to_string());
+        }
+    }
+
+    /// OpenCode stand-in whose prompt replies start after `delay` and then
+    /// trickle out `chunks` lines. Reports "closed" when a prompt's connection
+    /// goes away and "abort <query>" for abort calls.
+    async fn prompt_upstream(
+        delay: Duration,
+        chunks: usize,
+    ) -> (u16, tokio::sync::mpsc::UnboundedReceiver<String>) {
+        let (events, received) = tokio::sync::mpsc::unbounded_channel();
+        let on_close = events.clone();
+        let app = Router::new()
+            .route(
+                "/session/{id}/message",
+                post(move || {
+                    let closed = OnDrop(on_close.clone());
+                    async move {
+                        tokio::time::sleep(delay).await;
+                        let reply = futures_util::stream::unfold(closed, |closed| async move {
+                            tokio::time::sleep(Duration::from_millis(50)).await;
+                            let chunk = axum::body::Bytes::from_static(b"chunk\n");
+                            Some((Ok::<_, std::io::Error>(chunk), closed))
+                        });
+                        Body::from_stream(reply.take(chunks))
+                    }
+                }),
+            )
+            .route(
+                "/session/{id}/abort",
+                post(move |RawQuery(query): RawQuery| async move {
+                    let _ = events.send(format!("abort {}", query.unwrap_or_default()));
+                    "true"
+                }),
+            );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        tokio::spawn(async move {
+            axum::serve(listener, app).await.ok();
+        });
+        (port, received)
+    }
+
+    fn prompt() -> Request<Body> {
+        Request::builder()
+            .method(Method::POST)
+            .uri("/api/session/ses_1/message?directory=%2Fsrv%2Fapp")
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(Body::from(r#"{"parts":[]}"#))
+            .unwrap()
+    }
+
+    /// The next `count` events, failing if they take longer than a second
+    async fn next_events(
+        received: &mut tokio::sync::mpsc::UnboundedReceiver<String>,
+        count: usize,
+    ) -> Vec<String> {
+        let mut events = Vec::new();
+        let deadline = tokio::time::Instant::now() + Duration::from_secs(1);
+        while events.len() < count {
+            let event = tokio::time::timeout_at(deadline, received.recv()).await;
+            events.push(event.expect("upstream event within a second").unwrap());
+        }
+        events.sort();
+        events
+    }
+
+    #[tokio::test]
+    async fn clients_leaving_mid_reply_close_and_abort_the_prompt() {
+        let (port, mut received) = prompt_upstream(Duration::ZERO, usize::MAX).await;
+
+        let response = forward(proxy_to(port, u64::MAX), prompt()).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let mut body = response.into_body().into_data_stream();
+        assert_eq!(body.next().await.unwrap().unwrap(), "chunk\n");
+        drop(body);
+
+        assert_eq!(
+            next_events(&mut received, 2).await,
+            ["abort directory=%2Fsrv%2Fapp", "closed"]
+        );
+    }
+
+    #[tokio::test]
+    async fn clients_leaving_before_the_reply_close_and_abort_the_prompt() {
+        let (port, mut received) = prompt_upstream(Duration::from_secs(30), usize::MAX).await;
+
+        let request = tokio::spawn(forward(proxy_to(port, u64::MAX), prompt()));
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        request.abort();
+
+        assert_eq!(
+            next_events(&mut received, 2).await,
+            ["abort directory=%2Fsrv%2Fapp", "closed"]
+        );
+    }
+
+    #[tokio::test]
+    async fn prompts_relayed_in_full_are_not_aborted() {
+        let (port, mut received) = prompt_upstream(Duration::ZERO, 3).await;
+
+        let response = forward(proxy_to(port, u64::MAX), prompt()).await;
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        assert_eq!(body, "chunk\nchunk\nchunk\n");
+
+        assert_eq!(next_events(&mut received, 1).await, ["closed"]);
+        tokio::time::sleep(Duration::from_millis(300)).await;
+        assert!(received.try_recv().is_err());
+    }
+}
@@ -0,0 +1,239 @@
+use std::time::{Duration, Instant};
+
+use log::debug;
+use parking_lot::Mutex;
+use serde::Serialize;
+
+use crate::opencode_client::OpenCodeClient;
+
+/// Error code the UI maps to the provider onboarding prompt
+pub const NO_PROVIDER_CONFIGURED: &str = "NO_PROVIDER_CONFIGURED";
+
+const CAPABILITIES_TTL: Duration = Duration::from_secs(30);
+const CAPABILITIES_REQUEST_TIMEOUT: Duration = Duration::from_secs(3);
+
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ModelCapabilities {
+    /// False until OpenCode answered; callers should not block on unknown state
+    pub known: bool,
+    pub providers: Vec<String>,
+    pub default_model: Option<String>,
+}
+
+impl ModelCapabilities {
+    fn unknown() -> Self {
+        Self {
+            known: false,
+            providers: Vec::new(),
+            default_model: None,
+        }
+    }
+
+    pub fn has_provider(&self) -> bool {
+        !self.providers.is_empty()
+    }
+}
+
+/// Short-lived cache of which providers OpenCode has credentials for, so model-backed
+/// features can fail with a clear code instead of a raw provider error.
+/// Config mutations invalidate it.
+pub struct ModelCapabilityCache {
+    client: OpenCodeClient,
+    entry: Mutex<Option<(Instant, ModelCapabilities)>>,
+}
+
+impl ModelCapabilityCache {
+    pub fn new(client: OpenCodeClient) -> Self {
+        Self {
+            client: client.with_timeout(CAPABILITIES_REQUEST_TIMEOUT),
+            entry: Mutex::new(None),
+        }
+    }
+
+    pub fn invalidate(&self) {
+        *self.entry.lock() = None;
+    }
+
+    pub async fn get(&self) -> ModelCapabilities {
+        if let Some((fetched_at, capabilities)) = self.entry.lock().as_ref() {
+            if fetched_at.elapsed() < CAPABILITIES_TTL {
+                return capabilities.clone();
+            }
+        }
+
+        let capabilities = self.fetch().await;
+        // Unknown results aren't cached so the next call retries once OpenCode is up
+        if capabilities.known {
+            *self.entry.lock() = Some((Instant::now(), capabilities.clone()));
+        }
+        capabilities
+    }
+
+    /// Fail with NO_PROVIDER_CONFIGURED when OpenCode reports no usable provider
+    pub async fn require_provider(&self, feature: &str) -> Result<(), String> {
+        let capabilities = self.get().await;
+        if capabilities.known && !capabilities.has_provider() {
+            return Err(format!(
+                "{}: configure a model provider in OpenCode to use {}",
+                NO_PROVIDER_CONFIGURED, feature
+            ));
+        }
+        Ok(())
+    }
+
+    async fn fetch(&self) -> ModelCapabilities {
+        let (providers, config) =
+            tokio::join!(self.client.get_providers(), self.client.get_config());
+        let providers = match providers {
+            Ok(providers) => providers,
+            Err(err) => {
+                debug!("[desktop:models] Provider lookup failed: {}", err);
+                return ModelCapabilities::unknown();
+            }
+        };
+
+        let default_model = config.ok().and_then(|config| config.model).or_else(|| {
+            let (provider, model) = providers.default.iter().next()?;
+            Some(format!("{}/{}", provider, model.as_str()?))
+        });
+
+        ModelCapabilities {
+            known: true,
+            providers: providers
+                .providers
+                .into_iter()
+                .map(|provider| provider.id)
+                .collect(),
+            default_model,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{opencode_manager::OpenCodeManager, upstream_pool::UpstreamPool};
+    use axum::{http::StatusCode, routing::get, Json, Router};
+    use serde_json::{json, Value};
+    use std::{
+        path::PathBuf,
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc,
+        },
+    };
+
+    /// OpenCode's provider and config routes serving whatever the test sets;
+    /// a null provider list answers with a server error
+    struct MockOpenCode {
+        providers: Arc<Mutex<Value>>,
+        model: Arc<Mutex<Option<String>>>,
+        lookups: Arc<AtomicUsize>,
+        cache: ModelCapabilityCache,
+    }
+
+    async fn mock_opencode(providers: Value) -> MockOpenCode {
+        let providers = Arc::new(Mutex::new(providers));
+        let model = Arc::new(Mutex::new(None::<String>));
+        let lookups = Arc::new(AtomicUsize::new(0));
+        let (served, counter, config) = (providers.clone(), lookups.clone(), model.clone());
+        let app = Router::new()
+            .route(
+                "/config/providers",
+                get(move || async move {
+                    counter.fetch_add(1, Ordering::SeqCst);
+                    match served.lock().clone() {
+                        Value::Null => Err(StatusCode::INTERNAL_SERVER_ERROR),
+                        providers => Ok(Json(providers)),
+                    }
+                }),
+            )
+            .route(
+                "/config",
+                get(move || async move { Json(json!({ "model": *config.lock() })) }),
+            );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        tokio::spawn(async move {
+            axum::serve(listener, app).await.ok();
+        });
+
+        let opencode = Arc::new(OpenCodeManager::ready_on(
+            port,
+            PathBuf::from("/work/project"),
+        ));
+        let pool = Arc::new(UpstreamPool::new(opencode.clone()).unwrap());
+        MockOpenCode {
+            providers,
+            model,
+            lookups,
+            cache: ModelCapabilityCache::new(OpenCodeClient::new(pool, opencode)),
+        }
+    }
+
+    fn anthropic() -> Value {
+        json!({
+            "providers": [{ "id": "anthropic" }],
+            "default": { "anthropic": "claude-sonnet" },
+        })
+    }
+
+    #[tokio::test]
+    async fn no_provider_fails_with_the_onboarding_code() {
+        let mock = mock_opencode(json!({ "providers": [], "default": {} })).await;
+
+        let capabilities = mock.cache.get().await;
+        assert!(capabilities.known);
+        assert!(!capabilities.has_provider());
+        let err = mock
+            .cache
+            .require_provider("commit messages")
+            .await
+            .unwrap_err();
+        assert!(err.starts_with(NO_PROVIDER_CONFIGURED), "{err}");
+        assert!(err.contains("commit messages"), "{err}");
+    }
+
+    #[tokio::test]
+    async fn the_default_model_prefers_the_configured_one() {
+        let mock = mock_opencode(anthropic()).await;
+        assert_eq!(
+            mock.cache.get().await.default_model.as_deref(),
+            Some("anthropic/claude-sonnet")
+        );
+
+        *mock.model.lock() = Some("openai/gpt-5".to_string());
+        mock.cache.invalidate();
+        let capabilities = mock.cache.get().await;
+        assert_eq!(capabilities.providers, ["anthropic"]);
+        assert_eq!(capabilities.default_model.as_deref(), Some("openai/gpt-5"));
+    }
+
+    #[tokio::test]
+    async fn answers_are_cached_until_invalidated() {
+        let mock = mock_opencode(json!({ "providers": [] })).await;
+        assert!(mock.cache.require_provider("summaries").await.is_err());
+
+        // A provider added without a config change stays unseen until the cache expires
+        *mock.providers.lock() = anthropic();
+        assert!(mock.cache.require_provider("summaries").await.is_err());
+        assert_eq!(mock.lookups.load(Ordering::SeqCst), 1);
+
+        mock.cache.invalidate();
+        assert!(mock.cache.require_provider("summaries").await.is_ok());
+        assert_eq!(mock.lookups.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn unknown_state_neither_blocks_nor_sticks() {
+        let mock = mock_opencode(Value::Null).await;
+
+        assert!(!mock.cache.get().await.known);
+        assert!(mock.cache.require_provider("summaries").await.is_ok());
+        assert_eq!(mock.lookups.load(Ordering::SeqCst), 2);
+
+        *mock.providers.lock() = json!({ "providers": [] });
+        assert!(mock.cache.require_provider("summaries").await.is_err());
+    }
+}
@@ -0,0 +1,230 @@
+use std::{
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Duration,
+};
+
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use log::warn;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use tokio::{fs, sync::Mutex};
+
+use crate::{opencode_config, opencode_manager::OpenCodeManager, paths};
+
+/// OpenCode's own config file in a project root
+const PROJECT_CONFIG_NAME: &str = "opencode.json";
+/// Marks providers and models that came from a project's config
+const SOURCE_PROJECT: &str = "project";
+/// Last successful models.dev payload, kept for offline startup
+const DISK_CACHE_FILE: &str = "models-cache.json";
+
+pub const DEFAULT_SOURCE_URL: &str = "https://models.dev/api.json";
+/// Source URL for installs where `modelsMetadataUrl` is not set
+const SOURCE_URL_ENV: &str = "OPENCHAMBER_MODELS_URL";
+/// Response header naming the URL the payload came from
+pub const SOURCE_HEADER: &str = "x-openchamber-models-source";
+
+/// Response header set when models.dev was unreachable and an older copy is served
+pub const CACHE_STATUS_HEADER: &str = "x-openchamber-cache";
+pub const CACHE_STATUS_STALE: &str = "stale";
+
+/// A models.dev-compatible URL; only http(s) is fetched
+fn validate_source_url(raw: &str) -> Result<String, String> {
+    let url = reqwest::Url::parse(raw.trim())
+        .map_err(|err| format!("models metadata URL {:?} is invalid: {}", raw, err))?;
+    if !matches!(url.scheme(), "http" | "https") {
+        return Err(format!(
+            "models metadata URL {:?} must use http or https",
+            raw
+        ));
+    }
+    Ok(url.to_string())
+}
+
+/// `modelsMetadataUrl` as written, validated by save_settings; empty clears it
+pub fn parse_source_url(value: Option<&Value>) -> Result<Option<String>, String> {
+    match value {
+        None | Some(Value::Null) => Ok(None),
+        Some(Value::String(raw)) if raw.trim().is_empty() => Ok(None),
+        Some(Value::String(raw)) => validate_source_url(raw).map(Some),
+        Some(_) => Err("modelsMetadataUrl must be a string".to_string()),
+    }
+}
+
+/// Where to fetch models metadata: `modelsMetadataUrl`, then
+/// OPENCHAMBER_MODELS_URL, then models.dev. An invalid URL is skipped.
+pub fn source_url(settings: &Value) -> String {
+    let configured = parse_source_url(settings.get("modelsMetadataUrl"))
+        .map_err(|err| warn!("[desktop:http] Ignoring setting: {}", err))
+        .ok()
+        .flatten();
+    let from_env = || {
+        let raw = std::env::var(SOURCE_URL_ENV)
+            .ok()
+            .filter(|raw| !raw.trim().is_empty())?;
+        validate_source_url(&raw)
+            .map_err(|err| warn!("[desktop:http] Ignoring {}: {}", SOURCE_URL_ENV, err))
+            .ok()
+    };
+    configured
+        .or_else(from_env)
+        .unwrap_or_else(|| DEFAULT_SOURCE_URL.to_string())
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StoredPayload {
+    /// URL the payload came from; files written before this was recorded
+    /// hold models.dev
+    #[serde(default = "default_source_url")]
+    pub source: String,
+    pub fetched_at: DateTime<Utc>,
+    /// Validators models.dev sent with the payload, for conditional refreshes
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub etag: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_modified: Option<String>,
+    pub payload: Value,
+}
+
+fn default_source_url() -> String {
+    DEFAULT_SOURCE_URL.to_string()
+}
+
+impl StoredPayload {
+    /// Time since the fetch; `None` if the clock went backwards since
+    pub fn age(&self) -> Option<Duration> {
+        (Utc::now() - self.fetched_at).to_std().ok()
+    }
+}
+
+/// The models.dev payload on disk. Writes from concurrent requests are
+/// serialized like SettingsStore's.
+pub struct ModelsDiskCache {
+    path: Option<PathBuf>,
+    guard: Arc<Mutex<()>>,
+}
+
+impl ModelsDiskCache {
+    pub fn new() -> Self {
+        Self {
+            path: paths::cache_dir().map(|dir| dir.join(DISK_CACHE_FILE)),
+            guard: Arc::new(Mutex::new(())),
+        }
+    }
+
+    /// The stored payload; a missing or unreadable file is no cache
+    pub async fn load(&self) -> Option<StoredPayload> {
+        let path = self.path.as_ref()?;
+        let _lock = self.guard.lock().await;
+        let bytes = fs::read(path).await.ok()?;
+        match serde_json::from_slice(&bytes) {
+            Ok(stored) => Some(stored),
+            Err(err) => {
+                warn!("[desktop:http] Ignoring unreadable {:?}: {}", path, err);
+                None
+            }
+        }
+    }
+
+    pub async fn save(&self, stored: &StoredPayload) -> Result<()> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        let _lock = self.guard.lock().await;
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).await?;
+        }
+        // Written aside first, so a crash never leaves half a payload behind
+        let tmp = path.with_extension("json.tmp");
+        fs::write(&tmp, serde_json::to_vec(stored)?).await?;
+        fs::rename(&tmp, path).await?;
+        Ok(())
+    }
+}
+
+/// Config files whose `provider` entries apply to `directory`, lowest
+/// precedence first: the project's opencode.json, then the config the app
+/// passes OpenCode for it
+async fn config_files(opencode: &OpenCodeManager, directory: &Path) -> Vec<PathBuf> {
+    let mut files = vec![directory.join(PROJECT_CONFIG_NAME)];
+    if let Some(path) = opencode.resolve_config_path(directory).await {
+        if !files.contains(&path) {
+            files.push(path);
+        }
+    }
+    files
+}
+
+/// Every `provider` entry configured for `directory`, later files winning
+pub async fn project_providers(opencode: &OpenCodeManager, directory: &Path) -> Map<String, Value> {
+    let mut providers = Map::new();
+    for file in config_files(opencode, directory).await {
+        let config = match opencode_config::read_config_file(&file).await {
+            Ok(config) => config,
+            Err(err) => {
+                warn!(
+                    "[desktop:http] Ignoring {:?} for models metadata: {}",
+                    file, err
+                );
+                continue;
+            }
+        };
+        if let Some(Value::Object(configured)) = config.get("provider") {
+            for (id, provider) in configured {
+                merge_into(providers.entry(id.clone()).or_insert(Value::Null), provider);
+            }
+        }
+    }
+    providers
+}
+
+/// models.dev `payload` with `providers` merged over it. Merged providers and
+/// models get `"source": "project"`; providers models.dev lacks are added.
+pub fn merge_providers(payload: &Value, providers: &Map<String, Value>) -> Value {
+    let mut merged = payload.clone();
+    let Some(entries) = merged.as_object_mut() else {
+        return merged;
+    };
+    for (id, provider) in providers {
+        let entry = entries.entry(id.clone()).or_insert_with(|| {
+            let mut fresh = Map::new();
+            fresh.insert("id".to_string(), Value::String(id.clone()));
+            Value::Object(fresh)
+        });
+        merge_into(entry, provider);
+        if let Value::Object(entry) = entry {
+            entry.insert(
+                "source".to_string(),
+                Value::String(SOURCE_PROJECT.to_string()),
+            );
+            if let (Some(Value::Object(models)), Some(Value::Object(configured))) =
+                (entry.get_mut("models"), provider.get("models"))
+            {
+                for model_id in configured.keys() {
+                    if let Some(Value::Object(model)) = models.get_mut(model_id) {
+                        model.insert(
+                            "source".to_string(),
+                            Value::String(SOURCE_PROJECT.to_string()),
+                        );
+                    }
+                }
+            }
+        }
+    }
+    merged
+}
+
+/// Deep-merge `overlay` into `base`: objects merge key by key, anything else replaces
+fn merge_into(base: &mut Value, overlay: &Value) {
+    match (base, overlay) {
+        (Value::Object(base), Value::Object(overlay)) => {
+            for (key, value) in overlay {
+                merge_into(base.entry(key.clone()).or_insert(Value::Null), value);
+            }
+        }
+        (base, overlay) => *base = overlay.clone(),
+    }
+}
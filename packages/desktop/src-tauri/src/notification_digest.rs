@@ -0,0 +1,165 @@
+use std::time::{Duration, Instant};
+
+/// Default time the first completion waits for others to join a digest
+pub const DEFAULT_DIGEST_WINDOW: Duration = Duration::from_secs(15);
+
+/// Names listed in a digest before the rest are summarised as "+N more"
+const DIGEST_MAX_NAMES: usize = 3;
+
+/// A ready-to-show completion notification
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Completion {
+    /// Short name used in digests (session title or agent mode)
+    pub label: String,
+    pub title: String,
+    pub body: String,
+    pub sound: bool,
+}
+
+/// Result of pushing a completion into the batcher
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PushOutcome {
+    /// First completion of a new window; the caller must call `flush` at the deadline
+    Scheduled(Instant),
+    /// Joined an already scheduled window
+    Joined,
+}
+
+/// Holds completion notifications for a short window so bursts collapse into one
+/// digest. Timer-free: callers schedule the flush, which keeps this easy to drive
+/// with fake instants.
+#[derive(Debug)]
+pub struct CompletionBatcher {
+    window: Duration,
+    pending: Vec<Completion>,
+    deadline: Option<Instant>,
+}
+
+impl CompletionBatcher {
+    pub fn new(window: Duration) -> Self {
+        Self {
+            window,
+            pending: Vec::new(),
+            deadline: None,
+        }
+    }
+
+    pub fn set_window(&mut self, window: Duration) {
+        self.window = window;
+    }
+
+    pub fn push(&mut self, completion: Completion, now: Instant) -> PushOutcome {
+        self.pending.push(completion);
+        match self.deadline {
+            Some(_) => PushOutcome::Joined,
+            None => {
+                let deadline = now + self.window;
+                self.deadline = Some(deadline);
+                PushOutcome::Scheduled(deadline)
+            }
+        }
+    }
+
+    /// Take whatever is pending once the window has elapsed: the original
+    /// notification if it was alone, otherwise a digest of all of them.
+    pub fn flush(&mut self, now: Instant) -> Option<Completion> {
+        match self.deadline {
+            Some(deadline) if deadline <= now => {}
+            _ => return None,
+        }
+        self.deadline = None;
+
+        let mut pending = std::mem::take(&mut self.pending);
+        match pending.len() {
+            0 => None,
+            1 => pending.pop(),
+            _ => Some(digest(&pending)),
+        }
+    }
+}
+
+/// "3 agents finished: build-fixer, docs, review"
+pub fn digest(completions: &[Completion]) -> Completion {
+    let mut names: Vec<&str> = Vec::new();
+    for completion in completions {
+        if !names.contains(&completion.label.as_str()) {
+            names.push(&completion.label);
+        }
+    }
+
+    let mut body = names
+        .iter()
+        .take(DIGEST_MAX_NAMES)
+        .copied()
+        .collect::<Vec<_>>()
+        .join(", ");
+    if names.len() > DIGEST_MAX_NAMES {
+        body.push_str(&format!(" +{} more", names.len() - DIGEST_MAX_NAMES));
+    }
+
+    Completion {
+        label: String::new(),
+        title: format!("{} agents finished", completions.len()),
+        body,
+        sound: completions.iter().any(|completion| completion.sound),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn completion(label: &str, sound: bool) -> Completion {
+        Completion {
+            label: label.to_string(),
+            title: format!("{label} is ready"),
+            body: format!("{label} finished"),
+            sound,
+        }
+    }
+
+    #[test]
+    fn a_lone_completion_is_shown_as_is() {
+        let start = Instant::now();
+        let mut batcher = CompletionBatcher::new(Duration::from_secs(15));
+        let deadline = match batcher.push(completion("docs", true), start) {
+            PushOutcome::Scheduled(deadline) => deadline,
+            other => panic!("{other:?}"),
+        };
+        assert_eq!(deadline, start + Duration::from_secs(15));
+
+        assert_eq!(batcher.flush(start + Duration::from_secs(14)), None);
+        assert_eq!(batcher.flush(deadline), Some(completion("docs", true)));
+        assert_eq!(batcher.flush(deadline), None);
+    }
+
+    #[test]
+    fn a_burst_collapses_into_one_digest() {
+        let start = Instant::now();
+        let mut batcher = CompletionBatcher::new(Duration::from_secs(15));
+        let labels = ["build", "docs", "build", "review", "lint", "tests"];
+        for (i, label) in labels.iter().enumerate() {
+            let outcome = batcher.push(completion(label, i == 2), start);
+            assert_eq!(outcome == PushOutcome::Joined, i > 0);
+        }
+
+        let digest = batcher.flush(start + Duration::from_secs(15)).unwrap();
+        assert_eq!(digest.title, "6 agents finished");
+        assert_eq!(digest.body, "build, docs, review +2 more");
+        assert!(digest.sound);
+
+        // The next completion opens a new window
+        let later = start + Duration::from_secs(20);
+        assert!(matches!(
+            batcher.push(completion("docs", false), later),
+            PushOutcome::Scheduled(_)
+        ));
+    }
+
+    #[test]
+    fn quiet_bursts_stay_quiet() {
+        let digest = digest(&[completion("a", false), completion("b", false)]);
+        assert_eq!(digest.body, "a, b");
+        assert!(!digest.sound);
+    }
+}
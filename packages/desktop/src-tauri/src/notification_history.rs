@@ -0,0 +1,149 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use parking_lot::Mutex;
+use serde::Serialize;
+use tauri::{AppHandle, Emitter, Manager, Runtime};
+
+use crate::platform::PlatformCapabilities;
+
+/// Emitted with the new `NotificationRecord` whenever one is recorded
+pub const NOTIFICATION_RECORDED_EVENT: &str = "openchamber:notification-recorded";
+
+const MAX_ENTRIES: usize = 100;
+
+/// A notification the app raised, whether or not the OS got to show it
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NotificationRecord {
+    pub id: u64,
+    /// RFC 3339
+    pub at: String,
+    pub title: String,
+    pub body: String,
+    /// Held back because system DND was on and `respectSystemDnd` is set
+    pub suppressed_by_dnd: bool,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NotificationHistorySnapshot {
+    /// Newest last
+    pub entries: Vec<NotificationRecord>,
+    /// Recorded while the window was in the background; shown as the dock badge
+    pub unseen: u32,
+}
+
+/// Recent notifications plus the unseen count behind the dock badge. DND may
+/// keep a notification off screen, but it is still recorded here so it isn't lost.
+#[derive(Default)]
+pub struct NotificationHistory {
+    next_id: AtomicU64,
+    inner: Mutex<NotificationHistorySnapshot>,
+}
+
+impl NotificationHistory {
+    /// Store a notification; `unseen` only grows while the window is in the
+    /// background. Returns the record and the unseen count after it.
+    pub fn record(
+        &self,
+        title: &str,
+        body: &str,
+        suppressed_by_dnd: bool,
+        focused: bool,
+    ) -> (NotificationRecord, u32) {
+        let record = NotificationRecord {
+            id: self.next_id.fetch_add(1, Ordering::Relaxed),
+            at: chrono::Utc::now().to_rfc3339(),
+            title: title.to_string(),
+            body: body.to_string(),
+            suppressed_by_dnd,
+        };
+        let mut inner = self.inner.lock();
+        if inner.entries.len() == MAX_ENTRIES {
+            inner.entries.remove(0);
+        }
+        inner.entries.push(record.clone());
+        if !focused {
+            inner.unseen = inner.unseen.saturating_add(1);
+        }
+        (record, inner.unseen)
+    }
+
+    pub fn snapshot(&self) -> NotificationHistorySnapshot {
+        self.inner.lock().clone()
+    }
+
+    /// The user looked at the window; the history is kept
+    pub fn mark_seen(&self) {
+        self.inner.lock().unseen = 0;
+    }
+}
+
+/// Record a notification and bring the dock badge up to date. Call for every
+/// notification that passed the app's own policy, including ones DND held back.
+pub fn record<R: Runtime>(
+    app: &AppHandle<R>,
+    history: &NotificationHistory,
+    title: &str,
+    body: &str,
+    suppressed_by_dnd: bool,
+) {
+    let window = app.get_webview_window("main");
+    let focused = window
+        .as_ref()
+        .map(|window| {
+            window.is_focused().unwrap_or(false) && !window.is_minimized().unwrap_or(false)
+        })
+        .unwrap_or(false);
+    let (record, unseen) = history.record(title, body, suppressed_by_dnd, focused);
+
+    if let Some(window) = window.filter(|_| PlatformCapabilities::current().badge && unseen > 0) {
+        let _ = window.set_badge_count(Some(unseen as i64));
+    }
+    let _ = app.emit(NOTIFICATION_RECORDED_EVENT, &record);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn background_notifications_count_toward_the_badge() {
+        let history = NotificationHistory::default();
+
+        assert_eq!(history.record("a", "shown", false, false).1, 1);
+        let (record, unseen) = history.record("b", "held back", true, false);
+        assert_eq!(unseen, 2);
+        assert!(record.suppressed_by_dnd);
+        assert_eq!(history.record("c", "while focused", false, true).1, 2);
+
+        let snapshot = history.snapshot();
+        let titles: Vec<_> = snapshot.entries.iter().map(|e| e.title.as_str()).collect();
+        assert_eq!(titles, ["a", "b", "c"]);
+        assert_eq!(snapshot.unseen, 2);
+    }
+
+    #[test]
+    fn marking_seen_clears_the_badge_but_keeps_the_history() {
+        let history = NotificationHistory::default();
+        history.record("a", "", true, false);
+        history.mark_seen();
+
+        let snapshot = history.snapshot();
+        assert_eq!(snapshot.unseen, 0);
+        assert_eq!(snapshot.entries.len(), 1);
+    }
+
+    #[test]
+    fn history_keeps_the_newest_entries() {
+        let history = NotificationHistory::default();
+        for i in 0..MAX_ENTRIES + 5 {
+            history.record(&i.to_string(), "", false, true);
+        }
+
+        let entries = history.snapshot().entries;
+        assert_eq!(entries.len(), MAX_ENTRIES);
+        assert_eq!(entries[0].title, "5");
+        assert_eq!(entries[0].id, 5);
+    }
+}
@@ -0,0 +1,338 @@
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+
+use serde::Serialize;
+use serde_json::Value;
+
+/// Default budget for webview-requested notifications
+pub const DEFAULT_NOTIFICATIONS_PER_MINUTE: u32 = 10;
+
+/// Identical title+body pairs inside this window are dropped
+const DEDUP_WINDOW: Duration = Duration::from_secs(10);
+/// Bucket refill period; also how long suppressed notifications are collected
+/// before the summary is shown
+const RATE_WINDOW: Duration = Duration::from_secs(60);
+
+/// `notifications.rateLimitPerMinute`; 0 disables the limit (dedup still applies)
+pub fn rate_limit(settings: &Value) -> u32 {
+    settings
+        .get("notifications")
+        .and_then(|value| value.get("rateLimitPerMinute"))
+        .and_then(Value::as_u64)
+        .map(|limit| limit.min(u32::MAX as u64) as u32)
+        .unwrap_or(DEFAULT_NOTIFICATIONS_PER_MINUTE)
+}
+
+/// What happened to a notification request, reported back to the webview
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum NotifyOutcome {
+    Shown,
+    Deduped,
+    Suppressed,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Admission {
+    Show,
+    Deduped,
+    /// Over the limit. `Some` on the first suppression of a window: the caller
+    /// must call `flush` at that instant to show the summary.
+    Suppressed(Option<Instant>),
+}
+
+impl Admission {
+    pub fn outcome(&self) -> NotifyOutcome {
+        match self {
+            Admission::Show => NotifyOutcome::Shown,
+            Admission::Deduped => NotifyOutcome::Deduped,
+            Admission::Suppressed(_) => NotifyOutcome::Suppressed,
+        }
+    }
+}
+
+/// Token bucket plus short-term dedup guarding the OS notification center from
+/// webview loops. Timer-free like `CompletionBatcher`: callers pass `now` and
+/// schedule the summary flush themselves.
+#[derive(Debug)]
+pub struct NotificationLimiter {
+    per_minute: u32,
+    tokens: f64,
+    refilled_at: Option<Instant>,
+    recent: HashMap<(String, String), Instant>,
+    suppressed: u32,
+    summary_at: Option<Instant>,
+}
+
+impl Default for NotificationLimiter {
+    fn default() -> Self {
+        Self::new(DEFAULT_NOTIFICATIONS_PER_MINUTE)
+    }
+}
+
+impl NotificationLimiter {
+    pub fn new(per_minute: u32) -> Self {
+        Self {
+            per_minute,
+            tokens: per_minute as f64,
+            refilled_at: None,
+            recent: HashMap::new(),
+            suppressed: 0,
+            summary_at: None,
+        }
+    }
+
+    pub fn set_rate(&mut self, per_minute: u32) {
+        if per_minute != self.per_minute {
+            self.per_minute = per_minute;
+            self.tokens = self.tokens.min(per_minute as f64);
+        }
+    }
+
+    fn refill(&mut self, now: Instant) {
+        if let Some(last) = self.refilled_at {
+            let elapsed = now.saturating_duration_since(last).as_secs_f64();
+            let rate = self.per_minute as f64 / RATE_WINDOW.as_secs_f64();
+            self.tokens = (self.tokens + elapsed * rate).min(self.per_minute as f64);
+        }
+        self.refilled_at = Some(now);
+    }
+
+    pub fn check(&mut self, title: &str, body: &str, now: Instant) -> Admission {
+        self.recent
+            .retain(|_, seen| now.saturating_duration_since(*seen) < DEDUP_WINDOW);
+        let key = (title.to_string(), body.to_string());
+        if self.recent.contains_key(&key) {
+            return Admission::Deduped;
+        }
+        self.recent.insert(key, now);
+
+        if self.per_minute == 0 {
+            return Admission::Show;
+        }
+        self.refill(now);
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            return Admission::Show;
+        }
+
+        self.suppressed += 1;
+        match self.summary_at {
+            Some(_) => Admission::Suppressed(None),
+            None => {
+                let deadline = now + RATE_WINDOW;
+                self.summary_at = Some(deadline);
+                Admission::Suppressed(Some(deadline))
+            }
+        }
+    }
+
+    /// Number of notifications suppressed during the window, once it has closed
+    pub fn flush(&mut self, now: Instant) -> Option<u32> {
+        match self.summary_at {
+            Some(deadline) if deadline <= now => {}
+            _ => return None,
+        }
+        self.summary_at = None;
+        match std::mem::take(&mut self.suppressed) {
+            0 => None,
+            count => Some(count),
+        }
+    }
+}
+
+/// Body of the summary shown after a suppressed burst
+pub fn summary_body(count: u32) -> String {
+    if count == 1 {
+        "1 more notification suppressed".to_string()
+    } else {
+        format!("{} more notifications suppressed", count)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    /// Fills the bucket with `count` distinct notifications at `now`
+    fn spend(limiter: &mut NotificationLimiter, count: u32, now: Instant) {
+        for i in 0..count {
+            assert_eq!(
+                limiter.check("title", &format!("spend {i}"), now),
+                Admission::Show
+            );
+        }
+    }
+
+    #[test]
+    fn the_rate_comes_from_settings() {
+        assert_eq!(rate_limit(&json!({})), DEFAULT_NOTIFICATIONS_PER_MINUTE);
+        assert_eq!(
+            rate_limit(&json!({ "notifications": { "rateLimitPerMinute": 3 } })),
+            3
+        );
+        assert_eq!(
+            rate_limit(&json!({ "notifications": { "rateLimitPerMinute": "3" } })),
+            DEFAULT_NOTIFICATIONS_PER_MINUTE
+        );
+        assert_eq!(
+            rate_limit(&json!({ "notifications": { "rateLimitPerMinute": u64::MAX } })),
+            u32::MAX
+        );
+    }
+
+    #[test]
+    fn identical_notifications_are_deduped_within_the_window() {
+        let start = Instant::now();
+        let mut limiter = NotificationLimiter::new(0);
+
+        assert_eq!(
+            limiter.check("Done", "Build passed", start),
+            Admission::Show
+        );
+        assert_eq!(
+            limiter.check("Done", "Build passed", start + Duration::from_secs(9)),
+            Admission::Deduped
+        );
+        assert_eq!(
+            limiter.check("Done", "Build failed", start + Duration::from_secs(9)),
+            Admission::Show
+        );
+        assert_eq!(
+            limiter.check("Done", "Build passed", start + DEDUP_WINDOW),
+            Admission::Show
+        );
+    }
+
+    #[test]
+    fn deduped_notifications_cost_no_tokens() {
+        let start = Instant::now();
+        let mut limiter = NotificationLimiter::new(2);
+
+        assert_eq!(limiter.check("a", "", start), Admission::Show);
+        for _ in 0..5 {
+            assert_eq!(limiter.check("a", "", start), Admission::Deduped);
+        }
+        assert_eq!(limiter.check("b", "", start), Admission::Show);
+    }
+
+    #[test]
+    fn bursts_over_the_limit_are_suppressed_until_tokens_refill() {
+        let start = Instant::now();
+        let mut limiter = NotificationLimiter::new(10);
+        spend(&mut limiter, 10, start);
+
+        assert_eq!(
+            limiter.check("over", "1", start),
+            Admission::Suppressed(Some(start + RATE_WINDOW))
+        );
+        assert_eq!(
+            limiter.check("over", "2", start),
+            Admission::Suppressed(None)
+        );
+
+        // One token refills every six seconds at ten per minute
+        let almost = start + Duration::from_millis(5_900);
+        assert_eq!(
+            limiter.check("over", "3", almost),
+            Admission::Suppressed(None)
+        );
+        let refilled = start + Duration::from_secs(6);
+        assert_eq!(limiter.check("over", "4", refilled), Admission::Show);
+        assert_eq!(
+            limiter.check("over", "5", refilled),
+            Admission::Suppressed(None)
+        );
+    }
+
+    #[test]
+    fn the_summary_counts_suppressions_once_the_window_closes() {
+        let start = Instant::now();
+        let mut limiter = NotificationLimiter::new(1);
+        spend(&mut limiter, 1, start);
+
+        let Admission::Suppressed(Some(deadline)) = limiter.check("x", "1", start) else {
+            panic!("first suppression should schedule the summary");
+        };
+        limiter.check("x", "2", start + Duration::from_secs(1));
+        limiter.check("x", "3", start + Duration::from_secs(2));
+
+        assert_eq!(limiter.flush(deadline - Duration::from_millis(1)), None);
+        assert_eq!(limiter.flush(deadline), Some(3));
+        assert_eq!(limiter.flush(deadline), None);
+        assert_eq!(summary_body(3), "3 more notifications suppressed");
+        assert_eq!(summary_body(1), "1 more notification suppressed");
+    }
+
+    #[test]
+    fn a_new_window_starts_after_the_summary() {
+        let start = Instant::now();
+        let mut limiter = NotificationLimiter::new(1);
+        spend(&mut limiter, 1, start);
+        let Admission::Suppressed(Some(first)) = limiter.check("x", "1", start) else {
+            panic!("expected a scheduled summary");
+        };
+        assert_eq!(limiter.flush(first), Some(1));
+
+        // The bucket refilled over the window; drain it and overflow again
+        assert_eq!(limiter.check("y", "1", first), Admission::Show);
+        let later = first + Duration::from_secs(1);
+        assert_eq!(
+            limiter.check("y", "2", later),
+            Admission::Suppressed(Some(later + RATE_WINDOW))
+        );
+        assert_eq!(limiter.flush(later + RATE_WINDOW), Some(1));
+    }
+
+    #[test]
+    fn flushing_without_suppressions_shows_nothing() {
+        let start = Instant::now();
+        let mut limiter = NotificationLimiter::new(5);
+        spend(&mut limiter, 3, start);
+        assert_eq!(limiter.flush(start + RATE_WINDOW * 2), None);
+    }
+
+    #[test]
+    fn tokens_never_exceed_the_rate() {
+        let start = Instant::now();
+        let mut limiter = NotificationLimiter::new(3);
+        limiter.check("warm", "", start);
+        let idle = start + Duration::from_secs(3600);
+
+        spend(&mut limiter, 3, idle);
+        assert!(matches!(
+            limiter.check("x", "", idle),
+            Admission::Suppressed(_)
+        ));
+    }
+
+    #[test]
+    fn lowering_the_rate_caps_saved_tokens_and_zero_disables_the_limit() {
+        let start = Instant::now();
+        let mut limiter = NotificationLimiter::new(10);
+        limiter.set_rate(2);
+        spend(&mut limiter, 2, start);
+        assert!(matches!(
+            limiter.check("x", "", start),
+            Admission::Suppressed(_)
+        ));
+
+        limiter.set_rate(0);
+        for i in 0..50 {
+            assert_eq!(limiter.check("y", &i.to_string(), start), Admission::Show);
+        }
+    }
+
+    #[test]
+    fn outcomes_are_reported_in_camel_case() {
+        assert_eq!(
+            serde_json::to_value(Admission::Suppressed(None).outcome()).unwrap(),
+            "suppressed"
+        );
+        assert_eq!(Admission::Show.outcome(), NotifyOutcome::Shown);
+        assert_eq!(Admission::Deduped.outcome(), NotifyOutcome::Deduped);
+    }
+}
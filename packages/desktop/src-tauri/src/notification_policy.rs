@@ -0,0 +1,379 @@
+use std::{
+    collections::HashMap,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use log::warn;
+use serde_json::Value;
+use tokio::sync::Mutex;
+
+use crate::opencode_config::{self, ConfigPaths};
+
+/// Agent preferences are re-read at most this often even without config changes,
+/// so hand edits to the files are picked up
+const PREFERENCES_TTL: Duration = Duration::from_secs(5 * 60);
+
+/// `notify` in an agent's `openchamber` block
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum NotifyMode {
+    Never,
+    Always,
+    /// Only while the window is unfocused or minimized (the global behavior)
+    Unfocused,
+}
+
+impl NotifyMode {
+    fn parse(value: &str) -> Option<Self> {
+        match value {
+            "never" => Some(Self::Never),
+            "always" => Some(Self::Always),
+            "unfocused" => Some(Self::Unfocused),
+            _ => None,
+        }
+    }
+}
+
+/// Per-agent notification preferences from `openchamber: {notify, sound}`
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct AgentNotificationPrefs {
+    pub notify: Option<NotifyMode>,
+    pub sound: Option<String>,
+}
+
+impl AgentNotificationPrefs {
+    /// Parse an `openchamber` block; errors name the offending key
+    pub fn parse(block: &Value) -> Result<Self, String> {
+        let Some(block) = block.as_object() else {
+            return Err("openchamber must be an object".to_string());
+        };
+
+        let notify = match block.get("notify") {
+            None | Some(Value::Null) => None,
+            Some(value) => Some(
+                value
+                    .as_str()
+                    .and_then(NotifyMode::parse)
+                    .ok_or("openchamber.notify must be one of never, always, unfocused")?,
+            ),
+        };
+        let sound = match block.get("sound") {
+            None | Some(Value::Null) => None,
+            Some(Value::String(sound)) if !sound.trim().is_empty() => {
+                Some(sound.trim().to_string())
+            }
+            Some(_) => return Err("openchamber.sound must be a non-empty string".to_string()),
+        };
+
+        Ok(Self { notify, sound })
+    }
+}
+
+/// Reject malformed `openchamber` blocks in agent create/update payloads.
+/// A null block is a removal and always allowed.
+pub fn validate_agent_payload(payload: &HashMap<String, Value>) -> Result<(), String> {
+    match payload.get(opencode_config::OPENCHAMBER_FIELD) {
+        None | Some(Value::Null) => Ok(()),
+        Some(block) => AgentNotificationPrefs::parse(block).map(|_| ()),
+    }
+}
+
+/// `notifications.mutedSessions` in settings
+pub fn session_muted(settings: &Value, session_id: Option<&str>) -> bool {
+    let Some(session_id) = session_id else {
+        return false;
+    };
+    settings
+        .get("notifications")
+        .and_then(|value| value.get("mutedSessions"))
+        .and_then(Value::as_array)
+        .is_some_and(|ids| ids.iter().any(|id| id.as_str() == Some(session_id)))
+}
+
+/// Whether and how to notify for one completion, before the OS DND decision
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct EffectivePolicy {
+    pub show: bool,
+    pub sound: Option<String>,
+}
+
+/// Precedence: per-agent setting > per-session mute > global settings.
+/// `global_sound` is the sound from settings; `focused` is true when the window
+/// is in the foreground and not minimized.
+pub fn resolve(
+    agent: Option<&AgentNotificationPrefs>,
+    session_muted: bool,
+    global_sound: Option<String>,
+    focused: bool,
+) -> EffectivePolicy {
+    let show = match agent.and_then(|prefs| prefs.notify) {
+        Some(NotifyMode::Never) => false,
+        Some(NotifyMode::Always) => true,
+        Some(NotifyMode::Unfocused) => !focused,
+        None => !session_muted && !focused,
+    };
+    let sound = agent.and_then(|prefs| prefs.sound.clone()).or(global_sound);
+    EffectivePolicy { show, sound }
+}
+
+type AgentPreferences = Arc<HashMap<String, AgentNotificationPrefs>>;
+
+/// Per-agent preferences read from the OpenCode config, rebuilt after config
+/// mutations invalidate it
+pub struct AgentPreferenceCache {
+    paths: ConfigPaths,
+    entry: Mutex<Option<(Instant, AgentPreferences)>>,
+}
+
+impl AgentPreferenceCache {
+    pub fn new(paths: ConfigPaths) -> Self {
+        Self {
+            paths,
+            entry: Mutex::new(None),
+        }
+    }
+
+    pub async fn invalidate(&self) {
+        *self.entry.lock().await = None;
+    }
+
+    pub async fn get(&self, agent: &str) -> Option<AgentNotificationPrefs> {
+        let mut entry = self.entry.lock().await;
+        let fresh = entry
+            .as_ref()
+            .is_some_and(|(loaded_at, _)| loaded_at.elapsed() < PREFERENCES_TTL);
+        if !fresh {
+            *entry = Some((Instant::now(), Arc::new(self.load().await)));
+        }
+        entry.as_ref()?.1.get(agent).cloned()
+    }
+
+    async fn load(&self) -> HashMap<String, AgentNotificationPrefs> {
+        let blocks = match opencode_config::agent_openchamber_blocks(&self.paths).await {
+            Ok(blocks) => blocks,
+            Err(err) => {
+                warn!("[desktop:notify] Failed to read agent preferences: {}", err);
+                return HashMap::new();
+            }
+        };
+        let mut prefs = HashMap::new();
+        for (name, block) in blocks {
+            match AgentNotificationPrefs::parse(&block) {
+                Ok(parsed) => {
+                    prefs.insert(name, parsed);
+                }
+                Err(err) => warn!("[desktop:notify] Ignoring preferences of agent {name}: {err}"),
+            }
+        }
+        prefs
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use std::path::PathBuf;
+
+    /// A config directory and journal under the system temp dir, removed on drop
+    struct TempConfig {
+        root: PathBuf,
+        paths: ConfigPaths,
+    }
+
+    impl TempConfig {
+        fn new() -> Self {
+            let root =
+                std::env::temp_dir().join(format!("openchamber-notify-{}", uuid::Uuid::new_v4()));
+            let paths = ConfigPaths::new(root.join("opencode"), root.join("journal"));
+            Self { root, paths }
+        }
+    }
+
+    impl Drop for TempConfig {
+        fn drop(&mut self) {
+            std::fs::remove_dir_all(&self.root).ok();
+        }
+    }
+
+    fn prefs(notify: Option<NotifyMode>, sound: Option<&str>) -> AgentNotificationPrefs {
+        AgentNotificationPrefs {
+            notify,
+            sound: sound.map(str::to_string),
+        }
+    }
+
+    fn payload(value: Value) -> HashMap<String, Value> {
+        serde_json::from_value(value).unwrap()
+    }
+
+    #[test]
+    fn openchamber_blocks_are_parsed() {
+        assert_eq!(
+            AgentNotificationPrefs::parse(&json!({"notify": "always", "sound": " Hero "})).unwrap(),
+            prefs(Some(NotifyMode::Always), Some("Hero"))
+        );
+        assert_eq!(
+            AgentNotificationPrefs::parse(&json!({"notify": "never"})).unwrap(),
+            prefs(Some(NotifyMode::Never), None)
+        );
+        assert_eq!(
+            AgentNotificationPrefs::parse(&json!({"notify": null, "sound": null})).unwrap(),
+            AgentNotificationPrefs::default()
+        );
+
+        assert!(AgentNotificationPrefs::parse(&json!("always")).is_err());
+        assert!(
+            AgentNotificationPrefs::parse(&json!({"notify": "sometimes"}))
+                .unwrap_err()
+                .contains("openchamber.notify")
+        );
+        assert!(AgentNotificationPrefs::parse(&json!({"sound": "  "}))
+            .unwrap_err()
+            .contains("openchamber.sound"));
+        assert!(AgentNotificationPrefs::parse(&json!({"sound": 3})).is_err());
+    }
+
+    #[test]
+    fn agent_payloads_are_validated() {
+        assert!(validate_agent_payload(&payload(json!({"model": "a/b"}))).is_ok());
+        assert!(validate_agent_payload(&payload(json!({"openchamber": null}))).is_ok());
+        assert!(
+            validate_agent_payload(&payload(json!({"openchamber": {"notify": "unfocused"}})))
+                .is_ok()
+        );
+        assert!(
+            validate_agent_payload(&payload(json!({"openchamber": {"notify": "loud"}}))).is_err()
+        );
+    }
+
+    #[test]
+    fn muted_sessions_come_from_settings() {
+        let settings = json!({"notifications": {"mutedSessions": ["ses_1", "ses_2"]}});
+        assert!(session_muted(&settings, Some("ses_2")));
+        assert!(!session_muted(&settings, Some("ses_3")));
+        assert!(!session_muted(&settings, None));
+        assert!(!session_muted(&Value::Null, Some("ses_1")));
+    }
+
+    #[test]
+    fn per_agent_settings_win_over_session_mutes_and_globals() {
+        let global = || Some("Glass".to_string());
+        let never = prefs(Some(NotifyMode::Never), None);
+        let always = prefs(Some(NotifyMode::Always), Some("Hero"));
+        let unfocused = prefs(Some(NotifyMode::Unfocused), None);
+        let sound_only = prefs(None, Some("Ping"));
+
+        // (agent, session muted, focused) -> (show, sound)
+        let cases = [
+            (None, false, false, true, "Glass"),
+            (None, false, true, false, "Glass"),
+            (None, true, false, false, "Glass"),
+            (Some(&never), false, false, false, "Glass"),
+            (Some(&always), true, true, true, "Hero"),
+            (Some(&always), false, true, true, "Hero"),
+            (Some(&unfocused), true, false, true, "Glass"),
+            (Some(&unfocused), false, true, false, "Glass"),
+            (Some(&sound_only), true, false, false, "Ping"),
+            (Some(&sound_only), false, false, true, "Ping"),
+        ];
+        for (agent, muted, focused, show, sound) in cases {
+            assert_eq!(
+                resolve(agent, muted, global(), focused),
+                EffectivePolicy {
+                    show,
+                    sound: Some(sound.to_string()),
+                },
+                "agent {agent:?}, muted {muted}, focused {focused}"
+            );
+        }
+
+        assert_eq!(resolve(None, false, None, false).sound, None);
+        assert_eq!(
+            resolve(Some(&always), false, None, false).sound.as_deref(),
+            Some("Hero")
+        );
+    }
+
+    #[tokio::test]
+    async fn preferences_are_read_from_agent_files_and_opencode_json() {
+        let config = TempConfig::new();
+        opencode_config::create_agent(
+            &config.paths,
+            "watcher",
+            &payload(json!({"description": "Watches", "openchamber": {"notify": "never"}})),
+        )
+        .await
+        .unwrap();
+        opencode_config::create_agent(
+            &config.paths,
+            "team/deploy",
+            &payload(json!({"openchamber": {"notify": "unfocused"}})),
+        )
+        .await
+        .unwrap();
+        opencode_config::create_agent(&config.paths, "plain", &payload(json!({"model": "a/b"})))
+            .await
+            .unwrap();
+        tokio::fs::write(
+            config.paths.config_file(),
+            json!({"agent": {
+                "team/deploy": {"openchamber": {"notify": "always", "sound": "Hero"}},
+                "broken": {"openchamber": {"notify": "loud"}},
+            }})
+            .to_string(),
+        )
+        .await
+        .unwrap();
+
+        let cache = AgentPreferenceCache::new(config.paths.clone());
+        assert_eq!(
+            cache.get("watcher").await,
+            Some(prefs(Some(NotifyMode::Never), None))
+        );
+        // opencode.json overrides the agent file, as in OpenCode's merge order
+        assert_eq!(
+            cache.get("team/deploy").await,
+            Some(prefs(Some(NotifyMode::Always), Some("Hero")))
+        );
+        assert_eq!(cache.get("plain").await, None);
+        assert_eq!(cache.get("broken").await, None);
+        assert_eq!(cache.get("missing").await, None);
+    }
+
+    #[tokio::test]
+    async fn config_changes_show_up_after_invalidation() {
+        let config = TempConfig::new();
+        opencode_config::create_agent(
+            &config.paths,
+            "watcher",
+            &payload(json!({"openchamber": {"notify": "never"}})),
+        )
+        .await
+        .unwrap();
+        let cache = AgentPreferenceCache::new(config.paths.clone());
+        assert_eq!(
+            cache.get("watcher").await,
+            Some(prefs(Some(NotifyMode::Never), None))
+        );
+
+        opencode_config::update_agent(
+            &config.paths,
+            "watcher",
+            &payload(json!({"openchamber": {"notify": "always", "sound": "Hero"}})),
+        )
+        .await
+        .unwrap();
+        assert_eq!(
+            cache.get("watcher").await,
+            Some(prefs(Some(NotifyMode::Never), None)),
+            "cached until invalidated"
+        );
+
+        cache.invalidate().await;
+        assert_eq!(
+            cache.get("watcher").await,
+            Some(prefs(Some(NotifyMode::Always), Some("Hero")))
+        );
+    }
+}
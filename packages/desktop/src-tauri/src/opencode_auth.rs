@@ -0,0 +1,417 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, Result};
+use log::info;
+use serde::Serialize;
+use serde_json::{Map, Value};
+use tokio::{fs, io::AsyncWriteExt};
+
+use crate::opencode_config;
+
+const AUTH_FILE: &str = "auth.json";
+
+/// Kind of credential stored for a provider. Mirrors the `type` values
+/// `opencode auth login` writes; anything else is reported as unknown.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CredentialType {
+    Api,
+    Oauth,
+    WellKnown,
+    Unknown,
+}
+
+/// One provider entry with all secret material left out
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ProviderAuthStatus {
+    pub provider: String,
+    pub credential_type: CredentialType,
+    /// The entry holds a non-empty key or token
+    pub has_credentials: bool,
+    /// OAuth access token expiry (ms since epoch) when recorded
+    pub expires_at: Option<i64>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AuthStatus {
+    pub path: String,
+    pub exists: bool,
+    pub providers: Vec<ProviderAuthStatus>,
+    /// Set when the file exists but could not be read or parsed
+    pub error: Option<String>,
+}
+
+/// OpenCode keeps logins in its data dir: $XDG_DATA_HOME/opencode, else
+/// ~/.local/share/opencode (on every platform)
+pub fn auth_file_path() -> Option<PathBuf> {
+    let data_home = std::env::var("XDG_DATA_HOME")
+        .ok()
+        .map(|value| PathBuf::from(value.trim()))
+        .filter(|path| path.is_absolute())
+        .or_else(|| dirs::home_dir().map(|home| home.join(".local").join("share")))?;
+    Some(data_home.join("opencode").join(AUTH_FILE))
+}
+
+/// Provider ids end up on the `opencode auth login` command line, so they are
+/// held to config-name rules without path separators or a leading dash
+pub fn validate_provider_id(raw: &str) -> Result<String> {
+    let provider = opencode_config::validate_config_name(raw)?;
+    if provider.contains('/') || provider.starts_with('-') {
+        return Err(anyhow!("Invalid provider id"));
+    }
+    Ok(provider)
+}
+
+fn non_empty(entry: &Map<String, Value>, key: &str) -> bool {
+    entry
+        .get(key)
+        .and_then(Value::as_str)
+        .is_some_and(|value| !value.trim().is_empty())
+}
+
+/// Describe one entry. Tolerates older layouts: bare key strings and objects
+/// without a `type` field.
+pub fn describe_entry(provider: &str, entry: &Value) -> ProviderAuthStatus {
+    let (credential_type, has_credentials, expires_at) = match entry {
+        Value::String(key) => (CredentialType::Api, !key.trim().is_empty(), None),
+        Value::Object(entry) => {
+            let credential_type = match entry.get("type").and_then(Value::as_str) {
+                Some("api") => CredentialType::Api,
+                Some("oauth") => CredentialType::Oauth,
+                Some("wellknown") => CredentialType::WellKnown,
+                Some(_) => CredentialType::Unknown,
+                None if entry.contains_key("refresh") || entry.contains_key("access") => {
+                    CredentialType::Oauth
+                }
+                None if entry.contains_key("key") => CredentialType::Api,
+                None => CredentialType::Unknown,
+            };
+            let has_credentials = match credential_type {
+                CredentialType::Api => non_empty(entry, "key"),
+                CredentialType::Oauth => non_empty(entry, "refresh") || non_empty(entry, "access"),
+                CredentialType::WellKnown => non_empty(entry, "key") || non_empty(entry, "token"),
+                CredentialType::Unknown => entry
+                    .iter()
+                    .any(|(key, _)| key != "type" && non_empty(entry, key)),
+            };
+            let expires_at = (credential_type == CredentialType::Oauth)
+                .then(|| entry.get("expires").and_then(Value::as_i64))
+                .flatten();
+            (credential_type, has_credentials, expires_at)
+        }
+        _ => (CredentialType::Unknown, false, None),
+    };
+
+    ProviderAuthStatus {
+        provider: provider.to_string(),
+        credential_type,
+        has_credentials,
+        expires_at,
+    }
+}
+
+/// Parse the auth file contents; an empty file has no providers
+pub fn parse_auth_file(content: &str) -> Result<Vec<ProviderAuthStatus>> {
+    if content.trim().is_empty() {
+        return Ok(Vec::new());
+    }
+    let value: Value =
+        serde_json::from_str(content).map_err(|e| anyhow!("Failed to parse auth file: {}", e))?;
+    let Value::Object(entries) = value else {
+        return Err(anyhow!("Auth file is not a JSON object"));
+    };
+
+    let mut providers: Vec<ProviderAuthStatus> = entries
+        .iter()
+        .filter(|(_, entry)| !entry.is_null())
+        .map(|(provider, entry)| describe_entry(provider, entry))
+        .collect();
+    providers.sort_by(|a, b| a.provider.cmp(&b.provider));
+    Ok(providers)
+}
+
+pub async fn auth_status(path: &Path) -> AuthStatus {
+    let mut status = AuthStatus {
+        path: path.to_string_lossy().to_string(),
+        exists: path.is_file(),
+        providers: Vec::new(),
+        error: None,
+    };
+    if !status.exists {
+        return status;
+    }
+    match fs::read_to_string(path).await {
+        Ok(content) => match parse_auth_file(&content) {
+            Ok(providers) => status.providers = providers,
+            Err(err) => status.error = Some(err.to_string()),
+        },
+        Err(err) => status.error = Some(format!("Failed to read auth file: {}", err)),
+    }
+    status
+}
+
+/// Delete one provider's entry after backing the file up. Returns false when
+/// the provider had no entry.
+pub async fn remove_provider(path: &Path, provider: &str) -> Result<bool> {
+    if !path.is_file() {
+        return Ok(false);
+    }
+    let content = fs::read_to_string(path).await?;
+    let mut value: Value = if content.trim().is_empty() {
+        Value::Object(Map::new())
+    } else {
+        serde_json::from_str(&content).map_err(|e| anyhow!("Failed to parse auth file: {}", e))?
+    };
+    let Some(entries) = value.as_object_mut() else {
+        return Err(anyhow!("Auth file is not a JSON object"));
+    };
+    if entries.remove(provider).is_none() {
+        return Ok(false);
+    }
+
+    let backup_path = path.with_file_name(format!("{AUTH_FILE}.openchamber.backup"));
+    // fs::copy keeps the source permissions, so the backup stays owner-only
+    fs::copy(path, &backup_path).await?;
+    info!("Created auth backup: {}", backup_path.display());
+
+    // Created owner-only so the remaining secrets are never briefly world-readable
+    let temp_path = path.with_file_name(format!("{AUTH_FILE}.openchamber.tmp"));
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    options.mode(0o600);
+    let mut file = options.open(&temp_path).await?;
+    file.write_all(serde_json::to_string_pretty(&value)?.as_bytes())
+        .await?;
+    file.flush().await?;
+    drop(file);
+    fs::rename(&temp_path, path).await?;
+    info!(
+        "[desktop:config] Removed OpenCode credentials for {}",
+        provider
+    );
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// auth.json as written by current OpenCode releases
+    const CURRENT_FIXTURE: &str = r#"{
+  "openai": { "type": "api", "key": "sk-live-secret-1" },
+  "anthropic": {
+    "type": "oauth",
+    "refresh": "rt-secret-2",
+    "access": "at-secret-3",
+    "expires": 1767225600000
+  },
+  "enterprise": { "type": "wellknown", "key": "WK_TOKEN", "token": "wk-secret-4" },
+  "copilot": { "type": "oauth", "refresh": "", "access": "" }
+}"#;
+
+    /// Older layouts: bare keys, objects without `type`, cleared entries
+    const LEGACY_FIXTURE: &str = r#"{
+  "groq": "gsk-secret-5",
+  "mistral": { "key": "mk-secret-6" },
+  "github": { "refresh": "rt-secret-7", "expires": 1700000000000 },
+  "removed": null,
+  "blank": ""
+}"#;
+
+    /// A provider type this version does not know yet
+    const FUTURE_FIXTURE: &str = r#"{
+  "vertex": { "type": "service-account", "credentials": "sa-secret-8" },
+  "empty": { "type": "service-account" },
+  "odd": 42
+}"#;
+
+    /// An auth file under the system temp dir, removed on drop
+    struct TempAuth {
+        dir: PathBuf,
+        path: PathBuf,
+    }
+
+    impl TempAuth {
+        fn with(content: &str) -> Self {
+            let dir =
+                std::env::temp_dir().join(format!("openchamber-auth-{}", uuid::Uuid::new_v4()));
+            std::fs::create_dir_all(&dir).unwrap();
+            let path = dir.join(AUTH_FILE);
+            std::fs::write(&path, content).unwrap();
+            Self { dir, path }
+        }
+
+        fn backup(&self) -> PathBuf {
+            self.dir.join(format!("{AUTH_FILE}.openchamber.backup"))
+        }
+
+        fn entries(&self) -> Map<String, Value> {
+            serde_json::from_str(&std::fs::read_to_string(&self.path).unwrap()).unwrap()
+        }
+    }
+
+    impl Drop for TempAuth {
+        fn drop(&mut self) {
+            std::fs::remove_dir_all(&self.dir).ok();
+        }
+    }
+
+    fn status(
+        provider: &str,
+        credential_type: CredentialType,
+        has_credentials: bool,
+        expires_at: Option<i64>,
+    ) -> ProviderAuthStatus {
+        ProviderAuthStatus {
+            provider: provider.to_string(),
+            credential_type,
+            has_credentials,
+            expires_at,
+        }
+    }
+
+    #[test]
+    fn current_auth_files_are_described() {
+        assert_eq!(
+            parse_auth_file(CURRENT_FIXTURE).unwrap(),
+            vec![
+                status(
+                    "anthropic",
+                    CredentialType::Oauth,
+                    true,
+                    Some(1767225600000)
+                ),
+                status("copilot", CredentialType::Oauth, false, None),
+                status("enterprise", CredentialType::WellKnown, true, None),
+                status("openai", CredentialType::Api, true, None),
+            ]
+        );
+    }
+
+    #[test]
+    fn legacy_auth_files_are_described() {
+        assert_eq!(
+            parse_auth_file(LEGACY_FIXTURE).unwrap(),
+            vec![
+                status("blank", CredentialType::Api, false, None),
+                status("github", CredentialType::Oauth, true, Some(1700000000000)),
+                status("groq", CredentialType::Api, true, None),
+                status("mistral", CredentialType::Api, true, None),
+            ]
+        );
+    }
+
+    #[test]
+    fn unknown_credential_types_are_reported_as_such() {
+        assert_eq!(
+            parse_auth_file(FUTURE_FIXTURE).unwrap(),
+            vec![
+                status("empty", CredentialType::Unknown, false, None),
+                status("odd", CredentialType::Unknown, false, None),
+                status("vertex", CredentialType::Unknown, true, None),
+            ]
+        );
+    }
+
+    #[test]
+    fn no_secret_reaches_the_status() {
+        for fixture in [CURRENT_FIXTURE, LEGACY_FIXTURE, FUTURE_FIXTURE] {
+            let reported = serde_json::to_string(&parse_auth_file(fixture).unwrap()).unwrap();
+            assert!(!reported.contains("secret"), "{reported}");
+            assert!(!reported.contains("WK_TOKEN"), "{reported}");
+        }
+    }
+
+    #[test]
+    fn malformed_auth_files_are_errors() {
+        assert!(parse_auth_file("").unwrap().is_empty());
+        assert!(parse_auth_file("  \n").unwrap().is_empty());
+        assert!(parse_auth_file("{ \"openai\": ")
+            .unwrap_err()
+            .to_string()
+            .contains("Failed to parse auth file"));
+        assert_eq!(
+            parse_auth_file("[]").unwrap_err().to_string(),
+            "Auth file is not a JSON object"
+        );
+    }
+
+    #[test]
+    fn provider_ids_are_safe_for_the_command_line() {
+        assert_eq!(validate_provider_id(" openai ").unwrap(), "openai");
+        assert_eq!(
+            validate_provider_id("github-copilot").unwrap(),
+            "github-copilot"
+        );
+        assert!(validate_provider_id("").is_err());
+        assert!(validate_provider_id("team/openai").is_err());
+        assert!(validate_provider_id("--help").is_err());
+        assert!(validate_provider_id("../auth").is_err());
+    }
+
+    #[tokio::test]
+    async fn status_reports_missing_and_unreadable_files() {
+        let auth = TempAuth::with(CURRENT_FIXTURE);
+        let found = auth_status(&auth.path).await;
+        assert!(found.exists);
+        assert_eq!(found.providers.len(), 4);
+        assert_eq!(found.error, None);
+
+        let missing = auth_status(&auth.dir.join("missing.json")).await;
+        assert!(!missing.exists);
+        assert!(missing.providers.is_empty());
+        assert_eq!(missing.error, None);
+
+        let broken = TempAuth::with("not json");
+        let broken = auth_status(&broken.path).await;
+        assert!(broken.exists);
+        assert!(broken.providers.is_empty());
+        assert!(broken.error.unwrap().contains("Failed to parse auth file"));
+    }
+
+    #[tokio::test]
+    async fn removing_a_provider_keeps_a_backup_and_the_other_logins() {
+        let auth = TempAuth::with(CURRENT_FIXTURE);
+
+        assert!(remove_provider(&auth.path, "openai").await.unwrap());
+
+        let entries = auth.entries();
+        assert!(!entries.contains_key("openai"));
+        assert_eq!(entries.len(), 3);
+        assert_eq!(entries["anthropic"]["refresh"], "rt-secret-2");
+        assert_eq!(
+            std::fs::read_to_string(auth.backup()).unwrap(),
+            CURRENT_FIXTURE
+        );
+        assert!(!auth
+            .dir
+            .join(format!("{AUTH_FILE}.openchamber.tmp"))
+            .exists());
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = std::fs::metadata(&auth.path).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+    }
+
+    #[tokio::test]
+    async fn removing_an_unknown_provider_changes_nothing() {
+        let auth = TempAuth::with(LEGACY_FIXTURE);
+
+        assert!(!remove_provider(&auth.path, "openai").await.unwrap());
+        assert_eq!(std::fs::read_to_string(&auth.path).unwrap(), LEGACY_FIXTURE);
+        assert!(!auth.backup().exists());
+
+        assert!(!remove_provider(&auth.dir.join("missing.json"), "groq")
+            .await
+            .unwrap());
+        let empty = TempAuth::with("");
+        assert!(!remove_provider(&empty.path, "groq").await.unwrap());
+        let broken = TempAuth::with("[1]");
+        assert!(remove_provider(&broken.path, "groq").await.is_err());
+    }
+}
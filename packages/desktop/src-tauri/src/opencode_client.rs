@@ -0,0 +1,579 @@
+use std::{
+    fmt,
+    pin::Pin,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use futures_util::TryStreamExt;
+use log::{debug, warn};
+use reqwest::{Method, RequestBuilder, StatusCode, Url};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::{Map, Value};
+use tokio::io::{AsyncBufRead, AsyncBufReadExt};
+use tokio_util::io::StreamReader;
+
+use crate::{opencode_manager::OpenCodeManager, upstream_pool::UpstreamPool};
+
+/// How long requests wait for OpenCode to come up before failing with NotReady
+const DEFAULT_READY_WAIT: Duration = Duration::from_secs(5);
+const READY_POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+#[derive(Debug)]
+pub enum OpenCodeError {
+    /// OpenCode isn't running or didn't accept connections in time
+    NotReady,
+    /// OpenCode answered with a non-success status
+    Status {
+        status: StatusCode,
+        body: String,
+    },
+    Transport(reqwest::Error),
+    Decode(String),
+}
+
+impl fmt::Display for OpenCodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NotReady => write!(f, "OpenCode is not ready"),
+            Self::Status { status, body } if body.is_empty() => {
+                write!(f, "OpenCode returned {}", status)
+            }
+            Self::Status { status, body } => write!(f, "OpenCode returned {}: {}", status, body),
+            Self::Transport(err) => write!(f, "OpenCode request failed: {}", err),
+            Self::Decode(err) => write!(f, "Unexpected OpenCode response: {}", err),
+        }
+    }
+}
+
+impl std::error::Error for OpenCodeError {}
+
+impl From<reqwest::Error> for OpenCodeError {
+    fn from(err: reqwest::Error) -> Self {
+        Self::Transport(err)
+    }
+}
+
+pub type OpenCodeResult<T> = Result<T, OpenCodeError>;
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct SessionInfo {
+    pub id: String,
+    #[serde(default)]
+    pub title: Option<String>,
+    #[serde(default)]
+    pub time: Option<SessionTime>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct SessionTime {
+    /// Milliseconds since epoch
+    #[serde(default)]
+    pub updated: Option<f64>,
+}
+
+impl SessionInfo {
+    /// Trimmed title, None when unset or blank
+    pub fn display_title(&self) -> Option<&str> {
+        self.title
+            .as_deref()
+            .map(str::trim)
+            .filter(|s| !s.is_empty())
+    }
+
+    pub fn updated_at(&self) -> f64 {
+        self.time
+            .as_ref()
+            .and_then(|time| time.updated)
+            .unwrap_or(0.0)
+    }
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct MessageInfo {
+    pub id: String,
+    #[serde(default)]
+    pub role: Option<String>,
+    #[serde(default)]
+    pub finish: Option<String>,
+    /// Agent a user message was sent to
+    #[serde(default)]
+    pub agent: Option<String>,
+    /// Agent that produced an assistant message
+    #[serde(default)]
+    pub mode: Option<String>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct MessageWithParts {
+    pub info: MessageInfo,
+    #[serde(default)]
+    pub parts: Vec<Value>,
+}
+
+impl MessageWithParts {
+    /// All text parts joined by blank lines
+    pub fn text(&self) -> String {
+        self.text_parts().collect::<Vec<_>>().join("\n\n")
+    }
+
+    fn text_parts(&self) -> impl DoubleEndedIterator<Item = &str> {
+        self.parts
+            .iter()
+            .filter(|part| part.get("type").and_then(Value::as_str) == Some("text"))
+            .filter_map(|part| part.get("text").and_then(Value::as_str))
+            .map(str::trim)
+            .filter(|text| !text.is_empty())
+    }
+
+    /// Last non-empty text part, trimmed
+    pub fn last_text(&self) -> Option<&str> {
+        self.text_parts().next_back()
+    }
+}
+
+/// The parts of `/config` internal consumers read
+#[derive(Clone, Debug, Deserialize)]
+pub struct OpenCodeConfig {
+    #[serde(default)]
+    pub model: Option<String>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct ProviderInfo {
+    pub id: String,
+}
+
+/// `/config/providers`: providers with credentials and their default model ids
+#[derive(Clone, Debug, Deserialize)]
+pub struct ProviderList {
+    #[serde(default)]
+    pub providers: Vec<ProviderInfo>,
+    #[serde(default)]
+    pub default: Map<String, Value>,
+}
+
+/// One event from OpenCode's `/event` stream
+#[derive(Clone, Debug, Deserialize)]
+pub struct OpenCodeEvent {
+    #[serde(rename = "type")]
+    pub event_type: String,
+    #[serde(default)]
+    pub properties: Value,
+}
+
+/// Typed access to the OpenCode API for code inside the desktop shell. Builds
+/// URLs from the manager's current port, API prefix and working directory so
+/// callers don't hand-roll them, and waits briefly for OpenCode to come up.
+#[derive(Clone)]
+pub struct OpenCodeClient {
+    pool: Arc<UpstreamPool>,
+    opencode: Arc<OpenCodeManager>,
+    /// Overrides the manager's working directory for the `directory` query
+    directory: Option<String>,
+    timeout: Option<Duration>,
+    ready_wait: Duration,
+}
+
+impl OpenCodeClient {
+    pub fn new(pool: Arc<UpstreamPool>, opencode: Arc<OpenCodeManager>) -> Self {
+        Self {
+            pool,
+            opencode,
+            directory: None,
+            timeout: None,
+            ready_wait: DEFAULT_READY_WAIT,
+        }
+    }
+
+    /// Per-request timeout; also caps the wait for OpenCode to become ready
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self.ready_wait = self.ready_wait.min(timeout);
+        self
+    }
+
+    /// Scope requests to `directory` instead of the current working directory
+    pub fn with_directory(mut self, directory: impl Into<String>) -> Self {
+        self.directory = Some(directory.into());
+        self
+    }
+
+    /// Base URL including the API prefix, e.g. `http://127.0.0.1:4096/api`
+    pub fn base_url(&self) -> OpenCodeResult<Url> {
+        let port = self
+            .opencode
+            .current_port()
+            .ok_or(OpenCodeError::NotReady)?;
+        let url = format!(
+            "http://127.0.0.1:{port}{}",
+            self.opencode.api_prefix().trim_end_matches('/')
+        );
+        Url::parse(&url).map_err(|err| OpenCodeError::Decode(err.to_string()))
+    }
+
+    /// URL for `path` scoped to the client's directory
+    pub fn url(&self, path: &str) -> OpenCodeResult<Url> {
+        let mut url = self.base_url()?;
+        let joined = format!("{}{}", url.path().trim_end_matches('/'), path);
+        url.set_path(&joined);
+        let directory = match &self.directory {
+            Some(directory) => Some(directory.clone()),
+            None => self
+                .opencode
+                .get_working_directory()
+                .to_str()
+                .map(str::to_string),
+        };
+        if let Some(dir) = directory {
+            url.query_pairs_mut().append_pair("directory", &dir);
+        }
+        Ok(url)
+    }
+
+    pub async fn get_session(&self, id: &str) -> OpenCodeResult<SessionInfo> {
+        self.get_json(&format!("/session/{id}")).await
+    }
+
+    pub async fn list_sessions(&self) -> OpenCodeResult<Vec<SessionInfo>> {
+        self.get_json("/session").await
+    }
+
+    pub async fn create_session(&self) -> OpenCodeResult<SessionInfo> {
+        let response = self
+            .send(Method::POST, "/session", |request| {
+                request.json(&serde_json::json!({}))
+            })
+            .await?;
+        decode(response).await
+    }
+
+    pub async fn list_messages(&self, session_id: &str) -> OpenCodeResult<Vec<MessageWithParts>> {
+        self.get_json(&format!("/session/{session_id}/message"))
+            .await
+    }
+
+    pub async fn get_message(
+        &self,
+        session_id: &str,
+        message_id: &str,
+    ) -> OpenCodeResult<MessageWithParts> {
+        self.get_json(&format!("/session/{session_id}/message/{message_id}"))
+            .await
+    }
+
+    pub async fn get_config(&self) -> OpenCodeResult<OpenCodeConfig> {
+        self.get_json("/config").await
+    }
+
+    pub async fn get_providers(&self) -> OpenCodeResult<ProviderList> {
+        self.get_json("/config/providers").await
+    }
+
+    /// Send a prompt to a session; `body` is OpenCode's message payload (parts, model, agent)
+    pub async fn post_message<B: Serialize + ?Sized>(
+        &self,
+        session_id: &str,
+        body: &B,
+    ) -> OpenCodeResult<MessageWithParts> {
+        let path = format!("/session/{session_id}/message");
+        let response = self
+            .send(Method::POST, &path, |request| request.json(body))
+            .await?;
+        decode(response).await
+    }
+
+    /// Open the server-sent event stream. Not subject to the request timeout.
+    pub async fn event_stream(&self) -> OpenCodeResult<EventStream> {
+        let url = self.ready_url("/event").await?;
+        debug!("[desktop:opencode] Connecting SSE: {url}");
+        let response = self
+            .pool
+            .decoding()
+            .get(url)
+            .header("accept", "text/event-stream")
+            .header("accept-encoding", "identity")
+            .send()
+            .await?;
+        let response = check_status(response).await?;
+
+        let stream = response.bytes_stream().map_err(std::io::Error::other);
+        Ok(EventStream {
+            reader: Box::pin(StreamReader::new(stream)),
+            buf: Vec::new(),
+            data_lines: Vec::new(),
+        })
+    }
+
+    async fn get_json<T: DeserializeOwned>(&self, path: &str) -> OpenCodeResult<T> {
+        let response = self.send(Method::GET, path, |request| request).await?;
+        decode(response).await
+    }
+
+    /// Wait for OpenCode to report a port, up to `ready_wait`
+    async fn ready_url(&self, path: &str) -> OpenCodeResult<Url> {
+        let deadline = Instant::now() + self.ready_wait;
+        loop {
+            if self.opencode.is_ready() {
+                if let Ok(url) = self.url(path) {
+                    return Ok(url);
+                }
+            }
+            if Instant::now() + READY_POLL_INTERVAL > deadline {
+                return Err(OpenCodeError::NotReady);
+            }
+            tokio::time::sleep(READY_POLL_INTERVAL).await;
+        }
+    }
+
+    /// Send a request, retrying connection failures while OpenCode is (re)starting
+    async fn send(
+        &self,
+        method: Method,
+        path: &str,
+        build: impl Fn(RequestBuilder) -> RequestBuilder,
+    ) -> OpenCodeResult<reqwest::Response> {
+        let deadline = Instant::now() + self.ready_wait;
+        loop {
+            let url = self.ready_url(path).await?;
+            let mut request = build(self.pool.decoding().request(method.clone(), url));
+            if let Some(timeout) = self.timeout {
+                request = request.timeout(timeout);
+            }
+            match request.send().await {
+                Ok(response) => return check_status(response).await,
+                Err(err) if err.is_connect() && Instant::now() < deadline => {
+                    debug!("[desktop:opencode] {path} not reachable yet: {err}");
+                    tokio::time::sleep(READY_POLL_INTERVAL).await;
+                }
+                Err(err) if err.is_connect() => return Err(OpenCodeError::NotReady),
+                Err(err) => return Err(err.into()),
+            }
+        }
+    }
+}
+
+async fn check_status(response: reqwest::Response) -> OpenCodeResult<reqwest::Response> {
+    let status = response.status();
+    if status.is_success() {
+        return Ok(response);
+    }
+    let body = response.text().await.unwrap_or_default();
+    Err(OpenCodeError::Status {
+        status,
+        body: body.trim().to_string(),
+    })
+}
+
+async fn decode<T: DeserializeOwned>(response: reqwest::Response) -> OpenCodeResult<T> {
+    let bytes = response.bytes().await?;
+    serde_json::from_slice(&bytes).map_err(|err| OpenCodeError::Decode(err.to_string()))
+}
+
+/// Parsed `/event` stream; yields one event per SSE message
+pub struct EventStream {
+    reader: Pin<Box<dyn AsyncBufRead + Send>>,
+    buf: Vec<u8>,
+    data_lines: Vec<String>,
+}
+
+impl EventStream {
+    /// Next event, or None when the server closed the stream.
+    /// Malformed messages are logged and skipped.
+    pub async fn next_event(&mut self) -> std::io::Result<Option<OpenCodeEvent>> {
+        while let Some(raw) = self.next_data().await? {
+            match serde_json::from_str::<OpenCodeEvent>(&raw) {
+                Ok(event) => return Ok(Some(event)),
+                Err(err) => {
+                    warn!("[desktop:opencode] Failed to parse SSE data: {err}; raw={raw}");
+                }
+            }
+        }
+        Ok(None)
+    }
+
+    /// Data of the next SSE message, unparsed, or None when the server closed
+    /// the stream. For forwarding events without re-encoding them.
+    pub async fn next_data(&mut self) -> std::io::Result<Option<String>> {
+        loop {
+            self.buf.clear();
+            if self.reader.read_until(b'\n', &mut self.buf).await? == 0 {
+                return Ok(None);
+            }
+
+            let line = match std::str::from_utf8(&self.buf) {
+                Ok(s) => s.trim_end_matches(&['\r', '\n'][..]).to_string(),
+                Err(err) => {
+                    warn!("[desktop:opencode] Non-UTF8 SSE chunk: {err}");
+                    continue;
+                }
+            };
+
+            if line.is_empty() {
+                if self.data_lines.is_empty() {
+                    continue;
+                }
+                let raw = self.data_lines.join("\n");
+                self.data_lines.clear();
+                return Ok(Some(raw));
+            }
+
+            if let Some(rest) = line.strip_prefix("data:") {
+                self.data_lines.push(rest.trim_start().to_string());
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{
+        extract::{Path, RawQuery},
+        response::IntoResponse,
+        routing::{get, post},
+        Json, Router,
+    };
+    use serde_json::json;
+    use std::path::PathBuf;
+
+    const EVENTS: &str = concat!(
+        "data: {\"type\":\"server.connected\"}\n\n",
+        "data: not json\n\n",
+        ": keep-alive\n\n",
+        "data: {\"type\":\"session.status\",\n",
+        "data: \"properties\":{\"sessionID\":\"ses_1\"}}\n\n",
+    );
+
+    /// Serves the few OpenCode endpoints the client is tested against
+    async fn mock_opencode() -> u16 {
+        let app = Router::new()
+            .route(
+                "/session",
+                get(|RawQuery(query): RawQuery| async move {
+                    // Echo the query so tests can see the directory scoping
+                    Json(json!([{ "id": "ses_1", "title": query }]))
+                }),
+            )
+            .route(
+                "/session/{id}",
+                get(|Path(id): Path<String>| async move {
+                    match id.as_str() {
+                        "missing" => (StatusCode::NOT_FOUND, "no such session\n").into_response(),
+                        "garbled" => "not json".into_response(),
+                        _ => Json(json!({ "id": id, "time": { "updated": 5.0 } })).into_response(),
+                    }
+                }),
+            )
+            .route(
+                "/session/{id}/message",
+                post(|Json(body): Json<Value>| async move {
+                    Json(json!({
+                        "info": { "id": "msg_1", "role": "assistant" },
+                        "parts": [
+                            { "type": "text", "text": "thinking" },
+                            { "type": "tool" },
+                            { "type": "text", "text": body["parts"][0]["text"] },
+                        ],
+                    }))
+                }),
+            )
+            .route("/event", get(|| async { EVENTS }));
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        tokio::spawn(async move {
+            axum::serve(listener, app).await.ok();
+        });
+        port
+    }
+
+    fn client_for(opencode: OpenCodeManager) -> OpenCodeClient {
+        let opencode = Arc::new(opencode);
+        let pool = Arc::new(UpstreamPool::new(opencode.clone()).unwrap());
+        OpenCodeClient::new(pool, opencode).with_timeout(Duration::from_secs(5))
+    }
+
+    async fn client() -> OpenCodeClient {
+        let port = mock_opencode().await;
+        client_for(OpenCodeManager::ready_on(
+            port,
+            PathBuf::from("/work/project"),
+        ))
+    }
+
+    #[tokio::test]
+    async fn requests_are_scoped_to_the_working_directory() {
+        let client = client().await;
+        let sessions = client.list_sessions().await.unwrap();
+        assert_eq!(
+            sessions[0].title.as_deref(),
+            Some("directory=%2Fwork%2Fproject")
+        );
+
+        let sessions = client
+            .with_directory("/other")
+            .list_sessions()
+            .await
+            .unwrap();
+        assert_eq!(sessions[0].title.as_deref(), Some("directory=%2Fother"));
+    }
+
+    #[tokio::test]
+    async fn responses_are_decoded() {
+        let client = client().await;
+        let session = client.get_session("ses_2").await.unwrap();
+        assert_eq!(session.id, "ses_2");
+        assert_eq!(session.updated_at(), 5.0);
+        assert_eq!(session.display_title(), None);
+
+        let reply = client
+            .post_message(
+                "ses_2",
+                &json!({ "parts": [{ "type": "text", "text": "done" }] }),
+            )
+            .await
+            .unwrap();
+        assert_eq!(reply.info.role.as_deref(), Some("assistant"));
+        assert_eq!(reply.last_text(), Some("done"));
+        assert_eq!(reply.text(), "thinking\n\ndone");
+    }
+
+    #[tokio::test]
+    async fn failures_keep_the_status_and_body() {
+        let client = client().await;
+        match client.get_session("missing").await {
+            Err(OpenCodeError::Status { status, body }) => {
+                assert_eq!(status, StatusCode::NOT_FOUND);
+                assert_eq!(body, "no such session");
+            }
+            other => panic!("expected a status error, got {other:?}"),
+        }
+        assert!(matches!(
+            client.get_session("garbled").await,
+            Err(OpenCodeError::Decode(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn event_stream_skips_malformed_messages() {
+        let client = client().await;
+        let mut events = client.event_stream().await.unwrap();
+
+        let first = events.next_event().await.unwrap().unwrap();
+        assert_eq!(first.event_type, "server.connected");
+        let second = events.next_event().await.unwrap().unwrap();
+        assert_eq!(second.event_type, "session.status");
+        assert_eq!(second.properties["sessionID"], "ses_1");
+        assert!(events.next_event().await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn requests_fail_fast_while_opencode_is_down() {
+        let opencode = OpenCodeManager::new_with_directory(Some(PathBuf::from("/work/project")));
+        let client = client_for(opencode).with_timeout(Duration::from_millis(300));
+        assert!(matches!(
+            client.list_sessions().await,
+            Err(OpenCodeError::NotReady)
+        ));
+    }
+}
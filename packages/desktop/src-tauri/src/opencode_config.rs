@@ -6,11 +6,18 @@ use serde::Serialize;
 use serde_json::{Map, Value};
 use std::collections::HashMap;
 use std::path::{Path, PathBuf};
+use std::sync::Arc;
 use tokio::fs;
+use tokio::sync::{Mutex, MutexGuard};
+
+use crate::config_journal::{default_journal_dir, ConfigJournal};
 
 static PROMPT_FILE_PATTERN: Lazy<Regex> =
     Lazy::new(|| Regex::new(r"(?i)^\{file:(.+)\}$").expect("valid regex"));
 
+/// Maximum length of an agent/command name accepted from the config routes
+pub const MAX_CONFIG_NAME_LENGTH: usize = 128;
+
 #[derive(Debug, Serialize)]
 #[serde(rename_all = "camelCase")]
 pub struct SourceInfo {
@@ -26,40 +33,151 @@ pub struct ConfigSources {
     pub json: SourceInfo,
 }
 
-/// Get OpenCode config directory path
-fn get_config_dir() -> PathBuf {
+/// Resolved locations of the OpenCode configuration files.
+/// Passed explicitly so callers (and tests) can point the editor at any directory.
+#[derive(Clone, Debug)]
+pub struct ConfigPaths {
+    config_dir: PathBuf,
+    /// Serializes read-modify-write cycles on opencode.json and the .md sources
+    write_lock: Arc<Mutex<()>>,
+    /// Records multi-file mutations so an interrupted one can be detected and rolled back
+    journal: ConfigJournal,
+}
+
+impl ConfigPaths {
+    /// Edit the config in `config_dir`, journaling mutations to `journal_dir`
+    pub fn new(config_dir: PathBuf, journal_dir: PathBuf) -> Self {
+        Self {
+            config_dir,
+            write_lock: Arc::new(Mutex::new(())),
+            journal: ConfigJournal::new(journal_dir),
+        }
+    }
+
+    pub fn journal(&self) -> &ConfigJournal {
+        &self.journal
+    }
+
+    /// Hold for the whole read-modify-write of a mutation so concurrent edits can't drop each other
+    async fn lock(&self) -> MutexGuard<'_, ()> {
+        self.write_lock.lock().await
+    }
+
+    /// Resolve the config directory the same way OpenCode does:
+    /// OPENCODE_CONFIG_DIR, then $XDG_CONFIG_HOME/opencode, then ~/.config/opencode.
+    /// Mutations are journaled where startup recovery looks for them.
+    pub fn from_env() -> Self {
+        Self::new(env_config_dir(), default_journal_dir())
+    }
+
+    /// Get OpenCode config directory path
+    pub fn config_dir(&self) -> &Path {
+        &self.config_dir
+    }
+
+    /// Get agent directory path
+    pub fn agent_dir(&self) -> PathBuf {
+        self.config_dir.join("agent")
+    }
+
+    /// Get command directory path
+    pub fn command_dir(&self) -> PathBuf {
+        self.config_dir.join("command")
+    }
+
+    /// Get config file path
+    pub fn config_file(&self) -> PathBuf {
+        self.config_dir.join("opencode.json")
+    }
+}
+
+fn env_config_dir() -> PathBuf {
+    if let Ok(value) = std::env::var("OPENCODE_CONFIG_DIR") {
+        let trimmed = value.trim();
+        if !trimmed.is_empty() {
+            return PathBuf::from(trimmed);
+        }
+    }
+
+    if let Ok(value) = std::env::var("XDG_CONFIG_HOME") {
+        let trimmed = value.trim();
+        if !trimmed.is_empty() && Path::new(trimmed).is_absolute() {
+            return PathBuf::from(trimmed).join("opencode");
+        }
+    }
+
     dirs::home_dir()
         .expect("Cannot determine home directory")
         .join(".config")
         .join("opencode")
 }
 
-/// Get agent directory path
-fn get_agent_dir() -> PathBuf {
-    get_config_dir().join("agent")
-}
+/// Ensure required directories exist
+async fn ensure_dirs(paths: &ConfigPaths) -> Result<()> {
+    fs::create_dir_all(paths.config_dir()).await?;
+    fs::create_dir_all(paths.agent_dir()).await?;
+    fs::create_dir_all(paths.command_dir()).await?;
 
-/// Get command directory path
-fn get_command_dir() -> PathBuf {
-    get_config_dir().join("command")
+    Ok(())
 }
 
-/// Get config file path
-fn get_config_file() -> PathBuf {
-    get_config_dir().join("opencode.json")
+/// Decode percent-encoded sequences in a URL path segment
+fn percent_decode(input: &str) -> Result<String> {
+    let bytes = input.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut index = 0;
+
+    while index < bytes.len() {
+        if bytes[index] == b'%' {
+            let hex = bytes
+                .get(index + 1..index + 3)
+                .and_then(|pair| std::str::from_utf8(pair).ok())
+                .and_then(|pair| u8::from_str_radix(pair, 16).ok())
+                .ok_or_else(|| anyhow!("Invalid percent-encoding in name"))?;
+            decoded.push(hex);
+            index += 3;
+        } else {
+            decoded.push(bytes[index]);
+            index += 1;
+        }
+    }
+
+    String::from_utf8(decoded).map_err(|_| anyhow!("Name is not valid UTF-8"))
 }
 
-/// Ensure required directories exist
-async fn ensure_dirs() -> Result<()> {
-    let config_dir = get_config_dir();
-    let agent_dir = get_agent_dir();
-    let command_dir = get_command_dir();
+/// URL-decode and validate an agent/command name taken from a request path.
+/// Names may contain `[A-Za-z0-9._/-]` to allow nested definitions, but never
+/// `..` segments, empty segments, or absolute paths.
+pub fn validate_config_name(raw: &str) -> Result<String> {
+    let decoded = percent_decode(raw.trim())?;
+    let name = decoded.trim();
 
-    fs::create_dir_all(&config_dir).await?;
-    fs::create_dir_all(&agent_dir).await?;
-    fs::create_dir_all(&command_dir).await?;
+    if name.is_empty() {
+        return Err(anyhow!("Name is required"));
+    }
+    if name.len() > MAX_CONFIG_NAME_LENGTH {
+        return Err(anyhow!(
+            "Name exceeds {} characters",
+            MAX_CONFIG_NAME_LENGTH
+        ));
+    }
+    if !name
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '_' | '/' | '-'))
+    {
+        return Err(anyhow!("Name contains invalid characters"));
+    }
+    if name.starts_with('/') || Path::new(name).is_absolute() {
+        return Err(anyhow!("Name must not be an absolute path"));
+    }
+    if name
+        .split('/')
+        .any(|segment| segment.is_empty() || segment == "." || segment == "..")
+    {
+        return Err(anyhow!("Name contains an invalid path segment"));
+    }
 
-    Ok(())
+    Ok(name.to_string())
 }
 
 /// Check if a value is a prompt file reference like {file:./prompts/agent.txt}
@@ -68,7 +186,7 @@ fn is_prompt_file_reference(value: &str) -> bool {
 }
 
 /// Resolve a prompt file reference to an absolute path
-fn resolve_prompt_file_path(reference: &str) -> Option<PathBuf> {
+fn resolve_prompt_file_path(paths: &ConfigPaths, reference: &str) -> Option<PathBuf> {
     let trimmed = reference.trim();
     let captures = PROMPT_FILE_PATTERN.captures(trimmed)?;
     let target = captures.get(1)?.as_str().trim();
@@ -77,17 +195,32 @@ fn resolve_prompt_file_path(reference: &str) -> Option<PathBuf> {
         return None;
     }
 
-    let path = if target.starts_with("./") {
-        get_config_dir().join(&target[2..])
+    let path = if let Some(relative) = target.strip_prefix("./") {
+        paths.config_dir().join(relative)
     } else if Path::new(target).is_absolute() {
         PathBuf::from(target)
     } else {
-        get_config_dir().join(target)
+        paths.config_dir().join(target)
     };
 
     Some(path)
 }
 
+/// Prompt file an update may write through a `{file:...}` reference in opencode.json
+async fn prompt_file_target(
+    paths: &ConfigPaths,
+    section: &str,
+    name: &str,
+    field: &str,
+) -> Option<PathBuf> {
+    let config = read_config(paths).await.ok()?;
+    let reference = config.get(section)?.get(name)?.get(field)?.as_str()?;
+    if !is_prompt_file_reference(reference) {
+        return None;
+    }
+    resolve_prompt_file_path(paths, reference)
+}
+
 /// Write content to a prompt file
 async fn write_prompt_file(file_path: &Path, content: &str) -> Result<()> {
     if let Some(parent) = file_path.parent() {
@@ -160,14 +293,17 @@ fn strip_json_comments(content: &str) -> String {
 }
 
 /// Read opencode.json configuration file
-pub async fn read_config() -> Result<Value> {
-    let config_file = get_config_file();
+pub async fn read_config(paths: &ConfigPaths) -> Result<Value> {
+    read_config_file(&paths.config_file()).await
+}
 
+/// Read any OpenCode config file, e.g. a project's; missing or empty files are `{}`
+pub async fn read_config_file(config_file: &Path) -> Result<Value> {
     if !config_file.exists() {
         return Ok(Value::Object(serde_json::Map::new()));
     }
 
-    let content = fs::read_to_string(&config_file).await?;
+    let content = fs::read_to_string(config_file).await?;
     let normalized = strip_json_comments(&content).trim().to_string();
 
     if normalized.is_empty() {
@@ -178,8 +314,8 @@ pub async fn read_config() -> Result<Value> {
 }
 
 /// Write opencode.json configuration file with backup
-pub async fn write_config(config: &Value) -> Result<()> {
-    let config_file = get_config_file();
+pub async fn write_config(paths: &ConfigPaths, config: &Value) -> Result<()> {
+    let config_file = paths.config_file();
 
     // Create/overwrite single backup before writing
     if config_file.exists() {
@@ -243,6 +379,11 @@ async fn write_md_file(
     let yaml_str = serde_yaml::to_string(frontmatter)?;
     let content = format!("---\n{}---\n\n{}", yaml_str, body);
 
+    // Nested names (e.g. team/reviewer) live in subdirectories
+    if let Some(parent) = file_path.parent() {
+        fs::create_dir_all(parent).await?;
+    }
+
     fs::write(file_path, content).await?;
     info!("Successfully wrote markdown file: {}", file_path.display());
 
@@ -250,10 +391,10 @@ async fn write_md_file(
 }
 
 /// Get information about where agent configuration is stored
-pub async fn get_agent_sources(agent_name: &str) -> Result<ConfigSources> {
-    ensure_dirs().await?;
+pub async fn get_agent_sources(paths: &ConfigPaths, agent_name: &str) -> Result<ConfigSources> {
+    ensure_dirs(paths).await?;
 
-    let md_path = get_agent_dir().join(format!("{}.md", agent_name));
+    let md_path = paths.agent_dir().join(format!("{}.md", agent_name));
     let md_exists = md_path.exists();
 
     let mut md_fields = Vec::new();
@@ -265,7 +406,7 @@ pub async fn get_agent_sources(agent_name: &str) -> Result<ConfigSources> {
         }
     }
 
-    let config = read_config().await?;
+    let config = read_config(paths).await?;
     let json_section = config
         .get("agent")
         .and_then(|v| v.as_object())
@@ -284,7 +425,7 @@ pub async fn get_agent_sources(agent_name: &str) -> Result<ConfigSources> {
         },
         json: SourceInfo {
             exists: json_section.is_some(),
-            path: Some(get_config_file().display().to_string()),
+            path: Some(paths.config_file().display().to_string()),
             fields: json_fields,
         },
     };
@@ -292,18 +433,74 @@ pub async fn get_agent_sources(agent_name: &str) -> Result<ConfigSources> {
     Ok(sources)
 }
 
+/// Frontmatter / opencode.json key holding OpenChamber's own per-agent preferences
+pub const OPENCHAMBER_FIELD: &str = "openchamber";
+
+/// The `openchamber` block of every agent that has one, keyed by agent name.
+/// opencode.json entries override .md frontmatter, matching OpenCode's merge order.
+pub async fn agent_openchamber_blocks(paths: &ConfigPaths) -> Result<HashMap<String, Value>> {
+    let mut blocks = HashMap::new();
+
+    let agent_dir = paths.agent_dir();
+    let mut pending = vec![agent_dir.clone()];
+    while let Some(dir) = pending.pop() {
+        let Ok(mut entries) = fs::read_dir(&dir).await else {
+            continue;
+        };
+        while let Ok(Some(entry)) = entries.next_entry().await {
+            let path = entry.path();
+            if path.is_dir() {
+                pending.push(path);
+                continue;
+            }
+            if path.extension().and_then(|ext| ext.to_str()) != Some("md") {
+                continue;
+            }
+            let Some(name) = path
+                .strip_prefix(&agent_dir)
+                .ok()
+                .map(|relative| relative.with_extension(""))
+                .map(|relative| relative.to_string_lossy().replace('\\', "/"))
+            else {
+                continue;
+            };
+            if let Ok(data) = parse_md_file(&path).await {
+                if let Some(block) = data.frontmatter.get(OPENCHAMBER_FIELD) {
+                    blocks.insert(name, block.clone());
+                }
+            }
+        }
+    }
+
+    let config = read_config(paths).await?;
+    if let Some(agents) = config.get("agent").and_then(Value::as_object) {
+        for (name, agent) in agents {
+            if let Some(block) = agent.get(OPENCHAMBER_FIELD) {
+                blocks.insert(name.clone(), block.clone());
+            }
+        }
+    }
+
+    Ok(blocks)
+}
+
 /// Create new agent as .md file
-pub async fn create_agent(agent_name: &str, config: &HashMap<String, Value>) -> Result<()> {
-    ensure_dirs().await?;
+pub async fn create_agent(
+    paths: &ConfigPaths,
+    agent_name: &str,
+    config: &HashMap<String, Value>,
+) -> Result<()> {
+    let _guard = paths.lock().await;
+    ensure_dirs(paths).await?;
 
-    let md_path = get_agent_dir().join(format!("{}.md", agent_name));
+    let md_path = paths.agent_dir().join(format!("{}.md", agent_name));
 
     // Check if agent already exists
     if md_path.exists() {
         return Err(anyhow!("Agent {} already exists as .md file", agent_name));
     }
 
-    let existing_config = read_config().await?;
+    let existing_config = read_config(paths).await?;
     if let Some(agents) = existing_config.get("agent").and_then(|v| v.as_object()) {
         if agents.contains_key(agent_name) {
             return Err(anyhow!(
@@ -328,10 +525,37 @@ pub async fn create_agent(agent_name: &str, config: &HashMap<String, Value>) ->
 }
 
 /// Update existing agent using field-level logic
-pub async fn update_agent(agent_name: &str, updates: &HashMap<String, Value>) -> Result<()> {
-    ensure_dirs().await?;
+pub async fn update_agent(
+    paths: &ConfigPaths,
+    agent_name: &str,
+    updates: &HashMap<String, Value>,
+) -> Result<()> {
+    let _guard = paths.lock().await;
+    let mut files = vec![
+        paths.agent_dir().join(format!("{}.md", agent_name)),
+        paths.config_file(),
+    ];
+    if updates.contains_key("prompt") {
+        files.extend(prompt_file_target(paths, "agent", agent_name, "prompt").await);
+    }
+    let entry = paths
+        .journal()
+        .begin("update", &format!("agent:{}", agent_name), &files)
+        .await?;
+    let result = apply_agent_update(paths, agent_name, updates).await;
+    entry.finish(&result).await;
+    result
+}
+
+/// Field-level agent update; runs under the write lock inside a journal entry
+async fn apply_agent_update(
+    paths: &ConfigPaths,
+    agent_name: &str,
+    updates: &HashMap<String, Value>,
+) -> Result<()> {
+    ensure_dirs(paths).await?;
 
-    let md_path = get_agent_dir().join(format!("{}.md", agent_name));
+    let md_path = paths.agent_dir().join(format!("{}.md", agent_name));
     let md_exists = md_path.exists();
 
     let mut md_data = if md_exists {
@@ -340,7 +564,7 @@ pub async fn update_agent(agent_name: &str, updates: &HashMap<String, Value>) ->
         None
     };
 
-    let mut config = read_config().await?;
+    let mut config = read_config(paths).await?;
     let mut existing_agent = config
         .get("agent")
         .and_then(|v| v.as_object())
@@ -381,7 +605,7 @@ pub async fn update_agent(agent_name: &str, updates: &HashMap<String, Value>) ->
             } else if let Some(prompt_ref) = existing_agent.get("prompt").and_then(|v| v.as_str())
             {
                 if is_prompt_file_reference(prompt_ref) {
-                    if let Some(prompt_file_path) = resolve_prompt_file_path(prompt_ref) {
+                    if let Some(prompt_file_path) = resolve_prompt_file_path(paths, prompt_ref) {
                         write_prompt_file(&prompt_file_path, &normalized_value).await?;
                     } else {
                         return Err(anyhow!(
@@ -468,7 +692,7 @@ pub async fn update_agent(agent_name: &str, updates: &HashMap<String, Value>) ->
         let agents_obj = agents_entry.as_object_mut().unwrap();
         agents_obj.insert(agent_name.to_string(), Value::Object(existing_agent));
 
-        write_config(&config).await?;
+        write_config(paths, &config).await?;
     }
 
     info!(
@@ -479,9 +703,42 @@ pub async fn update_agent(agent_name: &str, updates: &HashMap<String, Value>) ->
     Ok(())
 }
 
+/// Replace an agent's definition with `definition`, dropping fields it lacks;
+/// returns the definition now on disk
+pub async fn replace_agent(
+    paths: &ConfigPaths,
+    agent_name: &str,
+    definition: &HashMap<String, Value>,
+) -> Result<Value> {
+    let _guard = paths.lock().await;
+    let target = DefinitionTarget {
+        section: "agent",
+        body_field: "prompt",
+        md_path: paths.agent_dir().join(format!("{}.md", agent_name)),
+        name: agent_name,
+    };
+    replace_definition(paths, &target, definition).await
+}
+
 /// Delete agent configuration
-pub async fn delete_agent(agent_name: &str) -> Result<()> {
-    let md_path = get_agent_dir().join(format!("{}.md", agent_name));
+pub async fn delete_agent(paths: &ConfigPaths, agent_name: &str) -> Result<()> {
+    let _guard = paths.lock().await;
+    let files = vec![
+        paths.agent_dir().join(format!("{}.md", agent_name)),
+        paths.config_file(),
+    ];
+    let entry = paths
+        .journal()
+        .begin("delete", &format!("agent:{}", agent_name), &files)
+        .await?;
+    let result = remove_agent(paths, agent_name).await;
+    entry.finish(&result).await;
+    result
+}
+
+/// Remove agent sources; runs under the write lock inside a journal entry
+async fn remove_agent(paths: &ConfigPaths, agent_name: &str) -> Result<()> {
+    let md_path = paths.agent_dir().join(format!("{}.md", agent_name));
     let mut deleted = false;
 
     // 1. Delete .md file if exists
@@ -492,10 +749,10 @@ pub async fn delete_agent(agent_name: &str) -> Result<()> {
     }
 
     // 2. Remove section from opencode.json if exists
-    let mut config = read_config().await?;
+    let mut config = read_config(paths).await?;
     if let Some(agents) = config.get_mut("agent").and_then(|v| v.as_object_mut()) {
         if agents.remove(agent_name).is_some() {
-            write_config(&config).await?;
+            write_config(paths, &config).await?;
             info!("Removed agent from opencode.json: {}", agent_name);
             deleted = true;
         }
@@ -520,7 +777,7 @@ pub async fn delete_agent(agent_name: &str) -> Result<()> {
             .as_object_mut()
             .unwrap()
             .insert(agent_name.to_string(), Value::Object(disable_obj));
-        write_config(&config).await?;
+        write_config(paths, &config).await?;
         info!("Disabled built-in agent: {}", agent_name);
     }
 
@@ -528,10 +785,13 @@ pub async fn delete_agent(agent_name: &str) -> Result<()> {
 }
 
 /// Get information about where command configuration is stored
-pub async fn get_command_sources(command_name: &str) -> Result<ConfigSources> {
-    ensure_dirs().await?;
+pub async fn get_command_sources(
+    paths: &ConfigPaths,
+    command_name: &str,
+) -> Result<ConfigSources> {
+    ensure_dirs(paths).await?;
 
-    let md_path = get_command_dir().join(format!("{}.md", command_name));
+    let md_path = paths.command_dir().join(format!("{}.md", command_name));
     let md_exists = md_path.exists();
 
     let mut md_fields = Vec::new();
@@ -543,7 +803,7 @@ pub async fn get_command_sources(command_name: &str) -> Result<ConfigSources> {
         }
     }
 
-    let config = read_config().await?;
+    let config = read_config(paths).await?;
     let json_section = config
         .get("command")
         .and_then(|v| v.as_object())
@@ -562,7 +822,7 @@ pub async fn get_command_sources(command_name: &str) -> Result<ConfigSources> {
         },
         json: SourceInfo {
             exists: json_section.is_some(),
-            path: Some(get_config_file().display().to_string()),
+            path: Some(paths.config_file().display().to_string()),
             fields: json_fields,
         },
     };
@@ -571,10 +831,15 @@ pub async fn get_command_sources(command_name: &str) -> Result<ConfigSources> {
 }
 
 /// Create new command as .md file
-pub async fn create_command(command_name: &str, config: &HashMap<String, Value>) -> Result<()> {
-    ensure_dirs().await?;
+pub async fn create_command(
+    paths: &ConfigPaths,
+    command_name: &str,
+    config: &HashMap<String, Value>,
+) -> Result<()> {
+    let _guard = paths.lock().await;
+    ensure_dirs(paths).await?;
 
-    let md_path = get_command_dir().join(format!("{}.md", command_name));
+    let md_path = paths.command_dir().join(format!("{}.md", command_name));
 
     // Check if command already exists
     if md_path.exists() {
@@ -584,7 +849,7 @@ pub async fn create_command(command_name: &str, config: &HashMap<String, Value>)
         ));
     }
 
-    let existing_config = read_config().await?;
+    let existing_config = read_config(paths).await?;
     if let Some(commands) = existing_config.get("command").and_then(|v| v.as_object()) {
         if commands.contains_key(command_name) {
             return Err(anyhow!(
@@ -610,12 +875,36 @@ pub async fn create_command(command_name: &str, config: &HashMap<String, Value>)
 
 /// Update existing command using field-level logic
 pub async fn update_command(
+    paths: &ConfigPaths,
     command_name: &str,
     updates: &HashMap<String, Value>,
 ) -> Result<()> {
-    ensure_dirs().await?;
+    let _guard = paths.lock().await;
+    let mut files = vec![
+        paths.command_dir().join(format!("{}.md", command_name)),
+        paths.config_file(),
+    ];
+    if updates.contains_key("template") {
+        files.extend(prompt_file_target(paths, "command", command_name, "template").await);
+    }
+    let entry = paths
+        .journal()
+        .begin("update", &format!("command:{}", command_name), &files)
+        .await?;
+    let result = apply_command_update(paths, command_name, updates).await;
+    entry.finish(&result).await;
+    result
+}
+
+/// Field-level command update; runs under the write lock inside a journal entry
+async fn apply_command_update(
+    paths: &ConfigPaths,
+    command_name: &str,
+    updates: &HashMap<String, Value>,
+) -> Result<()> {
+    ensure_dirs(paths).await?;
 
-    let md_path = get_command_dir().join(format!("{}.md", command_name));
+    let md_path = paths.command_dir().join(format!("{}.md", command_name));
     let md_exists = md_path.exists();
 
     let mut md_data = if md_exists {
@@ -624,7 +913,7 @@ pub async fn update_command(
         None
     };
 
-    let mut config = read_config().await?;
+    let mut config = read_config(paths).await?;
     let mut existing_command = config
         .get("command")
         .and_then(|v| v.as_object())
@@ -665,7 +954,7 @@ pub async fn update_command(
                 continue;
             } else if let Some(template_ref) = existing_command.get("template").and_then(|v| v.as_str()) {
                 if is_prompt_file_reference(template_ref) {
-                    if let Some(template_file_path) = resolve_prompt_file_path(template_ref) {
+                    if let Some(template_file_path) = resolve_prompt_file_path(paths, template_ref) {
                         write_prompt_file(&template_file_path, &normalized_value).await?;
                     } else {
                         return Err(anyhow!(
@@ -752,7 +1041,7 @@ pub async fn update_command(
         let commands_obj = commands_entry.as_object_mut().unwrap();
         commands_obj.insert(command_name.to_string(), Value::Object(existing_command));
 
-        write_config(&config).await?;
+        write_config(paths, &config).await?;
     }
 
     info!(
@@ -763,9 +1052,42 @@ pub async fn update_command(
     Ok(())
 }
 
+/// Replace a command's definition with `definition`, dropping fields it lacks;
+/// returns the definition now on disk
+pub async fn replace_command(
+    paths: &ConfigPaths,
+    command_name: &str,
+    definition: &HashMap<String, Value>,
+) -> Result<Value> {
+    let _guard = paths.lock().await;
+    let target = DefinitionTarget {
+        section: "command",
+        body_field: "template",
+        md_path: paths.command_dir().join(format!("{}.md", command_name)),
+        name: command_name,
+    };
+    replace_definition(paths, &target, definition).await
+}
+
 /// Delete command configuration
-pub async fn delete_command(command_name: &str) -> Result<()> {
-    let md_path = get_command_dir().join(format!("{}.md", command_name));
+pub async fn delete_command(paths: &ConfigPaths, command_name: &str) -> Result<()> {
+    let _guard = paths.lock().await;
+    let files = vec![
+        paths.command_dir().join(format!("{}.md", command_name)),
+        paths.config_file(),
+    ];
+    let entry = paths
+        .journal()
+        .begin("delete", &format!("command:{}", command_name), &files)
+        .await?;
+    let result = remove_command(paths, command_name).await;
+    entry.finish(&result).await;
+    result
+}
+
+/// Remove command sources; runs under the write lock inside a journal entry
+async fn remove_command(paths: &ConfigPaths, command_name: &str) -> Result<()> {
+    let md_path = paths.command_dir().join(format!("{}.md", command_name));
     let mut deleted = false;
 
     // 1. Delete .md file if exists
@@ -776,10 +1098,10 @@ pub async fn delete_command(command_name: &str) -> Result<()> {
     }
 
     // 2. Remove section from opencode.json if exists
-    let mut config = read_config().await?;
+    let mut config = read_config(paths).await?;
     if let Some(commands) = config.get_mut("command").and_then(|v| v.as_object_mut()) {
         if commands.remove(command_name).is_some() {
-            write_config(&config).await?;
+            write_config(paths, &config).await?;
             info!("Removed command from opencode.json: {}", command_name);
             deleted = true;
         }
@@ -792,3 +1114,471 @@ pub async fn delete_command(command_name: &str) -> Result<()> {
 
     Ok(())
 }
+
+/// An agent or command as stored: a `.md` file whose body holds `body_field`,
+/// and/or an entry under `section` in opencode.json
+struct DefinitionTarget<'a> {
+    section: &'static str,
+    body_field: &'static str,
+    md_path: PathBuf,
+    name: &'a str,
+}
+
+/// Whole-definition replace; runs under the write lock
+async fn replace_definition(
+    paths: &ConfigPaths,
+    target: &DefinitionTarget<'_>,
+    definition: &HashMap<String, Value>,
+) -> Result<Value> {
+    let mut files = vec![target.md_path.clone(), paths.config_file()];
+    if definition.contains_key(target.body_field) {
+        files.extend(
+            prompt_file_target(paths, target.section, target.name, target.body_field).await,
+        );
+    }
+    let entry = paths
+        .journal()
+        .begin(
+            "replace",
+            &format!("{}:{}", target.section, target.name),
+            &files,
+        )
+        .await?;
+    let result = apply_definition_replace(paths, target, definition).await;
+    entry.finish(&result).await;
+    result?;
+    read_definition(paths, target).await
+}
+
+/// Rewrite the `.md` source when there is one, dropping any opencode.json entry
+/// that would override it; otherwise replace the opencode.json entry
+async fn apply_definition_replace(
+    paths: &ConfigPaths,
+    target: &DefinitionTarget<'_>,
+    definition: &HashMap<String, Value>,
+) -> Result<()> {
+    ensure_dirs(paths).await?;
+
+    // Null means absent, as with updates
+    let mut fields: Map<String, Value> = definition
+        .iter()
+        .filter(|(_, value)| !value.is_null())
+        .map(|(field, value)| (field.clone(), value.clone()))
+        .collect();
+
+    let mut config = read_config(paths).await?;
+    if !config.is_object() {
+        config = Value::Object(Map::new());
+    }
+    let existing = config
+        .get(target.section)
+        .and_then(|v| v.as_object())
+        .and_then(|obj| obj.get(target.name))
+        .cloned();
+
+    if target.md_path.exists() {
+        let body = fields
+            .remove(target.body_field)
+            .and_then(|v| v.as_str().map(|s| s.to_string()))
+            .unwrap_or_default();
+        let frontmatter: HashMap<String, Value> = fields.into_iter().collect();
+        write_md_file(&target.md_path, &frontmatter, &body).await?;
+
+        if existing.is_some() {
+            if let Some(entries) = config
+                .get_mut(target.section)
+                .and_then(|v| v.as_object_mut())
+            {
+                entries.remove(target.name);
+            }
+            write_config(paths, &config).await?;
+        }
+        info!("Replaced {} {} (md)", target.section, target.name);
+        return Ok(());
+    }
+
+    // Keep a `{file:...}` body reference and write the new text through it
+    let reference = existing
+        .as_ref()
+        .and_then(|entry| entry.get(target.body_field))
+        .and_then(|v| v.as_str())
+        .filter(|reference| is_prompt_file_reference(reference))
+        .map(|reference| reference.to_string());
+    if let (Some(reference), Some(Value::String(body))) = (reference, fields.get(target.body_field))
+    {
+        if !is_prompt_file_reference(body) {
+            let file_path = resolve_prompt_file_path(paths, &reference).ok_or_else(|| {
+                anyhow!(
+                    "Invalid {} file reference for {} {}",
+                    target.body_field,
+                    target.section,
+                    target.name
+                )
+            })?;
+            write_prompt_file(&file_path, body).await?;
+            fields.insert(target.body_field.to_string(), Value::String(reference));
+        }
+    }
+
+    let config_obj = config.as_object_mut().unwrap();
+    let entries = config_obj
+        .entry(target.section.to_string())
+        .or_insert_with(|| Value::Object(Map::new()));
+    if !entries.is_object() {
+        *entries = Value::Object(Map::new());
+    }
+    entries
+        .as_object_mut()
+        .unwrap()
+        .insert(target.name.to_string(), Value::Object(fields));
+    write_config(paths, &config).await?;
+    info!("Replaced {} {} (json)", target.section, target.name);
+
+    Ok(())
+}
+
+/// The definition OpenCode will load: `.md` frontmatter and body, with the
+/// opencode.json entry over it
+async fn read_definition(paths: &ConfigPaths, target: &DefinitionTarget<'_>) -> Result<Value> {
+    let mut merged = Map::new();
+    if target.md_path.exists() {
+        let data = parse_md_file(&target.md_path).await?;
+        merged.extend(data.frontmatter);
+        merged.insert(target.body_field.to_string(), Value::String(data.body));
+    }
+    let config = read_config(paths).await?;
+    if let Some(entry) = config
+        .get(target.section)
+        .and_then(|v| v.get(target.name))
+        .and_then(|v| v.as_object())
+    {
+        merged.extend(entry.clone());
+    }
+    Ok(Value::Object(merged))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    /// A config directory and journal under the system temp dir, removed on drop
+    struct TempConfig {
+        root: PathBuf,
+        paths: ConfigPaths,
+    }
+
+    impl TempConfig {
+        fn new() -> Self {
+            let root =
+                std::env::temp_dir().join(format!("openchamber-config-{}", uuid::Uuid::new_v4()));
+            let paths = ConfigPaths::new(root.join("opencode"), root.join("journal"));
+            Self { root, paths }
+        }
+
+        async fn write_config(&self, content: &str) {
+            fs::create_dir_all(self.paths.config_dir()).await.unwrap();
+            fs::write(self.paths.config_file(), content).await.unwrap();
+        }
+    }
+
+    impl Drop for TempConfig {
+        fn drop(&mut self) {
+            std::fs::remove_dir_all(&self.root).ok();
+        }
+    }
+
+    fn payload(value: Value) -> HashMap<String, Value> {
+        serde_json::from_value(value).unwrap()
+    }
+
+    #[test]
+    fn config_names_are_decoded_and_validated() {
+        assert_eq!(validate_config_name("reviewer").unwrap(), "reviewer");
+        assert_eq!(
+            validate_config_name(" team/reviewer ").unwrap(),
+            "team/reviewer"
+        );
+        assert_eq!(
+            validate_config_name("team%2Freviewer").unwrap(),
+            "team/reviewer"
+        );
+        assert_eq!(validate_config_name("v1.2_beta-x").unwrap(), "v1.2_beta-x");
+
+        let too_long = "a".repeat(MAX_CONFIG_NAME_LENGTH + 1);
+        for raw in [
+            "",
+            "%20",
+            "a b",
+            "a%00b",
+            "a\\b",
+            "%zz",
+            "%e9",
+            too_long.as_str(),
+        ] {
+            assert!(validate_config_name(raw).is_err(), "accepted {raw:?}");
+        }
+    }
+
+    #[test]
+    fn traversal_is_rejected_raw_and_encoded() {
+        for raw in [
+            "..",
+            "../secrets",
+            "team/../../secrets",
+            "./reviewer",
+            "team//reviewer",
+            "/etc/passwd",
+            "%2e%2e",
+            "%2E%2E%2Fsecrets",
+            "team%2F..%2F..%2Fsecrets",
+            "%2Fetc%2Fpasswd",
+            "%252e%252e",
+        ] {
+            assert!(validate_config_name(raw).is_err(), "accepted {raw:?}");
+        }
+    }
+
+    #[tokio::test]
+    async fn accepted_names_stay_inside_the_config_dir() {
+        let config = TempConfig::new();
+        let paths = &config.paths;
+        for raw in ["team%2Freviewer", "deep/nested/name", "..%2F..%2Fescaped"] {
+            let Ok(name) = validate_config_name(raw) else {
+                continue;
+            };
+            create_agent(paths, &name, &payload(json!({ "prompt": "x" })))
+                .await
+                .unwrap();
+        }
+
+        assert!(paths.agent_dir().join("team/reviewer.md").exists());
+        assert!(paths.agent_dir().join("deep/nested/name.md").exists());
+        let mut entries = std::fs::read_dir(&config.root)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name())
+            .collect::<Vec<_>>();
+        entries.sort();
+        assert_eq!(entries, ["opencode"]);
+    }
+
+    #[tokio::test]
+    async fn create_agent_writes_markdown_and_refuses_duplicates() {
+        let config = TempConfig::new();
+        let paths = &config.paths;
+        create_agent(
+            paths,
+            "reviewer",
+            &payload(json!({ "model": "a/b", "prompt": "Review the diff" })),
+        )
+        .await
+        .unwrap();
+
+        let sources = get_agent_sources(paths, "reviewer").await.unwrap();
+        assert!(sources.md.exists);
+        assert!(!sources.json.exists);
+        assert!(sources.md.fields.contains(&"model".to_string()));
+        assert!(sources.md.fields.contains(&"prompt".to_string()));
+
+        let again = create_agent(paths, "reviewer", &payload(json!({}))).await;
+        assert!(again.is_err());
+    }
+
+    #[tokio::test]
+    async fn nested_agents_live_in_subdirectories() {
+        let config = TempConfig::new();
+        let paths = &config.paths;
+        create_agent(paths, "team/reviewer", &payload(json!({ "prompt": "x" })))
+            .await
+            .unwrap();
+        assert!(paths.agent_dir().join("team").join("reviewer.md").exists());
+    }
+
+    #[tokio::test]
+    async fn update_keeps_fields_where_they_are_defined() {
+        let config = TempConfig::new();
+        let paths = &config.paths;
+        config
+            .write_config(
+                r#"{
+                    // comments are allowed in opencode.json
+                    "theme": "dark",
+                    "agent": { "build": { "model": "a/b", "temperature": 0.2 } }
+                }"#,
+            )
+            .await;
+
+        update_agent(
+            paths,
+            "build",
+            &payload(json!({ "model": "c/d", "temperature": null, "prompt": "Build it" })),
+        )
+        .await
+        .unwrap();
+
+        let written = read_config(paths).await.unwrap();
+        assert_eq!(written["theme"], "dark");
+        assert_eq!(
+            written["agent"]["build"],
+            json!({ "model": "c/d", "prompt": "Build it" })
+        );
+        assert!(!paths.agent_dir().join("build.md").exists());
+    }
+
+    #[tokio::test]
+    async fn concurrent_updates_keep_both_edits() {
+        let config = TempConfig::new();
+        let paths = &config.paths;
+        config
+            .write_config(r#"{ "agent": { "build": { "model": "a/b" } } }"#)
+            .await;
+
+        let temperature = payload(json!({ "temperature": 0.1 }));
+        let top_p = payload(json!({ "top_p": 0.9 }));
+        let (first, second) = tokio::join!(
+            update_agent(paths, "build", &temperature),
+            update_agent(paths, "build", &top_p),
+        );
+        first.unwrap();
+        second.unwrap();
+
+        let written = read_config(paths).await.unwrap();
+        assert_eq!(
+            written["agent"]["build"],
+            json!({ "model": "a/b", "temperature": 0.1, "top_p": 0.9 })
+        );
+    }
+
+    #[tokio::test]
+    async fn update_writes_through_prompt_file_references() {
+        let config = TempConfig::new();
+        let paths = &config.paths;
+        config
+            .write_config(r#"{ "agent": { "docs": { "prompt": "{file:./prompts/docs.txt}" } } }"#)
+            .await;
+
+        update_agent(paths, "docs", &payload(json!({ "prompt": "Write docs" })))
+            .await
+            .unwrap();
+
+        let prompt = fs::read_to_string(paths.config_dir().join("prompts/docs.txt"))
+            .await
+            .unwrap();
+        assert_eq!(prompt, "Write docs");
+        let written = read_config(paths).await.unwrap();
+        assert_eq!(
+            written["agent"]["docs"]["prompt"],
+            "{file:./prompts/docs.txt}"
+        );
+    }
+
+    #[tokio::test]
+    async fn replace_drops_fields_the_definition_lacks() {
+        let config = TempConfig::new();
+        let paths = &config.paths;
+        create_agent(
+            paths,
+            "plan",
+            &payload(json!({ "model": "a/b", "temperature": 0.5, "prompt": "Plan" })),
+        )
+        .await
+        .unwrap();
+
+        let definition = replace_agent(
+            paths,
+            "plan",
+            &payload(json!({ "model": "c/d", "prompt": "Plan again" })),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(
+            definition,
+            json!({ "model": "c/d", "prompt": "Plan again" })
+        );
+    }
+
+    #[tokio::test]
+    async fn delete_removes_sources_or_disables_built_ins() {
+        let config = TempConfig::new();
+        let paths = &config.paths;
+        create_agent(paths, "custom", &payload(json!({ "prompt": "x" })))
+            .await
+            .unwrap();
+
+        delete_agent(paths, "custom").await.unwrap();
+        assert!(!paths.agent_dir().join("custom.md").exists());
+
+        delete_agent(paths, "general").await.unwrap();
+        let written = read_config(paths).await.unwrap();
+        assert_eq!(written["agent"]["general"], json!({ "disable": true }));
+    }
+
+    #[tokio::test]
+    async fn commands_round_trip_through_markdown() {
+        let config = TempConfig::new();
+        let paths = &config.paths;
+        create_command(
+            paths,
+            "test",
+            &payload(json!({ "description": "Run tests", "template": "cargo test" })),
+        )
+        .await
+        .unwrap();
+        update_command(
+            paths,
+            "test",
+            &payload(json!({ "description": "Run all tests" })),
+        )
+        .await
+        .unwrap();
+
+        let sources = get_command_sources(paths, "test").await.unwrap();
+        assert!(sources.md.exists);
+        let definition = replace_command(paths, "test", &payload(json!({ "template": "cargo t" })))
+            .await
+            .unwrap();
+        assert_eq!(definition, json!({ "template": "cargo t" }));
+
+        delete_command(paths, "test").await.unwrap();
+        assert!(!paths.command_dir().join("test.md").exists());
+    }
+
+    #[tokio::test]
+    async fn completed_mutations_leave_no_journal_records() {
+        let config = TempConfig::new();
+        let paths = &config.paths;
+        create_agent(paths, "journaled", &payload(json!({ "prompt": "x" })))
+            .await
+            .unwrap();
+        update_agent(paths, "journaled", &payload(json!({ "model": "a/b" })))
+            .await
+            .unwrap();
+        delete_agent(paths, "journaled").await.unwrap();
+
+        assert!(config.root.join("journal").is_dir());
+        assert!(paths.journal().pending().await.is_empty());
+    }
+
+    #[test]
+    fn prompt_file_references_resolve_against_the_config_dir() {
+        let config = TempConfig::new();
+        let paths = &config.paths;
+        assert_eq!(
+            resolve_prompt_file_path(paths, "{file:./prompts/a.txt}"),
+            Some(paths.config_dir().join("prompts/a.txt"))
+        );
+        assert_eq!(
+            resolve_prompt_file_path(paths, "{file:b.txt}"),
+            Some(paths.config_dir().join("b.txt"))
+        );
+        assert_eq!(resolve_prompt_file_path(paths, "inline prompt"), None);
+    }
+
+    #[test]
+    fn json_comments_are_stripped_outside_strings() {
+        let stripped = strip_json_comments("{ // note\n \"url\": \"http://x/*y*/\" /* block */ }");
+        let value: Value = serde_json::from_str(&stripped).unwrap();
+        assert_eq!(value, json!({ "url": "http://x/*y*/" }));
+    }
+}
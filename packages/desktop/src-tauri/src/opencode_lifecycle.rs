@@ -0,0 +1,376 @@
+use std::{
+    collections::VecDeque,
+    time::{Duration, Instant},
+};
+
+use serde::Serialize;
+use tokio::sync::broadcast::{self, error::RecvError};
+
+use crate::opencode_output::OutputLine;
+
+/// First retry after a crash; doubles per attempt up to CRASH_RETRY_MAX_DELAY
+pub const CRASH_RETRY_BASE_DELAY: Duration = Duration::from_secs(1);
+pub const CRASH_RETRY_MAX_DELAY: Duration = Duration::from_secs(30);
+/// Restarts remembered for diagnostics
+pub const RESTART_HISTORY_LIMIT: usize = 20;
+/// Crash retries before giving up until the next manual restart
+pub const MAX_CRASH_RETRIES: u32 = 8;
+/// Failed starts within this window count towards the crash-loop threshold
+pub const CRASH_LOOP_WINDOW: Duration = Duration::from_secs(5 * 60);
+/// Failed starts before giving up, unless `opencode.crashLoopThreshold` is set
+pub const DEFAULT_CRASH_LOOP_THRESHOLD: u32 = 3;
+
+/// Where the OpenCode server is in its lifecycle
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum LifecycleState {
+    /// First start (or a start after it was stopped)
+    Starting,
+    Ready,
+    /// Deliberate restart, e.g. after a config change or directory switch
+    Restarting,
+    /// Exited unexpectedly; the supervisor is retrying with backoff
+    Crashed,
+    CliMissing,
+    /// Not running and not being started: shut down, failed to start, or
+    /// crash retries exhausted
+    Stopped,
+    /// Failed to start repeatedly; starts are refused until the failure is reset
+    Failed,
+    /// Running but no longer answering health checks
+    Unresponsive,
+}
+
+/// Step of starting or stopping the server, emitted as `openchamber:opencode-lifecycle`
+/// and broadcast to in-process listeners as it happens
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+#[serde(tag = "event", rename_all = "kebab-case")]
+pub enum LifecycleEvent {
+    Spawning,
+    PortDetected {
+        port: u16,
+    },
+    Ready {
+        port: Option<u16>,
+        #[serde(rename = "apiPrefix")]
+        api_prefix: String,
+    },
+    Stopping,
+    Stopped,
+    Failed {
+        error: String,
+    },
+}
+
+/// Wait until OpenCode reports ready, at most `max`. Listeners that lost their
+/// connection use this instead of a fixed sleep, so they reconnect as soon as
+/// a restart completes.
+pub async fn wait_for_ready(events: &mut broadcast::Receiver<LifecycleEvent>, max: Duration) {
+    let _ = tokio::time::timeout(max, async {
+        loop {
+            match events.recv().await {
+                Ok(LifecycleEvent::Ready { .. }) | Err(RecvError::Lagged(_)) => return,
+                Ok(_) => {}
+                Err(RecvError::Closed) => std::future::pending::<()>().await,
+            }
+        }
+    })
+    .await;
+}
+
+/// Drop events from before a connection attempt, which already sees their outcome
+pub fn discard_pending(events: &mut broadcast::Receiver<LifecycleEvent>) {
+    while let Ok(_) | Err(broadcast::error::TryRecvError::Lagged(_)) = events.try_recv() {}
+}
+
+/// One restart of the server, deliberate or after a crash
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RestartRecord {
+    /// RFC 3339
+    pub at: String,
+    pub reason: String,
+}
+
+/// Recent restarts, oldest first, capped at RESTART_HISTORY_LIMIT
+#[derive(Debug, Default)]
+pub struct RestartHistory {
+    records: VecDeque<RestartRecord>,
+    /// Every restart since launch, including those no longer in `records`
+    total: u64,
+}
+
+impl RestartHistory {
+    pub fn record(&mut self, reason: &str) {
+        self.total += 1;
+        if self.records.len() == RESTART_HISTORY_LIMIT {
+            self.records.pop_front();
+        }
+        self.records.push_back(RestartRecord {
+            at: chrono::Utc::now().to_rfc3339(),
+            reason: reason.to_string(),
+        });
+    }
+
+    pub fn records(&self) -> Vec<RestartRecord> {
+        self.records.iter().cloned().collect()
+    }
+
+    pub fn last(&self) -> Option<RestartRecord> {
+        self.records.back().cloned()
+    }
+
+    pub fn total(&self) -> u64 {
+        self.total
+    }
+}
+
+/// Delay before crash retry `attempt` (1-based)
+pub fn crash_retry_delay(attempt: u32) -> Duration {
+    let factor = 2u32.saturating_pow(attempt.saturating_sub(1).min(16));
+    CRASH_RETRY_BASE_DELAY
+        .saturating_mul(factor)
+        .min(CRASH_RETRY_MAX_DELAY)
+}
+
+#[derive(Clone, Debug)]
+pub struct Lifecycle {
+    state: LifecycleState,
+    since: Instant,
+    reason: Option<String>,
+    attempt: u32,
+    next_retry_at: Option<Instant>,
+    /// How long the last start or restart took to become ready
+    last_startup: Option<Duration>,
+    /// Failed starts since the last time OpenCode was ready
+    failures: VecDeque<Instant>,
+}
+
+impl Lifecycle {
+    pub fn new(state: LifecycleState) -> Self {
+        Self {
+            state,
+            since: Instant::now(),
+            reason: None,
+            attempt: 0,
+            next_retry_at: None,
+            last_startup: None,
+            failures: VecDeque::new(),
+        }
+    }
+
+    pub fn state(&self) -> LifecycleState {
+        self.state
+    }
+
+    pub fn transition(&mut self, state: LifecycleState, reason: Option<String>) {
+        if state == LifecycleState::Ready {
+            if matches!(
+                self.state,
+                LifecycleState::Starting | LifecycleState::Restarting
+            ) {
+                self.last_startup = Some(self.since.elapsed());
+            }
+            self.attempt = 0;
+            self.failures.clear();
+        }
+        self.state = state;
+        self.since = Instant::now();
+        self.reason = reason;
+        self.next_retry_at = None;
+    }
+
+    /// Record a crash (or a failed retry) and when the next retry happens
+    pub fn crashed(&mut self, attempt: u32, reason: String, retry_in: Duration) {
+        if self.state != LifecycleState::Crashed {
+            self.since = Instant::now();
+        }
+        self.state = LifecycleState::Crashed;
+        self.reason = Some(reason);
+        self.attempt = attempt;
+        self.next_retry_at = Some(Instant::now() + retry_in);
+    }
+
+    /// Record a failed start. Once `threshold` failures fall within
+    /// CRASH_LOOP_WINDOW the state becomes Failed and this returns true.
+    pub fn record_failure(&mut self, reason: String, threshold: u32) -> bool {
+        let now = Instant::now();
+        self.failures
+            .retain(|at| now.duration_since(*at) <= CRASH_LOOP_WINDOW);
+        self.failures.push_back(now);
+        if self.failures.len() < threshold.max(1) as usize {
+            return false;
+        }
+        self.transition(LifecycleState::Failed, Some(reason));
+        true
+    }
+
+    /// Forget recorded failures, leaving Failed for Stopped
+    pub fn reset_failures(&mut self) {
+        self.failures.clear();
+        if self.state == LifecycleState::Failed {
+            self.transition(LifecycleState::Stopped, None);
+        }
+    }
+
+    pub fn status(&self) -> OpenCodeStatus {
+        let now = Instant::now();
+        let eta_ms = match self.state {
+            LifecycleState::Starting | LifecycleState::Restarting => self
+                .last_startup
+                .map(|startup| startup.saturating_sub(self.since.elapsed()).as_millis() as u64),
+            _ => None,
+        };
+        let next_retry_ms = self
+            .next_retry_at
+            .map(|at| at.saturating_duration_since(now).as_millis() as u64);
+        let attempt = match self.state {
+            LifecycleState::Crashed => Some(self.attempt),
+            LifecycleState::Failed => Some(self.failures.len() as u32),
+            _ => None,
+        };
+
+        OpenCodeStatus {
+            state: self.state,
+            since_ms: self.since.elapsed().as_millis() as u64,
+            message: status_message(
+                self.state,
+                self.reason.as_deref(),
+                attempt,
+                next_retry_ms,
+                eta_ms,
+            ),
+            reason: self.reason.clone(),
+            attempt,
+            next_retry_ms,
+            eta_ms,
+            recent_output: None,
+        }
+    }
+}
+
+/// Snapshot reported by /health, desktop_server_info and 503 proxy responses
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OpenCodeStatus {
+    pub state: LifecycleState,
+    /// Time spent in the current state
+    pub since_ms: u64,
+    /// What triggered a restart, or the last error
+    pub reason: Option<String>,
+    /// Crash retry attempt while crashed; failed starts while failed
+    pub attempt: Option<u32>,
+    /// Time until the next crash retry; 0 while a retry is in progress
+    pub next_retry_ms: Option<u64>,
+    /// Expected time until ready, based on how long the last start took
+    pub eta_ms: Option<u64>,
+    /// User-facing summary of the above
+    pub message: String,
+    /// Last lines of OpenCode output, while failed
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub recent_output: Option<Vec<OutputLine>>,
+}
+
+fn seconds(ms: u64) -> u64 {
+    ms.div_ceil(1000).max(1)
+}
+
+/// Human-readable description of a state for banners and error bodies
+pub fn status_message(
+    state: LifecycleState,
+    reason: Option<&str>,
+    attempt: Option<u32>,
+    next_retry_ms: Option<u64>,
+    eta_ms: Option<u64>,
+) -> String {
+    let eta = eta_ms
+        .map(|ms| format!(", ~{}s", seconds(ms)))
+        .unwrap_or_default();
+    match state {
+        LifecycleState::Starting => format!("OpenCode is starting{eta}"),
+        LifecycleState::Ready => "OpenCode is ready".to_string(),
+        LifecycleState::Restarting => match reason {
+            Some(reason) => format!("OpenCode is restarting after {reason}{eta}"),
+            None => format!("OpenCode is restarting{eta}"),
+        },
+        LifecycleState::Crashed => {
+            let attempt = attempt
+                .map(|attempt| format!(" (attempt {attempt} of {MAX_CRASH_RETRIES})"))
+                .unwrap_or_default();
+            match next_retry_ms {
+                Some(ms) if ms > 0 => format!(
+                    "OpenCode stopped unexpectedly, retrying in {}s{attempt}",
+                    seconds(ms)
+                ),
+                _ => format!("OpenCode stopped unexpectedly, retrying now{attempt}"),
+            }
+        }
+        LifecycleState::CliMissing => {
+            "OpenCode CLI not found. Install it or set OPENCODE_BINARY.".to_string()
+        }
+        LifecycleState::Failed => {
+            let failures = attempt
+                .map(|count| format!(" {count} times"))
+                .unwrap_or_default();
+            match reason {
+                Some(reason) => format!(
+                    "OpenCode failed to start{failures}; fix the problem and retry: {reason}"
+                ),
+                None => format!("OpenCode failed to start{failures}; fix the problem and retry"),
+            }
+        }
+        LifecycleState::Unresponsive => {
+            "OpenCode stopped responding; restart it if this persists".to_string()
+        }
+        LifecycleState::Stopped => match reason {
+            Some(reason) => format!("OpenCode is not running: {reason}"),
+            None => "OpenCode is not running".to_string(),
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn crash_retry_delay_doubles_up_to_the_cap() {
+        assert_eq!(crash_retry_delay(1), CRASH_RETRY_BASE_DELAY);
+        assert_eq!(crash_retry_delay(2), CRASH_RETRY_BASE_DELAY * 2);
+        assert_eq!(crash_retry_delay(3), CRASH_RETRY_BASE_DELAY * 4);
+        assert_eq!(crash_retry_delay(MAX_CRASH_RETRIES), CRASH_RETRY_MAX_DELAY);
+        assert_eq!(crash_retry_delay(u32::MAX), CRASH_RETRY_MAX_DELAY);
+    }
+
+    #[test]
+    fn status_reports_the_crash_attempt_until_ready() {
+        let mut lifecycle = Lifecycle::new(LifecycleState::Ready);
+        lifecycle.crashed(3, "exit code 1".to_string(), Duration::from_secs(4));
+        let status = lifecycle.status();
+        assert_eq!(status.state, LifecycleState::Crashed);
+        assert_eq!(status.attempt, Some(3));
+        assert!(status.next_retry_ms.is_some_and(|ms| ms <= 4000));
+        assert!(status
+            .message
+            .contains(&format!("attempt 3 of {MAX_CRASH_RETRIES}")));
+
+        lifecycle.transition(LifecycleState::Ready, None);
+        let status = lifecycle.status();
+        assert_eq!(status.attempt, None);
+        assert_eq!(status.next_retry_ms, None);
+    }
+
+    #[test]
+    fn repeated_failures_end_in_failed_until_reset() {
+        let mut lifecycle = Lifecycle::new(LifecycleState::Starting);
+        assert!(!lifecycle.record_failure("bad config".to_string(), 3));
+        assert!(!lifecycle.record_failure("bad config".to_string(), 3));
+        assert!(lifecycle.record_failure("bad config".to_string(), 3));
+        let status = lifecycle.status();
+        assert_eq!(status.state, LifecycleState::Failed);
+        assert_eq!(status.attempt, Some(3));
+
+        lifecycle.reset_failures();
+        assert_eq!(lifecycle.state(), LifecycleState::Stopped);
+    }
+}
@@ -1,46 +1,387 @@
 use anyhow::{anyhow, Result};
+use futures_util::future::{join_all, BoxFuture, FutureExt, Shared};
 use log::{debug, info, warn};
-use once_cell::sync::Lazy;
+use once_cell::sync::{Lazy, OnceCell};
 use parking_lot::RwLock;
 use regex::Regex;
 use reqwest::Client;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Map, Value};
 use std::{
-    collections::HashMap,
+    collections::{BTreeMap, HashMap},
+    ffi::{OsStr, OsString},
     path::{Path, PathBuf},
     sync::{
-        atomic::{AtomicBool, Ordering},
+        atomic::{AtomicBool, AtomicU16, AtomicU64, Ordering},
         Arc,
     },
-    time::Duration,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
 };
+use tauri::{AppHandle, Emitter};
 use tokio::{
     io::{AsyncBufReadExt, BufReader},
     process::{Child, Command},
-    sync::Mutex,
+    sync::{broadcast, Mutex},
     time::timeout,
 };
 
+use crate::{
+    cli_install, login_shell,
+    opencode_lifecycle::{
+        self, Lifecycle, LifecycleEvent, LifecycleState, OpenCodeStatus, RestartHistory,
+        RestartRecord,
+    },
+    opencode_output::{OutputLine, OutputLog},
+    opencode_version::{self, CliVersion},
+    process_stats::{ProcessSampler, ProcessStats},
+    SettingsStore,
+};
+
 static URL_REGEX: Lazy<Regex> = Lazy::new(|| {
     Regex::new(r#"https?://[^:\s]+:(?P<port>\d+)(?P<path>/[^\s"']*)?"#).expect("valid regex")
 });
 
 const FIRST_SIGNAL_TIMEOUT_MS: u64 = 750;
+/// Defaults for `opencode.readyTimeoutMs` and `opencode.readyIntervalMs`
 const READY_CHECK_TIMEOUT_MS: u64 = 20000;
-const READY_CHECK_INTERVAL_MS: u64 = 400;
+const READY_CHECK_INTERVAL_MS: u64 = 250;
+/// Per-request timeout of the /health poll, which should answer instantly once up
+const READY_PROBE_TIMEOUT: Duration = Duration::from_millis(500);
+/// Output lines appended to the not-ready error
+const READY_ERROR_OUTPUT_LINES: usize = 20;
+/// Output kept in the error for a process that exits before it is ready
+const STARTUP_OUTPUT_MAX_BYTES: usize = 2048;
+/// How long an exited process's output readers get to reach end of stream
+const OUTPUT_DRAIN_TIMEOUT: Duration = Duration::from_millis(500);
+/// Output lines reported with a Failed status
+const FAILED_STATUS_OUTPUT_LINES: usize = 50;
+const ADOPTION_PROBE_TIMEOUT: Duration = Duration::from_millis(1500);
+/// Settings key holding the last port and API prefix used per working directory
+const PORT_HINTS_KEY: &str = "opencodePorts";
+/// Settings map of canonical directory -> OpenCode config file for that project
+pub const PROJECT_CONFIGS_KEY: &str = "opencodeConfigs";
+/// Config file picked up from a project when settings name none
+const PROJECT_CONFIG_FILE: &str = ".openchamber/opencode.json";
+const MAX_PORT_HINTS: usize = 20;
+/// How graceful_stop asks OpenCode to exit, then forces it, for logging
+#[cfg(unix)]
+const STOP_SIGNAL: &str = "SIGTERM";
+#[cfg(unix)]
+const KILL_SIGNAL: &str = "SIGKILL";
+#[cfg(windows)]
+const STOP_SIGNAL: &str = "CTRL_BREAK";
+#[cfg(windows)]
+const KILL_SIGNAL: &str = "TerminateProcess";
+/// Lifecycle changes the UI should hear about without polling /health
+pub const OPENCODE_STATUS_EVENT: &str = "openchamber:opencode-status";
+/// Each spawn, port, ready, stop and failure step; see LifecycleEvent
+pub const OPENCODE_LIFECYCLE_EVENT: &str = "openchamber:opencode-lifecycle";
+/// Lifecycle events buffered per in-process listener
+const LIFECYCLE_CHANNEL_CAPACITY: usize = 32;
+/// The watchdog checks /health this often while OpenCode is ready
+const WATCHDOG_INTERVAL: Duration = Duration::from_secs(30);
+const WATCHDOG_PROBE_TIMEOUT: Duration = Duration::from_secs(5);
+/// Consecutive timed-out checks before OpenCode counts as hung
+const WATCHDOG_MAX_MISSES: u32 = 3;
+/// Budget of the /health check behind `Reachability`, which the health endpoint awaits
+const REACHABILITY_PROBE_TIMEOUT: Duration = Duration::from_millis(300);
+/// Health polls within this window reuse the last reachability check
+const REACHABILITY_CACHE_TTL: Duration = Duration::from_secs(2);
+/// Emitted when the CLI binary changed on disk since the running server was spawned
+pub const BINARY_UPDATED_EVENT: &str = "openchamber:opencode-binary-updated";
+/// How often the CLI binary is checked for an in-place upgrade (also checked on focus)
+const BINARY_CHECK_INTERVAL: Duration = Duration::from_secs(5 * 60);
+/// How long shutdown waits for an in-flight start before stopping the child anyway
+const SHUTDOWN_OPERATION_WAIT: Duration = Duration::from_secs(1);
+/// How often the supervisor checks that our OpenCode process is still alive
+const SUPERVISOR_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Emitted when a restart is skipped because the server is not ours to restart
+pub const RESTART_SKIPPED_EVENT: &str = "openchamber:opencode-restart-skipped";
+/// Emitted when re-detection found the server answering under a different API prefix
+pub const API_PREFIX_CHANGED_EVENT: &str = "openchamber:api-prefix-changed";
+/// API prefixes OpenCode has served under, most common first
+const API_PREFIX_CANDIDATES: [&str; 2] = ["", "/api"];
+
+/// Result of re-checking the API prefix of a running server
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PrefixRedetection {
+    pub previous: String,
+    pub current: String,
+    pub changed: bool,
+}
+
+/// Port and API prefix a directory's server last became ready on
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PortHint {
+    pub port: u16,
+    pub api_prefix: String,
+}
+
+/// Endpoints polled for readiness unless `opencode.readinessEndpoints` names others
+const DEFAULT_READINESS_ENDPOINTS: [&str; 3] = ["/health", "/config", "/agent"];
+
+/// Readiness endpoints in use, from `opencode.readinessEndpoints` and
+/// `opencode.lenientReadiness`
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ReadinessConfig {
+    /// The first is polled until it answers; the rest are checked once it does
+    pub endpoints: Vec<String>,
+    /// A 404 from any endpoint but the first counts as ready, for
+    /// OpenCode-compatible servers that leave some out
+    pub lenient: bool,
+}
+
+impl Default for ReadinessConfig {
+    fn default() -> Self {
+        Self {
+            endpoints: DEFAULT_READINESS_ENDPOINTS.map(String::from).to_vec(),
+            lenient: false,
+        }
+    }
+}
+
+/// Endpoint that failed a readiness check
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ReadinessFailure {
+    pub endpoint: String,
+    /// HTTP status, when the server answered
+    pub status: Option<u16>,
+    /// Connection error or timeout, when it did not
+    pub error: Option<String>,
+}
+
+impl ReadinessFailure {
+    fn unreachable(endpoint: &str, err: &reqwest::Error) -> Self {
+        let error = if err.is_timeout() {
+            "timed out".to_string()
+        } else if err.is_connect() {
+            "connection refused".to_string()
+        } else {
+            err.to_string()
+        };
+        Self {
+            endpoint: endpoint.to_string(),
+            status: None,
+            error: Some(error),
+        }
+    }
+
+    fn check(endpoint: &str, status: reqwest::StatusCode) -> Result<(), Self> {
+        if status.is_success() {
+            return Ok(());
+        }
+        Err(Self {
+            endpoint: endpoint.to_string(),
+            status: Some(status.as_u16()),
+            error: None,
+        })
+    }
+}
+
+impl std::fmt::Display for ReadinessFailure {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match (self.status, &self.error) {
+            (Some(status), _) => write!(f, "{} returned {}", self.endpoint, status),
+            (None, Some(error)) => write!(f, "{} {}", self.endpoint, error),
+            (None, None) => write!(f, "{} failed", self.endpoint),
+        }
+    }
+}
+
+/// OpenCode did not become ready in time; start errors can be downcast to it
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ReadinessError {
+    pub waited_ms: u64,
+    pub attempts: u32,
+    pub last_failure: Option<ReadinessFailure>,
+    #[serde(skip)]
+    recent_output: String,
+}
+
+impl std::fmt::Display for ReadinessError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "OpenCode not ready after {}ms ({} checks): ",
+            self.waited_ms, self.attempts
+        )?;
+        match &self.last_failure {
+            Some(failure) => write!(f, "{}", failure)?,
+            None => write!(f, "no error details")?,
+        }
+        if !self.recent_output.is_empty() {
+            write!(f, "\nRecent OpenCode output:\n{}", self.recent_output)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for ReadinessError {}
+
+/// OpenCode exited before it became ready, usually over a bad config or flag;
+/// start errors can be downcast to it
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StartupExitError {
+    /// Exit status as the OS reports it
+    pub status: String,
+    /// End of what it printed, at most STARTUP_OUTPUT_MAX_BYTES
+    pub output: String,
+}
+
+impl std::fmt::Display for StartupExitError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "OpenCode exited with {} before it was ready",
+            self.status
+        )?;
+        if self.output.is_empty() {
+            write!(f, " (no output)")
+        } else {
+            write!(f, ":\n{}", self.output)
+        }
+    }
+}
+
+impl std::error::Error for StartupExitError {}
+
+type QueuedRestart = Shared<BoxFuture<'static, Result<(), String>>>;
+
+/// `opencode.extraArgs` and `opencode.env` from settings, applied at every launch
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct LaunchOverrides {
+    pub args: Vec<String>,
+    pub env: HashMap<String, String>,
+}
+
+/// A ready server of ours, moved between managers by the instance pool
+pub(crate) struct RunningServer {
+    child: Child,
+    port: u16,
+    api_prefix: String,
+    supports_config_reload: bool,
+    supports_directory_scoping: bool,
+}
+
+/// Whether the server answered /health just now, unlike readiness, which only
+/// says the startup handshake finished
+#[derive(Clone, Debug, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Reachability {
+    pub reachable: bool,
+    /// Unix time in milliseconds; None when there was no port to check
+    pub last_checked_ms: Option<u64>,
+}
+
+/// Identity of the CLI binary on disk; an upgrade changes at least one field
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct BinaryFingerprint {
+    /// Symlinks resolved, since npm and brew upgrades repoint them
+    path: PathBuf,
+    modified: Option<SystemTime>,
+    len: u64,
+}
+
+impl BinaryFingerprint {
+    fn of(binary: &str) -> Option<Self> {
+        let path = std::fs::canonicalize(binary).ok()?;
+        let metadata = std::fs::metadata(&path).ok()?;
+        Some(Self {
+            path,
+            modified: metadata.modified().ok(),
+            len: metadata.len(),
+        })
+    }
+}
+
+/// Subset of OpenCode's /path response
+#[derive(Deserialize)]
+struct PathInfo {
+    directory: Option<String>,
+    worktree: Option<String>,
+}
 
 #[derive(Clone)]
 pub struct OpenCodeManager {
-    binary: Option<String>,
+    /// Set later when the CLI is installed from within the app
+    binary: Arc<RwLock<Option<String>>>,
     args: Vec<String>,
+    /// `OPENCHAMBER_OPENCODE_CONFIG`, used when the project has no config of its own
+    global_config: Option<PathBuf>,
+    /// `--config` for the working directory, recomputed when it changes
+    config_path: Arc<RwLock<Option<PathBuf>>>,
     env: HashMap<String, String>,
     working_dir: Arc<RwLock<PathBuf>>,
     desired_port: u16,
+    /// Port passed to `--port` for the current child; 0 lets OpenCode pick
+    launch_port: Arc<AtomicU16>,
     child: Arc<Mutex<Option<Child>>>,
     port: Arc<RwLock<Option<u16>>>,
     api_prefix: Arc<RwLock<String>>,
+    /// Prefix found by the last start-up detection, to notice it moving between restarts
+    detected_prefix: Arc<RwLock<Option<String>>>,
     is_ready: Arc<AtomicBool>,
+    /// Bumped each time a server becomes ready, so per-process state can be reset
+    server_epoch: Arc<AtomicU64>,
+    supports_config_reload: Arc<AtomicBool>,
+    /// The server answers for whichever project the `directory` query names
+    supports_directory_scoping: Arc<AtomicBool>,
     shutting_down: Arc<AtomicBool>,
     http_client: Client,
+    /// Set when we attached to a server someone else started; it is never killed
+    adopted: Arc<AtomicBool>,
+    /// `opencode.externalPort` from settings, probed before spawning
+    external_port: Arc<RwLock<Option<u16>>>,
+    app: Arc<OnceCell<AppHandle>>,
+    settings: Arc<OnceCell<Arc<SettingsStore>>>,
+    lifecycle: Arc<RwLock<Lifecycle>>,
+    lifecycle_events: broadcast::Sender<LifecycleEvent>,
+    restart_history: Arc<RwLock<RestartHistory>>,
+    /// Last reachability check and when it ran; held while checking so
+    /// concurrent health polls share one request
+    reachability: Arc<Mutex<Option<(Instant, Reachability)>>>,
+    /// Recent output of the current process, cleared when a new one is spawned
+    output_log: Arc<OutputLog>,
+    /// Tasks reading the current process's stdout and stderr
+    output_readers: Arc<parking_lot::Mutex<Vec<tauri::async_runtime::JoinHandle<()>>>>,
+    /// `opencode --version` as of the last spawn
+    cli_version: Arc<RwLock<Option<CliVersion>>>,
+    process_sampler: Arc<ProcessSampler>,
+    /// The binary as it was when the running server was spawned; None when adopted
+    spawned_binary: Arc<RwLock<Option<BinaryFingerprint>>>,
+    /// The binary changed since the spawn; cleared by the next spawn
+    binary_updated: Arc<AtomicBool>,
+    /// Held while starting, restarting or stopping, so those never interleave
+    operation: Arc<Mutex<()>>,
+    /// Restart waiting for `operation`; requests made meanwhile join it, since
+    /// it has not read the working directory or settings yet
+    queued_restart: Arc<RwLock<Option<QueuedRestart>>>,
+}
+
+/// OpenCode's own path for a request to the desktop's `/api` mount, without
+/// any backend prefix; `/api` itself is the root
+pub fn opencode_path(incoming_path: &str) -> String {
+    incoming_path
+        .strip_prefix("/api")
+        .filter(|rest| rest.is_empty() || rest.starts_with('/'))
+        .map(|rest| if rest.is_empty() { "/" } else { rest })
+        .unwrap_or(incoming_path)
+        .to_string()
+}
+
+/// `path` under a normalized `prefix`; the root of a prefixed server is the prefix itself
+fn with_api_prefix(prefix: &str, path: &str) -> String {
+    match (prefix, path) {
+        ("", _) => path.to_string(),
+        (_, "/") => prefix.to_string(),
+        _ => format!("{}{}", prefix, path),
+    }
 }
 
 fn normalize_api_prefix(prefix: &str) -> String {
@@ -75,19 +416,18 @@ impl OpenCodeManager {
             warn!("[desktop:opencode] OpenCode CLI not found - app will run in limited mode");
         }
 
-        let mut args = vec![
-            "serve".to_string(),
-            "--port".to_string(),
-            desired_port.to_string(),
-        ];
-        if let Ok(config) = std::env::var("OPENCHAMBER_OPENCODE_CONFIG") {
-            if !config.is_empty() {
-                args.push("--config".to_string());
-                args.push(config);
-            }
-        }
+        let args = vec!["serve".to_string()];
+        let global_config = std::env::var("OPENCHAMBER_OPENCODE_CONFIG")
+            .ok()
+            .filter(|config| !config.is_empty())
+            .map(PathBuf::from);
 
         let env = build_augmented_env();
+        let initial_state = if binary.is_some() {
+            LifecycleState::Stopped
+        } else {
+            LifecycleState::CliMissing
+        };
         let working_dir = initial_dir
             .unwrap_or_else(|| std::env::current_dir().unwrap_or_else(|_| PathBuf::from(".")));
 
@@ -97,46 +437,231 @@ impl OpenCodeManager {
         );
 
         Self {
-            binary,
+            binary: Arc::new(RwLock::new(binary)),
             args,
+            config_path: Arc::new(RwLock::new(global_config.clone())),
+            global_config,
             env,
             working_dir: Arc::new(RwLock::new(working_dir)),
             desired_port,
+            launch_port: Arc::new(AtomicU16::new(desired_port)),
             child: Arc::new(Mutex::new(None)),
             port: Arc::new(RwLock::new(None)),
             api_prefix: Arc::new(RwLock::new(String::new())),
+            detected_prefix: Arc::new(RwLock::new(None)),
             is_ready: Arc::new(AtomicBool::new(false)),
+            server_epoch: Arc::new(AtomicU64::new(0)),
+            supports_config_reload: Arc::new(AtomicBool::new(false)),
+            supports_directory_scoping: Arc::new(AtomicBool::new(false)),
             shutting_down: Arc::new(AtomicBool::new(false)),
             http_client: Client::builder()
                 .timeout(Duration::from_secs(5))
                 .build()
                 .unwrap(),
+            adopted: Arc::new(AtomicBool::new(false)),
+            external_port: Arc::new(RwLock::new(None)),
+            app: Arc::new(OnceCell::new()),
+            settings: Arc::new(OnceCell::new()),
+            lifecycle: Arc::new(RwLock::new(Lifecycle::new(initial_state))),
+            lifecycle_events: broadcast::channel(LIFECYCLE_CHANNEL_CAPACITY).0,
+            restart_history: Arc::new(RwLock::new(RestartHistory::default())),
+            reachability: Arc::new(Mutex::new(None)),
+            output_log: Arc::new(OutputLog::default()),
+            output_readers: Arc::new(parking_lot::Mutex::new(Vec::new())),
+            cli_version: Arc::new(RwLock::new(None)),
+            process_sampler: Arc::new(ProcessSampler::default()),
+            spawned_binary: Arc::new(RwLock::new(None)),
+            binary_updated: Arc::new(AtomicBool::new(false)),
+            operation: Arc::new(Mutex::new(())),
+            queued_restart: Arc::new(RwLock::new(None)),
         }
     }
 
+    /// Give the manager a handle for emitting events
+    pub fn attach_app(&self, app: AppHandle) {
+        let _ = self.app.set(app);
+    }
+
+    /// Give the manager the settings store used to remember ports per directory
+    pub(crate) fn attach_settings(&self, settings: Arc<SettingsStore>) {
+        let _ = self.settings.set(settings);
+    }
+
+    pub fn set_external_port(&self, port: Option<u16>) {
+        *self.external_port.write() = port.filter(|port| *port > 0);
+    }
+
+    /// True when the running server was started outside the app
+    pub fn is_adopted(&self) -> bool {
+        self.adopted.load(Ordering::SeqCst)
+    }
+
     pub fn is_cli_available(&self) -> bool {
-        self.binary.is_some()
+        self.binary.read().is_some()
+    }
+
+    /// Use a CLI installed after startup, leaving limited mode
+    pub fn set_binary(&self, binary: String) {
+        info!("[desktop:opencode] using binary: {}", binary);
+        *self.binary.write() = Some(binary);
+        let mut lifecycle = self.lifecycle.write();
+        if lifecycle.state() == LifecycleState::CliMissing {
+            lifecycle.transition(LifecycleState::Stopped, None);
+        }
+    }
+
+    /// Resolved CLI binary and the environment OpenCode is launched with, for
+    /// running other `opencode` subcommands the same way
+    pub fn cli_invocation(&self) -> Option<(String, &HashMap<String, String>)> {
+        let binary = self.binary.read().clone()?;
+        Some((binary, &self.env))
+    }
+
+    /// Current lifecycle state with timing details, for health reporting
+    pub fn status(&self) -> OpenCodeStatus {
+        let mut status = self.lifecycle.read().status();
+        if status.state == LifecycleState::Failed {
+            status.recent_output = Some(self.output_log.tail(FAILED_STATUS_OUTPUT_LINES));
+        }
+        status
+    }
+
+    /// Leave the Failed state so the next start is attempted again
+    pub fn reset_failure(&self) {
+        self.lifecycle.write().reset_failures();
+        info!("[desktop:opencode] Start failures reset");
+    }
+
+    /// `opencode.crashLoopThreshold` from settings
+    async fn crash_loop_threshold(&self) -> u32 {
+        let Some(store) = self.settings.get() else {
+            return opencode_lifecycle::DEFAULT_CRASH_LOOP_THRESHOLD;
+        };
+        store
+            .load()
+            .await
+            .ok()
+            .and_then(|settings| {
+                settings
+                    .get("opencode")?
+                    .get("crashLoopThreshold")?
+                    .as_u64()
+            })
+            .and_then(|threshold| u32::try_from(threshold).ok())
+            .filter(|threshold| *threshold > 0)
+            .unwrap_or(opencode_lifecycle::DEFAULT_CRASH_LOOP_THRESHOLD)
+    }
+
+    fn set_lifecycle(&self, state: LifecycleState, reason: Option<String>) {
+        self.lifecycle.write().transition(state, reason);
+    }
+
+    /// Lifecycle events from now on, for tasks that reconnect when OpenCode is ready
+    pub fn subscribe_lifecycle(&self) -> broadcast::Receiver<LifecycleEvent> {
+        self.lifecycle_events.subscribe()
+    }
+
+    fn emit_lifecycle(&self, event: LifecycleEvent) {
+        debug!("[desktop:opencode] lifecycle: {:?}", event);
+        if let Some(app) = self.app.get() {
+            let _ = app.emit(OPENCODE_LIFECYCLE_EVENT, &event);
+        }
+        // No receivers is fine
+        let _ = self.lifecycle_events.send(event);
     }
 
+    fn emit_ready(&self) {
+        self.emit_lifecycle(LifecycleEvent::Ready {
+            port: self.current_port(),
+            api_prefix: self.api_prefix(),
+        });
+    }
+
+    /// Start OpenCode unless it is running. Waits for a start, restart or
+    /// shutdown in progress and then acts on its outcome.
     pub async fn ensure_running(&self) -> Result<()> {
-        if self.binary.is_none() {
+        let _operation = self.operation.lock().await;
+        self.ensure_running_locked().await
+    }
+
+    async fn ensure_running_locked(&self) -> Result<()> {
+        if !self.is_cli_available() {
             return Err(anyhow!("OpenCode CLI is not available"));
         }
+        // Don't burn another ready timeout on a start that keeps failing
+        if self.lifecycle.read().state() == LifecycleState::Failed {
+            return Err(anyhow!("{}", self.status().message));
+        }
+
+        let result = self.start_if_needed().await;
+        match &result {
+            Ok(()) => {
+                if self.lifecycle.read().state() != LifecycleState::Ready {
+                    self.set_lifecycle(LifecycleState::Ready, None);
+                    self.emit_ready();
+                }
+            }
+            Err(err) => {
+                self.emit_lifecycle(LifecycleEvent::Failed {
+                    error: err.to_string(),
+                });
+                let threshold = self.crash_loop_threshold().await;
+                let mut lifecycle = self.lifecycle.write();
+                if lifecycle.record_failure(err.to_string(), threshold) {
+                    warn!(
+                        "[desktop:opencode] Giving up after {} failed starts; waiting for a manual retry",
+                        threshold
+                    );
+                } else if lifecycle.state() != LifecycleState::Crashed {
+                    // Crash retries report their own failures
+                    lifecycle.transition(LifecycleState::Stopped, Some(err.to_string()));
+                }
+            }
+        }
+        result
+    }
 
+    async fn start_if_needed(&self) -> Result<()> {
         let mut guard = self.child.lock().await;
         if let Some(child) = guard.as_mut() {
             if child.try_wait()?.is_none() && self.is_ready.load(Ordering::SeqCst) {
                 return Ok(());
             }
         }
+        if self.is_adopted() && self.is_ready.load(Ordering::SeqCst) {
+            return Ok(());
+        }
+        // A live child that is not ready is hung; it would keep holding its port
+        if let Some(mut stale) = guard.take() {
+            if stale.try_wait()?.is_none() {
+                warn!("[desktop:opencode] Killing unresponsive process before starting");
+                let _ = stale.kill().await;
+            }
+        }
+        // Shutdown may not have waited for this start; don't leave a child behind
+        if self.shutting_down.load(Ordering::SeqCst) {
+            return Err(anyhow!("OpenCode is shutting down"));
+        }
 
         self.is_ready.store(false, Ordering::SeqCst);
+        if !matches!(
+            self.lifecycle.read().state(),
+            LifecycleState::Restarting | LifecycleState::Crashed
+        ) {
+            self.set_lifecycle(LifecycleState::Starting, None);
+        }
+        if guard.is_none() && self.try_adopt().await {
+            return Ok(());
+        }
+        // The binary may have been updated since the last start
+        self.detect_cli_version().await;
+        self.emit_lifecycle(LifecycleEvent::Spawning);
         let child = self.spawn_process().await?;
         *guard = Some(child);
         drop(guard);
 
         // Wait for port detection from logs
-        if self.desired_port == 0 {
+        if self.launch_port.load(Ordering::SeqCst) == 0 {
             self.wait_for_port_detection().await?;
         }
 
@@ -145,17 +670,106 @@ impl OpenCodeManager {
 
         // Wait for OpenCode to become ready by polling endpoints
         self.wait_for_ready().await?;
+        self.verify_identity().await?;
 
         self.is_ready.store(true, Ordering::SeqCst);
+        self.server_epoch.fetch_add(1, Ordering::SeqCst);
         if let Some(port) = self.current_port() {
             info!("[desktop:opencode] ready on port {port}");
+            self.remember_port(port).await;
         }
+
+        self.detect_config_reload_support().await;
+        self.detect_directory_scoping().await;
         Ok(())
     }
 
-    pub async fn restart(&self) -> Result<()> {
-        info!("[desktop:opencode] restarting...");
+    /// Restart, reporting `reason` (e.g. "agent update") while it is in progress.
+    /// Restarts run one at a time; one requested while another is still queued
+    /// joins it and returns its result.
+    pub async fn restart_for(&self, reason: &str) -> Result<()> {
+        let restart = {
+            let mut queued = self.queued_restart.write();
+            match queued.as_ref() {
+                Some(restart) => {
+                    info!(
+                        "[desktop:opencode] Restart already queued, joining it ({})",
+                        reason
+                    );
+                    restart.clone()
+                }
+                None => {
+                    let restart = self.queue_restart(reason);
+                    *queued = Some(restart.clone());
+                    restart
+                }
+            }
+        };
+        restart.await.map_err(|err| anyhow!(err))
+    }
+
+    fn queue_restart(&self, reason: &str) -> QueuedRestart {
+        let manager = self.clone();
+        let reason = reason.to_string();
+        // Run detached so a caller going away cannot cancel a restart halfway
+        let handle = tauri::async_runtime::spawn(async move {
+            let _operation = manager.operation.lock().await;
+            // Later requests need a restart of their own from here on
+            manager.queued_restart.write().take();
+            manager
+                .restart_locked(&reason)
+                .await
+                .map_err(|err| err.to_string())
+        });
+
+        async move {
+            match handle.await {
+                Ok(result) => result,
+                Err(err) => Err(err.to_string()),
+            }
+        }
+        .boxed()
+        .shared()
+    }
+
+    /// Recent restarts and crash recoveries with their reasons, oldest first
+    pub fn restart_history(&self) -> Vec<RestartRecord> {
+        self.restart_history.read().records()
+    }
+
+    pub fn last_restart(&self) -> Option<RestartRecord> {
+        self.restart_history.read().last()
+    }
+
+    /// Restarts and crash recoveries since launch
+    pub fn restart_count(&self) -> u64 {
+        self.restart_history.read().total()
+    }
+
+    async fn restart_locked(&self, reason: &str) -> Result<()> {
+        if self.is_adopted() {
+            if self.adopted_server_matches().await {
+                warn!("[desktop:opencode] Not restarting adopted OpenCode server");
+                if let Some(app) = self.app.get() {
+                    let _ = app.emit(
+                        RESTART_SKIPPED_EVENT,
+                        json!({ "reason": "adopted", "port": self.current_port() }),
+                    );
+                }
+                return Ok(());
+            }
+            // The workspace moved away from the adopted server: let it be and start our own
+            info!("[desktop:opencode] Releasing adopted server after directory change");
+            self.restart_history.write().record(reason);
+            self.release_adopted();
+            self.set_lifecycle(LifecycleState::Restarting, Some(reason.to_string()));
+            return self.ensure_running_locked().await;
+        }
+
+        info!("[desktop:opencode] restarting after {}...", reason);
+        self.restart_history.write().record(reason);
         self.is_ready.store(false, Ordering::SeqCst);
+        self.set_lifecycle(LifecycleState::Restarting, Some(reason.to_string()));
 
         self.graceful_stop().await?;
 
@@ -167,141 +781,1018 @@ impl OpenCodeManager {
             *self.port.write() = None;
         }
         *self.api_prefix.write() = String::new();
+        self.supports_config_reload.store(false, Ordering::SeqCst);
+        self.supports_directory_scoping.store(false, Ordering::SeqCst);
 
-        self.ensure_running().await
+        self.ensure_running_locked().await
     }
 
     pub async fn shutdown(&self) -> Result<()> {
         self.shutting_down.store(true, Ordering::SeqCst);
+        // A start stuck waiting for readiness must not hold up exit; its child
+        // is stopped below either way
+        let _operation = timeout(SHUTDOWN_OPERATION_WAIT, self.operation.lock())
+            .await
+            .ok();
         self.is_ready.store(false, Ordering::SeqCst);
+        self.set_lifecycle(LifecycleState::Stopped, None);
+        if self.is_adopted() {
+            info!("[desktop:opencode] Leaving adopted server running");
+            return Ok(());
+        }
         self.graceful_stop().await
     }
 
-    pub async fn set_working_directory(&self, new_dir: PathBuf) -> Result<()> {
-        *self.working_dir.write() = new_dir;
-        Ok(())
+    /// Watch our OpenCode process and restart it with backoff when it exits
+    /// unexpectedly. Adopted servers are not ours to restart.
+    pub fn spawn_supervisor(&self) {
+        let manager = self.clone();
+        tauri::async_runtime::spawn(async move {
+            loop {
+                tokio::time::sleep(SUPERVISOR_INTERVAL).await;
+                if manager.shutting_down.load(Ordering::SeqCst) {
+                    break;
+                }
+                if manager.is_adopted()
+                    || !matches!(
+                        manager.lifecycle.read().state(),
+                        LifecycleState::Ready | LifecycleState::Unresponsive
+                    )
+                {
+                    continue;
+                }
+                let Some(exit) = manager.take_exited_child().await else {
+                    continue;
+                };
+                warn!("[desktop:opencode] Process exited unexpectedly ({})", exit);
+                manager.emit_lifecycle(LifecycleEvent::Stopped);
+                manager
+                    .restart_history
+                    .write()
+                    .record(&format!("crash: exited with {exit}"));
+                manager.recover(format!("exited with {exit}")).await;
+            }
+        });
     }
 
-    pub fn get_working_directory(&self) -> PathBuf {
-        self.working_dir.read().clone()
+    /// Check /health every WATCHDOG_INTERVAL while ready. After WATCHDOG_MAX_MISSES
+    /// timeouts in a row OpenCode is marked unresponsive (and restarted when
+    /// `opencode.restartWhenUnresponsive` is set); it is marked ready again if
+    /// it starts answering. Idle while starting or restarting; ends on shutdown.
+    pub fn spawn_watchdog(&self) {
+        let manager = self.clone();
+        tauri::async_runtime::spawn(async move {
+            let mut misses = 0;
+            loop {
+                tokio::time::sleep(WATCHDOG_INTERVAL).await;
+                if manager.shutting_down.load(Ordering::SeqCst) {
+                    break;
+                }
+                let state = manager.lifecycle.read().state();
+                if !matches!(state, LifecycleState::Ready | LifecycleState::Unresponsive) {
+                    misses = 0;
+                    continue;
+                }
+
+                let epoch = manager.server_epoch();
+                let timed_out = manager.health_timed_out().await;
+                // A restart began or finished while probing
+                if manager.server_epoch() != epoch || manager.lifecycle.read().state() != state {
+                    misses = 0;
+                    continue;
+                }
+                if !timed_out {
+                    misses = 0;
+                    if state == LifecycleState::Unresponsive {
+                        manager.mark_responsive();
+                    }
+                    continue;
+                }
+                if state == LifecycleState::Unresponsive {
+                    continue;
+                }
+                misses += 1;
+                warn!(
+                    "[desktop:opencode] /health timed out ({} of {})",
+                    misses, WATCHDOG_MAX_MISSES
+                );
+                if misses >= WATCHDOG_MAX_MISSES {
+                    misses = 0;
+                    manager.mark_unresponsive().await;
+                }
+            }
+        });
     }
 
-    async fn detect_api_prefix(&self) -> Result<()> {
+    /// Whether /health went unanswered; errors other than a timeout mean the
+    /// server is alive (or gone, which the supervisor handles)
+    async fn health_timed_out(&self) -> bool {
         let Some(port) = self.current_port() else {
-            return Err(anyhow!("Cannot detect API prefix without port"));
+            return false;
         };
+        let url = format!("http://127.0.0.1:{port}{}/health", self.api_prefix());
+        match self
+            .http_client
+            .get(&url)
+            .timeout(WATCHDOG_PROBE_TIMEOUT)
+            .send()
+            .await
+        {
+            Ok(_) => false,
+            Err(err) => err.is_timeout(),
+        }
+    }
 
-        // Try empty prefix first (OpenCode default), then /api (some installations)
-        let candidates = ["", "/api"];
-        for candidate in candidates {
-            let base = if candidate.is_empty() {
-                format!("http://127.0.0.1:{port}")
-            } else {
-                format!("http://127.0.0.1:{port}{candidate}")
-            };
-
-            let url = format!("{base}/config");
-            match self.http_client.get(&url).send().await {
-                Ok(resp) if resp.status().is_success() => {
-                    // Validate it's actually JSON config, not HTML
-                    if let Ok(text) = resp.text().await {
-                        if text.trim().starts_with('{') || text.trim().starts_with('[') {
-                            info!("[desktop:opencode] Detected API prefix: {:?}", candidate);
-                            *self.api_prefix.write() = normalize_api_prefix(candidate);
-                            return Ok(());
-                        }
-                    }
-                }
-                _ => continue,
+    /// Whether /health answers right now, checked at most once per
+    /// REACHABILITY_CACHE_TTL. Any HTTP response counts: the server is up.
+    pub async fn reachability(&self) -> Reachability {
+        let mut cached = self.reachability.lock().await;
+        if let Some((checked_at, reachability)) = cached.as_ref() {
+            if checked_at.elapsed() < REACHABILITY_CACHE_TTL {
+                return reachability.clone();
             }
         }
+        let Some(port) = self.current_port() else {
+            *cached = None;
+            return Reachability::default();
+        };
 
-        info!("[desktop:opencode] No API prefix detected, using empty prefix");
-        *self.api_prefix.write() = String::new();
-        Ok(())
+        let url = format!("http://127.0.0.1:{port}{}/health", self.api_prefix());
+        let send = |method: reqwest::Method| {
+            self.http_client
+                .request(method, &url)
+                .timeout(REACHABILITY_PROBE_TIMEOUT)
+                .send()
+        };
+        let reachable = match send(reqwest::Method::HEAD).await {
+            Ok(response) if response.status() == reqwest::StatusCode::METHOD_NOT_ALLOWED => {
+                send(reqwest::Method::GET).await.is_ok()
+            }
+            Ok(_) => true,
+            Err(_) => false,
+        };
+        let last_checked_ms = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|elapsed| elapsed.as_millis() as u64)
+            .ok();
+        let reachability = Reachability {
+            reachable,
+            last_checked_ms,
+        };
+        *cached = Some((Instant::now(), reachability.clone()));
+        reachability
     }
 
-    pub fn current_port(&self) -> Option<u16> {
-        *self.port.read()
+    async fn mark_unresponsive(&self) {
+        warn!("[desktop:opencode] Server is unresponsive");
+        self.is_ready.store(false, Ordering::SeqCst);
+        self.set_lifecycle(
+            LifecycleState::Unresponsive,
+            Some(format!(
+                "no /health response in {} checks",
+                WATCHDOG_MAX_MISSES
+            )),
+        );
+        self.emit_status();
+
+        if self.is_adopted() || !self.restart_when_unresponsive().await {
+            return;
+        }
+        let manager = self.clone();
+        tauri::async_runtime::spawn(async move {
+            if let Err(err) = manager.restart_for("unresponsive server").await {
+                warn!(
+                    "[desktop:opencode] Restart of unresponsive server failed: {}",
+                    err
+                );
+            }
+        });
     }
 
-    pub fn api_prefix(&self) -> String {
-        self.api_prefix.read().clone()
+    fn mark_responsive(&self) {
+        info!("[desktop:opencode] Server is responding again");
+        self.is_ready.store(true, Ordering::SeqCst);
+        self.set_lifecycle(LifecycleState::Ready, None);
+        self.emit_status();
+        self.emit_ready();
     }
 
-    pub fn is_ready(&self) -> bool {
-        self.is_ready.load(Ordering::SeqCst)
+    fn emit_status(&self) {
+        if let Some(app) = self.app.get() {
+            let _ = app.emit(OPENCODE_STATUS_EVENT, self.status());
+        }
     }
 
-    pub fn rewrite_path(&self, incoming_path: &str) -> String {
-        // Strip /api prefix to get OpenCode path
-        let result = incoming_path
-            .strip_prefix("/api")
-            .map(|rest| if rest.is_empty() { "/" } else { rest })
-            .unwrap_or(incoming_path)
-            .to_string();
+    /// `opencode.restartWhenUnresponsive` from settings, off by default
+    async fn restart_when_unresponsive(&self) -> bool {
+        let Some(store) = self.settings.get() else {
+            return false;
+        };
+        store
+            .load()
+            .await
+            .ok()
+            .and_then(|settings| {
+                settings
+                    .get("opencode")?
+                    .get("restartWhenUnresponsive")?
+                    .as_bool()
+            })
+            .unwrap_or(false)
+    }
 
-        debug!(
-            "[opencode_manager] rewrite_path: '{}' -> '{}'",
-            incoming_path, result
-        );
-        result
+    /// Remove and describe our child process if it has exited
+    async fn take_exited_child(&self) -> Option<String> {
+        let mut guard = self.child.lock().await;
+        let status = guard.as_mut()?.try_wait().ok()??;
+        guard.take();
+        Some(status.to_string())
     }
 
-    async fn spawn_process(&self) -> Result<Child> {
-        let binary = self.binary.as_ref().ok_or_else(|| {
-            anyhow!("Cannot spawn process: OpenCode CLI is not available")
-        })?;
+    async fn recover(&self, mut reason: String) {
+        self.is_ready.store(false, Ordering::SeqCst);
+        if self.desired_port == 0 {
+            *self.port.write() = None;
+        }
+        *self.api_prefix.write() = String::new();
+        self.supports_config_reload.store(false, Ordering::SeqCst);
+        self.supports_directory_scoping.store(false, Ordering::SeqCst);
+
+        for attempt in 1..=opencode_lifecycle::MAX_CRASH_RETRIES {
+            let delay = opencode_lifecycle::crash_retry_delay(attempt);
+            self.lifecycle
+                .write()
+                .crashed(attempt, reason.clone(), delay);
+            tokio::time::sleep(delay).await;
+            if self.shutting_down.load(Ordering::SeqCst) {
+                return;
+            }
+            // A restart or directory change took over in the meantime
+            if self.lifecycle.read().state() != LifecycleState::Crashed {
+                return;
+            }
+            match self.ensure_running().await {
+                Ok(()) => {
+                    info!(
+                        "[desktop:opencode] Recovered after crash (attempt {})",
+                        attempt
+                    );
+                    return;
+                }
+                Err(err) => {
+                    warn!(
+                        "[desktop:opencode] Crash recovery attempt {} failed: {}",
+                        attempt, err
+                    );
+                    if self.lifecycle.read().state() == LifecycleState::Failed {
+                        return;
+                    }
+                    reason = err.to_string();
+                }
+            }
+        }
 
-        info!(
-            "[desktop:opencode] launching {} {:?}",
-            binary, self.args
+        warn!("[desktop:opencode] Giving up on crash recovery");
+        self.set_lifecycle(
+            LifecycleState::Stopped,
+            Some(format!(
+                "gave up after {} restart attempts: {}",
+                opencode_lifecycle::MAX_CRASH_RETRIES,
+                reason
+            )),
         );
+    }
 
-        let working_dir = self.working_dir.read().clone();
-        let mut cmd = Command::new(binary);
-        cmd.args(&self.args)
-            .current_dir(&working_dir)
-            .stdout(std::process::Stdio::piped())
-            .stderr(std::process::Stdio::piped())
-            .kill_on_drop(false);
-
-        for (key, value) in &self.env {
-            cmd.env(key, value);
+    /// Ports where an already running server may be listening, most specific first
+    async fn adoption_candidates(&self) -> Vec<u16> {
+        let hint = self.port_hint().await.map(|hint| hint.port);
+        let mut candidates = Vec::new();
+        for port in [Some(self.desired_port), *self.external_port.read(), hint]
+            .into_iter()
+            .flatten()
+        {
+            if port > 0 && !candidates.contains(&port) {
+                candidates.push(port);
+            }
         }
+        candidates
+    }
 
-        let mut child = cmd.spawn().map_err(|e| {
-            if e.kind() == std::io::ErrorKind::NotFound {
-                anyhow!(
-                    "OpenCode binary '{}' not found. Set OPENCODE_BINARY or ensure it's in PATH.",
-                    binary
+    /// Probe the candidate ports for a healthy OpenCode serving our working directory
+    /// and attach to it instead of spawning a second instance.
+    async fn try_adopt(&self) -> bool {
+        let working_dir = canonical(&self.get_working_directory());
+        let readiness = self.readiness_config().await;
+        for port in self.adoption_candidates().await {
+            for prefix in ["", "/api"] {
+                let healthy = timeout(
+                    ADOPTION_PROBE_TIMEOUT,
+                    self.check_endpoints(port, prefix, &readiness),
                 )
-            } else {
-                anyhow!("Failed to spawn OpenCode: {}", e)
-            }
-        })?;
+                .await
+                .map(|result| result.is_ok())
+                .unwrap_or(false);
+                if !healthy {
+                    continue;
+                }
 
-        // Set port immediately if pre-configured
+                match self.server_directory(port, prefix).await {
+                    Some(directory) if canonical(&directory) == working_dir => {
+                        info!(
+                            "[desktop:opencode] Adopting running server on port {} for {}",
+                            port,
+                            directory.display()
+                        );
+                        *self.port.write() = Some(port);
+                        *self.api_prefix.write() = normalize_api_prefix(prefix);
+                        self.adopted.store(true, Ordering::SeqCst);
+                        // Not started from our binary, so its upgrades are not ours to report
+                        *self.spawned_binary.write() = None;
+                        self.binary_updated.store(false, Ordering::SeqCst);
+                        self.is_ready.store(true, Ordering::SeqCst);
+                        self.server_epoch.fetch_add(1, Ordering::SeqCst);
+                        self.detect_config_reload_support().await;
+                        self.detect_directory_scoping().await;
+                        self.remember_port(port).await;
+                        return true;
+                    }
+                    Some(directory) => {
+                        info!(
+                            "[desktop:opencode] Server on port {} serves {}, not adopting",
+                            port,
+                            directory.display()
+                        );
+                    }
+                    None => {
+                        debug!(
+                            "[desktop:opencode] Server on port {} did not report a directory",
+                            port
+                        );
+                    }
+                }
+                break;
+            }
+        }
+        false
+    }
+
+    /// Port for the next spawn: the pinned port, else the port this directory used
+    /// last time when it is still free, else 0 so OpenCode picks one. A reused
+    /// port also restores the API prefix seen with it.
+    async fn choose_launch_port(&self) -> Result<u16> {
         if self.desired_port > 0 {
-            *self.port.write() = Some(self.desired_port);
+            // OpenCode would fail or bind elsewhere while we proxy to whatever holds the port
+            if !port_is_free(self.desired_port) {
+                return Err(anyhow!(
+                    "port {} (OPENCHAMBER_OPENCODE_PORT) is already in use by another process",
+                    self.desired_port
+                ));
+            }
+            return Ok(self.desired_port);
+        }
+        let Some(hint) = self.port_hint().await else {
+            return Ok(0);
+        };
+        if !port_is_free(hint.port) {
+            info!(
+                "[desktop:opencode] Last used port {} is taken, letting OpenCode pick",
+                hint.port
+            );
+            return Ok(0);
+        }
+        info!(
+            "[desktop:opencode] Reusing port {} for this directory",
+            hint.port
+        );
+        *self.api_prefix.write() = normalize_api_prefix(&hint.api_prefix);
+        Ok(hint.port)
+    }
+
+    async fn port_hint(&self) -> Option<PortHint> {
+        let settings = self.settings.get()?.load().await.ok()?;
+        port_hint_for(&settings, &self.get_working_directory())
+    }
+
+    /// Store the port and prefix the current directory became ready on
+    async fn remember_port(&self, port: u16) {
+        let Some(store) = self.settings.get() else {
+            return;
+        };
+        let Ok(mut settings) = store.load().await else {
+            return;
+        };
+        let hint = PortHint {
+            port,
+            api_prefix: self.api_prefix(),
+        };
+        if port_hint_for(&settings, &self.get_working_directory()).as_ref() == Some(&hint) {
+            return;
+        }
+        record_port_hint(&mut settings, &self.get_working_directory(), &hint);
+        if let Err(err) = store.save(settings).await {
+            debug!("[desktop:opencode] Failed to remember port: {}", err);
+        }
+    }
+
+    /// Working directory reported by the server's /path endpoint
+    async fn server_directory(&self, port: u16, prefix: &str) -> Option<PathBuf> {
+        let url = format!("http://127.0.0.1:{port}{prefix}/path");
+        let response = self
+            .http_client
+            .get(&url)
+            .timeout(ADOPTION_PROBE_TIMEOUT)
+            .send()
+            .await
+            .ok()?;
+        if !response.status().is_success() {
+            return None;
+        }
+        let info: PathInfo = response.json().await.ok()?;
+        info.directory
+            .or(info.worktree)
+            .filter(|dir| !dir.trim().is_empty())
+            .map(PathBuf::from)
+    }
+
+    async fn adopted_server_matches(&self) -> bool {
+        let Some(port) = self.current_port() else {
+            return false;
+        };
+        let working_dir = canonical(&self.get_working_directory());
+        self.server_directory(port, &self.api_prefix())
+            .await
+            .is_some_and(|directory| canonical(&directory) == working_dir)
+    }
+
+    /// Forget the adopted server without stopping it
+    fn release_adopted(&self) {
+        self.adopted.store(false, Ordering::SeqCst);
+        self.is_ready.store(false, Ordering::SeqCst);
+        *self.port.write() = None;
+        *self.api_prefix.write() = String::new();
+        self.supports_config_reload.store(false, Ordering::SeqCst);
+        self.supports_directory_scoping.store(false, Ordering::SeqCst);
+    }
+
+    /// Take our ready server out of this manager, leaving it stopped, so another
+    /// manager can own it. Adopted servers and fixed ports stay put.
+    pub(crate) async fn detach_server(&self) -> Option<RunningServer> {
+        let _operation = self.operation.lock().await;
+        if self.is_adopted() || !self.is_ready() || self.desired_port != 0 {
+            return None;
+        }
+        let port = self.current_port()?;
+        let child = self.child.lock().await.take()?;
+        let server = RunningServer {
+            child,
+            port,
+            api_prefix: self.api_prefix(),
+            supports_config_reload: self.supports_config_reload(),
+            supports_directory_scoping: self.supports_directory_scoping(),
+        };
+        self.is_ready.store(false, Ordering::SeqCst);
+        *self.port.write() = None;
+        *self.api_prefix.write() = String::new();
+        self.supports_config_reload.store(false, Ordering::SeqCst);
+        self.supports_directory_scoping.store(false, Ordering::SeqCst);
+        self.set_lifecycle(
+            LifecycleState::Stopped,
+            Some("moved to instance pool".to_string()),
+        );
+        Some(server)
+    }
+
+    /// Take over a server detached from another manager
+    pub(crate) async fn attach_server(&self, server: RunningServer) {
+        let _operation = self.operation.lock().await;
+        *self.child.lock().await = Some(server.child);
+        *self.port.write() = Some(server.port);
+        *self.api_prefix.write() = server.api_prefix;
+        self.supports_config_reload
+            .store(server.supports_config_reload, Ordering::SeqCst);
+        self.supports_directory_scoping
+            .store(server.supports_directory_scoping, Ordering::SeqCst);
+        self.adopted.store(false, Ordering::SeqCst);
+        self.is_ready.store(true, Ordering::SeqCst);
+        self.server_epoch.fetch_add(1, Ordering::SeqCst);
+        self.set_lifecycle(LifecycleState::Ready, None);
+        info!(
+            "[desktop:opencode] Took over server on port {} for {:?}",
+            server.port,
+            self.get_working_directory()
+        );
+        self.emit_ready();
+    }
+
+    pub async fn set_working_directory(&self, new_dir: PathBuf) -> Result<()> {
+        let config = self.resolve_config_path(&new_dir).await;
+        *self.working_dir.write() = new_dir;
+        *self.config_path.write() = config;
+        Ok(())
+    }
+
+    /// Config file passed to `--config`, if any
+    pub fn config_path(&self) -> Option<PathBuf> {
+        self.config_path.read().clone()
+    }
+
+    /// Config for `directory`: the path settings name for it, else its
+    /// PROJECT_CONFIG_FILE, else OPENCHAMBER_OPENCODE_CONFIG
+    pub async fn resolve_config_path(&self, directory: &Path) -> Option<PathBuf> {
+        if let Some(store) = self.settings.get() {
+            if let Ok(settings) = store.load().await {
+                if let Some(path) = project_config_for(&settings, directory) {
+                    if path.is_file() {
+                        return Some(path);
+                    }
+                    warn!(
+                        "[desktop:opencode] Configured OpenCode config {:?} does not exist",
+                        path
+                    );
+                }
+            }
+        }
+        let local = directory.join(PROJECT_CONFIG_FILE);
+        if local.is_file() {
+            return Some(local);
+        }
+        self.global_config.clone()
+    }
+
+    pub fn get_working_directory(&self) -> PathBuf {
+        self.working_dir.read().clone()
+    }
+
+    /// Whether `prefix` + /config answers with JSON config rather than an error or HTML
+    async fn serves_config(&self, port: u16, prefix: &str) -> bool {
+        let url = format!("http://127.0.0.1:{port}{prefix}/config");
+        match self.http_client.get(&url).send().await {
+            Ok(resp) if resp.status().is_success() => resp.text().await.is_ok_and(|text| {
+                let text = text.trim();
+                text.starts_with('{') || text.starts_with('[')
+            }),
+            _ => false,
+        }
+    }
+
+    async fn probe_api_prefix(&self, port: u16) -> Option<&'static str> {
+        for candidate in API_PREFIX_CANDIDATES {
+            if self.serves_config(port, candidate).await {
+                return Some(candidate);
+            }
+        }
+        None
+    }
+
+    async fn detect_api_prefix(&self) -> Result<()> {
+        let Some(port) = self.current_port() else {
+            return Err(anyhow!("Cannot detect API prefix without port"));
+        };
+
+        // Try empty prefix first (OpenCode default), then /api (some installations)
+        let prefix = match self.probe_api_prefix(port).await {
+            Some(candidate) => {
+                info!("[desktop:opencode] Detected API prefix: {:?}", candidate);
+                normalize_api_prefix(candidate)
+            }
+            None => {
+                info!("[desktop:opencode] No API prefix detected, using empty prefix");
+                String::new()
+            }
+        };
+        *self.api_prefix.write() = prefix.clone();
+        let previous = self.detected_prefix.write().replace(prefix.clone());
+        if let Some(previous) = previous.filter(|previous| *previous != prefix) {
+            warn!(
+                "[desktop:opencode] API prefix changed across restarts from {:?} to {:?}",
+                previous, prefix
+            );
+        }
+        Ok(())
+    }
+
+    /// Re-check the prefix of the running server, e.g. after an in-place
+    /// upgrade changed its routing. The current prefix is kept while /config
+    /// still answers under it, so missing resources alone never switch it.
+    pub async fn redetect_api_prefix(&self) -> Result<PrefixRedetection> {
+        let port = self
+            .current_port()
+            .ok_or_else(|| anyhow!("OpenCode is not running"))?;
+        let previous = self.api_prefix();
+        let unchanged = |current: String| PrefixRedetection {
+            previous: previous.clone(),
+            current,
+            changed: false,
+        };
+
+        if self.serves_config(port, &previous).await {
+            return Ok(unchanged(previous.clone()));
+        }
+        let Some(candidate) = self.probe_api_prefix(port).await else {
+            warn!("[desktop:opencode] API prefix re-detection found no working prefix");
+            return Ok(unchanged(previous.clone()));
+        };
+        let current = normalize_api_prefix(candidate);
+        {
+            let mut prefix = self.api_prefix.write();
+            // A restart or another re-detection got there first
+            if *prefix != previous || current == previous {
+                return Ok(unchanged(prefix.clone()));
+            }
+            *prefix = current.clone();
+        }
+
+        info!(
+            "[desktop:opencode] API prefix changed from {:?} to {:?}",
+            previous, current
+        );
+        if let Some(app) = self.app.get() {
+            let _ = app.emit(
+                API_PREFIX_CHANGED_EVENT,
+                json!({ "previous": previous, "current": current }),
+            );
+        }
+        self.remember_port(port).await;
+        Ok(PrefixRedetection {
+            previous,
+            current,
+            changed: true,
+        })
+    }
+
+    /// Newer OpenCode builds expose POST /instance/dispose, which drops the cached
+    /// instance so the next request re-reads config without a process restart.
+    async fn detect_config_reload_support(&self) {
+        let Some(port) = self.current_port() else {
+            return;
+        };
+        let url = format!("http://127.0.0.1:{port}{}/doc", self.api_prefix());
+
+        let supported = match self.http_client.get(&url).send().await {
+            Ok(resp) if resp.status().is_success() => resp
+                .text()
+                .await
+                .map(|spec| spec.contains("/instance/dispose"))
+                .unwrap_or(false),
+            _ => false,
+        };
+
+        info!(
+            "[desktop:opencode] Config hot-reload {}",
+            if supported {
+                "supported"
+            } else {
+                "not supported, will restart on config changes"
+            }
+        );
+        self.supports_config_reload.store(supported, Ordering::SeqCst);
+    }
+
+    pub fn supports_config_reload(&self) -> bool {
+        self.supports_config_reload.load(Ordering::SeqCst)
+    }
+
+    /// Ask /path about another directory: a server that scopes requests by the
+    /// `directory` query reports that directory instead of its own
+    async fn detect_directory_scoping(&self) {
+        let Some(port) = self.current_port() else {
+            return;
+        };
+        let probe = canonical(&std::env::temp_dir());
+        let Ok(mut url) = reqwest::Url::parse(&format!(
+            "http://127.0.0.1:{port}{}/path",
+            self.api_prefix()
+        )) else {
+            return;
+        };
+        url.query_pairs_mut()
+            .append_pair("directory", &probe.to_string_lossy());
+        let supported = match self.http_client.get(url).send().await {
+            Ok(resp) if resp.status().is_success() => resp
+                .json::<PathInfo>()
+                .await
+                .ok()
+                .and_then(|info| info.directory)
+                .is_some_and(|directory| canonical(Path::new(&directory)) == probe),
+            _ => false,
+        };
+        info!(
+            "[desktop:opencode] Directory scoping {}",
+            if supported {
+                "supported"
+            } else {
+                "not supported, will restart on directory changes"
+            }
+        );
+        self.supports_directory_scoping
+            .store(supported, Ordering::SeqCst);
+    }
+
+    pub fn supports_directory_scoping(&self) -> bool {
+        self.supports_directory_scoping.load(Ordering::SeqCst)
+    }
+
+    /// Point the running server at `new_dir` without restarting it, relying on
+    /// requests naming the directory. Changes nothing and returns false when the
+    /// server cannot scope by directory or the new project needs another config.
+    pub async fn retarget_directory(&self, new_dir: &Path) -> bool {
+        if !self.is_ready() || !self.supports_directory_scoping() {
+            return false;
+        }
+        if self.resolve_config_path(new_dir).await != self.config_path() {
+            return false;
+        }
+        *self.working_dir.write() = new_dir.to_path_buf();
+        info!(
+            "[desktop:opencode] Switched to {:?} without restarting",
+            new_dir
+        );
+        true
+    }
+
+    /// Ask the running server to re-read its configuration without restarting
+    pub async fn reload_config(&self) -> Result<()> {
+        let port = self
+            .current_port()
+            .ok_or_else(|| anyhow!("OpenCode is not running"))?;
+        let url = format!(
+            "http://127.0.0.1:{port}{}/instance/dispose",
+            self.api_prefix()
+        );
+        let resp = self.http_client.post(&url).send().await?;
+        if !resp.status().is_success() {
+            return Err(anyhow!("/instance/dispose returned {}", resp.status()));
+        }
+        info!("[desktop:opencode] Configuration hot-reloaded");
+        Ok(())
+    }
+
+    pub fn current_port(&self) -> Option<u16> {
+        *self.port.read()
+    }
+
+    pub fn api_prefix(&self) -> String {
+        self.api_prefix.read().clone()
+    }
+
+    pub fn is_ready(&self) -> bool {
+        self.is_ready.load(Ordering::SeqCst)
+    }
+
+    /// Resource usage of our OpenCode process; all None while it is not
+    /// running or when the server was adopted
+    pub async fn process_stats(&self) -> ProcessStats {
+        let pid = self.child.lock().await.as_ref().and_then(Child::id);
+        self.process_sampler.sample(pid).await
+    }
+
+    /// Version of the CLI, detected before each spawn
+    pub fn cli_version(&self) -> Option<CliVersion> {
+        self.cli_version.read().clone()
+    }
+
+    /// Set when the CLI is too old (or its version unknown); the server still starts
+    pub fn version_warning(&self) -> Option<String> {
+        self.cli_version.read().as_ref()?.warning.clone()
+    }
+
+    async fn detect_cli_version(&self) {
+        let Some(binary) = self.binary.read().clone() else {
+            return;
+        };
+        match opencode_version::detect(&binary, &self.env).await {
+            Ok(version) => {
+                match &version.warning {
+                    Some(warning) => warn!("[desktop:opencode] {}", warning),
+                    None => info!("[desktop:opencode] CLI version {}", version.raw),
+                }
+                *self.cli_version.write() = Some(version);
+            }
+            // Keep what an earlier start detected
+            Err(err) => warn!("[desktop:opencode] Version detection failed: {}", err),
+        }
+    }
+
+    /// The CLI was upgraded on disk and the running server is still the old one
+    pub fn update_available(&self) -> bool {
+        self.binary_updated.load(Ordering::SeqCst)
+    }
+
+    /// Compare the binary on disk with the one the server was spawned from,
+    /// emitting BINARY_UPDATED_EVENT the first time they differ
+    pub fn check_binary_update(&self) -> bool {
+        let Some(spawned) = self.spawned_binary.read().clone() else {
+            return false;
+        };
+        if self.update_available() {
+            return true;
+        }
+        let Some(binary) = self.binary.read().clone() else {
+            return false;
+        };
+        // Mid-upgrade the binary can briefly be missing; look again next time
+        let Some(current) = BinaryFingerprint::of(&binary) else {
+            return false;
+        };
+        if current == spawned {
+            return false;
+        }
+        // Only the first check to notice emits
+        if self.binary_updated.swap(true, Ordering::SeqCst) {
+            return true;
+        }
+        info!(
+            "[desktop:opencode] CLI binary changed on disk ({:?}); restart to apply",
+            current.path
+        );
+        if let Some(app) = self.app.get() {
+            let _ = app.emit(
+                BINARY_UPDATED_EVENT,
+                json!({
+                    "binary": current.path,
+                    "runningVersion": self.cli_version().and_then(|version| version.version),
+                }),
+            );
+        }
+        true
+    }
+
+    /// Check for an upgraded binary every BINARY_CHECK_INTERVAL until shutdown
+    pub fn spawn_binary_watch(&self) {
+        let manager = self.clone();
+        tauri::async_runtime::spawn(async move {
+            loop {
+                tokio::time::sleep(BINARY_CHECK_INTERVAL).await;
+                if manager.shutting_down.load(Ordering::SeqCst) {
+                    break;
+                }
+                manager.check_binary_update();
+            }
+        });
+    }
+
+    /// Restart only when the binary changed since the spawn; returns whether it did
+    pub async fn restart_if_updated(&self) -> Result<bool> {
+        if !self.check_binary_update() {
+            return Ok(false);
+        }
+        self.restart_for("OpenCode update").await?;
+        Ok(true)
+    }
+
+    /// Recent stdout/stderr lines of the current OpenCode process, oldest first
+    pub fn output_lines(&self) -> Vec<OutputLine> {
+        self.output_log.lines()
+    }
+
+    /// Changes whenever a (new) server process becomes ready
+    pub fn server_epoch(&self) -> u64 {
+        self.server_epoch.load(Ordering::SeqCst)
+    }
+
+    /// Upstream path for a request to the desktop's `/api` mount, under the
+    /// backend's detected API prefix
+    pub fn rewrite_path(&self, incoming_path: &str) -> String {
+        let result = with_api_prefix(&self.api_prefix(), &opencode_path(incoming_path));
+
+        debug!(
+            "[opencode_manager] rewrite_path: '{}' -> '{}'",
+            incoming_path, result
+        );
+        result
+    }
+
+    /// Launch overrides from the current settings, read on every spawn so edits
+    /// apply on the next restart. Invalid sections (only possible when edited by
+    /// hand; save_settings rejects them) are ignored.
+    async fn launch_overrides(&self) -> LaunchOverrides {
+        let Some(store) = self.settings.get() else {
+            return LaunchOverrides::default();
+        };
+        let settings = store.load().await.unwrap_or_default();
+        let Some(section) = settings.get("opencode") else {
+            return LaunchOverrides::default();
+        };
+        parse_launch_overrides(section).unwrap_or_else(|err| {
+            warn!("[desktop:opencode] Ignoring launch settings: {}", err);
+            LaunchOverrides::default()
+        })
+    }
+
+    /// Environment the next spawn passes to OpenCode, with secrets redacted;
+    /// for checking which PATH and provider variables it receives
+    pub async fn debug_environment(&self) -> BTreeMap<String, String> {
+        let overrides = self.launch_overrides().await;
+        self.redacted_environment(&overrides)
+    }
+
+    /// Overrides win over the inherited environment, as in spawn_process
+    fn redacted_environment(&self, overrides: &LaunchOverrides) -> BTreeMap<String, String> {
+        self.env
+            .iter()
+            .chain(&overrides.env)
+            .map(|(key, value)| (key.clone(), redact_env_value(key, value)))
+            .collect()
+    }
+
+    async fn spawn_process(&self) -> Result<Child> {
+        let binary = self
+            .binary
+            .read()
+            .clone()
+            .ok_or_else(|| anyhow!("Cannot spawn process: OpenCode CLI is not available"))?;
+
+        let overrides = self.launch_overrides().await;
+        info!(
+            "[desktop:opencode] launching {} {:?} {:?}",
+            binary, self.args, overrides.args
+        );
+        if !overrides.env.is_empty() {
+            let mut names: Vec<&str> = overrides.env.keys().map(String::as_str).collect();
+            names.sort_unstable();
+            info!("[desktop:opencode] extra environment: {}", names.join(", "));
+        }
+        debug!(
+            "[desktop:opencode] environment: {:?}",
+            self.redacted_environment(&overrides)
+        );
+
+        let launch_port = self.choose_launch_port().await?;
+        self.launch_port.store(launch_port, Ordering::SeqCst);
+
+        let working_dir = self.working_dir.read().clone();
+        // Settings or the project's file may have changed since the directory was set
+        let config = self.resolve_config_path(&working_dir).await;
+        if let Some(config) = &config {
+            info!("[desktop:opencode] using config {:?}", config);
+        }
+        *self.config_path.write() = config.clone();
+
+        let mut cmd = Command::new(&binary);
+        cmd.args(&self.args);
+        if let Some(config) = &config {
+            cmd.arg("--config").arg(config);
+        }
+        cmd.args(&overrides.args)
+            .arg("--port")
+            .arg(launch_port.to_string())
+            .current_dir(&working_dir)
+            .stdout(std::process::Stdio::piped())
+            .stderr(std::process::Stdio::piped())
+            .kill_on_drop(false);
+
+        for (key, value) in self.env.iter().chain(&overrides.env) {
+            cmd.env(key, value);
+        }
+        // Its own process group can receive CTRL_BREAK without it reaching us; the
+        // hidden console is what graceful_stop attaches to for sending it
+        #[cfg(windows)]
+        {
+            use windows_sys::Win32::System::Threading::{
+                CREATE_NEW_PROCESS_GROUP, CREATE_NO_WINDOW,
+            };
+            cmd.creation_flags(CREATE_NEW_PROCESS_GROUP | CREATE_NO_WINDOW);
+        }
+
+        let mut child = cmd.spawn().map_err(|e| {
+            if e.kind() == std::io::ErrorKind::NotFound {
+                anyhow!(
+                    "OpenCode binary '{}' not found. Set OPENCODE_BINARY or ensure it's in PATH.",
+                    binary
+                )
+            } else {
+                anyhow!("Failed to spawn OpenCode: {}", e)
+            }
+        })?;
+
+        self.output_log.clear();
+        *self.spawned_binary.write() = BinaryFingerprint::of(&binary);
+        self.binary_updated.store(false, Ordering::SeqCst);
+
+        // Set port immediately if pre-configured or reused
+        if launch_port > 0 {
+            *self.port.write() = Some(launch_port);
+            self.emit_lifecycle(LifecycleEvent::PortDetected { port: launch_port });
         }
 
         // Wait for first signal (stdout/stderr) within 750ms to confirm startup
         let first_signal_received = Arc::new(AtomicBool::new(false));
+        let mut readers = Vec::new();
 
         if let Some(stdout) = child.stdout.take() {
             let signal_flag = first_signal_received.clone();
-            self.spawn_output_reader(stdout, "stdout", move || {
+            readers.push(self.spawn_output_reader(stdout, "stdout", move || {
                 signal_flag.store(true, Ordering::SeqCst);
-            });
+            }));
         }
 
         if let Some(stderr) = child.stderr.take() {
             let signal_flag = first_signal_received.clone();
-            self.spawn_output_reader(stderr, "stderr", move || {
+            readers.push(self.spawn_output_reader(stderr, "stderr", move || {
                 signal_flag.store(true, Ordering::SeqCst);
-            });
+            }));
         }
+        *self.output_readers.lock() = readers;
 
         // Wait for first signal or timeout
         let start = std::time::Instant::now();
@@ -309,8 +1800,8 @@ impl OpenCodeManager {
             if first_signal_received.load(Ordering::SeqCst) {
                 break;
             }
-            if let Ok(Some(_)) = child.try_wait() {
-                return Err(anyhow!("OpenCode process exited immediately after spawn"));
+            if let Ok(Some(status)) = child.try_wait() {
+                return Err(self.startup_exit_error(status.to_string()).await);
             }
             tokio::time::sleep(Duration::from_millis(50)).await;
         }
@@ -323,7 +1814,8 @@ impl OpenCodeManager {
         stream: impl tokio::io::AsyncRead + Unpin + Send + 'static,
         label: &'static str,
         on_first_line: F,
-    ) where
+    ) -> tauri::async_runtime::JoinHandle<()>
+    where
         F: FnOnce() + Send + 'static,
     {
         let manager = self.clone();
@@ -339,9 +1831,29 @@ impl OpenCodeManager {
                 }
 
                 debug!("[opencode:{label}] {line}");
+                manager.output_log.push(label, &line);
                 manager.ingest_output_line(&line);
             }
-        });
+        })
+    }
+
+    /// Exit status of our child if it has exited, leaving it in place
+    async fn exited_status(&self) -> Option<String> {
+        let mut guard = self.child.lock().await;
+        let status = guard.as_mut()?.try_wait().ok()??;
+        Some(status.to_string())
+    }
+
+    /// Error for a process that exited during startup, carrying the end of its
+    /// output once the readers have caught up with it
+    async fn startup_exit_error(&self, status: String) -> anyhow::Error {
+        let readers = std::mem::take(&mut *self.output_readers.lock());
+        let _ = timeout(OUTPUT_DRAIN_TIMEOUT, join_all(readers)).await;
+        StartupExitError {
+            status,
+            output: self.output_log.tail_text_bytes(STARTUP_OUTPUT_MAX_BYTES),
+        }
+        .into()
     }
 
     fn ingest_output_line(&self, line: &str) {
@@ -350,7 +1862,10 @@ impl OpenCodeManager {
                 .name("port")
                 .and_then(|m| m.as_str().parse::<u16>().ok())
             {
-                *self.port.write() = Some(port_match);
+                let previous = self.port.write().replace(port_match);
+                if previous != Some(port_match) {
+                    self.emit_lifecycle(LifecycleEvent::PortDetected { port: port_match });
+                }
             }
 
             if let Some(path_match) = captures.name("path") {
@@ -370,69 +1885,194 @@ impl OpenCodeManager {
             if self.current_port().is_some() {
                 return Ok(());
             }
+            if let Some(status) = self.exited_status().await {
+                return Err(self.startup_exit_error(status).await);
+            }
             tokio::time::sleep(Duration::from_millis(100)).await;
         }
 
         Err(anyhow!("OpenCode did not report port within 15 seconds"))
     }
 
+    /// Readiness timeout and poll interval, from settings within sane bounds
+    async fn ready_check_timing(&self) -> (Duration, Duration) {
+        let section = match self.settings.get() {
+            Some(store) => store
+                .load()
+                .await
+                .ok()
+                .and_then(|settings| settings.get("opencode").cloned()),
+            None => None,
+        };
+        let setting = |key: &str| {
+            section
+                .as_ref()
+                .and_then(|section| section.get(key)?.as_u64())
+        };
+        let timeout_ms = setting("readyTimeoutMs")
+            .unwrap_or(READY_CHECK_TIMEOUT_MS)
+            .clamp(1_000, 300_000);
+        let interval_ms = setting("readyIntervalMs")
+            .unwrap_or(READY_CHECK_INTERVAL_MS)
+            .clamp(50, 5_000);
+        (
+            Duration::from_millis(timeout_ms),
+            Duration::from_millis(interval_ms),
+        )
+    }
+
     async fn wait_for_ready(&self) -> Result<()> {
         let Some(port) = self.current_port() else {
             return Err(anyhow!("Cannot check readiness without port"));
         };
 
-        let deadline = tokio::time::Instant::now() + Duration::from_millis(READY_CHECK_TIMEOUT_MS);
-        let mut last_error: Option<String> = None;
+        let (ready_timeout, interval) = self.ready_check_timing().await;
+        let readiness = self.readiness_config().await;
+        let deadline = tokio::time::Instant::now() + ready_timeout;
+        let mut attempts = 0;
+        let mut last_failure: Option<ReadinessFailure> = None;
 
         while tokio::time::Instant::now() < deadline {
+            attempts += 1;
             let api_prefix = self.api_prefix();
 
-            // Try /health, /config, /agent endpoints
-            match self.check_endpoints(port, &api_prefix).await {
+            match self.check_endpoints(port, &api_prefix, &readiness).await {
                 Ok(()) => {
                     // Once ready, attempt to detect and persist the API prefix for proxying
                     let _ = self.detect_api_prefix().await;
                     return Ok(());
                 }
-                Err(e) => {
-                    last_error = Some(e.to_string());
+                Err(failure) => {
+                    if last_failure.as_ref() != Some(&failure) {
+                        debug!("[desktop:opencode] not ready yet: {}", failure);
+                    }
+                    last_failure = Some(failure);
                 }
             }
+            // No point polling a process that is gone
+            if let Some(status) = self.exited_status().await {
+                return Err(self.startup_exit_error(status).await);
+            }
 
-            tokio::time::sleep(Duration::from_millis(READY_CHECK_INTERVAL_MS)).await;
+            tokio::time::sleep(interval).await;
         }
 
+        Err(ReadinessError {
+            waited_ms: ready_timeout.as_millis() as u64,
+            attempts,
+            last_failure,
+            recent_output: self.output_log.tail_text(READY_ERROR_OUTPUT_LINES),
+        }
+        .into())
+    }
+
+    /// Make sure the server that answered the ready check is OpenCode: its
+    /// /config is a JSON object and /path names a directory. Guards against
+    /// another service holding the port our child was told to use.
+    async fn verify_identity(&self) -> Result<()> {
+        let Some(port) = self.current_port() else {
+            return Err(anyhow!("Cannot verify OpenCode without port"));
+        };
+        let prefix = self.api_prefix();
+        let config_url = format!("http://127.0.0.1:{port}{prefix}/config");
+        let config_is_object = match self.http_client.get(&config_url).send().await {
+            Ok(resp) => resp
+                .json::<Value>()
+                .await
+                .is_ok_and(|config| config.is_object()),
+            Err(_) => false,
+        };
+        if config_is_object && self.server_directory(port, &prefix).await.is_some() {
+            return Ok(());
+        }
         Err(anyhow!(
-            "OpenCode not ready after {}ms: {}",
-            READY_CHECK_TIMEOUT_MS,
-            last_error.unwrap_or_else(|| "no error details".to_string())
+            "the server on port {} does not identify as OpenCode; another process may be using that port",
+            port
         ))
     }
 
-    async fn check_endpoints(&self, port: u16, prefix: &str) -> Result<()> {
-        let base_url = format!("http://127.0.0.1:{port}{prefix}");
+    /// Readiness endpoints from settings; defaults when unset or invalid
+    pub async fn readiness_config(&self) -> ReadinessConfig {
+        let Some(store) = self.settings.get() else {
+            return ReadinessConfig::default();
+        };
+        let Some(section) = store
+            .load()
+            .await
+            .ok()
+            .and_then(|settings| settings.get("opencode").cloned())
+        else {
+            return ReadinessConfig::default();
+        };
+        parse_readiness_config(&section).unwrap_or_else(|err| {
+            warn!("[desktop:opencode] Ignoring readiness settings: {}", err);
+            ReadinessConfig::default()
+        })
+    }
 
-        // Check /health
-        let health_url = format!("{base_url}/health");
-        let health_resp = self.http_client.get(&health_url).send().await?;
-        if !health_resp.status().is_success() {
-            return Err(anyhow!("/health returned {}", health_resp.status()));
-        }
+    /// Poll the cheap first endpoint; the heavier ones are checked only once it answers
+    async fn check_endpoints(
+        &self,
+        port: u16,
+        prefix: &str,
+        readiness: &ReadinessConfig,
+    ) -> Result<(), ReadinessFailure> {
+        let Some((probe, rest)) = readiness.endpoints.split_first() else {
+            return Ok(());
+        };
+        self.probe_endpoint(port, prefix, probe).await?;
+        self.validate_endpoints(port, prefix, rest, readiness.lenient)
+            .await
+    }
 
-        // Check /config
-        let config_url = format!("{base_url}/config");
-        let config_resp = self.http_client.get(&config_url).send().await?;
-        if !config_resp.status().is_success() {
-            return Err(anyhow!("/config returned {}", config_resp.status()));
+    /// HEAD the endpoint, falling back to GET for servers that only route GET
+    async fn probe_endpoint(
+        &self,
+        port: u16,
+        prefix: &str,
+        endpoint: &str,
+    ) -> Result<(), ReadinessFailure> {
+        let url = format!("http://127.0.0.1:{port}{prefix}{endpoint}");
+        let send = |method: reqwest::Method| {
+            self.http_client
+                .request(method, &url)
+                .timeout(READY_PROBE_TIMEOUT)
+                .send()
+        };
+        let mut response = send(reqwest::Method::HEAD)
+            .await
+            .map_err(|err| ReadinessFailure::unreachable(endpoint, &err))?;
+        if response.status() == reqwest::StatusCode::METHOD_NOT_ALLOWED {
+            response = send(reqwest::Method::GET)
+                .await
+                .map_err(|err| ReadinessFailure::unreachable(endpoint, &err))?;
         }
+        ReadinessFailure::check(endpoint, response.status())
+    }
 
-        // Check /agent
-        let agent_url = format!("{base_url}/agent");
-        let agent_resp = self.http_client.get(&agent_url).send().await?;
-        if !agent_resp.status().is_success() {
-            return Err(anyhow!("/agent returned {}", agent_resp.status()));
+    /// GET each endpoint (by default /config and /agent, which the UI needs
+    /// answered before it loads); when lenient, one the server lacks is skipped
+    async fn validate_endpoints(
+        &self,
+        port: u16,
+        prefix: &str,
+        endpoints: &[String],
+        lenient: bool,
+    ) -> Result<(), ReadinessFailure> {
+        for endpoint in endpoints {
+            let url = format!("http://127.0.0.1:{port}{prefix}{endpoint}");
+            let response = self
+                .http_client
+                .get(&url)
+                .send()
+                .await
+                .map_err(|err| ReadinessFailure::unreachable(endpoint, &err))?;
+            if lenient && response.status() == reqwest::StatusCode::NOT_FOUND {
+                debug!("[desktop:opencode] {} not implemented, skipping", endpoint);
+                continue;
+            }
+            ReadinessFailure::check(endpoint, response.status())?;
         }
-
         Ok(())
     }
 
@@ -446,6 +2086,7 @@ impl OpenCodeManager {
             // Already exited
             return Ok(());
         }
+        self.emit_lifecycle(LifecycleEvent::Stopping);
 
         // SIGTERM
         #[cfg(unix)]
@@ -459,39 +2100,251 @@ impl OpenCodeManager {
                 info!("[desktop:opencode] sent SIGTERM");
             }
         }
+        // CTRL_BREAK, which OpenCode handles like SIGTERM
         #[cfg(windows)]
         {
-            let _ = child.kill().await;
+            match child.id() {
+                Some(id) if send_ctrl_break(id) => info!("[desktop:opencode] sent CTRL_BREAK"),
+                _ => warn!("[desktop:opencode] could not send CTRL_BREAK"),
+            }
         }
 
         // Wait 3 seconds for graceful exit
         match timeout(Duration::from_secs(3), child.wait()).await {
             Ok(_) => {
                 info!("[desktop:opencode] exited gracefully");
+                self.emit_lifecycle(LifecycleEvent::Stopped);
                 return Ok(());
             }
             Err(_) => {
-                warn!("[desktop:opencode] did not exit after SIGTERM, sending SIGKILL");
+                warn!(
+                    "[desktop:opencode] did not exit after {}, sending {}",
+                    STOP_SIGNAL, KILL_SIGNAL
+                );
             }
         }
 
-        // SIGKILL
+        // SIGKILL, TerminateProcess on Windows
         let _ = child.kill().await;
 
         // Wait up to 5 seconds for hard kill
         match timeout(Duration::from_secs(5), child.wait()).await {
             Ok(_) => {
-                info!("[desktop:opencode] exited after SIGKILL");
+                info!("[desktop:opencode] exited after {} (forced)", KILL_SIGNAL);
             }
             Err(_) => {
-                warn!("[desktop:opencode] unresponsive after SIGKILL, continuing anyway");
+                warn!(
+                    "[desktop:opencode] unresponsive after {}, continuing anyway",
+                    KILL_SIGNAL
+                );
             }
         }
+        self.emit_lifecycle(LifecycleEvent::Stopped);
 
         Ok(())
     }
 }
 
+/// Send CTRL_BREAK to a process spawned with CREATE_NEW_PROCESS_GROUP. The app
+/// has no console of its own, so it attaches to the child's for the call and
+/// ignores the event itself meanwhile.
+#[cfg(windows)]
+fn send_ctrl_break(pid: u32) -> bool {
+    use windows_sys::Win32::System::Console::{
+        AttachConsole, FreeConsole, GenerateConsoleCtrlEvent, SetConsoleCtrlHandler,
+        CTRL_BREAK_EVENT,
+    };
+
+    // SAFETY: no pointers are passed; the console and handler changes are undone before returning
+    unsafe {
+        FreeConsole();
+        if AttachConsole(pid) == 0 {
+            return false;
+        }
+        SetConsoleCtrlHandler(None, 1);
+        let sent = GenerateConsoleCtrlEvent(CTRL_BREAK_EVENT, pid) != 0;
+        FreeConsole();
+        SetConsoleCtrlHandler(None, 0);
+        sent
+    }
+}
+
+fn canonical(path: &Path) -> PathBuf {
+    std::fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf())
+}
+
+/// Validate and read the readiness settings of an `opencode` settings section
+pub fn parse_readiness_config(section: &Value) -> Result<ReadinessConfig, String> {
+    let mut config = ReadinessConfig::default();
+    match section.get("readinessEndpoints") {
+        None | Some(Value::Null) => {}
+        Some(Value::Array(items)) => {
+            if items.is_empty() {
+                return Err("opencode.readinessEndpoints cannot be empty".to_string());
+            }
+            let mut endpoints = Vec::with_capacity(items.len());
+            for (index, item) in items.iter().enumerate() {
+                let Some(endpoint) = item.as_str() else {
+                    return Err(format!(
+                        "opencode.readinessEndpoints[{index}] must be a string"
+                    ));
+                };
+                if !endpoint.starts_with('/') || endpoint.chars().any(char::is_whitespace) {
+                    return Err(format!(
+                        "opencode.readinessEndpoints[{index}] must be a path starting with /"
+                    ));
+                }
+                endpoints.push(endpoint.to_string());
+            }
+            config.endpoints = endpoints;
+        }
+        Some(_) => return Err("opencode.readinessEndpoints must be an array of paths".to_string()),
+    }
+    match section.get("lenientReadiness") {
+        None | Some(Value::Null) => {}
+        Some(Value::Bool(lenient)) => config.lenient = *lenient,
+        Some(_) => return Err("opencode.lenientReadiness must be true or false".to_string()),
+    }
+    Ok(config)
+}
+
+/// Validate and read the launch overrides of an `opencode` settings section
+pub fn parse_launch_overrides(section: &Value) -> Result<LaunchOverrides, String> {
+    let mut overrides = LaunchOverrides::default();
+    match section.get("extraArgs") {
+        None | Some(Value::Null) => {}
+        Some(Value::Array(items)) => {
+            for (index, item) in items.iter().enumerate() {
+                let Some(arg) = item.as_str() else {
+                    return Err(format!("opencode.extraArgs[{index}] must be a string"));
+                };
+                if arg == "--port" || arg.starts_with("--port=") {
+                    return Err(
+                        "opencode.extraArgs cannot set --port; it is chosen by the app".to_string(),
+                    );
+                }
+                overrides.args.push(arg.to_string());
+            }
+        }
+        Some(_) => return Err("opencode.extraArgs must be an array of strings".to_string()),
+    }
+    match section.get("env") {
+        None | Some(Value::Null) => {}
+        Some(Value::Object(vars)) => {
+            for (name, value) in vars {
+                if name.is_empty() || name.contains('=') || name.contains('\0') {
+                    return Err(format!(
+                        "opencode.env has an invalid variable name {:?}",
+                        name
+                    ));
+                }
+                let Some(value) = value.as_str() else {
+                    return Err(format!("opencode.env.{name} must be a string"));
+                };
+                if value.contains('\0') {
+                    return Err(format!("opencode.env.{name} cannot contain NUL"));
+                }
+                overrides.env.insert(name.clone(), value.to_string());
+            }
+        }
+        Some(_) => return Err("opencode.env must be an object of strings".to_string()),
+    }
+    Ok(overrides)
+}
+
+fn hint_key(directory: &Path) -> String {
+    canonical(directory).to_string_lossy().to_string()
+}
+
+fn project_config_for(settings: &Value, directory: &Path) -> Option<PathBuf> {
+    let path = settings
+        .get(PROJECT_CONFIGS_KEY)?
+        .get(hint_key(directory))?
+        .as_str()?
+        .trim();
+    (!path.is_empty()).then(|| PathBuf::from(path))
+}
+
+/// Set (or with None, clear) the config file used for `directory`
+pub(crate) fn record_project_config(settings: &mut Value, directory: &Path, config: Option<&Path>) {
+    if !settings.is_object() {
+        *settings = Value::Object(Map::new());
+    }
+    let root = settings.as_object_mut().unwrap();
+    let configs = root
+        .entry(PROJECT_CONFIGS_KEY.to_string())
+        .or_insert_with(|| Value::Object(Map::new()));
+    if !configs.is_object() {
+        *configs = Value::Object(Map::new());
+    }
+    let configs = configs.as_object_mut().unwrap();
+    match config {
+        Some(config) => {
+            configs.insert(
+                hint_key(directory),
+                Value::String(config.to_string_lossy().to_string()),
+            );
+        }
+        None => {
+            configs.remove(&hint_key(directory));
+        }
+    }
+}
+
+fn port_hint_for(settings: &Value, directory: &Path) -> Option<PortHint> {
+    let entry = settings.get(PORT_HINTS_KEY)?.get(hint_key(directory))?;
+    let port = u16::try_from(entry.get("port")?.as_u64()?).ok()?;
+    Some(PortHint {
+        port,
+        api_prefix: entry
+            .get("apiPrefix")
+            .and_then(Value::as_str)
+            .unwrap_or_default()
+            .to_string(),
+    })
+}
+
+/// Insert `hint` for `directory`, keeping only the most recently used entries
+fn record_port_hint(settings: &mut Value, directory: &Path, hint: &PortHint) {
+    if !settings.is_object() {
+        *settings = Value::Object(Map::new());
+    }
+    let root = settings.as_object_mut().unwrap();
+    let hints = root
+        .entry(PORT_HINTS_KEY.to_string())
+        .or_insert_with(|| Value::Object(Map::new()));
+    if !hints.is_object() {
+        *hints = Value::Object(Map::new());
+    }
+    let hints = hints.as_object_mut().unwrap();
+    hints.insert(
+        hint_key(directory),
+        json!({
+            "port": hint.port,
+            "apiPrefix": hint.api_prefix,
+            "updatedAt": chrono::Utc::now().timestamp_millis(),
+        }),
+    );
+
+    while hints.len() > MAX_PORT_HINTS {
+        let oldest = hints
+            .iter()
+            .min_by_key(|(_, entry)| entry.get("updatedAt").and_then(Value::as_i64).unwrap_or(0))
+            .map(|(key, _)| key.clone());
+        match oldest {
+            Some(key) => {
+                hints.remove(&key);
+            }
+            None => break,
+        }
+    }
+}
+
+/// A port is free when we can bind it on loopback right now
+fn port_is_free(port: u16) -> bool {
+    std::net::TcpListener::bind(("127.0.0.1", port)).is_ok()
+}
+
 /// Check if CLI binary exists (can be called dynamically for polling)
 pub fn check_cli_exists() -> bool {
     if std::env::var("OPENCHAMBER_DISABLE_CLI").is_ok() {
@@ -505,14 +2358,35 @@ fn resolve_opencode_binary() -> Option<String> {
         return None;
     }
 
-    if let Ok(value) = std::env::var("OPENCODE_BINARY") {
+    let found = locate_binary(
+        std::env::var("OPENCODE_BINARY").ok(),
+        login_shell::detect,
+        std::env::var_os("PATH"),
+        &fallback_dirs(),
+    );
+    if found.is_none() {
+        warn!("[desktop:opencode] opencode binary not found");
+    }
+    found
+}
+
+/// The CLI from, in order: `override_binary` (OPENCODE_BINARY), the login
+/// shell's OPENCODE_BINARY and PATH, the process PATH, then `fallback_dirs`.
+/// The login shell is only asked when the override is unusable.
+fn locate_binary(
+    override_binary: Option<String>,
+    shell_env: impl FnOnce() -> login_shell::ShellEnv,
+    process_path: Option<OsString>,
+    fallback_dirs: &[PathBuf],
+) -> Option<String> {
+    if let Some(value) = override_binary {
         if !value.is_empty() && Path::new(&value).exists() {
             info!("[desktop:opencode] using binary from OPENCODE_BINARY env: {}", value);
             return Some(value);
         }
     }
 
-    let shell_env = detect_shell_env();
+    let shell_env = shell_env();
 
     if let Some(ref binary) = shell_env.opencode_binary {
         if Path::new(binary).exists() {
@@ -522,30 +2396,137 @@ fn resolve_opencode_binary() -> Option<String> {
     }
 
     if let Some(ref login_path) = shell_env.path {
-        for dir in login_path.split(':') {
-            let candidate = format!("{}/opencode", dir);
-            if Path::new(&candidate).exists() {
-                info!("[desktop:opencode] found binary in PATH: {}", candidate);
-                return Some(candidate);
-            }
+        if let Some(found) = find_in_path(OsStr::new(login_path)) {
+            info!("[desktop:opencode] found binary in PATH: {:?}", found);
+            return Some(found.to_string_lossy().to_string());
         }
     }
 
-    if let Some(home) = dirs::home_dir() {
-        let fallback = home.join(".opencode/bin/opencode");
-        if fallback.exists() {
-            info!("[desktop:opencode] found binary in fallback location: {:?}", fallback);
-            return Some(fallback.to_string_lossy().to_string());
+    // The login shell's PATH is unavailable on Windows and when the shell fails
+    if let Some(path) = process_path {
+        if let Some(found) = find_in_path(&path) {
+            info!(
+                "[desktop:opencode] found binary in process PATH: {:?}",
+                found
+            );
+            return Some(found.to_string_lossy().to_string());
         }
     }
 
-    warn!("[desktop:opencode] opencode binary not found");
+    for dir in fallback_dirs {
+        if let Some(found) = find_in_dir(dir) {
+            info!(
+                "[desktop:opencode] found binary in fallback location: {:?}",
+                found
+            );
+            return Some(found.to_string_lossy().to_string());
+        }
+    }
     None
 }
 
+/// File names the CLI may have: `opencode` on Unix, `opencode` plus each
+/// PATHEXT extension (opencode.exe, opencode.cmd, ...) on Windows
+fn binary_names() -> Vec<String> {
+    if !cfg!(windows) {
+        return vec!["opencode".to_string()];
+    }
+    let pathext = std::env::var("PATHEXT").unwrap_or_else(|_| ".COM;.EXE;.BAT;.CMD".to_string());
+    pathext
+        .split(';')
+        .map(str::trim)
+        .filter(|ext| ext.starts_with('.'))
+        .map(|ext| format!("opencode{}", ext.to_ascii_lowercase()))
+        .collect()
+}
+
+fn find_in_dir(dir: &Path) -> Option<PathBuf> {
+    binary_names()
+        .into_iter()
+        .map(|name| dir.join(name))
+        .find(|candidate| candidate.is_file())
+}
+
+/// First match in a PATH-style list, in PATH order
+fn find_in_path(path: &OsStr) -> Option<PathBuf> {
+    std::env::split_paths(path)
+        .filter(|dir| !dir.as_os_str().is_empty())
+        .find_map(|dir| find_in_dir(&dir))
+}
+
+/// Install locations checked when the CLI is not on PATH
+fn fallback_dirs() -> Vec<PathBuf> {
+    let mut candidates = Vec::new();
+    // Where desktop_install_opencode puts the CLI
+    if let Some(dir) = cli_install::install_dir() {
+        candidates.push(dir);
+    }
+    if let Some(home) = dirs::home_dir() {
+        candidates.push(home.join(".opencode").join("bin"));
+        candidates.push(home.join(".local").join("bin"));
+        candidates.push(home.join(".bun").join("bin"));
+        candidates.push(home.join(".npm-global").join("bin"));
+    }
+    if cfg!(windows) {
+        if let Some(local) = std::env::var_os("LOCALAPPDATA").map(PathBuf::from) {
+            candidates.push(local.join("Programs").join("opencode"));
+            candidates.push(local.join("Programs"));
+        }
+        // npm's global bin on Windows
+        if let Some(roaming) = std::env::var_os("APPDATA").map(PathBuf::from) {
+            candidates.push(roaming.join("npm"));
+        }
+    } else {
+        candidates.push(PathBuf::from("/opt/homebrew/bin"));
+        candidates.push(PathBuf::from("/usr/local/bin"));
+    }
+    candidates
+}
+
+const REDACTED: &str = "[redacted]";
+/// Variable name suffixes that hold credentials, e.g. ANTHROPIC_API_KEY
+const SECRET_ENV_SUFFIXES: [&str; 5] = ["_KEY", "_TOKEN", "_SECRET", "_PASSWORD", "_CREDENTIALS"];
+const SECRET_ENV_NAMES: [&str; 2] = ["AUTHORIZATION", "PASSWORD"];
+
+/// Whether an environment variable may hold a credential; names are compared
+/// case-insensitively since Windows does not distinguish them
+fn is_secret_env_name(name: &str) -> bool {
+    let name = name.to_ascii_uppercase();
+    SECRET_ENV_NAMES.contains(&name.as_str())
+        || SECRET_ENV_SUFFIXES
+            .iter()
+            .any(|suffix| name.ends_with(suffix))
+}
+
+/// `value`, or a placeholder when the variable may hold a credential
+fn redact_env_value(name: &str, value: &str) -> String {
+    if is_secret_env_name(name) {
+        REDACTED.to_string()
+    } else {
+        value.to_string()
+    }
+}
+
+#[cfg(test)]
+impl OpenCodeManager {
+    /// A manager for `directory` that treats a server already listening on
+    /// `port` as its ready OpenCode
+    pub(crate) fn ready_on(port: u16, directory: PathBuf) -> Self {
+        let manager = Self::new_with_directory(Some(directory));
+        *manager.port.write() = Some(port);
+        manager.is_ready.store(true, Ordering::SeqCst);
+        manager
+    }
+
+    /// Point at `port` as a restart would, or at no port while one runs
+    pub(crate) fn move_to(&self, port: Option<u16>) {
+        *self.port.write() = port;
+    }
+}
+
 fn build_augmented_env() -> HashMap<String, String> {
     let mut env: HashMap<String, String> = std::env::vars().collect();
-    if let Ok(login_path) = detect_login_shell_path() {
+    if let Some(login_path) = login_shell::detect().path {
         let current = env.get("PATH").cloned().unwrap_or_default();
         env.insert("PATH".to_string(), merge_paths(&login_path, &current));
     }
@@ -567,110 +2548,959 @@ fn merge_paths(login_path: &str, current: &str) -> String {
     segments.join(":")
 }
 
-#[derive(Default)]
-struct ShellEnv {
-    path: Option<String>,
-    opencode_binary: Option<String>,
-}
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use axum::{
+        http::StatusCode,
+        response::IntoResponse,
+        routing::{get, post},
+        Json, Router,
+    };
+    use nix::{sys::signal::kill, unistd::Pid};
+    use std::os::unix::fs::PermissionsExt;
+
+    /// A working directory with a fake `opencode` that records each server it
+    /// starts and its arguments, and points the manager at a mock serving that
+    /// directory on `port`
+    struct FakeCli {
+        root: PathBuf,
+        binary: PathBuf,
+        pids: PathBuf,
+        args: PathBuf,
+        port: u16,
+    }
 
-#[cfg(target_os = "macos")]
-fn get_user_shell() -> Option<String> {
-    use std::process::Command;
+    impl FakeCli {
+        async fn new() -> Self {
+            let root =
+                std::env::temp_dir().join(format!("openchamber-cli-{}", uuid::Uuid::new_v4()));
+            std::fs::create_dir_all(&root).unwrap();
+            let port = serve(serving(&root)).await;
+
+            let binary = root.join("opencode");
+            let pids = root.join("pids");
+            let args = root.join("args");
+            std::fs::write(
+                &binary,
+                format!(
+                    "#!/bin/sh\n\
+                     [ \"$1\" = --version ] && {{ echo 1.0.0; exit 0; }}\n\
+                     echo $$ >> '{}'\n\
+                     echo \"$*\" >> '{}'\n\
+                     echo \"opencode server listening on http://127.0.0.1:{}\"\n\
+                     exec sleep 600\n",
+                    pids.display(),
+                    args.display(),
+                    port
+                ),
+            )
+            .unwrap();
+            std::fs::set_permissions(&binary, std::fs::Permissions::from_mode(0o755)).unwrap();
+            Self {
+                root,
+                binary,
+                pids,
+                args,
+                port,
+            }
+        }
 
-    let username = dirs::home_dir()
-        .and_then(|p| p.file_name().map(|s| s.to_string_lossy().to_string()))?;
+        fn manager(&self) -> OpenCodeManager {
+            let manager = OpenCodeManager::new_with_directory(Some(self.root.clone()));
+            manager.set_binary(self.binary.to_string_lossy().to_string());
+            manager
+        }
 
-    let output = Command::new("dscl")
-        .args([".", "-read", &format!("/Users/{}", username), "UserShell"])
-        .output()
-        .ok()?;
-    if output.status.success() {
-        let stdout = String::from_utf8_lossy(&output.stdout);
-        stdout.split(':').nth(1).map(|s| s.trim().to_string())
-    } else {
-        None
-    }
-}
+        /// The `--port` each server was launched with
+        fn launch_ports(&self) -> Vec<String> {
+            std::fs::read_to_string(&self.args)
+                .unwrap_or_default()
+                .lines()
+                .filter_map(|line| {
+                    let mut words = line.split_whitespace();
+                    words.find(|word| *word == "--port")?;
+                    words.next().map(str::to_string)
+                })
+                .collect()
+        }
 
-#[cfg(all(unix, not(target_os = "macos")))]
-fn get_user_shell() -> Option<String> {
-    std::env::var("SHELL").ok()
-}
+        /// Every server process the fake CLI started
+        fn spawned(&self) -> Vec<i32> {
+            std::fs::read_to_string(&self.pids)
+                .unwrap_or_default()
+                .lines()
+                .filter_map(|line| line.trim().parse().ok())
+                .collect()
+        }
 
-#[cfg(not(unix))]
-fn get_user_shell() -> Option<String> {
-    None
-}
+        fn alive(&self) -> Vec<i32> {
+            self.spawned()
+                .into_iter()
+                .filter(|pid| kill(Pid::from_raw(*pid), None).is_ok())
+                .collect()
+        }
+    }
 
-fn build_shell_env_command(shell: &str) -> Vec<String> {
-    let shell_name = std::path::Path::new(shell)
-        .file_name()
-        .and_then(|s| s.to_str())
-        .unwrap_or("sh");
+    impl Drop for FakeCli {
+        fn drop(&mut self) {
+            for pid in self.alive() {
+                kill(Pid::from_raw(pid), nix::sys::signal::Signal::SIGKILL).ok();
+            }
+            std::fs::remove_dir_all(&self.root).ok();
+        }
+    }
 
-    match shell_name {
-        "bash" => vec![
-            "-lic".to_string(),
-            "source ~/.bashrc 2>/dev/null; echo \"__PATH__=$PATH\"; echo \"__OPENCODE_BINARY__=$OPENCODE_BINARY\"".to_string(),
-        ],
-        _ => vec![
-            "-lic".to_string(),
-            "echo \"__PATH__=$PATH\"; echo \"__OPENCODE_BINARY__=$OPENCODE_BINARY\"".to_string(),
-        ],
+    /// A mock OpenCode that reports `directory` from /path and answers
+    /// everything else with an empty object
+    fn serving(directory: &Path) -> Router {
+        let directory = directory.to_string_lossy().to_string();
+        Router::new()
+            .route(
+                "/path",
+                get(move || async move { Json(json!({ "directory": directory })) }),
+            )
+            .fallback(|| async { Json(json!({})) })
     }
-}
 
-fn detect_shell_env() -> ShellEnv {
-    #[cfg(not(unix))]
-    {
-        ShellEnv::default()
+    /// Serve `app` on a free loopback port
+    async fn serve(app: Router) -> u16 {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        tokio::spawn(async move {
+            axum::serve(listener, app).await.ok();
+        });
+        port
     }
-    #[cfg(unix)]
-    {
-        use std::process::Command;
 
-        let shell = get_user_shell().unwrap_or_else(|| "/bin/zsh".into());
-        info!("[desktop:opencode] detected user shell: {}", shell);
-        let args = build_shell_env_command(&shell);
-        info!("[desktop:opencode] shell args: {:?}", args);
+    async fn child_pid(manager: &OpenCodeManager) -> Option<i32> {
+        let child = manager.child.lock().await;
+        child.as_ref()?.id().map(|id| id as i32)
+    }
 
-        let output = match Command::new(&shell).args(&args).output() {
-            Ok(o) => o,
-            Err(e) => {
-                warn!("[desktop:opencode] failed to run shell {}: {}", shell, e);
-                return ShellEnv::default();
+    #[tokio::test(flavor = "multi_thread")]
+    async fn concurrent_restarts_leave_exactly_one_server() {
+        let cli = FakeCli::new().await;
+        let manager = cli.manager();
+        manager.ensure_running().await.unwrap();
+        assert_eq!(cli.spawned().len(), 1);
+
+        let requests = (0..8).map(|i| {
+            let manager = manager.clone();
+            async move {
+                if i % 3 == 2 {
+                    manager.ensure_running().await
+                } else {
+                    manager.restart_for(&format!("edit {i}")).await
+                }
             }
+        });
+        for result in join_all(requests).await {
+            result.unwrap();
+        }
+
+        // Six restart requests: one runs, the rest join the one queued behind it
+        let restarts = manager.restart_count();
+        assert!((1..=2).contains(&restarts), "{restarts} restarts");
+        assert_eq!(cli.spawned().len() as u64, 1 + restarts);
+        assert!(manager.is_ready());
+        assert_eq!(cli.alive(), vec![child_pid(&manager).await.unwrap()]);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn shutdown_during_a_restart_leaves_no_server() {
+        let cli = FakeCli::new().await;
+        let manager = cli.manager();
+        manager.ensure_running().await.unwrap();
+
+        let restart = {
+            let manager = manager.clone();
+            tokio::spawn(async move { manager.restart_for("edit").await })
         };
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        manager.shutdown().await.unwrap();
+        // The restart either finished before shutdown or was refused by it
+        restart.await.unwrap().ok();
+        // A restart outliving the shutdown wait is stopped along with its child
+        manager.shutdown().await.unwrap();
+
+        assert!(!manager.is_ready());
+        assert!(cli.alive().is_empty(), "left running: {:?}", cli.alive());
+        assert!(manager.ensure_running().await.is_err());
+        assert!(cli.alive().is_empty());
+    }
+
+    /// A manager whose server is `app`, served on a free port
+    async fn served_by(app: Router) -> OpenCodeManager {
+        OpenCodeManager::ready_on(serve(app).await, std::env::temp_dir())
+    }
 
-        if !output.status.success() {
-            let stderr = String::from_utf8_lossy(&output.stderr);
-            warn!("[desktop:opencode] shell env detection failed for {}, stderr: {}", shell, stderr);
-            return ShellEnv::default();
+    #[tokio::test]
+    async fn config_hot_reload_is_used_when_the_server_documents_it() {
+        let disposed = Arc::new(AtomicU64::new(0));
+        let counter = disposed.clone();
+        let manager = served_by(
+            Router::new()
+                .route(
+                    "/doc",
+                    get(|| async { r#"{"paths":{"/instance/dispose":{}}}"# }),
+                )
+                .route(
+                    "/instance/dispose",
+                    post(move || async move {
+                        counter.fetch_add(1, Ordering::SeqCst);
+                        Json(true)
+                    }),
+                ),
+        )
+        .await;
+
+        manager.detect_config_reload_support().await;
+        assert!(manager.supports_config_reload());
+        manager.reload_config().await.unwrap();
+        assert_eq!(disposed.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn older_servers_fall_back_to_restarts() {
+        let manager = served_by(
+            Router::new()
+                .route("/doc", get(|| async { r#"{"paths":{"/session":{}}}"# }))
+                .route(
+                    "/instance/dispose",
+                    post(|| async { axum::http::StatusCode::NOT_FOUND }),
+                ),
+        )
+        .await;
+
+        manager.detect_config_reload_support().await;
+        assert!(!manager.supports_config_reload());
+        assert!(manager.reload_config().await.is_err());
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn a_server_already_serving_the_workspace_is_adopted() {
+        let cli = FakeCli::new().await;
+        let manager = cli.manager();
+        manager.set_external_port(Some(cli.port));
+
+        manager.ensure_running().await.unwrap();
+
+        assert!(manager.is_adopted());
+        assert!(manager.is_ready());
+        assert_eq!(manager.current_port(), Some(cli.port));
+        assert!(cli.spawned().is_empty());
+        assert!(child_pid(&manager).await.is_none());
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn a_server_for_another_directory_is_not_adopted() {
+        let cli = FakeCli::new().await;
+        let elsewhere = serve(serving(&std::env::temp_dir())).await;
+        let manager = cli.manager();
+        manager.set_external_port(Some(elsewhere));
+
+        manager.ensure_running().await.unwrap();
+
+        assert!(!manager.is_adopted());
+        assert_eq!(cli.spawned().len(), 1);
+        assert_eq!(manager.current_port(), Some(cli.port));
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn servers_that_fail_the_readiness_checks_are_not_adopted() {
+        let cli = FakeCli::new().await;
+        let broken = serve(
+            Router::new().fallback(|| async { axum::http::StatusCode::INTERNAL_SERVER_ERROR }),
+        )
+        .await;
+        let manager = cli.manager();
+        manager.set_external_port(Some(broken));
+
+        manager.ensure_running().await.unwrap();
+
+        assert!(!manager.is_adopted());
+        assert_eq!(cli.spawned().len(), 1);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn adopted_servers_are_neither_restarted_nor_stopped() {
+        let cli = FakeCli::new().await;
+        let manager = cli.manager();
+        manager.set_external_port(Some(cli.port));
+        manager.ensure_running().await.unwrap();
+
+        manager.restart_for("agent update").await.unwrap();
+        assert!(manager.is_adopted());
+        assert!(manager.is_ready());
+        assert_eq!(manager.restart_count(), 0);
+        assert!(cli.spawned().is_empty());
+
+        manager.shutdown().await.unwrap();
+        assert!(!manager.is_ready());
+        let still_serving = reqwest::get(format!("http://127.0.0.1:{}/path", cli.port))
+            .await
+            .unwrap();
+        assert!(still_serving.status().is_success());
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn changing_directory_releases_the_adopted_server() {
+        let cli = FakeCli::new().await;
+        let manager = cli.manager();
+        manager.set_external_port(Some(cli.port));
+        manager.ensure_running().await.unwrap();
+
+        let elsewhere =
+            std::env::temp_dir().join(format!("openchamber-moved-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&elsewhere).unwrap();
+        manager
+            .set_working_directory(elsewhere.clone())
+            .await
+            .unwrap();
+        let restarted = manager.restart_for("directory change").await;
+        std::fs::remove_dir_all(&elsewhere).ok();
+        restarted.unwrap();
+
+        assert!(!manager.is_adopted());
+        assert!(manager.is_ready());
+        assert_eq!(manager.restart_count(), 1);
+        assert_eq!(cli.spawned().len(), 1);
+        manager.shutdown().await.unwrap();
+        assert!(cli.alive().is_empty());
+    }
+
+    /// Settings for `cli` kept in its directory, attached to `manager`
+    fn attach_settings(cli: &FakeCli, manager: &OpenCodeManager) -> Arc<SettingsStore> {
+        let store = Arc::new(SettingsStore::at(cli.root.join("settings.json")));
+        manager.attach_settings(store.clone());
+        store
+    }
+
+    async fn hint(store: &SettingsStore, directory: &Path, port: u16, api_prefix: &str) {
+        let mut settings = store.load().await.unwrap();
+        let hint = PortHint {
+            port,
+            api_prefix: api_prefix.to_string(),
+        };
+        record_port_hint(&mut settings, directory, &hint);
+        store.save(settings).await.unwrap();
+    }
+
+    fn free_port() -> u16 {
+        std::net::TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port()
+    }
+
+    #[test]
+    fn port_hints_are_keyed_by_canonical_directory() {
+        let root = std::env::temp_dir().join(format!("openchamber-hints-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(root.join("project")).unwrap();
+        std::os::unix::fs::symlink(root.join("project"), root.join("link")).unwrap();
+        let hint = PortHint {
+            port: 4096,
+            api_prefix: "/api".to_string(),
+        };
+
+        let mut settings = json!("not an object");
+        record_port_hint(&mut settings, &root.join("project"), &hint);
+        let found = [
+            port_hint_for(&settings, &root.join("project")),
+            port_hint_for(&settings, &root.join("project/.")),
+            port_hint_for(&settings, &root.join("link")),
+            port_hint_for(&settings, &root),
+        ];
+        std::fs::remove_dir_all(&root).ok();
+
+        assert_eq!(found[0].as_ref(), Some(&hint));
+        assert_eq!(found[1].as_ref(), Some(&hint));
+        assert_eq!(found[2].as_ref(), Some(&hint));
+        assert_eq!(found[3], None);
+        assert_eq!(settings[PORT_HINTS_KEY].as_object().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn only_the_most_recent_port_hints_are_kept() {
+        let mut settings = json!({});
+        for i in 0..MAX_PORT_HINTS {
+            let hint = PortHint {
+                port: 5000 + i as u16,
+                api_prefix: String::new(),
+            };
+            record_port_hint(&mut settings, Path::new(&format!("/nowhere/{i}")), &hint);
+            settings[PORT_HINTS_KEY][format!("/nowhere/{i}")]["updatedAt"] = json!(i);
         }
+        let newest = PortHint {
+            port: 6000,
+            api_prefix: String::new(),
+        };
+        record_port_hint(&mut settings, Path::new("/nowhere/new"), &newest);
 
-        let stdout = String::from_utf8_lossy(&output.stdout);
-        info!("[desktop:opencode] shell stdout length: {}", stdout.len());
-        let mut env = ShellEnv::default();
+        assert_eq!(
+            settings[PORT_HINTS_KEY].as_object().unwrap().len(),
+            MAX_PORT_HINTS
+        );
+        assert_eq!(port_hint_for(&settings, Path::new("/nowhere/0")), None);
+        assert!(port_hint_for(&settings, Path::new("/nowhere/1")).is_some());
+        assert_eq!(
+            port_hint_for(&settings, Path::new("/nowhere/new")),
+            Some(newest)
+        );
+    }
 
-        for line in stdout.lines() {
-            if let Some(path) = line.strip_prefix("__PATH__=") {
-                if !path.is_empty() {
-                    env.path = Some(path.to_string());
-                }
-            } else if let Some(binary) = line.strip_prefix("__OPENCODE_BINARY__=") {
-                if !binary.is_empty() {
-                    env.opencode_binary = Some(binary.to_string());
+    #[tokio::test]
+    async fn launch_ports_reuse_free_hints_and_their_prefix() {
+        let cli = FakeCli::new().await;
+        let manager = cli.manager();
+        assert_eq!(manager.choose_launch_port().await.unwrap(), 0);
+
+        let store = attach_settings(&cli, &manager);
+        assert_eq!(manager.choose_launch_port().await.unwrap(), 0);
+
+        let free = free_port();
+        hint(&store, &cli.root, free, "api").await;
+        assert_eq!(manager.choose_launch_port().await.unwrap(), free);
+        assert_eq!(manager.api_prefix(), "/api");
+    }
+
+    #[tokio::test]
+    async fn taken_hints_let_opencode_pick_and_keep_the_prefix_unset() {
+        let cli = FakeCli::new().await;
+        let manager = cli.manager();
+        let store = attach_settings(&cli, &manager);
+        let taken = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        hint(
+            &store,
+            &cli.root,
+            taken.local_addr().unwrap().port(),
+            "/api",
+        )
+        .await;
+
+        assert_eq!(manager.choose_launch_port().await.unwrap(), 0);
+        assert_eq!(manager.api_prefix(), "");
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn ready_servers_are_remembered_for_the_next_launch() {
+        let cli = FakeCli::new().await;
+        let manager = cli.manager();
+        let store = attach_settings(&cli, &manager);
+
+        manager.ensure_running().await.unwrap();
+        let settings = store.load().await.unwrap();
+        assert_eq!(
+            port_hint_for(&settings, &cli.root),
+            Some(PortHint {
+                port: cli.port,
+                api_prefix: String::new(),
+            })
+        );
+        manager.shutdown().await.unwrap();
+
+        // A free remembered port is passed to the next launch
+        let free = free_port();
+        hint(&store, &cli.root, free, "").await;
+        let next = cli.manager();
+        next.attach_settings(store.clone());
+        next.ensure_running().await.unwrap();
+        next.shutdown().await.unwrap();
+
+        assert_eq!(cli.launch_ports(), ["0".to_string(), free.to_string()]);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn a_taken_remembered_port_falls_back_to_port_zero() {
+        let cli = FakeCli::new().await;
+        let other = serve(Router::new()).await;
+        let manager = cli.manager();
+        let store = attach_settings(&cli, &manager);
+        hint(&store, &cli.root, other, "").await;
+
+        manager.ensure_running().await.unwrap();
+        manager.shutdown().await.unwrap();
+
+        assert!(!manager.is_adopted());
+        assert_eq!(cli.launch_ports(), ["0"]);
+        let settings = store.load().await.unwrap();
+        assert_eq!(port_hint_for(&settings, &cli.root).unwrap().port, cli.port);
+    }
+
+    /// A server whose API lives under whichever prefix the test last picked.
+    /// The root serves the web UI, so a moved API answers /config with HTML.
+    async fn relocating_api(prefix: &'static str) -> (OpenCodeManager, Arc<RwLock<&'static str>>) {
+        let active = Arc::new(RwLock::new(prefix));
+        let root = active.clone();
+        let nested = active.clone();
+        let app = Router::new()
+            .route(
+                "/config",
+                get(move || async move {
+                    if root.read().is_empty() {
+                        Json(json!({})).into_response()
+                    } else {
+                        "<!doctype html><title>OpenCode</title>".into_response()
+                    }
+                }),
+            )
+            .route(
+                "/api/config",
+                get(move || async move {
+                    if *nested.read() == "/api" {
+                        Json(json!({})).into_response()
+                    } else {
+                        StatusCode::NOT_FOUND.into_response()
+                    }
+                }),
+            );
+        let manager = served_by(app).await;
+        *manager.api_prefix.write() = prefix.to_string();
+        (manager, active)
+    }
+
+    #[tokio::test]
+    async fn a_working_prefix_is_kept() {
+        let (manager, _active) = relocating_api("/api").await;
+
+        let result = manager.redetect_api_prefix().await.unwrap();
+        assert!(!result.changed);
+        assert_eq!(result.previous, "/api");
+        assert_eq!(result.current, "/api");
+        assert_eq!(manager.api_prefix(), "/api");
+    }
+
+    #[tokio::test]
+    async fn a_moved_api_is_followed() {
+        let (manager, active) = relocating_api("").await;
+        let root =
+            std::env::temp_dir().join(format!("openchamber-prefix-{}", uuid::Uuid::new_v4()));
+        let store = Arc::new(SettingsStore::at(root.join("settings.json")));
+        manager.attach_settings(store.clone());
+
+        // OpenCode was upgraded in place and now serves its API under /api
+        *active.write() = "/api";
+        let result = manager.redetect_api_prefix().await.unwrap();
+        assert!(result.changed);
+        assert_eq!(result.previous, "");
+        assert_eq!(result.current, "/api");
+        assert_eq!(manager.api_prefix(), "/api");
+
+        // The corrected prefix is remembered for the next launch
+        let settings = store.load().await.unwrap();
+        let hint = port_hint_for(&settings, &manager.get_working_directory()).unwrap();
+        assert_eq!(hint.api_prefix, "/api");
+
+        // And back again
+        *active.write() = "";
+        let result = manager.redetect_api_prefix().await.unwrap();
+        assert!(result.changed);
+        assert_eq!(manager.api_prefix(), "");
+        std::fs::remove_dir_all(root).ok();
+    }
+
+    #[tokio::test]
+    async fn no_working_prefix_keeps_the_current_one() {
+        let (manager, active) = relocating_api("/api").await;
+
+        *active.write() = "/v2";
+        let result = manager.redetect_api_prefix().await.unwrap();
+        assert!(!result.changed);
+        assert_eq!(manager.api_prefix(), "/api");
+    }
+
+    #[tokio::test]
+    async fn redetection_needs_a_running_server() {
+        let manager = OpenCodeManager::new_with_directory(Some(std::env::temp_dir()));
+        assert!(manager.redetect_api_prefix().await.is_err());
+    }
+
+    #[test]
+    fn only_the_desktop_api_mount_is_stripped() {
+        assert_eq!(opencode_path("/api"), "/");
+        assert_eq!(opencode_path("/api/"), "/");
+        assert_eq!(opencode_path("/api/config"), "/config");
+        assert_eq!(opencode_path("/apiary"), "/apiary");
+        assert_eq!(opencode_path("/session"), "/session");
+    }
+
+    #[test]
+    fn paths_are_rewritten_under_the_backend_prefix() {
+        let manager = OpenCodeManager::new_with_directory(Some(std::env::temp_dir()));
+        assert_eq!(manager.rewrite_path("/api"), "/");
+        assert_eq!(manager.rewrite_path("/api/config"), "/config");
+
+        *manager.api_prefix.write() = "/api".to_string();
+        assert_eq!(manager.rewrite_path("/api"), "/api");
+        assert_eq!(manager.rewrite_path("/api/config"), "/api/config");
+    }
+
+    #[tokio::test]
+    async fn detected_prefixes_are_remembered_across_restarts() {
+        let (manager, active) = relocating_api("").await;
+        *manager.api_prefix.write() = String::new();
+
+        manager.detect_api_prefix().await.unwrap();
+        assert_eq!(manager.api_prefix(), "");
+        assert_eq!(manager.rewrite_path("/api/config"), "/config");
+
+        // The restarted server serves its API under /api
+        *active.write() = "/api";
+        manager.detect_api_prefix().await.unwrap();
+        assert_eq!(manager.api_prefix(), "/api");
+        assert_eq!(manager.rewrite_path("/api/config"), "/api/config");
+        assert_eq!(manager.detected_prefix.read().as_deref(), Some("/api"));
+    }
+
+    /// Directories that may hold an `opencode` binary, removed on drop
+    struct BinDirs(PathBuf);
+
+    impl BinDirs {
+        fn new() -> Self {
+            let root =
+                std::env::temp_dir().join(format!("openchamber-bin-{}", uuid::Uuid::new_v4()));
+            std::fs::create_dir_all(&root).unwrap();
+            Self(root)
+        }
+
+        fn dir(&self, name: &str) -> PathBuf {
+            let dir = self.0.join(name);
+            std::fs::create_dir_all(&dir).unwrap();
+            dir
+        }
+
+        /// `name/opencode`, returned as the resolver reports it
+        fn binary(&self, name: &str) -> String {
+            let binary = self.dir(name).join("opencode");
+            std::fs::write(&binary, "#!/bin/sh\n").unwrap();
+            std::fs::set_permissions(&binary, std::fs::Permissions::from_mode(0o755)).unwrap();
+            binary.to_string_lossy().to_string()
+        }
+
+        fn path(&self, names: &[&str]) -> OsString {
+            std::env::join_paths(names.iter().map(|name| {
+                if name.is_empty() {
+                    PathBuf::new()
+                } else {
+                    self.dir(name)
                 }
-            }
+            }))
+            .unwrap()
+        }
+    }
+
+    impl Drop for BinDirs {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.0);
+        }
+    }
+
+    #[test]
+    fn path_entries_are_searched_in_order() {
+        let bins = BinDirs::new();
+        let second = bins.binary("second");
+        bins.binary("third");
+        // A directory named like the binary is not it
+        bins.dir("first/opencode");
+
+        let found = find_in_path(&bins.path(&["", "first", "second", "third"])).unwrap();
+        assert_eq!(found.to_string_lossy(), second);
+        assert_eq!(find_in_path(&bins.path(&["first", "empty"])), None);
+        assert_eq!(find_in_path(OsStr::new("")), None);
+    }
+
+    #[test]
+    fn the_binary_override_wins_without_asking_the_login_shell() {
+        let bins = BinDirs::new();
+        let custom = bins.binary("custom");
+        bins.binary("path");
+
+        let found = locate_binary(
+            Some(custom.clone()),
+            || panic!("the login shell was probed"),
+            Some(bins.path(&["path"])),
+            &[],
+        );
+        assert_eq!(found, Some(custom));
+    }
+
+    #[test]
+    fn binaries_are_resolved_in_priority_order() {
+        let bins = BinDirs::new();
+        let shell_binary = bins.binary("shell-binary");
+        let login = bins.binary("login");
+        let process = bins.binary("process");
+        let fallback = bins.binary("fallback");
+        let fallbacks = [bins.dir("empty"), bins.dir("fallback")];
+        let missing = bins
+            .0
+            .join("missing/opencode")
+            .to_string_lossy()
+            .to_string();
+        let shell = |binary: Option<&str>, path: &[&str]| {
+            let env = login_shell::ShellEnv {
+                path: (!path.is_empty()).then(|| bins.path(path).to_string_lossy().to_string()),
+                opencode_binary: binary.map(str::to_string),
+            };
+            move || env
+        };
+
+        // An override or shell binary that does not exist is skipped
+        let found = locate_binary(
+            Some(missing.clone()),
+            shell(Some(&shell_binary), &["login"]),
+            Some(bins.path(&["process"])),
+            &fallbacks,
+        );
+        assert_eq!(found.as_ref(), Some(&shell_binary));
+
+        let found = locate_binary(
+            Some(String::new()),
+            shell(Some(&missing), &["empty", "login"]),
+            Some(bins.path(&["process"])),
+            &fallbacks,
+        );
+        assert_eq!(found.as_ref(), Some(&login));
+
+        let found = locate_binary(
+            None,
+            shell(None, &["empty"]),
+            Some(bins.path(&["process"])),
+            &fallbacks,
+        );
+        assert_eq!(found.as_ref(), Some(&process));
+
+        let found = locate_binary(
+            None,
+            shell(None, &[]),
+            Some(bins.path(&["empty"])),
+            &fallbacks,
+        );
+        assert_eq!(found.as_ref(), Some(&fallback));
+
+        let found = locate_binary(None, shell(None, &[]), None, &fallbacks[..1]);
+        assert_eq!(found, None);
+    }
+
+    #[test]
+    fn credential_variables_are_recognised_in_any_case() {
+        for name in [
+            "ANTHROPIC_API_KEY",
+            "openai_api_key",
+            "Github_Token",
+            "AWS_SECRET_ACCESS_KEY",
+            "client_secret",
+            "DB_Password",
+            "GOOGLE_APPLICATION_CREDENTIALS",
+            "Authorization",
+            "password",
+        ] {
+            assert!(is_secret_env_name(name), "{name} is not redacted");
+        }
+        for name in [
+            "PATH",
+            "HOME",
+            "MONKEY",
+            "KEYBOARD_LAYOUT",
+            "TOKENIZERS_PARALLELISM",
+            "SECRETS_DIR",
+            "PASSWORD_STORE_DIR",
+            "OPENCODE_CONFIG",
+        ] {
+            assert!(!is_secret_env_name(name), "{name} is redacted");
         }
+    }
 
-        info!("[desktop:opencode] parsed path exists: {}", env.path.is_some());
-        env
+    #[test]
+    fn only_credential_values_are_replaced() {
+        assert_eq!(redact_env_value("OPENAI_API_KEY", "sk-live-123"), REDACTED);
+        assert_eq!(redact_env_value("Api_Token", ""), REDACTED);
+        assert_eq!(redact_env_value("PATH", "/usr/bin:/bin"), "/usr/bin:/bin");
+    }
+
+    #[tokio::test]
+    async fn the_debug_environment_applies_overrides_and_redacts() {
+        let cli = FakeCli::new().await;
+        let mut manager = OpenCodeManager::new_with_directory(Some(cli.root.clone()));
+        manager.env = HashMap::from([
+            ("PATH".to_string(), "/usr/bin".to_string()),
+            (
+                "ANTHROPIC_API_KEY".to_string(),
+                "sk-ant-inherited".to_string(),
+            ),
+            ("HTTP_PROXY".to_string(), "http://proxy:3128".to_string()),
+        ]);
+        let store = attach_settings(&cli, &manager);
+        store
+            .save(json!({
+                "opencode": {
+                    "env": {
+                        "PATH": "/opt/opencode/bin:/usr/bin",
+                        "openrouter_api_key": "sk-or-override",
+                    },
+                },
+            }))
+            .await
+            .unwrap();
+
+        let environment = manager.debug_environment().await;
+        assert_eq!(environment["PATH"], "/opt/opencode/bin:/usr/bin");
+        assert_eq!(environment["HTTP_PROXY"], "http://proxy:3128");
+        assert_eq!(environment["ANTHROPIC_API_KEY"], REDACTED);
+        assert_eq!(environment["openrouter_api_key"], REDACTED);
+        let dump = format!("{environment:?}");
+        assert!(
+            !dump.contains("sk-ant") && !dump.contains("sk-or"),
+            "{dump}"
+        );
     }
 }
 
-fn detect_login_shell_path() -> Result<String> {
-    detect_shell_env()
-        .path
-        .ok_or_else(|| anyhow!("shell PATH detection failed"))
+#[cfg(all(test, windows))]
+mod windows_tests {
+    use super::*;
+
+    /// A directory under the system temp dir, removed on drop
+    struct TempDir(PathBuf);
+
+    impl TempDir {
+        fn new() -> Self {
+            let root =
+                std::env::temp_dir().join(format!("openchamber-bin-{}", uuid::Uuid::new_v4()));
+            std::fs::create_dir_all(&root).unwrap();
+            Self(root)
+        }
+
+        fn file(&self, relative: &str) -> PathBuf {
+            let path = self.0.join(relative);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(&path, "").unwrap();
+            path
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.0);
+        }
+    }
+
+    #[test]
+    fn pathext_extensions_are_tried() {
+        let names = binary_names();
+        assert!(names.contains(&"opencode.exe".to_string()));
+        assert!(names.contains(&"opencode.cmd".to_string()));
+        assert!(!names.contains(&"opencode".to_string()));
+
+        let dir = TempDir::new();
+        let cmd = dir.file("npm/opencode.cmd");
+        assert_eq!(find_in_dir(&dir.0.join("npm")), Some(cmd));
+        dir.file("npm/opencode");
+        assert!(find_in_dir(&dir.0.join("npm"))
+            .unwrap()
+            .to_string_lossy()
+            .ends_with(".cmd"));
+    }
+
+    #[test]
+    fn executables_win_over_scripts_in_the_same_directory() {
+        let dir = TempDir::new();
+        dir.file("bin/opencode.cmd");
+        let exe = dir.file("bin/opencode.exe");
+        assert_eq!(find_in_dir(&dir.0.join("bin")), Some(exe));
+    }
+
+    #[test]
+    fn path_entries_are_searched_in_order() {
+        let dir = TempDir::new();
+        let first = dir.file("first/opencode.cmd");
+        dir.file("second/opencode.exe");
+        let path = std::env::join_paths([
+            dir.0.join("empty"),
+            dir.0.join("first"),
+            dir.0.join("second"),
+        ])
+        .unwrap();
+        assert_eq!(find_in_path(&path), Some(first));
+    }
+
+    /// This test binary re-run as a child for `name`, one of the ignored
+    /// helpers below, in its own process group with a hidden console like
+    /// OpenCode is spawned
+    fn helper(name: &str) -> tokio::process::Command {
+        use windows_sys::Win32::System::Threading::{CREATE_NEW_PROCESS_GROUP, CREATE_NO_WINDOW};
+
+        let mut command = tokio::process::Command::new(std::env::current_exe().unwrap());
+        command
+            .args([
+                "--exact",
+                &format!("opencode_manager::windows_tests::{name}"),
+            ])
+            .args(["--ignored", "--nocapture"])
+            .creation_flags(CREATE_NEW_PROCESS_GROUP | CREATE_NO_WINDOW)
+            .kill_on_drop(true);
+        command
+    }
+
+    /// Stands in for OpenCode; has no console handler, so CTRL_BREAK ends it
+    #[test]
+    #[ignore = "child process of ctrl_break_stops_a_process_group"]
+    fn sleeping_child() {
+        if std::env::var_os("OPENCHAMBER_TEST_CHILD").is_some() {
+            std::thread::sleep(Duration::from_secs(30));
+        }
+    }
+
+    /// Sends CTRL_BREAK from its own process, since attaching to the child's
+    /// console would detach the test runner from its own; exits 0 when sent
+    #[test]
+    #[ignore = "child process of ctrl_break_stops_a_process_group"]
+    fn ctrl_break_sender() {
+        if let Some(pid) = std::env::var("OPENCHAMBER_TEST_PID")
+            .ok()
+            .and_then(|pid| pid.parse().ok())
+        {
+            std::process::exit(if send_ctrl_break(pid) { 0 } else { 3 });
+        }
+    }
+
+    #[tokio::test]
+    async fn ctrl_break_stops_a_process_group() {
+        let mut child = helper("sleeping_child")
+            .env("OPENCHAMBER_TEST_CHILD", "1")
+            .spawn()
+            .unwrap();
+        tokio::time::sleep(Duration::from_secs(1)).await;
+        let pid = child.id().unwrap();
+
+        let sent = helper("ctrl_break_sender")
+            .env("OPENCHAMBER_TEST_PID", pid.to_string())
+            .status()
+            .await
+            .unwrap();
+        assert!(sent.success(), "CTRL_BREAK was not sent: {sent:?}");
+
+        // Within graceful_stop's window, so no TerminateProcess is needed
+        let exited = timeout(Duration::from_secs(3), child.wait())
+            .await
+            .expect("still running after CTRL_BREAK")
+            .unwrap();
+        assert!(!exited.success());
+    }
+
+    #[tokio::test]
+    async fn ctrl_break_to_a_missing_process_is_not_sent() {
+        let mut child = helper("sleeping_child").spawn().unwrap();
+        let pid = child.id().unwrap();
+        child.wait().await.unwrap();
+
+        let sent = helper("ctrl_break_sender")
+            .env("OPENCHAMBER_TEST_PID", pid.to_string())
+            .status()
+            .await
+            .unwrap();
+        assert_eq!(sent.code(), Some(3));
+    }
 }
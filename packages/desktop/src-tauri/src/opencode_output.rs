@@ -0,0 +1,88 @@
+use std::collections::VecDeque;
+
+use parking_lot::Mutex;
+use serde::Serialize;
+
+/// Lines of OpenCode output kept for diagnosing startup failures
+const OUTPUT_LOG_CAPACITY: usize = 500;
+
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OutputLine {
+    /// RFC 3339
+    pub timestamp: String,
+    /// "stdout" or "stderr"
+    pub stream: &'static str,
+    pub line: String,
+}
+
+/// Ring buffer of the current OpenCode process's stdout/stderr
+#[derive(Default)]
+pub struct OutputLog {
+    lines: Mutex<VecDeque<OutputLine>>,
+}
+
+impl OutputLog {
+    pub fn push(&self, stream: &'static str, line: &str) {
+        let mut lines = self.lines.lock();
+        if lines.len() == OUTPUT_LOG_CAPACITY {
+            lines.pop_front();
+        }
+        lines.push_back(OutputLine {
+            timestamp: chrono::Utc::now().to_rfc3339(),
+            stream,
+            line: line.to_string(),
+        });
+    }
+
+    pub fn clear(&self) {
+        self.lines.lock().clear();
+    }
+
+    /// Oldest first
+    pub fn lines(&self) -> Vec<OutputLine> {
+        self.lines.lock().iter().cloned().collect()
+    }
+
+    /// The last `count` lines, oldest first
+    pub fn tail(&self, count: usize) -> Vec<OutputLine> {
+        let lines = self.lines.lock();
+        let skip = lines.len().saturating_sub(count);
+        lines.iter().skip(skip).cloned().collect()
+    }
+
+    /// The last `count` lines joined for an error message
+    pub fn tail_text(&self, count: usize) -> String {
+        self.tail(count)
+            .iter()
+            .map(|entry| format!("[{}] {}", entry.stream, entry.line))
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    /// As many of the last lines as fit in `max_bytes`, joined like tail_text;
+    /// a single longer line keeps only its end
+    pub fn tail_text_bytes(&self, max_bytes: usize) -> String {
+        let lines = self.lines.lock();
+        let mut kept: Vec<String> = Vec::new();
+        let mut total = 0;
+        for entry in lines.iter().rev() {
+            let text = format!("[{}] {}", entry.stream, entry.line);
+            let needed = text.len() + usize::from(!kept.is_empty());
+            if total + needed > max_bytes {
+                if kept.is_empty() {
+                    let mut start = text.len() - max_bytes;
+                    while !text.is_char_boundary(start) {
+                        start += 1;
+                    }
+                    kept.push(text[start..].to_string());
+                }
+                break;
+            }
+            total += needed;
+            kept.push(text);
+        }
+        kept.reverse();
+        kept.join("\n")
+    }
+}
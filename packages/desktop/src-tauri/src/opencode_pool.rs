@@ -0,0 +1,263 @@
+use std::{
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Instant,
+};
+
+use anyhow::Result;
+use log::{info, warn};
+use serde::Serialize;
+use serde_json::Value;
+use tokio::sync::Mutex;
+
+use crate::{opencode_manager::OpenCodeManager, SettingsStore};
+
+/// Servers kept alive by default, counting the primary; 1 disables pooling
+const DEFAULT_MAX_INSTANCES: usize = 1;
+
+/// `opencode.maxInstances` from settings
+pub fn max_instances(settings: &Value) -> usize {
+    settings
+        .get("opencode")
+        .and_then(|opencode| opencode.get("maxInstances"))
+        .and_then(Value::as_u64)
+        .and_then(|max| usize::try_from(max).ok())
+        .filter(|max| *max > 0)
+        .unwrap_or(DEFAULT_MAX_INSTANCES)
+}
+
+/// One running server, for health reporting
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct InstanceInfo {
+    pub directory: String,
+    pub port: Option<u16>,
+    pub ready: bool,
+    /// Serves the app's current working directory
+    pub primary: bool,
+}
+
+struct PooledInstance {
+    /// Canonical form, for comparisons
+    directory: PathBuf,
+    manager: Arc<OpenCodeManager>,
+    last_used: Instant,
+}
+
+/// Keeps OpenCode servers for recently used directories alive next to the
+/// primary one, so switching projects does not kill the sessions running in
+/// the previous one. The primary manager always serves the current working
+/// directory; on a switch its server is parked here and a parked server for
+/// the new directory, if any, moves into it.
+pub struct OpenCodeInstancePool {
+    primary: Arc<OpenCodeManager>,
+    settings: Arc<SettingsStore>,
+    /// Servers alive at once, including the primary
+    capacity: usize,
+    instances: Mutex<Vec<PooledInstance>>,
+}
+
+fn canonical(path: &Path) -> PathBuf {
+    std::fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf())
+}
+
+impl OpenCodeInstancePool {
+    pub fn new(
+        primary: Arc<OpenCodeManager>,
+        settings: Arc<SettingsStore>,
+        capacity: usize,
+    ) -> Self {
+        Self {
+            primary,
+            settings,
+            capacity: capacity.max(1),
+            instances: Mutex::new(Vec::new()),
+        }
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.capacity > 1
+    }
+
+    fn is_primary_directory(&self, directory: &Path) -> bool {
+        canonical(directory) == canonical(&self.primary.get_working_directory())
+    }
+
+    /// Server for a request naming `directory`, started on first use. Falls back
+    /// to the primary when pooling is off or no directory is named.
+    pub async fn backend_for(&self, directory: Option<&str>) -> Result<Arc<OpenCodeManager>> {
+        let Some(directory) = directory
+            .map(str::trim)
+            .filter(|directory| !directory.is_empty())
+            .map(PathBuf::from)
+        else {
+            return Ok(self.primary.clone());
+        };
+        // A primary that scopes requests by directory serves every project itself
+        if !self.enabled()
+            || self.primary.supports_directory_scoping()
+            || !directory.is_dir()
+            || self.is_primary_directory(&directory)
+        {
+            return Ok(self.primary.clone());
+        }
+
+        let key = canonical(&directory);
+        let (manager, evicted) = {
+            let mut instances = self.instances.lock().await;
+            match instances.iter_mut().find(|entry| entry.directory == key) {
+                Some(entry) => {
+                    entry.last_used = Instant::now();
+                    (entry.manager.clone(), Vec::new())
+                }
+                None => {
+                    info!("[desktop:opencode] Starting pooled server for {:?}", key);
+                    let manager = self.new_manager(key.clone());
+                    instances.push(PooledInstance {
+                        directory: key,
+                        manager: manager.clone(),
+                        last_used: Instant::now(),
+                    });
+                    (manager, self.take_evicted(&mut instances))
+                }
+            }
+        };
+        stop_evicted(evicted).await;
+
+        if !manager.is_ready() {
+            manager.ensure_running().await?;
+        }
+        Ok(manager)
+    }
+
+    /// Point the primary manager at `directory`, parking its current server and
+    /// reusing a parked one for `directory` when there is one. Returns whether a
+    /// parked server was reused; otherwise the primary is restarted.
+    pub async fn switch_primary(&self, directory: PathBuf) -> Result<bool> {
+        if !self.enabled() {
+            self.primary.set_working_directory(directory).await?;
+            self.primary.restart_for("directory change").await?;
+            return Ok(false);
+        }
+
+        let previous = canonical(&self.primary.get_working_directory());
+        let key = canonical(&directory);
+        let parked = self.primary.detach_server().await;
+
+        let (reused, evicted) = {
+            let mut instances = self.instances.lock().await;
+            if let Some(server) = parked {
+                // A pooled server for the same directory would be a duplicate
+                if let Some(index) = instances
+                    .iter()
+                    .position(|entry| entry.directory == previous)
+                {
+                    let stale = instances.remove(index);
+                    tauri::async_runtime::spawn(async move {
+                        let _ = stale.manager.shutdown().await;
+                    });
+                }
+                let manager = self.new_manager(previous.clone());
+                manager.attach_server(server).await;
+                info!("[desktop:opencode] Parked server for {:?}", previous);
+                instances.push(PooledInstance {
+                    directory: previous,
+                    manager,
+                    last_used: Instant::now(),
+                });
+            }
+
+            let mut reused = None;
+            let mut evicted = Vec::new();
+            if let Some(index) = instances.iter().position(|entry| entry.directory == key) {
+                let entry = instances.remove(index);
+                reused = entry.manager.detach_server().await;
+                // Still starting or crashed: the primary starts its own instead
+                if reused.is_none() {
+                    evicted.push(entry);
+                }
+            }
+            evicted.extend(self.take_evicted(&mut instances));
+            (reused, evicted)
+        };
+        stop_evicted(evicted).await;
+
+        self.primary.set_working_directory(directory).await?;
+        match reused {
+            Some(server) => {
+                self.primary.attach_server(server).await;
+                Ok(true)
+            }
+            None => {
+                self.primary.restart_for("directory change").await?;
+                Ok(false)
+            }
+        }
+    }
+
+    /// Every running server, primary first
+    pub async fn instances(&self) -> Vec<InstanceInfo> {
+        let mut infos = vec![InstanceInfo {
+            directory: self
+                .primary
+                .get_working_directory()
+                .to_string_lossy()
+                .to_string(),
+            port: self.primary.current_port(),
+            ready: self.primary.is_ready(),
+            primary: true,
+        }];
+        let instances = self.instances.lock().await;
+        infos.extend(instances.iter().map(|entry| InstanceInfo {
+            directory: entry.directory.to_string_lossy().to_string(),
+            port: entry.manager.current_port(),
+            ready: entry.manager.is_ready(),
+            primary: false,
+        }));
+        infos
+    }
+
+    /// Stop every pooled server; the primary is stopped by its own teardown
+    pub async fn shutdown(&self) {
+        let instances = std::mem::take(&mut *self.instances.lock().await);
+        stop_evicted(instances).await;
+    }
+
+    fn new_manager(&self, directory: PathBuf) -> Arc<OpenCodeManager> {
+        let manager = Arc::new(OpenCodeManager::new_with_directory(Some(directory)));
+        manager.attach_settings(self.settings.clone());
+        manager
+    }
+
+    /// Remove the least recently used instances beyond the cap
+    fn take_evicted(&self, instances: &mut Vec<PooledInstance>) -> Vec<PooledInstance> {
+        let mut evicted = Vec::new();
+        while instances.len() + 1 > self.capacity {
+            let Some(index) = instances
+                .iter()
+                .enumerate()
+                .min_by_key(|(_, entry)| entry.last_used)
+                .map(|(index, _)| index)
+            else {
+                break;
+            };
+            evicted.push(instances.remove(index));
+        }
+        evicted
+    }
+}
+
+async fn stop_evicted(instances: Vec<PooledInstance>) {
+    for entry in instances {
+        info!(
+            "[desktop:opencode] Stopping pooled server for {:?}",
+            entry.directory
+        );
+        if let Err(err) = entry.manager.shutdown().await {
+            warn!(
+                "[desktop:opencode] Failed to stop pooled server for {:?}: {}",
+                entry.directory, err
+            );
+        }
+    }
+}
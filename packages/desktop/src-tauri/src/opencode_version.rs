@@ -0,0 +1,106 @@
+use std::{collections::HashMap, fmt, process::Stdio, time::Duration};
+
+use anyhow::{anyhow, Result};
+use once_cell::sync::Lazy;
+use regex::Regex;
+use serde::Serialize;
+use tokio::{process::Command, time::timeout};
+
+/// Oldest OpenCode whose /agent and /config endpoints match what the UI expects
+pub const MIN_VERSION: Version = Version {
+    major: 0,
+    minor: 5,
+    patch: 0,
+};
+
+const VERSION_TIMEOUT: Duration = Duration::from_secs(5);
+
+static VERSION_REGEX: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"(\d+)\.(\d+)\.(\d+)").expect("valid regex"));
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Version {
+    pub major: u64,
+    pub minor: u64,
+    pub patch: u64,
+}
+
+impl Version {
+    /// First `major.minor.patch` in `text`, e.g. "opencode 0.6.3" or "v1.0.0-beta.2"
+    pub fn parse(text: &str) -> Option<Self> {
+        let captures = VERSION_REGEX.captures(text)?;
+        Some(Self {
+            major: captures[1].parse().ok()?,
+            minor: captures[2].parse().ok()?,
+            patch: captures[3].parse().ok()?,
+        })
+    }
+}
+
+impl fmt::Display for Version {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{}.{}", self.major, self.minor, self.patch)
+    }
+}
+
+/// What `opencode --version` reported
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CliVersion {
+    /// Parsed semver, None when the output had none
+    pub version: Option<String>,
+    /// Trimmed output, for display when parsing failed
+    pub raw: String,
+    /// Set when the CLI is older than MIN_VERSION or its version is unknown
+    pub warning: Option<String>,
+}
+
+impl CliVersion {
+    pub fn from_output(raw: &str) -> Self {
+        let raw = raw.trim().to_string();
+        let version = Version::parse(&raw);
+        let warning = match version {
+            Some(version) if version < MIN_VERSION => Some(format!(
+                "OpenCode {} is older than the minimum supported {}; update it if agents or settings misbehave",
+                version, MIN_VERSION
+            )),
+            Some(_) => None,
+            None => Some(format!(
+                "Could not determine the OpenCode version from {:?}",
+                raw
+            )),
+        };
+        Self {
+            version: version.map(|version| version.to_string()),
+            raw,
+            warning,
+        }
+    }
+}
+
+/// Run `<binary> --version` with the launch environment
+pub async fn detect(binary: &str, env: &HashMap<String, String>) -> Result<CliVersion> {
+    let mut cmd = Command::new(binary);
+    cmd.arg("--version")
+        .envs(env)
+        .stdin(Stdio::null())
+        .kill_on_drop(true);
+    let output = timeout(VERSION_TIMEOUT, cmd.output())
+        .await
+        .map_err(|_| anyhow!("`{} --version` timed out", binary))??;
+    if !output.status.success() {
+        return Err(anyhow!(
+            "`{} --version` exited with {}",
+            binary,
+            output.status
+        ));
+    }
+    // Some builds print the version on stderr
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let text = if stdout.trim().is_empty() {
+        String::from_utf8_lossy(&output.stderr).to_string()
+    } else {
+        stdout.to_string()
+    };
+    Ok(CliVersion::from_output(&text))
+}
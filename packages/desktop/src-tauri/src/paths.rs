@@ -0,0 +1,399 @@
+use log::{info, warn};
+use once_cell::sync::Lazy;
+use std::path::{Path, PathBuf};
+
+const APP_DIR_NAME: &str = "openchamber";
+const PORTABLE_DATA_DIR: &str = "data";
+const MIGRATION_MARKER: &str = ".migrated-to";
+
+/// Files that historically lived directly in ~/.config/openchamber
+const LEGACY_CONFIG_FILES: &[&str] = &["settings.json", "git-identities.json"];
+const LEGACY_STATE_FILES: &[&str] = &["window-state.json"];
+
+#[derive(Debug, Clone)]
+struct Layout {
+    config: PathBuf,
+    state: PathBuf,
+    cache: PathBuf,
+    logs: PathBuf,
+    portable: bool,
+}
+
+static LAYOUT: Lazy<Option<Layout>> = Lazy::new(resolve_layout);
+
+/// Directory for user-editable configuration (settings, git identities)
+pub fn config_dir() -> Option<PathBuf> {
+    LAYOUT.as_ref().map(|layout| layout.config.clone())
+}
+
+/// Directory for machine-local state (window geometry, journals, heartbeat)
+pub fn state_dir() -> Option<PathBuf> {
+    LAYOUT.as_ref().map(|layout| layout.state.clone())
+}
+
+/// Directory for disposable caches
+pub fn cache_dir() -> Option<PathBuf> {
+    LAYOUT.as_ref().map(|layout| layout.cache.clone())
+}
+
+/// Directory for log files
+pub fn logs_dir() -> Option<PathBuf> {
+    LAYOUT.as_ref().map(|layout| layout.logs.clone())
+}
+
+/// The location every platform used before XDG/AppData support
+fn legacy_config_dir() -> Option<PathBuf> {
+    dirs::home_dir().map(|home| home.join(".config").join(APP_DIR_NAME))
+}
+
+/// `OPENCHAMBER_PORTABLE` or a `--portable` argument
+fn portable_requested(env: Option<String>, mut args: impl Iterator<Item = String>) -> bool {
+    let from_env = env.is_some_and(|value| matches!(value.trim(), "1" | "true" | "yes"));
+    from_env || args.any(|arg| arg == "--portable")
+}
+
+#[cfg(not(target_os = "macos"))]
+fn env_dir(key: &str) -> Option<PathBuf> {
+    dir_value(std::env::var(key).ok())
+}
+
+/// A directory from an environment variable; blank and relative values are ignored
+#[cfg(not(target_os = "macos"))]
+fn dir_value(value: Option<String>) -> Option<PathBuf> {
+    value
+        .map(|value| value.trim().to_string())
+        .filter(|value| !value.is_empty())
+        .map(PathBuf::from)
+        .filter(|path| path.is_absolute())
+}
+
+fn resolve_layout() -> Option<Layout> {
+    if portable_requested(std::env::var("OPENCHAMBER_PORTABLE").ok(), std::env::args()) {
+        let exe = std::env::current_exe().ok()?;
+        return portable_layout(&exe);
+    }
+
+    platform_layout()
+}
+
+/// Everything in a data directory next to the executable
+fn portable_layout(exe: &Path) -> Option<Layout> {
+    let root = exe.parent()?.join(PORTABLE_DATA_DIR);
+    Some(Layout {
+        config: root.join("config"),
+        state: root.join("state"),
+        cache: root.join("cache"),
+        logs: root.join("logs"),
+        portable: true,
+    })
+}
+
+#[cfg(target_os = "macos")]
+fn platform_layout() -> Option<Layout> {
+    // Keep ~/.config/openchamber on macOS for consistency with the web runtime
+    let home = dirs::home_dir()?;
+    let config = home.join(".config").join(APP_DIR_NAME);
+    Some(Layout {
+        state: config.clone(),
+        cache: home.join("Library").join("Caches").join("OpenChamber"),
+        logs: home.join("Library").join("Logs").join("OpenChamber"),
+        config,
+        portable: false,
+    })
+}
+
+#[cfg(windows)]
+fn platform_layout() -> Option<Layout> {
+    let roaming = env_dir("APPDATA").or_else(dirs::config_dir)?;
+    let local = env_dir("LOCALAPPDATA")
+        .or_else(dirs::data_local_dir)
+        .unwrap_or_else(|| roaming.clone());
+    let local_root = local.join("OpenChamber");
+    Some(Layout {
+        config: roaming.join("OpenChamber"),
+        state: local_root.join("state"),
+        cache: local_root.join("cache"),
+        logs: local_root.join("logs"),
+        portable: false,
+    })
+}
+
+#[cfg(all(not(target_os = "macos"), not(windows)))]
+fn platform_layout() -> Option<Layout> {
+    Some(xdg_layout(&dirs::home_dir()?, env_dir))
+}
+
+/// XDG base directories, falling back to their defaults under `home`
+#[cfg(all(not(target_os = "macos"), not(windows)))]
+fn xdg_layout(home: &Path, env_dir: impl Fn(&str) -> Option<PathBuf>) -> Layout {
+    let config_home = env_dir("XDG_CONFIG_HOME").unwrap_or_else(|| home.join(".config"));
+    let state_home = env_dir("XDG_STATE_HOME").unwrap_or_else(|| home.join(".local").join("state"));
+    let cache_home = env_dir("XDG_CACHE_HOME").unwrap_or_else(|| home.join(".cache"));
+    let state = state_home.join(APP_DIR_NAME);
+    Layout {
+        config: config_home.join(APP_DIR_NAME),
+        logs: state.join("logs"),
+        state,
+        cache: cache_home.join(APP_DIR_NAME),
+        portable: false,
+    }
+}
+
+/// Move state files from the legacy ~/.config/openchamber location into the
+/// resolved directories. Runs once: a marker left in the legacy directory
+/// records where the files went so later launches skip the work.
+pub fn migrate_legacy_files() {
+    if let (Some(layout), Some(legacy)) = (LAYOUT.as_ref(), legacy_config_dir()) {
+        migrate(layout, &legacy);
+    }
+}
+
+/// A portable install only copies: the legacy directory still belongs to the
+/// regular install, so its files stay and no marker is written
+fn migrate(layout: &Layout, legacy: &Path) {
+    if !legacy.is_dir() || legacy.join(MIGRATION_MARKER).exists() {
+        return;
+    }
+
+    if layout.config == legacy && layout.state == legacy {
+        return;
+    }
+
+    let mut moved = 0;
+    for (files, target) in [
+        (LEGACY_CONFIG_FILES, &layout.config),
+        (LEGACY_STATE_FILES, &layout.state),
+    ] {
+        if target == legacy {
+            continue;
+        }
+        for file in files {
+            let (source, destination) = (legacy.join(file), target.join(file));
+            let result = if layout.portable {
+                copy_file(&source, &destination)
+            } else {
+                move_file(&source, &destination)
+            };
+            match result {
+                Ok(true) => moved += 1,
+                Ok(false) => {}
+                Err(err) => warn!("[desktop:paths] Failed to migrate {}: {}", file, err),
+            }
+        }
+    }
+
+    if !layout.portable {
+        let marker = format!(
+            "config={}\nstate={}\n",
+            layout.config.display(),
+            layout.state.display()
+        );
+        if let Err(err) = std::fs::write(legacy.join(MIGRATION_MARKER), marker) {
+            warn!("[desktop:paths] Failed to write migration marker: {}", err);
+        }
+    }
+
+    info!(
+        "[desktop:paths] Migrated {} file(s) from {} (portable: {})",
+        moved,
+        legacy.display(),
+        layout.portable
+    );
+}
+
+/// Copy a single file unless the destination already exists. Returns whether it copied.
+fn copy_file(source: &Path, destination: &Path) -> std::io::Result<bool> {
+    if !source.is_file() || destination.exists() {
+        return Ok(false);
+    }
+    if let Some(parent) = destination.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    std::fs::copy(source, destination)?;
+    Ok(true)
+}
+
+/// Move a single file unless the destination already exists. Returns whether it moved.
+pub(crate) fn move_file(source: &Path, destination: &Path) -> std::io::Result<bool> {
+    if !source.is_file() || destination.exists() {
+        return Ok(false);
+    }
+    if let Some(parent) = destination.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    if std::fs::rename(source, destination).is_err() {
+        // Cross-device moves (e.g. portable installs on another volume) need copy + delete
+        std::fs::copy(source, destination)?;
+        std::fs::remove_file(source)?;
+    }
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A scratch directory under the system temp dir, removed on drop
+    struct TempDir(PathBuf);
+
+    impl TempDir {
+        fn new() -> Self {
+            let dir =
+                std::env::temp_dir().join(format!("openchamber-paths-{}", uuid::Uuid::new_v4()));
+            std::fs::create_dir_all(&dir).unwrap();
+            Self(dir)
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            std::fs::remove_dir_all(&self.0).ok();
+        }
+    }
+
+    /// A legacy directory holding every file the migration knows about
+    fn legacy_with_files(root: &Path) -> PathBuf {
+        let legacy = root.join("legacy");
+        std::fs::create_dir_all(&legacy).unwrap();
+        for file in LEGACY_CONFIG_FILES.iter().chain(LEGACY_STATE_FILES) {
+            std::fs::write(legacy.join(file), format!("legacy {file}")).unwrap();
+        }
+        legacy
+    }
+
+    fn layout_in(root: &Path, portable: bool) -> Layout {
+        Layout {
+            config: root.join("config"),
+            state: root.join("state"),
+            cache: root.join("cache"),
+            logs: root.join("logs"),
+            portable,
+        }
+    }
+
+    fn args(values: &[&str]) -> impl Iterator<Item = String> {
+        values
+            .iter()
+            .map(|value| value.to_string())
+            .collect::<Vec<_>>()
+            .into_iter()
+    }
+
+    #[test]
+    fn portable_mode_comes_from_the_env_or_the_flag() {
+        assert!(!portable_requested(None, args(&["openchamber"])));
+        assert!(portable_requested(
+            None,
+            args(&["openchamber", "--portable"])
+        ));
+        assert!(portable_requested(Some(" true ".into()), args(&[])));
+        assert!(portable_requested(Some("1".into()), args(&[])));
+        assert!(!portable_requested(Some("0".into()), args(&[])));
+    }
+
+    #[test]
+    fn portable_data_lives_next_to_the_executable() {
+        let layout = portable_layout(Path::new("/opt/openchamber/openchamber")).unwrap();
+        assert!(layout.portable);
+        assert_eq!(layout.config, Path::new("/opt/openchamber/data/config"));
+        assert_eq!(layout.state, Path::new("/opt/openchamber/data/state"));
+        assert_eq!(layout.cache, Path::new("/opt/openchamber/data/cache"));
+        assert_eq!(layout.logs, Path::new("/opt/openchamber/data/logs"));
+    }
+
+    #[cfg(not(target_os = "macos"))]
+    #[test]
+    fn blank_and_relative_env_dirs_are_ignored() {
+        assert_eq!(dir_value(None), None);
+        assert_eq!(dir_value(Some("  ".into())), None);
+        assert_eq!(dir_value(Some("relative/dir".into())), None);
+        assert_eq!(
+            dir_value(Some(" /srv/config ".into())),
+            Some(PathBuf::from("/srv/config"))
+        );
+    }
+
+    #[cfg(all(not(target_os = "macos"), not(windows)))]
+    #[test]
+    fn xdg_dirs_take_precedence_over_home_defaults() {
+        let home = Path::new("/home/user");
+        let defaults = xdg_layout(home, |_| None);
+        assert_eq!(defaults.config, home.join(".config/openchamber"));
+        assert_eq!(defaults.state, home.join(".local/state/openchamber"));
+        assert_eq!(defaults.logs, home.join(".local/state/openchamber/logs"));
+        assert_eq!(defaults.cache, home.join(".cache/openchamber"));
+
+        let custom = xdg_layout(home, |key| match key {
+            "XDG_CONFIG_HOME" => Some(PathBuf::from("/xdg/config")),
+            "XDG_CACHE_HOME" => Some(PathBuf::from("/xdg/cache")),
+            _ => None,
+        });
+        assert_eq!(custom.config, Path::new("/xdg/config/openchamber"));
+        assert_eq!(custom.cache, Path::new("/xdg/cache/openchamber"));
+        assert_eq!(custom.state, defaults.state);
+    }
+
+    #[test]
+    fn migration_moves_legacy_files_once() {
+        let root = TempDir::new();
+        let legacy = legacy_with_files(&root.0);
+        let layout = layout_in(&root.0, false);
+        std::fs::create_dir_all(&layout.config).unwrap();
+        std::fs::write(layout.config.join("git-identities.json"), "current").unwrap();
+
+        migrate(&layout, &legacy);
+
+        let read = |path: PathBuf| std::fs::read_to_string(path).unwrap();
+        assert_eq!(
+            read(layout.config.join("settings.json")),
+            "legacy settings.json"
+        );
+        assert_eq!(
+            read(layout.state.join("window-state.json")),
+            "legacy window-state.json"
+        );
+        // An existing file in the new location wins over the legacy one
+        assert_eq!(read(layout.config.join("git-identities.json")), "current");
+        assert!(!legacy.join("settings.json").exists());
+        assert!(read(legacy.join(MIGRATION_MARKER)).contains("config="));
+
+        // The marker stops later launches from migrating files that reappear
+        std::fs::write(legacy.join("settings.json"), "recreated").unwrap();
+        migrate(&layout, &legacy);
+        assert_eq!(read(legacy.join("settings.json")), "recreated");
+    }
+
+    #[test]
+    fn portable_migration_leaves_the_regular_install_alone() {
+        let root = TempDir::new();
+        let legacy = legacy_with_files(&root.0);
+        let layout = layout_in(&root.0, true);
+
+        migrate(&layout, &legacy);
+
+        assert_eq!(
+            std::fs::read_to_string(layout.config.join("settings.json")).unwrap(),
+            "legacy settings.json"
+        );
+        for file in LEGACY_CONFIG_FILES.iter().chain(LEGACY_STATE_FILES) {
+            assert!(legacy.join(file).is_file(), "{file} was removed");
+        }
+        assert!(!legacy.join(MIGRATION_MARKER).exists());
+    }
+
+    #[test]
+    fn migration_skips_a_layout_that_is_the_legacy_dir() {
+        let root = TempDir::new();
+        let legacy = legacy_with_files(&root.0);
+        let layout = Layout {
+            config: legacy.clone(),
+            state: legacy.clone(),
+            ..layout_in(&root.0, false)
+        };
+
+        migrate(&layout, &legacy);
+
+        assert!(legacy.join("settings.json").is_file());
+        assert!(!legacy.join(MIGRATION_MARKER).exists());
+    }
+}
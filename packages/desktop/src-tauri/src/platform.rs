@@ -0,0 +1,165 @@
+use serde::Serialize;
+use serde_json::Value;
+
+/// Desktop features that only exist on some platforms, so the frontend can adapt
+/// without sniffing the user agent. Every target builds the struct literally, so
+/// adding a field fails to compile until each platform states its value.
+#[derive(Clone, Copy, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PlatformCapabilities {
+    pub vibrancy: bool,
+    pub badge: bool,
+    /// Linux plays only a freedesktop sound name configured in `notifications.sound`
+    pub notification_sounds: bool,
+    /// macOS Focus / Windows Focus Assist detection behind `respectSystemDnd`;
+    /// GNOME is detected too, but other Linux desktops are not
+    pub focus_assist_detection: bool,
+    pub security_scoped_bookmarks: bool,
+}
+
+impl PlatformCapabilities {
+    #[cfg(target_os = "macos")]
+    pub const fn current() -> Self {
+        Self {
+            vibrancy: true,
+            badge: true,
+            notification_sounds: true,
+            focus_assist_detection: true,
+            security_scoped_bookmarks: true,
+        }
+    }
+
+    #[cfg(windows)]
+    pub const fn current() -> Self {
+        Self {
+            vibrancy: false,
+            badge: false,
+            notification_sounds: true,
+            focus_assist_detection: true,
+            security_scoped_bookmarks: false,
+        }
+    }
+
+    #[cfg(all(not(target_os = "macos"), not(windows)))]
+    pub const fn current() -> Self {
+        Self {
+            vibrancy: false,
+            badge: true,
+            notification_sounds: true,
+            focus_assist_detection: false,
+            security_scoped_bookmarks: false,
+        }
+    }
+}
+
+#[cfg(target_os = "macos")]
+const DEFAULT_NOTIFICATION_SOUND: Option<&str> = Some("Glass");
+
+#[cfg(windows)]
+const DEFAULT_NOTIFICATION_SOUND: Option<&str> = Some("Default");
+
+// Freedesktop servers reject unknown names, so stay silent unless one is configured
+#[cfg(all(not(target_os = "macos"), not(windows)))]
+const DEFAULT_NOTIFICATION_SOUND: Option<&str> = None;
+
+/// Sound for desktop notifications: `notifications.sound` when set (a macOS system
+/// sound or freedesktop sound name such as `message-new-instant`), else the platform default
+pub fn notification_sound(settings: &Value) -> Option<String> {
+    settings
+        .get("notifications")
+        .and_then(|notifications| notifications.get("sound"))
+        .and_then(Value::as_str)
+        .map(str::trim)
+        .filter(|sound| !sound.is_empty())
+        .map(str::to_string)
+        .or_else(|| DEFAULT_NOTIFICATION_SOUND.map(str::to_string))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    /// Evaluated at compile time for whichever target is being built
+    const CURRENT: PlatformCapabilities = PlatformCapabilities::current();
+
+    #[test]
+    fn every_capability_is_reported() {
+        let reported = serde_json::to_value(CURRENT).unwrap();
+        let mut keys: Vec<_> = reported.as_object().unwrap().keys().cloned().collect();
+        keys.sort();
+        assert_eq!(
+            keys,
+            [
+                "badge",
+                "focusAssistDetection",
+                "notificationSounds",
+                "securityScopedBookmarks",
+                "vibrancy",
+            ]
+        );
+    }
+
+    #[cfg(target_os = "macos")]
+    #[test]
+    fn macos_capabilities() {
+        assert_eq!(
+            serde_json::to_value(CURRENT).unwrap(),
+            json!({
+                "vibrancy": true,
+                "badge": true,
+                "notificationSounds": true,
+                "focusAssistDetection": true,
+                "securityScopedBookmarks": true,
+            })
+        );
+        assert_eq!(notification_sound(&json!({})).as_deref(), Some("Glass"));
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn windows_capabilities() {
+        assert_eq!(
+            serde_json::to_value(CURRENT).unwrap(),
+            json!({
+                "vibrancy": false,
+                "badge": false,
+                "notificationSounds": true,
+                "focusAssistDetection": true,
+                "securityScopedBookmarks": false,
+            })
+        );
+        assert_eq!(notification_sound(&json!({})).as_deref(), Some("Default"));
+    }
+
+    #[cfg(all(not(target_os = "macos"), not(windows)))]
+    #[test]
+    fn linux_capabilities() {
+        assert_eq!(
+            serde_json::to_value(CURRENT).unwrap(),
+            json!({
+                "vibrancy": false,
+                "badge": true,
+                "notificationSounds": true,
+                "focusAssistDetection": false,
+                "securityScopedBookmarks": false,
+            })
+        );
+        assert_eq!(notification_sound(&json!({})), None);
+    }
+
+    #[test]
+    fn a_configured_sound_is_passed_through() {
+        let settings = json!({ "notifications": { "sound": " message-new-instant " } });
+        assert_eq!(
+            notification_sound(&settings).as_deref(),
+            Some("message-new-instant")
+        );
+
+        let blank = json!({ "notifications": { "sound": "  " } });
+        assert_eq!(
+            notification_sound(&blank),
+            DEFAULT_NOTIFICATION_SOUND.map(str::to_string)
+        );
+    }
+}
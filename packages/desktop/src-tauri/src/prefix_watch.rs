@@ -0,0 +1,189 @@
+use std::{
+    collections::{HashSet, VecDeque},
+    time::{Duration, Instant},
+};
+
+use axum::http::StatusCode;
+use parking_lot::Mutex;
+
+/// 404s on previously working routes within BURST_WINDOW that suggest a stale prefix
+const BURST_THRESHOLD: usize = 5;
+const BURST_WINDOW: Duration = Duration::from_secs(10);
+/// Minimum time between re-detections triggered by bursts
+const REDETECT_COOLDOWN: Duration = Duration::from_secs(60);
+
+#[derive(Default)]
+struct WatchState {
+    /// First path segments that have answered successfully, e.g. "/session"
+    working_routes: HashSet<String>,
+    not_found: VecDeque<Instant>,
+    last_trigger: Option<Instant>,
+}
+
+/// Watches proxied responses for a burst of 404s on routes that used to work,
+/// the symptom of OpenCode changing its API prefix under a running app.
+/// 404s on routes never seen working (or on missing resources of working
+/// routes, which the re-detection rules out) do not switch anything.
+#[derive(Default)]
+pub struct PrefixWatch {
+    state: Mutex<WatchState>,
+}
+
+/// First segment of `path` after `prefix`, or None for the root
+fn route_of<'a>(path: &'a str, prefix: &str) -> Option<&'a str> {
+    let path = match path.strip_prefix(prefix) {
+        Some(rest) if !prefix.is_empty() && rest.starts_with('/') => rest,
+        _ => path,
+    };
+    let segment = path.trim_start_matches('/').split('/').next()?;
+    if segment.is_empty() {
+        return None;
+    }
+    Some(&path[..segment.len() + 1])
+}
+
+impl PrefixWatch {
+    /// Record a proxied response to `path`. Returns true when the caller
+    /// should re-detect the API prefix.
+    pub fn observe(&self, path: &str, prefix: &str, status: StatusCode) -> bool {
+        self.observe_at(path, prefix, status, Instant::now())
+    }
+
+    fn observe_at(&self, path: &str, prefix: &str, status: StatusCode, now: Instant) -> bool {
+        let Some(route) = route_of(path, prefix) else {
+            return false;
+        };
+        let mut state = self.state.lock();
+
+        if status.is_success() {
+            if !state.working_routes.contains(route) {
+                state.working_routes.insert(route.to_string());
+            }
+            return false;
+        }
+        if status != StatusCode::NOT_FOUND || !state.working_routes.contains(route) {
+            return false;
+        }
+
+        while state
+            .not_found
+            .front()
+            .is_some_and(|at| now.duration_since(*at) > BURST_WINDOW)
+        {
+            state.not_found.pop_front();
+        }
+        state.not_found.push_back(now);
+        if state.not_found.len() < BURST_THRESHOLD {
+            return false;
+        }
+        if state
+            .last_trigger
+            .is_some_and(|at| now.duration_since(at) < REDETECT_COOLDOWN)
+        {
+            return false;
+        }
+
+        state.not_found.clear();
+        state.last_trigger = Some(now);
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const OK: StatusCode = StatusCode::OK;
+    const NOT_FOUND: StatusCode = StatusCode::NOT_FOUND;
+
+    /// A watch that has seen `/session` and `/config` work, and a fixed clock
+    fn warmed_up() -> (PrefixWatch, Instant) {
+        let watch = PrefixWatch::default();
+        let start = Instant::now();
+        assert!(!watch.observe_at("/session", "", OK, start));
+        assert!(!watch.observe_at("/config/providers", "", OK, start));
+        (watch, start)
+    }
+
+    /// Feed `count` 404s for `path`, one per second from `from`; returns
+    /// whether any of them asked for re-detection
+    fn not_found(watch: &PrefixWatch, path: &str, prefix: &str, count: u64, from: Instant) -> bool {
+        let mut triggered = false;
+        for i in 0..count {
+            triggered |= watch.observe_at(path, prefix, NOT_FOUND, from + Duration::from_secs(i));
+        }
+        triggered
+    }
+
+    #[test]
+    fn routes_are_the_first_segment_after_the_prefix() {
+        assert_eq!(route_of("/session/ses_1/message", ""), Some("/session"));
+        assert_eq!(route_of("/api/session/ses_1", "/api"), Some("/session"));
+        assert_eq!(route_of("/config", "/api"), Some("/config"));
+        // Only a whole leading segment counts as the prefix
+        assert_eq!(route_of("/apiary/x", "/api"), Some("/apiary"));
+        assert_eq!(route_of("/api", "/api"), Some("/api"));
+        assert_eq!(route_of("/", ""), None);
+        assert_eq!(route_of("", "/api"), None);
+    }
+
+    #[test]
+    fn a_burst_on_working_routes_asks_for_redetection() {
+        let (watch, start) = warmed_up();
+
+        assert!(!not_found(&watch, "/session/ses_1", "", 4, start));
+        assert!(watch.observe_at("/config", "", NOT_FOUND, start + Duration::from_secs(4)));
+    }
+
+    #[test]
+    fn routes_never_seen_working_are_ignored() {
+        let (watch, start) = warmed_up();
+
+        assert!(!not_found(&watch, "/experimental/tool", "", 20, start));
+        assert!(!not_found(&watch, "/", "", 20, start));
+    }
+
+    #[test]
+    fn other_failures_do_not_count() {
+        let (watch, start) = warmed_up();
+
+        for i in 0..20 {
+            let at = start + Duration::from_millis(i);
+            assert!(!watch.observe_at("/session", "", StatusCode::BAD_GATEWAY, at));
+            assert!(!watch.observe_at("/session", "", StatusCode::UNAUTHORIZED, at));
+        }
+    }
+
+    #[test]
+    fn scattered_misses_outside_the_window_do_not_add_up() {
+        let (watch, start) = warmed_up();
+
+        for i in 0..20 {
+            let at = start + BURST_WINDOW * i / 3 + Duration::from_millis(i.into());
+            assert!(!watch.observe_at("/session/missing", "", NOT_FOUND, at));
+        }
+    }
+
+    #[test]
+    fn redetection_is_rate_limited() {
+        let (watch, start) = warmed_up();
+        assert!(not_found(&watch, "/session/x", "", 5, start));
+
+        // Another burst right away is absorbed by the cooldown
+        let soon = start + Duration::from_secs(10);
+        assert!(!not_found(&watch, "/session/x", "", 10, soon));
+
+        let later = start + REDETECT_COOLDOWN + Duration::from_secs(5);
+        assert!(not_found(&watch, "/session/x", "", 5, later));
+    }
+
+    #[test]
+    fn routes_are_tracked_relative_to_the_current_prefix() {
+        let watch = PrefixWatch::default();
+        let start = Instant::now();
+        assert!(!watch.observe_at("/api/session", "/api", OK, start));
+
+        // After OpenCode moved to the root the same route 404s under the old prefix
+        assert!(not_found(&watch, "/api/session/ses_1", "/api", 5, start));
+    }
+}
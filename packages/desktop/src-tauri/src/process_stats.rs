@@ -0,0 +1,128 @@
+use std::time::Instant;
+
+use serde::Serialize;
+use sysinfo::{Pid, ProcessRefreshKind, ProcessesToUpdate, System, MINIMUM_CPU_UPDATE_INTERVAL};
+use tokio::sync::Mutex;
+
+/// Resource usage of a process; every field is None when it is not running
+#[derive(Clone, Debug, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ProcessStats {
+    pub pid: Option<u32>,
+    pub rss_bytes: Option<u64>,
+    /// Percent of one core, so above 100 when several are busy
+    pub cpu_percent: Option<f32>,
+    pub uptime_secs: Option<u64>,
+    /// File descriptors on Unix, handles on Windows
+    pub open_files: Option<u64>,
+}
+
+struct SamplerState {
+    system: System,
+    /// Process and time of the previous refresh; CPU usage is measured between two
+    last_refresh: Option<(u32, Instant)>,
+}
+
+/// Samples one process at a time, reusing the previous refresh as the start of
+/// the CPU measurement window so repeated calls do not wait
+pub struct ProcessSampler {
+    state: Mutex<SamplerState>,
+}
+
+impl Default for ProcessSampler {
+    fn default() -> Self {
+        Self {
+            state: Mutex::new(SamplerState {
+                system: System::new(),
+                last_refresh: None,
+            }),
+        }
+    }
+}
+
+fn refresh(system: &mut System, pid: Pid) {
+    system.refresh_processes_specifics(
+        ProcessesToUpdate::Some(&[pid]),
+        true,
+        ProcessRefreshKind::new().with_cpu().with_memory(),
+    );
+}
+
+impl ProcessSampler {
+    pub async fn sample(&self, pid: Option<u32>) -> ProcessStats {
+        let Some(pid) = pid else {
+            return ProcessStats::default();
+        };
+        let mut state = self.state.lock().await;
+        let sys_pid = Pid::from_u32(pid);
+
+        let fresh_window = !matches!(
+            state.last_refresh,
+            Some((last, at)) if last == pid && at.elapsed() >= MINIMUM_CPU_UPDATE_INTERVAL
+        );
+        if fresh_window {
+            refresh(&mut state.system, sys_pid);
+            tokio::time::sleep(MINIMUM_CPU_UPDATE_INTERVAL).await;
+        }
+        refresh(&mut state.system, sys_pid);
+        state.last_refresh = Some((pid, Instant::now()));
+
+        let Some(process) = state.system.process(sys_pid) else {
+            return ProcessStats::default();
+        };
+        ProcessStats {
+            pid: Some(pid),
+            rss_bytes: Some(process.memory()),
+            cpu_percent: Some(process.cpu_usage()),
+            uptime_secs: Some(process.run_time()),
+            open_files: open_files(pid),
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn open_files(pid: u32) -> Option<u64> {
+    let entries = std::fs::read_dir(format!("/proc/{pid}/fd")).ok()?;
+    Some(entries.count() as u64)
+}
+
+#[cfg(target_os = "macos")]
+fn open_files(pid: u32) -> Option<u64> {
+    // With no buffer, proc_pidinfo returns the size the descriptor list needs
+    // SAFETY: a null buffer of size 0 is the documented way to query the size
+    let bytes = unsafe {
+        libc::proc_pidinfo(
+            pid as libc::c_int,
+            libc::PROC_PIDLISTFDS,
+            0,
+            std::ptr::null_mut(),
+            0,
+        )
+    };
+    (bytes > 0).then(|| bytes as u64 / std::mem::size_of::<libc::proc_fdinfo>() as u64)
+}
+
+#[cfg(windows)]
+fn open_files(pid: u32) -> Option<u64> {
+    use windows_sys::Win32::Foundation::CloseHandle;
+    use windows_sys::Win32::System::Threading::{
+        GetProcessHandleCount, OpenProcess, PROCESS_QUERY_LIMITED_INFORMATION,
+    };
+
+    // SAFETY: the handle is checked before use and closed before returning
+    unsafe {
+        let handle = OpenProcess(PROCESS_QUERY_LIMITED_INFORMATION, 0, pid);
+        if handle.is_null() {
+            return None;
+        }
+        let mut count = 0u32;
+        let ok = GetProcessHandleCount(handle, &mut count) != 0;
+        CloseHandle(handle);
+        ok.then_some(count as u64)
+    }
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos", windows)))]
+fn open_files(_pid: u32) -> Option<u64> {
+    None
+}
@@ -0,0 +1,449 @@
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use anyhow::{anyhow, Result};
+use log::warn;
+use serde_json::Value;
+use tokio::{fs, sync::Mutex};
+
+use crate::{commands::git::add_openchamber_exclude, paths};
+
+const WORKSPACE_STATE_DIR: &str = ".openchamber/state";
+/// Random id identifying a workspace even after it is moved; the leading dot
+/// keeps it out of the key namespace
+const FINGERPRINT_FILE: &str = ".workspace-id";
+const GLOBAL_STATE_DIR: &str = "project-state";
+const MAX_KEY_LEN: usize = 128;
+pub const MAX_STATE_VALUE_BYTES: usize = 256 * 1024; // 256KB
+
+/// Per-project UI state stored as one JSON file per key under
+/// `<workspace>/.openchamber/state/`, or a global directory when no project is
+/// open. Each file is guarded by its own mutex, like `SettingsStore`, so writes
+/// to different keys never wait on each other.
+#[derive(Default)]
+pub struct ProjectStateStore {
+    locks: parking_lot::Mutex<HashMap<PathBuf, Arc<Mutex<()>>>>,
+}
+
+/// Keys become file names, so keep them to a portable character set
+pub fn validate_key(key: &str) -> Result<()> {
+    let valid = !key.is_empty()
+        && key.len() <= MAX_KEY_LEN
+        && !key.starts_with('.')
+        && key
+            .chars()
+            .all(|ch| ch.is_ascii_alphanumeric() || matches!(ch, '-' | '_' | '.'));
+    if valid {
+        Ok(())
+    } else {
+        Err(anyhow!("Invalid state key: {:?}", key))
+    }
+}
+
+/// The home directory is where OpenCode starts without a project; never litter it
+fn is_workspace(dir: &Path) -> bool {
+    dir.is_dir() && dirs::home_dir().as_deref() != Some(dir)
+}
+
+impl ProjectStateStore {
+    /// Directory holding state for `workspace`, falling back to the global one
+    pub fn state_dir(workspace: Option<&Path>) -> Result<PathBuf> {
+        match workspace.filter(|dir| is_workspace(dir)) {
+            Some(dir) => Ok(dir.join(WORKSPACE_STATE_DIR)),
+            None => paths::state_dir()
+                .map(|dir| dir.join(GLOBAL_STATE_DIR))
+                .ok_or_else(|| anyhow!("No state directory")),
+        }
+    }
+
+    fn lock_for(&self, path: &Path) -> Arc<Mutex<()>> {
+        self.locks
+            .lock()
+            .entry(path.to_path_buf())
+            .or_default()
+            .clone()
+    }
+
+    pub async fn get(&self, workspace: Option<&Path>, key: &str) -> Result<Option<Value>> {
+        validate_key(key)?;
+        let path = Self::state_dir(workspace)?.join(format!("{key}.json"));
+        let lock = self.lock_for(&path);
+        let _guard = lock.lock().await;
+
+        match fs::read(&path).await {
+            Ok(bytes) => Ok(serde_json::from_slice(&bytes).ok()),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    pub async fn set(&self, workspace: Option<&Path>, key: &str, value: &Value) -> Result<()> {
+        validate_key(key)?;
+        let bytes = serde_json::to_vec(value)?;
+        if bytes.len() > MAX_STATE_VALUE_BYTES {
+            return Err(anyhow!(
+                "State value for {} exceeds {} bytes",
+                key,
+                MAX_STATE_VALUE_BYTES
+            ));
+        }
+
+        let dir = Self::state_dir(workspace)?;
+        self.ensure_dir(workspace, &dir).await?;
+
+        let path = dir.join(format!("{key}.json"));
+        let lock = self.lock_for(&path);
+        let _guard = lock.lock().await;
+
+        // Write beside the target and rename so readers never see a partial file
+        let tmp = dir.join(format!(".{key}.{}.tmp", uuid::Uuid::new_v4().simple()));
+        fs::write(&tmp, &bytes).await?;
+        if let Err(err) = fs::rename(&tmp, &path).await {
+            let _ = fs::remove_file(&tmp).await;
+            return Err(err.into());
+        }
+        Ok(())
+    }
+
+    pub async fn keys(&self, workspace: Option<&Path>) -> Result<Vec<String>> {
+        let dir = Self::state_dir(workspace)?;
+        let mut entries = match fs::read_dir(&dir).await {
+            Ok(entries) => entries,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(err) => return Err(err.into()),
+        };
+
+        let mut keys = Vec::new();
+        while let Some(entry) = entries.next_entry().await? {
+            let name = entry.file_name().to_string_lossy().to_string();
+            if let Some(key) = name.strip_suffix(".json") {
+                if validate_key(key).is_ok() {
+                    keys.push(key.to_string());
+                }
+            }
+        }
+        keys.sort();
+        Ok(keys)
+    }
+
+    /// The workspace's fingerprint, written on first call. None for the home
+    /// directory and other non-workspaces.
+    pub async fn ensure_fingerprint(&self, workspace: &Path) -> Result<Option<String>> {
+        if !is_workspace(workspace) {
+            return Ok(None);
+        }
+        if let Some(existing) = Self::read_fingerprint(workspace).await {
+            return Ok(Some(existing));
+        }
+
+        let dir = Self::state_dir(Some(workspace))?;
+        self.ensure_dir(Some(workspace), &dir).await?;
+        let path = dir.join(FINGERPRINT_FILE);
+        let lock = self.lock_for(&path);
+        let _guard = lock.lock().await;
+        let id = uuid::Uuid::new_v4().to_string();
+        fs::write(&path, &id).await?;
+        Ok(Some(id))
+    }
+
+    pub async fn read_fingerprint(workspace: &Path) -> Option<String> {
+        let path = workspace.join(WORKSPACE_STATE_DIR).join(FINGERPRINT_FILE);
+        let id = fs::read_to_string(path).await.ok()?;
+        let id = id.trim();
+        (!id.is_empty()).then(|| id.to_string())
+    }
+
+    /// Copy state files from `from` into `to` when `to` has no state yet, e.g.
+    /// after a workspace was copied rather than moved. Returns whether it copied.
+    pub async fn copy_workspace_state(&self, from: &Path, to: &Path) -> Result<bool> {
+        let source = from.join(WORKSPACE_STATE_DIR);
+        let target = Self::state_dir(Some(to))?;
+        if source == target || !source.is_dir() || target.is_dir() || !is_workspace(to) {
+            return Ok(false);
+        }
+        self.ensure_dir(Some(to), &target).await?;
+
+        let mut entries = fs::read_dir(&source).await?;
+        while let Some(entry) = entries.next_entry().await? {
+            let name = entry.file_name();
+            // The fingerprint stays with the original folder
+            if name.to_string_lossy() == FINGERPRINT_FILE || !entry.path().is_file() {
+                continue;
+            }
+            fs::copy(entry.path(), target.join(name)).await?;
+        }
+        Ok(true)
+    }
+
+    /// Create the state directory on first write, keeping `.openchamber` out of git
+    async fn ensure_dir(&self, workspace: Option<&Path>, dir: &Path) -> Result<()> {
+        if fs::metadata(dir).await.map(|m| m.is_dir()).unwrap_or(false) {
+            return Ok(());
+        }
+        fs::create_dir_all(dir).await?;
+
+        if let Some(root) = workspace.filter(|root| dir.starts_with(root)) {
+            if root.join(".git").exists() {
+                if let Err(err) = add_openchamber_exclude(root).await {
+                    warn!(
+                        "[desktop:state] Failed to ignore .openchamber in {}: {}",
+                        root.display(),
+                        err
+                    );
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    /// A scratch directory under the system temp dir, removed on drop
+    struct TempDir(PathBuf);
+
+    impl TempDir {
+        fn new() -> Self {
+            let dir =
+                std::env::temp_dir().join(format!("openchamber-state-{}", uuid::Uuid::new_v4()));
+            std::fs::create_dir_all(&dir).unwrap();
+            Self(dir)
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            std::fs::remove_dir_all(&self.0).ok();
+        }
+    }
+
+    #[tokio::test]
+    async fn state_is_kept_inside_the_workspace() {
+        let workspace = TempDir::new();
+        let store = ProjectStateStore::default();
+        let ws = Some(workspace.0.as_path());
+
+        assert_eq!(store.get(ws, "tabs").await.unwrap(), None);
+        store.set(ws, "tabs", &json!(["a", "b"])).await.unwrap();
+        store
+            .set(ws, "panel.sizes", &json!({ "left": 240 }))
+            .await
+            .unwrap();
+
+        let file = workspace.0.join(".openchamber/state/tabs.json");
+        assert_eq!(std::fs::read_to_string(file).unwrap(), r#"["a","b"]"#);
+        assert_eq!(
+            store.get(ws, "tabs").await.unwrap(),
+            Some(json!(["a", "b"]))
+        );
+        store.ensure_fingerprint(&workspace.0).await.unwrap();
+        assert_eq!(store.keys(ws).await.unwrap(), ["panel.sizes", "tabs"]);
+    }
+
+    #[tokio::test]
+    async fn keys_cannot_leave_the_state_dir() {
+        let workspace = TempDir::new();
+        let store = ProjectStateStore::default();
+        let ws = Some(workspace.0.as_path());
+
+        for key in [
+            "",
+            "../escape",
+            "a/b",
+            "a\\b",
+            ".hidden",
+            &"k".repeat(MAX_KEY_LEN + 1),
+        ] {
+            assert!(store.set(ws, key, &json!(1)).await.is_err(), "{key:?}");
+            assert!(store.get(ws, key).await.is_err(), "{key:?}");
+        }
+        assert!(!workspace.0.join(".openchamber").exists());
+    }
+
+    #[tokio::test]
+    async fn oversized_values_are_refused() {
+        let workspace = TempDir::new();
+        let store = ProjectStateStore::default();
+        let ws = Some(workspace.0.as_path());
+
+        let big = json!("x".repeat(MAX_STATE_VALUE_BYTES));
+        assert!(store.set(ws, "big", &big).await.is_err());
+        assert_eq!(store.keys(ws).await.unwrap(), Vec::<String>::new());
+    }
+
+    #[tokio::test]
+    async fn git_workspaces_ignore_the_state_dir() {
+        let repo = TempDir::new();
+        std::fs::create_dir(repo.0.join(".git")).unwrap();
+        let plain = TempDir::new();
+        let store = ProjectStateStore::default();
+
+        store.set(Some(&repo.0), "tabs", &json!([])).await.unwrap();
+        let exclude = std::fs::read_to_string(repo.0.join(".git/info/exclude")).unwrap();
+        assert!(exclude.contains(".openchamber"), "{exclude}");
+
+        store.set(Some(&plain.0), "tabs", &json!([])).await.unwrap();
+        assert!(!plain.0.join(".git").exists());
+    }
+
+    #[test]
+    fn the_home_directory_uses_the_global_state_dir() {
+        let Some(home) = dirs::home_dir() else {
+            return;
+        };
+        let dir = ProjectStateStore::state_dir(Some(&home)).unwrap();
+        assert!(dir.ends_with(GLOBAL_STATE_DIR), "{}", dir.display());
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn concurrent_writes_to_different_keys_all_land() {
+        let workspace = TempDir::new();
+        let store = Arc::new(ProjectStateStore::default());
+
+        let mut writers = tokio::task::JoinSet::new();
+        for i in 0..32 {
+            let store = store.clone();
+            let ws = workspace.0.clone();
+            writers.spawn(async move {
+                for round in 0..5 {
+                    let value = json!({ "writer": i, "round": round });
+                    store
+                        .set(Some(&ws), &format!("key-{i}"), &value)
+                        .await
+                        .unwrap();
+                }
+            });
+        }
+        while let Some(result) = writers.join_next().await {
+            result.unwrap();
+        }
+
+        let ws = Some(workspace.0.as_path());
+        assert_eq!(store.keys(ws).await.unwrap().len(), 32);
+        for i in 0..32 {
+            assert_eq!(
+                store.get(ws, &format!("key-{i}")).await.unwrap(),
+                Some(json!({ "writer": i, "round": 4 }))
+            );
+        }
+        // No temporary files are left behind
+        let leftovers = std::fs::read_dir(workspace.0.join(WORKSPACE_STATE_DIR))
+            .unwrap()
+            .filter(|entry| {
+                entry
+                    .as_ref()
+                    .unwrap()
+                    .file_name()
+                    .to_string_lossy()
+                    .ends_with(".tmp")
+            })
+            .count();
+        assert_eq!(leftovers, 0);
+    }
+
+    #[tokio::test]
+    async fn fingerprints_are_written_once_and_survive_a_rename() {
+        let parent = TempDir::new();
+        let workspace = parent.0.join("app");
+        std::fs::create_dir(&workspace).unwrap();
+        let store = ProjectStateStore::default();
+
+        assert_eq!(ProjectStateStore::read_fingerprint(&workspace).await, None);
+        let id = store.ensure_fingerprint(&workspace).await.unwrap().unwrap();
+        assert!(uuid::Uuid::parse_str(&id).is_ok());
+        assert_eq!(
+            store.ensure_fingerprint(&workspace).await.unwrap(),
+            Some(id.clone())
+        );
+
+        let renamed = parent.0.join("app-renamed");
+        std::fs::rename(&workspace, &renamed).unwrap();
+        assert_eq!(
+            ProjectStateStore::read_fingerprint(&renamed).await,
+            Some(id)
+        );
+    }
+
+    #[tokio::test]
+    async fn non_workspaces_get_no_fingerprint() {
+        let parent = TempDir::new();
+        let store = ProjectStateStore::default();
+
+        assert_eq!(
+            store
+                .ensure_fingerprint(&parent.0.join("missing"))
+                .await
+                .unwrap(),
+            None
+        );
+        if let Some(home) = dirs::home_dir() {
+            assert_eq!(store.ensure_fingerprint(&home).await.unwrap(), None);
+        }
+
+        // Blank fingerprint files are treated as absent
+        let dir = parent.0.join(WORKSPACE_STATE_DIR);
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join(FINGERPRINT_FILE), " \n").unwrap();
+        assert_eq!(ProjectStateStore::read_fingerprint(&parent.0).await, None);
+    }
+
+    #[tokio::test]
+    async fn state_is_copied_into_a_fresh_workspace_without_the_fingerprint() {
+        let original = TempDir::new();
+        let copy = TempDir::new();
+        let store = ProjectStateStore::default();
+        store
+            .set(Some(&original.0), "tabs", &json!(["a"]))
+            .await
+            .unwrap();
+        let id = store.ensure_fingerprint(&original.0).await.unwrap();
+
+        assert!(store
+            .copy_workspace_state(&original.0, &copy.0)
+            .await
+            .unwrap());
+        assert_eq!(
+            store.get(Some(&copy.0), "tabs").await.unwrap(),
+            Some(json!(["a"]))
+        );
+        assert_eq!(ProjectStateStore::read_fingerprint(&copy.0).await, None);
+        assert_eq!(ProjectStateStore::read_fingerprint(&original.0).await, id);
+
+        // A workspace that already has state is left alone
+        store
+            .set(Some(&original.0), "tabs", &json!(["b"]))
+            .await
+            .unwrap();
+        assert!(!store
+            .copy_workspace_state(&original.0, &copy.0)
+            .await
+            .unwrap());
+        assert_eq!(
+            store.get(Some(&copy.0), "tabs").await.unwrap(),
+            Some(json!(["a"]))
+        );
+    }
+
+    #[tokio::test]
+    async fn nothing_is_copied_without_source_state() {
+        let original = TempDir::new();
+        let copy = TempDir::new();
+        let store = ProjectStateStore::default();
+
+        assert!(!store
+            .copy_workspace_state(&original.0, &copy.0)
+            .await
+            .unwrap());
+        assert!(!store
+            .copy_workspace_state(&original.0, &original.0)
+            .await
+            .unwrap());
+        assert!(!copy.0.join(".openchamber").exists());
+    }
+}
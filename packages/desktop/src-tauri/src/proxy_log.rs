@@ -0,0 +1,272 @@
+use std::{
+    collections::{BTreeMap, VecDeque},
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc,
+    },
+    time::Instant,
+};
+
+use axum::{
+    body::{Body, HttpBody},
+    extract::{Query, Request, State},
+    http::{header, HeaderMap, Response, StatusCode, Uri},
+    middleware::Next,
+    response::IntoResponse,
+    Json,
+};
+use futures_util::StreamExt;
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::{loopback_auth, proxy_routes, ServerState};
+
+/// Settings flag that turns recording on
+pub const PROXY_DEBUG_SETTING: &str = "proxyDebug";
+/// Served by `log_handler`; requests to it are not recorded
+pub const LOG_PATH: &str = "/api/openchamber/proxy-log";
+
+const MAX_ENTRIES: usize = 200;
+/// Body bytes kept per request and per response
+const MAX_BODY_BYTES: usize = 4 * 1024;
+const REDACTED: &str = "[redacted]";
+/// Headers that carry credentials; never stored
+const SENSITIVE_HEADERS: &[&str] = &[
+    "authorization",
+    "proxy-authorization",
+    "cookie",
+    "set-cookie",
+    loopback_auth::TOKEN_HEADER,
+];
+
+/// One request through the desktop server
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ProxyLogEntry {
+    pub id: u64,
+    /// RFC 3339
+    pub at: String,
+    pub method: String,
+    /// With query; the EventSource token is removed
+    pub path: String,
+    pub status: u16,
+    /// Until the response headers were ready
+    pub duration_ms: u64,
+    pub request_headers: BTreeMap<String, String>,
+    pub response_headers: BTreeMap<String, String>,
+    pub request_body: Option<String>,
+    pub request_body_truncated: bool,
+    /// None for event streams, which are never captured
+    pub response_body: Option<String>,
+    pub response_body_truncated: bool,
+}
+
+/// Recent requests for debugging, kept in memory only while `proxyDebug` is on
+#[derive(Default)]
+pub struct ProxyLog {
+    enabled: AtomicBool,
+    next_id: AtomicU64,
+    entries: Mutex<VecDeque<ProxyLogEntry>>,
+}
+
+impl ProxyLog {
+    /// Apply `proxyDebug` from settings; turning it off drops what was recorded
+    pub fn configure(&self, settings: &Value) {
+        let enabled = settings
+            .get(PROXY_DEBUG_SETTING)
+            .and_then(Value::as_bool)
+            .unwrap_or(false);
+        let was_enabled = self.enabled.swap(enabled, Ordering::SeqCst);
+        if was_enabled && !enabled {
+            self.clear();
+        }
+    }
+
+    fn enabled(&self) -> bool {
+        self.enabled.load(Ordering::SeqCst)
+    }
+
+    /// Newest last, at most `limit`
+    pub fn entries(&self, limit: usize) -> Vec<ProxyLogEntry> {
+        let entries = self.entries.lock();
+        let skip = entries.len().saturating_sub(limit);
+        entries.iter().skip(skip).cloned().collect()
+    }
+
+    pub fn clear(&self) {
+        self.entries.lock().clear();
+    }
+
+    fn push(&self, entry: ProxyLogEntry) {
+        let mut entries = self.entries.lock();
+        if entries.len() >= MAX_ENTRIES {
+            entries.pop_front();
+        }
+        entries.push_back(entry);
+    }
+}
+
+#[derive(Default)]
+struct Capture {
+    bytes: Vec<u8>,
+    truncated: bool,
+}
+
+impl Capture {
+    fn append(&mut self, chunk: &[u8]) {
+        let room = MAX_BODY_BYTES.saturating_sub(self.bytes.len());
+        self.bytes
+            .extend_from_slice(&chunk[..chunk.len().min(room)]);
+        self.truncated |= chunk.len() > room;
+    }
+
+    fn text(&self) -> Option<String> {
+        (!self.bytes.is_empty()).then(|| String::from_utf8_lossy(&self.bytes).into_owned())
+    }
+}
+
+/// Entry waiting for its response body to finish; stored when dropped, which
+/// happens at the end of the body or when the client goes away
+struct PendingEntry {
+    log: Arc<ProxyLog>,
+    entry: ProxyLogEntry,
+    request_body: Arc<Mutex<Capture>>,
+    response_body: Option<Arc<Mutex<Capture>>>,
+}
+
+impl Drop for PendingEntry {
+    fn drop(&mut self) {
+        let mut entry = self.entry.clone();
+        {
+            let request_body = self.request_body.lock();
+            entry.request_body = request_body.text();
+            entry.request_body_truncated = request_body.truncated;
+        }
+        if let Some(response_body) = &self.response_body {
+            let response_body = response_body.lock();
+            entry.response_body = response_body.text();
+            entry.response_body_truncated = response_body.truncated;
+        }
+        self.log.push(entry);
+    }
+}
+
+fn redact_headers(headers: &HeaderMap) -> BTreeMap<String, String> {
+    headers
+        .iter()
+        .map(|(name, value)| {
+            let value = if SENSITIVE_HEADERS.contains(&name.as_str()) {
+                REDACTED.to_string()
+            } else {
+                String::from_utf8_lossy(value.as_bytes()).into_owned()
+            };
+            (name.as_str().to_string(), value)
+        })
+        .collect()
+}
+
+fn redacted_path(uri: &Uri) -> String {
+    let query = uri
+        .query()
+        .map(|query| proxy_routes::without_query_param(query, loopback_auth::TOKEN_QUERY))
+        .filter(|query| !query.is_empty());
+    match query {
+        Some(query) => format!("{}?{}", uri.path(), query),
+        None => uri.path().to_string(),
+    }
+}
+
+fn is_event_stream(headers: &HeaderMap) -> bool {
+    headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.starts_with("text/event-stream"))
+}
+
+/// Copy up to MAX_BODY_BYTES of `body` into `capture` as it streams through.
+/// `guard` is dropped with the stream.
+fn tap<G: Send + 'static>(body: Body, capture: Arc<Mutex<Capture>>, guard: G) -> Body {
+    let stream = body.into_data_stream().map(move |chunk| {
+        let _held = &guard;
+        if let Ok(chunk) = &chunk {
+            capture.lock().append(chunk);
+        }
+        chunk
+    });
+    Body::from_stream(stream)
+}
+
+/// Middleware recording each request while `proxyDebug` is on. Bodies are
+/// captured as they stream, so recording never buffers a whole upload.
+pub async fn record(
+    State(state): State<ServerState>,
+    request: Request,
+    next: Next,
+) -> Response<Body> {
+    let log = state.proxy_log.clone();
+    if !log.enabled() || request.uri().path() == LOG_PATH {
+        return next.run(request).await;
+    }
+
+    let started = Instant::now();
+    let method = request.method().to_string();
+    let path = redacted_path(request.uri());
+    let request_headers = redact_headers(request.headers());
+    let request_body = Arc::new(Mutex::new(Capture::default()));
+    // An empty body stays as it is, so the proxy still sees it has none
+    let request = if request.body().size_hint().exact() == Some(0) {
+        request
+    } else {
+        let capture = request_body.clone();
+        request.map(|body| tap(body, capture, ()))
+    };
+
+    let response = next.run(request).await;
+    let skip_body =
+        is_event_stream(response.headers()) || response.body().size_hint().exact() == Some(0);
+    let mut pending = PendingEntry {
+        log,
+        entry: ProxyLogEntry {
+            id: state.proxy_log.next_id.fetch_add(1, Ordering::SeqCst),
+            at: chrono::Utc::now().to_rfc3339(),
+            method,
+            path,
+            status: response.status().as_u16(),
+            duration_ms: started.elapsed().as_millis() as u64,
+            request_headers,
+            response_headers: redact_headers(response.headers()),
+            request_body: None,
+            request_body_truncated: false,
+            response_body: None,
+            response_body_truncated: false,
+        },
+        request_body,
+        response_body: None,
+    };
+    if skip_body {
+        return response;
+    }
+    let capture = Arc::new(Mutex::new(Capture::default()));
+    pending.response_body = Some(capture.clone());
+    response.map(|body| tap(body, capture, pending))
+}
+
+#[derive(Deserialize)]
+pub struct LogQuery {
+    limit: Option<usize>,
+}
+
+/// `GET /api/openchamber/proxy-log?limit=`: recorded requests, newest last
+pub async fn log_handler(
+    State(state): State<ServerState>,
+    Query(query): Query<LogQuery>,
+) -> Json<Vec<ProxyLogEntry>> {
+    Json(state.proxy_log.entries(query.limit.unwrap_or(MAX_ENTRIES)))
+}
+
+/// `DELETE /api/openchamber/proxy-log`
+pub async fn clear_handler(State(state): State<ServerState>) -> Response<Body> {
+    state.proxy_log.clear();
+    StatusCode::NO_CONTENT.into_response()
+}
@@ -0,0 +1,716 @@
+use std::time::Duration;
+
+use axum::http::{header, HeaderMap, Method};
+use log::warn;
+use reqwest::Url;
+use serde_json::{json, Value};
+
+/// Event emitted to the webview whenever a proxied call changed the session list
+pub const SESSIONS_CHANGED_EVENT: &str = "openchamber:sessions-changed";
+
+/// Header naming the project a proxied request is for
+pub const DIRECTORY_HEADER: &str = "x-openchamber-directory";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SessionMutationKind {
+    Created,
+    Updated,
+    Deleted,
+}
+
+impl SessionMutationKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SessionMutationKind::Created => "created",
+            SessionMutationKind::Updated => "updated",
+            SessionMutationKind::Deleted => "deleted",
+        }
+    }
+}
+
+/// A proxied request that mutates the session list
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SessionMutation {
+    pub kind: SessionMutationKind,
+    /// Session id parsed from the path; creates learn their id from the response body
+    pub session_id: Option<String>,
+}
+
+/// Match a method + OpenCode path (after the desktop `/api` mount is stripped)
+/// against the allowlist of session list mutations.
+pub fn match_session_mutation(method: &Method, path: &str) -> Option<SessionMutation> {
+    let segments: Vec<&str> = path
+        .trim_end_matches('/')
+        .split('/')
+        .filter(|segment| !segment.is_empty())
+        .collect();
+
+    match (method, segments.as_slice()) {
+        (&Method::POST, ["session"]) => Some(SessionMutation {
+            kind: SessionMutationKind::Created,
+            session_id: None,
+        }),
+        (&Method::PATCH, ["session", id]) => Some(SessionMutation {
+            kind: SessionMutationKind::Updated,
+            session_id: Some((*id).to_string()),
+        }),
+        (&Method::DELETE, ["session", id]) => Some(SessionMutation {
+            kind: SessionMutationKind::Deleted,
+            session_id: Some((*id).to_string()),
+        }),
+        _ => None,
+    }
+}
+
+/// Session id of a prompt, `POST /session/{id}/message`. OpenCode keeps
+/// generating the reply even if the client that asked for it goes away.
+pub fn match_prompt<'a>(method: &Method, path: &'a str) -> Option<&'a str> {
+    let segments: Vec<&str> = path
+        .trim_end_matches('/')
+        .split('/')
+        .filter(|segment| !segment.is_empty())
+        .collect();
+
+    match (method, segments.as_slice()) {
+        (&Method::POST, ["session", id, "message"]) => Some(id),
+        _ => None,
+    }
+}
+
+/// The abort endpoint for a prompt's `target` URL: `/message` becomes
+/// `/abort`, the query (and so the directory) is kept
+pub fn prompt_abort_target(target: &str) -> Option<String> {
+    let mut url = Url::parse(target).ok()?;
+    let path = url.path().trim_end_matches('/').strip_suffix("/message")?;
+    let path = format!("{}/abort", path);
+    url.set_path(&path);
+    Some(url.to_string())
+}
+
+/// Build the `openchamber:sessions-changed` payload from a matched mutation and
+/// the buffered upstream response body. Returns None when no session id can be resolved.
+pub fn sessions_changed_payload(mutation: &SessionMutation, body: &[u8]) -> Option<Value> {
+    let session_id = match &mutation.session_id {
+        Some(id) => id.clone(),
+        None => serde_json::from_slice::<Value>(body)
+            .ok()
+            .and_then(|value| value.get("id").and_then(Value::as_str).map(str::to_string))?,
+    };
+
+    Some(json!({
+        "operation": mutation.kind.as_str(),
+        "sessionId": session_id,
+    }))
+}
+
+/// First path segments of OpenCode endpoints that accept a `directory` query to
+/// pick the project instance. Everything else (`/global`, `/auth`, `/doc`, ...)
+/// is instance-independent and forwarded untouched.
+const DIRECTORY_SCOPED_ROOTS: &[&str] = &[
+    "agent",
+    "command",
+    "config",
+    "event",
+    "experimental",
+    "file",
+    "find",
+    "formatter",
+    "instance",
+    "lsp",
+    "mcp",
+    "path",
+    "permission",
+    "project",
+    "provider",
+    "pty",
+    "question",
+    "session",
+    "skill",
+    "tui",
+    "vcs",
+];
+
+/// `opencode.forwardDirectory`; on unless explicitly disabled. Read once at startup.
+pub fn forward_directory_enabled(settings: &Value) -> bool {
+    settings
+        .get("opencode")
+        .and_then(|opencode| opencode.get("forwardDirectory"))
+        .and_then(Value::as_bool)
+        .unwrap_or(true)
+}
+
+/// Default for `server.maxBodyMb`, the limit bodies were collected under
+/// before uploads streamed
+const DEFAULT_MAX_REQUEST_BODY_MB: u64 = 32;
+
+/// `server.maxBodyMb` as written, validated by save_settings
+pub fn parse_max_body_mb(section: &Value) -> Result<Option<u64>, String> {
+    match section.get("maxBodyMb") {
+        None | Some(Value::Null) => Ok(None),
+        Some(value) => value
+            .as_u64()
+            .filter(|mb| *mb > 0)
+            .map(Some)
+            .ok_or_else(|| "server.maxBodyMb must be a positive whole number".to_string()),
+    }
+}
+
+/// The largest request body in bytes, from `server.maxBodyMb`; config payloads
+/// are capped lower still. Re-read whenever settings are saved.
+pub fn max_request_body_bytes(settings: &Value) -> u64 {
+    settings
+        .get("server")
+        .and_then(|server| server.get("maxBodyMb"))
+        .and_then(Value::as_u64)
+        .filter(|mb| *mb > 0)
+        .unwrap_or(DEFAULT_MAX_REQUEST_BODY_MB)
+        .saturating_mul(1024 * 1024)
+}
+
+/// Default for `opencode.proxyTimeoutMs`
+const DEFAULT_PROXY_TIMEOUT: Duration = Duration::from_secs(120);
+/// Default for `opencode.proxyTimeoutExempt`: sending a prompt waits for the reply
+const DEFAULT_TIMEOUT_EXEMPT: &[&str] = &["/session/*/message"];
+
+/// How long proxied requests may take, from `opencode.proxyTimeoutMs` (0
+/// disables the timeout) and `opencode.proxyTimeoutExempt`, a list of OpenCode
+/// paths where `*` matches one segment. Read once at startup.
+#[derive(Clone, Debug)]
+pub struct ProxyTimeouts {
+    timeout: Option<Duration>,
+    exempt: Vec<String>,
+}
+
+impl ProxyTimeouts {
+    pub fn from_settings(settings: &Value) -> Self {
+        let section = settings.get("opencode");
+        let timeout = match section.and_then(|opencode| opencode.get("proxyTimeoutMs")) {
+            Some(value) => match value.as_u64() {
+                Some(0) => None,
+                Some(ms) => Some(Duration::from_millis(ms)),
+                None => {
+                    warn!(
+                        "[desktop:http] Ignoring invalid opencode.proxyTimeoutMs: {}",
+                        value
+                    );
+                    Some(DEFAULT_PROXY_TIMEOUT)
+                }
+            },
+            None => Some(DEFAULT_PROXY_TIMEOUT),
+        };
+        let exempt = match section.and_then(|opencode| opencode.get("proxyTimeoutExempt")) {
+            Some(Value::Array(paths)) => paths
+                .iter()
+                .filter_map(Value::as_str)
+                .map(str::to_string)
+                .collect(),
+            _ => DEFAULT_TIMEOUT_EXEMPT
+                .iter()
+                .map(|pattern| pattern.to_string())
+                .collect(),
+        };
+        Self { timeout, exempt }
+    }
+
+    /// Timeout for a request to an OpenCode path; event streams and exempt
+    /// paths stay open as long as OpenCode keeps them open
+    pub fn timeout_for(&self, path: &str, headers: &HeaderMap) -> Option<Duration> {
+        let timeout = self.timeout?;
+        let wants_stream = headers
+            .get(header::ACCEPT)
+            .and_then(|value| value.to_str().ok())
+            .is_some_and(|value| value.contains("text/event-stream"));
+        let exempt = self
+            .exempt
+            .iter()
+            .any(|pattern| path_matches(pattern, path));
+        (!wants_stream && !exempt).then_some(timeout)
+    }
+}
+
+/// Default for `opencode.proxyRetryAttempts`
+const DEFAULT_RETRY_ATTEMPTS: u32 = 3;
+/// Default for `opencode.proxyRetryWindowMs`: long enough for a config restart
+const DEFAULT_RETRY_WINDOW: Duration = Duration::from_secs(4);
+
+/// Retries for GET and HEAD requests that find OpenCode down, e.g. while it
+/// restarts after a config change. `opencode.proxyRetryAttempts` retries (0
+/// disables them) are spread evenly over `opencode.proxyRetryWindowMs`. Read
+/// once at startup.
+#[derive(Clone, Debug)]
+pub struct ProxyRetry {
+    attempts: u32,
+    window: Duration,
+}
+
+impl ProxyRetry {
+    pub fn from_settings(settings: &Value) -> Self {
+        let section = settings.get("opencode");
+        let attempts = section
+            .and_then(|opencode| opencode.get("proxyRetryAttempts"))
+            .and_then(Value::as_u64)
+            .map(|attempts| attempts.min(u32::MAX as u64) as u32)
+            .unwrap_or(DEFAULT_RETRY_ATTEMPTS);
+        let window = section
+            .and_then(|opencode| opencode.get("proxyRetryWindowMs"))
+            .and_then(Value::as_u64)
+            .map(Duration::from_millis)
+            .unwrap_or(DEFAULT_RETRY_WINDOW);
+        Self { attempts, window }
+    }
+
+    /// Only requests that are safe to send twice are retried
+    pub fn applies_to(&self, method: &Method) -> bool {
+        self.attempts > 0 && (method == Method::GET || method == Method::HEAD)
+    }
+
+    pub fn attempts(&self) -> u32 {
+        self.attempts
+    }
+
+    /// Pause before each retry
+    pub fn delay(&self) -> Duration {
+        self.window / self.attempts.max(1)
+    }
+
+    /// `Retry-After` for requests that still failed, in whole seconds
+    pub fn retry_after_secs(&self) -> u64 {
+        self.window.as_secs().max(1)
+    }
+}
+
+/// `pattern` against `path` segment by segment, `*` matching any one segment
+fn path_matches(pattern: &str, path: &str) -> bool {
+    let mut expected = pattern.split('/').filter(|segment| !segment.is_empty());
+    let mut actual = path.split('/').filter(|segment| !segment.is_empty());
+    loop {
+        match (expected.next(), actual.next()) {
+            (None, None) => return true,
+            (Some(expected), Some(actual)) if expected == "*" || expected == actual => {}
+            _ => return false,
+        }
+    }
+}
+
+/// Whether an OpenCode path (after the desktop `/api` mount is stripped) is
+/// scoped by the `directory` query
+pub fn is_directory_scoped(path: &str) -> bool {
+    path.split('/')
+        .find(|segment| !segment.is_empty())
+        .is_some_and(|root| DIRECTORY_SCOPED_ROOTS.contains(&root))
+}
+
+/// Append `directory=<directory>` to `target` unless its query already names a
+/// directory. Existing parameters and any fragment are kept as they are; an
+/// unparseable target is returned unchanged.
+pub fn with_directory_query(target: &str, directory: &str) -> String {
+    let Ok(mut url) = Url::parse(target) else {
+        return target.to_string();
+    };
+    if url.query_pairs().any(|(key, _)| key == "directory") {
+        return target.to_string();
+    }
+    url.query_pairs_mut().append_pair("directory", directory);
+    url.to_string()
+}
+
+/// `query` without any `name` parameter; the others are kept verbatim
+pub fn without_query_param(query: &str, name: &str) -> String {
+    query
+        .split('&')
+        .filter(|pair| pair.split('=').next() != Some(name))
+        .collect::<Vec<_>>()
+        .join("&")
+}
+
+/// Project a proxied request names, from its `directory` query parameter or,
+/// failing that, the DIRECTORY_HEADER
+pub fn requested_directory(query: Option<&str>, headers: &HeaderMap) -> Option<String> {
+    let from_query = query
+        .and_then(|query| Url::parse(&format!("http://localhost/?{query}")).ok())
+        .and_then(|url| {
+            url.query_pairs()
+                .find(|(key, _)| key == "directory")
+                .map(|(_, value)| value.into_owned())
+        });
+    from_query
+        .or_else(|| {
+            headers
+                .get(DIRECTORY_HEADER)
+                .and_then(|value| value.to_str().ok())
+                .map(str::to_string)
+        })
+        .filter(|directory| !directory.trim().is_empty())
+}
+
+/// Config routes answered by the desktop server instead of being proxied
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ConfigRoute {
+    Agent,
+    Command,
+    Reload,
+    /// OpenCode's stored provider logins; the name is the provider id, empty for the list
+    Auth,
+}
+
+const CONFIG_ENTRY_METHODS: &[Method] = &[
+    Method::GET,
+    Method::HEAD,
+    Method::POST,
+    Method::PUT,
+    Method::PATCH,
+    Method::DELETE,
+    Method::OPTIONS,
+];
+const CONFIG_RELOAD_METHODS: &[Method] = &[Method::POST, Method::OPTIONS];
+const CONFIG_AUTH_METHODS: &[Method] =
+    &[Method::GET, Method::HEAD, Method::DELETE, Method::OPTIONS];
+
+impl ConfigRoute {
+    /// Methods the route accepts; drives OPTIONS and 405 responses
+    pub fn allowed_methods(&self) -> &'static [Method] {
+        match self {
+            ConfigRoute::Agent | ConfigRoute::Command => CONFIG_ENTRY_METHODS,
+            ConfigRoute::Reload => CONFIG_RELOAD_METHODS,
+            ConfigRoute::Auth => CONFIG_AUTH_METHODS,
+        }
+    }
+
+    pub fn allows(&self, method: &Method) -> bool {
+        self.allowed_methods().contains(method)
+    }
+
+    /// Value for the `Allow` header
+    pub fn allow_header(&self) -> String {
+        self.allowed_methods()
+            .iter()
+            .map(Method::as_str)
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+}
+
+/// Match an incoming desktop path against the config routes, returning the
+/// route and the raw (still encoded) agent/command name where applicable.
+pub fn match_config_route(path: &str) -> Option<(ConfigRoute, &str)> {
+    if let Some(name) = path.strip_prefix("/api/config/agents/") {
+        return Some((ConfigRoute::Agent, name));
+    }
+    if let Some(name) = path.strip_prefix("/api/config/commands/") {
+        return Some((ConfigRoute::Command, name));
+    }
+    if path == "/api/config/reload" {
+        return Some((ConfigRoute::Reload, ""));
+    }
+    if let Some(provider) = path.strip_prefix("/api/config/auth/") {
+        return Some((ConfigRoute::Auth, provider));
+    }
+    if path == "/api/config/auth" {
+        return Some((ConfigRoute::Auth, ""));
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MB: u64 = 1024 * 1024;
+
+    #[test]
+    fn session_list_mutations_are_matched() {
+        assert_eq!(
+            match_session_mutation(&Method::POST, "/session"),
+            Some(SessionMutation {
+                kind: SessionMutationKind::Created,
+                session_id: None,
+            })
+        );
+        assert_eq!(
+            match_session_mutation(&Method::PATCH, "/session/ses_1/"),
+            Some(SessionMutation {
+                kind: SessionMutationKind::Updated,
+                session_id: Some("ses_1".to_string()),
+            })
+        );
+        assert_eq!(
+            match_session_mutation(&Method::DELETE, "session/ses_1"),
+            Some(SessionMutation {
+                kind: SessionMutationKind::Deleted,
+                session_id: Some("ses_1".to_string()),
+            })
+        );
+    }
+
+    #[test]
+    fn other_session_calls_are_not_mutations() {
+        assert_eq!(match_session_mutation(&Method::GET, "/session"), None);
+        assert_eq!(match_session_mutation(&Method::GET, "/session/ses_1"), None);
+        assert_eq!(
+            match_session_mutation(&Method::POST, "/session/ses_1/message"),
+            None
+        );
+        assert_eq!(match_session_mutation(&Method::DELETE, "/session"), None);
+        assert_eq!(
+            match_session_mutation(&Method::PATCH, "/sessions/ses_1"),
+            None
+        );
+    }
+
+    #[test]
+    fn prompts_are_matched_by_session() {
+        assert_eq!(
+            match_prompt(&Method::POST, "/session/ses_1/message"),
+            Some("ses_1")
+        );
+        assert_eq!(
+            match_prompt(&Method::POST, "session/ses_1/message/"),
+            Some("ses_1")
+        );
+        assert_eq!(match_prompt(&Method::GET, "/session/ses_1/message"), None);
+        assert_eq!(match_prompt(&Method::POST, "/session/ses_1/abort"), None);
+        assert_eq!(
+            match_prompt(&Method::POST, "/session/ses_1/message/msg_1"),
+            None
+        );
+    }
+
+    #[test]
+    fn prompts_are_aborted_at_the_same_directory() {
+        assert_eq!(
+            prompt_abort_target(
+                "http://127.0.0.1:4096/session/ses_1/message?directory=%2Fsrv%2Fapp"
+            )
+            .as_deref(),
+            Some("http://127.0.0.1:4096/session/ses_1/abort?directory=%2Fsrv%2Fapp")
+        );
+        assert_eq!(
+            prompt_abort_target("http://127.0.0.1:4096/api/session/ses_1/message/").as_deref(),
+            Some("http://127.0.0.1:4096/api/session/ses_1/abort")
+        );
+        assert_eq!(
+            prompt_abort_target("http://127.0.0.1:4096/session/ses_1"),
+            None
+        );
+        assert_eq!(prompt_abort_target("/session/ses_1/message"), None);
+    }
+
+    #[test]
+    fn sessions_changed_payload_takes_the_id_from_the_path_or_body() {
+        let updated = match_session_mutation(&Method::PATCH, "/session/ses_1").unwrap();
+        assert_eq!(
+            sessions_changed_payload(&updated, b""),
+            Some(json!({ "operation": "updated", "sessionId": "ses_1" }))
+        );
+
+        let created = match_session_mutation(&Method::POST, "/session").unwrap();
+        assert_eq!(
+            sessions_changed_payload(&created, br#"{"id":"ses_2","title":"New"}"#),
+            Some(json!({ "operation": "created", "sessionId": "ses_2" }))
+        );
+        assert_eq!(sessions_changed_payload(&created, b"not json"), None);
+        assert_eq!(
+            sessions_changed_payload(&created, br#"{"title":"New"}"#),
+            None
+        );
+    }
+
+    #[test]
+    fn config_routes_are_matched_with_their_names() {
+        assert_eq!(
+            match_config_route("/api/config/agents/build%20fixer"),
+            Some((ConfigRoute::Agent, "build%20fixer"))
+        );
+        assert_eq!(
+            match_config_route("/api/config/commands/review"),
+            Some((ConfigRoute::Command, "review"))
+        );
+        assert_eq!(
+            match_config_route("/api/config/reload"),
+            Some((ConfigRoute::Reload, ""))
+        );
+        assert_eq!(match_config_route("/api/config/reload/now"), None);
+        assert_eq!(match_config_route("/api/config"), None);
+    }
+
+    #[test]
+    fn config_routes_list_their_methods() {
+        assert!(ConfigRoute::Agent.allows(&Method::HEAD));
+        assert!(ConfigRoute::Command.allows(&Method::DELETE));
+        assert!(!ConfigRoute::Command.allows(&Method::TRACE));
+        assert!(!ConfigRoute::Reload.allows(&Method::GET));
+        assert_eq!(ConfigRoute::Reload.allow_header(), "POST, OPTIONS");
+    }
+
+    #[test]
+    fn max_request_body_defaults_to_32mb() {
+        assert_eq!(max_request_body_bytes(&json!({})), 32 * MB);
+        assert_eq!(
+            max_request_body_bytes(&json!({"server": {"maxBodyMb": 0}})),
+            32 * MB
+        );
+    }
+
+    #[test]
+    fn max_request_body_reads_only_server_max_body_mb() {
+        assert_eq!(
+            max_request_body_bytes(&json!({"server": {"maxBodyMb": 512}})),
+            512 * MB
+        );
+        assert_eq!(
+            max_request_body_bytes(&json!({"opencode": {"maxRequestBodyMb": 512}})),
+            32 * MB
+        );
+    }
+
+    #[test]
+    fn parse_max_body_mb_accepts_positive_whole_numbers() {
+        assert_eq!(parse_max_body_mb(&json!({})), Ok(None));
+        assert_eq!(parse_max_body_mb(&json!({"maxBodyMb": null})), Ok(None));
+        assert_eq!(parse_max_body_mb(&json!({"maxBodyMb": 64})), Ok(Some(64)));
+        assert!(parse_max_body_mb(&json!({"maxBodyMb": 0})).is_err());
+        assert!(parse_max_body_mb(&json!({"maxBodyMb": 1.5})).is_err());
+        assert!(parse_max_body_mb(&json!({"maxBodyMb": "64"})).is_err());
+    }
+
+    #[test]
+    fn directory_forwarding_is_on_unless_disabled() {
+        assert!(forward_directory_enabled(&json!({})));
+        assert!(forward_directory_enabled(
+            &json!({"opencode": {"forwardDirectory": true}})
+        ));
+        assert!(!forward_directory_enabled(
+            &json!({"opencode": {"forwardDirectory": false}})
+        ));
+    }
+
+    #[test]
+    fn only_instance_endpoints_are_directory_scoped() {
+        assert!(is_directory_scoped("/session"));
+        assert!(is_directory_scoped("/session/ses_1/message"));
+        assert!(is_directory_scoped("//file/content"));
+        assert!(is_directory_scoped("event"));
+        assert!(is_directory_scoped("/config/providers"));
+
+        assert!(!is_directory_scoped("/global/event"));
+        assert!(!is_directory_scoped("/auth/openai"));
+        assert!(!is_directory_scoped("/doc"));
+        assert!(!is_directory_scoped("/sessions"));
+        assert!(!is_directory_scoped("/"));
+        assert!(!is_directory_scoped(""));
+    }
+
+    #[test]
+    fn inspected_session_mutations_are_directory_scoped() {
+        for (method, path) in [
+            (Method::POST, "/session"),
+            (Method::PATCH, "/session/ses_1"),
+            (Method::DELETE, "/session/ses_1"),
+        ] {
+            assert!(match_session_mutation(&method, path).is_some());
+            assert!(is_directory_scoped(path), "{path}");
+        }
+    }
+
+    #[test]
+    fn the_directory_is_appended_to_the_query() {
+        assert_eq!(
+            with_directory_query("http://127.0.0.1:4096/session", "/home/ada/app"),
+            "http://127.0.0.1:4096/session?directory=%2Fhome%2Fada%2Fapp"
+        );
+        assert_eq!(
+            with_directory_query("http://127.0.0.1:4096/find?pattern=a+b&limit=5", "/srv/app"),
+            "http://127.0.0.1:4096/find?pattern=a+b&limit=5&directory=%2Fsrv%2Fapp"
+        );
+        // Trailing separators do not leave an empty parameter behind
+        assert_eq!(
+            with_directory_query("http://127.0.0.1:4096/session?", "/srv/app"),
+            "http://127.0.0.1:4096/session?directory=%2Fsrv%2Fapp"
+        );
+    }
+
+    #[test]
+    fn directories_are_url_encoded() {
+        assert_eq!(
+            with_directory_query(
+                "http://127.0.0.1:4096/session",
+                "/Users/ada/My Projects/a&b#c?d=e+f"
+            ),
+            "http://127.0.0.1:4096/session?directory=%2FUsers%2Fada%2FMy+Projects%2Fa%26b%23c%3Fd%3De%2Bf"
+        );
+        assert_eq!(
+            with_directory_query("http://127.0.0.1:4096/session", r"C:\Users\ada\répertoire"),
+            "http://127.0.0.1:4096/session?directory=C%3A%5CUsers%5Cada%5Cr%C3%A9pertoire"
+        );
+    }
+
+    #[test]
+    fn fragments_stay_after_the_query() {
+        assert_eq!(
+            with_directory_query("http://127.0.0.1:4096/session?limit=1#top", "/srv/app"),
+            "http://127.0.0.1:4096/session?limit=1&directory=%2Fsrv%2Fapp#top"
+        );
+        assert_eq!(
+            with_directory_query("http://127.0.0.1:4096/session#top", "/srv/app"),
+            "http://127.0.0.1:4096/session?directory=%2Fsrv%2Fapp#top"
+        );
+    }
+
+    #[test]
+    fn an_existing_directory_is_left_alone() {
+        for target in [
+            "http://127.0.0.1:4096/session?directory=%2Fother",
+            "http://127.0.0.1:4096/session?limit=1&directory=/other#top",
+            "http://127.0.0.1:4096/session?directory=",
+            "http://127.0.0.1:4096/session?directory",
+        ] {
+            assert_eq!(with_directory_query(target, "/srv/app"), target);
+        }
+        // Only the exact key counts
+        assert_eq!(
+            with_directory_query("http://127.0.0.1:4096/session?directoryId=1", "/srv/app"),
+            "http://127.0.0.1:4096/session?directoryId=1&directory=%2Fsrv%2Fapp"
+        );
+    }
+
+    #[test]
+    fn unparseable_targets_are_returned_unchanged() {
+        assert_eq!(with_directory_query("/session", "/srv/app"), "/session");
+        assert_eq!(with_directory_query("", "/srv/app"), "");
+    }
+
+    #[test]
+    fn retries_default_to_three_over_four_seconds() {
+        let retry = ProxyRetry::from_settings(&json!({}));
+        assert_eq!(retry.attempts(), 3);
+        assert_eq!(retry.delay(), Duration::from_secs(4) / 3);
+        assert_eq!(retry.retry_after_secs(), 4);
+    }
+
+    #[test]
+    fn retry_settings_are_read_from_the_opencode_section() {
+        let retry = ProxyRetry::from_settings(
+            &json!({"opencode": {"proxyRetryAttempts": 5, "proxyRetryWindowMs": 500}}),
+        );
+        assert_eq!(retry.attempts(), 5);
+        assert_eq!(retry.delay(), Duration::from_millis(100));
+        assert_eq!(retry.retry_after_secs(), 1);
+    }
+
+    #[test]
+    fn only_gets_and_heads_are_retried() {
+        let retry = ProxyRetry::from_settings(&json!({}));
+        assert!(retry.applies_to(&Method::GET));
+        assert!(retry.applies_to(&Method::HEAD));
+        for method in [Method::POST, Method::PUT, Method::PATCH, Method::DELETE] {
+            assert!(!retry.applies_to(&method), "{method}");
+        }
+    }
+
+    #[test]
+    fn zero_attempts_disable_retries() {
+        let retry = ProxyRetry::from_settings(&json!({"opencode": {"proxyRetryAttempts": 0}}));
+        assert!(!retry.applies_to(&Method::GET));
+        assert_eq!(retry.delay(), DEFAULT_RETRY_WINDOW);
+    }
+}
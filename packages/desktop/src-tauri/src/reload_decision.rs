@@ -0,0 +1,310 @@
+use std::{collections::HashSet, time::Duration};
+
+use log::debug;
+use serde::Serialize;
+
+use crate::{
+    opencode_client::OpenCodeClient,
+    session_activity::{ActivityPhase, SessionPhases},
+};
+
+/// Bounds the message lookups so config responses aren't held up
+const DEPENDENCY_LOOKUP_TIMEOUT: Duration = Duration::from_secs(3);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum EntityKind {
+    Agent,
+    Command,
+}
+
+/// A config entity touched by a mutation, reported back as `changedEntities`
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ChangedEntity {
+    pub kind: EntityKind,
+    pub name: String,
+}
+
+impl ChangedEntity {
+    pub fn agent(name: &str) -> Self {
+        Self {
+            kind: EntityKind::Agent,
+            name: name.to_string(),
+        }
+    }
+
+    pub fn command(name: &str) -> Self {
+        Self {
+            kind: EntityKind::Command,
+            name: name.to_string(),
+        }
+    }
+}
+
+/// What the sessions open in the UI depend on
+#[derive(Debug, Default)]
+pub struct SessionDependencies {
+    /// Agents that produced or received messages in those sessions
+    pub agents: HashSet<String>,
+    /// Some session is mid-turn and may be running any command
+    pub busy: bool,
+}
+
+/// Whether the webview must reload after OpenCode restarted for `changed`.
+/// Conservative: unknown dependencies or an unscoped change (manual reload)
+/// always reload; otherwise only entities open sessions rely on do.
+pub fn requires_client_reload(
+    changed: &[ChangedEntity],
+    dependencies: Option<&SessionDependencies>,
+) -> bool {
+    let Some(dependencies) = dependencies else {
+        return true;
+    };
+    if changed.is_empty() {
+        return true;
+    }
+    changed.iter().any(|entity| match entity.kind {
+        EntityKind::Agent => dependencies.agents.contains(&entity.name),
+        // Commands are resolved per invocation, so only a running turn can be affected
+        EntityKind::Command => dependencies.busy,
+    })
+}
+
+/// Collect agents referenced by the active session and any session that is
+/// busy or cooling down. None when a lookup fails, which callers treat as
+/// "reload to be safe".
+pub async fn collect_session_dependencies(
+    client: OpenCodeClient,
+    phases: &SessionPhases,
+    active_session: Option<String>,
+) -> Option<SessionDependencies> {
+    let mut dependencies = SessionDependencies::default();
+    let mut sessions: Vec<String> = Vec::new();
+    for (session_id, phase) in phases.lock().await.iter() {
+        if *phase == ActivityPhase::Idle {
+            continue;
+        }
+        dependencies.busy |= *phase == ActivityPhase::Busy;
+        sessions.push(session_id.clone());
+    }
+    if let Some(active) = active_session {
+        if !sessions.contains(&active) {
+            sessions.push(active);
+        }
+    }
+
+    let client = client.with_timeout(DEPENDENCY_LOOKUP_TIMEOUT);
+    for session_id in sessions {
+        let messages = match client.list_messages(&session_id).await {
+            Ok(messages) => messages,
+            Err(err) => {
+                debug!(
+                    "[desktop:config] Could not read session {} for reload decision: {}",
+                    session_id, err
+                );
+                return None;
+            }
+        };
+        for message in messages {
+            dependencies
+                .agents
+                .extend(message.info.agent.into_iter().chain(message.info.mode));
+        }
+    }
+    Some(dependencies)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{opencode_manager::OpenCodeManager, upstream_pool::UpstreamPool};
+    use axum::{extract::Path, http::StatusCode, routing::get, Json, Router};
+    use serde_json::{json, Value};
+    use std::{collections::HashMap, path::PathBuf, sync::Arc};
+    use tokio::sync::Mutex;
+
+    fn dependencies(agents: &[&str], busy: bool) -> SessionDependencies {
+        SessionDependencies {
+            agents: agents.iter().map(|agent| agent.to_string()).collect(),
+            busy,
+        }
+    }
+
+    #[test]
+    fn the_reload_decision_matrix() {
+        let agent = ChangedEntity::agent("reviewer");
+        let other_agent = ChangedEntity::agent("planner");
+        let command = ChangedEntity::command("deploy");
+        let uses_reviewer = dependencies(&["build", "reviewer"], false);
+        let busy = dependencies(&["build"], true);
+        let idle = dependencies(&["build"], false);
+
+        let cases: [(&str, Vec<ChangedEntity>, Option<&SessionDependencies>, bool); 10] = [
+            ("lookup failed", vec![agent.clone()], None, true),
+            ("manual reload", vec![], Some(&idle), true),
+            (
+                "agent in use",
+                vec![agent.clone()],
+                Some(&uses_reviewer),
+                true,
+            ),
+            (
+                "agent unused",
+                vec![other_agent.clone()],
+                Some(&uses_reviewer),
+                false,
+            ),
+            (
+                "agent unused while busy",
+                vec![agent.clone()],
+                Some(&busy),
+                false,
+            ),
+            (
+                "command while idle",
+                vec![command.clone()],
+                Some(&idle),
+                false,
+            ),
+            (
+                "command while busy",
+                vec![command.clone()],
+                Some(&busy),
+                true,
+            ),
+            ("command, lookup failed", vec![command.clone()], None, true),
+            (
+                "one of several in use",
+                vec![other_agent.clone(), agent.clone()],
+                Some(&uses_reviewer),
+                true,
+            ),
+            (
+                "none of several in use",
+                vec![other_agent, command],
+                Some(&uses_reviewer),
+                false,
+            ),
+        ];
+        for (case, changed, dependencies, expected) in cases {
+            assert_eq!(
+                requires_client_reload(&changed, dependencies),
+                expected,
+                "{case}"
+            );
+        }
+    }
+
+    #[test]
+    fn changed_entities_serialize_for_the_frontend() {
+        assert_eq!(
+            serde_json::to_value([ChangedEntity::agent("a"), ChangedEntity::command("c")]).unwrap(),
+            json!([
+                { "kind": "agent", "name": "a" },
+                { "kind": "command", "name": "c" },
+            ])
+        );
+    }
+
+    /// OpenCode serving the messages of `sessions`; other sessions are 404
+    async fn client_with(sessions: Value) -> OpenCodeClient {
+        let sessions: HashMap<String, Value> = serde_json::from_value(sessions).unwrap();
+        let app = Router::new().route(
+            "/session/{id}/message",
+            get(move |Path(id): Path<String>| async move {
+                sessions
+                    .get(&id)
+                    .cloned()
+                    .map(Json)
+                    .ok_or(StatusCode::NOT_FOUND)
+            }),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        tokio::spawn(async move {
+            axum::serve(listener, app).await.ok();
+        });
+
+        let opencode = Arc::new(OpenCodeManager::ready_on(
+            port,
+            PathBuf::from("/work/project"),
+        ));
+        let pool = Arc::new(UpstreamPool::new(opencode.clone()).unwrap());
+        OpenCodeClient::new(pool, opencode)
+    }
+
+    fn message(agent: Option<&str>, mode: Option<&str>) -> Value {
+        json!({ "info": { "id": "msg", "agent": agent, "mode": mode }, "parts": [] })
+    }
+
+    fn phases(entries: &[(&str, ActivityPhase)]) -> SessionPhases {
+        Arc::new(Mutex::new(
+            entries
+                .iter()
+                .map(|(id, phase)| (id.to_string(), phase.clone()))
+                .collect(),
+        ))
+    }
+
+    fn sorted(dependencies: &SessionDependencies) -> Vec<&str> {
+        let mut agents: Vec<_> = dependencies.agents.iter().map(String::as_str).collect();
+        agents.sort();
+        agents
+    }
+
+    #[tokio::test]
+    async fn open_sessions_contribute_their_agents() {
+        let client = client_with(json!({
+            "ses_busy": [message(Some("build"), None), message(None, Some("build-mode"))],
+            "ses_cooling": [message(None, Some("reviewer"))],
+            "ses_active": [message(Some("planner"), None)],
+            "ses_idle": [message(Some("never-read"), None)],
+        }))
+        .await;
+        let phases = phases(&[
+            ("ses_busy", ActivityPhase::Busy),
+            ("ses_cooling", ActivityPhase::Cooldown),
+            ("ses_idle", ActivityPhase::Idle),
+        ]);
+
+        let found = collect_session_dependencies(client, &phases, Some("ses_active".to_string()))
+            .await
+            .unwrap();
+        assert_eq!(
+            sorted(&found),
+            ["build", "build-mode", "planner", "reviewer"]
+        );
+        assert!(found.busy);
+    }
+
+    #[tokio::test]
+    async fn nothing_open_means_nothing_depends_on_the_change() {
+        let client = client_with(json!({})).await;
+        let phases = phases(&[("ses_idle", ActivityPhase::Idle)]);
+
+        let found = collect_session_dependencies(client, &phases, None)
+            .await
+            .unwrap();
+        assert!(found.agents.is_empty());
+        assert!(!found.busy);
+        assert!(!requires_client_reload(
+            &[ChangedEntity::agent("reviewer")],
+            Some(&found)
+        ));
+    }
+
+    #[tokio::test]
+    async fn a_failed_lookup_leaves_the_decision_unknown() {
+        let client = client_with(json!({ "ses_a": [message(Some("build"), None)] })).await;
+        let phases = phases(&[("ses_a", ActivityPhase::Cooldown)]);
+
+        let found =
+            collect_session_dependencies(client, &phases, Some("ses_missing".to_string())).await;
+        assert!(found.is_none());
+        assert!(requires_client_reload(
+            &[ChangedEntity::agent("unrelated")],
+            found.as_ref()
+        ));
+    }
+}
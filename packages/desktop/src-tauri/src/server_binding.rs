@@ -0,0 +1,230 @@
+use std::net::{IpAddr, Ipv4Addr, SocketAddr, UdpSocket};
+
+use log::{info, warn};
+use serde_json::Value;
+use tokio::sync::watch;
+
+use crate::web_ui::WebUiAccess;
+
+/// Default for `server.bindAddress`: only this machine can connect
+pub const DEFAULT_BIND_ADDRESS: IpAddr = IpAddr::V4(Ipv4Addr::LOCALHOST);
+
+/// `server.bindAddress` as written, validated by save_settings
+pub fn parse_bind_address(section: &Value) -> Result<Option<IpAddr>, String> {
+    match section.get("bindAddress") {
+        None | Some(Value::Null) => Ok(None),
+        Some(Value::String(address)) => {
+            let address = address
+                .trim()
+                .parse::<IpAddr>()
+                .map_err(|_| format!("server.bindAddress is not an IP address: {}", address))?;
+            // The webview reaches the server on 127.0.0.1, which `::` may not cover
+            if address.is_ipv6() && address.is_unspecified() {
+                return Err("server.bindAddress cannot be ::; use 0.0.0.0".to_string());
+            }
+            Ok(Some(address))
+        }
+        Some(_) => Err("server.bindAddress must be a string".to_string()),
+    }
+}
+
+/// Address to bind to for these settings. Remote browsers authenticate through
+/// the served web UI, so a non-loopback address needs `server.serveWebUi`;
+/// without it, or for an invalid address, the server stays on loopback.
+pub fn bind_address(settings: &Value, web_ui: &WebUiAccess) -> IpAddr {
+    let Some(section) = settings.get("server") else {
+        return DEFAULT_BIND_ADDRESS;
+    };
+    let address = match parse_bind_address(section) {
+        Ok(address) => address.unwrap_or(DEFAULT_BIND_ADDRESS),
+        Err(err) => {
+            warn!(
+                "[desktop:http] {}; staying on {}",
+                err, DEFAULT_BIND_ADDRESS
+            );
+            return DEFAULT_BIND_ADDRESS;
+        }
+    };
+    if !address.is_loopback() && !web_ui.enabled() {
+        warn!(
+            "[desktop:http] Not binding to {}: remote browsers need server.serveWebUi",
+            address
+        );
+        return DEFAULT_BIND_ADDRESS;
+    }
+    address
+}
+
+/// Addresses to listen on. A specific LAN address keeps loopback alongside it,
+/// as the webview always connects to 127.0.0.1.
+pub fn listen_addresses(address: IpAddr, port: u16) -> Vec<SocketAddr> {
+    let mut addresses = vec![SocketAddr::new(address, port)];
+    if !address.is_loopback() && !address.is_unspecified() {
+        addresses.push(SocketAddr::new(DEFAULT_BIND_ADDRESS, port));
+    }
+    addresses
+}
+
+/// The address other machines can use: `address` itself, or for 0.0.0.0 the
+/// one on the default route. Connecting a UDP socket sends nothing.
+fn reachable_address(address: IpAddr) -> Option<IpAddr> {
+    if !address.is_unspecified() {
+        return Some(address);
+    }
+    let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0)).ok()?;
+    socket.connect((Ipv4Addr::new(192, 0, 2, 1), 9)).ok()?;
+    Some(socket.local_addr().ok()?.ip())
+}
+
+/// Base URL other machines reach the server at; None while it only listens on loopback
+pub fn remote_base_url(address: IpAddr, port: u16) -> Option<String> {
+    if address.is_loopback() {
+        return None;
+    }
+    let host = match reachable_address(address) {
+        Some(IpAddr::V6(v6)) => format!("[{}]", v6),
+        Some(ip) => ip.to_string(),
+        None => "<this machine's address>".to_string(),
+    };
+    Some(format!("http://{}:{}", host, port))
+}
+
+/// Log where other machines find the web UI. The sign-in link carries a live
+/// exchange code, so it is only handed out by desktop_get_web_ui_url, never logged.
+pub fn log_remote_url(address: IpAddr, port: u16, web_ui: &WebUiAccess) {
+    if !web_ui.enabled() {
+        return;
+    }
+    if let Some(base) = remote_base_url(address, port) {
+        info!(
+            "[desktop:web] Web UI reachable from other machines at {}; copy a sign-in link from the desktop app",
+            base
+        );
+    }
+}
+
+/// Where the desktop HTTP server listens. Changing it makes the server close
+/// its listeners and bind again; the app keeps running.
+pub struct ServerBinding {
+    sender: watch::Sender<IpAddr>,
+}
+
+impl ServerBinding {
+    pub fn new(address: IpAddr) -> Self {
+        Self {
+            sender: watch::Sender::new(address),
+        }
+    }
+
+    pub fn current(&self) -> IpAddr {
+        *self.sender.borrow()
+    }
+
+    pub fn subscribe(&self) -> watch::Receiver<IpAddr> {
+        self.sender.subscribe()
+    }
+
+    /// Back to loopback after the configured address could not be bound
+    pub fn fall_back(&self) {
+        self.sender.send_replace(DEFAULT_BIND_ADDRESS);
+    }
+
+    /// Apply `server.bindAddress` from saved settings
+    pub fn configure(&self, settings: &Value, web_ui: &WebUiAccess) {
+        let address = bind_address(settings, web_ui);
+        let changed = self.sender.send_if_modified(|current| {
+            let changed = *current != address;
+            *current = address;
+            changed
+        });
+        if changed {
+            info!("[desktop:http] Rebinding to {}", address);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    const LAN: IpAddr = IpAddr::V4(Ipv4Addr::new(192, 168, 1, 20));
+
+    #[test]
+    fn bind_addresses_are_validated() {
+        assert_eq!(parse_bind_address(&json!({})), Ok(None));
+        assert_eq!(
+            parse_bind_address(&json!({ "bindAddress": " 192.168.1.20 " })),
+            Ok(Some(LAN))
+        );
+        assert!(parse_bind_address(&json!({ "bindAddress": "::" })).is_err());
+        assert!(parse_bind_address(&json!({ "bindAddress": "my-laptop" })).is_err());
+        assert!(parse_bind_address(&json!({ "bindAddress": 8 })).is_err());
+    }
+
+    #[test]
+    fn lan_addresses_need_the_web_ui() {
+        let settings = json!({ "server": { "bindAddress": "192.168.1.20" } });
+        assert_eq!(
+            bind_address(&settings, &WebUiAccess::new(false)),
+            DEFAULT_BIND_ADDRESS
+        );
+        assert_eq!(bind_address(&settings, &WebUiAccess::new(true)), LAN);
+
+        let invalid = json!({ "server": { "bindAddress": "nope" } });
+        assert_eq!(
+            bind_address(&invalid, &WebUiAccess::new(true)),
+            DEFAULT_BIND_ADDRESS
+        );
+    }
+
+    #[test]
+    fn lan_listeners_keep_loopback_for_the_webview() {
+        assert_eq!(
+            listen_addresses(LAN, 4000),
+            [
+                SocketAddr::new(LAN, 4000),
+                SocketAddr::new(DEFAULT_BIND_ADDRESS, 4000)
+            ]
+        );
+        let any = IpAddr::V4(Ipv4Addr::UNSPECIFIED);
+        assert_eq!(listen_addresses(any, 4000), [SocketAddr::new(any, 4000)]);
+        assert_eq!(
+            listen_addresses(DEFAULT_BIND_ADDRESS, 4000),
+            [SocketAddr::new(DEFAULT_BIND_ADDRESS, 4000)]
+        );
+    }
+
+    #[test]
+    fn remote_urls_name_the_reachable_host_without_a_code() {
+        assert_eq!(remote_base_url(DEFAULT_BIND_ADDRESS, 4000), None);
+        assert_eq!(
+            remote_base_url(LAN, 4000).as_deref(),
+            Some("http://192.168.1.20:4000")
+        );
+        assert_eq!(
+            remote_base_url("fd00::20".parse().unwrap(), 4000).as_deref(),
+            Some("http://[fd00::20]:4000")
+        );
+    }
+
+    #[test]
+    fn configure_rebinds_only_on_change() {
+        let binding = ServerBinding::new(DEFAULT_BIND_ADDRESS);
+        let mut changes = binding.subscribe();
+        let web_ui = WebUiAccess::new(true);
+
+        binding.configure(&json!({}), &web_ui);
+        assert!(!changes.has_changed().unwrap());
+
+        binding.configure(
+            &json!({ "server": { "bindAddress": "192.168.1.20" } }),
+            &web_ui,
+        );
+        assert!(changes.has_changed().unwrap());
+        assert_eq!(*changes.borrow_and_update(), LAN);
+
+        binding.fall_back();
+        assert_eq!(binding.current(), DEFAULT_BIND_ADDRESS);
+    }
+}
@@ -0,0 +1,185 @@
+use std::{
+    collections::BTreeMap,
+    fmt::Write,
+    sync::atomic::{AtomicU64, Ordering},
+    time::Instant,
+};
+
+use axum::{
+    body::Body,
+    extract::{Query, Request, State},
+    http::{header, Response, StatusCode},
+    middleware::Next,
+    response::IntoResponse,
+    Json,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::ServerState;
+
+pub const METRICS_PATH: &str = "/api/openchamber/metrics";
+
+const STATUS_CLASSES: [&str; 5] = ["1xx", "2xx", "3xx", "4xx", "5xx"];
+
+/// Counters for the desktop HTTP server. Plain atomics, so counting never
+/// contends on a lock in the request path.
+pub struct ServerMetrics {
+    started: Instant,
+    requests: AtomicU64,
+    /// Indexed like STATUS_CLASSES
+    status_classes: [AtomicU64; 5],
+    bytes_up: AtomicU64,
+    bytes_down: AtomicU64,
+}
+
+impl Default for ServerMetrics {
+    fn default() -> Self {
+        Self {
+            started: Instant::now(),
+            requests: AtomicU64::new(0),
+            status_classes: Default::default(),
+            bytes_up: AtomicU64::new(0),
+            bytes_down: AtomicU64::new(0),
+        }
+    }
+}
+
+impl ServerMetrics {
+    /// Request body bytes forwarded to OpenCode
+    pub fn add_bytes_up(&self, bytes: usize) {
+        self.bytes_up.fetch_add(bytes as u64, Ordering::Relaxed);
+    }
+
+    /// Response body bytes relayed back from OpenCode
+    pub fn add_bytes_down(&self, bytes: usize) {
+        self.bytes_down.fetch_add(bytes as u64, Ordering::Relaxed);
+    }
+
+    fn observe(&self, status: StatusCode) {
+        self.requests.fetch_add(1, Ordering::Relaxed);
+        let class = (status.as_u16() / 100).clamp(1, 5) as usize - 1;
+        self.status_classes[class].fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MetricsSnapshot {
+    pub total_requests: u64,
+    /// Keyed by `1xx` to `5xx`
+    pub requests_by_status: BTreeMap<&'static str, u64>,
+    pub bytes_proxied_up: u64,
+    pub bytes_proxied_down: u64,
+    /// Through the event hub or streamed via the plain proxy
+    pub active_event_streams: usize,
+    /// Restarts and crash recoveries since launch
+    pub opencode_restarts: u64,
+    pub uptime_secs: u64,
+}
+
+impl MetricsSnapshot {
+    fn collect(state: &ServerState) -> Self {
+        let metrics = &state.metrics;
+        Self {
+            total_requests: metrics.requests.load(Ordering::Relaxed),
+            requests_by_status: STATUS_CLASSES
+                .iter()
+                .zip(&metrics.status_classes)
+                .map(|(class, count)| (*class, count.load(Ordering::Relaxed)))
+                .collect(),
+            bytes_proxied_up: metrics.bytes_up.load(Ordering::Relaxed),
+            bytes_proxied_down: metrics.bytes_down.load(Ordering::Relaxed),
+            active_event_streams: state.event_hub.active_streams(),
+            opencode_restarts: state.opencode.restart_count(),
+            uptime_secs: metrics.started.elapsed().as_secs(),
+        }
+    }
+
+    /// Prometheus text exposition format, version 0.0.4
+    fn prometheus(&self) -> String {
+        let mut out = String::new();
+        let mut metric = |name: &str, kind: &str, help: &str, samples: &[(String, u64)]| {
+            let _ = writeln!(out, "# HELP openchamber_{name} {help}");
+            let _ = writeln!(out, "# TYPE openchamber_{name} {kind}");
+            for (labels, value) in samples {
+                let _ = writeln!(out, "openchamber_{name}{labels} {value}");
+            }
+        };
+        metric(
+            "requests_total",
+            "counter",
+            "Requests served by the desktop server",
+            &[(String::new(), self.total_requests)],
+        );
+        metric(
+            "requests_by_status_total",
+            "counter",
+            "Requests served by the desktop server, by status class",
+            &self
+                .requests_by_status
+                .iter()
+                .map(|(class, count)| (format!("{{class=\"{class}\"}}"), *count))
+                .collect::<Vec<_>>(),
+        );
+        metric(
+            "proxied_bytes_total",
+            "counter",
+            "Body bytes proxied to and from OpenCode",
+            &[
+                ("{direction=\"up\"}".to_string(), self.bytes_proxied_up),
+                ("{direction=\"down\"}".to_string(), self.bytes_proxied_down),
+            ],
+        );
+        metric(
+            "active_event_streams",
+            "gauge",
+            "Event streams currently open",
+            &[(String::new(), self.active_event_streams as u64)],
+        );
+        metric(
+            "opencode_restarts_total",
+            "counter",
+            "OpenCode restarts and crash recoveries",
+            &[(String::new(), self.opencode_restarts)],
+        );
+        metric(
+            "uptime_seconds",
+            "gauge",
+            "Seconds since the desktop server started",
+            &[(String::new(), self.uptime_secs)],
+        );
+        out
+    }
+}
+
+/// Middleware counting every response by status class
+pub async fn count_requests(
+    State(state): State<ServerState>,
+    request: Request,
+    next: Next,
+) -> Response<Body> {
+    let response = next.run(request).await;
+    state.metrics.observe(response.status());
+    response
+}
+
+#[derive(Deserialize)]
+pub struct MetricsQuery {
+    format: Option<String>,
+}
+
+/// `GET /api/openchamber/metrics`: JSON, or Prometheus text with `?format=prometheus`
+pub async fn metrics_handler(
+    State(state): State<ServerState>,
+    Query(query): Query<MetricsQuery>,
+) -> Response<Body> {
+    let snapshot = MetricsSnapshot::collect(&state);
+    if query.format.as_deref() == Some("prometheus") {
+        return (
+            [(header::CONTENT_TYPE, "text/plain; version=0.0.4")],
+            snapshot.prometheus(),
+        )
+            .into_response();
+    }
+    Json(snapshot).into_response()
+}
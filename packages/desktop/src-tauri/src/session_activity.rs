@@ -1,28 +1,23 @@
 use std::{
-    collections::HashMap,
+    collections::{HashMap, HashSet},
     sync::Arc,
     time::Duration,
 };
 
 use anyhow::Result;
-use futures_util::TryStreamExt;
-use log::{debug, info, warn};
-use reqwest::Client;
-use serde::Deserialize;
+use log::{info, warn};
+use serde::Serialize;
 use serde_json::Value;
 use tauri::{AppHandle, Emitter};
 use tokio::sync::Mutex;
-use tokio_util::io::StreamReader;
 
-use crate::DesktopRuntime;
+use crate::{
+    opencode_client::{OpenCodeError, OpenCodeEvent},
+    opencode_lifecycle, session_search, usage_budget, DesktopRuntime,
+};
 
-#[derive(Deserialize)]
-struct EventEnvelope {
-    #[serde(rename = "type")]
-    event_type: String,
-    #[serde(default)]
-    properties: Value,
-}
+/// Longest wait before reconnecting to the event stream when OpenCode does not report ready
+const RECONNECT_DELAY: Duration = Duration::from_secs(2);
 
 #[derive(Clone, Debug, PartialEq)]
 pub enum ActivityPhase {
@@ -31,19 +26,96 @@ pub enum ActivityPhase {
     Cooldown,
 }
 
+impl ActivityPhase {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ActivityPhase::Idle => "idle",
+            ActivityPhase::Busy => "busy",
+            ActivityPhase::Cooldown => "cooldown",
+        }
+    }
+}
+
+/// Latest phase per session, shared with the HTTP server and commands
+pub type SessionPhases = Arc<Mutex<HashMap<String, ActivityPhase>>>;
+
+/// Settings key listing sessions the user pinned
+pub const PINNED_SESSIONS_KEY: &str = "pinnedSessions";
+
+/// Pinned session ids, loaded from settings at startup and kept in sync by the pin commands
+pub type SessionPins = Arc<parking_lot::RwLock<HashSet<String>>>;
+
+pub fn load_pins(settings: &Value) -> SessionPins {
+    let pins = settings
+        .get(PINNED_SESSIONS_KEY)
+        .and_then(Value::as_array)
+        .map(|ids| {
+            ids.iter()
+                .filter_map(Value::as_str)
+                .filter(|id| !id.is_empty())
+                .map(str::to_string)
+                .collect()
+        })
+        .unwrap_or_default();
+    Arc::new(parking_lot::RwLock::new(pins))
+}
+
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BusySession {
+    pub session_id: String,
+    pub phase: &'static str,
+    pub pinned: bool,
+}
+
+/// Sessions an OpenCode restart would interrupt
+pub async fn busy_sessions(phases: &SessionPhases, pins: &SessionPins) -> Vec<BusySession> {
+    let snapshot: Vec<(String, ActivityPhase)> = phases
+        .lock()
+        .await
+        .iter()
+        .filter(|(_, phase)| matches!(phase, ActivityPhase::Busy))
+        .map(|(session_id, phase)| (session_id.clone(), phase.clone()))
+        .collect();
+    let pins = pins.read();
+    let mut busy: Vec<BusySession> = snapshot
+        .into_iter()
+        .map(|(session_id, phase)| BusySession {
+            pinned: pins.contains(&session_id),
+            session_id,
+            phase: phase.as_str(),
+        })
+        .collect();
+    busy.sort_by(|a, b| a.session_id.cmp(&b.session_id));
+    busy
+}
+
+/// Whether `busy` sessions block a restart. `force` overrides ordinary busy
+/// sessions; a busy pinned session also needs `override_pinned`.
+pub fn restart_blocked(busy: &[BusySession], force: bool, override_pinned: bool) -> bool {
+    if busy.iter().any(|session| session.pinned) {
+        return !(force && override_pinned);
+    }
+    !busy.is_empty() && !force
+}
+
+fn activity_payload(session_id: &str, phase: &ActivityPhase, pins: &SessionPins) -> Value {
+    serde_json::json!({
+        "sessionId": session_id,
+        "phase": phase.as_str(),
+        "pinned": pins.read().contains(session_id),
+    })
+}
+
 pub fn spawn_session_activity_tracker(
     app: AppHandle,
     runtime: DesktopRuntime,
 ) -> tauri::async_runtime::JoinHandle<()> {
     tauri::async_runtime::spawn(async move {
-        let client = Client::builder()
-            .timeout(Duration::from_secs(24 * 60 * 60))
-            .tcp_keepalive(Some(Duration::from_secs(30)))
-            .build()
-            .expect("failed to build reqwest client");
-
         let mut shutdown_rx = runtime.subscribe_shutdown();
-        let phases = Arc::new(Mutex::new(HashMap::<String, ActivityPhase>::new()));
+        let mut lifecycle_rx = runtime.opencode_manager().subscribe_lifecycle();
+        let phases = runtime.session_phases();
+        let pins = runtime.session_pins();
         let cooldowns = Arc::new(Mutex::new(HashMap::<String, tauri::async_runtime::JoinHandle<()>>::new()));
 
         loop {
@@ -54,12 +126,13 @@ pub fn spawn_session_activity_tracker(
                 }
                 _ = async {
                     // Reset stale phases to idle before connecting so UI doesn't stay stuck on "working" after wake.
-                    reset_and_emit_all_phases(&app, phases.clone(), cooldowns.clone()).await;
+                    reset_and_emit_all_phases(&app, &pins, phases.clone(), cooldowns.clone()).await;
 
-                    if let Err(err) = run_once(&app, &runtime, &client, phases.clone(), cooldowns.clone()).await {
+                    opencode_lifecycle::discard_pending(&mut lifecycle_rx);
+                    if let Err(err) = run_once(&app, &runtime, phases.clone(), cooldowns.clone()).await {
                         warn!("[desktop:activity] SSE loop error: {err:?}");
                     }
-                    tokio::time::sleep(Duration::from_secs(2)).await;
+                    opencode_lifecycle::wait_for_ready(&mut lifecycle_rx, RECONNECT_DELAY).await;
                 } => {}
             }
         }
@@ -69,116 +142,52 @@ pub fn spawn_session_activity_tracker(
 async fn run_once(
     app: &AppHandle,
     runtime: &DesktopRuntime,
-    client: &Client,
     phases: Arc<Mutex<HashMap<String, ActivityPhase>>>,
     cooldowns: Arc<Mutex<HashMap<String, tauri::async_runtime::JoinHandle<()>>>>,
 ) -> Result<()> {
-    let opencode = runtime.opencode_manager();
-
-    let port = match opencode.current_port() {
-        Some(port) => port,
-        None => {
+    let mut events = match runtime.opencode_client().event_stream().await {
+        Ok(events) => events,
+        Err(OpenCodeError::NotReady) => {
             warn!("[desktop:activity] OpenCode port unavailable; will retry");
-            tokio::time::sleep(Duration::from_secs(2)).await;
+            return Ok(());
+        }
+        Err(err) => {
+            warn!("[desktop:activity] SSE connect failed: {err}");
             return Ok(());
         }
     };
 
-    let prefix = opencode.api_prefix();
-    let mut url = format!("http://127.0.0.1:{port}{}/event", prefix);
-
-    if let Some(dir) = opencode.get_working_directory().to_str().map(|s| s.to_string()) {
-        let mut parsed = reqwest::Url::parse(&url)?;
-        parsed
-            .query_pairs_mut()
-            .append_pair("directory", &dir);
-        url = parsed.to_string();
-    }
-
-    debug!("[desktop:activity] Connecting SSE for activity phases: {url}");
-
-    let response = client
-        .get(&url)
-        .header("accept", "text/event-stream")
-        .header("accept-encoding", "identity")
-        .send()
-        .await?;
-
-    debug!(
-        "[desktop:activity] SSE response status={} headers={:?}",
-        response.status(),
-        response.headers()
-    );
-
-    if !response.status().is_success() {
-        warn!(
-            "[desktop:activity] SSE connect failed with status {}",
-            response.status()
-        );
-        tokio::time::sleep(Duration::from_secs(2)).await;
-        return Ok(());
+    while let Some(event) = events.next_event().await.map_err(|err| {
+        warn!("[desktop:activity] Read error in SSE stream: {err:?}");
+        err
+    })? {
+        update_session_index(runtime, &event).await;
+        handle_event(app, runtime, event, phases.clone(), cooldowns.clone()).await;
     }
 
-    use tokio::io::AsyncBufReadExt;
-
-    let stream = response
-        .bytes_stream()
-        .map_err(|err| std::io::Error::new(std::io::ErrorKind::Other, err));
-    let mut reader = StreamReader::new(stream);
-    let mut buf = Vec::new();
-    let mut data_lines: Vec<String> = Vec::new();
-
-    loop {
-        buf.clear();
-        let bytes_read = match reader.read_until(b'\n', &mut buf).await {
-            Ok(n) => n,
-            Err(err) => {
-                warn!("[desktop:activity] Read error in SSE stream: {err:?}");
-                return Err(err.into());
-            }
-        };
-        if bytes_read == 0 {
-            break;
-        }
-
-        let line = match std::str::from_utf8(&buf) {
-            Ok(s) => s.trim_end_matches(&['\r', '\n'][..]).to_string(),
-            Err(err) => {
-                warn!("[desktop:activity] Non-UTF8 SSE chunk: {err}");
-                continue;
-            }
-        };
-
-        if line.is_empty() {
-            if data_lines.is_empty() {
-                continue;
-            }
-            let raw = data_lines.join("\n");
-            data_lines.clear();
-
-            match serde_json::from_str::<EventEnvelope>(&raw) {
-                Ok(event) => handle_event(app, event, phases.clone(), cooldowns.clone()).await,
-                Err(err) => {
-                    warn!("[desktop:activity] Failed to parse SSE data: {err}; raw={raw}");
-                }
-            }
-            continue;
-        }
+    Ok(())
+}
 
-        if let Some(rest) = line.strip_prefix("data:") {
-            data_lines.push(rest.trim_start().to_string());
+async fn update_session_index(runtime: &DesktopRuntime, event: &OpenCodeEvent) {
+    let indexing = match event.event_type.as_str() {
+        "message.updated" => {
+            let settings = runtime.settings().load().await.unwrap_or_default();
+            session_search::indexing_enabled(&settings)
         }
-    }
-
-    Ok(())
+        "session.updated" | "session.deleted" => false,
+        _ => return,
+    };
+    runtime.session_search().observe(event, indexing);
 }
 
 async fn handle_event(
     app: &AppHandle,
-    event: EventEnvelope,
+    runtime: &DesktopRuntime,
+    event: OpenCodeEvent,
     phases: Arc<Mutex<HashMap<String, ActivityPhase>>>,
     cooldowns: Arc<Mutex<HashMap<String, tauri::async_runtime::JoinHandle<()>>>>,
 ) {
+    let pins = runtime.session_pins();
     match event.event_type.as_str() {
         "session.status" => {
             let session_id = event
@@ -198,11 +207,15 @@ async fn handle_event(
                 } else {
                     ActivityPhase::Idle
                 };
-                set_phase(app, &id, phase, phases.clone(), cooldowns.clone()).await;
+                set_phase(app, &pins, &id, phase, phases.clone(), cooldowns.clone()).await;
             }
         }
         "message.updated" => {
             if let Some(info) = event.properties.get("info") {
+                if runtime.usage().record_message(app, info) {
+                    usage_budget::check_budgets(app, runtime).await;
+                }
+
                 let role = info.get("role").and_then(Value::as_str).unwrap_or_default();
                 if role != "assistant" {
                     return;
@@ -222,9 +235,10 @@ async fn handle_event(
                     // If current phase is busy, move to cooldown for 2s then idle
                     let current = { phases.lock().await.get(&id).cloned() };
                     if matches!(current, Some(ActivityPhase::Busy)) {
-                        set_phase(app, &id, ActivityPhase::Cooldown, phases.clone(), cooldowns.clone()).await;
+                        set_phase(app, &pins, &id, ActivityPhase::Cooldown, phases.clone(), cooldowns.clone()).await;
 
                         let app_clone = app.clone();
+                        let pins_clone = pins.clone();
                         let phases_clone = phases.clone();
                         let cooldowns_clone = cooldowns.clone();
                         let id_clone = id.clone();
@@ -232,7 +246,7 @@ async fn handle_event(
                             tokio::time::sleep(Duration::from_secs(2)).await;
                             let current = { phases_clone.lock().await.get(&id_clone).cloned() };
                             if matches!(current, Some(ActivityPhase::Cooldown)) {
-                                set_phase(&app_clone, &id_clone, ActivityPhase::Idle, phases_clone, cooldowns_clone).await;
+                                set_phase(&app_clone, &pins_clone, &id_clone, ActivityPhase::Idle, phases_clone, cooldowns_clone).await;
                             }
                         });
 
@@ -252,6 +266,7 @@ async fn handle_event(
 
 async fn set_phase(
     app: &AppHandle,
+    pins: &SessionPins,
     session_id: &str,
     phase: ActivityPhase,
     phases: Arc<Mutex<HashMap<String, ActivityPhase>>>,
@@ -274,20 +289,15 @@ async fn set_phase(
     }
 
     // Emit to webview so UI stays in sync
-    let payload = serde_json::json!({
-        "sessionId": session_id,
-        "phase": match phase {
-            ActivityPhase::Idle => "idle",
-            ActivityPhase::Busy => "busy",
-            ActivityPhase::Cooldown => "cooldown",
-        }
-    });
-
-    let _ = app.emit("openchamber:session-activity", payload);
+    let _ = app.emit(
+        "openchamber:session-activity",
+        activity_payload(session_id, &phase, pins),
+    );
 }
 
 async fn reset_and_emit_all_phases(
     app: &AppHandle,
+    pins: &SessionPins,
     phases: Arc<Mutex<HashMap<String, ActivityPhase>>>,
     cooldowns: Arc<Mutex<HashMap<String, tauri::async_runtime::JoinHandle<()>>>>,
 ) {
@@ -313,14 +323,94 @@ async fn reset_and_emit_all_phases(
     }
 
     for (session_id, phase) in snapshot {
-        let payload = serde_json::json!({
-            "sessionId": session_id,
-            "phase": match phase {
-                ActivityPhase::Idle => "idle",
-                ActivityPhase::Busy => "busy",
-                ActivityPhase::Cooldown => "cooldown",
-            }
-        });
-        let _ = app.emit("openchamber:session-activity", payload);
+        let _ = app.emit(
+            "openchamber:session-activity",
+            activity_payload(&session_id, &phase, pins),
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn phases(entries: &[(&str, ActivityPhase)]) -> SessionPhases {
+        Arc::new(Mutex::new(
+            entries
+                .iter()
+                .map(|(id, phase)| (id.to_string(), phase.clone()))
+                .collect(),
+        ))
+    }
+
+    fn pins(ids: &[&str]) -> SessionPins {
+        Arc::new(parking_lot::RwLock::new(
+            ids.iter().map(|id| id.to_string()).collect(),
+        ))
+    }
+
+    #[tokio::test]
+    async fn only_busy_sessions_are_reported() {
+        let phases = phases(&[
+            ("ses_b", ActivityPhase::Busy),
+            ("ses_a", ActivityPhase::Busy),
+            ("ses_c", ActivityPhase::Cooldown),
+            ("ses_d", ActivityPhase::Idle),
+        ]);
+        let busy = busy_sessions(&phases, &pins(&["ses_b", "ses_d"])).await;
+
+        let ids: Vec<_> = busy.iter().map(|s| s.session_id.as_str()).collect();
+        assert_eq!(ids, ["ses_a", "ses_b"]);
+        assert!(!busy[0].pinned);
+        assert!(busy[1].pinned);
+        assert!(busy.iter().all(|s| s.phase == "busy"));
+    }
+
+    #[tokio::test]
+    async fn busy_sessions_block_until_forced() {
+        let phases = phases(&[("ses_a", ActivityPhase::Busy)]);
+        let busy = busy_sessions(&phases, &pins(&[])).await;
+
+        assert!(restart_blocked(&busy, false, false));
+        assert!(!restart_blocked(&busy, true, false));
+        assert!(!restart_blocked(&[], false, false));
+    }
+
+    #[tokio::test]
+    async fn pinned_busy_sessions_also_need_the_pinned_override() {
+        let phases = phases(&[
+            ("ses_a", ActivityPhase::Busy),
+            ("ses_b", ActivityPhase::Busy),
+        ]);
+        let busy = busy_sessions(&phases, &pins(&["ses_b"])).await;
+
+        assert!(restart_blocked(&busy, false, false));
+        assert!(restart_blocked(&busy, true, false));
+        assert!(restart_blocked(&busy, false, true));
+        assert!(!restart_blocked(&busy, true, true));
+    }
+
+    #[test]
+    fn pins_load_from_settings() {
+        let settings = serde_json::json!({ PINNED_SESSIONS_KEY: ["ses_a", "", 3, "ses_b"] });
+        let pins = load_pins(&settings);
+        let mut ids: Vec<_> = pins.read().iter().cloned().collect();
+        ids.sort();
+        assert_eq!(ids, ["ses_a", "ses_b"]);
+        assert!(load_pins(&serde_json::json!({})).read().is_empty());
+    }
+
+    #[test]
+    fn activity_payloads_carry_the_pinned_flag() {
+        let pins = pins(&["ses_a"]);
+
+        assert_eq!(
+            activity_payload("ses_a", &ActivityPhase::Busy, &pins),
+            serde_json::json!({ "sessionId": "ses_a", "phase": "busy", "pinned": true })
+        );
+        assert_eq!(
+            activity_payload("ses_b", &ActivityPhase::Idle, &pins)["pinned"],
+            false
+        );
     }
 }
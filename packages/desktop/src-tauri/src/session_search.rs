@@ -0,0 +1,1100 @@
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use futures_util::{stream, StreamExt};
+use log::{debug, info, warn};
+use parking_lot::RwLock;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tokio::fs;
+
+use crate::{
+    opencode_client::{OpenCodeClient, OpenCodeEvent, SessionInfo},
+    paths,
+};
+
+const INDEX_FILE: &str = "session-index.json";
+const INDEX_VERSION: u32 = 1;
+/// Longer messages are indexed by their beginning only
+const MAX_INDEXED_CHARS: usize = 32 * 1024;
+const FETCH_CONCURRENCY: usize = 4;
+const FETCH_TIMEOUT: Duration = Duration::from_secs(5);
+/// Live searches stop fetching after this and return what they have
+const DEFAULT_TIME_BUDGET: Duration = Duration::from_secs(5);
+const DEFAULT_RESULT_LIMIT: usize = 20;
+const MAX_RESULT_LIMIT: usize = 100;
+const MATCHES_PER_SESSION: usize = 3;
+/// Characters of context kept on each side of the best match
+const SNIPPET_RADIUS: usize = 60;
+/// Index changes from the event stream are written out at most this often
+const FLUSH_DELAY: Duration = Duration::from_secs(30);
+const MIN_TOKEN_CHARS: usize = 2;
+
+/// `sessionSearch.indexing` in settings; off by default
+pub fn indexing_enabled(settings: &Value) -> bool {
+    settings
+        .get("sessionSearch")
+        .and_then(|search| search.get("indexing"))
+        .and_then(Value::as_bool)
+        .unwrap_or(false)
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SearchOptions {
+    pub limit: Option<usize>,
+    /// Overall budget for fetching transcripts from OpenCode
+    pub time_budget_ms: Option<u64>,
+}
+
+/// A run of snippet text; `matched` runs contain a query term
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SnippetSegment {
+    pub text: String,
+    pub matched: bool,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MessageMatch {
+    pub message_id: String,
+    pub role: String,
+    pub snippet: Vec<SnippetSegment>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SessionHit {
+    pub session_id: String,
+    pub title: Option<String>,
+    pub score: f64,
+    pub matches: Vec<MessageMatch>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SearchResponse {
+    pub hits: Vec<SessionHit>,
+    /// Sessions whose transcripts were searched
+    pub searched_sessions: usize,
+    pub total_sessions: usize,
+    /// The time budget ran out before every transcript was fetched
+    pub truncated: bool,
+    /// Answered from the on-disk index (possibly after fetching changed sessions)
+    pub from_index: bool,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RebuildResult {
+    pub sessions: usize,
+    pub messages: usize,
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+struct IndexedMessage {
+    role: String,
+    text: String,
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+struct IndexedSession {
+    #[serde(default)]
+    title: Option<String>,
+    /// `time.updated` of the session when its transcript was fetched
+    #[serde(default)]
+    updated: f64,
+    /// Keyed by message id, which OpenCode issues in chronological order
+    #[serde(default)]
+    messages: BTreeMap<String, IndexedMessage>,
+}
+
+impl IndexedSession {
+    fn tokens(&self) -> HashSet<String> {
+        self.title
+            .iter()
+            .map(String::as_str)
+            .chain(self.messages.values().map(|message| message.text.as_str()))
+            .flat_map(tokenize)
+            .collect()
+    }
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct IndexFile {
+    version: u32,
+    #[serde(default)]
+    sessions: HashMap<String, IndexedSession>,
+}
+
+/// Sessions plus an inverted index from token to session ids
+#[derive(Default)]
+struct IndexData {
+    sessions: HashMap<String, IndexedSession>,
+    postings: BTreeMap<String, HashSet<String>>,
+    loaded: bool,
+}
+
+impl IndexData {
+    fn upsert(&mut self, session_id: &str, session: IndexedSession) {
+        let old_tokens = self
+            .sessions
+            .get(session_id)
+            .map(IndexedSession::tokens)
+            .unwrap_or_default();
+        let new_tokens = session.tokens();
+        for token in old_tokens.difference(&new_tokens) {
+            self.remove_posting(token, session_id);
+        }
+        for token in new_tokens.difference(&old_tokens) {
+            self.postings
+                .entry(token.clone())
+                .or_default()
+                .insert(session_id.to_string());
+        }
+        self.sessions.insert(session_id.to_string(), session);
+    }
+
+    fn remove(&mut self, session_id: &str) -> bool {
+        let Some(session) = self.sessions.remove(session_id) else {
+            return false;
+        };
+        for token in session.tokens() {
+            self.remove_posting(&token, session_id);
+        }
+        true
+    }
+
+    fn remove_posting(&mut self, token: &str, session_id: &str) {
+        if let Some(ids) = self.postings.get_mut(token) {
+            ids.remove(session_id);
+            if ids.is_empty() {
+                self.postings.remove(token);
+            }
+        }
+    }
+
+    /// Sessions containing every term, each as a token prefix
+    fn candidates(&self, terms: &[String]) -> HashSet<String> {
+        let mut result: Option<HashSet<String>> = None;
+        for term in terms {
+            let matching: HashSet<String> = self
+                .postings
+                .range(term.clone()..)
+                .take_while(|(token, _)| token.starts_with(term.as_str()))
+                .flat_map(|(_, ids)| ids.iter().cloned())
+                .collect();
+            result = Some(match result {
+                Some(previous) => previous.intersection(&matching).cloned().collect(),
+                None => matching,
+            });
+        }
+        result.unwrap_or_default()
+    }
+}
+
+/// Lowercased alphanumeric words of at least MIN_TOKEN_CHARS characters
+fn tokenize(text: &str) -> impl Iterator<Item = String> + '_ {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|word| word.chars().count() >= MIN_TOKEN_CHARS)
+        .map(str::to_lowercase)
+}
+
+/// Distinct query terms in order of appearance
+pub fn query_terms(query: &str) -> Vec<String> {
+    let mut seen = HashSet::new();
+    tokenize(query)
+        .filter(|term| seen.insert(term.clone()))
+        .collect()
+}
+
+/// Lowercase char by char so positions line up with the original text
+fn fold(text: &str) -> (Vec<char>, Vec<char>) {
+    let original: Vec<char> = text.chars().collect();
+    let folded = original
+        .iter()
+        .map(|c| c.to_lowercase().next().unwrap_or(*c))
+        .collect();
+    (original, folded)
+}
+
+fn occurrences(haystack: &[char], needle: &[char]) -> Vec<usize> {
+    if needle.is_empty() || needle.len() > haystack.len() {
+        return Vec::new();
+    }
+    (0..=haystack.len() - needle.len())
+        .filter(|&start| haystack[start..start + needle.len()] == *needle)
+        .collect()
+}
+
+/// Relevance of `text` for `terms`: every term must occur (as a substring,
+/// so prefixes match); repeated terms add with diminishing weight and the
+/// query appearing as a phrase earns a bonus. None when a term is missing.
+pub fn score_text(text: &str, terms: &[String]) -> Option<f64> {
+    if terms.is_empty() {
+        return None;
+    }
+    let (_, folded) = fold(text);
+    let mut score = 0.0;
+    for term in terms {
+        let term: Vec<char> = term.chars().collect();
+        let count = occurrences(&folded, &term).len();
+        if count == 0 {
+            return None;
+        }
+        score += 1.0 + (count as f64).ln();
+    }
+    if terms.len() > 1 {
+        let phrase: Vec<char> = terms.join(" ").chars().collect();
+        if !occurrences(&folded, &phrase).is_empty() {
+            score += terms.len() as f64;
+        }
+    }
+    Some(score)
+}
+
+/// Window of `text` around the spot where the most distinct terms occur
+/// close together, split into matched and unmatched runs. Whitespace is
+/// collapsed and cut edges are marked with an ellipsis.
+pub fn extract_snippet(text: &str, terms: &[String], radius: usize) -> Vec<SnippetSegment> {
+    let (original, folded) = fold(text);
+    let mut spans: Vec<(usize, usize)> = terms
+        .iter()
+        .flat_map(|term| {
+            let term: Vec<char> = term.chars().collect();
+            let len = term.len();
+            occurrences(&folded, &term)
+                .into_iter()
+                .map(move |start| (start, start + len))
+        })
+        .collect();
+    spans.sort();
+
+    let anchor = spans
+        .iter()
+        .max_by_key(|(start, _)| {
+            let nearby: HashSet<&str> = terms
+                .iter()
+                .filter(|term| {
+                    let term: Vec<char> = term.chars().collect();
+                    let from = start.saturating_sub(radius);
+                    let to = (start + radius + term.len()).min(folded.len());
+                    !occurrences(&folded[from..to], &term).is_empty()
+                })
+                .map(String::as_str)
+                .collect();
+            // Prefer more distinct terms, then the earliest position
+            (nearby.len(), std::cmp::Reverse(*start))
+        })
+        .map(|(start, _)| *start)
+        .unwrap_or(0);
+
+    let mut from = anchor.saturating_sub(radius);
+    let mut to = (anchor + radius).min(original.len());
+    // Snap to word boundaries when one is close
+    if let Some(offset) = original[from..anchor]
+        .iter()
+        .take(12)
+        .position(|c| c.is_whitespace())
+    {
+        if from > 0 {
+            from += offset + 1;
+        }
+    }
+    if let Some(offset) = original[anchor..to]
+        .iter()
+        .rev()
+        .take(12)
+        .position(|c| c.is_whitespace())
+    {
+        if to < original.len() {
+            to -= offset + 1;
+        }
+    }
+
+    let mut segments: Vec<SnippetSegment> = Vec::new();
+    let mut push = |chars: &[char], matched: bool| {
+        let mut text = String::new();
+        for c in chars {
+            if c.is_whitespace() {
+                if !text.ends_with(' ') {
+                    text.push(' ');
+                }
+            } else {
+                text.push(*c);
+            }
+        }
+        if text.is_empty() {
+            return;
+        }
+        match segments.last_mut() {
+            Some(last) if last.matched == matched => last.text.push_str(&text),
+            _ => segments.push(SnippetSegment { text, matched }),
+        }
+    };
+
+    if from > 0 {
+        push(&['…'], false);
+    }
+    let mut cursor = from;
+    for (start, end) in spans {
+        let start = start.max(cursor);
+        let end = end.min(to);
+        if start >= end {
+            continue;
+        }
+        push(&original[cursor..start], false);
+        push(&original[start..end], true);
+        cursor = end;
+    }
+    push(&original[cursor..to], false);
+    if to < original.len() {
+        push(&['…'], false);
+    }
+
+    segments
+}
+
+/// Score a session: the best message, plus a little for every other matching
+/// message and a bonus when the title matches
+fn rank_session(
+    session_id: &str,
+    session: &IndexedSession,
+    terms: &[String],
+) -> Option<SessionHit> {
+    let title_score = session
+        .title
+        .as_deref()
+        .and_then(|title| score_text(title, terms));
+    let mut scored: Vec<(&String, &IndexedMessage, f64)> = session
+        .messages
+        .iter()
+        .filter_map(|(id, message)| score_text(&message.text, terms).map(|s| (id, message, s)))
+        .collect();
+    if title_score.is_none() && scored.is_empty() {
+        return None;
+    }
+    scored.sort_by(|a, b| b.2.total_cmp(&a.2));
+
+    let best = scored.first().map(|(_, _, score)| *score).unwrap_or(0.0);
+    let rest: f64 = scored
+        .iter()
+        .skip(1)
+        .map(|(_, _, score)| score * 0.25)
+        .sum();
+    let score = best + rest.min(best) + title_score.map(|s| s * 2.0).unwrap_or(0.0);
+
+    let matches = scored
+        .into_iter()
+        .take(MATCHES_PER_SESSION)
+        .map(|(id, message, _)| MessageMatch {
+            message_id: id.clone(),
+            role: message.role.clone(),
+            snippet: extract_snippet(&message.text, terms, SNIPPET_RADIUS),
+        })
+        .collect();
+
+    Some(SessionHit {
+        session_id: session_id.to_string(),
+        title: session.title.clone(),
+        score,
+        matches,
+    })
+}
+
+fn rank<'a>(
+    sessions: impl Iterator<Item = (&'a String, &'a IndexedSession)>,
+    terms: &[String],
+    limit: usize,
+) -> Vec<SessionHit> {
+    let mut hits: Vec<(SessionHit, f64)> = sessions
+        .filter_map(|(id, session)| {
+            rank_session(id, session, terms).map(|hit| (hit, session.updated))
+        })
+        .collect();
+    // Ties go to the more recently updated session
+    hits.sort_by(|a, b| b.0.score.total_cmp(&a.0.score).then(b.1.total_cmp(&a.1)));
+    hits.into_iter().take(limit).map(|(hit, _)| hit).collect()
+}
+
+fn truncate_chars(mut text: String, max: usize) -> String {
+    if let Some((index, _)) = text.char_indices().nth(max) {
+        text.truncate(index);
+    }
+    text
+}
+
+/// Content search over session transcripts. Without indexing every search
+/// fetches transcripts live; with it, transcripts are kept in an on-disk index
+/// that is refreshed from the event stream and by fetching only sessions whose
+/// `time.updated` moved since they were indexed.
+pub struct SessionSearch {
+    client: OpenCodeClient,
+    index: RwLock<IndexData>,
+    path: Option<PathBuf>,
+    flush_scheduled: AtomicBool,
+}
+
+impl SessionSearch {
+    pub fn new(client: OpenCodeClient) -> Self {
+        Self {
+            client: client.with_timeout(FETCH_TIMEOUT),
+            index: RwLock::new(IndexData::default()),
+            path: paths::cache_dir().map(|dir| dir.join(INDEX_FILE)),
+            flush_scheduled: AtomicBool::new(false),
+        }
+    }
+
+    async fn ensure_loaded(&self) {
+        if self.index.read().loaded {
+            return;
+        }
+        let file = match &self.path {
+            Some(path) => match fs::read(path).await {
+                Ok(bytes) => serde_json::from_slice::<IndexFile>(&bytes)
+                    .ok()
+                    .filter(|file| file.version == INDEX_VERSION),
+                Err(_) => None,
+            },
+            None => None,
+        };
+
+        let mut index = self.index.write();
+        if index.loaded {
+            return;
+        }
+        index.loaded = true;
+        if let Some(file) = file {
+            for (id, session) in file.sessions {
+                index.upsert(&id, session);
+            }
+            debug!(
+                "[desktop:search] Loaded session index with {} sessions",
+                index.sessions.len()
+            );
+        }
+    }
+
+    async fn persist(&self) {
+        let Some(path) = &self.path else {
+            return;
+        };
+        let bytes = {
+            let index = self.index.read();
+            let file = IndexFile {
+                version: INDEX_VERSION,
+                sessions: index.sessions.clone(),
+            };
+            match serde_json::to_vec(&file) {
+                Ok(bytes) => bytes,
+                Err(err) => {
+                    warn!(
+                        "[desktop:search] Failed to serialize session index: {}",
+                        err
+                    );
+                    return;
+                }
+            }
+        };
+        if let Some(parent) = path.parent() {
+            let _ = fs::create_dir_all(parent).await;
+        }
+        let temp = path.with_extension("json.tmp");
+        let result = match fs::write(&temp, bytes).await {
+            Ok(()) => fs::rename(&temp, path).await,
+            Err(err) => Err(err),
+        };
+        if let Err(err) = result {
+            warn!("[desktop:search] Failed to write session index: {}", err);
+        }
+    }
+
+    /// Write the index once FLUSH_DELAY has passed, coalescing changes
+    fn schedule_flush(self: &Arc<Self>) {
+        if self.flush_scheduled.swap(true, Ordering::SeqCst) {
+            return;
+        }
+        let search = self.clone();
+        tauri::async_runtime::spawn(async move {
+            tokio::time::sleep(FLUSH_DELAY).await;
+            search.flush_scheduled.store(false, Ordering::SeqCst);
+            search.persist().await;
+        });
+    }
+
+    async fn fetch_session(&self, info: &SessionInfo) -> Option<IndexedSession> {
+        let messages = match self.client.list_messages(&info.id).await {
+            Ok(messages) => messages,
+            Err(err) => {
+                debug!(
+                    "[desktop:search] Could not fetch session {}: {}",
+                    info.id, err
+                );
+                return None;
+            }
+        };
+        let messages = messages
+            .into_iter()
+            .filter_map(|message| {
+                let text = message.text();
+                (!text.is_empty()).then(|| {
+                    let role = message.info.role.clone().unwrap_or_default();
+                    (
+                        message.info.id,
+                        IndexedMessage {
+                            role,
+                            text: truncate_chars(text, MAX_INDEXED_CHARS),
+                        },
+                    )
+                })
+            })
+            .collect();
+        Some(IndexedSession {
+            title: info.display_title().map(str::to_string),
+            updated: info.updated_at(),
+            messages,
+        })
+    }
+
+    /// Fetch `sessions` with bounded concurrency until `budget` runs out.
+    /// Returns the fetched transcripts and whether the budget was exhausted.
+    async fn fetch_all(
+        &self,
+        sessions: Vec<SessionInfo>,
+        budget: Option<Duration>,
+    ) -> (Vec<(String, IndexedSession)>, bool) {
+        let deadline = budget.map(|budget| tokio::time::Instant::now() + budget);
+        let mut pending = stream::iter(sessions)
+            .map(|info| async move {
+                let fetched = self.fetch_session(&info).await;
+                (info.id, fetched)
+            })
+            .buffer_unordered(FETCH_CONCURRENCY);
+
+        let mut fetched = Vec::new();
+        loop {
+            let next = match deadline {
+                Some(deadline) => match tokio::time::timeout_at(deadline, pending.next()).await {
+                    Ok(next) => next,
+                    Err(_) => return (fetched, true),
+                },
+                None => pending.next().await,
+            };
+            match next {
+                Some((id, Some(session))) => fetched.push((id, session)),
+                Some((_, None)) => {}
+                None => return (fetched, false),
+            }
+        }
+    }
+
+    pub async fn search(
+        self: &Arc<Self>,
+        query: &str,
+        options: SearchOptions,
+        indexing: bool,
+    ) -> Result<SearchResponse, String> {
+        let terms = query_terms(query);
+        if terms.is_empty() {
+            return Err("Query is empty".to_string());
+        }
+        let limit = options
+            .limit
+            .unwrap_or(DEFAULT_RESULT_LIMIT)
+            .clamp(1, MAX_RESULT_LIMIT);
+        let budget = options
+            .time_budget_ms
+            .map(Duration::from_millis)
+            .unwrap_or(DEFAULT_TIME_BUDGET);
+
+        if indexing {
+            self.ensure_loaded().await;
+        }
+
+        let sessions = match self.client.list_sessions().await {
+            Ok(sessions) => sessions,
+            Err(err) if indexing => {
+                // Serve what the index has while OpenCode is unavailable
+                warn!(
+                    "[desktop:search] Listing sessions failed, searching the index only: {}",
+                    err
+                );
+                let index = self.index.read();
+                return Ok(SearchResponse {
+                    hits: rank(index.sessions.iter(), &terms, limit),
+                    searched_sessions: index.sessions.len(),
+                    total_sessions: index.sessions.len(),
+                    truncated: true,
+                    from_index: true,
+                });
+            }
+            Err(err) => return Err(format!("Failed to list sessions: {}", err)),
+        };
+        let total_sessions = sessions.len();
+
+        if !indexing {
+            let mut sessions = sessions;
+            sessions.sort_by(|a, b| b.updated_at().total_cmp(&a.updated_at()));
+            let (fetched, truncated) = self.fetch_all(sessions, Some(budget)).await;
+            let hits = rank(
+                fetched.iter().map(|(id, session)| (id, session)),
+                &terms,
+                limit,
+            );
+            return Ok(SearchResponse {
+                hits,
+                searched_sessions: fetched.len(),
+                total_sessions,
+                truncated,
+                from_index: false,
+            });
+        }
+
+        let (truncated, scope) = self.sync(sessions, Some(budget)).await;
+        let index = self.index.read();
+        let candidates = index.candidates(&terms);
+        let hits = rank(
+            index
+                .sessions
+                .iter()
+                .filter(|(id, _)| scope.contains(*id) && candidates.contains(*id)),
+            &terms,
+            limit,
+        );
+        let searched_sessions = scope
+            .iter()
+            .filter(|id| index.sessions.contains_key(*id))
+            .count();
+        Ok(SearchResponse {
+            hits,
+            searched_sessions,
+            total_sessions,
+            truncated,
+            from_index: true,
+        })
+    }
+
+    /// Bring the index up to date with `sessions` (the current project's list),
+    /// fetching only new or changed transcripts. Returns whether the budget ran
+    /// out and the ids in scope.
+    async fn sync(
+        self: &Arc<Self>,
+        mut sessions: Vec<SessionInfo>,
+        budget: Option<Duration>,
+    ) -> (bool, HashSet<String>) {
+        let scope: HashSet<String> = sessions.iter().map(|info| info.id.clone()).collect();
+        sessions.retain(|info| {
+            let index = self.index.read();
+            match index.sessions.get(&info.id) {
+                Some(indexed) => indexed.updated < info.updated_at() || info.updated_at() == 0.0,
+                None => true,
+            }
+        });
+        sessions.sort_by(|a, b| b.updated_at().total_cmp(&a.updated_at()));
+
+        let stale = sessions.len();
+        let (fetched, truncated) = self.fetch_all(sessions, budget).await;
+        if !fetched.is_empty() {
+            let mut index = self.index.write();
+            for (id, session) in fetched {
+                index.upsert(&id, session);
+            }
+        }
+        if stale > 0 {
+            self.schedule_flush();
+        }
+        (truncated, scope)
+    }
+
+    /// Drop the index and re-fetch every session of the current project
+    pub async fn rebuild(self: &Arc<Self>) -> Result<RebuildResult, String> {
+        self.ensure_loaded().await;
+        let sessions = self
+            .client
+            .list_sessions()
+            .await
+            .map_err(|err| format!("Failed to list sessions: {}", err))?;
+        {
+            let mut index = self.index.write();
+            index.sessions.clear();
+            index.postings.clear();
+        }
+        self.sync(sessions, None).await;
+        self.persist().await;
+
+        let index = self.index.read();
+        let result = RebuildResult {
+            sessions: index.sessions.len(),
+            messages: index.sessions.values().map(|s| s.messages.len()).sum(),
+        };
+        info!(
+            "[desktop:search] Rebuilt session index: {} sessions, {} messages",
+            result.sessions, result.messages
+        );
+        Ok(result)
+    }
+
+    /// Forget a deleted session
+    pub fn remove_session(self: &Arc<Self>, session_id: &str) {
+        if self.index.write().remove(session_id) {
+            self.schedule_flush();
+        }
+    }
+
+    /// Keep the index current from OpenCode's event stream: re-fetch a
+    /// session when a turn finishes (only with indexing on), follow renames
+    /// and deletions
+    pub fn observe(self: &Arc<Self>, event: &OpenCodeEvent, indexing: bool) {
+        let info = event.properties.get("info");
+        match event.event_type.as_str() {
+            "message.updated" if indexing => {
+                let Some(info) = info else {
+                    return;
+                };
+                // Steps ending in tool calls are followed by more of the same turn
+                let finished = info.get("role").and_then(Value::as_str) == Some("assistant")
+                    && info
+                        .get("finish")
+                        .and_then(Value::as_str)
+                        .is_some_and(|finish| finish != "tool-calls");
+                let session_id = info.get("sessionID").and_then(Value::as_str);
+                if let (true, Some(session_id)) = (finished, session_id) {
+                    let search = self.clone();
+                    let session_id = session_id.to_string();
+                    tauri::async_runtime::spawn(async move {
+                        search.refresh_session(&session_id).await;
+                    });
+                }
+            }
+            "session.updated" => {
+                let Some(info) =
+                    info.and_then(|info| serde_json::from_value::<SessionInfo>(info.clone()).ok())
+                else {
+                    return;
+                };
+                let mut index = self.index.write();
+                let Some(indexed) = index.sessions.get(&info.id) else {
+                    return;
+                };
+                let title = info.display_title().map(str::to_string);
+                if indexed.title != title {
+                    let mut updated = indexed.clone();
+                    updated.title = title;
+                    index.upsert(&info.id, updated);
+                    drop(index);
+                    self.schedule_flush();
+                }
+            }
+            "session.deleted" => {
+                if let Some(id) = info.and_then(|info| info.get("id")).and_then(Value::as_str) {
+                    self.remove_session(id);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    async fn refresh_session(self: &Arc<Self>, session_id: &str) {
+        self.ensure_loaded().await;
+        let info = match self.client.get_session(session_id).await {
+            Ok(info) => info,
+            Err(err) => {
+                debug!(
+                    "[desktop:search] Could not refresh session {}: {}",
+                    session_id, err
+                );
+                return;
+            }
+        };
+        if let Some(session) = self.fetch_session(&info).await {
+            self.index.write().upsert(session_id, session);
+            self.schedule_flush();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A short session where the migration is the main topic
+    const MIGRATION_TRANSCRIPT: &[(&str, &str, &str)] = &[
+        (
+            "msg_001",
+            "user",
+            "How do we migrate the users table to the new schema?",
+        ),
+        (
+            "msg_002",
+            "assistant",
+            "The migration runs in three steps. First the users table is copied, \
+             then the migration backfills the new columns, and finally the old \
+             users table is dropped once the migration is verified.",
+        ),
+        ("msg_003", "user", "Thanks, that works."),
+    ];
+
+    /// A session that mentions the migration once, in passing
+    const DEPLOY_TRANSCRIPT: &[(&str, &str, &str)] = &[
+        ("msg_101", "user", "Why does the deploy fail on staging?"),
+        (
+            "msg_102",
+            "assistant",
+            "The deploy script waits for the migration of the users table and \
+             times out after ten minutes.",
+        ),
+    ];
+
+    const UNRELATED_TRANSCRIPT: &[(&str, &str, &str)] = &[
+        ("msg_201", "user", "Rename the button label."),
+        ("msg_202", "assistant", "Done, the label now reads Save."),
+    ];
+
+    fn session(title: &str, updated: f64, transcript: &[(&str, &str, &str)]) -> IndexedSession {
+        IndexedSession {
+            title: Some(title.to_string()),
+            updated,
+            messages: transcript
+                .iter()
+                .map(|(id, role, text)| {
+                    (
+                        id.to_string(),
+                        IndexedMessage {
+                            role: role.to_string(),
+                            text: text.to_string(),
+                        },
+                    )
+                })
+                .collect(),
+        }
+    }
+
+    fn fixture_sessions() -> HashMap<String, IndexedSession> {
+        HashMap::from([
+            (
+                "ses_migration".to_string(),
+                session("Schema work", 100.0, MIGRATION_TRANSCRIPT),
+            ),
+            (
+                "ses_deploy".to_string(),
+                session("Staging deploy", 200.0, DEPLOY_TRANSCRIPT),
+            ),
+            (
+                "ses_unrelated".to_string(),
+                session("Button copy", 300.0, UNRELATED_TRANSCRIPT),
+            ),
+        ])
+    }
+
+    fn segment(text: &str, matched: bool) -> SnippetSegment {
+        SnippetSegment {
+            text: text.to_string(),
+            matched,
+        }
+    }
+
+    fn ids(hits: &[SessionHit]) -> Vec<&str> {
+        hits.iter().map(|hit| hit.session_id.as_str()).collect()
+    }
+
+    #[test]
+    fn queries_are_split_into_distinct_lowercase_terms() {
+        assert_eq!(
+            query_terms("Migration of the  MIGRATION users-table, x"),
+            vec!["migration", "of", "the", "users", "table"]
+        );
+        assert!(query_terms(" a ! ").is_empty());
+    }
+
+    #[test]
+    fn indexing_is_opt_in() {
+        assert!(!indexing_enabled(&Value::Null));
+        assert!(!indexing_enabled(&serde_json::json!({"sessionSearch": {}})));
+        assert!(indexing_enabled(
+            &serde_json::json!({"sessionSearch": {"indexing": true}})
+        ));
+    }
+
+    #[test]
+    fn every_term_must_occur_for_a_score() {
+        let text = "Run the Migration for the users table";
+        assert!(score_text(text, &query_terms("migration users")).is_some());
+        // Terms match as prefixes of longer words
+        assert!(score_text(text, &query_terms("migr tab")).is_some());
+        assert_eq!(score_text(text, &query_terms("migration orders")), None);
+        assert_eq!(score_text(text, &[]), None);
+    }
+
+    #[test]
+    fn repeats_and_phrases_raise_the_score() {
+        let terms = query_terms("users table");
+        let once = score_text("table of users", &terms).unwrap();
+        let repeated = score_text("table of users, more users", &terms).unwrap();
+        let phrase = score_text("the users table", &terms).unwrap();
+
+        assert_eq!(once, 2.0);
+        assert!(repeated > once && repeated < once + 1.0);
+        assert_eq!(phrase, once + 2.0);
+    }
+
+    #[test]
+    fn short_texts_are_highlighted_whole() {
+        assert_eq!(
+            extract_snippet(
+                "Run the  Migration\nnow",
+                &query_terms("migration"),
+                SNIPPET_RADIUS
+            ),
+            vec![
+                segment("Run the ", false),
+                segment("Migration", true),
+                segment(" now", false),
+            ]
+        );
+    }
+
+    #[test]
+    fn snippets_center_on_the_densest_cluster_of_terms() {
+        let text = "Users first.\n\n   Then a long stretch of unrelated words before the \
+                    migration and the users table finally meet again here";
+        assert_eq!(
+            extract_snippet(text, &query_terms("users migration"), 30),
+            vec![
+                segment("…unrelated words before the ", false),
+                segment("migration", true),
+                segment(" and the ", false),
+                segment("users", true),
+                segment(" table…", false),
+            ]
+        );
+    }
+
+    #[test]
+    fn snippet_windows_snap_to_word_boundaries() {
+        let text = format!(
+            "{}The migration of the users table runs in batches.{}",
+            "filler text ".repeat(20),
+            " more filler".repeat(20)
+        );
+        let snippet = extract_snippet(&text, &query_terms("users migration"), 30);
+        let joined: String = snippet.iter().map(|s| s.text.as_str()).collect();
+
+        assert_eq!(
+            joined,
+            "…filler text filler text The migration of the users table…"
+        );
+    }
+
+    #[test]
+    fn sessions_are_ranked_by_their_transcripts_and_titles() {
+        let sessions = fixture_sessions();
+        let hits = rank(sessions.iter(), &query_terms("migration users"), 10);
+
+        assert_eq!(ids(&hits), vec!["ses_migration", "ses_deploy"]);
+        assert!(hits[0].score > hits[1].score);
+
+        // Matches come best first and carry their message id and role
+        let matches = &hits[0].matches;
+        assert_eq!(matches[0].message_id, "msg_002");
+        assert_eq!(matches[0].role, "assistant");
+        assert_eq!(matches.len(), 1);
+        assert_eq!(hits[1].matches[0].message_id, "msg_102");
+
+        // Titles match even when no single message holds every term
+        let title_only = rank(sessions.iter(), &query_terms("schema work"), 10);
+        assert_eq!(ids(&title_only), vec!["ses_migration"]);
+        assert!(title_only[0].matches.is_empty());
+    }
+
+    #[test]
+    fn ties_go_to_the_most_recent_session_and_results_are_capped() {
+        let sessions = HashMap::from([
+            (
+                "ses_old".to_string(),
+                session("Old", 1.0, DEPLOY_TRANSCRIPT),
+            ),
+            (
+                "ses_new".to_string(),
+                session("New", 2.0, DEPLOY_TRANSCRIPT),
+            ),
+        ]);
+        let terms = query_terms("deploy");
+
+        assert_eq!(
+            ids(&rank(sessions.iter(), &terms, 10)),
+            vec!["ses_new", "ses_old"]
+        );
+        assert_eq!(ids(&rank(sessions.iter(), &terms, 1)), vec!["ses_new"]);
+    }
+
+    #[test]
+    fn at_most_three_messages_are_shown_per_session() {
+        let transcript: Vec<(String, String)> = (0..5)
+            .map(|i| (format!("msg_{i}"), "migration ".repeat(i + 1)))
+            .collect();
+        let borrowed: Vec<(&str, &str, &str)> = transcript
+            .iter()
+            .map(|(id, text)| (id.as_str(), "assistant", text.as_str()))
+            .collect();
+        let sessions = HashMap::from([("ses".to_string(), session("", 0.0, &borrowed))]);
+
+        let hits = rank(sessions.iter(), &query_terms("migration"), 10);
+        let shown: Vec<&str> = hits[0]
+            .matches
+            .iter()
+            .map(|m| m.message_id.as_str())
+            .collect();
+        assert_eq!(shown, vec!["msg_4", "msg_3", "msg_2"]);
+    }
+
+    #[test]
+    fn the_inverted_index_follows_upserts_and_removals() {
+        let mut index = IndexData::default();
+        for (id, session) in fixture_sessions() {
+            index.upsert(&id, session);
+        }
+        let candidates = |index: &IndexData, query: &str| {
+            let mut ids: Vec<String> = index.candidates(&query_terms(query)).into_iter().collect();
+            ids.sort();
+            ids
+        };
+
+        assert_eq!(
+            candidates(&index, "migr users"),
+            vec!["ses_deploy", "ses_migration"]
+        );
+        assert_eq!(candidates(&index, "label"), vec!["ses_unrelated"]);
+        assert!(candidates(&index, "migration label").is_empty());
+
+        // Replacing a transcript drops the words it no longer contains
+        index.upsert(
+            "ses_deploy",
+            session("Staging deploy", 201.0, UNRELATED_TRANSCRIPT),
+        );
+        assert_eq!(candidates(&index, "migration"), vec!["ses_migration"]);
+        assert_eq!(
+            candidates(&index, "label"),
+            vec!["ses_deploy", "ses_unrelated"]
+        );
+
+        assert!(index.remove("ses_unrelated"));
+        assert!(!index.remove("ses_unrelated"));
+        assert_eq!(candidates(&index, "label"), vec!["ses_deploy"]);
+        assert!(index.remove("ses_deploy"));
+        assert!(!index.postings.contains_key("label"));
+    }
+
+    #[test]
+    fn indexed_text_is_cut_on_a_character_boundary() {
+        assert_eq!(truncate_chars("héllo wörld".to_string(), 7), "héllo w");
+        assert_eq!(truncate_chars("short".to_string(), 10), "short");
+    }
+}
@@ -0,0 +1,327 @@
+use std::path::{Path, PathBuf};
+
+use log::{info, warn};
+use once_cell::sync::Lazy;
+use portable_pty::CommandBuilder;
+
+use crate::paths;
+
+const INTEGRATION_DIR: &str = "shell-integration";
+const SCRIPT_ENV: &str = "OPENCHAMBER_SHELL_INTEGRATION_SCRIPT";
+
+/// Longest unterminated OSC sequence carried over to the next read
+const MAX_PENDING_SEQUENCE: usize = 4096;
+
+const ZSHENV_SHIM: &str = include_str!("../shell-integration/zshenv.zsh");
+const ZPROFILE_SHIM: &str = include_str!("../shell-integration/zprofile.zsh");
+const ZSHRC_SHIM: &str = include_str!("../shell-integration/zshrc.zsh");
+const ZSH_SCRIPT: &str = include_str!("../shell-integration/openchamber.zsh");
+const BASH_RCFILE: &str = include_str!("../shell-integration/bash-rc.bash");
+const BASH_SCRIPT: &str = include_str!("../shell-integration/openchamber.bash");
+const FISH_SCRIPT: &str = include_str!("../shell-integration/openchamber.fish");
+
+/// Scripts are written once per launch so upgrades replace stale copies
+static INSTALLED: Lazy<Option<PathBuf>> = Lazy::new(install_scripts);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum ShellKind {
+    Zsh,
+    Bash,
+    Fish,
+}
+
+fn detect_shell(shell_path: &str) -> Option<ShellKind> {
+    let name = Path::new(shell_path)
+        .file_name()
+        .and_then(|name| name.to_str())
+        .unwrap_or(shell_path)
+        .to_lowercase();
+    match name.trim_end_matches(".exe") {
+        "zsh" => Some(ShellKind::Zsh),
+        "bash" => Some(ShellKind::Bash),
+        "fish" => Some(ShellKind::Fish),
+        _ => None,
+    }
+}
+
+fn install_scripts() -> Option<PathBuf> {
+    let root = paths::state_dir()?.join(INTEGRATION_DIR);
+    let files = [
+        ("zsh/.zshenv", ZSHENV_SHIM),
+        ("zsh/.zprofile", ZPROFILE_SHIM),
+        ("zsh/.zshrc", ZSHRC_SHIM),
+        ("openchamber.zsh", ZSH_SCRIPT),
+        ("bash-rc.bash", BASH_RCFILE),
+        ("openchamber.bash", BASH_SCRIPT),
+        ("openchamber.fish", FISH_SCRIPT),
+    ];
+
+    for (relative, contents) in files {
+        let path = root.join(relative);
+        let result = path
+            .parent()
+            .map(std::fs::create_dir_all)
+            .unwrap_or(Ok(()))
+            .and_then(|_| std::fs::write(&path, contents));
+        if let Err(err) = result {
+            warn!(
+                "[desktop:terminal] Failed to install shell integration {}: {}",
+                path.display(),
+                err
+            );
+            return None;
+        }
+    }
+
+    info!(
+        "[desktop:terminal] Installed shell integration to {}",
+        root.display()
+    );
+    Some(root)
+}
+
+/// Arrange for the shell to load OpenChamber's integration after the user's own
+/// startup files. Returns false, leaving `cmd` untouched, for shells we don't
+/// know or when the scripts could not be installed.
+pub fn configure(cmd: &mut CommandBuilder, shell_path: &str) -> bool {
+    let Some(kind) = detect_shell(shell_path) else {
+        return false;
+    };
+    let Some(root) = INSTALLED.as_ref() else {
+        return false;
+    };
+
+    match kind {
+        ShellKind::Zsh => {
+            let script = root.join("openchamber.zsh");
+            if let Ok(user_zdotdir) = std::env::var("ZDOTDIR") {
+                cmd.env("OPENCHAMBER_USER_ZDOTDIR", user_zdotdir);
+            }
+            cmd.env("ZDOTDIR", root.join("zsh"));
+            cmd.env(SCRIPT_ENV, script);
+            cmd.arg("-l");
+        }
+        ShellKind::Bash => {
+            // A login bash ignores --rcfile, so the rcfile sources the profile files itself
+            cmd.env(SCRIPT_ENV, root.join("openchamber.bash"));
+            cmd.arg("--rcfile");
+            cmd.arg(root.join("bash-rc.bash"));
+            cmd.arg("-i");
+        }
+        ShellKind::Fish => {
+            // --init-command runs after config.fish has been read
+            cmd.env(SCRIPT_ENV, root.join("openchamber.fish"));
+            cmd.arg("-l");
+            cmd.arg("--init-command");
+            cmd.arg(format!("source ${}", SCRIPT_ENV));
+        }
+    }
+    true
+}
+
+/// A shell-integration mark decoded from an OSC 133 (FinalTerm) or 633 (VS Code) sequence
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ShellMark {
+    PromptStart,
+    PromptEnd,
+    CommandLine(String),
+    CommandStart,
+    CommandFinished(Option<i32>),
+}
+
+/// Extracts shell-integration marks from terminal output. Sequences split across
+/// reads are held back until their terminator (BEL or ST) arrives; the raw output
+/// itself is forwarded untouched by the caller.
+#[derive(Default)]
+pub struct OscParser {
+    pending: String,
+}
+
+impl OscParser {
+    pub fn feed(&mut self, data: &str) -> Vec<ShellMark> {
+        let mut text = std::mem::take(&mut self.pending);
+        text.push_str(data);
+
+        let mut marks = Vec::new();
+        let mut offset = 0;
+        loop {
+            let Some(start) = text[offset..].find("\x1b]").map(|index| offset + index) else {
+                if text.ends_with('\x1b') {
+                    self.pending.push('\x1b');
+                }
+                break;
+            };
+            let body_start = start + 2;
+            let Some((body_len, terminator_len)) = find_terminator(&text[body_start..]) else {
+                if text.len() - start <= MAX_PENDING_SEQUENCE {
+                    self.pending = text[start..].to_string();
+                }
+                break;
+            };
+            if let Some(mark) = parse_mark(&text[body_start..body_start + body_len]) {
+                marks.push(mark);
+            }
+            offset = body_start + body_len + terminator_len;
+        }
+        marks
+    }
+}
+
+/// Length of the OSC body and of its terminator, or None while incomplete
+fn find_terminator(text: &str) -> Option<(usize, usize)> {
+    let bytes = text.as_bytes();
+    for (index, byte) in bytes.iter().enumerate() {
+        match byte {
+            0x07 => return Some((index, 1)),
+            0x1b => {
+                return match bytes.get(index + 1) {
+                    Some(b'\\') => Some((index, 2)),
+                    Some(_) => Some((index, 0)),
+                    None => None,
+                };
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+fn parse_mark(body: &str) -> Option<ShellMark> {
+    let (code, rest) = body.split_once(';')?;
+    if code != "133" && code != "633" {
+        return None;
+    }
+    let (kind, args) = rest.split_once(';').unwrap_or((rest, ""));
+    match kind {
+        "A" => Some(ShellMark::PromptStart),
+        "B" => Some(ShellMark::PromptEnd),
+        "C" => Some(ShellMark::CommandStart),
+        "D" => Some(ShellMark::CommandFinished(
+            args.split(';').next().and_then(|code| code.trim().parse().ok()),
+        )),
+        "E" => {
+            // VS Code appends `;<nonce>`; literal semicolons in the command are escaped
+            let command = args.split(';').next().unwrap_or_default();
+            Some(ShellMark::CommandLine(unescape(command)))
+        }
+        _ => None,
+    }
+}
+
+/// Reverse the `\\` and `\xHH` escaping the integration scripts apply
+fn unescape(value: &str) -> String {
+    let mut result = String::with_capacity(value.len());
+    let mut chars = value.chars().peekable();
+    while let Some(ch) = chars.next() {
+        if ch != '\\' {
+            result.push(ch);
+            continue;
+        }
+        match chars.peek() {
+            Some('\\') => {
+                chars.next();
+                result.push('\\');
+            }
+            Some('x') => {
+                chars.next();
+                let hex: String = chars.by_ref().take(2).collect();
+                match u8::from_str_radix(&hex, 16) {
+                    Ok(byte) => result.push(byte as char),
+                    Err(_) => {
+                        result.push_str("\\x");
+                        result.push_str(&hex);
+                    }
+                }
+            }
+            _ => result.push('\\'),
+        }
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn shells_are_detected_by_name() {
+        assert_eq!(detect_shell("/bin/zsh"), Some(ShellKind::Zsh));
+        assert_eq!(detect_shell("/usr/local/bin/bash"), Some(ShellKind::Bash));
+        assert_eq!(
+            detect_shell("/opt/homebrew/bin/fish"),
+            Some(ShellKind::Fish)
+        );
+        assert_eq!(detect_shell("/usr/bin/BASH.EXE"), Some(ShellKind::Bash));
+        assert_eq!(detect_shell("/bin/sh"), None);
+        assert_eq!(detect_shell("/usr/bin/nu"), None);
+    }
+
+    #[test]
+    fn unknown_shells_are_left_untouched() {
+        let mut cmd = CommandBuilder::new("/usr/bin/nu");
+        assert!(!configure(&mut cmd, "/usr/bin/nu"));
+        assert_eq!(cmd.get_argv().len(), 1);
+    }
+
+    #[test]
+    fn marks_are_read_from_both_terminators_and_both_codes() {
+        let mut parser = OscParser::default();
+        let marks = parser.feed(
+            "\x1b]133;A\x07user@host % \x1b]133;B\x1b\\\x1b]633;E;ls -la\x07\x1b]133;C\x07out\x1b]133;D;2\x07",
+        );
+        assert_eq!(
+            marks,
+            [
+                ShellMark::PromptStart,
+                ShellMark::PromptEnd,
+                ShellMark::CommandLine("ls -la".to_string()),
+                ShellMark::CommandStart,
+                ShellMark::CommandFinished(Some(2)),
+            ]
+        );
+    }
+
+    #[test]
+    fn sequences_split_across_reads_are_completed_later() {
+        let mut parser = OscParser::default();
+        assert_eq!(parser.feed("output\x1b"), []);
+        assert_eq!(parser.feed("]133"), []);
+        assert_eq!(parser.feed(";D;0"), []);
+        assert_eq!(parser.feed("\x1b"), []);
+        assert_eq!(
+            parser.feed("\\more output\x1b]133;A"),
+            [ShellMark::CommandFinished(Some(0))]
+        );
+        assert_eq!(parser.feed("\x07"), [ShellMark::PromptStart]);
+    }
+
+    #[test]
+    fn other_sequences_and_plain_escape_codes_are_ignored() {
+        let mut parser = OscParser::default();
+        assert_eq!(
+            parser.feed("\x1b[31mred\x1b[0m\x1b]0;window title\x07\x1b]133;Z\x07"),
+            []
+        );
+        assert_eq!(
+            parser.feed("\x1b]133;D\x07"),
+            [ShellMark::CommandFinished(None)]
+        );
+    }
+
+    #[test]
+    fn runaway_sequences_are_dropped() {
+        let mut parser = OscParser::default();
+        parser.feed(&format!("\x1b]133;E;{}", "x".repeat(MAX_PENDING_SEQUENCE)));
+        assert_eq!(parser.feed("\x07\x1b]133;C\x07"), [ShellMark::CommandStart]);
+    }
+
+    #[test]
+    fn command_lines_are_unescaped() {
+        let mut parser = OscParser::default();
+        assert_eq!(
+            parser.feed("\x1b]633;E;echo a\\x3bb \\\\n;nonce123\x07"),
+            [ShellMark::CommandLine("echo a;b \\n".to_string())]
+        );
+        assert_eq!(unescape("bad \\xZZ escape"), "bad \\xZZ escape");
+    }
+}
@@ -0,0 +1,320 @@
+use std::{
+    future::Future,
+    sync::atomic::{AtomicBool, Ordering},
+    time::Duration,
+};
+
+use futures_util::future::{BoxFuture, FutureExt};
+use log::{info, warn};
+use parking_lot::Mutex;
+use tokio::time::{timeout, Instant};
+
+/// Everything registered must finish within this; the app exits regardless
+pub const SHUTDOWN_BUDGET: Duration = Duration::from_secs(8);
+
+/// Teardown stages, run in declaration order
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum ShutdownStage {
+    PersistState,
+    StopListeners,
+    CloseTerminals,
+    StopOpenCode,
+}
+
+type TeardownFn = Box<dyn FnOnce() -> BoxFuture<'static, ()> + Send>;
+
+struct Teardown {
+    name: &'static str,
+    stage: ShutdownStage,
+    timeout: Duration,
+    run: TeardownFn,
+}
+
+/// Teardowns that did not finish, by name
+#[derive(Debug, Default)]
+pub struct ShutdownReport {
+    pub timed_out: Vec<&'static str>,
+    /// Not started because the budget ran out
+    pub skipped: Vec<&'static str>,
+}
+
+/// Runs subsystem teardowns once, ordered by stage (registration order within
+/// a stage), each under its own timeout and all within SHUTDOWN_BUDGET, so a
+/// hanging subsystem delays exit but never blocks it.
+pub struct ShutdownCoordinator {
+    teardowns: Mutex<Vec<Teardown>>,
+    started: AtomicBool,
+    budget: Duration,
+}
+
+impl Default for ShutdownCoordinator {
+    fn default() -> Self {
+        Self {
+            teardowns: Mutex::new(Vec::new()),
+            started: AtomicBool::new(false),
+            budget: SHUTDOWN_BUDGET,
+        }
+    }
+}
+
+impl ShutdownCoordinator {
+    #[cfg(test)]
+    fn with_budget(budget: Duration) -> Self {
+        Self {
+            budget,
+            ..Self::default()
+        }
+    }
+
+    pub fn register<F, Fut>(
+        &self,
+        name: &'static str,
+        stage: ShutdownStage,
+        timeout: Duration,
+        teardown: F,
+    ) where
+        F: FnOnce() -> Fut + Send + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        if self.started.load(Ordering::SeqCst) {
+            warn!(
+                "[desktop:shutdown] Ignoring {} registered during shutdown",
+                name
+            );
+            return;
+        }
+        self.teardowns.lock().push(Teardown {
+            name,
+            stage,
+            timeout,
+            run: Box::new(move || teardown().boxed()),
+        });
+    }
+
+    /// Run every teardown. Later calls return immediately with an empty report.
+    pub async fn run(&self) -> ShutdownReport {
+        let mut report = ShutdownReport::default();
+        if self.started.swap(true, Ordering::SeqCst) {
+            return report;
+        }
+        let mut teardowns = std::mem::take(&mut *self.teardowns.lock());
+        // Stable, so registration order holds within a stage
+        teardowns.sort_by_key(|teardown| teardown.stage);
+
+        let deadline = Instant::now() + self.budget;
+        for teardown in teardowns {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() {
+                report.skipped.push(teardown.name);
+                continue;
+            }
+            let started = Instant::now();
+            match timeout(teardown.timeout.min(remaining), (teardown.run)()).await {
+                Ok(()) => info!(
+                    "[desktop:shutdown] {} done in {:?}",
+                    teardown.name,
+                    started.elapsed()
+                ),
+                Err(_) => {
+                    warn!(
+                        "[desktop:shutdown] {} timed out after {:?}",
+                        teardown.name,
+                        started.elapsed()
+                    );
+                    report.timed_out.push(teardown.name);
+                }
+            }
+        }
+
+        if !report.skipped.is_empty() {
+            warn!(
+                "[desktop:shutdown] Budget exhausted, skipped: {}",
+                report.skipped.join(", ")
+            );
+        }
+        report
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    /// Names of the fake subsystems in the order their teardown finished
+    #[derive(Clone, Default)]
+    struct Log(Arc<Mutex<Vec<&'static str>>>);
+
+    impl Log {
+        fn register(
+            &self,
+            coordinator: &ShutdownCoordinator,
+            name: &'static str,
+            stage: ShutdownStage,
+            work: Duration,
+        ) {
+            let log = self.clone();
+            coordinator.register(
+                name,
+                stage,
+                Duration::from_millis(200),
+                move || async move {
+                    tokio::time::sleep(work).await;
+                    log.0.lock().push(name);
+                },
+            );
+        }
+
+        fn finished(&self) -> Vec<&'static str> {
+            self.0.lock().clone()
+        }
+    }
+
+    fn hang(coordinator: &ShutdownCoordinator, name: &'static str, timeout: Duration) {
+        coordinator.register(
+            name,
+            ShutdownStage::StopListeners,
+            timeout,
+            futures_util::future::pending,
+        );
+    }
+
+    #[tokio::test]
+    async fn teardowns_run_by_stage_then_registration_order() {
+        let coordinator = ShutdownCoordinator::default();
+        let log = Log::default();
+        let quick = Duration::from_millis(1);
+        log.register(&coordinator, "opencode", ShutdownStage::StopOpenCode, quick);
+        log.register(
+            &coordinator,
+            "terminals",
+            ShutdownStage::CloseTerminals,
+            quick,
+        );
+        log.register(&coordinator, "watcher", ShutdownStage::StopListeners, quick);
+        log.register(
+            &coordinator,
+            "window state",
+            ShutdownStage::PersistState,
+            quick,
+        );
+        log.register(
+            &coordinator,
+            "event hub",
+            ShutdownStage::StopListeners,
+            quick,
+        );
+
+        let report = coordinator.run().await;
+        assert!(report.timed_out.is_empty());
+        assert!(report.skipped.is_empty());
+        assert_eq!(
+            log.finished(),
+            [
+                "window state",
+                "watcher",
+                "event hub",
+                "terminals",
+                "opencode"
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn a_hanging_subsystem_times_out_and_the_rest_still_run() {
+        let coordinator = ShutdownCoordinator::default();
+        let log = Log::default();
+        log.register(
+            &coordinator,
+            "window state",
+            ShutdownStage::PersistState,
+            Duration::from_millis(1),
+        );
+        hang(&coordinator, "watcher", Duration::from_millis(100));
+        log.register(
+            &coordinator,
+            "opencode",
+            ShutdownStage::StopOpenCode,
+            Duration::from_millis(1),
+        );
+
+        let started = Instant::now();
+        let report = coordinator.run().await;
+        assert!(started.elapsed() < Duration::from_secs(1));
+        assert_eq!(report.timed_out, ["watcher"]);
+        assert!(report.skipped.is_empty());
+        assert_eq!(log.finished(), ["window state", "opencode"]);
+    }
+
+    #[tokio::test]
+    async fn the_budget_bounds_shutdown_even_with_generous_timeouts() {
+        let coordinator = ShutdownCoordinator::with_budget(Duration::from_millis(300));
+        let log = Log::default();
+        hang(&coordinator, "watcher", Duration::from_secs(60));
+        log.register(
+            &coordinator,
+            "terminals",
+            ShutdownStage::CloseTerminals,
+            Duration::from_millis(1),
+        );
+        log.register(
+            &coordinator,
+            "opencode",
+            ShutdownStage::StopOpenCode,
+            Duration::from_millis(1),
+        );
+
+        let started = Instant::now();
+        let report = coordinator.run().await;
+        let elapsed = started.elapsed();
+        assert!(elapsed >= Duration::from_millis(300));
+        assert!(elapsed < Duration::from_secs(2), "took {:?}", elapsed);
+        assert_eq!(report.timed_out, ["watcher"]);
+        assert_eq!(report.skipped, ["terminals", "opencode"]);
+        assert!(log.finished().is_empty());
+    }
+
+    #[tokio::test]
+    async fn a_slow_teardown_is_cut_at_its_own_timeout() {
+        let coordinator = ShutdownCoordinator::default();
+        let log = Log::default();
+        // Needs longer than the 200ms each fake subsystem is given
+        log.register(
+            &coordinator,
+            "terminals",
+            ShutdownStage::CloseTerminals,
+            Duration::from_secs(5),
+        );
+
+        let started = Instant::now();
+        let report = coordinator.run().await;
+        assert!(started.elapsed() < Duration::from_secs(1));
+        assert_eq!(report.timed_out, ["terminals"]);
+        assert!(log.finished().is_empty());
+    }
+
+    #[tokio::test]
+    async fn shutdown_runs_once() {
+        let coordinator = ShutdownCoordinator::default();
+        let log = Log::default();
+        log.register(
+            &coordinator,
+            "opencode",
+            ShutdownStage::StopOpenCode,
+            Duration::from_millis(1),
+        );
+        coordinator.run().await;
+
+        // Registering once shutdown started is ignored
+        log.register(
+            &coordinator,
+            "late",
+            ShutdownStage::PersistState,
+            Duration::from_millis(1),
+        );
+        let again = coordinator.run().await;
+        assert!(again.timed_out.is_empty() && again.skipped.is_empty());
+        assert_eq!(log.finished(), ["opencode"]);
+        assert!(coordinator.teardowns.lock().is_empty());
+    }
+}
@@ -0,0 +1,172 @@
+use std::time::Duration;
+
+use axum::body::Bytes;
+use futures_util::{Stream, StreamExt};
+
+/// Quiet time after which a proxied event stream gets a comment frame, below
+/// the idle cut-off of common reverse proxies and VPNs
+pub const KEEP_ALIVE_INTERVAL: Duration = Duration::from_secs(25);
+
+/// An SSE comment; EventSource ignores it
+const KEEP_ALIVE_FRAME: &[u8] = b": keep-alive\n\n";
+
+/// Tracks whether the bytes forwarded so far end between two events
+struct FrameTracker {
+    /// Last bytes forwarded, enough to spot `\r\n\r\n`
+    tail: Vec<u8>,
+}
+
+impl FrameTracker {
+    fn observe(&mut self, chunk: &[u8]) {
+        self.tail.extend_from_slice(chunk);
+        let excess = self.tail.len().saturating_sub(4);
+        self.tail.drain(..excess);
+    }
+
+    /// An event ends with a blank line; nothing forwarded yet counts as a boundary
+    fn at_boundary(&self) -> bool {
+        self.tail.is_empty()
+            || self.tail.ends_with(b"\n\n")
+            || self.tail.ends_with(b"\r\r")
+            || self.tail.ends_with(b"\r\n\r\n")
+    }
+}
+
+/// Forward `upstream` unchanged, adding a keep-alive comment whenever it has
+/// been quiet for `interval`. A comment is only sent between events; while
+/// an event is half forwarded, the stream waits for the rest instead.
+pub fn with_keep_alive<S, E>(
+    upstream: S,
+    interval: Duration,
+) -> impl Stream<Item = Result<Bytes, E>>
+where
+    S: Stream<Item = Result<Bytes, E>> + Send + 'static,
+{
+    let tracker = FrameTracker { tail: Vec::new() };
+    futures_util::stream::unfold(
+        (Box::pin(upstream), tracker),
+        move |(mut upstream, mut tracker)| async move {
+            loop {
+                // `next()` is cancel-safe, so a timeout drops no data
+                match tokio::time::timeout(interval, upstream.next()).await {
+                    Ok(Some(Ok(chunk))) => {
+                        tracker.observe(&chunk);
+                        return Some((Ok(chunk), (upstream, tracker)));
+                    }
+                    Ok(Some(Err(err))) => return Some((Err(err), (upstream, tracker))),
+                    Ok(None) => return None,
+                    Err(_) if tracker.at_boundary() => {
+                        let frame = Bytes::from_static(KEEP_ALIVE_FRAME);
+                        return Some((Ok(frame), (upstream, tracker)));
+                    }
+                    Err(_) => continue,
+                }
+            }
+        },
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{body::Body, routing::get, Router};
+    use futures_util::stream;
+    use std::convert::Infallible;
+
+    const INTERVAL: Duration = Duration::from_millis(100);
+
+    fn tracker_after(chunks: &[&[u8]]) -> FrameTracker {
+        let mut tracker = FrameTracker { tail: Vec::new() };
+        for chunk in chunks {
+            tracker.observe(chunk);
+        }
+        tracker
+    }
+
+    /// Chunks sent after their pause, as a stalling upstream would
+    fn paced(chunks: Vec<(u64, &'static str)>) -> impl Stream<Item = Result<Bytes, Infallible>> {
+        stream::iter(chunks).then(|(pause_ms, chunk)| async move {
+            tokio::time::sleep(Duration::from_millis(pause_ms)).await;
+            Ok(Bytes::from_static(chunk.as_bytes()))
+        })
+    }
+
+    async fn collect<E: std::fmt::Debug>(
+        stream: impl Stream<Item = Result<Bytes, E>> + Send + 'static,
+    ) -> String {
+        let chunks: Vec<_> = with_keep_alive(stream, INTERVAL).collect().await;
+        let bytes: Vec<u8> = chunks
+            .into_iter()
+            .flat_map(|chunk| chunk.unwrap().to_vec())
+            .collect();
+        String::from_utf8(bytes).unwrap()
+    }
+
+    #[test]
+    fn blank_lines_end_an_event() {
+        assert!(tracker_after(&[]).at_boundary());
+        assert!(tracker_after(&[b"data: a\n\n"]).at_boundary());
+        assert!(tracker_after(&[b"data: a\r\n\r\n"]).at_boundary());
+        assert!(tracker_after(&[b"data: a\r\r"]).at_boundary());
+        assert!(tracker_after(&[b"data: a\r\n", b"\r", b"\n"]).at_boundary());
+        assert!(!tracker_after(&[b"data: a"]).at_boundary());
+        assert!(!tracker_after(&[b"data: a\n"]).at_boundary());
+        assert!(!tracker_after(&[b"data: a\n\n", b"data: b"]).at_boundary());
+    }
+
+    #[tokio::test]
+    async fn busy_streams_pass_through_unchanged() {
+        let relayed = collect(paced(vec![(0, "data: a\n\n"), (10, "data: b\n\n")])).await;
+        assert_eq!(relayed, "data: a\n\ndata: b\n\n");
+    }
+
+    #[tokio::test]
+    async fn quiet_streams_get_keep_alives_between_events() {
+        let relayed = collect(paced(vec![(0, "data: a\n\n"), (350, "data: b\n\n")])).await;
+        assert!(
+            relayed.starts_with("data: a\n\n: keep-alive\n\n"),
+            "{relayed}"
+        );
+        assert!(
+            relayed.ends_with(": keep-alive\n\ndata: b\n\n"),
+            "{relayed}"
+        );
+        assert_eq!(
+            relayed.replace(": keep-alive\n\n", ""),
+            "data: a\n\ndata: b\n\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn upstreams_stalling_mid_event_are_not_interrupted() {
+        let app = Router::new().route(
+            "/event",
+            get(|| async {
+                Body::from_stream(paced(vec![
+                    (0, "data: a\n\n"),
+                    (0, "data: par"),
+                    (350, "tial\n"),
+                    (0, "\n"),
+                    (250, "data: b\n\n"),
+                ]))
+            }),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            axum::serve(listener, app).await.ok();
+        });
+
+        let response = reqwest::get(format!("http://{addr}/event")).await.unwrap();
+        let relayed = collect(response.bytes_stream()).await;
+
+        assert!(
+            relayed.contains("data: partial\n\n: keep-alive\n\n"),
+            "{relayed}"
+        );
+        assert_eq!(
+            relayed.replace(": keep-alive\n\n", ""),
+            "data: a\n\ndata: partial\n\ndata: b\n\n"
+        );
+    }
+}
@@ -0,0 +1,547 @@
+use std::{sync::Arc, time::Duration};
+
+use log::{info, warn};
+use parking_lot::Mutex;
+use serde::Serialize;
+use tauri::{AppHandle, Emitter};
+
+/// Emitted with the full `SystemAppearance` whenever any field changes
+pub const APPEARANCE_CHANGED_EVENT: &str = "openchamber:appearance-changed";
+
+/// None of the platforms offer an observer we can register without a native
+/// event loop hook, so changes are picked up by polling plus window theme events
+const POLL_INTERVAL: Duration = Duration::from_secs(5);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ColorScheme {
+    Dark,
+    Light,
+    /// Detection is unsupported on this platform or failed
+    Unknown,
+}
+
+/// OS appearance and accessibility preferences the webview can't always read itself
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SystemAppearance {
+    pub color_scheme: ColorScheme,
+    /// `#rrggbb`, when the platform exposes one
+    pub accent_color: Option<String>,
+    pub reduce_motion: bool,
+    pub reduce_transparency: bool,
+    pub increase_contrast: bool,
+}
+
+impl Default for SystemAppearance {
+    fn default() -> Self {
+        Self {
+            color_scheme: ColorScheme::Unknown,
+            accent_color: None,
+            reduce_motion: false,
+            reduce_transparency: false,
+            increase_contrast: false,
+        }
+    }
+}
+
+/// Source of appearance preferences, split out so the change plumbing does not
+/// depend on the platform APIs
+pub trait AppearanceReader: Send + Sync {
+    /// Blocking; may spawn `gsettings` or `gdbus` on Linux
+    fn read(&self) -> SystemAppearance;
+}
+
+/// Reads the preferences of the platform the app runs on
+pub struct PlatformAppearance;
+
+impl AppearanceReader for PlatformAppearance {
+    fn read(&self) -> SystemAppearance {
+        platform::read()
+    }
+}
+
+/// Current appearance of this platform. Blocking, so call it from a blocking
+/// task when on the async runtime.
+pub fn read_system_appearance() -> SystemAppearance {
+    PlatformAppearance.read()
+}
+
+/// Remembers the last appearance seen so changes are reported once
+pub struct AppearanceMonitor {
+    reader: Box<dyn AppearanceReader>,
+    last: Mutex<Option<SystemAppearance>>,
+}
+
+impl AppearanceMonitor {
+    pub fn new(reader: Box<dyn AppearanceReader>) -> Self {
+        Self {
+            reader,
+            last: Mutex::new(None),
+        }
+    }
+
+    /// Read the appearance; Some when it differs from the last one seen.
+    /// The first read only establishes the baseline.
+    pub fn poll(&self) -> Option<SystemAppearance> {
+        let appearance = self.reader.read();
+        let mut last = self.last.lock();
+        match last.replace(appearance.clone()) {
+            Some(previous) if previous != appearance => Some(appearance),
+            _ => None,
+        }
+    }
+
+    /// Poll from a blocking task and emit APPEARANCE_CHANGED_EVENT on changes
+    pub async fn check(self: &Arc<Self>, app: &AppHandle) {
+        let monitor = self.clone();
+        match tokio::task::spawn_blocking(move || monitor.poll()).await {
+            Ok(Some(appearance)) => {
+                info!(
+                    "[desktop:appearance] System appearance changed: {:?}",
+                    appearance
+                );
+                let _ = app.emit(APPEARANCE_CHANGED_EVENT, &appearance);
+            }
+            Ok(None) => {}
+            Err(err) => warn!("[desktop:appearance] Failed to read appearance: {}", err),
+        }
+    }
+}
+
+/// Watch the appearance for the lifetime of the app
+pub fn spawn_appearance_watcher(app: AppHandle, monitor: Arc<AppearanceMonitor>) {
+    tauri::async_runtime::spawn(async move {
+        loop {
+            monitor.check(&app).await;
+            tokio::time::sleep(POLL_INTERVAL).await;
+        }
+    });
+}
+
+/// `#rrggbb` from 0..=255 channels
+fn hex_color(red: u8, green: u8, blue: u8) -> String {
+    format!("#{:02x}{:02x}{:02x}", red, green, blue)
+}
+
+#[cfg(target_os = "macos")]
+mod platform {
+    use super::{hex_color, ColorScheme, SystemAppearance};
+    use objc2::rc::Retained;
+    use objc2::runtime::AnyObject;
+    use objc2::{class, msg_send};
+    use objc2_foundation::NSString;
+
+    pub fn read() -> SystemAppearance {
+        // SAFETY: class methods and property getters of NSWorkspace and
+        // NSUserDefaults, both documented as thread-safe
+        unsafe {
+            let workspace: *mut AnyObject = msg_send![class!(NSWorkspace), sharedWorkspace];
+            let defaults: *mut AnyObject = msg_send![class!(NSUserDefaults), standardUserDefaults];
+            if workspace.is_null() || defaults.is_null() {
+                return SystemAppearance::default();
+            }
+
+            let style_key = NSString::from_str("AppleInterfaceStyle");
+            let style: Option<Retained<NSString>> = msg_send![defaults, stringForKey: &*style_key];
+            let color_scheme = match style {
+                Some(style) if style.to_string().eq_ignore_ascii_case("dark") => ColorScheme::Dark,
+                _ => ColorScheme::Light,
+            };
+
+            // Absent means the default blue; -1 is graphite
+            let accent_key = NSString::from_str("AppleAccentColor");
+            let accent: *mut AnyObject = msg_send![defaults, objectForKey: &*accent_key];
+            let accent_index: isize = if accent.is_null() {
+                4
+            } else {
+                msg_send![defaults, integerForKey: &*accent_key]
+            };
+
+            let reduce_motion: bool = msg_send![workspace, accessibilityDisplayShouldReduceMotion];
+            let reduce_transparency: bool =
+                msg_send![workspace, accessibilityDisplayShouldReduceTransparency];
+            let increase_contrast: bool =
+                msg_send![workspace, accessibilityDisplayShouldIncreaseContrast];
+
+            SystemAppearance {
+                color_scheme,
+                accent_color: accent_color(accent_index),
+                reduce_motion,
+                reduce_transparency,
+                increase_contrast,
+            }
+        }
+    }
+
+    /// System Settings accent swatches, keyed by `AppleAccentColor`
+    fn accent_color(index: isize) -> Option<String> {
+        let (red, green, blue) = match index {
+            -1 => (140, 140, 140),
+            0 => (255, 82, 87),
+            1 => (247, 130, 27),
+            2 => (255, 198, 0),
+            3 => (98, 186, 70),
+            4 => (0, 122, 255),
+            5 => (165, 80, 167),
+            6 => (247, 79, 158),
+            _ => return None,
+        };
+        Some(hex_color(red, green, blue))
+    }
+}
+
+#[cfg(windows)]
+mod platform {
+    use super::{hex_color, ColorScheme, SystemAppearance};
+    use std::ffi::c_void;
+    use windows_sys::Win32::System::Registry::{RegGetValueW, HKEY_CURRENT_USER, RRF_RT_REG_DWORD};
+    use windows_sys::Win32::UI::Accessibility::{HCF_HIGHCONTRASTON, HIGHCONTRASTW};
+    use windows_sys::Win32::UI::WindowsAndMessaging::{
+        SystemParametersInfoW, SPI_GETCLIENTAREAANIMATION, SPI_GETHIGHCONTRAST,
+    };
+
+    const PERSONALIZE_KEY: &str = r"Software\Microsoft\Windows\CurrentVersion\Themes\Personalize";
+    const DWM_KEY: &str = r"Software\Microsoft\Windows\DWM";
+
+    fn wide(value: &str) -> Vec<u16> {
+        value.encode_utf16().chain(std::iter::once(0)).collect()
+    }
+
+    fn registry_dword(key: &str, value: &str) -> Option<u32> {
+        let key = wide(key);
+        let value = wide(value);
+        let mut data = 0u32;
+        let mut size = std::mem::size_of::<u32>() as u32;
+        // SAFETY: key and value are NUL-terminated and `size` matches the buffer
+        let status = unsafe {
+            RegGetValueW(
+                HKEY_CURRENT_USER,
+                key.as_ptr(),
+                value.as_ptr(),
+                RRF_RT_REG_DWORD,
+                std::ptr::null_mut(),
+                &mut data as *mut u32 as *mut c_void,
+                &mut size,
+            )
+        };
+        (status == 0).then_some(data)
+    }
+
+    fn client_area_animation() -> Option<bool> {
+        let mut enabled = 0i32;
+        // SAFETY: SPI_GETCLIENTAREAANIMATION writes a BOOL to the pointer
+        let ok = unsafe {
+            SystemParametersInfoW(
+                SPI_GETCLIENTAREAANIMATION,
+                0,
+                &mut enabled as *mut i32 as *mut c_void,
+                0,
+            )
+        };
+        (ok != 0).then_some(enabled != 0)
+    }
+
+    fn high_contrast() -> bool {
+        let mut contrast = HIGHCONTRASTW {
+            cbSize: std::mem::size_of::<HIGHCONTRASTW>() as u32,
+            dwFlags: 0,
+            lpszDefaultScheme: std::ptr::null_mut(),
+        };
+        // SAFETY: cbSize is set and the struct outlives the call
+        let ok = unsafe {
+            SystemParametersInfoW(
+                SPI_GETHIGHCONTRAST,
+                contrast.cbSize,
+                &mut contrast as *mut HIGHCONTRASTW as *mut c_void,
+                0,
+            )
+        };
+        ok != 0 && contrast.dwFlags & HCF_HIGHCONTRASTON != 0
+    }
+
+    pub fn read() -> SystemAppearance {
+        let color_scheme = match registry_dword(PERSONALIZE_KEY, "AppsUseLightTheme") {
+            Some(0) => ColorScheme::Dark,
+            Some(_) => ColorScheme::Light,
+            None => ColorScheme::Unknown,
+        };
+        // Stored as 0xAABBGGRR
+        let accent_color = registry_dword(DWM_KEY, "AccentColor")
+            .map(|abgr| hex_color(abgr as u8, (abgr >> 8) as u8, (abgr >> 16) as u8));
+
+        SystemAppearance {
+            color_scheme,
+            accent_color,
+            reduce_motion: client_area_animation() == Some(false),
+            reduce_transparency: registry_dword(PERSONALIZE_KEY, "EnableTransparency") == Some(0),
+            increase_contrast: high_contrast(),
+        }
+    }
+}
+
+#[cfg(all(not(target_os = "macos"), not(windows)))]
+mod platform {
+    use super::{hex_color, ColorScheme, SystemAppearance};
+    use std::process::Command;
+
+    /// Value of an `org.freedesktop.appearance` key from the settings portal,
+    /// as printed by gdbus, e.g. `(<uint32 1>,)`
+    fn portal_setting(key: &str) -> Option<String> {
+        let output = Command::new("gdbus")
+            .args([
+                "call",
+                "--session",
+                "--dest",
+                "org.freedesktop.portal.Desktop",
+                "--object-path",
+                "/org/freedesktop/portal/desktop",
+                "--method",
+                "org.freedesktop.portal.Settings.ReadOne",
+                "org.freedesktop.appearance",
+                key,
+            ])
+            .output()
+            .ok()?;
+        output
+            .status
+            .success()
+            .then(|| String::from_utf8_lossy(&output.stdout).trim().to_string())
+    }
+
+    fn portal_uint(key: &str) -> Option<u32> {
+        parse_uint(&portal_setting(key)?)
+    }
+
+    pub(super) fn parse_uint(value: &str) -> Option<u32> {
+        let start = value.find("uint32 ")? + "uint32 ".len();
+        value[start..]
+            .split(|c: char| !c.is_ascii_digit())
+            .next()?
+            .parse()
+            .ok()
+    }
+
+    /// The portal reports the accent as an sRGB triple of doubles, e.g. `(<(0.2, 0.5, 0.9)>,)`
+    fn portal_accent() -> Option<String> {
+        parse_accent(&portal_setting("accent-color")?)
+    }
+
+    pub(super) fn parse_accent(value: &str) -> Option<String> {
+        let channels: Vec<f64> = value
+            .split(|c: char| !(c.is_ascii_digit() || c == '.'))
+            .filter(|part| !part.is_empty())
+            .filter_map(|part| part.parse().ok())
+            .collect();
+        let [red, green, blue] = channels.as_slice() else {
+            return None;
+        };
+        // Out of range means "no accent set"
+        if [red, green, blue].iter().any(|c| !(0.0..=1.0).contains(*c)) {
+            return None;
+        }
+        let channel = |c: &f64| (c * 255.0).round() as u8;
+        Some(hex_color(channel(red), channel(green), channel(blue)))
+    }
+
+    fn gsettings(schema: &str, key: &str) -> Option<String> {
+        let output = Command::new("gsettings")
+            .args(["get", schema, key])
+            .output()
+            .ok()?;
+        output.status.success().then(|| {
+            String::from_utf8_lossy(&output.stdout)
+                .trim()
+                .trim_matches('\'')
+                .to_string()
+        })
+    }
+
+    fn gnome_accent() -> Option<String> {
+        named_accent(&gsettings("org.gnome.desktop.interface", "accent-color")?)
+    }
+
+    /// GNOME 47 named accents
+    pub(super) fn named_accent(name: &str) -> Option<String> {
+        let (red, green, blue) = match name {
+            "blue" => (53, 132, 228),
+            "teal" => (33, 144, 164),
+            "green" => (58, 148, 74),
+            "yellow" => (200, 136, 0),
+            "orange" => (237, 91, 0),
+            "red" => (230, 45, 66),
+            "pink" => (213, 97, 153),
+            "purple" => (145, 65, 172),
+            "slate" => (111, 131, 150),
+            _ => return None,
+        };
+        Some(hex_color(red, green, blue))
+    }
+
+    pub fn read() -> SystemAppearance {
+        // 0 = no preference, 1 = dark, 2 = light
+        let color_scheme = match portal_uint("color-scheme") {
+            Some(1) => ColorScheme::Dark,
+            Some(2) => ColorScheme::Light,
+            _ => match gsettings("org.gnome.desktop.interface", "color-scheme").as_deref() {
+                Some("prefer-dark") => ColorScheme::Dark,
+                Some(_) => ColorScheme::Light,
+                None => ColorScheme::Unknown,
+            },
+        };
+        let increase_contrast = match portal_uint("contrast") {
+            Some(contrast) => contrast == 1,
+            None => {
+                gsettings("org.gnome.desktop.a11y.interface", "high-contrast").as_deref()
+                    == Some("true")
+            }
+        };
+        let reduce_motion = gsettings("org.gnome.desktop.interface", "enable-animations")
+            .as_deref()
+            == Some("false");
+
+        SystemAppearance {
+            color_scheme,
+            accent_color: portal_accent().or_else(gnome_accent),
+            reduce_motion,
+            // No freedesktop setting for transparency
+            reduce_transparency: false,
+            increase_contrast,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Reports whatever the test last set, like a user flipping OS settings
+    #[derive(Clone, Default)]
+    struct MockReader {
+        current: Arc<Mutex<SystemAppearance>>,
+        reads: Arc<Mutex<usize>>,
+    }
+
+    impl MockReader {
+        fn set(&self, change: impl FnOnce(&mut SystemAppearance)) {
+            change(&mut self.current.lock());
+        }
+    }
+
+    impl AppearanceReader for MockReader {
+        fn read(&self) -> SystemAppearance {
+            *self.reads.lock() += 1;
+            self.current.lock().clone()
+        }
+    }
+
+    fn monitor() -> (AppearanceMonitor, MockReader) {
+        let reader = MockReader::default();
+        (AppearanceMonitor::new(Box::new(reader.clone())), reader)
+    }
+
+    #[test]
+    fn the_first_poll_only_sets_the_baseline() {
+        let (monitor, reader) = monitor();
+        reader.set(|appearance| appearance.color_scheme = ColorScheme::Dark);
+        assert_eq!(monitor.poll(), None);
+        assert_eq!(monitor.poll(), None);
+        assert_eq!(*reader.reads.lock(), 2);
+    }
+
+    #[test]
+    fn every_field_change_is_reported_once() {
+        let (monitor, reader) = monitor();
+        monitor.poll();
+
+        let changes: Vec<fn(&mut SystemAppearance)> = vec![
+            |appearance| appearance.color_scheme = ColorScheme::Light,
+            |appearance| appearance.accent_color = Some("#3584e4".to_string()),
+            |appearance| appearance.reduce_motion = true,
+            |appearance| appearance.reduce_transparency = true,
+            |appearance| appearance.increase_contrast = true,
+            |appearance| appearance.accent_color = None,
+        ];
+        for change in changes {
+            reader.set(change);
+            let expected = reader.current.lock().clone();
+            assert_eq!(monitor.poll(), Some(expected));
+            assert_eq!(monitor.poll(), None);
+        }
+    }
+
+    #[test]
+    fn a_change_undone_between_polls_is_not_reported() {
+        let (monitor, reader) = monitor();
+        monitor.poll();
+        reader.set(|appearance| appearance.reduce_motion = true);
+        reader.set(|appearance| appearance.reduce_motion = false);
+        assert_eq!(monitor.poll(), None);
+    }
+
+    #[test]
+    fn concurrent_polls_report_a_change_once() {
+        let (monitor, reader) = monitor();
+        let monitor = Arc::new(monitor);
+        monitor.poll();
+        reader.set(|appearance| appearance.color_scheme = ColorScheme::Dark);
+
+        let pollers: Vec<_> = (0..8)
+            .map(|_| {
+                let monitor = monitor.clone();
+                std::thread::spawn(move || monitor.poll())
+            })
+            .collect();
+        let reported = pollers
+            .into_iter()
+            .filter_map(|poller| poller.join().unwrap())
+            .count();
+        assert_eq!(reported, 1);
+    }
+
+    #[test]
+    fn appearance_serializes_for_the_webview() {
+        let appearance = SystemAppearance {
+            color_scheme: ColorScheme::Dark,
+            accent_color: Some(hex_color(0, 122, 255)),
+            reduce_motion: true,
+            reduce_transparency: false,
+            increase_contrast: true,
+        };
+        assert_eq!(
+            serde_json::to_value(&appearance).unwrap(),
+            serde_json::json!({
+                "colorScheme": "dark",
+                "accentColor": "#007aff",
+                "reduceMotion": true,
+                "reduceTransparency": false,
+                "increaseContrast": true,
+            })
+        );
+        assert_eq!(
+            serde_json::to_value(SystemAppearance::default()).unwrap()["colorScheme"],
+            "unknown"
+        );
+    }
+
+    #[cfg(all(not(target_os = "macos"), not(windows)))]
+    #[test]
+    fn portal_values_are_parsed() {
+        assert_eq!(platform::parse_uint("(<<uint32 1>>,)"), Some(1));
+        assert_eq!(platform::parse_uint("(<uint32 2>,)"), Some(2));
+        assert_eq!(platform::parse_uint("(<'default'>,)"), None);
+
+        assert_eq!(
+            platform::parse_accent(
+                "(<<(0.20784313725490197, 0.51764705882352946, 0.89411764705882357)>>,)"
+            ),
+            Some("#3584e4".to_string())
+        );
+        // Out of range channels mean no accent is set
+        assert_eq!(platform::parse_accent("(<(2.0, 2.0, 2.0)>,)"), None);
+        assert_eq!(platform::parse_accent("(<(0.5, 0.5)>,)"), None);
+
+        assert_eq!(platform::named_accent("slate"), Some("#6f8396".to_string()));
+        assert_eq!(platform::named_accent("chartreuse"), None);
+    }
+}
@@ -0,0 +1,239 @@
+use serde::Serialize;
+use serde_json::Value;
+
+/// Whether the OS focus / do-not-disturb mode is currently active
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DndState {
+    On,
+    /// Only reported where detection is implemented (macOS, Windows, GNOME)
+    Off,
+    /// Detection is unsupported on this platform or failed
+    Unknown,
+}
+
+/// What to do with a notification given the DND state and user preference
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct NotificationDecision {
+    pub show: bool,
+    pub sound: bool,
+}
+
+/// Banners are left to the OS while DND is on, but the sound is always dropped.
+/// With `respect_system_dnd` the notification is skipped entirely.
+pub fn decide(state: DndState, respect_system_dnd: bool) -> NotificationDecision {
+    match state {
+        DndState::On => NotificationDecision {
+            show: !respect_system_dnd,
+            sound: false,
+        },
+        DndState::Off | DndState::Unknown => NotificationDecision {
+            show: true,
+            sound: true,
+        },
+    }
+}
+
+/// Read `notifications.respectSystemDnd` from the desktop settings payload
+pub fn respect_system_dnd(settings: &Value) -> bool {
+    settings
+        .get("notifications")
+        .and_then(|value| value.get("respectSystemDnd"))
+        .and_then(Value::as_bool)
+        .unwrap_or(false)
+}
+
+/// Query the OS focus state. Blocking (may spawn `defaults` on macOS), so call it
+/// from a blocking task when on the async runtime.
+pub fn get_system_dnd_state() -> DndState {
+    platform::dnd_state()
+}
+
+#[cfg(target_os = "macos")]
+mod platform {
+    use super::DndState;
+    use std::process::Command;
+
+    pub fn dnd_state() -> DndState {
+        focus_assertions().unwrap_or_else(legacy_dnd_default)
+    }
+
+    /// macOS 12+: active Focus modes are recorded as assertions in this store
+    fn focus_assertions() -> Option<DndState> {
+        let path = dirs::home_dir()?
+            .join("Library")
+            .join("DoNotDisturb")
+            .join("DB")
+            .join("Assertions.json");
+        let bytes = std::fs::read(path).ok()?;
+        let value: serde_json::Value = serde_json::from_slice(&bytes).ok()?;
+        let active = value
+            .get("data")
+            .and_then(|data| data.as_array())
+            .map(|entries| {
+                entries.iter().any(|entry| {
+                    entry
+                        .get("storeAssertionRecords")
+                        .and_then(|records| records.as_array())
+                        .map(|records| !records.is_empty())
+                        .unwrap_or(false)
+                })
+            })?;
+        Some(if active { DndState::On } else { DndState::Off })
+    }
+
+    /// Pre-Monterey DND lives in the notification center defaults
+    fn legacy_dnd_default() -> DndState {
+        let output = Command::new("defaults")
+            .args([
+                "-currentHost",
+                "read",
+                "com.apple.notificationcenterui",
+                "doNotDisturb",
+            ])
+            .output();
+        match output {
+            Ok(output) if output.status.success() => {
+                match String::from_utf8_lossy(&output.stdout).trim() {
+                    "1" => DndState::On,
+                    "0" => DndState::Off,
+                    _ => DndState::Unknown,
+                }
+            }
+            _ => DndState::Unknown,
+        }
+    }
+}
+
+#[cfg(windows)]
+mod platform {
+    use super::DndState;
+    use std::ffi::c_void;
+
+    /// WNF_SHEL_QUIETHOURS_ACTIVE_PROFILE_CHANGED, the state backing Focus Assist
+    const WNF_SHEL_QUIETHOURS_ACTIVE_PROFILE_CHANGED: u64 = 0x0D83_063E_A3BF_1C75;
+
+    #[link(name = "ntdll")]
+    extern "system" {
+        fn NtQueryWnfStateData(
+            state_name: *const u64,
+            type_id: *const c_void,
+            explicit_scope: *const c_void,
+            change_stamp: *mut u32,
+            buffer: *mut c_void,
+            buffer_size: *mut u32,
+        ) -> i32;
+    }
+
+    pub fn dnd_state() -> DndState {
+        let state_name = WNF_SHEL_QUIETHOURS_ACTIVE_PROFILE_CHANGED;
+        let mut change_stamp = 0u32;
+        let mut profile = 0u32;
+        let mut size = std::mem::size_of::<u32>() as u32;
+
+        // SAFETY: all pointers reference live locals and `size` matches the buffer
+        let status = unsafe {
+            NtQueryWnfStateData(
+                &state_name,
+                std::ptr::null(),
+                std::ptr::null(),
+                &mut change_stamp,
+                &mut profile as *mut u32 as *mut c_void,
+                &mut size,
+            )
+        };
+
+        if status < 0 || size == 0 {
+            return DndState::Unknown;
+        }
+
+        // 0 = off, 1 = priority only, 2 = alarms only
+        match profile {
+            0 => DndState::Off,
+            1 | 2 => DndState::On,
+            _ => DndState::Unknown,
+        }
+    }
+}
+
+#[cfg(all(not(target_os = "macos"), not(windows)))]
+mod platform {
+    use super::DndState;
+    use std::process::Command;
+
+    /// GNOME's "Do Not Disturb" toggle turns banners off; other desktops report Unknown
+    pub fn dnd_state() -> DndState {
+        let output = Command::new("gsettings")
+            .args(["get", "org.gnome.desktop.notifications", "show-banners"])
+            .output();
+        match output {
+            Ok(output) if output.status.success() => {
+                show_banners_state(&String::from_utf8_lossy(&output.stdout))
+            }
+            _ => DndState::Unknown,
+        }
+    }
+
+    pub(super) fn show_banners_state(stdout: &str) -> DndState {
+        match stdout.trim() {
+            "false" => DndState::On,
+            "true" => DndState::Off,
+            _ => DndState::Unknown,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn dnd_drops_the_sound_and_hides_only_when_respected() {
+        let quiet = NotificationDecision {
+            show: true,
+            sound: false,
+        };
+        let hidden = NotificationDecision {
+            show: false,
+            sound: false,
+        };
+        assert_eq!(decide(DndState::On, false), quiet);
+        assert_eq!(decide(DndState::On, true), hidden);
+    }
+
+    #[test]
+    fn off_or_unknown_dnd_changes_nothing() {
+        let normal = NotificationDecision {
+            show: true,
+            sound: true,
+        };
+        for state in [DndState::Off, DndState::Unknown] {
+            assert_eq!(decide(state, false), normal);
+            assert_eq!(decide(state, true), normal);
+        }
+    }
+
+    #[test]
+    fn respect_system_dnd_defaults_to_off() {
+        assert!(!respect_system_dnd(&Value::Null));
+        assert!(!respect_system_dnd(&json!({ "notifications": {} })));
+        assert!(!respect_system_dnd(
+            &json!({ "notifications": { "respectSystemDnd": "yes" } })
+        ));
+        assert!(!respect_system_dnd(
+            &json!({ "notifications": { "respectSystemDnd": false } })
+        ));
+        assert!(respect_system_dnd(
+            &json!({ "notifications": { "respectSystemDnd": true } })
+        ));
+    }
+
+    #[cfg(all(not(target_os = "macos"), not(windows)))]
+    #[test]
+    fn gnome_banner_setting_maps_to_dnd() {
+        assert_eq!(platform::show_banners_state("false\n"), DndState::On);
+        assert_eq!(platform::show_banners_state("true\n"), DndState::Off);
+        assert_eq!(platform::show_banners_state(""), DndState::Unknown);
+    }
+}
@@ -0,0 +1,218 @@
+use std::{sync::Arc, time::Duration};
+
+use anyhow::Result;
+use log::{info, warn};
+use parking_lot::RwLock;
+use reqwest::Client;
+
+use crate::opencode_manager::OpenCodeManager;
+
+/// Localhost connections are cheap to open, so idle ones are not kept long
+const POOL_IDLE_TIMEOUT: Duration = Duration::from_secs(15);
+/// The UI fires bursts of small requests; keep enough idle connections to absorb them
+const POOL_MAX_IDLE_PER_HOST: usize = 32;
+
+struct PoolClients {
+    /// Port and server epoch the clients' connections belong to
+    key: Option<(u16, u64)>,
+    decoding: Client,
+    passthrough: Client,
+}
+
+/// HTTP clients for traffic to the local OpenCode server. Both are rebuilt
+/// whenever OpenCode comes up again on a new port or after a restart, so
+/// pooled connections to the previous process are dropped instead of being
+/// tried (and failing) on the first requests after the restart.
+pub struct UpstreamPool {
+    opencode: Arc<OpenCodeManager>,
+    clients: RwLock<PoolClients>,
+}
+
+fn build_clients() -> Result<(Client, Client)> {
+    // Internal consumers parse bodies themselves, so let reqwest decode them
+    let decoding = Client::builder()
+        .gzip(true)
+        .brotli(true)
+        .deflate(true)
+        .pool_idle_timeout(POOL_IDLE_TIMEOUT)
+        .pool_max_idle_per_host(POOL_MAX_IDLE_PER_HOST)
+        // Small SSE frames should not wait for Nagle's algorithm
+        .tcp_nodelay(true)
+        // Keeps long-lived event streams alive through idle periods
+        .tcp_keepalive(Some(Duration::from_secs(30)))
+        .build()?;
+    // The proxy streams bytes through untouched, keeping the upstream encoding headers valid
+    let passthrough = Client::builder()
+        .no_gzip()
+        .no_brotli()
+        .no_deflate()
+        .pool_idle_timeout(POOL_IDLE_TIMEOUT)
+        .pool_max_idle_per_host(POOL_MAX_IDLE_PER_HOST)
+        .tcp_nodelay(true)
+        .build()?;
+    Ok((decoding, passthrough))
+}
+
+impl UpstreamPool {
+    pub fn new(opencode: Arc<OpenCodeManager>) -> Result<Self> {
+        let (decoding, passthrough) = build_clients()?;
+        Ok(Self {
+            opencode,
+            clients: RwLock::new(PoolClients {
+                key: None,
+                decoding,
+                passthrough,
+            }),
+        })
+    }
+
+    /// Client that decodes response bodies, for callers that parse them
+    pub fn decoding(&self) -> Client {
+        self.current().0
+    }
+
+    /// Client that leaves content encodings alone, for streaming responses through
+    pub fn passthrough(&self) -> Client {
+        self.current().1
+    }
+
+    fn current(&self) -> (Client, Client) {
+        let key = self
+            .opencode
+            .current_port()
+            .map(|port| (port, self.opencode.server_epoch()));
+        {
+            let clients = self.clients.read();
+            // While OpenCode is down there is nothing to recycle for yet
+            if key.is_none() || clients.key == key {
+                return (clients.decoding.clone(), clients.passthrough.clone());
+            }
+        }
+
+        let mut clients = self.clients.write();
+        if clients.key != key {
+            if clients.key.is_some() {
+                match build_clients() {
+                    Ok((decoding, passthrough)) => {
+                        clients.decoding = decoding;
+                        clients.passthrough = passthrough;
+                        info!(
+                            "[desktop:http] Recycled OpenCode connection pool for port {}",
+                            key.map(|(port, _)| port).unwrap_or_default()
+                        );
+                    }
+                    Err(err) => {
+                        warn!("[desktop:http] Failed to rebuild connection pool: {}", err)
+                    }
+                }
+            }
+            clients.key = key;
+        }
+        (clients.decoding.clone(), clients.passthrough.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::opencode_client::OpenCodeClient;
+    use axum::{
+        body::Body,
+        http::{header, HeaderMap, Response},
+        Router,
+    };
+    use std::{io::Write, path::PathBuf};
+
+    const SESSION_JSON: &str = r#"{"id":"ses_1","title":"Compressed"}"#;
+
+    fn gzip(bytes: &[u8]) -> Vec<u8> {
+        let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        encoder.write_all(bytes).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    fn deflate(bytes: &[u8]) -> Vec<u8> {
+        let mut encoder =
+            flate2::write::ZlibEncoder::new(Vec::new(), flate2::Compression::default());
+        encoder.write_all(bytes).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    /// Serves the session JSON in whichever of gzip or deflate the client
+    /// accepts first, regardless of path
+    async fn compressing_opencode() -> Arc<OpenCodeManager> {
+        let app = Router::new().fallback(|headers: HeaderMap| async move {
+            let accepted = headers
+                .get(header::ACCEPT_ENCODING)
+                .and_then(|value| value.to_str().ok())
+                .unwrap_or("gzip");
+            let (encoding, body) = if accepted.trim_start().starts_with("deflate") {
+                ("deflate", deflate(SESSION_JSON.as_bytes()))
+            } else {
+                ("gzip", gzip(SESSION_JSON.as_bytes()))
+            };
+            Response::builder()
+                .header(header::CONTENT_TYPE, "application/json")
+                .header(header::CONTENT_ENCODING, encoding)
+                .body(Body::from(body))
+                .unwrap()
+        });
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        tokio::spawn(async move {
+            axum::serve(listener, app).await.ok();
+        });
+        Arc::new(OpenCodeManager::ready_on(
+            port,
+            PathBuf::from("/work/project"),
+        ))
+    }
+
+    fn url(opencode: &OpenCodeManager) -> String {
+        format!(
+            "http://127.0.0.1:{}/session/ses_1",
+            opencode.current_port().unwrap()
+        )
+    }
+
+    #[tokio::test]
+    async fn internal_consumers_get_decoded_json() {
+        let opencode = compressing_opencode().await;
+        let pool = Arc::new(UpstreamPool::new(opencode.clone()).unwrap());
+
+        for encoding in ["gzip", "deflate"] {
+            let response = pool
+                .decoding()
+                .get(url(&opencode))
+                .header(header::ACCEPT_ENCODING, encoding)
+                .send()
+                .await
+                .unwrap();
+            assert!(response.headers().get(header::CONTENT_ENCODING).is_none());
+            assert_eq!(response.text().await.unwrap(), SESSION_JSON, "{encoding}");
+        }
+
+        let session = OpenCodeClient::new(pool, opencode)
+            .get_session("ses_1")
+            .await
+            .unwrap();
+        assert_eq!(session.title.as_deref(), Some("Compressed"));
+    }
+
+    #[tokio::test]
+    async fn the_proxy_client_leaves_encoded_bodies_alone() {
+        let opencode = compressing_opencode().await;
+        let pool = UpstreamPool::new(opencode.clone()).unwrap();
+
+        let response = pool
+            .passthrough()
+            .get(url(&opencode))
+            .header(header::ACCEPT_ENCODING, "gzip")
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.headers()[header::CONTENT_ENCODING], "gzip");
+        let body = response.bytes().await.unwrap();
+        assert_eq!(body.as_ref(), gzip(SESSION_JSON.as_bytes()));
+    }
+}
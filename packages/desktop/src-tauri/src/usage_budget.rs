@@ -0,0 +1,270 @@
+use chrono::{Datelike, Days, NaiveDate};
+use log::info;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tauri::{AppHandle, Emitter};
+use tauri_plugin_notification::NotificationExt;
+
+use crate::{
+    notification_history, platform,
+    system_dnd::{self, DndState},
+    DesktopRuntime,
+};
+
+/// Event emitted to the webview when spend crosses a budget threshold
+pub const BUDGET_THRESHOLD_EVENT: &str = "openchamber:budget-threshold";
+
+/// Percentages of a budget that trigger an alert, each at most once per period
+pub const BUDGET_THRESHOLDS: [u32; 2] = [80, 100];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BudgetPeriod {
+    Daily,
+    Weekly,
+}
+
+impl BudgetPeriod {
+    pub const ALL: [BudgetPeriod; 2] = [BudgetPeriod::Daily, BudgetPeriod::Weekly];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            BudgetPeriod::Daily => "daily",
+            BudgetPeriod::Weekly => "weekly",
+        }
+    }
+
+    /// First local date of the period containing `today`; weeks start on Monday
+    pub fn start(&self, today: NaiveDate) -> NaiveDate {
+        match self {
+            BudgetPeriod::Daily => today,
+            BudgetPeriod::Weekly => today
+                .checked_sub_days(Days::new(today.weekday().num_days_from_monday() as u64))
+                .unwrap_or(today),
+        }
+    }
+
+    /// Identifies the current period so fired flags reset when it rolls over
+    pub fn key(&self, today: NaiveDate) -> String {
+        self.start(today).to_string()
+    }
+}
+
+/// Budgets from `usage.dailyBudgetUsd` / `usage.weeklyBudgetUsd`; unset or non-positive disables
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct BudgetSettings {
+    pub daily: Option<f64>,
+    pub weekly: Option<f64>,
+}
+
+impl BudgetSettings {
+    pub fn from_settings(settings: &Value) -> Self {
+        let read = |key: &str| {
+            settings
+                .get("usage")
+                .and_then(|usage| usage.get(key))
+                .and_then(Value::as_f64)
+                .filter(|budget| budget.is_finite() && *budget > 0.0)
+        };
+        Self {
+            daily: read("dailyBudgetUsd"),
+            weekly: read("weeklyBudgetUsd"),
+        }
+    }
+
+    pub fn budget(&self, period: BudgetPeriod) -> Option<f64> {
+        match period {
+            BudgetPeriod::Daily => self.daily,
+            BudgetPeriod::Weekly => self.weekly,
+        }
+    }
+}
+
+/// Thresholds already announced for one period, persisted alongside usage totals
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct FiredThresholds {
+    pub period: String,
+    pub thresholds: Vec<u32>,
+}
+
+impl FiredThresholds {
+    /// Record and return the thresholds `spend` newly crossed in `period_key`,
+    /// forgetting flags left over from an earlier period.
+    pub fn cross(&mut self, period_key: &str, spend: f64, budget: f64) -> Vec<u32> {
+        if self.period != period_key {
+            self.period = period_key.to_string();
+            self.thresholds.clear();
+        }
+
+        let percentage = spend / budget * 100.0;
+        let crossed: Vec<u32> = BUDGET_THRESHOLDS
+            .iter()
+            .copied()
+            .filter(|threshold| percentage >= *threshold as f64)
+            .filter(|threshold| !self.thresholds.contains(threshold))
+            .collect();
+        self.thresholds.extend(&crossed);
+        crossed
+    }
+}
+
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BudgetStatus {
+    pub period: BudgetPeriod,
+    pub spend: f64,
+    pub budget: Option<f64>,
+    pub percentage: Option<f64>,
+}
+
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BudgetAlert {
+    pub period: BudgetPeriod,
+    pub threshold: u32,
+    pub spend: f64,
+    pub budget: f64,
+}
+
+impl BudgetAlert {
+    pub fn notification_title(&self) -> String {
+        if self.threshold >= 100 {
+            format!("{} usage budget reached", capitalized(self.period))
+        } else {
+            format!("{}% of {} usage budget used", self.threshold, self.period.as_str())
+        }
+    }
+
+    pub fn notification_body(&self) -> String {
+        format!("${:.2} of ${:.2} spent", self.spend, self.budget)
+    }
+}
+
+fn capitalized(period: BudgetPeriod) -> &'static str {
+    match period {
+        BudgetPeriod::Daily => "Daily",
+        BudgetPeriod::Weekly => "Weekly",
+    }
+}
+
+/// Check budgets after usage was recorded and announce newly crossed thresholds
+pub async fn check_budgets(app: &AppHandle, runtime: &DesktopRuntime) {
+    let settings = runtime.settings().load().await.unwrap_or(Value::Null);
+    let budgets = BudgetSettings::from_settings(&settings);
+    if budgets.daily.is_none() && budgets.weekly.is_none() {
+        return;
+    }
+
+    let alerts = runtime.usage().evaluate_budgets(&budgets);
+    if alerts.is_empty() {
+        return;
+    }
+
+    let dnd = tokio::task::spawn_blocking(system_dnd::get_system_dnd_state)
+        .await
+        .unwrap_or(DndState::Unknown);
+    let decision = system_dnd::decide(dnd, system_dnd::respect_system_dnd(&settings));
+    let sound = platform::notification_sound(&settings);
+
+    for alert in alerts {
+        info!(
+            "[desktop:usage] {} budget crossed {}% (${:.2} of ${:.2})",
+            alert.period.as_str(),
+            alert.threshold,
+            alert.spend,
+            alert.budget
+        );
+        let _ = app.emit(BUDGET_THRESHOLD_EVENT, &alert);
+        notification_history::record(
+            app,
+            runtime.notification_history(),
+            &alert.notification_title(),
+            &alert.notification_body(),
+            !decision.show,
+        );
+
+        if decision.show {
+            let mut builder = app
+                .notification()
+                .builder()
+                .title(alert.notification_title())
+                .body(alert.notification_body());
+            if let Some(sound) = sound.as_deref().filter(|_| decision.sound) {
+                builder = builder.sound(sound);
+            }
+            let _ = builder.show();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn date(y: i32, m: u32, d: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(y, m, d).unwrap()
+    }
+
+    #[test]
+    fn weeks_start_on_monday() {
+        // 2026-03-04 is a Wednesday
+        let wednesday = date(2026, 3, 4);
+        assert_eq!(BudgetPeriod::Daily.start(wednesday), wednesday);
+        assert_eq!(BudgetPeriod::Weekly.start(wednesday), date(2026, 3, 2));
+        assert_eq!(
+            BudgetPeriod::Weekly.start(date(2026, 3, 2)),
+            date(2026, 3, 2)
+        );
+        assert_eq!(BudgetPeriod::Weekly.key(date(2026, 3, 8)), "2026-03-02");
+    }
+
+    #[test]
+    fn only_positive_budgets_are_enabled() {
+        let settings = json!({ "usage": { "dailyBudgetUsd": 5, "weeklyBudgetUsd": 0 } });
+        assert_eq!(
+            BudgetSettings::from_settings(&settings),
+            BudgetSettings {
+                daily: Some(5.0),
+                weekly: None,
+            }
+        );
+        assert_eq!(
+            BudgetSettings::from_settings(&json!({ "usage": { "dailyBudgetUsd": "5" } })),
+            BudgetSettings::default()
+        );
+    }
+
+    #[test]
+    fn each_threshold_fires_once_per_period() {
+        let mut fired = FiredThresholds::default();
+        assert!(fired.cross("2026-03-02", 3.0, 10.0).is_empty());
+        assert_eq!(fired.cross("2026-03-02", 8.0, 10.0), vec![80]);
+        assert!(fired.cross("2026-03-02", 9.0, 10.0).is_empty());
+        assert_eq!(fired.cross("2026-03-02", 10.0, 10.0), vec![100]);
+        assert!(fired.cross("2026-03-02", 12.0, 10.0).is_empty());
+
+        // A new period starts over, and a jump past both reports both
+        assert_eq!(fired.cross("2026-03-09", 11.0, 10.0), vec![80, 100]);
+    }
+
+    #[test]
+    fn alerts_read_as_notifications() {
+        let alert = |threshold| BudgetAlert {
+            period: BudgetPeriod::Weekly,
+            threshold,
+            spend: 8.456,
+            budget: 10.0,
+        };
+        assert_eq!(
+            alert(80).notification_title(),
+            "80% of weekly usage budget used"
+        );
+        assert_eq!(
+            alert(100).notification_title(),
+            "Weekly usage budget reached"
+        );
+        assert_eq!(alert(80).notification_body(), "$8.46 of $10.00 spent");
+    }
+}
@@ -0,0 +1,529 @@
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use chrono::{DateTime, Local, NaiveDate, TimeZone};
+use log::{debug, warn};
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tauri::{AppHandle, Emitter};
+
+use crate::{
+    paths,
+    usage_budget::{BudgetAlert, BudgetPeriod, BudgetSettings, BudgetStatus, FiredThresholds},
+};
+
+/// Event emitted to the webview after a message's usage was recorded
+pub const USAGE_UPDATED_EVENT: &str = "openchamber:usage-updated";
+
+const USAGE_FILE_NAME: &str = "usage.json";
+const FLUSH_DEBOUNCE: Duration = Duration::from_secs(2);
+/// Completed message ids remembered to avoid double counting repeated updates
+const COUNTED_MESSAGES_LIMIT: usize = 2048;
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct UsageTotals {
+    pub input_tokens: u64,
+    pub output_tokens: u64,
+    pub reasoning_tokens: u64,
+    pub cache_read_tokens: u64,
+    pub cache_write_tokens: u64,
+    pub cost: f64,
+    pub messages: u64,
+}
+
+impl UsageTotals {
+    fn add(&mut self, other: &UsageTotals) {
+        self.input_tokens += other.input_tokens;
+        self.output_tokens += other.output_tokens;
+        self.reasoning_tokens += other.reasoning_tokens;
+        self.cache_read_tokens += other.cache_read_tokens;
+        self.cache_write_tokens += other.cache_write_tokens;
+        self.cost += other.cost;
+        self.messages += other.messages;
+    }
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+struct UsageData {
+    sessions: HashMap<String, UsageTotals>,
+    /// Keyed by local date (YYYY-MM-DD) so BTreeMap order is chronological
+    days: BTreeMap<String, UsageTotals>,
+    /// Budget thresholds already announced, keyed by period name
+    budget_alerts: BTreeMap<String, FiredThresholds>,
+}
+
+impl UsageData {
+    fn cost_since(&self, start: NaiveDate) -> f64 {
+        self.days
+            .range(start.to_string()..)
+            .map(|(_, day)| day.cost)
+            .sum()
+    }
+}
+
+/// A completed assistant message's usage, extracted from a `message.updated` payload
+#[derive(Clone, Debug, PartialEq)]
+pub struct MessageUsage {
+    pub message_id: String,
+    pub session_id: String,
+    pub date: NaiveDate,
+    pub totals: UsageTotals,
+}
+
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DailyUsage {
+    pub date: String,
+    pub totals: UsageTotals,
+}
+
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UsageSummary {
+    pub range: String,
+    pub totals: UsageTotals,
+    pub days: Vec<DailyUsage>,
+}
+
+/// Pull usage out of a completed assistant message. Fields missing in older
+/// OpenCode versions count as zero; messages still streaming are ignored.
+pub fn extract_message_usage(info: &Value) -> Option<MessageUsage> {
+    if info.get("role").and_then(Value::as_str) != Some("assistant") {
+        return None;
+    }
+
+    let completed_ms = info
+        .get("time")
+        .and_then(|time| time.get("completed"))
+        .and_then(Value::as_i64)?;
+    let date = Local
+        .timestamp_millis_opt(completed_ms)
+        .single()
+        .map(|dt: DateTime<Local>| dt.date_naive())?;
+
+    let message_id = info.get("id").and_then(Value::as_str)?.to_string();
+    let session_id = info.get("sessionID").and_then(Value::as_str)?.to_string();
+
+    let tokens = info.get("tokens");
+    let count = |value: Option<&Value>| value.and_then(Value::as_u64).unwrap_or(0);
+    let cache = tokens.and_then(|t| t.get("cache"));
+
+    Some(MessageUsage {
+        message_id,
+        session_id,
+        date,
+        totals: UsageTotals {
+            input_tokens: count(tokens.and_then(|t| t.get("input"))),
+            output_tokens: count(tokens.and_then(|t| t.get("output"))),
+            reasoning_tokens: count(tokens.and_then(|t| t.get("reasoning"))),
+            cache_read_tokens: count(cache.and_then(|c| c.get("read"))),
+            cache_write_tokens: count(cache.and_then(|c| c.get("write"))),
+            cost: info
+                .get("cost")
+                .and_then(Value::as_f64)
+                .filter(|cost| cost.is_finite() && *cost >= 0.0)
+                .unwrap_or(0.0),
+            messages: 1,
+        },
+    })
+}
+
+/// Aggregates token and cost usage per session and per local day, persisted to
+/// usage.json in the state directory
+pub struct UsageTracker {
+    path: Option<PathBuf>,
+    data: Mutex<UsageData>,
+    counted: Mutex<HashSet<String>>,
+    flush_scheduled: AtomicBool,
+}
+
+impl UsageTracker {
+    pub fn load() -> Arc<Self> {
+        let path = paths::state_dir().map(|dir| dir.join(USAGE_FILE_NAME));
+        if let (Some(path), Some(config)) = (&path, paths::config_dir()) {
+            adopt_config_file(path, &config.join(USAGE_FILE_NAME));
+        }
+        Self::load_from(path)
+    }
+
+    fn load_from(path: Option<PathBuf>) -> Arc<Self> {
+        let data = path
+            .as_ref()
+            .and_then(|path| std::fs::read(path).ok())
+            .and_then(|bytes| match serde_json::from_slice::<UsageData>(&bytes) {
+                Ok(data) => Some(data),
+                Err(err) => {
+                    warn!("[desktop:usage] Ignoring unreadable usage file: {}", err);
+                    None
+                }
+            })
+            .unwrap_or_default();
+
+        Arc::new(Self {
+            path,
+            data: Mutex::new(data),
+            counted: Mutex::new(HashSet::new()),
+            flush_scheduled: AtomicBool::new(false),
+        })
+    }
+
+    /// Record a `message.updated` info payload, emitting `openchamber:usage-updated`
+    /// and scheduling a debounced flush. Returns whether anything was recorded.
+    pub fn record_message(self: &Arc<Self>, app: &AppHandle, info: &Value) -> bool {
+        let Some((usage, session, day)) = self.record(info) else {
+            return false;
+        };
+
+        let _ = app.emit(
+            USAGE_UPDATED_EVENT,
+            serde_json::json!({
+                "sessionId": usage.session_id,
+                "date": usage.date.to_string(),
+                "session": session,
+                "day": day,
+            }),
+        );
+
+        self.schedule_flush();
+        true
+    }
+
+    /// Count a message once; returns its usage with the updated session and day totals
+    fn record(&self, info: &Value) -> Option<(MessageUsage, UsageTotals, UsageTotals)> {
+        let usage = extract_message_usage(info)?;
+
+        {
+            let mut counted = self.counted.lock();
+            if counted.contains(&usage.message_id) {
+                return None;
+            }
+            if counted.len() >= COUNTED_MESSAGES_LIMIT {
+                counted.clear();
+            }
+            counted.insert(usage.message_id.clone());
+        }
+
+        let (session, day) = self.apply(&usage);
+        Some((usage, session, day))
+    }
+
+    fn apply(&self, usage: &MessageUsage) -> (UsageTotals, UsageTotals) {
+        let mut data = self.data.lock();
+        let session = data.sessions.entry(usage.session_id.clone()).or_default();
+        session.add(&usage.totals);
+        let session = session.clone();
+        let day = data.days.entry(usage.date.to_string()).or_default();
+        day.add(&usage.totals);
+        (session, day.clone())
+    }
+
+    pub fn session_usage(&self, session_id: &str) -> Option<UsageTotals> {
+        self.data.lock().sessions.get(session_id).cloned()
+    }
+
+    /// Summarise usage for `today`, `7d`, `30d` or `all` (the default)
+    pub fn summary(&self, range: &str) -> Result<UsageSummary, String> {
+        let today = Local::now().date_naive();
+        let since = match range {
+            "today" => Some(today),
+            "7d" => today.checked_sub_days(chrono::Days::new(6)),
+            "30d" => today.checked_sub_days(chrono::Days::new(29)),
+            "all" => None,
+            other => return Err(format!("Unknown usage range: {}", other)),
+        };
+        let since = since.map(|date| date.to_string());
+
+        let data = self.data.lock();
+        let mut totals = UsageTotals::default();
+        let days = data
+            .days
+            .iter()
+            .filter(|(date, _)| since.as_ref().map(|since| *date >= since).unwrap_or(true))
+            .map(|(date, day)| {
+                totals.add(day);
+                DailyUsage {
+                    date: date.clone(),
+                    totals: day.clone(),
+                }
+            })
+            .collect();
+
+        Ok(UsageSummary {
+            range: range.to_string(),
+            totals,
+            days,
+        })
+    }
+
+    pub fn budget_status(&self, budgets: &BudgetSettings) -> Vec<BudgetStatus> {
+        let today = Local::now().date_naive();
+        let data = self.data.lock();
+        BudgetPeriod::ALL
+            .iter()
+            .map(|period| {
+                let spend = data.cost_since(period.start(today));
+                let budget = budgets.budget(*period);
+                BudgetStatus {
+                    period: *period,
+                    spend,
+                    budget,
+                    percentage: budget.map(|budget| spend / budget * 100.0),
+                }
+            })
+            .collect()
+    }
+
+    /// Mark and return budget thresholds crossed since the last check.
+    /// Fired flags are persisted so restarts don't repeat alerts within a period.
+    pub fn evaluate_budgets(self: &Arc<Self>, budgets: &BudgetSettings) -> Vec<BudgetAlert> {
+        let today = Local::now().date_naive();
+        let mut alerts = Vec::new();
+        {
+            let mut data = self.data.lock();
+            for period in BudgetPeriod::ALL {
+                let Some(budget) = budgets.budget(period) else {
+                    continue;
+                };
+                let spend = data.cost_since(period.start(today));
+                let fired = data
+                    .budget_alerts
+                    .entry(period.as_str().to_string())
+                    .or_default();
+                for threshold in fired.cross(&period.key(today), spend, budget) {
+                    alerts.push(BudgetAlert {
+                        period,
+                        threshold,
+                        spend,
+                        budget,
+                    });
+                }
+            }
+        }
+
+        if !alerts.is_empty() {
+            self.schedule_flush();
+        }
+        alerts
+    }
+
+    fn schedule_flush(self: &Arc<Self>) {
+        if self.flush_scheduled.swap(true, Ordering::SeqCst) {
+            return;
+        }
+        let tracker = self.clone();
+        tauri::async_runtime::spawn(async move {
+            tokio::time::sleep(FLUSH_DEBOUNCE).await;
+            tracker.flush_scheduled.store(false, Ordering::SeqCst);
+            if let Err(err) = tracker.flush().await {
+                warn!("[desktop:usage] Failed to persist usage: {}", err);
+            }
+        });
+    }
+
+    pub async fn flush(&self) -> anyhow::Result<()> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        let bytes = serde_json::to_vec_pretty(&*self.data.lock())?;
+        if let Some(parent) = path.parent() {
+            tokio::fs::create_dir_all(parent).await?;
+        }
+        let tmp = path.with_extension("json.tmp");
+        tokio::fs::write(&tmp, bytes).await?;
+        tokio::fs::rename(&tmp, path).await?;
+        debug!("[desktop:usage] Usage persisted to {}", path.display());
+        Ok(())
+    }
+}
+
+/// Earlier builds kept usage.json with the settings; move it to the state directory
+fn adopt_config_file(path: &Path, previous: &Path) {
+    if path == previous {
+        return;
+    }
+    match paths::move_file(previous, path) {
+        Ok(true) => debug!(
+            "[desktop:usage] Moved {} to {}",
+            previous.display(),
+            path.display()
+        ),
+        Ok(false) => {}
+        Err(err) => warn!("[desktop:usage] Failed to move usage file: {}", err),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    /// A scratch directory under the system temp dir, removed on drop
+    struct TempDir(PathBuf);
+
+    impl TempDir {
+        fn new() -> Self {
+            let dir =
+                std::env::temp_dir().join(format!("openchamber-usage-{}", uuid::Uuid::new_v4()));
+            std::fs::create_dir_all(&dir).unwrap();
+            Self(dir)
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            std::fs::remove_dir_all(&self.0).ok();
+        }
+    }
+
+    fn completed_at(y: i32, m: u32, d: u32, h: u32, min: u32) -> i64 {
+        Local
+            .with_ymd_and_hms(y, m, d, h, min, 0)
+            .unwrap()
+            .timestamp_millis()
+    }
+
+    fn message(id: &str, session: &str, completed: Option<i64>, input: u64, cost: f64) -> Value {
+        json!({
+            "id": id,
+            "sessionID": session,
+            "role": "assistant",
+            "time": { "created": 0, "completed": completed },
+            "tokens": { "input": input, "output": 10, "cache": { "read": 5, "write": 0 } },
+            "cost": cost,
+        })
+    }
+
+    #[test]
+    fn usage_is_split_across_a_day_boundary() {
+        let tracker = UsageTracker::load_from(None);
+        let events = [
+            message("m1", "s1", Some(completed_at(2026, 3, 1, 23, 58)), 100, 0.5),
+            // Streaming update, then the completed one, then a repeat of it
+            message("m2", "s1", None, 50, 0.0),
+            message("m2", "s1", Some(completed_at(2026, 3, 2, 0, 1)), 200, 0.25),
+            message("m2", "s1", Some(completed_at(2026, 3, 2, 0, 1)), 200, 0.25),
+            message("m3", "s2", Some(completed_at(2026, 3, 2, 0, 5)), 300, 1.0),
+        ];
+        let recorded = events
+            .iter()
+            .filter(|e| tracker.record(e).is_some())
+            .count();
+        assert_eq!(recorded, 3);
+
+        let s1 = tracker.session_usage("s1").unwrap();
+        assert_eq!(
+            (s1.input_tokens, s1.output_tokens, s1.messages),
+            (300, 20, 2)
+        );
+        assert_eq!(s1.cache_read_tokens, 10);
+        assert_eq!(s1.cost, 0.75);
+
+        let data = tracker.data.lock();
+        let days: Vec<_> = data
+            .days
+            .iter()
+            .map(|(date, day)| (date.as_str(), day.messages, day.cost))
+            .collect();
+        assert_eq!(days, [("2026-03-01", 1, 0.5), ("2026-03-02", 2, 1.25)]);
+        assert_eq!(
+            data.cost_since(NaiveDate::from_ymd_opt(2026, 3, 2).unwrap()),
+            1.25
+        );
+    }
+
+    #[test]
+    fn fields_missing_from_older_versions_count_as_zero() {
+        let tracker = UsageTracker::load_from(None);
+        let info = json!({
+            "id": "m1",
+            "sessionID": "s1",
+            "role": "assistant",
+            "time": { "completed": completed_at(2026, 3, 1, 12, 0) },
+        });
+        assert!(tracker.record(&info).is_some());
+        assert!(tracker
+            .record(&json!({ "role": "user", "id": "m2" }))
+            .is_none());
+
+        let totals = tracker.session_usage("s1").unwrap();
+        assert_eq!(
+            totals,
+            UsageTotals {
+                messages: 1,
+                ..UsageTotals::default()
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn totals_survive_a_restart() {
+        let dir = TempDir::new();
+        let path = dir.0.join("state").join(USAGE_FILE_NAME);
+
+        let tracker = UsageTracker::load_from(Some(path.clone()));
+        tracker.record(&message(
+            "m1",
+            "s1",
+            Some(completed_at(2026, 3, 1, 23, 59)),
+            100,
+            0.5,
+        ));
+        tracker.record(&message(
+            "m2",
+            "s1",
+            Some(completed_at(2026, 3, 2, 0, 0)),
+            100,
+            0.5,
+        ));
+        tracker.flush().await.unwrap();
+
+        let reloaded = UsageTracker::load_from(Some(path));
+        assert_eq!(reloaded.session_usage("s1"), tracker.session_usage("s1"));
+        assert_eq!(reloaded.data.lock().days.len(), 2);
+    }
+
+    #[test]
+    fn usage_kept_with_the_settings_moves_to_the_state_dir() {
+        let dir = TempDir::new();
+        let previous = dir.0.join("config").join(USAGE_FILE_NAME);
+        let path = dir.0.join("state").join(USAGE_FILE_NAME);
+        std::fs::create_dir_all(previous.parent().unwrap()).unwrap();
+        std::fs::write(&previous, "{}").unwrap();
+
+        adopt_config_file(&path, &previous);
+        assert!(path.is_file());
+        assert!(!previous.exists());
+
+        // A newer file in the state dir is never replaced
+        std::fs::write(&previous, "old").unwrap();
+        adopt_config_file(&path, &previous);
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "{}");
+    }
+
+    #[test]
+    fn budget_status_counts_todays_spend() {
+        let tracker = UsageTracker::load_from(None);
+        let now = Local::now().timestamp_millis();
+        tracker.record(&message("m1", "s1", Some(now), 100, 4.0));
+        let budgets = BudgetSettings {
+            daily: Some(5.0),
+            weekly: None,
+        };
+
+        let status = tracker.budget_status(&budgets);
+        assert_eq!(status[0].period, BudgetPeriod::Daily);
+        assert_eq!((status[0].spend, status[0].percentage), (4.0, Some(80.0)));
+        assert_eq!(status[1].period, BudgetPeriod::Weekly);
+        assert_eq!((status[1].spend, status[1].percentage), (4.0, None));
+    }
+}
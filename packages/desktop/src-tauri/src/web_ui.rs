@@ -0,0 +1,321 @@
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use axum::{
+    body::Body,
+    extract::{FromRef, Query, Request, State},
+    http::{header, HeaderMap, HeaderValue, Method, Response, StatusCode},
+    response::IntoResponse,
+};
+use log::{info, warn};
+use parking_lot::Mutex;
+use serde::Deserialize;
+use serde_json::Value;
+
+use crate::{
+    external_run::{presented_token, tokens_match},
+    ServerState,
+};
+
+/// Browsers open this with a one-time code to receive the session cookie
+pub const EXCHANGE_PATH: &str = "/auth/exchange";
+
+const SESSION_COOKIE: &str = "openchamber_web";
+const EXCHANGE_CODE_TTL: Duration = Duration::from_secs(5 * 60);
+const INDEX_PATH: &str = "/index.html";
+
+/// `server.serveWebUi`; read once at startup
+pub fn serve_web_ui_enabled(settings: &Value) -> bool {
+    settings
+        .get("server")
+        .and_then(|server| server.get("serveWebUi"))
+        .and_then(Value::as_bool)
+        .unwrap_or(false)
+}
+
+struct WebUiInner {
+    enabled: bool,
+    codes: Mutex<HashMap<String, Instant>>,
+    /// Cookie values (or bearer tokens) that authorize /api requests from a
+    /// browser, one per redeemed code
+    sessions: Mutex<HashSet<String>>,
+}
+
+/// Serves the bundled frontend to regular browsers when enabled. Browsers
+/// authenticate /api calls with a cookie obtained by redeeming a one-time code;
+/// the webview uses its own token, see loopback_auth.
+#[derive(Clone)]
+pub struct WebUiAccess {
+    inner: Arc<WebUiInner>,
+}
+
+impl WebUiAccess {
+    pub fn new(enabled: bool) -> Self {
+        Self {
+            inner: Arc::new(WebUiInner {
+                enabled,
+                codes: Mutex::new(HashMap::new()),
+                sessions: Mutex::new(HashSet::new()),
+            }),
+        }
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.inner.enabled
+    }
+
+    /// Single-use code for the exchange endpoint
+    pub fn issue_code(&self) -> String {
+        let code = uuid::Uuid::new_v4().simple().to_string();
+        let now = Instant::now();
+        let mut codes = self.inner.codes.lock();
+        codes.retain(|_, expires_at| *expires_at > now);
+        codes.insert(code.clone(), now + EXCHANGE_CODE_TTL);
+        code
+    }
+
+    /// Trade a live code for a new browser session; returns its secret
+    fn redeem(&self, code: &str) -> Option<String> {
+        let now = Instant::now();
+        let expires_at = self.inner.codes.lock().remove(code)?;
+        if expires_at <= now {
+            return None;
+        }
+        let secret = uuid::Uuid::new_v4().simple().to_string();
+        self.inner.sessions.lock().insert(secret.clone());
+        Some(secret)
+    }
+
+    /// A browser session of the served web UI; always false while it is not served
+    pub(crate) fn authorized(&self, headers: &HeaderMap) -> bool {
+        if !self.enabled() {
+            return false;
+        }
+        let Some(presented) = presented_token(headers).or_else(|| session_cookie(headers)) else {
+            return false;
+        };
+        self.inner
+            .sessions
+            .lock()
+            .iter()
+            .any(|secret| tokens_match(secret, presented))
+    }
+}
+
+impl FromRef<ServerState> for WebUiAccess {
+    fn from_ref(state: &ServerState) -> Self {
+        state.web_ui.clone()
+    }
+}
+
+fn session_cookie(headers: &HeaderMap) -> Option<&str> {
+    headers
+        .get_all(header::COOKIE)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(';'))
+        .filter_map(|pair| pair.trim().split_once('='))
+        .find(|(name, _)| *name == SESSION_COOKIE)
+        .map(|(_, value)| value)
+}
+
+#[derive(Deserialize)]
+pub struct ExchangeQuery {
+    token: Option<String>,
+}
+
+/// `GET /auth/exchange?token=`: trade a one-time code for the session cookie
+pub async fn exchange_handler(
+    State(web_ui): State<WebUiAccess>,
+    Query(query): Query<ExchangeQuery>,
+) -> Response<Body> {
+    if !web_ui.enabled() {
+        return StatusCode::NOT_FOUND.into_response();
+    }
+    let Some(code) = query.token.as_deref().map(str::trim) else {
+        return (StatusCode::BAD_REQUEST, "Missing token").into_response();
+    };
+    let Some(secret) = web_ui.redeem(code) else {
+        warn!("[desktop:web] Rejected expired or reused web UI token");
+        return (StatusCode::UNAUTHORIZED, "Token expired or already used").into_response();
+    };
+
+    info!("[desktop:web] Browser session opened for the web UI");
+    let cookie = format!("{SESSION_COOKIE}={secret}; Path=/; HttpOnly; SameSite=Strict");
+    Response::builder()
+        .status(StatusCode::SEE_OTHER)
+        .header(header::LOCATION, "/")
+        .header(header::SET_COOKIE, cookie)
+        .body(Body::empty())
+        .unwrap_or_else(|_| StatusCode::INTERNAL_SERVER_ERROR.into_response())
+}
+
+/// Router fallback: bundled frontend assets, with unknown routes answered by
+/// index.html so client-side routing works after a reload
+pub async fn static_handler(State(state): State<ServerState>, request: Request) -> Response<Body> {
+    if !state.web_ui.enabled() {
+        return StatusCode::NOT_FOUND.into_response();
+    }
+    if request.method() != Method::GET && request.method() != Method::HEAD {
+        return StatusCode::METHOD_NOT_ALLOWED.into_response();
+    }
+
+    let path = match request.uri().path() {
+        "" | "/" => INDEX_PATH,
+        path => path,
+    };
+    if path.split('/').any(|segment| segment == "..") {
+        return StatusCode::BAD_REQUEST.into_response();
+    }
+
+    let resolver = state.app.asset_resolver();
+    let asset = match resolver.get(path.to_string()) {
+        Some(asset) => asset,
+        // Missing files keep their 404 so broken asset links stay visible
+        None if looks_like_file(path) => return StatusCode::NOT_FOUND.into_response(),
+        None => match resolver.get(INDEX_PATH.to_string()) {
+            Some(asset) => asset,
+            None => return StatusCode::NOT_FOUND.into_response(),
+        },
+    };
+
+    let mut response = Response::new(if request.method() == Method::HEAD {
+        Body::empty()
+    } else {
+        Body::from(asset.bytes)
+    });
+    if let Ok(value) = HeaderValue::from_str(&asset.mime_type) {
+        response.headers_mut().insert(header::CONTENT_TYPE, value);
+    }
+    if asset.mime_type.starts_with("text/html") {
+        response
+            .headers_mut()
+            .insert(header::CACHE_CONTROL, HeaderValue::from_static("no-cache"));
+    }
+    response
+}
+
+fn looks_like_file(path: &str) -> bool {
+    path.rsplit('/')
+        .next()
+        .is_some_and(|name| name.contains('.'))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::loopback_auth::{require_api_auth, ApiAuth, LoopbackAuth};
+    use axum::{middleware, routing::get, Router};
+
+    /// The exchange route plus an /api route behind the desktop server's auth layer
+    async fn serve(web_ui: WebUiAccess) -> String {
+        let auth = ApiAuth {
+            loopback: LoopbackAuth::new(None),
+            web_ui: web_ui.clone(),
+        };
+        let app = Router::new()
+            .route("/api/ping", get(|| async { "pong" }))
+            .route_layer(middleware::from_fn_with_state(auth, require_api_auth))
+            .route(EXCHANGE_PATH, get(exchange_handler))
+            .with_state(web_ui);
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        tokio::spawn(async move {
+            axum::serve(listener, app).await.ok();
+        });
+        format!("http://127.0.0.1:{port}")
+    }
+
+    fn client() -> reqwest::Client {
+        reqwest::Client::builder()
+            .redirect(reqwest::redirect::Policy::none())
+            .build()
+            .unwrap()
+    }
+
+    /// Open the exchange link for `code` the way a browser would
+    async fn exchange(base: &str, code: &str) -> reqwest::Response {
+        client()
+            .get(format!("{base}{EXCHANGE_PATH}?token={code}"))
+            .send()
+            .await
+            .unwrap()
+    }
+
+    /// The `name=value` part of the session cookie the exchange set
+    fn cookie_pair(response: &reqwest::Response) -> String {
+        let set_cookie = response.headers()[header::SET_COOKIE].to_str().unwrap();
+        assert!(set_cookie.contains("HttpOnly"), "{set_cookie}");
+        set_cookie.split(';').next().unwrap().to_string()
+    }
+
+    async fn ping(base: &str, cookie: &str) -> reqwest::StatusCode {
+        client()
+            .get(format!("{base}/api/ping"))
+            .header(header::COOKIE, cookie)
+            .send()
+            .await
+            .unwrap()
+            .status()
+    }
+
+    #[tokio::test]
+    async fn each_exchange_opens_its_own_session() {
+        let web_ui = WebUiAccess::new(true);
+        let base = serve(web_ui.clone()).await;
+
+        let first = exchange(&base, &web_ui.issue_code()).await;
+        assert_eq!(first.status(), reqwest::StatusCode::SEE_OTHER);
+        assert_eq!(first.headers()[header::LOCATION], "/");
+        let first = cookie_pair(&first);
+        let second = cookie_pair(&exchange(&base, &web_ui.issue_code()).await);
+
+        assert_ne!(first, second);
+        assert_eq!(ping(&base, &first).await, reqwest::StatusCode::OK);
+        assert_eq!(ping(&base, &second).await, reqwest::StatusCode::OK);
+        assert_eq!(
+            ping(&base, &format!("{SESSION_COOKIE}=guess")).await,
+            reqwest::StatusCode::UNAUTHORIZED
+        );
+    }
+
+    #[tokio::test]
+    async fn used_and_expired_codes_are_rejected() {
+        let web_ui = WebUiAccess::new(true);
+        let base = serve(web_ui.clone()).await;
+
+        let code = web_ui.issue_code();
+        assert_eq!(
+            exchange(&base, &code).await.status(),
+            reqwest::StatusCode::SEE_OTHER
+        );
+        let replay = exchange(&base, &code).await;
+        assert_eq!(replay.status(), reqwest::StatusCode::UNAUTHORIZED);
+        assert!(replay.headers().get(header::SET_COOKIE).is_none());
+
+        let stale = "stale".to_string();
+        web_ui
+            .inner
+            .codes
+            .lock()
+            .insert(stale.clone(), Instant::now());
+        assert_eq!(
+            exchange(&base, &stale).await.status(),
+            reqwest::StatusCode::UNAUTHORIZED
+        );
+        assert_eq!(web_ui.inner.sessions.lock().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn nothing_is_exchanged_while_the_web_ui_is_off() {
+        let web_ui = WebUiAccess::new(false);
+        let base = serve(web_ui.clone()).await;
+
+        let response = exchange(&base, &web_ui.issue_code()).await;
+        assert_eq!(response.status(), reqwest::StatusCode::NOT_FOUND);
+        assert!(web_ui.inner.sessions.lock().is_empty());
+    }
+}
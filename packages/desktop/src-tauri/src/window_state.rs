@@ -7,6 +7,8 @@ use std::{
 use tauri::{LogicalPosition, LogicalSize, WebviewWindow, Window};
 use tokio::fs as async_fs;
 
+use crate::paths;
+
 const WINDOW_STATE_FILE: &str = "window-state.json";
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -77,9 +79,7 @@ impl WindowStateManager {
 }
 
 fn state_file_path() -> Result<PathBuf> {
-    let mut path = dirs::home_dir().ok_or_else(|| anyhow!("No home directory"))?;
-    path.push(".config");
-    path.push("openchamber");
+    let mut path = paths::state_dir().ok_or_else(|| anyhow!("No state directory"))?;
     path.push(WINDOW_STATE_FILE);
     Ok(path)
 }
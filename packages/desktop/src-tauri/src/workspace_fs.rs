@@ -0,0 +1,515 @@
+use std::{
+    collections::HashSet,
+    path::{Component, Path, PathBuf},
+};
+
+use log::warn;
+use once_cell::sync::Lazy;
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Emitter};
+
+/// Event emitted once per directory when a workspace sits on a problematic filesystem
+pub const WORKSPACE_FS_WARNING_EVENT: &str = "openchamber:workspace-fs-warning";
+
+/// Directories already warned about in this run
+static WARNED: Lazy<Mutex<HashSet<String>>> = Lazy::new(|| Mutex::new(HashSet::new()));
+
+/// How a path component identifies a sync client's folder
+enum Marker {
+    /// The whole component, with the exact casing the client uses
+    Name(&'static str),
+    /// Start of the component, for folders like "OneDrive - Contoso"
+    Prefix(&'static str),
+}
+
+/// Folder names sync clients create by default, on every platform. Covers
+/// macOS File Provider folders (~/Library/CloudStorage/<Provider>-<account>) too.
+const CLOUD_SYNC_MARKERS: &[(Marker, &str)] = &[
+    (Marker::Name("Dropbox"), "Dropbox"),
+    (Marker::Prefix("Dropbox ("), "Dropbox"),
+    (Marker::Prefix("Dropbox-"), "Dropbox"),
+    (Marker::Prefix("OneDrive"), "OneDrive"),
+    (Marker::Name("iCloud Drive"), "iCloud Drive"),
+    (Marker::Name("iCloudDrive"), "iCloud Drive"),
+    (Marker::Name("Mobile Documents"), "iCloud Drive"),
+    (Marker::Name("Google Drive"), "Google Drive"),
+    (Marker::Prefix("GoogleDrive"), "Google Drive"),
+    (Marker::Name("Box"), "Box"),
+    (Marker::Name("Box Sync"), "Box"),
+    (Marker::Prefix("Box-"), "Box"),
+    (Marker::Name("pCloud Drive"), "pCloud"),
+    (Marker::Name("MEGA"), "MEGA"),
+    (Marker::Name("Nextcloud"), "Nextcloud"),
+    (Marker::Name("ownCloud"), "ownCloud"),
+    (Marker::Name("Seafile"), "Seafile"),
+    (Marker::Name("SynologyDrive"), "Synology Drive"),
+];
+
+/// Linux `statfs` magic numbers of network filesystems
+#[cfg(target_os = "linux")]
+const NETWORK_FS_MAGIC: &[(u64, &str)] = &[
+    (0x6969, "nfs"),
+    (0x517B, "smb"),
+    (0xFF53_4D42, "cifs"),
+    (0xFE53_4D42, "smb2"),
+    (0x5346_414F, "afs"),
+    (0x7375_7245, "coda"),
+    (0x564C, "ncp"),
+    (0x0102_1997, "9p"),
+    (0x00C3_6400, "ceph"),
+    (0x4750_4653, "gpfs"),
+    (0x0BD0_0BD0, "lustre"),
+];
+
+/// Linux FUSE magic; the real filesystem is only known from the mount table
+#[cfg(target_os = "linux")]
+const FUSE_SUPER_MAGIC: u64 = 0x6573_5546;
+
+/// Filesystem type names (macOS `f_fstypename`, Linux mount table) that are
+/// network-backed. FUSE mounts appear as `fuse.<name>` on Linux.
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+const NETWORK_FS_NAMES: &[&str] = &[
+    "nfs",
+    "nfs4",
+    "smbfs",
+    "smb3",
+    "cifs",
+    "afpfs",
+    "webdav",
+    "davfs",
+    "ftp",
+    "afs",
+    "9p",
+    "ceph",
+    "glusterfs",
+    "sshfs",
+    "rclone",
+    "s3fs",
+    "gcsfuse",
+];
+
+/// Where a workspace lives and what that means for agents working in it
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WorkspaceFsInfo {
+    pub path: String,
+    /// Sync client that owns the folder, e.g. "Dropbox"
+    pub cloud_sync: Option<String>,
+    /// Network filesystem type, e.g. "nfs"
+    pub network_fs: Option<String>,
+    /// None when it could not be probed without writing to the folder
+    pub case_insensitive: Option<bool>,
+    /// Advice for each problem found; empty when the location is fine
+    pub warnings: Vec<String>,
+}
+
+/// Classify `path` by its components alone
+pub fn cloud_provider_from_path(path: &Path) -> Option<&'static str> {
+    path.components().find_map(|component| match component {
+        Component::Normal(name) => cloud_provider_from_name(&name.to_string_lossy()),
+        _ => None,
+    })
+}
+
+fn cloud_provider_from_name(name: &str) -> Option<&'static str> {
+    CLOUD_SYNC_MARKERS
+        .iter()
+        .find(|(marker, _)| match marker {
+            Marker::Name(marker) => name == *marker,
+            Marker::Prefix(marker) => name.starts_with(marker),
+        })
+        .map(|(_, provider)| *provider)
+}
+
+/// Map a Linux `statfs` magic number to a network filesystem name
+#[cfg(target_os = "linux")]
+pub fn network_fs_from_magic(magic: u64) -> Option<&'static str> {
+    NETWORK_FS_MAGIC
+        .iter()
+        .find(|(known, _)| *known == magic)
+        .map(|(_, name)| *name)
+}
+
+/// Map a filesystem type name to its network filesystem name
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+pub fn network_fs_from_name(fs_type: &str) -> Option<&'static str> {
+    let fs_type = fs_type.to_lowercase();
+    let base = fs_type.strip_prefix("fuse.").unwrap_or(&fs_type);
+    NETWORK_FS_NAMES.iter().copied().find(|name| *name == base)
+}
+
+/// Inspect `path` off the async runtime; a stale network mount can block
+/// filesystem calls for a long time
+pub async fn inspect(path: PathBuf) -> Option<WorkspaceFsInfo> {
+    tokio::task::spawn_blocking(move || classify(&path))
+        .await
+        .ok()
+}
+
+pub fn classify(path: &Path) -> WorkspaceFsInfo {
+    let cloud_sync = cloud_provider_from_path(path)
+        .map(str::to_string)
+        .or_else(|| synced_root_provider(path));
+    let network_fs = network_fs(path);
+    let case_insensitive = probe_case_insensitive(path);
+
+    let mut warnings = Vec::new();
+    if let Some(provider) = &cloud_sync {
+        warnings.push(format!(
+            "This folder is synced by {provider}. Sync clients race agents for .git and \
+             build output, causing conflicted copies and file-watcher storms. Move the \
+             project outside the synced folder, or exclude it from sync."
+        ));
+    }
+    if let Some(fs_type) = &network_fs {
+        warnings.push(format!(
+            "This folder is on a network filesystem ({fs_type}). File watching and git are \
+             slow there and changes may be missed. Clone the project to a local disk for \
+             agent work."
+        ));
+    }
+
+    WorkspaceFsInfo {
+        path: path.to_string_lossy().to_string(),
+        cloud_sync,
+        network_fs,
+        case_insensitive,
+        warnings,
+    }
+}
+
+/// Emit the warning event the first time a problematic directory is opened
+pub fn warn_once(app: &AppHandle, info: &WorkspaceFsInfo) {
+    if info.warnings.is_empty() || !WARNED.lock().insert(info.path.clone()) {
+        return;
+    }
+    for warning in &info.warnings {
+        warn!("[desktop:workspace] {}: {}", info.path, warning);
+    }
+    let _ = app.emit(WORKSPACE_FS_WARNING_EVENT, info);
+}
+
+/// Sync roots the clients themselves report, for folders that were renamed
+/// or moved away from the default name
+fn synced_root_provider(path: &Path) -> Option<String> {
+    if dropbox_roots().iter().any(|root| path.starts_with(root)) {
+        return Some("Dropbox".to_string());
+    }
+    #[cfg(windows)]
+    for var in ["OneDrive", "OneDriveCommercial", "OneDriveConsumer"] {
+        if let Some(root) = std::env::var_os(var).filter(|root| !root.is_empty()) {
+            if path.starts_with(PathBuf::from(root)) {
+                return Some("OneDrive".to_string());
+            }
+        }
+    }
+    None
+}
+
+/// Account folders listed in Dropbox's info.json
+fn dropbox_roots() -> Vec<PathBuf> {
+    let mut candidates = Vec::new();
+    if let Some(home) = dirs::home_dir() {
+        candidates.push(home.join(".dropbox").join("info.json"));
+    }
+    #[cfg(windows)]
+    for dir in [dirs::data_local_dir(), dirs::data_dir()]
+        .into_iter()
+        .flatten()
+    {
+        candidates.push(dir.join("Dropbox").join("info.json"));
+    }
+
+    candidates
+        .into_iter()
+        .filter_map(|file| std::fs::read(file).ok())
+        .filter_map(|content| serde_json::from_slice::<serde_json::Value>(&content).ok())
+        .flat_map(|info| {
+            info.as_object()
+                .map(|accounts| {
+                    accounts
+                        .values()
+                        .filter_map(|account| account.get("path")?.as_str().map(PathBuf::from))
+                        .collect::<Vec<_>>()
+                })
+                .unwrap_or_default()
+        })
+        .collect()
+}
+
+#[cfg(target_os = "linux")]
+fn network_fs(path: &Path) -> Option<String> {
+    let stat = nix::sys::statfs::statfs(path).ok()?;
+    // The field type differs between libc targets
+    #[allow(clippy::unnecessary_cast)]
+    let magic = stat.filesystem_type().0 as u64;
+    if magic == FUSE_SUPER_MAGIC {
+        return fuse_mount_type(path)
+            .as_deref()
+            .and_then(network_fs_from_name)
+            .map(str::to_string);
+    }
+    network_fs_from_magic(magic).map(str::to_string)
+}
+
+/// Type of the FUSE mount containing `path`
+#[cfg(target_os = "linux")]
+fn fuse_mount_type(path: &Path) -> Option<String> {
+    let mounts = std::fs::read_to_string("/proc/self/mounts").ok()?;
+    mount_type(&mounts, path)
+}
+
+/// Type of the mount containing `path` in a `/proc/self/mounts` style table
+#[cfg(target_os = "linux")]
+fn mount_type(mounts: &str, path: &Path) -> Option<String> {
+    mounts
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.split_whitespace();
+            let _source = fields.next()?;
+            // Spaces in mount points are escaped as \040
+            let mount_point = fields.next()?.replace("\\040", " ");
+            let fs_type = fields.next()?;
+            // The longest matching mount point is the innermost one
+            path.starts_with(&mount_point)
+                .then(|| (mount_point.len(), fs_type.to_string()))
+        })
+        .max_by_key(|(len, _)| *len)
+        .map(|(_, fs_type)| fs_type)
+}
+
+#[cfg(target_os = "macos")]
+fn network_fs(path: &Path) -> Option<String> {
+    let stat = nix::sys::statfs::statfs(path).ok()?;
+    network_fs_from_name(stat.filesystem_type_name()).map(str::to_string)
+}
+
+#[cfg(windows)]
+fn network_fs(path: &Path) -> Option<String> {
+    use std::os::windows::ffi::OsStrExt;
+    use std::path::Prefix;
+    use windows_sys::Win32::{
+        Storage::FileSystem::GetDriveTypeW, System::WindowsProgramming::DRIVE_REMOTE,
+    };
+
+    let Some(Component::Prefix(prefix)) = path.components().next() else {
+        return None;
+    };
+    match prefix.kind() {
+        Prefix::UNC(..) | Prefix::VerbatimUNC(..) => Some("smb".to_string()),
+        Prefix::Disk(letter) | Prefix::VerbatimDisk(letter) => {
+            let root: Vec<u16> = std::ffi::OsStr::new(&format!("{}:\\", letter as char))
+                .encode_wide()
+                .chain(std::iter::once(0))
+                .collect();
+            // SAFETY: `root` is a NUL-terminated UTF-16 string that outlives the call
+            let drive_type = unsafe { GetDriveTypeW(root.as_ptr()) };
+            (drive_type == DRIVE_REMOTE).then(|| "network drive".to_string())
+        }
+        _ => None,
+    }
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos", windows)))]
+fn network_fs(_path: &Path) -> Option<String> {
+    None
+}
+
+/// Look up a case-flipped variant of the nearest name with letters. It
+/// resolving without being a separate directory entry means the filesystem
+/// folds case. Read-only, so it never touches the workspace.
+fn probe_case_insensitive(path: &Path) -> Option<bool> {
+    for candidate in path.ancestors() {
+        let (Some(parent), Some(name)) = (candidate.parent(), candidate.file_name()) else {
+            continue;
+        };
+        let name = name.to_string_lossy();
+        let flipped: String = name
+            .chars()
+            .map(|c| {
+                if c.is_ascii_lowercase() {
+                    c.to_ascii_uppercase()
+                } else {
+                    c.to_ascii_lowercase()
+                }
+            })
+            .collect();
+        if flipped == name {
+            continue;
+        }
+        if !parent.join(&flipped).exists() {
+            return Some(false);
+        }
+        let distinct_entry = std::fs::read_dir(parent)
+            .ok()?
+            .flatten()
+            .any(|entry| entry.file_name().to_string_lossy() == flipped);
+        if !distinct_entry {
+            return Some(true);
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A scratch directory under the system temp dir, removed on drop
+    struct TempDir(PathBuf);
+
+    impl TempDir {
+        fn new() -> Self {
+            let dir = std::env::temp_dir().join(format!("openchamber-fs-{}", uuid::Uuid::new_v4()));
+            std::fs::create_dir_all(&dir).unwrap();
+            Self(std::fs::canonicalize(dir).unwrap())
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            std::fs::remove_dir_all(&self.0).ok();
+        }
+    }
+
+    #[test]
+    fn sync_folders_are_recognized_by_their_default_names() {
+        let cases = [
+            ("/Users/ada/Dropbox/app", Some("Dropbox")),
+            ("/Users/ada/Dropbox (Contoso)/app", Some("Dropbox")),
+            (
+                "/Users/ada/Library/CloudStorage/Dropbox-Personal/app",
+                Some("Dropbox"),
+            ),
+            ("/Users/ada/OneDrive - Contoso/app", Some("OneDrive")),
+            (
+                "/Users/ada/Library/CloudStorage/OneDrive-Personal",
+                Some("OneDrive"),
+            ),
+            (
+                "/Users/ada/Library/Mobile Documents/com~apple~CloudDocs/app",
+                Some("iCloud Drive"),
+            ),
+            ("/Users/ada/iCloud Drive/app", Some("iCloud Drive")),
+            (
+                "/Users/ada/Library/CloudStorage/GoogleDrive-ada@example.com/app",
+                Some("Google Drive"),
+            ),
+            ("/Volumes/Google Drive/My Drive/app", Some("Google Drive")),
+            ("/home/ada/Box Sync/app", Some("Box")),
+            ("/home/ada/Nextcloud/app", Some("Nextcloud")),
+            ("/home/ada/SynologyDrive/app", Some("Synology Drive")),
+            // Names must match exactly or by the documented prefix
+            ("/home/ada/dropbox/app", None),
+            ("/home/ada/MyDropbox/app", None),
+            ("/home/ada/Boxes/app", None),
+            ("/home/ada/src/app", None),
+        ];
+        for (path, provider) in cases {
+            assert_eq!(
+                cloud_provider_from_path(Path::new(path)),
+                provider,
+                "{path}"
+            );
+        }
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn statfs_magic_numbers_map_to_network_filesystems() {
+        assert_eq!(network_fs_from_magic(0x6969), Some("nfs"));
+        assert_eq!(network_fs_from_magic(0xFF53_4D42), Some("cifs"));
+        assert_eq!(network_fs_from_magic(0xFE53_4D42), Some("smb2"));
+        assert_eq!(network_fs_from_magic(0x0102_1997), Some("9p"));
+        // ext4, btrfs, tmpfs
+        assert_eq!(network_fs_from_magic(0xEF53), None);
+        assert_eq!(network_fs_from_magic(0x9123_683E), None);
+        assert_eq!(network_fs_from_magic(0x0102_1994), None);
+        // FUSE needs the mount table
+        assert_eq!(network_fs_from_magic(FUSE_SUPER_MAGIC), None);
+    }
+
+    #[cfg(any(target_os = "linux", target_os = "macos"))]
+    #[test]
+    fn filesystem_type_names_map_to_network_filesystems() {
+        assert_eq!(network_fs_from_name("nfs4"), Some("nfs4"));
+        assert_eq!(network_fs_from_name("smbfs"), Some("smbfs"));
+        assert_eq!(network_fs_from_name("fuse.sshfs"), Some("sshfs"));
+        assert_eq!(network_fs_from_name("fuse.rclone"), Some("rclone"));
+        assert_eq!(network_fs_from_name("NFS"), Some("nfs"));
+        assert_eq!(network_fs_from_name("apfs"), None);
+        assert_eq!(network_fs_from_name("fuse.gocryptfs"), None);
+        assert_eq!(network_fs_from_name("fuseblk"), None);
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn fuse_mounts_are_resolved_from_the_innermost_mount_point() {
+        let mounts = "\
+/dev/sda1 / ext4 rw,relatime 0 0
+user@host:/srv /home/ada/remote fuse.sshfs rw,nosuid 0 0
+remote: /home/ada/remote/cloud\\040drive fuse.rclone rw 0 0
+";
+        let mount = |path: &str| mount_type(mounts, Path::new(path));
+
+        assert_eq!(mount("/home/ada/src").as_deref(), Some("ext4"));
+        assert_eq!(mount("/home/ada/remote/app").as_deref(), Some("fuse.sshfs"));
+        assert_eq!(
+            mount("/home/ada/remote/cloud drive/app").as_deref(),
+            Some("fuse.rclone")
+        );
+        // Component-wise, so a sibling sharing the prefix is not inside the mount
+        assert_eq!(mount("/home/ada/remote2").as_deref(), Some("ext4"));
+        assert_eq!(mount_type("", Path::new("/home")), None);
+    }
+
+    #[test]
+    fn local_folders_need_no_warning() {
+        let dir = TempDir::new();
+        let info = classify(&dir.0);
+
+        assert_eq!(info.path, dir.0.to_string_lossy());
+        assert_eq!(info.cloud_sync, None);
+        // The temp dir itself may be a network mount on some machines
+        assert_eq!(info.warnings.len(), usize::from(info.network_fs.is_some()));
+    }
+
+    #[test]
+    fn synced_folders_come_with_advice() {
+        let dir = TempDir::new();
+        let project = dir.0.join("Dropbox").join("app");
+        std::fs::create_dir_all(&project).unwrap();
+
+        let info = classify(&project);
+        assert_eq!(info.cloud_sync.as_deref(), Some("Dropbox"));
+        assert!(info.warnings[0].contains("synced by Dropbox"));
+    }
+
+    #[test]
+    fn case_folding_is_probed_without_writing() {
+        let dir = TempDir::new();
+        let project = dir.0.join("project");
+        std::fs::create_dir(&project).unwrap();
+
+        let folds = Path::new(&project.to_string_lossy().to_uppercase()).exists();
+        assert_eq!(probe_case_insensitive(&project), Some(folds));
+        assert_eq!(std::fs::read_dir(&project).unwrap().count(), 0);
+
+        // Nothing with letters to flip
+        assert_eq!(probe_case_insensitive(Path::new("/")), None);
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn distinct_case_variants_defer_to_the_parent() {
+        let dir = TempDir::new();
+        let lower = dir.0.join("abc").join("123");
+        std::fs::create_dir_all(&lower).unwrap();
+        std::fs::create_dir(dir.0.join("ABC")).unwrap();
+
+        // "abc" and "ABC" are separate entries, so the probe moves up to the
+        // temp dir, whose flipped name does not exist
+        assert_eq!(probe_case_insensitive(&lower), Some(false));
+    }
+}
@@ -0,0 +1,437 @@
+use std::path::{Path, PathBuf};
+
+use log::{debug, info, warn};
+use serde::Serialize;
+use serde_json::{json, Map, Value};
+use tauri::{AppHandle, Emitter};
+
+use crate::{project_state::ProjectStateStore, SettingsStore};
+
+/// Emitted when a missing workspace was found under a new name next to where it was
+pub const RELINK_SUGGESTED_EVENT: &str = "openchamber:workspace-relink-suggested";
+
+/// Settings key mapping workspace paths to their fingerprint
+const WORKSPACE_IDS_KEY: &str = "workspaceIds";
+/// Sibling directories looked at when searching for a moved workspace
+const MAX_SIBLINGS_SCANNED: usize = 500;
+
+/// Settings keys holding one directory path
+const PATH_KEYS: &[&str] = &["lastDirectory", "homeDirectory"];
+/// Settings keys holding a list of directory paths
+const PATH_LIST_KEYS: &[&str] = &["approvedDirectories", "pinnedDirectories"];
+/// Settings keys holding an object keyed by directory path
+const PATH_MAP_KEYS: &[&str] = &["opencodePorts", "workspaceTrust", WORKSPACE_IDS_KEY];
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RelinkResult {
+    pub old_path: String,
+    pub new_path: String,
+    /// Settings keys that referenced the old path
+    pub changed_keys: Vec<String>,
+    /// Per-project state was copied because the old folder still exists
+    pub state_copied: bool,
+    /// The old path was the active workspace, so the app switched to the new one
+    pub switched: bool,
+}
+
+/// `value` with the `old` prefix replaced by `new` when it is `old` or inside it
+pub fn relocate(value: &str, old: &Path, new: &Path) -> Option<String> {
+    let rest = Path::new(value).strip_prefix(old).ok()?;
+    let relocated = if rest.as_os_str().is_empty() {
+        new.to_path_buf()
+    } else {
+        new.join(rest)
+    };
+    Some(relocated.to_string_lossy().to_string())
+}
+
+fn relocate_list(list: &mut Vec<Value>, old: &Path, new: &Path) -> bool {
+    let mut changed = false;
+    let mut relocated: Vec<Value> = Vec::with_capacity(list.len());
+    for item in list.drain(..) {
+        let item = match item.as_str().and_then(|path| relocate(path, old, new)) {
+            Some(path) => {
+                changed = true;
+                Value::String(path)
+            }
+            None => item,
+        };
+        if !relocated.contains(&item) {
+            relocated.push(item);
+        }
+    }
+    *list = relocated;
+    changed
+}
+
+fn relocate_map(map: &mut Map<String, Value>, old: &Path, new: &Path) -> bool {
+    let moved: Vec<(String, String)> = map
+        .keys()
+        .filter_map(|key| relocate(key, old, new).map(|relocated| (key.clone(), relocated)))
+        .collect();
+    for (key, relocated) in &moved {
+        if let Some(value) = map.remove(key) {
+            // The moved entry wins over one created for the new path meanwhile
+            map.insert(relocated.clone(), value);
+        }
+    }
+    !moved.is_empty()
+}
+
+/// Rewrite every settings reference to `old` (or a folder inside it) to point
+/// at `new`. Returns the keys that changed. This is the one place that knows
+/// which settings hold paths; new path-bearing keys belong in the lists above.
+pub fn rewrite_settings(settings: &mut Value, old: &Path, new: &Path) -> Vec<String> {
+    let Some(object) = settings.as_object_mut() else {
+        return Vec::new();
+    };
+    let mut changed = Vec::new();
+
+    for key in PATH_KEYS {
+        if let Some(value) = object.get_mut(*key) {
+            if let Some(relocated) = value.as_str().and_then(|path| relocate(path, old, new)) {
+                *value = Value::String(relocated);
+                changed.push(key.to_string());
+            }
+        }
+    }
+    for key in PATH_LIST_KEYS {
+        if let Some(Value::Array(list)) = object.get_mut(*key) {
+            if relocate_list(list, old, new) {
+                changed.push(key.to_string());
+            }
+        }
+    }
+    for key in PATH_MAP_KEYS {
+        if let Some(Value::Object(map)) = object.get_mut(*key) {
+            if relocate_map(map, old, new) {
+                changed.push(key.to_string());
+            }
+        }
+    }
+
+    changed
+}
+
+/// Whether `lastDirectory` points at `path`
+pub fn is_last_directory(settings: &Value, path: &Path) -> bool {
+    settings
+        .get("lastDirectory")
+        .and_then(Value::as_str)
+        .is_some_and(|last| Path::new(last) == path)
+}
+
+fn workspace_id(settings: &Value, path: &Path) -> Option<String> {
+    settings
+        .get(WORKSPACE_IDS_KEY)?
+        .get(path.to_string_lossy().as_ref())?
+        .as_str()
+        .map(str::to_string)
+}
+
+/// Give an activated workspace a fingerprint and remember it, so the folder
+/// can be recognized after a rename
+pub async fn remember_workspace(
+    store: &SettingsStore,
+    project_state: &ProjectStateStore,
+    path: &Path,
+) {
+    let id = match project_state.ensure_fingerprint(path).await {
+        Ok(Some(id)) => id,
+        Ok(None) => return,
+        Err(err) => {
+            debug!(
+                "[desktop:relink] Could not fingerprint {}: {}",
+                path.display(),
+                err
+            );
+            return;
+        }
+    };
+    let Ok(mut settings) = store.load().await else {
+        return;
+    };
+    if workspace_id(&settings, path).as_deref() == Some(id.as_str()) {
+        return;
+    }
+    let Some(object) = settings.as_object_mut() else {
+        return;
+    };
+    let ids = object
+        .entry(WORKSPACE_IDS_KEY.to_string())
+        .or_insert_with(|| Value::Object(Map::new()));
+    if !ids.is_object() {
+        *ids = Value::Object(Map::new());
+    }
+    if let Some(ids) = ids.as_object_mut() {
+        ids.insert(path.to_string_lossy().to_string(), Value::String(id));
+    }
+    if let Err(err) = store.save(settings).await {
+        warn!("[desktop:relink] Failed to remember workspace id: {}", err);
+    }
+}
+
+/// Sibling of the missing `path` carrying the fingerprint recorded for it
+pub async fn find_moved_workspace(settings: &Value, path: &Path) -> Option<PathBuf> {
+    let id = workspace_id(settings, path)?;
+    let parent = path.parent()?;
+    let mut entries = tokio::fs::read_dir(parent).await.ok()?;
+    let mut scanned = 0;
+    while let Ok(Some(entry)) = entries.next_entry().await {
+        scanned += 1;
+        if scanned > MAX_SIBLINGS_SCANNED {
+            break;
+        }
+        let candidate = entry.path();
+        if candidate == path || !candidate.is_dir() {
+            continue;
+        }
+        if ProjectStateStore::read_fingerprint(&candidate)
+            .await
+            .as_deref()
+            == Some(id.as_str())
+        {
+            return Some(candidate);
+        }
+    }
+    None
+}
+
+/// After activating `path` failed because it no longer exists, look for the
+/// folder under a new name and suggest relinking it
+pub fn suggest_relink(app: &AppHandle, store: &SettingsStore, path: &Path) {
+    let app = app.clone();
+    let store = store.clone();
+    let path = path.to_path_buf();
+    tauri::async_runtime::spawn(async move {
+        let Ok(settings) = store.load().await else {
+            return;
+        };
+        let Some(moved) = find_moved_workspace(&settings, &path).await else {
+            return;
+        };
+        info!(
+            "[desktop:relink] {} appears to have moved to {}",
+            path.display(),
+            moved.display()
+        );
+        let _ = app.emit(
+            RELINK_SUGGESTED_EVENT,
+            json!({
+                "oldPath": path.to_string_lossy(),
+                "newPath": moved.to_string_lossy(),
+            }),
+        );
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const OLD: &str = "/home/ada/app";
+    const NEW: &str = "/home/ada/app-renamed";
+
+    /// A scratch directory under the system temp dir, removed on drop
+    struct TempDir(PathBuf);
+
+    impl TempDir {
+        fn new() -> Self {
+            let dir =
+                std::env::temp_dir().join(format!("openchamber-relink-{}", uuid::Uuid::new_v4()));
+            std::fs::create_dir_all(&dir).unwrap();
+            Self(dir)
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            std::fs::remove_dir_all(&self.0).ok();
+        }
+    }
+
+    fn rewrite(settings: &mut Value) -> Vec<String> {
+        rewrite_settings(settings, Path::new(OLD), Path::new(NEW))
+    }
+
+    #[test]
+    fn paths_inside_the_old_folder_are_relocated() {
+        let relocate = |value: &str| relocate(value, Path::new(OLD), Path::new(NEW));
+
+        assert_eq!(relocate(OLD).as_deref(), Some(NEW));
+        assert_eq!(relocate("/home/ada/app/").as_deref(), Some(NEW));
+        assert_eq!(
+            relocate("/home/ada/app/packages/web").as_deref(),
+            Some("/home/ada/app-renamed/packages/web")
+        );
+        // Siblings sharing the name as a prefix are different folders
+        assert_eq!(relocate("/home/ada/app2"), None);
+        assert_eq!(relocate("/home/ada"), None);
+        assert_eq!(relocate("relative/app"), None);
+    }
+
+    #[test]
+    fn every_path_bearing_key_is_rewritten() {
+        let inside = format!("{OLD}/sub");
+        let mut settings = Value::Object(Map::new());
+        let object = settings.as_object_mut().unwrap();
+        for key in PATH_KEYS {
+            object.insert(key.to_string(), json!(OLD));
+        }
+        for key in PATH_LIST_KEYS {
+            object.insert(key.to_string(), json!(["/elsewhere", OLD, inside]));
+        }
+        for key in PATH_MAP_KEYS {
+            object.insert(key.to_string(), json!({ OLD: 1, inside.clone(): 2 }));
+        }
+
+        let mut changed = rewrite(&mut settings);
+        changed.sort();
+        let mut expected: Vec<&str> = PATH_KEYS
+            .iter()
+            .chain(PATH_LIST_KEYS)
+            .chain(PATH_MAP_KEYS)
+            .copied()
+            .collect();
+        expected.sort();
+        assert_eq!(changed, expected);
+
+        // Nothing references the old folder anymore
+        let serialized = settings.to_string();
+        assert!(!serialized.contains(&format!("\"{OLD}\"")), "{serialized}");
+        assert!(!serialized.contains(&format!("\"{OLD}/")), "{serialized}");
+    }
+
+    #[test]
+    fn rewritten_settings_keep_their_shape() {
+        let mut settings = json!({
+            "lastDirectory": OLD,
+            "homeDirectory": "/home/ada",
+            "approvedDirectories": ["/srv", OLD, NEW, 7],
+            "pinnedDirectories": [format!("{OLD}/web")],
+            "opencodePorts": { OLD: { "port": 4100, "prefix": "/api" }, "/srv": { "port": 4200 } },
+            "workspaceTrust": { "/home/ada": true, NEW: false, OLD: true },
+            "workspaceIds": "not a map",
+            "theme": "dark",
+        });
+
+        let changed = rewrite(&mut settings);
+        assert_eq!(
+            changed,
+            vec![
+                "lastDirectory",
+                "approvedDirectories",
+                "pinnedDirectories",
+                "opencodePorts",
+                "workspaceTrust",
+            ]
+        );
+        assert_eq!(
+            settings,
+            json!({
+                "lastDirectory": NEW,
+                "homeDirectory": "/home/ada",
+                // The relocated entry collapses into the existing one
+                "approvedDirectories": ["/srv", NEW, 7],
+                "pinnedDirectories": [format!("{NEW}/web")],
+                "opencodePorts": { NEW: { "port": 4100, "prefix": "/api" }, "/srv": { "port": 4200 } },
+                // The moved decision wins over one made for the new path meanwhile
+                "workspaceTrust": { "/home/ada": true, NEW: true },
+                "workspaceIds": "not a map",
+                "theme": "dark",
+            })
+        );
+    }
+
+    #[test]
+    fn unrelated_settings_are_untouched() {
+        let original = json!({
+            "lastDirectory": "/home/ada/app2",
+            "approvedDirectories": ["/srv"],
+            "opencodePorts": { "/srv": 4200 },
+        });
+        let mut settings = original.clone();
+        assert!(rewrite(&mut settings).is_empty());
+        assert_eq!(settings, original);
+
+        let mut not_an_object = json!([OLD]);
+        assert!(rewrite(&mut not_an_object).is_empty());
+    }
+
+    #[test]
+    fn the_active_workspace_is_recognized() {
+        let settings = json!({ "lastDirectory": OLD });
+        assert!(is_last_directory(&settings, Path::new(OLD)));
+        assert!(!is_last_directory(&settings, Path::new(NEW)));
+        assert!(!is_last_directory(&json!({}), Path::new(OLD)));
+    }
+
+    #[tokio::test]
+    async fn activated_workspaces_are_remembered_by_fingerprint() {
+        let dir = TempDir::new();
+        let workspace = dir.0.join("app");
+        std::fs::create_dir(&workspace).unwrap();
+        let store = SettingsStore::at(dir.0.join("settings.json"));
+        let project_state = ProjectStateStore::default();
+
+        remember_workspace(&store, &project_state, &workspace).await;
+        let settings = store.load().await.unwrap();
+        let id = ProjectStateStore::read_fingerprint(&workspace).await;
+        assert!(id.is_some());
+        assert_eq!(workspace_id(&settings, &workspace), id);
+
+        // Missing folders are not fingerprinted
+        remember_workspace(&store, &project_state, &dir.0.join("missing")).await;
+        assert_eq!(store.load().await.unwrap(), settings);
+    }
+
+    #[tokio::test]
+    async fn moved_workspaces_are_found_among_their_siblings() {
+        let dir = TempDir::new();
+        let workspace = dir.0.join("app");
+        std::fs::create_dir(&workspace).unwrap();
+        std::fs::create_dir(dir.0.join("other")).unwrap();
+        std::fs::write(dir.0.join("notes.txt"), "").unwrap();
+        let store = SettingsStore::at(dir.0.join("settings.json"));
+        let project_state = ProjectStateStore::default();
+        project_state
+            .ensure_fingerprint(&dir.0.join("other"))
+            .await
+            .unwrap();
+        remember_workspace(&store, &project_state, &workspace).await;
+        let settings = store.load().await.unwrap();
+
+        let renamed = dir.0.join("app-renamed");
+        std::fs::rename(&workspace, &renamed).unwrap();
+        assert_eq!(
+            find_moved_workspace(&settings, &workspace).await,
+            Some(renamed)
+        );
+
+        // Unknown workspaces have no fingerprint to look for
+        assert_eq!(
+            find_moved_workspace(&settings, &dir.0.join("other-missing")).await,
+            None
+        );
+    }
+
+    #[tokio::test]
+    async fn deleted_workspaces_have_no_match() {
+        let dir = TempDir::new();
+        let workspace = dir.0.join("app");
+        std::fs::create_dir(&workspace).unwrap();
+        let store = SettingsStore::at(dir.0.join("settings.json"));
+        let project_state = ProjectStateStore::default();
+        remember_workspace(&store, &project_state, &workspace).await;
+        let settings = store.load().await.unwrap();
+
+        std::fs::remove_dir_all(&workspace).unwrap();
+        std::fs::create_dir(dir.0.join("app-new")).unwrap();
+        project_state
+            .ensure_fingerprint(&dir.0.join("app-new"))
+            .await
+            .unwrap();
+        assert_eq!(find_moved_workspace(&settings, &workspace).await, None);
+    }
+}
@@ -0,0 +1,1283 @@
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::{anyhow, Context, Result};
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tokio::process::Command;
+
+use crate::commands::files::should_skip_directory;
+
+/// Hidden refs holding git snapshots; not branches, so they stay out of logs and pushes
+const SNAPSHOT_REF_PREFIX: &str = "refs/openchamber/snapshots/";
+const SNAPSHOT_MESSAGE_PREFIX: &str = "OpenChamber snapshot";
+/// Copy snapshots of non-git workspaces, one directory per snapshot
+const COPY_SNAPSHOTS_DIR: &str = ".openchamber/snapshots";
+const MANIFEST_FILE: &str = "manifest.json";
+const COPY_FILES_DIR: &str = "files";
+const DEFAULT_MAX_AGE_DAYS: u64 = 14;
+/// Copy snapshots refuse workspaces bigger than this rather than filling the disk
+const MAX_COPY_FILES: usize = 20_000;
+const MAX_COPY_BYTES: u64 = 512 * 1024 * 1024;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum SnapshotKind {
+    /// Commit on a hidden ref; the index and HEAD are untouched
+    Git,
+    /// Files copied under .openchamber/snapshots
+    Copy,
+}
+
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SnapshotInfo {
+    pub id: String,
+    pub label: String,
+    /// Unix time in milliseconds
+    pub created_at: u64,
+    pub kind: SnapshotKind,
+    /// Files that differed from HEAD (git) or were captured (copy)
+    pub file_count: usize,
+}
+
+/// A file changed since the snapshot was taken, left alone unless forced
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RestoreConflict {
+    pub path: String,
+    /// Unix time in milliseconds
+    pub modified_at: u64,
+}
+
+#[derive(Clone, Debug, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RestoreResult {
+    /// False when conflicts stopped the restore; nothing was written then
+    pub applied: bool,
+    pub restored: Vec<String>,
+    pub conflicts: Vec<RestoreConflict>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ManifestEntry {
+    path: String,
+    size: u64,
+    modified_at: u64,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Manifest {
+    id: String,
+    label: String,
+    created_at: u64,
+    files: Vec<ManifestEntry>,
+}
+
+/// `snapshots.maxAgeDays`: snapshots older than this are pruned; 0 keeps them
+pub fn max_age(settings: &Value) -> Option<Duration> {
+    let days = settings
+        .get("snapshots")
+        .and_then(|snapshots| snapshots.get("maxAgeDays"))
+        .and_then(Value::as_u64)
+        .unwrap_or(DEFAULT_MAX_AGE_DAYS);
+    (days > 0).then(|| Duration::from_secs(days * 24 * 60 * 60))
+}
+
+fn now_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_millis() as u64)
+        .unwrap_or(0)
+}
+
+fn modified_ms(metadata: &std::fs::Metadata) -> u64 {
+    metadata
+        .modified()
+        .ok()
+        .and_then(|modified| modified.duration_since(UNIX_EPOCH).ok())
+        .map(|elapsed| elapsed.as_millis() as u64)
+        .unwrap_or(0)
+}
+
+/// Snapshot ids are creation timestamps; anything else could escape the snapshot directory
+fn validate_id(id: &str) -> Result<u64> {
+    if id.is_empty() || !id.bytes().all(|byte| byte.is_ascii_digit()) {
+        return Err(anyhow!("Invalid snapshot id: {}", id));
+    }
+    id.parse()
+        .map_err(|_| anyhow!("Invalid snapshot id: {}", id))
+}
+
+/// Whether `path` is `filter` or inside it; no filters selects everything
+fn selected(path: &str, filters: &[String]) -> bool {
+    filters.is_empty()
+        || filters.iter().any(|filter| {
+            path == filter
+                || path
+                    .strip_prefix(filter.as_str())
+                    .is_some_and(|rest| rest.starts_with('/'))
+        })
+}
+
+fn normalize_filters(paths: Option<Vec<String>>) -> Vec<String> {
+    paths
+        .unwrap_or_default()
+        .into_iter()
+        .map(|path| {
+            path.trim()
+                .trim_start_matches("./")
+                .trim_end_matches('/')
+                .replace('\\', "/")
+        })
+        .filter(|path| !path.is_empty())
+        .collect()
+}
+
+/// Take a snapshot of `workspace`, then prune ones older than `max_age`
+pub async fn create(
+    workspace: &Path,
+    label: &str,
+    max_age: Option<Duration>,
+) -> Result<SnapshotInfo> {
+    let info = if is_git_workspace(workspace).await {
+        git::create(workspace, label).await?
+    } else {
+        let workspace = workspace.to_path_buf();
+        let label = label.to_string();
+        tokio::task::spawn_blocking(move || copy::create(&workspace, &label)).await??
+    };
+    info!(
+        "[desktop:snapshots] Created {:?} snapshot {} ({} files)",
+        info.kind, info.id, info.file_count
+    );
+    prune(workspace, max_age).await;
+    Ok(info)
+}
+
+/// Snapshots of `workspace`, newest first
+pub async fn list(workspace: &Path) -> Result<Vec<SnapshotInfo>> {
+    let mut snapshots = Vec::new();
+    if is_git_workspace(workspace).await {
+        snapshots.extend(git::list(workspace).await?);
+    }
+    let workspace = workspace.to_path_buf();
+    snapshots.extend(tokio::task::spawn_blocking(move || copy::list(&workspace)).await??);
+    snapshots.sort_by_key(|snapshot| std::cmp::Reverse(snapshot.created_at));
+    Ok(snapshots)
+}
+
+/// Restore `paths` (files or folders, workspace-relative) from snapshot `id`,
+/// or every file when empty. Files modified after the snapshot are reported
+/// as conflicts and nothing is written unless `force` is set.
+pub async fn restore(
+    workspace: &Path,
+    id: &str,
+    paths: Option<Vec<String>>,
+    force: bool,
+) -> Result<RestoreResult> {
+    let created_at = validate_id(id)?;
+    let filters = normalize_filters(paths);
+    let result = if git::exists(workspace, id).await {
+        git::restore(workspace, id, created_at, &filters, force).await?
+    } else {
+        let workspace = workspace.to_path_buf();
+        let id = id.to_string();
+        tokio::task::spawn_blocking(move || copy::restore(&workspace, &id, &filters, force))
+            .await??
+    };
+    if result.applied {
+        info!(
+            "[desktop:snapshots] Restored {} file(s) from snapshot {}",
+            result.restored.len(),
+            id
+        );
+    }
+    Ok(result)
+}
+
+pub async fn delete(workspace: &Path, id: &str) -> Result<()> {
+    validate_id(id)?;
+    if git::exists(workspace, id).await {
+        return git::delete(workspace, id).await;
+    }
+    let dir = workspace.join(COPY_SNAPSHOTS_DIR).join(id);
+    if !dir.is_dir() {
+        return Err(anyhow!("Snapshot not found: {}", id));
+    }
+    tokio::fs::remove_dir_all(&dir)
+        .await
+        .with_context(|| format!("Failed to delete snapshot {}", id))
+}
+
+/// Delete snapshots older than `max_age`; failures are logged, not returned
+pub async fn prune(workspace: &Path, max_age: Option<Duration>) {
+    let Some(max_age) = max_age else {
+        return;
+    };
+    let cutoff = now_ms().saturating_sub(max_age.as_millis() as u64);
+    let snapshots = match list(workspace).await {
+        Ok(snapshots) => snapshots,
+        Err(err) => {
+            warn!(
+                "[desktop:snapshots] Failed to list snapshots for pruning: {}",
+                err
+            );
+            return;
+        }
+    };
+    for snapshot in snapshots
+        .iter()
+        .filter(|snapshot| snapshot.created_at < cutoff)
+    {
+        match delete(workspace, &snapshot.id).await {
+            Ok(()) => info!("[desktop:snapshots] Pruned snapshot {}", snapshot.id),
+            Err(err) => warn!(
+                "[desktop:snapshots] Failed to prune snapshot {}: {}",
+                snapshot.id, err
+            ),
+        }
+    }
+}
+
+async fn is_git_workspace(workspace: &Path) -> bool {
+    git::text(workspace, &["rev-parse", "--is-inside-work-tree"], None)
+        .await
+        .is_ok_and(|output| output == "true")
+}
+
+mod git {
+    use super::*;
+
+    /// Index file used instead of the real one while capturing the working tree
+    struct TempIndex(PathBuf);
+
+    impl TempIndex {
+        fn new() -> Self {
+            Self(std::env::temp_dir().join(format!(
+                "openchamber-snapshot-{}.index",
+                uuid::Uuid::new_v4()
+            )))
+        }
+    }
+
+    impl Drop for TempIndex {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_file(&self.0);
+        }
+    }
+
+    /// One path of `git diff-tree --raw` output, toward the snapshot
+    struct RawChange {
+        mode: String,
+        blob: String,
+        status: char,
+        path: String,
+    }
+
+    pub(super) async fn run(cwd: &Path, args: &[&str], index: Option<&Path>) -> Result<Vec<u8>> {
+        let mut command = Command::new("git");
+        command
+            .args(args)
+            .current_dir(cwd)
+            .env("GIT_OPTIONAL_LOCKS", "0")
+            .env("LC_ALL", "C")
+            // Snapshot commits must not depend on the user's identity being configured
+            .env("GIT_AUTHOR_NAME", "OpenChamber")
+            .env("GIT_AUTHOR_EMAIL", "snapshots@openchamber.local")
+            .env("GIT_COMMITTER_NAME", "OpenChamber")
+            .env("GIT_COMMITTER_EMAIL", "snapshots@openchamber.local");
+        if let Some(index) = index {
+            command.env("GIT_INDEX_FILE", index);
+        }
+        let output = command
+            .output()
+            .await
+            .context("Failed to execute git command")?;
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr).trim().to_string();
+            return Err(anyhow!("{}", stderr));
+        }
+        Ok(output.stdout)
+    }
+
+    pub(super) async fn text(cwd: &Path, args: &[&str], index: Option<&Path>) -> Result<String> {
+        let output = run(cwd, args, index).await?;
+        Ok(String::from_utf8_lossy(&output).trim().to_string())
+    }
+
+    fn ref_name(id: &str) -> String {
+        format!("{SNAPSHOT_REF_PREFIX}{id}")
+    }
+
+    pub(super) async fn exists(workspace: &Path, id: &str) -> bool {
+        let name = format!("{}^{{commit}}", ref_name(id));
+        text(workspace, &["rev-parse", "--verify", "-q", &name], None)
+            .await
+            .is_ok()
+    }
+
+    /// Write the working tree (tracked and untracked, minus ignored files) as
+    /// a tree object through a throwaway index. Returns it with HEAD, if any.
+    async fn capture_tree(workspace: &Path) -> Result<(String, Option<String>)> {
+        let head = text(workspace, &["rev-parse", "--verify", "-q", "HEAD"], None)
+            .await
+            .ok()
+            .filter(|head| !head.is_empty());
+        let index = TempIndex::new();
+
+        // Starting from a copy of the real index keeps staged changes and lets
+        // git reuse its stat cache instead of rehashing every file
+        let real_index =
+            workspace.join(text(workspace, &["rev-parse", "--git-path", "index"], None).await?);
+        if real_index.is_file() {
+            tokio::fs::copy(&real_index, &index.0)
+                .await
+                .context("Failed to copy the git index")?;
+        } else if let Some(head) = &head {
+            run(workspace, &["read-tree", head], Some(&index.0)).await?;
+        }
+        run(
+            workspace,
+            &["add", "-A", "--", ".", ":(exclude).openchamber"],
+            Some(&index.0),
+        )
+        .await?;
+        let tree = text(workspace, &["write-tree"], Some(&index.0)).await?;
+        Ok((tree, head))
+    }
+
+    async fn changed_paths(workspace: &Path, commit: &str) -> Result<Vec<String>> {
+        let output = run(
+            workspace,
+            &[
+                "diff-tree",
+                "-r",
+                "-z",
+                "--name-only",
+                "--no-commit-id",
+                "--relative",
+                "--root",
+                commit,
+            ],
+            None,
+        )
+        .await?;
+        Ok(output
+            .split(|byte| *byte == 0)
+            .filter(|path| !path.is_empty())
+            .map(|path| String::from_utf8_lossy(path).to_string())
+            .collect())
+    }
+
+    pub(super) async fn create(workspace: &Path, label: &str) -> Result<SnapshotInfo> {
+        let (tree, head) = capture_tree(workspace).await?;
+        let created_at = now_ms();
+        let id = created_at.to_string();
+        let message = if label.is_empty() {
+            SNAPSHOT_MESSAGE_PREFIX.to_string()
+        } else {
+            format!("{SNAPSHOT_MESSAGE_PREFIX}: {label}")
+        };
+
+        let mut args = vec!["commit-tree", tree.as_str(), "-m", message.as_str()];
+        if let Some(head) = &head {
+            args.extend(["-p", head.as_str()]);
+        }
+        let commit = text(workspace, &args, None).await?;
+        // An empty old value makes update-ref fail instead of replacing an existing snapshot
+        run(
+            workspace,
+            &["update-ref", &ref_name(&id), &commit, ""],
+            None,
+        )
+        .await?;
+
+        Ok(SnapshotInfo {
+            file_count: changed_paths(workspace, &commit).await?.len(),
+            id,
+            label: label.to_string(),
+            created_at,
+            kind: SnapshotKind::Git,
+        })
+    }
+
+    pub(super) async fn list(workspace: &Path) -> Result<Vec<SnapshotInfo>> {
+        let output = text(
+            workspace,
+            &[
+                "for-each-ref",
+                "--format=%(refname)%00%(objectname)%00%(contents:subject)",
+                SNAPSHOT_REF_PREFIX,
+            ],
+            None,
+        )
+        .await?;
+
+        let mut snapshots = Vec::new();
+        for line in output.lines() {
+            let mut fields = line.split('\0');
+            let (Some(name), Some(commit), subject) = (fields.next(), fields.next(), fields.next())
+            else {
+                continue;
+            };
+            let Some(id) = name.strip_prefix(SNAPSHOT_REF_PREFIX) else {
+                continue;
+            };
+            let Ok(created_at) = validate_id(id) else {
+                continue;
+            };
+            let label = subject
+                .unwrap_or_default()
+                .strip_prefix(SNAPSHOT_MESSAGE_PREFIX)
+                .map(|rest| rest.trim_start_matches(':').trim())
+                .unwrap_or_default()
+                .to_string();
+            snapshots.push(SnapshotInfo {
+                id: id.to_string(),
+                label,
+                created_at,
+                kind: SnapshotKind::Git,
+                file_count: changed_paths(workspace, commit).await?.len(),
+            });
+        }
+        Ok(snapshots)
+    }
+
+    /// Paths whose current content differs from the snapshot, with the
+    /// snapshot's mode and blob. Files created after the snapshot are not
+    /// included; restoring never deletes.
+    async fn differences(workspace: &Path, commit: &str) -> Result<Vec<RawChange>> {
+        let (current, _) = capture_tree(workspace).await?;
+        let output = run(
+            workspace,
+            &["diff-tree", "-r", "-z", "--relative", &current, commit],
+            None,
+        )
+        .await?;
+
+        // Raw records are ":<old mode> <new mode> <old blob> <new blob> <status>\0<path>\0"
+        let mut changes = Vec::new();
+        let mut fields = output.split(|byte| *byte == 0);
+        while let (Some(meta), Some(path)) = (fields.next(), fields.next()) {
+            let meta = String::from_utf8_lossy(meta);
+            let parts: Vec<&str> = meta.trim_start_matches(':').split(' ').collect();
+            let [_, mode, _, blob, status] = parts.as_slice() else {
+                continue;
+            };
+            let status = status.chars().next().unwrap_or('?');
+            if status == 'D' {
+                continue;
+            }
+            changes.push(RawChange {
+                mode: mode.to_string(),
+                blob: blob.to_string(),
+                status,
+                path: String::from_utf8_lossy(path).to_string(),
+            });
+        }
+        Ok(changes)
+    }
+
+    pub(super) async fn restore(
+        workspace: &Path,
+        id: &str,
+        created_at: u64,
+        filters: &[String],
+        force: bool,
+    ) -> Result<RestoreResult> {
+        let commit = text(
+            workspace,
+            &[
+                "rev-parse",
+                "--verify",
+                &format!("{}^{{commit}}", ref_name(id)),
+            ],
+            None,
+        )
+        .await?;
+        let changes: Vec<RawChange> = differences(workspace, &commit)
+            .await?
+            .into_iter()
+            .filter(|change| selected(&change.path, filters))
+            .collect();
+
+        let conflicts: Vec<RestoreConflict> = changes
+            .iter()
+            .filter(|change| change.status != 'A')
+            .filter_map(|change| {
+                let metadata = std::fs::symlink_metadata(workspace.join(&change.path)).ok()?;
+                let modified_at = modified_ms(&metadata);
+                (modified_at > created_at).then(|| RestoreConflict {
+                    path: change.path.clone(),
+                    modified_at,
+                })
+            })
+            .collect();
+        if !conflicts.is_empty() && !force {
+            return Ok(RestoreResult {
+                applied: false,
+                restored: Vec::new(),
+                conflicts,
+            });
+        }
+
+        let mut restored = Vec::new();
+        for change in changes {
+            // Submodule entries have no content to restore
+            if change.mode == "160000" {
+                continue;
+            }
+            let content = run(workspace, &["cat-file", "blob", &change.blob], None).await?;
+            write_entry(&workspace.join(&change.path), &change.mode, &content)
+                .await
+                .with_context(|| format!("Failed to restore {}", change.path))?;
+            restored.push(change.path);
+        }
+        Ok(RestoreResult {
+            applied: true,
+            restored,
+            conflicts,
+        })
+    }
+
+    async fn write_entry(target: &Path, mode: &str, content: &[u8]) -> Result<()> {
+        if let Some(parent) = target.parent() {
+            tokio::fs::create_dir_all(parent).await?;
+        }
+        if let Ok(metadata) = tokio::fs::symlink_metadata(target).await {
+            if metadata.is_dir() {
+                return Err(anyhow!("a directory is in the way"));
+            }
+            if metadata.file_type().is_symlink() || mode == "120000" {
+                tokio::fs::remove_file(target).await?;
+            }
+        }
+
+        #[cfg(unix)]
+        if mode == "120000" {
+            let link = PathBuf::from(String::from_utf8_lossy(content).to_string());
+            tokio::fs::symlink(link, target).await?;
+            return Ok(());
+        }
+
+        tokio::fs::write(target, content).await?;
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let permissions = if mode == "100755" { 0o755 } else { 0o644 };
+            tokio::fs::set_permissions(target, std::fs::Permissions::from_mode(permissions))
+                .await?;
+        }
+        Ok(())
+    }
+
+    pub(super) async fn delete(workspace: &Path, id: &str) -> Result<()> {
+        run(workspace, &["update-ref", "-d", &ref_name(id)], None)
+            .await
+            .map(|_| ())
+    }
+}
+
+mod copy {
+    use super::*;
+
+    fn skip_directory(name: &str) -> bool {
+        name == ".git"
+            || name == ".openchamber"
+            || (!name.starts_with('.') && should_skip_directory(name))
+    }
+
+    fn snapshots_root(workspace: &Path) -> PathBuf {
+        workspace.join(COPY_SNAPSHOTS_DIR)
+    }
+
+    fn read_manifest(dir: &Path) -> Option<Manifest> {
+        let raw = std::fs::read_to_string(dir.join(MANIFEST_FILE)).ok()?;
+        serde_json::from_str(&raw).ok()
+    }
+
+    fn manifests(workspace: &Path) -> Vec<(PathBuf, Manifest)> {
+        let Ok(entries) = std::fs::read_dir(snapshots_root(workspace)) else {
+            return Vec::new();
+        };
+        let mut manifests: Vec<(PathBuf, Manifest)> = entries
+            .flatten()
+            .map(|entry| entry.path())
+            .filter_map(|dir| read_manifest(&dir).map(|manifest| (dir, manifest)))
+            .collect();
+        manifests.sort_by_key(|(_, manifest)| std::cmp::Reverse(manifest.created_at));
+        manifests
+    }
+
+    fn collect_files(workspace: &Path) -> Result<Vec<ManifestEntry>> {
+        let mut files = Vec::new();
+        let mut total_bytes = 0u64;
+        let mut pending = vec![PathBuf::new()];
+        while let Some(relative) = pending.pop() {
+            for entry in std::fs::read_dir(workspace.join(&relative))? {
+                let entry = entry?;
+                let file_type = entry.file_type()?;
+                let name = entry.file_name();
+                let path = relative.join(&name);
+                if file_type.is_dir() {
+                    if !skip_directory(&name.to_string_lossy()) {
+                        pending.push(path);
+                    }
+                    continue;
+                }
+                if !file_type.is_file() {
+                    continue;
+                }
+                let metadata = entry.metadata()?;
+                total_bytes += metadata.len();
+                if files.len() >= MAX_COPY_FILES || total_bytes > MAX_COPY_BYTES {
+                    return Err(anyhow!(
+                        "Workspace is too large to snapshot without git (limit {} files, {} MB)",
+                        MAX_COPY_FILES,
+                        MAX_COPY_BYTES / (1024 * 1024)
+                    ));
+                }
+                files.push(ManifestEntry {
+                    path: path.to_string_lossy().replace('\\', "/"),
+                    size: metadata.len(),
+                    modified_at: modified_ms(&metadata),
+                });
+            }
+        }
+        files.sort_by(|a, b| a.path.cmp(&b.path));
+        Ok(files)
+    }
+
+    pub(super) fn create(workspace: &Path, label: &str) -> Result<SnapshotInfo> {
+        let files = collect_files(workspace)?;
+        let created_at = now_ms();
+        let id = created_at.to_string();
+        let dir = snapshots_root(workspace).join(&id);
+        if dir.exists() {
+            return Err(anyhow!("Snapshot {} already exists", id));
+        }
+
+        // Files unchanged since the previous snapshot are hard-linked from it,
+        // so only changed files take new space
+        let previous = manifests(workspace).into_iter().next();
+        let unchanged: HashMap<&str, &ManifestEntry> = previous
+            .as_ref()
+            .map(|(_, manifest)| {
+                manifest
+                    .files
+                    .iter()
+                    .map(|entry| (entry.path.as_str(), entry))
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        let result = (|| -> Result<()> {
+            for entry in &files {
+                let target = dir.join(COPY_FILES_DIR).join(&entry.path);
+                if let Some(parent) = target.parent() {
+                    std::fs::create_dir_all(parent)?;
+                }
+                let linked = match (unchanged.get(entry.path.as_str()), &previous) {
+                    (Some(old), Some((previous_dir, _)))
+                        if old.size == entry.size && old.modified_at == entry.modified_at =>
+                    {
+                        std::fs::hard_link(
+                            previous_dir.join(COPY_FILES_DIR).join(&entry.path),
+                            &target,
+                        )
+                        .is_ok()
+                    }
+                    _ => false,
+                };
+                if !linked {
+                    std::fs::copy(workspace.join(&entry.path), &target)
+                        .with_context(|| format!("Failed to copy {}", entry.path))?;
+                }
+            }
+            let manifest = Manifest {
+                id: id.clone(),
+                label: label.to_string(),
+                created_at,
+                files: files.clone(),
+            };
+            std::fs::write(
+                dir.join(MANIFEST_FILE),
+                serde_json::to_vec_pretty(&manifest)?,
+            )?;
+            Ok(())
+        })();
+        if let Err(err) = result {
+            let _ = std::fs::remove_dir_all(&dir);
+            return Err(err);
+        }
+
+        Ok(SnapshotInfo {
+            id,
+            label: label.to_string(),
+            created_at,
+            kind: SnapshotKind::Copy,
+            file_count: files.len(),
+        })
+    }
+
+    pub(super) fn list(workspace: &Path) -> Result<Vec<SnapshotInfo>> {
+        Ok(manifests(workspace)
+            .into_iter()
+            .map(|(_, manifest)| SnapshotInfo {
+                file_count: manifest.files.len(),
+                id: manifest.id,
+                label: manifest.label,
+                created_at: manifest.created_at,
+                kind: SnapshotKind::Copy,
+            })
+            .collect())
+    }
+
+    pub(super) fn restore(
+        workspace: &Path,
+        id: &str,
+        filters: &[String],
+        force: bool,
+    ) -> Result<RestoreResult> {
+        let dir = snapshots_root(workspace).join(id);
+        let manifest = read_manifest(&dir).ok_or_else(|| anyhow!("Snapshot not found: {}", id))?;
+
+        let mut pending = Vec::new();
+        let mut conflicts = Vec::new();
+        for entry in manifest
+            .files
+            .iter()
+            .filter(|entry| selected(&entry.path, filters))
+        {
+            let current = workspace.join(&entry.path);
+            if let Ok(metadata) = std::fs::metadata(&current) {
+                let modified_at = modified_ms(&metadata);
+                if metadata.len() == entry.size && modified_at == entry.modified_at {
+                    continue;
+                }
+                if modified_at > manifest.created_at {
+                    conflicts.push(RestoreConflict {
+                        path: entry.path.clone(),
+                        modified_at,
+                    });
+                }
+            }
+            pending.push(entry);
+        }
+        if !conflicts.is_empty() && !force {
+            return Ok(RestoreResult {
+                applied: false,
+                restored: Vec::new(),
+                conflicts,
+            });
+        }
+
+        let mut restored = Vec::new();
+        for entry in pending {
+            let target = workspace.join(&entry.path);
+            if let Some(parent) = target.parent() {
+                std::fs::create_dir_all(parent)?;
+            }
+            std::fs::copy(dir.join(COPY_FILES_DIR).join(&entry.path), &target)
+                .with_context(|| format!("Failed to restore {}", entry.path))?;
+            restored.push(entry.path.clone());
+        }
+        Ok(RestoreResult {
+            applied: true,
+            restored,
+            conflicts,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A workspace under the system temp dir, removed on drop
+    struct Workspace(PathBuf);
+
+    impl Workspace {
+        fn new() -> Self {
+            let root =
+                std::env::temp_dir().join(format!("openchamber-snapshot-{}", uuid::Uuid::new_v4()));
+            std::fs::create_dir_all(&root).unwrap();
+            Self(std::fs::canonicalize(root).unwrap())
+        }
+
+        /// A repository with `a.txt`, `b.txt` and `src/lib.rs` committed and
+        /// `*.log` ignored
+        fn repository() -> Self {
+            let workspace = Self::new();
+            workspace.git(&["init", "-q"]);
+            workspace.write("a.txt", "a1");
+            workspace.write("b.txt", "b1");
+            workspace.write("src/lib.rs", "lib1");
+            workspace.write(".gitignore", "*.log\n");
+            workspace.git(&["add", "-A"]);
+            workspace.git(&["commit", "-q", "-m", "init"]);
+            workspace
+        }
+
+        fn git(&self, args: &[&str]) -> String {
+            let output = std::process::Command::new("git")
+                .args(["-c", "user.name=Test", "-c", "user.email=test@example.com"])
+                .args(args)
+                .current_dir(&self.0)
+                .output()
+                .unwrap();
+            assert!(output.status.success(), "git {:?} failed", args);
+            String::from_utf8_lossy(&output.stdout).trim().to_string()
+        }
+
+        fn write(&self, relative: &str, content: &str) {
+            let path = self.0.join(relative);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, content).unwrap();
+        }
+
+        fn read(&self, relative: &str) -> Option<String> {
+            std::fs::read_to_string(self.0.join(relative)).ok()
+        }
+
+        /// Rewrite a file as if it had been changed before the snapshot was taken
+        fn write_before(&self, relative: &str, content: &str, snapshot: &SnapshotInfo) {
+            self.write(relative, content);
+            let modified = UNIX_EPOCH + Duration::from_millis(snapshot.created_at - 60_000);
+            std::fs::File::options()
+                .write(true)
+                .open(self.0.join(relative))
+                .unwrap()
+                .set_modified(modified)
+                .unwrap();
+        }
+
+        async fn snapshot(&self, label: &str) -> SnapshotInfo {
+            // Ids are creation milliseconds; keep consecutive snapshots apart
+            // and later writes strictly newer than the snapshot
+            tokio::time::sleep(Duration::from_millis(5)).await;
+            let info = create(&self.0, label, None).await.unwrap();
+            tokio::time::sleep(Duration::from_millis(5)).await;
+            info
+        }
+    }
+
+    impl Drop for Workspace {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.0);
+        }
+    }
+
+    fn paths(list: &[&str]) -> Option<Vec<String>> {
+        Some(list.iter().map(|path| path.to_string()).collect())
+    }
+
+    fn sorted(mut paths: Vec<String>) -> Vec<String> {
+        paths.sort();
+        paths
+    }
+
+    fn copy_manifest(dir: &Path) -> Manifest {
+        serde_json::from_slice(&std::fs::read(dir.join(MANIFEST_FILE)).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn max_age_defaults_and_zero_disables() {
+        let day = 24 * 60 * 60;
+        assert_eq!(
+            max_age(&serde_json::json!({})),
+            Some(Duration::from_secs(14 * day))
+        );
+        assert_eq!(
+            max_age(&serde_json::json!({ "snapshots": { "maxAgeDays": 3 } })),
+            Some(Duration::from_secs(3 * day))
+        );
+        assert_eq!(
+            max_age(&serde_json::json!({ "snapshots": { "maxAgeDays": 0 } })),
+            None
+        );
+        assert_eq!(
+            max_age(&serde_json::json!({ "snapshots": { "maxAgeDays": "soon" } })),
+            Some(Duration::from_secs(14 * day))
+        );
+    }
+
+    #[test]
+    fn ids_must_be_timestamps() {
+        assert_eq!(validate_id("1700000000000").unwrap(), 1_700_000_000_000);
+        for id in ["", "../1", "12a", "-1", "1/2", "99999999999999999999999"] {
+            assert!(validate_id(id).is_err(), "{:?} was accepted", id);
+        }
+    }
+
+    #[test]
+    fn filters_select_files_and_folders() {
+        let filters = normalize_filters(paths(&[" ./src/ ", "docs\\guide.md", "", "/"]));
+        assert_eq!(filters, vec!["src", "docs/guide.md"]);
+
+        assert!(selected("src", &filters));
+        assert!(selected("src/lib.rs", &filters));
+        assert!(selected("src/nested/mod.rs", &filters));
+        assert!(selected("docs/guide.md", &filters));
+        assert!(!selected("srcs/lib.rs", &filters));
+        assert!(!selected("docs/guide.md.bak", &filters));
+        assert!(!selected("a.txt", &filters));
+
+        assert!(normalize_filters(None).is_empty());
+        assert!(selected("anything", &[]));
+    }
+
+    #[tokio::test]
+    async fn git_snapshots_leave_head_index_and_branches_alone() {
+        let workspace = Workspace::repository();
+        workspace.write("a.txt", "a2");
+        workspace.write("b.txt", "b2");
+        workspace.git(&["add", "b.txt"]);
+        workspace.write("new.txt", "new");
+        workspace.write("debug.log", "ignored");
+        workspace.write(".openchamber/state.json", "{}");
+
+        let head = workspace.git(&["rev-parse", "HEAD"]);
+        let status = workspace.git(&["status", "--porcelain"]);
+        let staged = workspace.git(&["diff", "--cached", "--name-only"]);
+        let branches = workspace.git(&["branch", "--list"]);
+        let index = std::fs::read(workspace.0.join(".git/index")).unwrap();
+
+        let info = workspace.snapshot("before refactor").await;
+        assert_eq!(info.kind, SnapshotKind::Git);
+        assert_eq!(info.label, "before refactor");
+        assert_eq!(info.id, info.created_at.to_string());
+        assert_eq!(info.file_count, 3);
+
+        assert_eq!(workspace.git(&["rev-parse", "HEAD"]), head);
+        assert_eq!(workspace.git(&["status", "--porcelain"]), status);
+        assert_eq!(workspace.git(&["diff", "--cached", "--name-only"]), staged);
+        assert_eq!(workspace.git(&["branch", "--list"]), branches);
+        assert_eq!(
+            std::fs::read(workspace.0.join(".git/index")).unwrap(),
+            index
+        );
+        assert!(workspace.git(&["stash", "list"]).is_empty());
+
+        let name = format!("{SNAPSHOT_REF_PREFIX}{}", info.id);
+        assert_eq!(
+            workspace.git(&["for-each-ref", "--format=%(refname)"]),
+            format!("refs/heads/{}\n{}", branches.trim_start_matches("* "), name)
+        );
+        assert_eq!(workspace.git(&["rev-parse", &format!("{name}^")]), head);
+        assert_eq!(
+            workspace.git(&["log", "-1", "--format=%s", &name]),
+            "OpenChamber snapshot: before refactor"
+        );
+        let files = workspace.git(&["ls-tree", "-r", "--name-only", &name]);
+        assert_eq!(
+            files.lines().collect::<Vec<_>>(),
+            vec![".gitignore", "a.txt", "b.txt", "new.txt", "src/lib.rs"]
+        );
+        assert_eq!(workspace.git(&["show", &format!("{name}:a.txt")]), "a2");
+    }
+
+    #[tokio::test]
+    async fn git_snapshots_are_listed_newest_first() {
+        let workspace = Workspace::repository();
+        let first = workspace.snapshot("").await;
+        workspace.write("a.txt", "a2");
+        let second = workspace.snapshot("with: colon").await;
+
+        let snapshots = list(&workspace.0).await.unwrap();
+        let summary: Vec<(&str, &str, usize)> = snapshots
+            .iter()
+            .map(|snapshot| {
+                (
+                    snapshot.id.as_str(),
+                    snapshot.label.as_str(),
+                    snapshot.file_count,
+                )
+            })
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                (second.id.as_str(), "with: colon", 1),
+                (first.id.as_str(), "", 0),
+            ]
+        );
+        assert!(snapshots
+            .iter()
+            .all(|snapshot| snapshot.kind == SnapshotKind::Git));
+    }
+
+    #[tokio::test]
+    async fn git_restore_brings_back_changed_and_deleted_files() {
+        let workspace = Workspace::repository();
+        workspace.write("a.txt", "a2");
+        workspace.write("new.txt", "new");
+        let info = workspace.snapshot("").await;
+
+        workspace.write_before("a.txt", "a3", &info);
+        std::fs::remove_file(workspace.0.join("new.txt")).unwrap();
+        std::fs::remove_dir_all(workspace.0.join("src")).unwrap();
+        workspace.write_before("later.txt", "later", &info);
+
+        let result = restore(&workspace.0, &info.id, None, false).await.unwrap();
+        assert!(result.applied);
+        assert!(result.conflicts.is_empty());
+        assert_eq!(
+            sorted(result.restored),
+            vec!["a.txt", "new.txt", "src/lib.rs"]
+        );
+        assert_eq!(workspace.read("a.txt").as_deref(), Some("a2"));
+        assert_eq!(workspace.read("new.txt").as_deref(), Some("new"));
+        assert_eq!(workspace.read("src/lib.rs").as_deref(), Some("lib1"));
+        // Restoring never deletes files created after the snapshot
+        assert_eq!(workspace.read("later.txt").as_deref(), Some("later"));
+
+        let again = restore(&workspace.0, &info.id, None, false).await.unwrap();
+        assert!(again.applied);
+        assert!(again.restored.is_empty());
+    }
+
+    #[tokio::test]
+    async fn git_restore_can_be_limited_to_files_and_folders() {
+        let workspace = Workspace::repository();
+        workspace.write("src/nested/mod.rs", "mod1");
+        let info = workspace.snapshot("").await;
+
+        workspace.write_before("a.txt", "a2", &info);
+        workspace.write_before("b.txt", "b2", &info);
+        workspace.write_before("src/lib.rs", "lib2", &info);
+        workspace.write_before("src/nested/mod.rs", "mod2", &info);
+
+        let result = restore(&workspace.0, &info.id, paths(&["./src/", "b.txt"]), false)
+            .await
+            .unwrap();
+        assert!(result.applied);
+        assert_eq!(
+            sorted(result.restored),
+            vec!["b.txt", "src/lib.rs", "src/nested/mod.rs"]
+        );
+        assert_eq!(workspace.read("a.txt").as_deref(), Some("a2"));
+        assert_eq!(workspace.read("b.txt").as_deref(), Some("b1"));
+        assert_eq!(workspace.read("src/lib.rs").as_deref(), Some("lib1"));
+        assert_eq!(workspace.read("src/nested/mod.rs").as_deref(), Some("mod1"));
+    }
+
+    #[tokio::test]
+    async fn git_restore_stops_on_files_changed_since_the_snapshot() {
+        let workspace = Workspace::repository();
+        let info = workspace.snapshot("").await;
+
+        workspace.write("a.txt", "edited after");
+        workspace.write_before("b.txt", "b2", &info);
+
+        let blocked = restore(&workspace.0, &info.id, None, false).await.unwrap();
+        assert!(!blocked.applied);
+        assert!(blocked.restored.is_empty());
+        let conflicts: Vec<&str> = blocked
+            .conflicts
+            .iter()
+            .map(|conflict| conflict.path.as_str())
+            .collect();
+        assert_eq!(conflicts, vec!["a.txt"]);
+        assert!(blocked.conflicts[0].modified_at > info.created_at);
+        assert_eq!(workspace.read("a.txt").as_deref(), Some("edited after"));
+        assert_eq!(workspace.read("b.txt").as_deref(), Some("b2"));
+
+        // Conflicts outside the selection do not block it
+        let partial = restore(&workspace.0, &info.id, paths(&["b.txt"]), false)
+            .await
+            .unwrap();
+        assert!(partial.applied);
+        assert_eq!(partial.restored, vec!["b.txt"]);
+
+        let forced = restore(&workspace.0, &info.id, None, true).await.unwrap();
+        assert!(forced.applied);
+        assert_eq!(forced.restored, vec!["a.txt"]);
+        assert_eq!(forced.conflicts.len(), 1);
+        assert_eq!(workspace.read("a.txt").as_deref(), Some("a1"));
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn git_restore_keeps_modes_and_symlinks() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let workspace = Workspace::repository();
+        workspace.write("run.sh", "#!/bin/sh\n");
+        std::fs::set_permissions(
+            workspace.0.join("run.sh"),
+            std::fs::Permissions::from_mode(0o755),
+        )
+        .unwrap();
+        std::os::unix::fs::symlink("a.txt", workspace.0.join("link")).unwrap();
+        let info = workspace.snapshot("").await;
+
+        std::fs::remove_file(workspace.0.join("run.sh")).unwrap();
+        std::fs::remove_file(workspace.0.join("link")).unwrap();
+        restore(&workspace.0, &info.id, None, false).await.unwrap();
+
+        let mode = std::fs::metadata(workspace.0.join("run.sh"))
+            .unwrap()
+            .permissions()
+            .mode();
+        assert_eq!(mode & 0o777, 0o755);
+        assert_eq!(
+            std::fs::read_link(workspace.0.join("link")).unwrap(),
+            PathBuf::from("a.txt")
+        );
+    }
+
+    #[tokio::test]
+    async fn git_snapshots_are_deleted_and_pruned() {
+        let workspace = Workspace::repository();
+        let kept = workspace.snapshot("kept").await;
+        let removed = workspace.snapshot("removed").await;
+        let commit = workspace.git(&["rev-parse", &format!("{SNAPSHOT_REF_PREFIX}{}", kept.id)]);
+        workspace.git(&["update-ref", &format!("{SNAPSHOT_REF_PREFIX}1000"), &commit]);
+
+        delete(&workspace.0, &removed.id).await.unwrap();
+        assert!(!git::exists(&workspace.0, &removed.id).await);
+        assert!(delete(&workspace.0, &removed.id).await.is_err());
+        assert!(delete(&workspace.0, "../1000").await.is_err());
+
+        prune(&workspace.0, None).await;
+        assert_eq!(list(&workspace.0).await.unwrap().len(), 2);
+
+        prune(&workspace.0, Some(Duration::from_secs(24 * 60 * 60))).await;
+        let ids: Vec<String> = list(&workspace.0)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|snapshot| snapshot.id)
+            .collect();
+        assert_eq!(ids, vec![kept.id]);
+    }
+
+    #[tokio::test]
+    async fn copy_snapshots_capture_files_but_not_caches_or_metadata() {
+        let workspace = Workspace::new();
+        workspace.write("notes.md", "notes1");
+        workspace.write("src/main.rs", "main1");
+        workspace.write("node_modules/pkg/index.js", "dependency");
+        workspace.write(".git/HEAD", "not a repository");
+        workspace.write(".config/settings.json", "{}");
+
+        let info = workspace.snapshot("plain").await;
+        assert_eq!(info.kind, SnapshotKind::Copy);
+        assert_eq!(info.file_count, 3);
+
+        let dir = workspace.0.join(COPY_SNAPSHOTS_DIR).join(&info.id);
+        let manifest = copy_manifest(&dir);
+        let files: Vec<&str> = manifest
+            .files
+            .iter()
+            .map(|entry| entry.path.as_str())
+            .collect();
+        assert_eq!(
+            files,
+            vec![".config/settings.json", "notes.md", "src/main.rs"]
+        );
+        assert_eq!(manifest.label, "plain");
+        assert_eq!(
+            std::fs::read_to_string(dir.join(COPY_FILES_DIR).join("src/main.rs")).unwrap(),
+            "main1"
+        );
+
+        // Earlier snapshots are not captured by later ones
+        let second = workspace.snapshot("").await;
+        assert_eq!(second.file_count, 3);
+
+        let listed = list(&workspace.0).await.unwrap();
+        assert_eq!(listed.len(), 2);
+        assert_eq!(listed[0].id, second.id);
+        assert_eq!(listed[1].label, "plain");
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn copy_snapshots_link_unchanged_files() {
+        use std::os::unix::fs::MetadataExt;
+
+        let workspace = Workspace::new();
+        workspace.write("same.txt", "same");
+        workspace.write("changed.txt", "v1");
+        let first = workspace.snapshot("").await;
+        workspace.write("changed.txt", "version 2");
+        let second = workspace.snapshot("").await;
+
+        let stored = |info: &SnapshotInfo, path: &str| {
+            std::fs::metadata(
+                workspace
+                    .0
+                    .join(COPY_SNAPSHOTS_DIR)
+                    .join(&info.id)
+                    .join(COPY_FILES_DIR)
+                    .join(path),
+            )
+            .unwrap()
+            .ino()
+        };
+        assert_eq!(stored(&first, "same.txt"), stored(&second, "same.txt"));
+        assert_ne!(
+            stored(&first, "changed.txt"),
+            stored(&second, "changed.txt")
+        );
+    }
+
+    #[tokio::test]
+    async fn copy_restore_handles_conflicts_and_selections() {
+        let workspace = Workspace::new();
+        workspace.write("a.txt", "a1");
+        workspace.write("b.txt", "b1");
+        workspace.write("docs/guide.md", "guide1");
+        let info = workspace.snapshot("").await;
+
+        workspace.write("a.txt", "edited after");
+        workspace.write_before("b.txt", "b2", &info);
+        std::fs::remove_dir_all(workspace.0.join("docs")).unwrap();
+
+        let blocked = restore(&workspace.0, &info.id, None, false).await.unwrap();
+        assert!(!blocked.applied);
+        assert_eq!(blocked.conflicts.len(), 1);
+        assert_eq!(blocked.conflicts[0].path, "a.txt");
+        assert_eq!(workspace.read("b.txt").as_deref(), Some("b2"));
+        assert_eq!(workspace.read("docs/guide.md"), None);
+
+        let partial = restore(&workspace.0, &info.id, paths(&["docs", "b.txt"]), false)
+            .await
+            .unwrap();
+        assert!(partial.applied);
+        assert_eq!(partial.restored, vec!["b.txt", "docs/guide.md"]);
+        assert_eq!(workspace.read("b.txt").as_deref(), Some("b1"));
+        assert_eq!(workspace.read("docs/guide.md").as_deref(), Some("guide1"));
+        assert_eq!(workspace.read("a.txt").as_deref(), Some("edited after"));
+
+        let forced = restore(&workspace.0, &info.id, None, true).await.unwrap();
+        assert!(forced.applied);
+        assert!(forced.restored.contains(&"a.txt".to_string()));
+        assert_eq!(workspace.read("a.txt").as_deref(), Some("a1"));
+    }
+
+    #[tokio::test]
+    async fn copy_snapshots_are_deleted_and_missing_ones_reported() {
+        let workspace = Workspace::new();
+        workspace.write("a.txt", "a1");
+        let info = workspace.snapshot("").await;
+
+        assert!(restore(&workspace.0, "1000", None, false).await.is_err());
+        assert!(restore(&workspace.0, "../..", None, false).await.is_err());
+
+        delete(&workspace.0, &info.id).await.unwrap();
+        assert!(!workspace.0.join(COPY_SNAPSHOTS_DIR).join(&info.id).exists());
+        assert!(list(&workspace.0).await.unwrap().is_empty());
+        assert!(delete(&workspace.0, &info.id).await.is_err());
+        assert_eq!(workspace.read("a.txt").as_deref(), Some("a1"));
+    }
+}
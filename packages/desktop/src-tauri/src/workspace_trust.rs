@@ -0,0 +1,246 @@
+use std::path::{Path, PathBuf};
+
+use log::info;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+use crate::SettingsStore;
+
+/// Error code the UI maps to the workspace trust dialog
+pub const TRUST_REQUIRED: &str = "TRUST_REQUIRED";
+
+/// Settings key mapping canonical directory paths to trust decisions
+const TRUST_KEY: &str = "workspaceTrust";
+
+/// Trust state of a directory and where the decision came from
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WorkspaceTrust {
+    pub path: String,
+    pub trusted: bool,
+    /// Directory holding the decision: the path itself or a parent. None when
+    /// nothing was decided yet, which counts as untrusted.
+    pub decided_at: Option<String>,
+}
+
+fn canonical(path: &Path) -> PathBuf {
+    std::fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf())
+}
+
+fn decisions(settings: &Value) -> Option<&Map<String, Value>> {
+    settings.get(TRUST_KEY).and_then(Value::as_object)
+}
+
+fn decisions_mut(settings: &mut Value) -> Option<&mut Map<String, Value>> {
+    let object = settings.as_object_mut()?;
+    let entry = object
+        .entry(TRUST_KEY.to_string())
+        .or_insert_with(|| Value::Object(Map::new()));
+    if !entry.is_object() {
+        *entry = Value::Object(Map::new());
+    }
+    entry.as_object_mut()
+}
+
+/// Nearest decision for `path`: its own entry, else the closest parent's
+pub fn trust_for(settings: &Value, path: &Path) -> WorkspaceTrust {
+    let path = canonical(path);
+    let decided = decisions(settings).and_then(|decisions| {
+        path.ancestors().find_map(|ancestor| {
+            let key = ancestor.to_string_lossy();
+            decisions
+                .get(key.as_ref())
+                .and_then(Value::as_bool)
+                .map(|trusted| (key.to_string(), trusted))
+        })
+    });
+
+    WorkspaceTrust {
+        path: path.to_string_lossy().to_string(),
+        trusted: decided.as_ref().is_some_and(|(_, trusted)| *trusted),
+        decided_at: decided.map(|(key, _)| key),
+    }
+}
+
+/// Record `path` as untrusted when neither it nor a parent has a decision, so
+/// the UI can ask. Returns whether settings changed.
+pub fn mark_untrusted_if_new(settings: &mut Value, path: &Path) -> bool {
+    let trust = trust_for(settings, path);
+    if trust.decided_at.is_some() {
+        return false;
+    }
+    match decisions_mut(settings) {
+        Some(decisions) => {
+            decisions.insert(trust.path, Value::Bool(false));
+            true
+        }
+        None => false,
+    }
+}
+
+/// Decide trust for `path`. A decision on a folder replaces earlier decisions
+/// on folders inside it, so trusting a parent covers its children.
+pub fn set_trust(settings: &mut Value, path: &Path, trusted: bool) {
+    let path = canonical(path);
+    let Some(decisions) = decisions_mut(settings) else {
+        return;
+    };
+    decisions.retain(|key, _| !Path::new(key).starts_with(&path));
+    decisions.insert(path.to_string_lossy().to_string(), Value::Bool(trusted));
+}
+
+/// Note a newly activated directory and report its trust
+pub async fn record_activation(
+    store: &SettingsStore,
+    path: &Path,
+) -> Result<WorkspaceTrust, String> {
+    let mut settings = store
+        .load()
+        .await
+        .map_err(|e| format!("Failed to load settings: {}", e))?;
+    if mark_untrusted_if_new(&mut settings, path) {
+        info!(
+            "[desktop:trust] {} is not trusted yet",
+            canonical(path).display()
+        );
+        store
+            .save(settings.clone())
+            .await
+            .map_err(|e| format!("Failed to save settings: {}", e))?;
+    }
+    Ok(trust_for(&settings, path))
+}
+
+/// Fail with TRUST_REQUIRED unless `path` is trusted; guards execution the
+/// desktop starts on its own (terminals, quick runs), not OpenCode itself
+pub async fn require_trust(
+    store: &SettingsStore,
+    path: &Path,
+    feature: &str,
+) -> Result<(), String> {
+    let settings = store.load().await.unwrap_or_default();
+    if trust_for(&settings, path).trusted {
+        return Ok(());
+    }
+    Err(format!(
+        "{}: trust {} to use {}",
+        TRUST_REQUIRED,
+        canonical(path).display(),
+        feature
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    /// A project tree under the system temp dir, removed on drop
+    struct Tree {
+        root: PathBuf,
+    }
+
+    impl Tree {
+        fn new(dirs: &[&str]) -> Self {
+            let root =
+                std::env::temp_dir().join(format!("openchamber-trust-{}", uuid::Uuid::new_v4()));
+            std::fs::create_dir_all(&root).unwrap();
+            for dir in dirs {
+                std::fs::create_dir_all(root.join(dir)).unwrap();
+            }
+            Self {
+                root: canonical(&root),
+            }
+        }
+
+        fn path(&self, relative: &str) -> PathBuf {
+            self.root.join(relative)
+        }
+
+        fn key(&self, relative: &str) -> String {
+            self.path(relative).to_string_lossy().to_string()
+        }
+    }
+
+    impl Drop for Tree {
+        fn drop(&mut self) {
+            std::fs::remove_dir_all(&self.root).ok();
+        }
+    }
+
+    #[test]
+    fn undecided_directories_are_untrusted() {
+        let tree = Tree::new(&["app"]);
+        let trust = trust_for(&json!({}), &tree.path("app"));
+        assert!(!trust.trusted);
+        assert_eq!(trust.decided_at, None);
+        assert_eq!(trust.path, tree.key("app"));
+    }
+
+    #[test]
+    fn children_inherit_the_nearest_decision() {
+        let tree = Tree::new(&["work/app/src", "work/vendor"]);
+        let mut settings = json!({});
+        set_trust(&mut settings, &tree.path("work"), true);
+
+        let trust = trust_for(&settings, &tree.path("work/app/src"));
+        assert!(trust.trusted);
+        assert_eq!(trust.decided_at, Some(tree.key("work")));
+
+        set_trust(&mut settings, &tree.path("work/vendor"), false);
+        assert!(!trust_for(&settings, &tree.path("work/vendor")).trusted);
+        assert!(trust_for(&settings, &tree.path("work/app")).trusted);
+    }
+
+    #[test]
+    fn deciding_a_parent_replaces_decisions_inside_it() {
+        let tree = Tree::new(&["work/app", "work/vendor"]);
+        let mut settings = json!({});
+        set_trust(&mut settings, &tree.path("work/vendor"), false);
+        set_trust(&mut settings, &tree.path("work"), true);
+
+        assert!(trust_for(&settings, &tree.path("work/vendor")).trusted);
+        assert_eq!(settings[TRUST_KEY], json!({ tree.key("work"): true }));
+    }
+
+    #[test]
+    fn only_undecided_directories_are_marked_untrusted() {
+        let tree = Tree::new(&["work/app", "other"]);
+        let mut settings = json!({});
+        set_trust(&mut settings, &tree.path("work"), true);
+
+        assert!(!mark_untrusted_if_new(
+            &mut settings,
+            &tree.path("work/app")
+        ));
+        assert!(mark_untrusted_if_new(&mut settings, &tree.path("other")));
+        assert!(!mark_untrusted_if_new(&mut settings, &tree.path("other")));
+        assert_eq!(settings[TRUST_KEY][tree.key("other")], json!(false));
+    }
+
+    #[tokio::test]
+    async fn each_feature_requires_trust_until_granted() {
+        let tree = Tree::new(&["project"]);
+        let store = SettingsStore::at(tree.path("settings.json"));
+        let project = tree.path("project");
+
+        let activated = record_activation(&store, &project).await.unwrap();
+        assert!(!activated.trusted);
+        assert_eq!(activated.decided_at, Some(tree.key("project")));
+
+        for feature in ["the terminal", "quick runs"] {
+            let err = require_trust(&store, &project, feature).await.unwrap_err();
+            assert!(err.starts_with(TRUST_REQUIRED));
+            assert!(err.ends_with(&format!("to use {feature}")));
+        }
+
+        let mut settings = store.load().await.unwrap();
+        set_trust(&mut settings, &tree.root, true);
+        store.save(settings).await.unwrap();
+
+        for feature in ["the terminal", "quick runs"] {
+            assert!(require_trust(&store, &project, feature).await.is_ok());
+        }
+        assert!(record_activation(&store, &project).await.unwrap().trusted);
+    }
+}
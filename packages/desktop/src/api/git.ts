@@ -48,7 +48,7 @@ export const createDesktopGitAPI = (): GitAPI => ({
   },
 
   async getGitDiff(directory: string, options: GetGitDiffOptions): Promise<GitDiffResponse> {
-    const diff = await safeGitInvoke<string>('get_git_diff', {
+    const { diff } = await safeGitInvoke<{ diff: string; truncated: boolean }>('get_git_diff', {
       directory,
       pathStr: options.path,
       staged: options.staged,
@@ -30,7 +30,7 @@ export const createDesktopNotificationsAPI = (): NotificationsAPI => ({
         return false;
       }
 
-      await safeInvoke(
+      const outcome = await safeInvoke<'shown' | 'deduped' | 'suppressed'>(
         'desktop_notify',
         { payload },
         {
@@ -40,7 +40,10 @@ export const createDesktopNotificationsAPI = (): NotificationsAPI => ({
           },
         },
       );
-      return true;
+      if (outcome && outcome !== 'shown') {
+        console.info(`[notifications] Notification ${outcome} by the desktop rate limiter`);
+      }
+      return outcome === 'shown';
     } catch (error) {
       console.error('[notifications] Failed to send notification:', error);
       return false;
@@ -6,8 +6,16 @@ type ServerInfo = {
   opencode_port?: number | null;
   api_prefix?: string | null;
   cli_available?: boolean;
+  opencode_version?: string | null;
+  version_warning?: string | null;
+  api_token: string;
 };
 
+// The desktop server rejects /api requests without the per-launch token
+const TOKEN_HEADER = 'x-openchamber-token';
+// EventSource cannot set headers, so it passes the token in the query instead
+const TOKEN_QUERY = 'openchamberToken';
+
 declare global {
   interface Window {
     __OPENCHAMBER_DESKTOP_SERVER__?: {
@@ -15,6 +23,8 @@ declare global {
       opencodePort: number | null;
       apiPrefix: string;
       cliAvailable: boolean;
+      opencodeVersion: string | null;
+      versionWarning: string | null;
     };
   }
 }
@@ -43,10 +53,15 @@ async function setupBridge(): Promise<void> {
       opencodePort: info.opencode_port ?? null,
       apiPrefix: info.api_prefix ?? '',
       cliAvailable: info.cli_available ?? false,
+      opencodeVersion: info.opencode_version ?? null,
+      versionWarning: info.version_warning ?? null,
     };
+    if (info.version_warning) {
+      console.warn('[bridge]', info.version_warning);
+    }
 
-    patchFetch(origin);
-    patchEventSource(origin);
+    patchFetch(origin, info.api_token);
+    patchEventSource(origin, info.api_token);
 
     const cleanupDevtools = registerDevtoolsShortcut();
 
@@ -73,9 +88,23 @@ async function setupBridge(): Promise<void> {
   }
 }
 
-function patchFetch(origin: string) {
+function isDesktopUrl(url: string, origin: string): boolean {
+  return url === origin || url.startsWith(`${origin}/`);
+}
+
+function patchFetch(origin: string, token: string) {
   const originalFetch = window.fetch.bind(window);
 
+  // Headers in init replace the request's own, so start from those when init has none
+  const withToken = (url: string, init?: RequestInit, base?: Headers): RequestInit | undefined => {
+    if (!isDesktopUrl(url, origin)) {
+      return init;
+    }
+    const headers = new Headers(init?.headers ?? base);
+    headers.set(TOKEN_HEADER, token);
+    return { ...init, headers };
+  };
+
   const rewrite = (value: string): string => {
     if (value.startsWith('http://') || value.startsWith('https://')) {
       return value;
@@ -91,37 +120,46 @@ function patchFetch(origin: string) {
 
   window.fetch = (input: RequestInfo | URL, init?: RequestInit) => {
     if (typeof input === 'string') {
-      return originalFetch(rewrite(input), init);
+      const rewritten = rewrite(input);
+      return originalFetch(rewritten, withToken(rewritten, init));
     }
 
     if (input instanceof Request) {
       const rewritten = rewrite(input.url);
-      if (rewritten === input.url) {
-        return originalFetch(input, init);
-      }
-      const cloned = new Request(rewritten, input);
-      return originalFetch(cloned, init);
+      const request = rewritten === input.url ? input : new Request(rewritten, input);
+      return originalFetch(request, withToken(request.url, init, request.headers));
     }
 
     if (input instanceof URL) {
-      return originalFetch(rewrite(input.toString()), init);
+      const rewritten = rewrite(input.toString());
+      return originalFetch(rewritten, withToken(rewritten, init));
     }
 
     return originalFetch(input, init);
   };
 }
 
-function patchEventSource(origin: string) {
+function patchEventSource(origin: string, token: string) {
   if (typeof window.EventSource === 'undefined') {
     return;
   }
 
   const OriginalEventSource = window.EventSource;
 
+  const withTokenQuery = (url: string): string => {
+    if (!isDesktopUrl(url, origin)) {
+      return url;
+    }
+    const parsed = new URL(url);
+    parsed.searchParams.set(TOKEN_QUERY, token);
+    return parsed.toString();
+  };
+
   class DesktopEventSource extends OriginalEventSource {
     constructor(url: string | URL, eventSourceInit?: EventSourceInit) {
       const normalized = typeof url === 'string' ? url : url.toString();
-      super(normalized.startsWith('/') ? `${origin}${normalized}` : normalized, eventSourceInit);
+      const resolved = normalized.startsWith('/') ? `${origin}${normalized}` : normalized;
+      super(withTokenQuery(resolved), eventSourceInit);
     }
   }
 
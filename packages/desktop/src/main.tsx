@@ -55,6 +55,16 @@ try {
   });
   cleanupFunctions.push(() => activityUnlisten());
 
+  const lifecycleUnlisten = await listen('openchamber:opencode-lifecycle', (event) => {
+    window.dispatchEvent(new CustomEvent('openchamber:opencode-lifecycle', { detail: event.payload }));
+  });
+  cleanupFunctions.push(() => lifecycleUnlisten());
+
+  const cliInstallUnlisten = await listen('openchamber:cli-install-progress', (event) => {
+    window.dispatchEvent(new CustomEvent('openchamber:cli-install-progress', { detail: event.payload }));
+  });
+  cleanupFunctions.push(() => cliInstallUnlisten());
+
   requestInitialNotificationPermission().catch(err => {
     console.error('[main] Failed to request notification permission:', err);
   });
@@ -157,23 +167,10 @@ window.opencodeDesktop = {
   },
   async requestDirectoryAccess() {
     try {
-
-      const { open } = await import('@tauri-apps/plugin-dialog');
-      const selected = await open({
-        directory: true,
-        multiple: false,
-        title: 'Select Working Directory'
+      // Native picker; validates and records the selection in one call
+      return await invoke<{ success: boolean; path?: string; error?: string }>('pick_directory', {
+        options: { title: 'Select Working Directory', allowCreate: true }
       });
-
-      if (!selected || typeof selected !== 'string') {
-        return { success: false, error: 'Directory selection cancelled' };
-      }
-
-      const result = await invoke<{ success: boolean; path?: string; error?: string }>('process_directory_selection', {
-        path: selected
-      });
-
-      return result;
     } catch (error) {
       console.error('[desktop] Error requesting directory access:', error);
       return { success: false, error: error instanceof Error ? error.message : String(error) };